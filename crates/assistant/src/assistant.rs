@@ -39,6 +39,7 @@ use language_model::{
     LanguageModelId, LanguageModelProviderId, LanguageModelRegistry, LanguageModelResponseMessage,
 };
 pub(crate) use model_selector::*;
+use ollama_completions::OllamaSettings;
 pub use patch::*;
 pub use prompts::PromptBuilder;
 use prompts::PromptLoadingParams;
@@ -48,8 +49,9 @@ use settings::{update_settings_file, Settings, SettingsStore};
 use slash_command::search_command::SearchSlashCommandFeatureFlag;
 use slash_command::{
     auto_command, cargo_workspace_command, default_command, delta_command, diagnostics_command,
-    docs_command, fetch_command, file_command, now_command, project_command, prompt_command,
-    search_command, selection_command, symbols_command, tab_command, terminal_command,
+    docs_command, fetch_command, file_command, now_command, ollama_command, project_command,
+    prompt_command, search_command, selection_command, symbols_command, tab_command,
+    terminal_command,
 };
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -396,6 +398,15 @@ fn register_slash_commands(prompt_builder: Option<Arc<PromptBuilder>>, cx: &mut
     cx.observe_global::<SettingsStore>(update_slash_commands_from_settings)
         .detach();
 
+    // Not every caller initializes Ollama before the assistant.
+    OllamaSettings::register(cx);
+    let mut ollama_commands = Vec::new();
+    update_ollama_slash_commands(&mut ollama_commands, cx);
+    cx.observe_global::<SettingsStore>(move |cx| {
+        update_ollama_slash_commands(&mut ollama_commands, cx)
+    })
+    .detach();
+
     cx.observe_flag::<search_command::SearchSlashCommandFeatureFlag, _>({
         let slash_command_registry = slash_command_registry.clone();
         move |is_enabled, _cx| {
@@ -426,6 +437,31 @@ fn update_slash_commands_from_settings(cx: &mut AppContext) {
     }
 }
 
+/// Registers the slash commands defined in the `ollama.slash_commands`
+/// setting, replacing the ones in `registered`, which were registered for its
+/// previous value. Commands named like an existing one are skipped.
+fn update_ollama_slash_commands(registered: &mut Vec<String>, cx: &mut AppContext) {
+    let slash_command_registry = SlashCommandRegistry::global(cx);
+    for name in registered.drain(..) {
+        slash_command_registry.unregister_command_by_name(&name);
+    }
+
+    let settings = OllamaSettings::get_global(cx);
+    let mut commands = settings.slash_commands.iter().collect::<Vec<_>>();
+    commands.sort_by_key(|(name, _)| *name);
+    for (name, command) in commands {
+        if slash_command_registry.command(name).is_some() {
+            log::warn!("the Ollama slash command /{name} conflicts with an existing command");
+            continue;
+        }
+        slash_command_registry.register_command(
+            ollama_command::OllamaPromptSlashCommand::new(name.clone(), command.clone()),
+            true,
+        );
+        registered.push(name.clone());
+    }
+}
+
 fn register_tools(cx: &mut AppContext) {
     let tool_registry = ToolRegistry::global(cx);
     tool_registry.register_tool(tools::now_tool::NowTool);
@@ -1,6 +1,10 @@
 use feature_flags::ZedPro;
 
-use language_model::{LanguageModel, LanguageModelAvailability, LanguageModelRegistry};
+use language_model::{
+    provider::ollama::is_ollama_model, LanguageModel, LanguageModelAvailability,
+    LanguageModelRegistry,
+};
+use ollama_completions::OllamaSettings;
 use proto::Plan;
 use workspace::ShowConfiguration;
 
@@ -11,7 +15,9 @@ use fs::Fs;
 use gpui::{Action, AnyElement, DismissEvent, SharedString, Task};
 use picker::{Picker, PickerDelegate};
 use settings::update_settings_file;
-use ui::{prelude::*, ListItem, ListItemSpacing, PopoverMenu, PopoverMenuHandle, PopoverTrigger};
+use ui::{
+    prelude::*, ListItem, ListItemSpacing, PopoverMenu, PopoverMenuHandle, PopoverTrigger, Tooltip,
+};
 
 const TRY_ZED_PRO_URL: &str = "https://zed.dev/pro";
 
@@ -36,6 +42,8 @@ struct ModelInfo {
     icon: IconName,
     availability: LanguageModelAvailability,
     is_selected: bool,
+    /// Whether Ollama's inline completions are made with this model.
+    is_completion_model: bool,
 }
 
 impl<T: PopoverTrigger> ModelSelector<T> {
@@ -59,6 +67,29 @@ impl<T: PopoverTrigger> ModelSelector<T> {
     }
 }
 
+impl ModelPickerDelegate {
+    /// Makes Ollama's inline completions use the highlighted model, if
+    /// Ollama serves it, leaving the assistant's model as it is.
+    fn use_for_completions(&mut self, cx: &mut ViewContext<Picker<Self>>) {
+        let Some(model_info) = self.filtered_models.get(self.selected_index) else {
+            return;
+        };
+        if !is_ollama_model(model_info.model.as_ref()) {
+            return;
+        }
+        let model_id = model_info.model.id();
+        let model = model_id.0.to_string();
+        update_settings_file::<OllamaSettings>(self.fs.clone(), cx, move |settings, _| {
+            settings.model = Some(model);
+        });
+        for model in self.all_models.iter_mut().chain(&mut self.filtered_models) {
+            model.is_completion_model =
+                is_ollama_model(model.model.as_ref()) && model.model.id() == model_id;
+        }
+        cx.emit(DismissEvent);
+    }
+}
+
 impl PickerDelegate for ModelPickerDelegate {
     type ListItem = ListItem;
 
@@ -134,7 +165,11 @@ impl PickerDelegate for ModelPickerDelegate {
         })
     }
 
-    fn confirm(&mut self, _secondary: bool, cx: &mut ViewContext<Picker<Self>>) {
+    fn confirm(&mut self, secondary: bool, cx: &mut ViewContext<Picker<Self>>) {
+        if secondary {
+            self.use_for_completions(cx);
+            return;
+        }
         if let Some(model_info) = self.filtered_models.get(self.selected_index) {
             let model = model_info.model.clone();
             update_settings_file::<AssistantSettings>(self.fs.clone(), cx, move |settings, _| {
@@ -221,6 +256,13 @@ impl PickerDelegate for ModelPickerDelegate {
                                         .size(LabelSize::XSmall)
                                         .color(Color::Muted),
                                 )
+                                .when(model_info.is_completion_model, |this| {
+                                    this.child(
+                                        Label::new("Completions")
+                                            .size(LabelSize::XSmall)
+                                            .color(Color::Accent),
+                                    )
+                                })
                                 .children(match model_info.availability {
                                     LanguageModelAvailability::Public => None,
                                     LanguageModelAvailability::RequiresPlan(Plan::Free) => None,
@@ -234,6 +276,14 @@ impl PickerDelegate for ModelPickerDelegate {
                                 }),
                         ),
                 )
+                .when(
+                    is_ollama_model(model_info.model.as_ref()) && !model_info.is_completion_model,
+                    |this| {
+                        this.tooltip(|cx| {
+                            Tooltip::for_action("Use for Completions", &menu::SecondaryConfirm, cx)
+                        })
+                    },
+                )
                 .end_slot(div().when(model_info.is_selected, |this| {
                     this.child(
                         Icon::new(IconName::Check)
@@ -306,6 +356,8 @@ impl<T: PopoverTrigger> RenderOnce for ModelSelector<T> {
             .active_model()
             .map(|m| m.id());
 
+        let completion_model = OllamaSettings::get_global(cx).model.clone();
+
         let all_models = LanguageModelRegistry::global(cx)
             .read(cx)
             .providers()
@@ -315,6 +367,7 @@ impl<T: PopoverTrigger> RenderOnce for ModelSelector<T> {
                 let icon = provider.icon();
                 let selected_model = selected_model.clone();
                 let selected_provider = selected_provider.clone();
+                let completion_model = completion_model.clone();
 
                 provider.provided_models(cx).into_iter().map(move |model| {
                     let model = model.clone();
@@ -326,6 +379,8 @@ impl<T: PopoverTrigger> RenderOnce for ModelSelector<T> {
                         availability: model.availability(),
                         is_selected: selected_model.as_ref() == Some(&model.id())
                             && selected_provider.as_ref() == Some(&provider_id),
+                        is_completion_model: is_ollama_model(model.as_ref())
+                            && model.id().0 == completion_model,
                     }
                 })
             })
@@ -29,6 +29,7 @@ pub mod docs_command;
 pub mod fetch_command;
 pub mod file_command;
 pub mod now_command;
+pub mod ollama_command;
 pub mod project_command;
 pub mod prompt_command;
 pub mod search_command;
@@ -0,0 +1,151 @@
+use anyhow::{anyhow, Result};
+use assistant_slash_command::{
+    ArgumentCompletion, SlashCommand, SlashCommandContent, SlashCommandEvent,
+    SlashCommandOutputSection, SlashCommandResult,
+};
+use editor::{Editor, ToOffset as _};
+use futures::StreamExt;
+use gpui::{Task, WeakView};
+use language::{BufferSnapshot, LspAdapterDelegate};
+use ollama::stream_generate;
+use ollama_completions::{
+    file_repository, http_client_for, OllamaSettings, OllamaSlashCommand, SlashCommandInputs,
+};
+use settings::Settings;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use ui::prelude::*;
+use workspace::Workspace;
+
+/// A slash command defined in the `ollama.slash_commands` setting, which
+/// streams the model's answer to its prompt into the conversation.
+pub(crate) struct OllamaPromptSlashCommand {
+    name: String,
+    command: OllamaSlashCommand,
+}
+
+impl OllamaPromptSlashCommand {
+    pub fn new(name: String, command: OllamaSlashCommand) -> Self {
+        Self { name, command }
+    }
+}
+
+impl SlashCommand for OllamaPromptSlashCommand {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> String {
+        self.command
+            .description
+            .clone()
+            .unwrap_or_else(|| "Ask Ollama".into())
+    }
+
+    fn icon(&self) -> IconName {
+        IconName::Ai
+    }
+
+    fn menu_text(&self) -> String {
+        self.description()
+    }
+
+    fn requires_argument(&self) -> bool {
+        false
+    }
+
+    fn accepts_arguments(&self) -> bool {
+        self.command.uses("{arguments}")
+    }
+
+    fn complete_argument(
+        self: Arc<Self>,
+        _arguments: &[String],
+        _cancel: Arc<AtomicBool>,
+        _workspace: Option<WeakView<Workspace>>,
+        _cx: &mut WindowContext,
+    ) -> Task<Result<Vec<ArgumentCompletion>>> {
+        Task::ready(Ok(Vec::new()))
+    }
+
+    fn run(
+        self: Arc<Self>,
+        arguments: &[String],
+        _context_slash_command_output_sections: &[SlashCommandOutputSection<language::Anchor>],
+        _context_buffer: BufferSnapshot,
+        workspace: WeakView<Workspace>,
+        _delegate: Option<Arc<dyn LspAdapterDelegate>>,
+        cx: &mut WindowContext,
+    ) -> Task<SlashCommandResult> {
+        let mut inputs = SlashCommandInputs {
+            arguments: arguments.join(" "),
+            ..Default::default()
+        };
+        let mut repository = None;
+        if let Some(workspace) = workspace.upgrade() {
+            let workspace = workspace.read(cx);
+            let editor = workspace
+                .active_item(cx)
+                .and_then(|item| item.act_as::<Editor>(cx));
+            if let Some(editor) = editor {
+                let editor = editor.read(cx);
+                let snapshot = editor.buffer().read(cx).snapshot(cx);
+                let selection = editor.selections.newest_anchor();
+                let selection =
+                    selection.start.to_offset(&snapshot)..selection.end.to_offset(&snapshot);
+                if !selection.is_empty() {
+                    inputs.selection = Some(snapshot.text_for_range(selection).collect());
+                }
+                if let Some(buffer) = editor.buffer().read(cx).as_singleton() {
+                    let buffer = buffer.read(cx);
+                    if let Some(file) = buffer.file() {
+                        inputs.path = Some(file.full_path(cx).to_string_lossy().into_owned());
+                        if self.command.uses("{diff}") {
+                            repository = file_repository(workspace.project().read(cx), file, cx);
+                        }
+                    }
+                    if self.command.uses("{file}") {
+                        inputs.file = Some(buffer.text());
+                    }
+                }
+            }
+        }
+
+        let settings = OllamaSettings::get_global(cx);
+        if !settings.enabled {
+            return Task::ready(Err(anyhow!("Ollama is disabled")));
+        }
+        let command = self.command.clone();
+        let http_client = http_client_for(settings, &cx.http_client());
+        let settings = settings.clone();
+        cx.background_executor().spawn(async move {
+            if let Some(repository) = repository {
+                inputs.diff = Some(repository.staged_diff()?);
+            }
+            let request = command.request(&inputs, &settings);
+            let api_key = settings.effective_api_key();
+            let stream = stream_generate(
+                http_client.as_ref(),
+                &settings.api_url,
+                api_key.as_deref(),
+                request,
+                None,
+            )
+            .await?;
+            Ok(stream
+                .filter_map(|response| async move {
+                    match response {
+                        Ok(response) if response.response.is_empty() => None,
+                        Ok(response) => {
+                            Some(Ok(SlashCommandEvent::Content(SlashCommandContent::Text {
+                                text: response.response,
+                                run_commands_in_text: false,
+                            })))
+                        }
+                        Err(error) => Some(Err(error)),
+                    }
+                })
+                .boxed())
+        })
+    }
+}
@@ -6,7 +6,10 @@ use assistant_slash_command::{
 use feature_flags::FeatureFlag;
 use gpui::{AppContext, Task, WeakView};
 use language::{CodeLabel, LspAdapterDelegate};
+use ollama::rerank;
+use ollama_completions::{http_client_for, OllamaSettings};
 use semantic_index::{LoadedSearchResult, SemanticDb};
+use settings::Settings as _;
 use std::{
     fmt::Write,
     sync::{atomic::AtomicBool, Arc},
@@ -17,6 +20,10 @@ use workspace::Workspace;
 use crate::slash_command::create_label_for_command;
 use crate::slash_command::file_command::{build_entry_output_section, codeblock_fence_for_path};
 
+/// How many more results are searched for than requested when they're
+/// reranked, for the reranker to pick the best ones from.
+const RERANK_CANDIDATES_PER_RESULT: usize = 4;
+
 pub(crate) struct SearchSlashCommandFeatureFlag;
 
 impl FeatureFlag for SearchSlashCommandFeatureFlag {
@@ -107,14 +114,66 @@ impl SlashCommand for SearchSlashCommand {
             return Task::ready(Err(anyhow::anyhow!("no project indexer")));
         };
 
+        let limit = limit.unwrap_or(5);
+        let settings = OllamaSettings::get_global(cx);
+        let reranker = settings
+            .rerank_model
+            .as_ref()
+            .filter(|_| ollama_completions::is_enabled(cx))
+            .map(|model| {
+                (
+                    settings.secret_redactor(),
+                    http_client_for(settings, &cx.http_client()),
+                    settings.preferred_endpoint(cx),
+                    settings.effective_api_key(),
+                    settings.resolve_model(model).to_string(),
+                )
+            });
+        let search_limit = if reranker.is_some() {
+            limit * RERANK_CANDIDATES_PER_RESULT
+        } else {
+            limit
+        };
+
         cx.spawn(|cx| async move {
             let results = project_index
                 .read_with(&cx, |project_index, cx| {
-                    project_index.search(vec![query.clone()], limit.unwrap_or(5), cx)
+                    project_index.search(vec![query.clone()], search_limit, cx)
                 })?
                 .await?;
 
-            let loaded_results = SemanticDb::load_results(results, &fs, &cx).await?;
+            let mut loaded_results = SemanticDb::load_results(results, &fs, &cx).await?;
+            if let Some((redactor, http_client, api_url, api_key, model)) = reranker {
+                let redact = |text: &str| match &redactor {
+                    Some(redactor) => redactor.redact(text).into_owned(),
+                    None => text.to_string(),
+                };
+                let excerpts = loaded_results
+                    .iter()
+                    .map(|result| redact(&result.excerpt_content))
+                    .collect::<Vec<_>>();
+                let excerpts = excerpts.iter().map(String::as_str).collect::<Vec<_>>();
+                let ranked = rerank(
+                    http_client.as_ref(),
+                    &api_url,
+                    api_key.as_deref(),
+                    &model,
+                    &redact(&query),
+                    &excerpts,
+                )
+                .await;
+                match ranked {
+                    Ok(ranked) => {
+                        let mut results = loaded_results.into_iter().map(Some).collect::<Vec<_>>();
+                        loaded_results = ranked
+                            .into_iter()
+                            .filter_map(|candidate| results[candidate.index].take())
+                            .collect();
+                    }
+                    Err(error) => log::error!("failed to rerank search results: {error:?}"),
+                }
+                loaded_results.truncate(limit);
+            }
 
             let output = cx
                 .background_executor()
@@ -18,7 +18,8 @@ use std::time::Instant;
 use std::{env, mem, path::PathBuf, sync::Arc, time::Duration};
 use telemetry_events::{
     ActionEvent, AppEvent, AssistantEvent, CallEvent, EditEvent, EditorEvent, Event,
-    EventRequestBody, EventWrapper, ExtensionEvent, InlineCompletionEvent, ReplEvent, SettingEvent,
+    EventRequestBody, EventWrapper, ExtensionEvent, InlineCompletionEvent, InlineCompletionMetrics,
+    ReplEvent, SettingEvent,
 };
 use util::{ResultExt, TryFutureExt};
 use worktree::{UpdatedEntriesSet, WorktreeId};
@@ -344,13 +345,35 @@ impl Telemetry {
         suggestion_accepted: bool,
         file_extension: Option<String>,
     ) {
-        let event = Event::InlineCompletion(InlineCompletionEvent {
+        self.report_inline_completion_event_with_metrics(
             provider,
             suggestion_accepted,
             file_extension,
-        });
+            None,
+        )
+    }
 
-        self.report_event(event)
+    pub fn report_inline_completion_event_with_metrics(
+        self: &Arc<Self>,
+        provider: String,
+        suggestion_accepted: bool,
+        file_extension: Option<String>,
+        metrics: Option<InlineCompletionMetrics>,
+    ) {
+        self.report_inline_completion(InlineCompletionEvent {
+            provider,
+            suggestion_accepted,
+            file_extension,
+            metrics,
+            acceptance: None,
+            cycled: false,
+            candidate_index: None,
+            candidate_count: None,
+        })
+    }
+
+    pub fn report_inline_completion(self: &Arc<Self>, event: InlineCompletionEvent) {
+        self.report_event(Event::InlineCompletion(event));
     }
 
     pub fn report_assistant_event(self: &Arc<Self>, event: AssistantEvent) {
@@ -1,7 +1,9 @@
 use crate::{Completion, Copilot};
 use anyhow::Result;
 use client::telemetry::Telemetry;
-use editor::{CompletionProposal, Direction, InlayProposal, InlineCompletionProvider};
+use editor::{
+    CompletionMetadata, CompletionProposal, Direction, InlayProposal, InlineCompletionProvider,
+};
 use gpui::{AppContext, EntityId, Model, ModelContext, Task};
 use language::{
     language_settings::{all_language_settings, AllLanguageSettings},
@@ -232,6 +234,15 @@ impl InlineCompletionProvider for CopilotCompletionProvider {
         }
     }
 
+    fn discard_pending(&mut self, cx: &mut ModelContext<Self>) {
+        self.discard(false, cx);
+        self.pending_refresh = Task::ready(Ok(()));
+        self.pending_cycling_refresh = Task::ready(Ok(()));
+        self.completions.clear();
+        self.active_completion_index = 0;
+        cx.notify();
+    }
+
     fn active_completion_text<'a>(
         &'a self,
         buffer: &Model<Buffer>,
@@ -274,6 +285,7 @@ impl InlineCompletionProvider for CopilotCompletionProvider {
                     )],
                     text: completion_text.into(),
                     delete_range: None,
+                    metadata: Some(CompletionMetadata::new(Self::name())),
                 })
             }
         } else {
@@ -206,6 +206,7 @@ gpui::actions!(
         AcceptInlineCompletion,
         AcceptPartialCopilotSuggestion,
         AcceptPartialInlineCompletion,
+        AcceptPartialInlineCompletionLine,
         AddSelectionAbove,
         AddSelectionBelow,
         ApplyAllDiffHunks,
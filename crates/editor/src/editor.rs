@@ -457,6 +457,13 @@ struct CompletionState {
     // delete_range is the range of text that this completion state covers.
     // if the completion is accepted, this range should be deleted.
     delete_range: Option<Range<multi_buffer::Anchor>>,
+    // appendable is true when the completion is shown as suggestion text at
+    // the cursor and nothing else, so a proposal extending it can be shown
+    // by inserting just the new text after it.
+    appendable: bool,
+    // proposal is what the provider last proposed, so proposing the same
+    // thing again leaves the inlays as they are instead of replacing them.
+    proposal: CompletionProposal,
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, PartialOrd, Ord, Debug, Default)]
@@ -2394,6 +2401,10 @@ impl Editor {
         self.workspace.as_ref()?.0.upgrade()
     }
 
+    pub fn project(&self) -> Option<&Model<Project>> {
+        self.project.as_ref()
+    }
+
     pub fn title<'a>(&self, cx: &'a AppContext) -> Cow<'a, str> {
         self.buffer().read(cx).title(cx)
     }
@@ -2666,11 +2677,14 @@ impl Editor {
             return false;
         };
 
+        // Scopes like "comment.inclusive" are disabled by their first part,
+        // as well as by their full name.
         scope.override_name().map_or(false, |scope_name| {
+            let kind = scope_name.split('.').next().unwrap_or(scope_name);
             settings
                 .inline_completions_disabled_in
                 .iter()
-                .any(|s| s == scope_name)
+                .any(|s| s == scope_name || s == kind)
         })
     }
 
@@ -3328,7 +3342,7 @@ impl Editor {
             return true;
         }
 
-        if self.discard_inline_completion(should_report_inline_completion_event, cx) {
+        if self.discard_pending_inline_completion(should_report_inline_completion_event, cx) {
             return true;
         }
 
@@ -5403,7 +5417,12 @@ impl Editor {
         let Some(completion) = self.take_active_inline_completion(cx) else {
             return;
         };
+        let mut format = AcceptedCompletionFormat::None;
         if let Some(provider) = self.inline_completion_provider() {
+            let cursor = self.selections.newest_anchor().head();
+            if let Some((buffer, _)) = self.buffer.read(cx).text_anchor_for_position(cursor, cx) {
+                format = provider.accepted_format(&buffer, cx);
+            }
             provider.accept(cx);
         }
 
@@ -5415,7 +5434,13 @@ impl Editor {
         if let Some(range) = completion.delete_range {
             self.change_selections(None, cx, |s| s.select_ranges([range]))
         }
-        self.insert_with_autoindent_mode(&completion.text.to_string(), None, cx);
+        let start = self.selections.newest::<Point>(cx).start;
+        let autoindent =
+            (format == AcceptedCompletionFormat::Indent).then_some(AutoindentMode::EachLine);
+        self.insert_with_autoindent_mode(&completion.text.to_string(), autoindent, cx);
+        if format == AcceptedCompletionFormat::Format {
+            self.format_inserted_completion(start, cx);
+        }
         self.refresh_inline_completion(true, true, cx);
         cx.notify();
     }
@@ -5424,23 +5449,28 @@ impl Editor {
         &mut self,
         _: &AcceptPartialInlineCompletion,
         cx: &mut ViewContext<Self>,
+    ) {
+        self.accept_inline_completion_segment(next_word_segment, PartialAccept::Word, cx);
+    }
+
+    pub fn accept_partial_inline_completion_line(
+        &mut self,
+        _: &AcceptPartialInlineCompletionLine,
+        cx: &mut ViewContext<Self>,
+    ) {
+        self.accept_inline_completion_segment(next_line_segment, PartialAccept::Line, cx);
+    }
+
+    fn accept_inline_completion_segment(
+        &mut self,
+        segment: fn(&str) -> &str,
+        kind: PartialAccept,
+        cx: &mut ViewContext<Self>,
     ) {
         if self.selections.count() == 1 && self.has_active_inline_completion(cx) {
             if let Some(completion) = self.take_active_inline_completion(cx) {
-                let mut partial_completion = completion
-                    .text
-                    .chars()
-                    .by_ref()
-                    .take_while(|c| c.is_alphabetic())
-                    .collect::<String>();
-                if partial_completion.is_empty() {
-                    partial_completion = completion
-                        .text
-                        .chars()
-                        .by_ref()
-                        .take_while(|c| c.is_whitespace() || !c.is_alphabetic())
-                        .collect::<String>();
-                }
+                let text = completion.text.to_string();
+                let partial_completion = segment(&text).to_string();
 
                 cx.emit(EditorEvent::InputHandled {
                     utf16_range_to_replace: None,
@@ -5452,12 +5482,49 @@ impl Editor {
                 }
                 self.insert_with_autoindent_mode(&partial_completion, None, cx);
 
-                self.refresh_inline_completion(true, true, cx);
+                let kept_remainder = maybe!({
+                    let provider = self.inline_completion_provider()?;
+                    let cursor = self.selections.newest_anchor().head();
+                    let (buffer, cursor_buffer_position) =
+                        self.buffer.read(cx).text_anchor_for_position(cursor, cx)?;
+                    Some(provider.accept_partial(
+                        &partial_completion,
+                        kind,
+                        &buffer,
+                        cursor_buffer_position,
+                        cx,
+                    ))
+                });
+                if kept_remainder == Some(true) {
+                    self.update_visible_inline_completion(cx);
+                } else {
+                    self.refresh_inline_completion(true, true, cx);
+                }
                 cx.notify();
             }
         }
     }
 
+    /// Formats the text of an accepted completion, from `start` to the cursor.
+    fn format_inserted_completion(&mut self, start: Point, cx: &mut ViewContext<Self>) {
+        let Some(project) = self.project.clone() else {
+            return;
+        };
+        let mut inserted = self.selections.newest::<Point>(cx);
+        if inserted.head() <= start {
+            return;
+        }
+        inserted.start = start;
+        inserted.reversed = false;
+        self.perform_format(
+            project,
+            FormatTrigger::Manual,
+            FormatTarget::Ranges(vec![inserted]),
+            cx,
+        )
+        .detach_and_log_err(cx);
+    }
+
     fn discard_inline_completion(
         &mut self,
         should_report_inline_completion_event: bool,
@@ -5470,6 +5537,22 @@ impl Editor {
         self.take_active_inline_completion(cx).is_some()
     }
 
+    /// Discards the active inline completion like [`Self::discard_inline_completion`],
+    /// and has the provider stop working on any it was still requesting, for
+    /// when the user has moved on.
+    fn discard_pending_inline_completion(
+        &mut self,
+        should_report_inline_completion_event: bool,
+        cx: &mut ViewContext<Self>,
+    ) -> bool {
+        if let Some(provider) = self.inline_completion_provider() {
+            provider.discard(should_report_inline_completion_event, cx);
+            provider.discard_pending(cx);
+        }
+
+        self.take_active_inline_completion(cx).is_some()
+    }
+
     pub fn has_active_inline_completion(&self, cx: &AppContext) -> bool {
         if let Some(completion) = self.active_inline_completion.as_ref() {
             let buffer = self.buffer.read(cx).read(cx);
@@ -5514,12 +5597,31 @@ impl Editor {
                     if let Some(proposal) =
                         provider.active_completion_text(&buffer, cursor_buffer_position, cx)
                     {
+                        let show_source = all_language_settings(None, cx)
+                            .inline_completions
+                            .show_source;
+                        if let Some(completion) = self.active_inline_completion.as_ref() {
+                            if completion.position == cursor
+                                && completion.proposal.shows_same_as(&proposal)
+                                && (!show_source
+                                    || completion.proposal.metadata == proposal.metadata)
+                            {
+                                return;
+                            }
+                        }
+                        if self.append_to_inline_completion(cursor, &proposal, cx) {
+                            if let Some(completion) = self.active_inline_completion.as_mut() {
+                                completion.proposal = proposal;
+                            }
+                            return;
+                        }
+
                         let mut to_remove = Vec::new();
                         if let Some(completion) = self.active_inline_completion.take() {
                             to_remove.extend(completion.render_inlay_ids.iter());
                         }
 
-                        let to_add = proposal
+                        let mut to_add = proposal
                             .inlays
                             .iter()
                             .filter_map(|inlay| {
@@ -5539,17 +5641,46 @@ impl Editor {
                                 }
                             })
                             .collect_vec();
-
+                        let source_hint =
+                            proposal.metadata.as_ref().filter(|_| show_source).and_then(
+                                |metadata| {
+                                    let snapshot = self.buffer.read(cx).snapshot(cx);
+                                    let hint = completion_source_hint(
+                                        &buffer,
+                                        cursor_buffer_position,
+                                        metadata,
+                                        cx,
+                                    );
+                                    let position =
+                                        snapshot.anchor_in_excerpt(excerpt_id, hint.position)?;
+                                    Some(Inlay::hint(
+                                        post_inc(&mut self.next_inlay_id),
+                                        position,
+                                        &hint,
+                                    ))
+                                },
+                            );
+                        // Text appended to the suggestion would land after
+                        // the source hint when both are at the end of the line.
+                        let appendable = source_hint.is_none()
+                            && proposal.delete_range.is_none()
+                            && matches!(
+                                proposal.inlays.as_slice(),
+                                [InlayProposal::Suggestion(..)]
+                            );
+                        to_add.extend(source_hint);
                         self.active_inline_completion = Some(CompletionState {
                             position: cursor,
-                            text: proposal.text,
-                            delete_range: proposal.delete_range.and_then(|range| {
+                            text: proposal.text.clone(),
+                            appendable,
+                            delete_range: proposal.delete_range.clone().and_then(|range| {
                                 let snapshot = self.buffer.read(cx).snapshot(cx);
                                 let start = snapshot.anchor_in_excerpt(excerpt_id, range.start);
                                 let end = snapshot.anchor_in_excerpt(excerpt_id, range.end);
                                 Some(start?..end?)
                             }),
                             render_inlay_ids: to_add.iter().map(|i| i.id).collect(),
+                            proposal,
                         });
 
                         self.display_map
@@ -5565,6 +5696,56 @@ impl Editor {
         self.discard_inline_completion(false, cx);
     }
 
+    /// Shows a proposal that only adds text to the end of the active inline
+    /// completion by inserting an inlay with the added text, rather than
+    /// replacing the completion's inlays, so a suggestion that grows as it's
+    /// streamed doesn't lay out everything it spans again on each update.
+    /// Returns false when the proposal has to be shown in full.
+    fn append_to_inline_completion(
+        &mut self,
+        cursor: Anchor,
+        proposal: &CompletionProposal,
+        cx: &mut ViewContext<Self>,
+    ) -> bool {
+        let Some(completion) = self.active_inline_completion.as_mut() else {
+            return false;
+        };
+        if !completion.appendable || completion.position != cursor {
+            return false;
+        }
+        let [InlayProposal::Suggestion(position, _)] = proposal.inlays.as_slice() else {
+            return false;
+        };
+        if proposal.delete_range.is_some() {
+            return false;
+        }
+        let Some(position) = self
+            .buffer
+            .read(cx)
+            .snapshot(cx)
+            .anchor_in_excerpt(cursor.excerpt_id, *position)
+        else {
+            return false;
+        };
+        let shown = completion.text.to_string();
+        let proposed = proposal.text.to_string();
+        let Some(appended) = proposed.strip_prefix(&shown) else {
+            return false;
+        };
+        if appended.is_empty() {
+            return true;
+        }
+
+        let id = post_inc(&mut self.next_inlay_id);
+        let inlay = Inlay::suggestion(id, position, appended);
+        completion.render_inlay_ids.push(inlay.id);
+        completion.text = proposal.text.clone();
+        self.display_map
+            .update(cx, |map, cx| map.splice_inlays(Vec::new(), vec![inlay], cx));
+        cx.notify();
+        true
+    }
+
     fn inline_completion_provider(&self) -> Option<Arc<dyn InlineCompletionProviderHandle>> {
         Some(self.inline_completion_provider.as_ref()?.provider.clone())
     }
@@ -10551,6 +10732,16 @@ impl Editor {
                         }
                     })
                     .detach();
+                    if let Some(provider) = this.inline_completion_provider() {
+                        rename_editor.update(cx, |rename_editor, cx| {
+                            provider.start_rename(
+                                rename_editor,
+                                &cursor_buffer,
+                                rename_range.clone(),
+                                cx,
+                            );
+                        });
+                    }
 
                     let write_highlights =
                         this.clear_background_highlights::<DocumentHighlightWrite>(cx);
@@ -12687,7 +12878,10 @@ impl Editor {
                 self.sync_expanded_diff_hunks(buffer.clone(), cx);
                 cx.notify();
             }
-            multi_buffer::Event::Closed => cx.emit(EditorEvent::Closed),
+            multi_buffer::Event::Closed => {
+                self.discard_pending_inline_completion(false, cx);
+                cx.emit(EditorEvent::Closed);
+            }
             multi_buffer::Event::DiagnosticsUpdated => {
                 self.refresh_active_diagnostics(cx);
                 self.scrollbar_marker_state.dirty = true;
@@ -13179,6 +13373,11 @@ impl Editor {
         }
 
         self.hide_context_menu(cx);
+        // The rename editor taking focus shares the provider, which may
+        // already be suggesting names there.
+        if self.pending_rename.is_none() {
+            self.discard_pending_inline_completion(false, cx);
+        }
         cx.emit(EditorEvent::Blurred);
         cx.notify();
     }
@@ -14055,6 +14254,68 @@ impl SemanticsProvider for Model<Project> {
     }
 }
 
+/// The leading word of an inline completion, or the punctuation and
+/// whitespace before it.
+fn next_word_segment(text: &str) -> &str {
+    let word_len = text
+        .chars()
+        .take_while(|c| c.is_alphabetic())
+        .map(char::len_utf8)
+        .sum::<usize>();
+    if word_len > 0 {
+        return &text[..word_len];
+    }
+    let separator_len = text
+        .chars()
+        .take_while(|c| c.is_whitespace() || !c.is_alphabetic())
+        .map(char::len_utf8)
+        .sum::<usize>();
+    &text[..separator_len]
+}
+
+/// The rest of the current line of an inline completion, or the next line
+/// when the completion starts with a line break.
+fn next_line_segment(text: &str) -> &str {
+    let line_start = text.len() - text.trim_start_matches('\n').len();
+    match text[line_start..].find('\n') {
+        Some(line_len) => &text[..line_start + line_len],
+        None => text,
+    }
+}
+
+/// A hint at the end of the cursor's line saying where the inline completion
+/// came from, so it trails the completion instead of splitting it.
+fn completion_source_hint(
+    buffer: &Model<Buffer>,
+    cursor: text::Anchor,
+    metadata: &CompletionMetadata,
+    cx: &AppContext,
+) -> InlayHint {
+    let buffer = buffer.read(cx);
+    let mut point = text::ToPoint::to_point(&cursor, buffer);
+    point.column = buffer.line_len(point.row);
+    let position = buffer.anchor_after(point);
+    InlayHint {
+        position,
+        label: project::InlayHintLabel::String(metadata.label()),
+        kind: None,
+        padding_left: true,
+        padding_right: false,
+        tooltip: Some(project::InlayHintTooltip::String(metadata.description())),
+        resolve_state: project::ResolveState::Resolved,
+    }
+}
+
+#[test]
+fn test_inline_completion_segments() {
+    assert_eq!(next_word_segment("foo(bar)"), "foo");
+    assert_eq!(next_word_segment("(bar)"), "(");
+    assert_eq!(next_word_segment("  \n  bar"), "  \n  ");
+    assert_eq!(next_line_segment("a + b;\n    c\n"), "a + b;");
+    assert_eq!(next_line_segment("\n    c\n}"), "\n    c");
+    assert_eq!(next_line_segment("last"), "last");
+}
+
 fn inlay_hint_settings(
     location: Anchor,
     snapshot: &MultiBufferSnapshot,
@@ -1,7 +1,7 @@
-use crate::Direction;
-use gpui::{AppContext, Model, ModelContext};
+use crate::{Direction, Editor};
+use gpui::{AppContext, Model, ModelContext, ViewContext};
 use language::Buffer;
-use std::ops::Range;
+use std::{ops::Range, time::Duration};
 use text::{Anchor, Rope};
 
 pub enum InlayProposal {
@@ -13,6 +13,125 @@ pub struct CompletionProposal {
     pub inlays: Vec<InlayProposal>,
     pub text: Rope,
     pub delete_range: Option<Range<Anchor>>,
+    /// Where the proposal came from, shown at the end of the cursor's line
+    /// when `inline_completions.show_source` is on.
+    pub metadata: Option<CompletionMetadata>,
+}
+
+/// How a proposal was generated: by which provider and model, and what it
+/// took.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompletionMetadata {
+    /// The provider's [`InlineCompletionProvider::name`].
+    pub provider: &'static str,
+    /// The model that generated the proposal, when the provider says.
+    pub model: Option<String>,
+    /// How many tokens the prompt was.
+    pub prompt_tokens: Option<u64>,
+    /// How many tokens were generated.
+    pub completion_tokens: Option<u64>,
+    /// Time from sending the request to receiving the full response.
+    pub latency: Option<Duration>,
+}
+
+impl CompletionMetadata {
+    pub fn new(provider: &'static str) -> Self {
+        Self {
+            provider,
+            ..Default::default()
+        }
+    }
+
+    /// A short label like "ollama · qwen2.5-coder:1.5b · 37 tokens · 240 ms".
+    pub fn label(&self) -> String {
+        let mut label = self.provider.to_string();
+        if let Some(model) = &self.model {
+            label.push_str(" · ");
+            label.push_str(model);
+        }
+        if let Some(completion_tokens) = self.completion_tokens {
+            label.push_str(&format!(" · {completion_tokens} tokens"));
+        }
+        if let Some(latency) = self.latency {
+            label.push_str(&format!(" · {} ms", latency.as_millis()));
+        }
+        label
+    }
+
+    /// Everything that's known about the proposal, for the label's tooltip.
+    pub fn description(&self) -> String {
+        let mut description = format!("Suggested by {}", self.provider);
+        if let Some(model) = &self.model {
+            description.push_str(&format!(" using {model}"));
+        }
+        if let Some(latency) = self.latency {
+            description.push_str(&format!(" in {} ms", latency.as_millis()));
+        }
+        match (self.prompt_tokens, self.completion_tokens) {
+            (Some(prompt), Some(completion)) => description.push_str(&format!(
+                ", from a {prompt}-token prompt, generating {completion} tokens"
+            )),
+            (Some(prompt), None) => {
+                description.push_str(&format!(", from a {prompt}-token prompt"))
+            }
+            (None, Some(completion)) => {
+                description.push_str(&format!(", generating {completion} tokens"))
+            }
+            (None, None) => {}
+        }
+        description
+    }
+}
+
+impl CompletionProposal {
+    /// Whether `other` is shown exactly like this proposal, so the inlays
+    /// showing this one can be kept for it.
+    pub fn shows_same_as(&self, other: &CompletionProposal) -> bool {
+        self.delete_range == other.delete_range
+            && same_text(&self.text, &other.text)
+            && self.inlays.len() == other.inlays.len()
+            && self
+                .inlays
+                .iter()
+                .zip(&other.inlays)
+                .all(|inlays| match inlays {
+                    (InlayProposal::Hint(a, a_hint), InlayProposal::Hint(b, b_hint)) => {
+                        a == b && a_hint == b_hint
+                    }
+                    (
+                        InlayProposal::Suggestion(a, a_text),
+                        InlayProposal::Suggestion(b, b_text),
+                    ) => a == b && same_text(a_text, b_text),
+                    _ => false,
+                })
+    }
+}
+
+fn same_text(a: &Rope, b: &Rope) -> bool {
+    a.len() == b.len() && a.chars().eq(b.chars())
+}
+
+/// What the editor does with the text of an accepted completion once it's
+/// inserted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AcceptedCompletionFormat {
+    /// Leave the text as the provider suggested it.
+    #[default]
+    None,
+    /// Reindent the inserted lines following the language's rules.
+    Indent,
+    /// Run the buffer's formatter over the inserted range.
+    Format,
+}
+
+/// How much of the active completion a partial accept inserted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PartialAccept {
+    /// The next word, from `editor::AcceptPartialInlineCompletion`.
+    Word,
+    /// The rest of the line, from
+    /// `editor::AcceptPartialInlineCompletionLine`.
+    Line,
 }
 
 pub trait InlineCompletionProvider: 'static + Sized {
@@ -38,13 +157,56 @@ pub trait InlineCompletionProvider: 'static + Sized {
         cx: &mut ModelContext<Self>,
     );
     fn accept(&mut self, cx: &mut ModelContext<Self>);
+    /// How the text of the active completion is tidied up when it's accepted
+    /// in `buffer`. Asked before [`Self::accept`].
+    fn accepted_format(
+        &self,
+        _buffer: &Model<Buffer>,
+        _cx: &AppContext,
+    ) -> AcceptedCompletionFormat {
+        AcceptedCompletionFormat::None
+    }
+    /// Called after the editor inserted `accepted_text`, a leading part of the
+    /// active completion, as the next `kind` of it. Providers that return
+    /// `true` keep proposing the rest of the completion at `cursor_position`
+    /// instead of being refreshed.
+    fn accept_partial(
+        &mut self,
+        _accepted_text: &str,
+        _kind: PartialAccept,
+        _buffer: &Model<Buffer>,
+        _cursor_position: language::Anchor,
+        _cx: &mut ModelContext<Self>,
+    ) -> bool {
+        false
+    }
     fn discard(&mut self, should_report_inline_completion_event: bool, cx: &mut ModelContext<Self>);
+    /// Called when the user has moved on: on Escape, when the editor loses
+    /// focus, and when its buffer is closed. Providers drop the active
+    /// completion and stop any request still in flight, so nothing they were
+    /// working on shows up afterwards.
+    fn discard_pending(&mut self, cx: &mut ModelContext<Self>) {
+        self.discard(false, cx);
+    }
     fn active_completion_text<'a>(
         &'a self,
         buffer: &Model<Buffer>,
         cursor_position: language::Anchor,
         cx: &'a AppContext,
     ) -> Option<CompletionProposal>;
+    /// Called when the editor starts renaming the symbol at `range` in
+    /// `buffer`, with the buffer of the single-line editor the new name is
+    /// typed in. Providers that return `true` are also used in that editor,
+    /// to suggest names.
+    fn start_rename(
+        &mut self,
+        _rename_buffer: &Model<Buffer>,
+        _buffer: &Model<Buffer>,
+        _range: Range<language::Anchor>,
+        _cx: &mut ModelContext<Self>,
+    ) -> bool {
+        false
+    }
 }
 
 pub trait InlineCompletionProviderHandle {
@@ -69,13 +231,32 @@ pub trait InlineCompletionProviderHandle {
         cx: &mut AppContext,
     );
     fn accept(&self, cx: &mut AppContext);
+    fn accepted_format(&self, buffer: &Model<Buffer>, cx: &AppContext) -> AcceptedCompletionFormat;
+    fn accept_partial(
+        &self,
+        accepted_text: &str,
+        kind: PartialAccept,
+        buffer: &Model<Buffer>,
+        cursor_position: language::Anchor,
+        cx: &mut AppContext,
+    ) -> bool;
     fn discard(&self, should_report_inline_completion_event: bool, cx: &mut AppContext);
+    fn discard_pending(&self, cx: &mut AppContext);
     fn active_completion_text<'a>(
         &'a self,
         buffer: &Model<Buffer>,
         cursor_position: language::Anchor,
         cx: &'a AppContext,
     ) -> Option<CompletionProposal>;
+    /// Offers the provider to `rename_editor`, which renames the symbol at
+    /// `range` in `buffer`, and registers it there if it accepts.
+    fn start_rename(
+        &self,
+        rename_editor: &mut Editor,
+        buffer: &Model<Buffer>,
+        range: Range<language::Anchor>,
+        cx: &mut ViewContext<Editor>,
+    );
 }
 
 impl<T> InlineCompletionProviderHandle for Model<T>
@@ -119,12 +300,33 @@ where
         self.update(cx, |this, cx| this.accept(cx))
     }
 
+    fn accepted_format(&self, buffer: &Model<Buffer>, cx: &AppContext) -> AcceptedCompletionFormat {
+        self.read(cx).accepted_format(buffer, cx)
+    }
+
+    fn accept_partial(
+        &self,
+        accepted_text: &str,
+        kind: PartialAccept,
+        buffer: &Model<Buffer>,
+        cursor_position: language::Anchor,
+        cx: &mut AppContext,
+    ) -> bool {
+        self.update(cx, |this, cx| {
+            this.accept_partial(accepted_text, kind, buffer, cursor_position, cx)
+        })
+    }
+
     fn discard(&self, should_report_inline_completion_event: bool, cx: &mut AppContext) {
         self.update(cx, |this, cx| {
             this.discard(should_report_inline_completion_event, cx)
         })
     }
 
+    fn discard_pending(&self, cx: &mut AppContext) {
+        self.update(cx, |this, cx| this.discard_pending(cx))
+    }
+
     fn active_completion_text<'a>(
         &'a self,
         buffer: &Model<Buffer>,
@@ -134,4 +336,23 @@ where
         self.read(cx)
             .active_completion_text(buffer, cursor_position, cx)
     }
+
+    fn start_rename(
+        &self,
+        rename_editor: &mut Editor,
+        buffer: &Model<Buffer>,
+        range: Range<language::Anchor>,
+        cx: &mut ViewContext<Editor>,
+    ) {
+        let Some(rename_buffer) = rename_editor.buffer().read(cx).as_singleton() else {
+            return;
+        };
+        if self.update(cx, |this, cx| {
+            this.start_rename(&rename_buffer, buffer, range, cx)
+        }) {
+            // Single-line editors don't show inline completions otherwise.
+            rename_editor.show_inline_completions_override = Some(true);
+            rename_editor.set_inline_completion_provider(Some(self.clone()), cx);
+        }
+    }
 }
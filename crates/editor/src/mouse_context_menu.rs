@@ -7,9 +7,11 @@ use crate::{
 };
 use gpui::prelude::FluentBuilder;
 use gpui::{DismissEvent, Pixels, Point, Subscription, View, ViewContext};
+use language::language_settings::{all_language_settings, InlineCompletionProvider};
 use std::ops::Range;
 use text::PointUtf16;
 use workspace::OpenInTerminal;
+use zed_actions::ollama;
 
 #[derive(Debug)]
 pub enum MenuPosition {
@@ -170,6 +172,8 @@ pub fn deploy_context_menu(
             .all::<PointUtf16>(cx)
             .into_iter()
             .any(|s| !s.is_empty());
+        let has_ollama_actions = all_language_settings(None, cx).inline_completions.provider
+            == InlineCompletionProvider::Ollama;
 
         ui::ContextMenu::build(cx, |menu, _cx| {
             let builder = menu
@@ -191,6 +195,17 @@ pub fn deploy_context_menu(
                         deployed_from_indicator: None,
                     }),
                 )
+                .when(has_ollama_actions, |builder| {
+                    builder
+                        .separator()
+                        .action("Explain Selection", Box::new(ollama::ExplainSelection))
+                        .action("Rewrite Selection", Box::new(ollama::RewriteSelection))
+                        .action(
+                            "Generate Tests",
+                            Box::new(ollama::GenerateTestsForSelection),
+                        )
+                        .action("Document Symbol", Box::new(ollama::DocumentSymbol))
+                })
                 .separator()
                 .action("Cut", Box::new(Cut))
                 .action("Copy", Box::new(Copy))
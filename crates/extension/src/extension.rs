@@ -95,6 +95,13 @@ pub trait Extension: Send + Sync + 'static {
         package_name: Arc<str>,
         kv_store: Arc<dyn KeyValueStoreDelegate>,
     ) -> Result<()>;
+
+    async fn provide_completion_context(
+        &self,
+        provider_id: Arc<str>,
+        context: CompletionContext,
+        worktree: Option<Arc<dyn WorktreeDelegate>>,
+    ) -> Result<String>;
 }
 
 pub fn parse_wasm_extension_version(
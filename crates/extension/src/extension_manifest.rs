@@ -82,6 +82,8 @@ pub struct ExtensionManifest {
     #[serde(default)]
     pub indexed_docs_providers: BTreeMap<Arc<str>, IndexedDocsProviderEntry>,
     #[serde(default)]
+    pub completion_context_providers: BTreeMap<Arc<str>, CompletionContextProviderEntry>,
+    #[serde(default)]
     pub snippets: Option<PathBuf>,
 }
 
@@ -149,6 +151,14 @@ pub struct SlashCommandManifestEntry {
 #[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
 pub struct IndexedDocsProviderEntry {}
 
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct CompletionContextProviderEntry {
+    /// The languages whose files the provider adds context for. When empty,
+    /// it's asked for files in any language.
+    #[serde(default)]
+    pub languages: Vec<LanguageName>,
+}
+
 impl ExtensionManifest {
     pub async fn load(fs: Arc<dyn Fs>, extension_dir: &Path) -> Result<Self> {
         let extension_name = extension_dir
@@ -214,6 +224,7 @@ fn manifest_from_old_manifest(
         context_servers: BTreeMap::default(),
         slash_commands: BTreeMap::default(),
         indexed_docs_providers: BTreeMap::default(),
+        completion_context_providers: BTreeMap::default(),
         snippets: None,
     }
 }
@@ -1,8 +1,10 @@
+mod completion_context;
 mod lsp;
 mod slash_command;
 
 use std::ops::Range;
 
+pub use completion_context::*;
 pub use lsp::*;
 pub use slash_command::*;
 
@@ -0,0 +1,12 @@
+/// The text around the cursor that an inline completion is requested for.
+#[derive(Debug, Clone)]
+pub struct CompletionContext {
+    /// The path of the file, relative to its worktree.
+    pub path: String,
+    /// The name of the file's language, or an empty string if it has none.
+    pub language: String,
+    /// The text before the cursor.
+    pub prefix: String,
+    /// The text after the cursor.
+    pub suffix: String,
+}
@@ -15,6 +15,7 @@ pub use serde_json;
 // that we may want to shadow to provide a cleaner Rust API.
 pub use wit::{
     download_file, make_file_executable,
+    zed::extension::completion_context::CompletionContext,
     zed::extension::github::{
         github_release_by_tag_name, latest_github_release, GithubRelease, GithubReleaseAsset,
         GithubReleaseOptions,
@@ -156,6 +157,18 @@ pub trait Extension: Send + Sync {
     ) -> Result<(), String> {
         Err("`index_docs` not implemented".to_string())
     }
+
+    /// Returns extra context for the prompt of an inline completion requested
+    /// in the given context, such as definitions from other files that the
+    /// code around the cursor relies on.
+    fn provide_completion_context(
+        &self,
+        _provider_id: String,
+        _context: CompletionContext,
+        _worktree: Option<&Worktree>,
+    ) -> Result<String, String> {
+        Ok(String::new())
+    }
 }
 
 /// Registers the provided type as a Zed extension.
@@ -195,7 +208,7 @@ mod wit {
 
     wit_bindgen::generate!({
         skip: ["init-extension"],
-        path: "./wit/since_v0.2.0",
+        path: "./wit/since_v0.3.0",
     });
 }
 
@@ -298,6 +311,14 @@ impl wit::Guest for Component {
     ) -> Result<(), String> {
         extension().index_docs(provider, package, database)
     }
+
+    fn provide_completion_context(
+        provider_id: String,
+        context: CompletionContext,
+        worktree: Option<&Worktree>,
+    ) -> Result<String, String> {
+        extension().provide_completion_context(provider_id, context, worktree)
+    }
 }
 
 /// The ID of a language server.
@@ -150,6 +150,16 @@ pub trait ExtensionRegistrationHooks: Send + Sync + 'static {
 
     fn register_docs_provider(&self, _extension: Arc<dyn Extension>, _provider_id: Arc<str>) {}
 
+    fn register_completion_context_provider(
+        &self,
+        _extension: Arc<dyn Extension>,
+        _provider_id: Arc<str>,
+        _languages: Vec<LanguageName>,
+    ) {
+    }
+
+    fn remove_completion_context_provider(&self, _provider_id: &Arc<str>) {}
+
     fn register_snippets(&self, _path: &PathBuf, _snippet_contents: &str) -> Result<()> {
         Ok(())
     }
@@ -1093,6 +1103,10 @@ impl ExtensionStore {
                         .remove_lsp_adapter(&language, language_server_name);
                 }
             }
+            for provider_id in extension.manifest.completion_context_providers.keys() {
+                self.registration_hooks
+                    .remove_completion_context_provider(provider_id);
+            }
         }
 
         self.wasm_extensions
@@ -1273,6 +1287,14 @@ impl ExtensionStore {
                         this.registration_hooks
                             .register_docs_provider(extension.clone(), provider_id.clone());
                     }
+
+                    for (provider_id, provider) in &manifest.completion_context_providers {
+                        this.registration_hooks.register_completion_context_provider(
+                            extension.clone(),
+                            provider_id.clone(),
+                            provider.languages.clone(),
+                        );
+                    }
                 }
 
                 this.wasm_extensions.extend(wasm_extensions);
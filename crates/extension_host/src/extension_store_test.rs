@@ -240,6 +240,7 @@ async fn test_extension_store(cx: &mut TestAppContext) {
                         context_servers: BTreeMap::default(),
                         slash_commands: BTreeMap::default(),
                         indexed_docs_providers: BTreeMap::default(),
+                        completion_context_providers: BTreeMap::default(),
                         snippets: None,
                     }),
                     dev: false,
@@ -267,6 +268,7 @@ async fn test_extension_store(cx: &mut TestAppContext) {
                         context_servers: BTreeMap::default(),
                         slash_commands: BTreeMap::default(),
                         indexed_docs_providers: BTreeMap::default(),
+                        completion_context_providers: BTreeMap::default(),
                         snippets: None,
                     }),
                     dev: false,
@@ -430,6 +432,7 @@ async fn test_extension_store(cx: &mut TestAppContext) {
                 context_servers: BTreeMap::default(),
                 slash_commands: BTreeMap::default(),
                 indexed_docs_providers: BTreeMap::default(),
+                completion_context_providers: BTreeMap::default(),
                 snippets: None,
             }),
             dev: false,
@@ -4,7 +4,7 @@ use crate::{ExtensionManifest, ExtensionRegistrationHooks};
 use anyhow::{anyhow, bail, Context as _, Result};
 use async_trait::async_trait;
 use extension::{
-    CodeLabel, Command, Completion, KeyValueStoreDelegate, SlashCommand,
+    CodeLabel, Command, Completion, CompletionContext, KeyValueStoreDelegate, SlashCommand,
     SlashCommandArgumentCompletion, SlashCommandOutput, Symbol, WorktreeDelegate,
 };
 use fs::{normalize_path, Fs};
@@ -278,6 +278,37 @@ impl extension::Extension for WasmExtension {
         })
         .await
     }
+
+    async fn provide_completion_context(
+        &self,
+        provider_id: Arc<str>,
+        context: CompletionContext,
+        delegate: Option<Arc<dyn WorktreeDelegate>>,
+    ) -> Result<String> {
+        self.call(|extension, store| {
+            async move {
+                let resource = if let Some(delegate) = delegate {
+                    Some(store.data_mut().table().push(delegate)?)
+                } else {
+                    None
+                };
+
+                let context = extension
+                    .call_provide_completion_context(
+                        store,
+                        provider_id.as_ref(),
+                        &context.into(),
+                        resource,
+                    )
+                    .await?
+                    .map_err(|err| anyhow!("{err}"))?;
+
+                Ok(context)
+            }
+            .boxed()
+        })
+        .await
+    }
 }
 
 pub struct WasmState {
@@ -11,7 +11,8 @@ use extension_host::{extension_lsp_adapter::ExtensionLspAdapter, wasm_host};
 use fs::Fs;
 use gpui::{AppContext, BackgroundExecutor, Model, Task};
 use indexed_docs::{ExtensionIndexedDocsProvider, IndexedDocsRegistry, ProviderId};
-use language::{LanguageRegistry, LanguageServerBinaryStatus, LoadedLanguage};
+use language::{LanguageName, LanguageRegistry, LanguageServerBinaryStatus, LoadedLanguage};
+use ollama_completions::{CompletionContextProviders, ExtensionCompletionContextProvider};
 use snippet_provider::SnippetRegistry;
 use theme::{ThemeRegistry, ThemeSettings};
 use ui::SharedString;
@@ -24,6 +25,7 @@ pub struct ConcreteExtensionRegistrationHooks {
     snippet_registry: Arc<SnippetRegistry>,
     language_registry: Arc<LanguageRegistry>,
     context_server_factory_registry: Model<ContextServerFactoryRegistry>,
+    completion_context_providers: Arc<CompletionContextProviders>,
     executor: BackgroundExecutor,
 }
 
@@ -35,6 +37,7 @@ impl ConcreteExtensionRegistrationHooks {
         snippet_registry: Arc<SnippetRegistry>,
         language_registry: Arc<LanguageRegistry>,
         context_server_factory_registry: Model<ContextServerFactoryRegistry>,
+        completion_context_providers: Arc<CompletionContextProviders>,
         cx: &AppContext,
     ) -> Arc<dyn extension_host::ExtensionRegistrationHooks> {
         Arc::new(Self {
@@ -44,6 +47,7 @@ impl ConcreteExtensionRegistrationHooks {
             snippet_registry,
             language_registry,
             context_server_factory_registry,
+            completion_context_providers,
             executor: cx.background_executor().clone(),
         })
     }
@@ -143,6 +147,21 @@ impl extension_host::ExtensionRegistrationHooks for ConcreteExtensionRegistratio
             )));
     }
 
+    fn register_completion_context_provider(
+        &self,
+        extension: Arc<dyn Extension>,
+        provider_id: Arc<str>,
+        languages: Vec<LanguageName>,
+    ) {
+        self.completion_context_providers.register(Arc::new(
+            ExtensionCompletionContextProvider::new(extension, provider_id, languages),
+        ));
+    }
+
+    fn remove_completion_context_provider(&self, provider_id: &Arc<str>) {
+        self.completion_context_providers.remove(provider_id);
+    }
+
     fn register_snippets(&self, path: &PathBuf, snippet_contents: &str) -> Result<()> {
         self.snippet_registry
             .register_snippets(path, snippet_contents)
@@ -46,6 +46,9 @@ pub trait GitRepository: Send + Sync {
 
     fn blame(&self, path: &Path, content: Rope) -> Result<crate::blame::Blame>;
 
+    /// Returns the changes staged for the next commit, as a unified diff.
+    fn staged_diff(&self) -> Result<String>;
+
     fn path(&self) -> PathBuf;
 }
 
@@ -224,6 +227,22 @@ impl GitRepository for RealGitRepository {
             self.hosting_provider_registry.clone(),
         )
     }
+
+    fn staged_diff(&self) -> Result<String> {
+        let repo = self.repository.lock();
+        // An unborn branch has no HEAD tree, so everything in the index is new.
+        let head_tree = repo.head().ok().and_then(|head| head.peel_to_tree().ok());
+        let diff = repo.diff_tree_to_index(head_tree.as_ref(), None, None)?;
+        let mut patch = String::new();
+        diff.print(git2::DiffFormat::Patch, |_, _, line| {
+            if matches!(line.origin(), '+' | '-' | ' ') {
+                patch.push(line.origin());
+            }
+            patch.push_str(&String::from_utf8_lossy(line.content()));
+            true
+        })?;
+        Ok(patch)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -238,6 +257,7 @@ pub struct FakeGitRepositoryState {
     pub index_contents: HashMap<PathBuf, String>,
     pub blames: HashMap<PathBuf, Blame>,
     pub worktree_statuses: HashMap<RepoPath, GitFileStatus>,
+    pub staged_diff: String,
     pub current_branch_name: Option<String>,
     pub branches: HashSet<String>,
 }
@@ -256,6 +276,7 @@ impl FakeGitRepositoryState {
             index_contents: Default::default(),
             blames: Default::default(),
             worktree_statuses: Default::default(),
+            staged_diff: Default::default(),
             current_branch_name: Default::default(),
             branches: Default::default(),
         }
@@ -357,6 +378,11 @@ impl GitRepository for FakeGitRepository {
             .with_context(|| format!("failed to get blame for {:?}", path))
             .cloned()
     }
+
+    fn staged_diff(&self) -> Result<String> {
+        let state = self.state.lock();
+        Ok(state.staged_diff.clone())
+    }
 }
 
 fn check_path_to_repo_path_errors(relative_file_path: &Path) -> Result<()> {
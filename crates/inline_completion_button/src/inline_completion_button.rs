@@ -1,10 +1,22 @@
+mod ollama_chat_history;
+mod ollama_completion_stats;
+mod ollama_errors;
+mod ollama_loaded_models;
+mod ollama_model_benchmark;
+mod ollama_model_pull;
+mod ollama_model_selector;
+mod ollama_model_variant;
+mod ollama_performance_report;
+mod ollama_request_log;
+
 use anyhow::Result;
 use copilot::{Copilot, CopilotCodeVerification, Status};
 use editor::{scroll::Autoscroll, Editor};
 use fs::Fs;
 use gpui::{
-    div, Action, AnchorCorner, AppContext, AsyncWindowContext, Entity, IntoElement, ParentElement,
-    Render, Subscription, View, ViewContext, WeakView, WindowContext,
+    div, prelude::FluentBuilder as _, Action, AnchorCorner, AppContext, AsyncWindowContext,
+    Context as _, Entity, IntoElement, ParentElement, Render, Subscription, View, ViewContext,
+    WeakView, WindowContext,
 };
 use language::{
     language_settings::{
@@ -12,6 +24,7 @@ use language::{
     },
     File, Language,
 };
+use ollama_loaded_models::{render_loaded_models, LoadedModels};
 use settings::{update_settings_file, Settings, SettingsStore};
 use std::{path::Path, sync::Arc};
 use supermaven::{AccountStatus, Supermaven};
@@ -21,7 +34,8 @@ use workspace::{
     item::ItemHandle,
     notifications::NotificationId,
     ui::{
-        ButtonCommon, Clickable, ContextMenu, IconButton, IconName, IconSize, PopoverMenu, Tooltip,
+        h_flex, Button, ButtonCommon, Clickable, Color, ContextMenu, IconButton, IconName,
+        IconSize, LabelSize, PopoverMenu, Tooltip,
     },
     StatusItemView, Toast, Workspace,
 };
@@ -29,6 +43,17 @@ use zed_actions::OpenBrowser;
 
 const COPILOT_SETTINGS_URL: &str = "https://github.com/settings/copilot";
 
+pub fn init(cx: &mut AppContext) {
+    ollama_chat_history::init(cx);
+    ollama_completion_stats::init(cx);
+    ollama_errors::init(cx);
+    ollama_model_benchmark::init(cx);
+    ollama_model_selector::init(cx);
+    ollama_model_variant::init(cx);
+    ollama_performance_report::init(cx);
+    ollama_request_log::init(cx);
+}
+
 struct CopilotStartingToast;
 
 struct CopilotErrorToast;
@@ -36,6 +61,8 @@ struct CopilotErrorToast;
 pub struct InlineCompletionButton {
     editor_subscription: Option<(Subscription, usize)>,
     editor_enabled: Option<bool>,
+    /// Whether Ollama completions were turned off for the active buffer.
+    ollama_buffer_disabled: bool,
     language: Option<Arc<Language>>,
     file: Option<Arc<dyn File>>,
     fs: Arc<dyn Fs>,
@@ -196,6 +223,109 @@ impl Render for InlineCompletionButton {
                         ),
                 );
             }
+
+            InlineCompletionProvider::Ollama => {
+                let enabled = self.editor_enabled.unwrap_or_else(|| {
+                    all_language_settings.inline_completions_enabled(None, None, cx)
+                }) && !self.ollama_buffer_disabled;
+                let this = cx.view().clone();
+                let generating = ollama_completions::Generations::global(cx)
+                    .map_or(false, |generations| !generations.read(cx).is_empty());
+                let (offline, throttled) = ollama_completions::Connectivity::global(cx).map_or(
+                    (false, false),
+                    |connectivity| {
+                        let connectivity = connectivity.read(cx);
+                        (connectivity.is_offline(), connectivity.is_throttled())
+                    },
+                );
+                let error = ollama_completions::ErrorReports::global(cx)
+                    .and_then(|reports| reports.read(cx).current().map(ToString::to_string));
+                let speed = ollama_completions::GenerationSpeed::global(cx)
+                    .filter(|_| {
+                        ollama_completions::OllamaSettings::get_global(cx).show_generation_speed
+                    })
+                    .and_then(|speed| speed.read(cx).label());
+
+                h_flex()
+                    .when_some(speed, |row, speed| {
+                        row.child(
+                            Button::new("ollama-speed", speed)
+                                .label_size(LabelSize::Small)
+                                .color(Color::Muted)
+                                .tooltip(|cx| {
+                                    Tooltip::with_meta(
+                                        "Completion Speed",
+                                        Some(&zed_actions::ollama::ShowCompletionStats),
+                                        "Recent generation speed and the last suggestion's latency",
+                                        cx,
+                                    )
+                                })
+                                .on_click(|_, cx| {
+                                    cx.dispatch_action(
+                                        zed_actions::ollama::ShowCompletionStats.boxed_clone(),
+                                    )
+                                }),
+                        )
+                    })
+                    .when(generating, |row| {
+                        row.child(
+                            IconButton::new("ollama-stop", IconName::Stop)
+                                .icon_color(Color::Error)
+                                .tooltip(|cx| {
+                                    Tooltip::for_action(
+                                        "Stop Generating",
+                                        &zed_actions::ollama::StopGenerating,
+                                        cx,
+                                    )
+                                })
+                                .on_click(|_, cx| {
+                                    cx.dispatch_action(
+                                        zed_actions::ollama::StopGenerating.boxed_clone(),
+                                    )
+                                }),
+                        )
+                    })
+                    .child(
+                        PopoverMenu::new("ollama")
+                            .menu(move |cx| {
+                                Some(this.update(cx, |this, cx| this.build_ollama_context_menu(cx)))
+                            })
+                            .anchor(AnchorCorner::BottomRight)
+                            .trigger(
+                                IconButton::new("ollama-icon", IconName::AiOllama)
+                                    .icon_color(if !enabled {
+                                        Color::Disabled
+                                    } else if error.is_some() && !offline {
+                                        Color::Error
+                                    } else if offline || throttled {
+                                        Color::Warning
+                                    } else {
+                                        Color::Default
+                                    })
+                                    .tooltip(move |cx| {
+                                        if let Some(error) = error.as_ref().filter(|_| !offline) {
+                                            Tooltip::with_meta("Ollama", None, error.clone(), cx)
+                                        } else if offline {
+                                            Tooltip::with_meta(
+                                                "Ollama",
+                                                None,
+                                                "Server unreachable, retrying in the background",
+                                                cx,
+                                            )
+                                        } else if throttled {
+                                            Tooltip::with_meta(
+                                                "Ollama",
+                                                None,
+                                                "Server busy, completions paused briefly",
+                                                cx,
+                                            )
+                                        } else {
+                                            Tooltip::text("Ollama", cx)
+                                        }
+                                    }),
+                            ),
+                    )
+            }
         }
     }
 }
@@ -206,12 +336,29 @@ impl InlineCompletionButton {
             cx.observe(&copilot, |_, _, cx| cx.notify()).detach()
         }
 
+        if let Some(generations) = ollama_completions::Generations::global(cx) {
+            cx.observe(&generations, |_, _, cx| cx.notify()).detach()
+        }
+
+        if let Some(connectivity) = ollama_completions::Connectivity::global(cx) {
+            cx.observe(&connectivity, |_, _, cx| cx.notify()).detach()
+        }
+
+        if let Some(error_reports) = ollama_completions::ErrorReports::global(cx) {
+            cx.observe(&error_reports, |_, _, cx| cx.notify()).detach()
+        }
+
+        if let Some(speed) = ollama_completions::GenerationSpeed::global(cx) {
+            cx.observe(&speed, |_, _, cx| cx.notify()).detach()
+        }
+
         cx.observe_global::<SettingsStore>(move |_, cx| cx.notify())
             .detach();
 
         Self {
             editor_subscription: None,
             editor_enabled: None,
+            ollama_buffer_disabled: false,
             language: None,
             file: None,
             fs,
@@ -328,12 +475,103 @@ impl InlineCompletionButton {
         })
     }
 
+    fn build_ollama_context_menu(&self, cx: &mut ViewContext<Self>) -> View<ContextMenu> {
+        let loaded_models = cx.new_model(LoadedModels::new);
+        ContextMenu::build(cx, |menu, cx| {
+            cx.observe(&loaded_models, |_, _, cx| cx.notify()).detach();
+            let settings = ollama_completions::OllamaSettings::get_global(cx);
+            let model = settings.model.clone();
+            // Models without fill-in-the-middle only see the text before the
+            // cursor, unless a prompt template is set for them.
+            let resolved_model = settings.resolve_model(&model);
+            let prefix_only = !settings.raw
+                && settings.prompt_template_for(resolved_model).is_none()
+                && !ollama_completions::ContextWindows::global(cx)
+                    .supports_fim(&settings.preferred_endpoint(cx), resolved_model);
+            let connectivity = ollama_completions::Connectivity::global(cx)
+                .filter(|connectivity| connectivity.read(cx).is_offline());
+            let token_usage = ollama::TokenUsage::global();
+            let completion_tokens = token_usage
+                .total(ollama::TokenUsageKind::Completion)
+                .total();
+            let chat_tokens = token_usage.total(ollama::TokenUsageKind::Chat).total();
+            self.build_language_settings_menu(menu, cx)
+                .when(self.editor_enabled.is_some(), |menu| {
+                    menu.action(
+                        if self.ollama_buffer_disabled {
+                            "Enable Ollama for This Buffer"
+                        } else {
+                            "Disable Ollama for This Buffer"
+                        },
+                        zed_actions::ollama::ToggleBufferCompletions.boxed_clone(),
+                    )
+                })
+                .separator()
+                .when_some(connectivity, |menu, connectivity| {
+                    menu.entry("Retry Connection", None, move |cx| {
+                        connectivity.update(cx, |connectivity, cx| connectivity.retry_now(cx))
+                    })
+                })
+                .action(
+                    format!("Change Model ({model})"),
+                    zed_actions::ollama::SelectModel.boxed_clone(),
+                )
+                .when(prefix_only, |menu| {
+                    menu.label("Prefix Only: Model Can't Fill in the Middle")
+                })
+                .action(
+                    "Show Acceptance Rates",
+                    zed_actions::ollama::ShowCompletionStats.boxed_clone(),
+                )
+                .action(
+                    "Show Performance Report",
+                    zed_actions::ollama::ShowPerformanceReport.boxed_clone(),
+                )
+                .when(completion_tokens + chat_tokens > 0, |menu| {
+                    menu.label(format!(
+                        "Tokens This Session: {} Completions, {} Chat",
+                        ollama::format_token_count(completion_tokens),
+                        ollama::format_token_count(chat_tokens),
+                    ))
+                })
+                .action(
+                    "Benchmark Models",
+                    zed_actions::ollama::BenchmarkModels.boxed_clone(),
+                )
+                .action(
+                    "New Model Variant",
+                    zed_actions::ollama::NewModelVariant.boxed_clone(),
+                )
+                .action(
+                    "Open Request Log",
+                    zed_actions::ollama::OpenRequestLog.boxed_clone(),
+                )
+                .action(
+                    "Chat History",
+                    zed_actions::ollama::OpenChatHistory.boxed_clone(),
+                )
+                .action("Import Chat", zed_actions::ollama::ImportChat.boxed_clone())
+                .separator()
+                .header("Loaded Models")
+                .custom_row(move |cx| render_loaded_models(&loaded_models, cx))
+        })
+    }
+
     pub fn update_enabled(&mut self, editor: View<Editor>, cx: &mut ViewContext<Self>) {
         let editor = editor.read(cx);
         let snapshot = editor.buffer().read(cx).snapshot(cx);
         let suggestion_anchor = editor.selections.newest_anchor().start;
         let language = snapshot.language_at(suggestion_anchor);
         let file = snapshot.file_at(suggestion_anchor).cloned();
+        self.ollama_buffer_disabled = editor
+            .buffer()
+            .read(cx)
+            .text_anchor_for_position(suggestion_anchor, cx)
+            .zip(ollama_completions::DisabledBuffers::global(cx))
+            .map_or(false, |((buffer, _), disabled)| {
+                let project = editor.project().map(|project| project.entity_id());
+                disabled.is_disabled(buffer.read(cx), project, cx)
+            });
         self.editor_enabled = {
             let file = file.as_ref();
             Some(
@@ -364,6 +602,7 @@ impl StatusItemView for InlineCompletionButton {
             self.language = None;
             self.editor_subscription = None;
             self.editor_enabled = None;
+            self.ollama_buffer_disabled = false;
         }
         cx.notify();
     }
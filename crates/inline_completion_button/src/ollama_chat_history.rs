@@ -0,0 +1,227 @@
+use chrono::Local;
+use fuzzy::{match_strings, StringMatch, StringMatchCandidate};
+use gpui::{
+    AppContext, DismissEvent, EventEmitter, FocusHandle, FocusableView, ParentElement,
+    PathPromptOptions, Render, Styled, Task, View, ViewContext, VisualContext, WeakView,
+};
+use ollama_completions::{chat_editor, import_chat, ChatHistory, ChatThread, OllamaSettings};
+use picker::{Picker, PickerDelegate};
+use settings::Settings as _;
+use std::sync::Arc;
+use ui::{prelude::*, HighlightedLabel, ListItem, ListItemSpacing};
+use util::ResultExt;
+use workspace::{notifications::DetachAndPromptErr as _, ModalView, Workspace};
+use zed_actions::ollama::{ImportChat, OpenChatHistory};
+
+pub fn init(cx: &mut AppContext) {
+    cx.observe_new_views(OllamaChatHistory::register).detach();
+}
+
+/// Lists past Ollama chats, most recent first. Confirming resumes the chosen
+/// chat, and secondary confirm branches it into a new chat that is saved
+/// separately.
+pub struct OllamaChatHistory {
+    picker: View<Picker<OllamaChatHistoryDelegate>>,
+}
+
+impl OllamaChatHistory {
+    fn register(workspace: &mut Workspace, _: &mut ViewContext<Workspace>) {
+        workspace.register_action(|workspace, _: &OpenChatHistory, cx| {
+            let workspace_handle = cx.view().downgrade();
+            workspace.toggle_modal(cx, |cx| Self::new(workspace_handle, cx));
+        });
+        workspace.register_action(|workspace, _: &ImportChat, cx| {
+            import_chat_file(workspace, cx);
+        });
+    }
+
+    fn new(workspace: WeakView<Workspace>, cx: &mut ViewContext<Self>) -> Self {
+        let delegate = OllamaChatHistoryDelegate::new(cx.view().downgrade(), workspace, cx);
+        let picker = cx.new_view(|cx| Picker::uniform_list(delegate, cx));
+        Self { picker }
+    }
+}
+
+/// Opens a chat exported with `ollama: export chat`, to be continued, and adds
+/// it to the history.
+fn import_chat_file(workspace: &mut Workspace, cx: &mut ViewContext<Workspace>) {
+    let paths = cx.prompt_for_paths(PathPromptOptions {
+        files: true,
+        directories: false,
+        multiple: false,
+    });
+    let fs = workspace.app_state().fs.clone();
+    cx.spawn(|workspace, mut cx| async move {
+        let Some(path) = paths.await??.and_then(|paths| paths.into_iter().next()) else {
+            return Ok(());
+        };
+        let text = fs.load(&path).await?;
+        workspace.update(&mut cx, |workspace, cx| {
+            let settings = OllamaSettings::get_global(cx);
+            let thread = import_chat(&text, settings.resolve_model(&settings.model))?;
+            ChatHistory::global(cx).save(thread.clone(), cx);
+            let project = workspace.project().clone();
+            let editor = chat_editor(thread, project, false, cx);
+            workspace.add_item_to_active_pane(Box::new(editor), None, true, cx);
+            anyhow::Ok(())
+        })?
+    })
+    .detach_and_prompt_err("Failed to import chat", cx, |_, _| None);
+}
+
+impl Render for OllamaChatHistory {
+    fn render(&mut self, _cx: &mut ViewContext<Self>) -> impl IntoElement {
+        v_flex().w(rems(34.)).child(self.picker.clone())
+    }
+}
+
+impl FocusableView for OllamaChatHistory {
+    fn focus_handle(&self, cx: &AppContext) -> FocusHandle {
+        self.picker.focus_handle(cx)
+    }
+}
+
+impl EventEmitter<DismissEvent> for OllamaChatHistory {}
+impl ModalView for OllamaChatHistory {}
+
+pub struct OllamaChatHistoryDelegate {
+    history: WeakView<OllamaChatHistory>,
+    workspace: WeakView<Workspace>,
+    threads: Vec<ChatThread>,
+    matches: Vec<StringMatch>,
+    selected_index: usize,
+}
+
+impl OllamaChatHistoryDelegate {
+    fn new(
+        history: WeakView<OllamaChatHistory>,
+        workspace: WeakView<Workspace>,
+        cx: &AppContext,
+    ) -> Self {
+        Self {
+            history,
+            workspace,
+            threads: ChatHistory::global(cx).threads(),
+            matches: Vec::new(),
+            selected_index: 0,
+        }
+    }
+}
+
+impl PickerDelegate for OllamaChatHistoryDelegate {
+    type ListItem = ListItem;
+
+    fn placeholder_text(&self, _cx: &mut WindowContext) -> Arc<str> {
+        "Search Ollama chats...".into()
+    }
+
+    fn match_count(&self) -> usize {
+        self.matches.len()
+    }
+
+    fn confirm(&mut self, secondary: bool, cx: &mut ViewContext<Picker<Self>>) {
+        if let Some(mat) = self.matches.get(self.selected_index) {
+            let thread = &self.threads[mat.candidate_id];
+            let thread = if secondary {
+                thread.branch()
+            } else {
+                thread.clone()
+            };
+            self.workspace
+                .update(cx, |workspace, cx| {
+                    let project = workspace.project().clone();
+                    let editor = chat_editor(thread, project, false, cx);
+                    workspace.add_item_to_active_pane(Box::new(editor), None, true, cx);
+                })
+                .log_err();
+        }
+        self.dismissed(cx);
+    }
+
+    fn dismissed(&mut self, cx: &mut ViewContext<Picker<Self>>) {
+        self.history
+            .update(cx, |_, cx| cx.emit(DismissEvent))
+            .log_err();
+    }
+
+    fn selected_index(&self) -> usize {
+        self.selected_index
+    }
+
+    fn set_selected_index(&mut self, ix: usize, _: &mut ViewContext<Picker<Self>>) {
+        self.selected_index = ix;
+    }
+
+    fn update_matches(&mut self, query: String, cx: &mut ViewContext<Picker<Self>>) -> Task<()> {
+        let background = cx.background_executor().clone();
+        let candidates = self
+            .threads
+            .iter()
+            .enumerate()
+            .map(|(id, thread)| StringMatchCandidate::new(id, thread.title()))
+            .collect::<Vec<_>>();
+        cx.spawn(|this, mut cx| async move {
+            let matches = if query.is_empty() {
+                candidates
+                    .into_iter()
+                    .map(|candidate| StringMatch {
+                        candidate_id: candidate.id,
+                        string: candidate.string,
+                        positions: Vec::new(),
+                        score: 0.0,
+                    })
+                    .collect()
+            } else {
+                match_strings(
+                    &candidates,
+                    &query,
+                    false,
+                    100,
+                    &Default::default(),
+                    background,
+                )
+                .await
+            };
+
+            this.update(&mut cx, |this, cx| {
+                let delegate = &mut this.delegate;
+                delegate.matches = matches;
+                delegate.selected_index = delegate
+                    .selected_index
+                    .min(delegate.matches.len().saturating_sub(1));
+                cx.notify();
+            })
+            .log_err();
+        })
+    }
+
+    fn render_match(
+        &self,
+        ix: usize,
+        selected: bool,
+        _: &mut ViewContext<Picker<Self>>,
+    ) -> Option<Self::ListItem> {
+        let mat = &self.matches[ix];
+        let thread = &self.threads[mat.candidate_id];
+        let updated_at = thread
+            .updated_at
+            .with_timezone(&Local)
+            .format("%b %-d, %H:%M");
+
+        Some(
+            ListItem::new(ix)
+                .inset(true)
+                .spacing(ListItemSpacing::Sparse)
+                .selected(selected)
+                .child(HighlightedLabel::new(
+                    mat.string.clone(),
+                    mat.positions.clone(),
+                ))
+                .end_slot(
+                    Label::new(format!("{} · {updated_at}", thread.model))
+                        .size(LabelSize::Small)
+                        .color(Color::Muted),
+                ),
+        )
+    }
+}
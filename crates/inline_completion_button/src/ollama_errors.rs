@@ -0,0 +1,119 @@
+use crate::ollama_model_pull::pull_model;
+use gpui::{
+    AppContext, Model, SharedString, ViewContext, VisualContext as _, WeakView, WindowContext,
+};
+use ollama::OllamaError;
+use ollama_completions::{ErrorReports, ModelSubstitutes};
+use workspace::{
+    notifications::{simple_message_notification::MessageNotification, NotificationId},
+    Workspace,
+};
+use zed_actions::OpenSettings;
+
+struct OllamaErrorNotification;
+
+struct OllamaModelSubstituteNotification;
+
+pub fn init(cx: &mut AppContext) {
+    cx.observe_new_views(register).detach();
+}
+
+fn register(_: &mut Workspace, cx: &mut ViewContext<Workspace>) {
+    if let Some(substitutes) = ModelSubstitutes::global(cx) {
+        cx.subscribe(&substitutes, |workspace, _, substituted, cx| {
+            let missing = substituted.missing.clone();
+            let message = format!(
+                "Ollama doesn't have the model '{missing}', so completions use '{}' until it's pulled",
+                substituted.substitute
+            );
+            let workspace_handle = cx.view().downgrade();
+            workspace.show_notification(
+                NotificationId::composite::<OllamaModelSubstituteNotification>(
+                    SharedString::from(missing.clone()),
+                ),
+                cx,
+                |cx| {
+                    cx.new_view(|_| {
+                        MessageNotification::new(message)
+                            .with_click_message(format!("Pull {missing}"))
+                            .on_click(move |cx| {
+                                pull_missing_model(missing.clone(), workspace_handle.clone(), cx)
+                            })
+                    })
+                },
+            );
+        })
+        .detach();
+    }
+    let Some(reports) = ErrorReports::global(cx) else {
+        return;
+    };
+    cx.subscribe(&reports, |workspace, reports, error, cx| {
+        let error = error.clone();
+        let workspace_handle = cx.view().downgrade();
+        workspace.show_notification(
+            NotificationId::unique::<OllamaErrorNotification>(),
+            cx,
+            |cx| cx.new_view(|_| error_notification(&error, reports, workspace_handle)),
+        );
+    })
+    .detach();
+}
+
+/// Explains a notable Ollama error, with a button to fix it where there's a
+/// way to, and one to stop showing errors of its kind.
+fn error_notification(
+    error: &OllamaError,
+    reports: Model<ErrorReports>,
+    workspace: WeakView<Workspace>,
+) -> MessageNotification {
+    let notification = match error {
+        OllamaError::ConnectionRefused { .. } => MessageNotification::new(
+            "Couldn't connect to Ollama. Start it with `ollama serve`, \
+            or check \"api_url\" in the Ollama settings.",
+        )
+        .with_click_message("Open Settings")
+        .on_click(|cx| cx.dispatch_action(Box::new(OpenSettings))),
+        OllamaError::ModelNotFound { model } => {
+            let model = model.clone();
+            MessageNotification::new(format!("Ollama doesn't have the model '{model}'"))
+                .with_click_message("Pull Model")
+                .on_click(move |cx| pull_missing_model(model.clone(), workspace.clone(), cx))
+        }
+        OllamaError::Unauthorized => {
+            MessageNotification::new("The Ollama server rejected the API key")
+                .with_click_message("Open Settings")
+                .on_click(|cx| cx.dispatch_action(Box::new(OpenSettings)))
+        }
+        OllamaError::SignInRequired {
+            signin_url: Some(signin_url),
+        } => {
+            let signin_url = signin_url.clone();
+            MessageNotification::new(error.to_string())
+                .with_click_message("Sign In")
+                .on_click(move |cx| cx.open_url(&signin_url))
+        }
+        OllamaError::SignInRequired { signin_url: None } => MessageNotification::new(
+            "Sign in to ollama.com to use Ollama's hosted models, by running `ollama signin`",
+        ),
+        OllamaError::ContextExceeded => MessageNotification::new(
+            "Ollama prompts don't fit in the model's context window. \
+            Lower \"num_ctx\" in the Ollama settings to match the model.",
+        ),
+        error => MessageNotification::new(error.to_string()),
+    };
+    let kind = error.kind();
+    notification
+        .with_secondary_click_message("Don't Show Again")
+        .on_secondary_click(move |cx| reports.update(cx, |reports, cx| reports.mute(kind, cx)))
+}
+
+fn pull_missing_model(model: String, workspace: WeakView<Workspace>, cx: &mut WindowContext) {
+    let Some(fs) = workspace
+        .upgrade()
+        .map(|workspace| workspace.read(cx).app_state().fs.clone())
+    else {
+        return;
+    };
+    pull_model(model, fs, workspace, cx)
+}
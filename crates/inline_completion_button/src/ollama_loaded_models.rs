@@ -0,0 +1,97 @@
+use gpui::{AnyElement, Model, ModelContext, WindowContext};
+use ollama::{running_models, unload_model, RunningModel};
+use ollama_completions::{http_client_for, OllamaSettings};
+use settings::Settings as _;
+use ui::prelude::*;
+use util::ResultExt as _;
+
+/// The models the Ollama server has loaded into memory, fetched from
+/// `/api/ps` each time the status bar menu opens.
+pub struct LoadedModels {
+    /// `None` until the first response arrives.
+    models: Option<Result<Vec<RunningModel>, String>>,
+}
+
+impl LoadedModels {
+    pub fn new(cx: &mut ModelContext<Self>) -> Self {
+        let mut this = Self { models: None };
+        this.refresh(cx);
+        this
+    }
+
+    fn refresh(&mut self, cx: &mut ModelContext<Self>) {
+        let settings = OllamaSettings::get_global(cx);
+        let http_client = http_client_for(settings, &cx.http_client());
+        let api_url = settings.preferred_endpoint(cx);
+        let api_key = settings.effective_api_key();
+        cx.spawn(|this, mut cx| async move {
+            let models = running_models(http_client.as_ref(), &api_url, api_key.as_deref())
+                .await
+                .map_err(|error| error.to_string());
+            this.update(&mut cx, |this, cx| {
+                this.models = Some(models);
+                cx.notify();
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
+    /// Frees the memory `model` takes up, then lists what's left.
+    fn unload(&mut self, model: String, cx: &mut ModelContext<Self>) {
+        let settings = OllamaSettings::get_global(cx);
+        let http_client = http_client_for(settings, &cx.http_client());
+        let api_url = settings.preferred_endpoint(cx);
+        let api_key = settings.effective_api_key();
+        cx.spawn(|this, mut cx| async move {
+            unload_model(http_client.as_ref(), &api_url, api_key.as_deref(), &model)
+                .await
+                .log_err();
+            this.update(&mut cx, |this, cx| this.refresh(cx))
+        })
+        .detach_and_log_err(cx);
+    }
+}
+
+/// A row for each loaded model with its memory use, when it will be
+/// unloaded, and a button to unload it now.
+pub fn render_loaded_models(loaded: &Model<LoadedModels>, cx: &mut WindowContext) -> AnyElement {
+    let muted_label = |text: String| {
+        Label::new(text)
+            .size(LabelSize::Small)
+            .color(Color::Muted)
+            .into_any_element()
+    };
+    let models = match &loaded.read(cx).models {
+        None => return muted_label("Loading…".into()),
+        Some(Err(error)) => {
+            let error = error.lines().next().unwrap_or_default();
+            return muted_label(format!("Unavailable: {error}"));
+        }
+        Some(Ok(models)) if models.is_empty() => return muted_label("None".into()),
+        Some(Ok(models)) => models.clone(),
+    };
+
+    let now = chrono::Utc::now();
+    v_flex()
+        .gap_1()
+        .children(models.into_iter().enumerate().map(|(ix, model)| {
+            let loaded = loaded.clone();
+            let name = model.name.clone();
+            h_flex()
+                .gap_2()
+                .justify_between()
+                .child(
+                    v_flex()
+                        .child(Label::new(model.name.clone()))
+                        .child(muted_label(model.description(now))),
+                )
+                .child(
+                    Button::new(("unload-ollama-model", ix), "Unload")
+                        .size(ButtonSize::Compact)
+                        .on_click(move |_, cx| {
+                            loaded.update(cx, |loaded, cx| loaded.unload(name.clone(), cx))
+                        }),
+                )
+        }))
+        .into_any_element()
+}
@@ -0,0 +1,91 @@
+use editor::{Editor, MultiBuffer};
+use gpui::{AppContext, Context as _, ViewContext, VisualContext as _};
+use ollama::get_models;
+use ollama_completions::{
+    benchmark_model, benchmark_report, http_client_for, OllamaSettings, BENCHMARK_RUNS,
+};
+use settings::Settings as _;
+use util::ResultExt as _;
+use workspace::Workspace;
+use zed_actions::ollama::BenchmarkModels;
+
+pub fn init(cx: &mut AppContext) {
+    cx.observe_new_views(register).detach();
+}
+
+fn register(workspace: &mut Workspace, _: &mut ViewContext<Workspace>) {
+    workspace.register_action(|workspace, _: &BenchmarkModels, cx| benchmark_models(workspace, cx));
+}
+
+/// Times every model installed on the server with the same completion
+/// request, and opens a read-only report that fills in as each model is
+/// measured.
+fn benchmark_models(workspace: &mut Workspace, cx: &mut ViewContext<Workspace>) {
+    let settings = OllamaSettings::get_global(cx);
+    let http_client = http_client_for(settings, &cx.http_client());
+    let api_url = settings.preferred_endpoint(cx);
+    let api_key = settings.effective_api_key();
+    let api_format = settings.api_format;
+    let configured_model = settings.resolve_model(&settings.model).to_string();
+
+    let project = workspace.project().clone();
+    let markdown = project.read(cx).languages().language_for_name("Markdown");
+    let report = benchmark_report(&api_url, BENCHMARK_RUNS, &[], &[]);
+    let buffer = project.update(cx, |project, cx| {
+        project.create_local_buffer(&report, None, cx)
+    });
+    let multibuffer = cx.new_model(|cx| {
+        MultiBuffer::singleton(buffer.clone(), cx).with_title("Ollama Model Benchmark".into())
+    });
+    let editor = cx.new_view(|cx| {
+        let mut editor = Editor::for_multibuffer(multibuffer, Some(project), true, cx);
+        editor.set_read_only(true);
+        editor
+    });
+    workspace.add_item_to_active_pane(Box::new(editor), None, true, cx);
+
+    cx.spawn(|_, mut cx| async move {
+        if let Some(markdown) = markdown.await.log_err() {
+            buffer.update(&mut cx, |buffer, cx| {
+                buffer.set_language(Some(markdown), cx)
+            })?;
+        }
+
+        // OpenAI-compatible servers can't list their models, so only the
+        // configured one is measured there.
+        let mut pending = get_models(http_client.as_ref(), &api_url, api_key.as_deref(), None)
+            .await
+            .log_err()
+            .map(|models| {
+                models
+                    .into_iter()
+                    .map(|model| model.name)
+                    .collect::<Vec<_>>()
+            })
+            .filter(|models| !models.is_empty())
+            .unwrap_or_else(|| vec![configured_model]);
+        pending.sort();
+
+        let mut results = Vec::new();
+        while !pending.is_empty() {
+            let report = benchmark_report(&api_url, BENCHMARK_RUNS, &results, &pending);
+            buffer.update(&mut cx, |buffer, cx| buffer.set_text(report, cx))?;
+            let model = pending.remove(0);
+            results.push(
+                benchmark_model(
+                    http_client.as_ref(),
+                    &api_url,
+                    api_key.as_deref(),
+                    api_format,
+                    &model,
+                    BENCHMARK_RUNS,
+                )
+                .await,
+            );
+        }
+        let report = benchmark_report(&api_url, BENCHMARK_RUNS, &results, &pending);
+        buffer.update(&mut cx, |buffer, cx| buffer.set_text(report, cx))?;
+        anyhow::Ok(())
+    })
+    .detach_and_log_err(cx);
+}
@@ -0,0 +1,113 @@
+use fs::Fs;
+use gpui::{SharedString, WeakView, WindowContext};
+use ollama::{pull_model_with_progress, PullProgress, PULL_RETRY};
+use ollama_completions::{http_client_for, ModelSubstitutes, OllamaSettings};
+use settings::{update_settings_file, Settings as _};
+use std::{
+    fmt::Write as _,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use workspace::{notifications::NotificationId, Toast, Workspace};
+
+/// How often the progress shown is updated while a layer downloads.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+struct OllamaModelPull;
+
+/// Pulls `model` to the Ollama server, showing how far along each of its
+/// layers is, and offers to make it the model for inline completions once
+/// it's there.
+pub(crate) fn pull_model(
+    model: String,
+    fs: Arc<dyn Fs>,
+    workspace: WeakView<Workspace>,
+    cx: &mut WindowContext,
+) {
+    let settings = OllamaSettings::get_global(cx);
+    let http_client = http_client_for(settings, &cx.http_client());
+    let api_url = settings.preferred_endpoint(cx);
+    let api_key = settings.effective_api_key();
+    let is_current_model = settings.model == model;
+    // Pulls of different models each get their own notification.
+    let id = NotificationId::composite::<OllamaModelPull>(SharedString::from(model.clone()));
+    let show = move |toast: Toast, cx: &mut WindowContext| {
+        workspace
+            .update(cx, |workspace, cx| workspace.show_toast(toast, cx))
+            .ok();
+    };
+    show(Toast::new(id.clone(), format!("Pulling {model}…")), cx);
+
+    cx.spawn(|mut cx| async move {
+        let mut last_update: Option<(String, Instant)> = None;
+        let result = pull_model_with_progress(
+            http_client.as_ref(),
+            &api_url,
+            api_key.as_deref(),
+            &model,
+            PULL_RETRY,
+            |progress| {
+                let now = Instant::now();
+                let due = last_update.as_ref().map_or(true, |(status, updated_at)| {
+                    *status != progress.status || now - *updated_at >= PROGRESS_INTERVAL
+                });
+                if due {
+                    last_update = Some((progress.status.clone(), now));
+                    let message = progress_message(&model, progress);
+                    cx.update(|cx| show(Toast::new(id.clone(), message), cx))
+                        .ok();
+                }
+            },
+        )
+        .await;
+
+        if result.is_ok() {
+            // Nothing needs to stand in for the model anymore.
+            cx.update(|cx| {
+                if let Some(substitutes) = ModelSubstitutes::global(cx) {
+                    substitutes.update(cx, |substitutes, _| substitutes.forget(&model));
+                }
+            })
+            .ok();
+        }
+        let toast = match result {
+            Ok(()) if is_current_model => Toast::new(id, format!("Pulled {model}")).autohide(),
+            Ok(()) => Toast::new(id, format!("Pulled {model}")).on_click(
+                "Use for Completions",
+                move |cx| {
+                    let model = model.clone();
+                    update_settings_file::<OllamaSettings>(fs.clone(), cx, move |settings, _| {
+                        settings.model = Some(model);
+                    });
+                },
+            ),
+            Err(error) => Toast::new(id, format!("Couldn't pull {model}: {error}")),
+        };
+        cx.update(|cx| show(toast, cx))
+    })
+    .detach_and_log_err(cx);
+}
+
+/// The model's overall progress, followed by a line per layer.
+fn progress_message(model: &str, progress: &PullProgress) -> String {
+    let mut message = format!("Pulling {model}: {}", progress.status);
+    let total = progress.total_bytes();
+    if total > 0 {
+        write!(
+            message,
+            ", {}% of {:.1} GB",
+            progress.completed_bytes() * 100 / total,
+            total as f64 / 1e9
+        )
+        .ok();
+    }
+    for layer in &progress.layers {
+        let digest = layer.digest.get(7..19).unwrap_or(&layer.digest);
+        let percent = match layer.total {
+            0 => 0,
+            total => layer.completed * 100 / total,
+        };
+        write!(message, "\n{digest}: {percent}%").ok();
+    }
+    message
+}
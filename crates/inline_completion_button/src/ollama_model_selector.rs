@@ -0,0 +1,279 @@
+use crate::ollama_model_pull::pull_model;
+use fs::Fs;
+use fuzzy::{match_strings, StringMatch, StringMatchCandidate};
+use gpui::{
+    AppContext, DismissEvent, EventEmitter, FocusHandle, FocusableView, ParentElement, Render,
+    Styled, Task, View, ViewContext, VisualContext, WeakView,
+};
+use ollama::{get_cloud_models, get_models, is_cloud_model, is_cloud_url, pull_model};
+use ollama_completions::{http_client_for, OllamaSettings};
+use picker::{Picker, PickerDelegate};
+use settings::{update_settings_file, Settings};
+use std::{collections::HashSet, sync::Arc};
+use ui::{prelude::*, HighlightedLabel, ListItem, ListItemSpacing};
+use util::ResultExt;
+use workspace::{ModalView, Workspace};
+use zed_actions::ollama::SelectModel;
+
+pub fn init(cx: &mut AppContext) {
+    cx.observe_new_views(OllamaModelSelector::register).detach();
+}
+
+/// Lists the models installed on the Ollama server and makes the chosen one
+/// the model for inline completions. With an API key for ollama.com, the
+/// models it hosts are listed too, and pulled to the server when chosen so it
+/// can proxy them. A name that isn't listed can be pulled from the Ollama
+/// library.
+pub struct OllamaModelSelector {
+    picker: View<Picker<OllamaModelSelectorDelegate>>,
+}
+
+impl OllamaModelSelector {
+    fn register(workspace: &mut Workspace, _: &mut ViewContext<Workspace>) {
+        workspace.register_action(|workspace, _: &SelectModel, cx| {
+            let fs = workspace.app_state().fs.clone();
+            let workspace_handle = cx.view().downgrade();
+            workspace.toggle_modal(cx, |cx| Self::new(fs, workspace_handle, cx));
+        });
+    }
+
+    fn new(fs: Arc<dyn Fs>, workspace: WeakView<Workspace>, cx: &mut ViewContext<Self>) -> Self {
+        let delegate = OllamaModelSelectorDelegate::new(cx.view().downgrade(), workspace, fs, cx);
+        let picker = cx.new_view(|cx| Picker::uniform_list(delegate, cx));
+        let this = Self { picker };
+        this.fetch_models(cx);
+        this
+    }
+
+    fn fetch_models(&self, cx: &mut ViewContext<Self>) {
+        let settings = OllamaSettings::get_global(cx);
+        let http_client = http_client_for(settings, &cx.http_client());
+        let api_url = settings.preferred_endpoint(cx);
+        let api_key = settings.effective_api_key();
+        let picker = self.picker.downgrade();
+        cx.spawn(|_, mut cx| async move {
+            let mut models = get_models(http_client.as_ref(), &api_url, api_key.as_deref(), None)
+                .await
+                .log_err()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|model| model.name)
+                .collect::<Vec<_>>();
+            let mut not_pulled = HashSet::default();
+            if api_key.is_some() && !is_cloud_url(&api_url) {
+                let cloud_models = get_cloud_models(http_client.as_ref(), api_key.as_deref())
+                    .await
+                    .log_err()
+                    .unwrap_or_default();
+                for model in cloud_models {
+                    if !models.contains(&model.name) {
+                        not_pulled.insert(model.name.clone());
+                        models.push(model.name);
+                    }
+                }
+            }
+            picker.update(&mut cx, |picker, cx| {
+                picker.delegate.candidates = models
+                    .into_iter()
+                    .enumerate()
+                    .map(|(id, model)| StringMatchCandidate::new(id, model))
+                    .collect();
+                picker.delegate.not_pulled = not_pulled;
+                picker.refresh(cx);
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+}
+
+impl Render for OllamaModelSelector {
+    fn render(&mut self, _cx: &mut ViewContext<Self>) -> impl IntoElement {
+        v_flex().w(rems(34.)).child(self.picker.clone())
+    }
+}
+
+impl FocusableView for OllamaModelSelector {
+    fn focus_handle(&self, cx: &AppContext) -> FocusHandle {
+        self.picker.focus_handle(cx)
+    }
+}
+
+impl EventEmitter<DismissEvent> for OllamaModelSelector {}
+impl ModalView for OllamaModelSelector {}
+
+pub struct OllamaModelSelectorDelegate {
+    selector: WeakView<OllamaModelSelector>,
+    workspace: WeakView<Workspace>,
+    fs: Arc<dyn Fs>,
+    current_model: String,
+    candidates: Vec<StringMatchCandidate>,
+    /// Hosted models the server doesn't have yet.
+    not_pulled: HashSet<String>,
+    matches: Vec<StringMatch>,
+    /// A model named in the query that isn't listed, offered to be pulled
+    /// after the matches.
+    model_to_pull: Option<String>,
+    selected_index: usize,
+}
+
+impl OllamaModelSelectorDelegate {
+    fn new(
+        selector: WeakView<OllamaModelSelector>,
+        workspace: WeakView<Workspace>,
+        fs: Arc<dyn Fs>,
+        cx: &AppContext,
+    ) -> Self {
+        Self {
+            selector,
+            workspace,
+            fs,
+            current_model: OllamaSettings::get_global(cx).model.clone(),
+            candidates: Vec::new(),
+            not_pulled: HashSet::default(),
+            matches: Vec::new(),
+            model_to_pull: None,
+            selected_index: 0,
+        }
+    }
+}
+
+impl PickerDelegate for OllamaModelSelectorDelegate {
+    type ListItem = ListItem;
+
+    fn placeholder_text(&self, _cx: &mut WindowContext) -> Arc<str> {
+        "Select an Ollama model...".into()
+    }
+
+    fn match_count(&self) -> usize {
+        self.matches.len() + usize::from(self.model_to_pull.is_some())
+    }
+
+    fn confirm(&mut self, _: bool, cx: &mut ViewContext<Picker<Self>>) {
+        if self.selected_index == self.matches.len() {
+            if let Some(model) = self.model_to_pull.clone() {
+                pull_model(model, self.fs.clone(), self.workspace.clone(), cx);
+            }
+        } else if let Some(mat) = self.matches.get(self.selected_index) {
+            let model = mat.string.clone();
+            let fs = self.fs.clone();
+            if self.not_pulled.contains(&model) {
+                // Pulling a hosted model only fetches its manifest, so this
+                // doesn't take long.
+                let settings = OllamaSettings::get_global(cx);
+                let http_client = http_client_for(settings, &cx.http_client());
+                let api_url = settings.preferred_endpoint(cx);
+                let api_key = settings.effective_api_key();
+                cx.spawn(|_, mut cx| async move {
+                    pull_model(http_client.as_ref(), &api_url, api_key.as_deref(), &model).await?;
+                    cx.update(|cx| {
+                        update_settings_file::<OllamaSettings>(fs, cx, move |settings, _| {
+                            settings.model = Some(model);
+                        })
+                    })
+                })
+                .detach_and_log_err(cx);
+            } else {
+                update_settings_file::<OllamaSettings>(fs, cx, move |settings, _| {
+                    settings.model = Some(model);
+                });
+            }
+        }
+        self.dismissed(cx);
+    }
+
+    fn dismissed(&mut self, cx: &mut ViewContext<Picker<Self>>) {
+        self.selector
+            .update(cx, |_, cx| cx.emit(DismissEvent))
+            .log_err();
+    }
+
+    fn selected_index(&self) -> usize {
+        self.selected_index
+    }
+
+    fn set_selected_index(&mut self, ix: usize, _: &mut ViewContext<Picker<Self>>) {
+        self.selected_index = ix;
+    }
+
+    fn update_matches(&mut self, query: String, cx: &mut ViewContext<Picker<Self>>) -> Task<()> {
+        let background = cx.background_executor().clone();
+        let candidates = self.candidates.clone();
+        let model_to_pull = Some(query.trim().to_string()).filter(|model| {
+            !model.is_empty()
+                && !model.contains(char::is_whitespace)
+                && !candidates
+                    .iter()
+                    .any(|candidate| candidate.string == *model)
+        });
+        cx.spawn(|this, mut cx| async move {
+            let matches = if query.is_empty() {
+                candidates
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, candidate)| StringMatch {
+                        candidate_id: index,
+                        string: candidate.string,
+                        positions: Vec::new(),
+                        score: 0.0,
+                    })
+                    .collect()
+            } else {
+                match_strings(
+                    &candidates,
+                    &query,
+                    false,
+                    100,
+                    &Default::default(),
+                    background,
+                )
+                .await
+            };
+
+            this.update(&mut cx, |this, cx| {
+                let delegate = &mut this.delegate;
+                delegate.matches = matches;
+                delegate.model_to_pull = model_to_pull;
+                delegate.selected_index = delegate
+                    .selected_index
+                    .min(delegate.match_count().saturating_sub(1));
+                cx.notify();
+            })
+            .log_err();
+        })
+    }
+
+    fn render_match(
+        &self,
+        ix: usize,
+        selected: bool,
+        _: &mut ViewContext<Picker<Self>>,
+    ) -> Option<Self::ListItem> {
+        let Some(mat) = self.matches.get(ix) else {
+            let model = self.model_to_pull.as_ref()?;
+            return Some(
+                ListItem::new(ix)
+                    .inset(true)
+                    .spacing(ListItemSpacing::Sparse)
+                    .selected(selected)
+                    .child(Label::new(format!(
+                        "Pull \"{model}\" from the Ollama library"
+                    ))),
+            );
+        };
+        let mut label = mat.string.clone();
+        if is_cloud_model(&mat.string) {
+            label.push_str(" (cloud)");
+        }
+        if mat.string == self.current_model {
+            label.push_str(" (current)");
+        }
+
+        Some(
+            ListItem::new(ix)
+                .inset(true)
+                .spacing(ListItemSpacing::Sparse)
+                .selected(selected)
+                .child(HighlightedLabel::new(label, mat.positions.clone())),
+        )
+    }
+}
@@ -0,0 +1,97 @@
+use editor::{Editor, MultiBuffer};
+use gpui::{AppContext, Context as _, ViewContext, VisualContext as _};
+use ollama::create_model;
+use ollama_completions::{http_client_for, parse_modelfile, variant_modelfile, OllamaSettings};
+use settings::{update_settings_file, Settings as _};
+use workspace::{notifications::NotificationId, Toast, Workspace};
+use zed_actions::ollama::{CreateModel, NewModelVariant};
+
+struct CreateModelToast;
+
+pub fn init(cx: &mut AppContext) {
+    cx.observe_new_views(register).detach();
+}
+
+fn register(workspace: &mut Workspace, _: &mut ViewContext<Workspace>) {
+    workspace
+        .register_action(|workspace, _: &NewModelVariant, cx| new_model_variant(workspace, cx));
+    workspace
+        .register_action(|workspace, _: &CreateModel, cx| create_model_from_editor(workspace, cx));
+}
+
+/// Opens a Modelfile deriving a completion-tuned variant of the configured
+/// model, ready to be edited and created with [`CreateModel`].
+fn new_model_variant(workspace: &mut Workspace, cx: &mut ViewContext<Workspace>) {
+    let modelfile = variant_modelfile(OllamaSettings::get_global(cx));
+    let project = workspace.project().clone();
+    let buffer = project.update(cx, |project, cx| {
+        project.create_local_buffer(&modelfile, None, cx)
+    });
+    let multibuffer =
+        cx.new_model(|cx| MultiBuffer::singleton(buffer, cx).with_title("Modelfile".into()));
+    let editor = cx.new_view(|cx| Editor::for_multibuffer(multibuffer, Some(project), true, cx));
+    workspace.add_item_to_active_pane(Box::new(editor), None, true, cx);
+}
+
+/// Creates the model described by the Modelfile in the active editor, under
+/// the name in its `# Name:` comment.
+fn create_model_from_editor(workspace: &mut Workspace, cx: &mut ViewContext<Workspace>) {
+    let Some(editor) = workspace.active_item_as::<Editor>(cx) else {
+        return;
+    };
+    let modelfile = editor.read(cx).text(cx);
+    let Some(model) = parse_modelfile(&modelfile).name else {
+        workspace.show_toast(
+            Toast::new(
+                NotificationId::unique::<CreateModelToast>(),
+                "Add a \"# Name: <model>\" line to the Modelfile to name the model to create",
+            ),
+            cx,
+        );
+        return;
+    };
+
+    let settings = OllamaSettings::get_global(cx);
+    let http_client = http_client_for(settings, &cx.http_client());
+    let api_url = settings.preferred_endpoint(cx);
+    let api_key = settings.effective_api_key();
+    let fs = workspace.app_state().fs.clone();
+    workspace.show_toast(
+        Toast::new(
+            NotificationId::unique::<CreateModelToast>(),
+            format!("Creating {model}…"),
+        ),
+        cx,
+    );
+
+    cx.spawn(|workspace, mut cx| async move {
+        let result = create_model(
+            http_client.as_ref(),
+            &api_url,
+            api_key.as_deref(),
+            &model,
+            &modelfile,
+        )
+        .await;
+        workspace.update(&mut cx, |workspace, cx| {
+            let toast = match result {
+                Ok(()) => Toast::new(
+                    NotificationId::unique::<CreateModelToast>(),
+                    format!("Created {model}"),
+                )
+                .on_click("Use for Completions", move |cx| {
+                    let model = model.clone();
+                    update_settings_file::<OllamaSettings>(fs.clone(), cx, move |settings, _| {
+                        settings.model = Some(model);
+                    });
+                }),
+                Err(error) => Toast::new(
+                    NotificationId::unique::<CreateModelToast>(),
+                    format!("Couldn't create {model}: {error}"),
+                ),
+            };
+            workspace.show_toast(toast, cx);
+        })
+    })
+    .detach_and_log_err(cx);
+}
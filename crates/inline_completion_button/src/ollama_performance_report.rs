@@ -0,0 +1,51 @@
+use editor::{Editor, MultiBuffer};
+use gpui::{AppContext, Context as _, ViewContext, VisualContext as _};
+use ollama::TokenUsage;
+use ollama_completions::PerformanceReport;
+use util::ResultExt as _;
+use workspace::Workspace;
+use zed_actions::ollama::ShowPerformanceReport;
+
+pub fn init(cx: &mut AppContext) {
+    cx.observe_new_views(register).detach();
+}
+
+fn register(workspace: &mut Workspace, _: &mut ViewContext<Workspace>) {
+    workspace.register_action(|workspace, _: &ShowPerformanceReport, cx| {
+        show_performance_report(workspace, cx)
+    });
+}
+
+/// Opens a read-only report of each model's latency percentiles, timeout
+/// rate, cache hit rate and token usage since Zed started.
+fn show_performance_report(workspace: &mut Workspace, cx: &mut ViewContext<Workspace>) {
+    let report = format!(
+        "{}\n{}",
+        PerformanceReport::global(cx).markdown(),
+        TokenUsage::global().markdown()
+    );
+    let project = workspace.project().clone();
+    let markdown = project.read(cx).languages().language_for_name("Markdown");
+    let buffer = project.update(cx, |project, cx| {
+        project.create_local_buffer(&report, None, cx)
+    });
+    let multibuffer = cx.new_model(|cx| {
+        MultiBuffer::singleton(buffer.clone(), cx).with_title("Ollama Performance".into())
+    });
+    let editor = cx.new_view(|cx| {
+        let mut editor = Editor::for_multibuffer(multibuffer, Some(project), true, cx);
+        editor.set_read_only(true);
+        editor
+    });
+    workspace.add_item_to_active_pane(Box::new(editor), None, true, cx);
+
+    cx.spawn(|_, mut cx| async move {
+        if let Some(markdown) = markdown.await.log_err() {
+            buffer.update(&mut cx, |buffer, cx| {
+                buffer.set_language(Some(markdown), cx)
+            })?;
+        }
+        anyhow::Ok(())
+    })
+    .detach_and_log_err(cx);
+}
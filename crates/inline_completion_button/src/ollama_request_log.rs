@@ -0,0 +1,63 @@
+use editor::{Editor, MultiBuffer};
+use gpui::{AppContext, Context as _, ViewContext, VisualContext as _};
+use ollama_completions::RequestLog;
+use util::ResultExt as _;
+use workspace::Workspace;
+use zed_actions::ollama::OpenRequestLog;
+
+pub fn init(cx: &mut AppContext) {
+    cx.observe_new_views(register).detach();
+}
+
+fn register(workspace: &mut Workspace, _: &mut ViewContext<Workspace>) {
+    workspace.register_action(|workspace, _: &OpenRequestLog, cx| open_request_log(workspace, cx));
+}
+
+/// Opens a read-only log of inline completion requests that fills in as they
+/// are made, with their prompts, responses and what became of them. Requests
+/// are only recorded while a log is open.
+fn open_request_log(workspace: &mut Workspace, cx: &mut ViewContext<Workspace>) {
+    let Some(log) = RequestLog::global(cx) else {
+        return;
+    };
+    log.update(cx, |log, _| log.add_viewer());
+
+    let project = workspace.project().clone();
+    let markdown = project.read(cx).languages().language_for_name("Markdown");
+    let text = log.read(cx).markdown();
+    let buffer = project.update(cx, |project, cx| {
+        project.create_local_buffer(&text, None, cx)
+    });
+    let multibuffer = cx.new_model(|cx| {
+        MultiBuffer::singleton(buffer.clone(), cx).with_title("Ollama Request Log".into())
+    });
+    let editor = cx.new_view(|cx| {
+        let mut editor = Editor::for_multibuffer(multibuffer, Some(project), true, cx);
+        editor.set_read_only(true);
+        cx.observe(&log, {
+            let buffer = buffer.clone();
+            move |_, log, cx| {
+                let text = log.read(cx).markdown();
+                buffer.update(cx, |buffer, cx| buffer.set_text(text, cx));
+            }
+        })
+        .detach();
+        cx.on_release({
+            let log = log.clone();
+            move |_, _, cx| log.update(cx, |log, _| log.remove_viewer())
+        })
+        .detach();
+        editor
+    });
+    workspace.add_item_to_active_pane(Box::new(editor), None, true, cx);
+
+    cx.spawn(|_, mut cx| async move {
+        if let Some(markdown) = markdown.await.log_err() {
+            buffer.update(&mut cx, |buffer, cx| {
+                buffer.set_language(Some(markdown), cx)
+            })?;
+        }
+        anyhow::Ok(())
+    })
+    .detach_and_log_err(cx);
+}
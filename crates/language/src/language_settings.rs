@@ -197,6 +197,7 @@ pub enum InlineCompletionProvider {
     #[default]
     Copilot,
     Supermaven,
+    Ollama,
 }
 
 /// The settings for inline completions, such as [GitHub Copilot](https://github.com/features/copilot)
@@ -207,6 +208,8 @@ pub struct InlineCompletionSettings {
     pub provider: InlineCompletionProvider,
     /// A list of globs representing files that inline completions should be disabled for.
     pub disabled_globs: Vec<GlobMatcher>,
+    /// Whether to show which provider and model suggested the inline completion.
+    pub show_source: bool,
 }
 
 /// The settings for all languages.
@@ -389,6 +392,12 @@ pub struct InlineCompletionSettingsContent {
     /// A list of globs representing files that inline completions should be disabled for.
     #[serde(default)]
     pub disabled_globs: Option<Vec<String>>,
+    /// Whether to show which provider and model suggested the inline completion, at the
+    /// end of the cursor's line.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub show_source: Option<bool>,
 }
 
 /// The settings for enabling/disabling features.
@@ -986,6 +995,11 @@ impl settings::Settings for AllLanguageSettings {
             .as_ref()
             .and_then(|c| c.disabled_globs.as_ref())
             .ok_or_else(Self::missing_default)?;
+        let mut show_completion_source = default_value
+            .inline_completions
+            .as_ref()
+            .and_then(|c| c.show_source)
+            .unwrap_or(false);
 
         let mut file_types: HashMap<Arc<str>, GlobSet> = HashMap::default();
 
@@ -1017,6 +1031,13 @@ impl settings::Settings for AllLanguageSettings {
             {
                 completion_globs = globs;
             }
+            if let Some(show_source) = user_settings
+                .inline_completions
+                .as_ref()
+                .and_then(|f| f.show_source)
+            {
+                show_completion_source = show_source;
+            }
 
             // A user's global settings override the default global settings and
             // all default language-specific settings.
@@ -1068,6 +1089,7 @@ impl settings::Settings for AllLanguageSettings {
                     .iter()
                     .filter_map(|g| Some(globset::Glob::new(g).ok()?.compile_matcher()))
                     .collect(),
+                show_source: show_completion_source,
             },
             defaults,
             languages,
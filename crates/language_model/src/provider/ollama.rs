@@ -1,15 +1,18 @@
 use anyhow::{anyhow, bail, Result};
+use collections::HashMap;
 use futures::{future::BoxFuture, stream::BoxStream, FutureExt, StreamExt};
 use gpui::{AnyView, AppContext, AsyncAppContext, ModelContext, Subscription, Task};
 use http_client::HttpClient;
 use ollama::{
-    get_models, preload_model, stream_chat_completion, ChatMessage, ChatOptions, ChatRequest,
-    ChatResponseDelta, KeepAlive, OllamaToolCall,
+    get_models, preload_model, show_model, stream_chat_completion, ChatMessage, ChatOptions,
+    ChatRequest, ChatResponseDelta, KeepAlive, OllamaToolCall, RequestPriority, UnixSocketClient,
 };
+use ollama_completions::{strip_code_fence, OllamaDisabled};
+use parking_lot::Mutex;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use settings::{Settings, SettingsStore};
-use std::{collections::BTreeMap, sync::Arc};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
 use ui::{prelude::*, ButtonLike, Indicator};
 use util::ResultExt;
 
@@ -31,6 +34,8 @@ const PROVIDER_NAME: &str = "Ollama";
 pub struct OllamaSettings {
     pub api_url: String,
     pub available_models: Vec<AvailableModel>,
+    pub api_key: Option<String>,
+    pub chat_timeout: Option<Duration>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
@@ -53,6 +58,8 @@ pub struct OllamaLanguageModelProvider {
 pub struct State {
     http_client: Arc<dyn HttpClient>,
     available_models: Vec<ollama::Model>,
+    /// Whether each model can call tools, as looked up on first use.
+    tool_support: Arc<Mutex<HashMap<String, bool>>>,
     fetch_model_task: Option<Task<Result<()>>>,
     _subscription: Subscription,
 }
@@ -63,13 +70,23 @@ impl State {
     }
 
     fn fetch_models(&mut self, cx: &mut ModelContext<Self>) -> Task<Result<()>> {
+        if !ollama_completions::is_enabled(cx) {
+            self.available_models.clear();
+            cx.notify();
+            return Task::ready(Err(OllamaDisabled.into()));
+        }
+        // Models may have been pulled again in a newer version.
+        self.tool_support.lock().clear();
+
         let settings = &AllLanguageModelSettings::get_global(cx).ollama;
         let http_client = self.http_client.clone();
         let api_url = settings.api_url.clone();
+        let api_key = ollama::resolve_api_key(settings.api_key.as_deref());
 
         // As a proxy for the server being "authenticated", we'll check if its up by fetching the models
         cx.spawn(|this, mut cx| async move {
-            let models = get_models(http_client.as_ref(), &api_url, None).await?;
+            let models =
+                get_models(http_client.as_ref(), &api_url, api_key.as_deref(), None).await?;
 
             let mut models: Vec<ollama::Model> = models
                 .into_iter()
@@ -105,15 +122,20 @@ impl State {
 
 impl OllamaLanguageModelProvider {
     pub fn new(http_client: Arc<dyn HttpClient>, cx: &mut AppContext) -> Self {
+        // `api_url` may be a unix socket.
+        let http_client: Arc<dyn HttpClient> = Arc::new(UnixSocketClient::new(http_client));
         let this = Self {
             http_client: http_client.clone(),
             state: cx.new_model(|cx| {
                 let subscription = cx.observe_global::<SettingsStore>({
                     let mut settings = AllLanguageModelSettings::get_global(cx).ollama.clone();
+                    let mut enabled = ollama_completions::is_enabled(cx);
                     move |this: &mut State, cx| {
                         let new_settings = &AllLanguageModelSettings::get_global(cx).ollama;
-                        if &settings != new_settings {
+                        let new_enabled = ollama_completions::is_enabled(cx);
+                        if &settings != new_settings || enabled != new_enabled {
                             settings = new_settings.clone();
+                            enabled = new_enabled;
                             this.restart_fetch_models_task(cx);
                             cx.notify();
                         }
@@ -123,6 +145,7 @@ impl OllamaLanguageModelProvider {
                 State {
                     http_client,
                     available_models: Default::default(),
+                    tool_support: Default::default(),
                     fetch_model_task: None,
                     _subscription: subscription,
                 }
@@ -156,6 +179,7 @@ impl LanguageModelProvider for OllamaLanguageModelProvider {
     }
 
     fn provided_models(&self, cx: &AppContext) -> Vec<Arc<dyn LanguageModel>> {
+        let tool_support = self.state.read(cx).tool_support.clone();
         let mut models: BTreeMap<String, ollama::Model> = BTreeMap::default();
 
         // Add models from the Ollama API
@@ -180,13 +204,30 @@ impl LanguageModelProvider for OllamaLanguageModelProvider {
             );
         }
 
+        // Add the model profiles from the Ollama settings, under their own names
+        for (name, profile) in &ollama_completions::OllamaSettings::get_global(cx).profiles {
+            let mut model = ollama::Model::new(&profile.model, Some(name), profile.num_ctx);
+            if let Some(keep_alive) = &profile.keep_alive {
+                model.keep_alive = Some(keep_alive.clone());
+            }
+            models.insert(name.clone(), model);
+        }
+
+        // The inline completion model is listed even before the server has
+        // answered, so it can be seen and changed in the same picker.
+        let completion_model = &ollama_completions::OllamaSettings::get_global(cx).model;
         models
-            .into_values()
-            .map(|model| {
+            .entry(completion_model.clone())
+            .or_insert_with(|| ollama::Model::new(completion_model, None, None));
+
+        models
+            .into_iter()
+            .map(|(id, model)| {
                 Arc::new(OllamaLanguageModel {
-                    id: LanguageModelId::from(model.name.clone()),
+                    id: LanguageModelId::from(id),
                     model: model.clone(),
                     http_client: self.http_client.clone(),
+                    tool_support: tool_support.clone(),
                     request_limiter: RateLimiter::new(4),
                 }) as Arc<dyn LanguageModel>
             })
@@ -194,12 +235,20 @@ impl LanguageModelProvider for OllamaLanguageModelProvider {
     }
 
     fn load_model(&self, model: Arc<dyn LanguageModel>, cx: &AppContext) {
+        if !ollama_completions::is_enabled(cx) {
+            return;
+        }
         let settings = &AllLanguageModelSettings::get_global(cx).ollama;
         let http_client = self.http_client.clone();
         let api_url = settings.api_url.clone();
-        let id = model.id().0.to_string();
-        cx.spawn(|_| async move { preload_model(http_client, &api_url, &id).await })
-            .detach_and_log_err(cx);
+        let api_key = ollama::resolve_api_key(settings.api_key.as_deref());
+        let id = ollama_completions::OllamaSettings::get_global(cx)
+            .resolve_model(&model.id().0)
+            .to_string();
+        cx.spawn(
+            |_| async move { preload_model(http_client, &api_url, api_key.as_deref(), &id).await },
+        )
+        .detach_and_log_err(cx);
     }
 
     fn is_authenticated(&self, cx: &AppContext) -> bool {
@@ -220,16 +269,35 @@ impl LanguageModelProvider for OllamaLanguageModelProvider {
     }
 }
 
+/// Whether `model` is served by Ollama, and so can make inline completions.
+pub fn is_ollama_model(model: &dyn LanguageModel) -> bool {
+    model.provider_id().0.as_ref() == PROVIDER_ID
+}
+
 pub struct OllamaLanguageModel {
     id: LanguageModelId,
     model: ollama::Model,
     http_client: Arc<dyn HttpClient>,
+    tool_support: Arc<Mutex<HashMap<String, bool>>>,
     request_limiter: RateLimiter,
 }
 
 impl OllamaLanguageModel {
-    fn to_ollama_request(&self, request: LanguageModelRequest) -> ChatRequest {
-        ChatRequest {
+    fn to_ollama_request(
+        &self,
+        request: LanguageModelRequest,
+        cx: &AsyncAppContext,
+    ) -> ChatRequest {
+        let (options, redactor) = cx
+            .update(|cx| {
+                let settings = ollama_completions::OllamaSettings::get_global(cx);
+                (
+                    settings.chat_options(&self.id.0),
+                    settings.secret_redactor(),
+                )
+            })
+            .unwrap_or_default();
+        let mut request = ChatRequest {
             model: self.model.name.clone(),
             messages: request
                 .messages
@@ -252,12 +320,18 @@ impl OllamaLanguageModel {
             options: Some(ChatOptions {
                 num_ctx: Some(self.model.max_tokens),
                 stop: Some(request.stop),
-                temperature: request.temperature.or(Some(1.0)),
-                ..Default::default()
+                temperature: request.temperature.or(options.temperature).or(Some(1.0)),
+                ..options
             }),
             tools: vec![],
+            priority: RequestPriority::UserInitiated,
+        };
+        if let Some(redactor) = redactor {
+            redactor.redact_chat_request(&mut request);
         }
+        request
     }
+
     fn request_completion(
         &self,
         request: ChatRequest,
@@ -265,14 +339,64 @@ impl OllamaLanguageModel {
     ) -> BoxFuture<'static, Result<ChatResponseDelta>> {
         let http_client = self.http_client.clone();
 
-        let Ok(api_url) = cx.update(|cx| {
+        let Ok((enabled, api_url, api_key, timeout)) = cx.update(|cx| {
             let settings = &AllLanguageModelSettings::get_global(cx).ollama;
-            settings.api_url.clone()
+            (
+                ollama_completions::is_enabled(cx),
+                settings.api_url.clone(),
+                ollama::resolve_api_key(settings.api_key.as_deref()),
+                settings.chat_timeout,
+            )
         }) else {
             return futures::future::ready(Err(anyhow!("App state dropped"))).boxed();
         };
+        if !enabled {
+            return futures::future::ready(Err(OllamaDisabled.into())).boxed();
+        }
 
-        async move { ollama::complete(http_client.as_ref(), &api_url, request).await }.boxed()
+        async move {
+            ollama::complete(
+                http_client.as_ref(),
+                &api_url,
+                api_key.as_deref(),
+                request,
+                timeout,
+            )
+            .await
+        }
+        .boxed()
+    }
+
+    /// Whether the model can call tools. Assumed when the server can't say.
+    fn supports_tools(&self, cx: &AsyncAppContext) -> BoxFuture<'static, bool> {
+        let model = self.model.name.clone();
+        if let Some(supported) = self.tool_support.lock().get(&model) {
+            return futures::future::ready(*supported).boxed();
+        }
+        let Ok((api_url, api_key)) = cx.update(|cx| {
+            let settings = &AllLanguageModelSettings::get_global(cx).ollama;
+            (
+                settings.api_url.clone(),
+                ollama::resolve_api_key(settings.api_key.as_deref()),
+            )
+        }) else {
+            return futures::future::ready(true).boxed();
+        };
+        let http_client = self.http_client.clone();
+        let tool_support = self.tool_support.clone();
+        async move {
+            let Some(details) =
+                show_model(http_client.as_ref(), &api_url, api_key.as_deref(), &model)
+                    .await
+                    .log_err()
+            else {
+                return true;
+            };
+            let supported = details.supports_tools();
+            tool_support.lock().insert(model, supported);
+            supported
+        }
+        .boxed()
     }
 }
 
@@ -323,18 +447,33 @@ impl LanguageModel for OllamaLanguageModel {
         request: LanguageModelRequest,
         cx: &AsyncAppContext,
     ) -> BoxFuture<'static, Result<BoxStream<'static, Result<LanguageModelCompletionEvent>>>> {
-        let request = self.to_ollama_request(request);
+        let request = self.to_ollama_request(request, cx);
 
         let http_client = self.http_client.clone();
-        let Ok(api_url) = cx.update(|cx| {
+        let Ok((enabled, api_url, api_key, timeout)) = cx.update(|cx| {
             let settings = &AllLanguageModelSettings::get_global(cx).ollama;
-            settings.api_url.clone()
+            (
+                ollama_completions::is_enabled(cx),
+                settings.api_url.clone(),
+                ollama::resolve_api_key(settings.api_key.as_deref()),
+                settings.chat_timeout,
+            )
         }) else {
             return futures::future::ready(Err(anyhow!("App state dropped"))).boxed();
         };
+        if !enabled {
+            return futures::future::ready(Err(OllamaDisabled.into())).boxed();
+        }
 
         let future = self.request_limiter.stream(async move {
-            let response = stream_chat_completion(http_client.as_ref(), &api_url, request).await?;
+            let response = stream_chat_completion(
+                http_client.as_ref(),
+                &api_url,
+                api_key.as_deref(),
+                request,
+                timeout,
+            )
+            .await?;
             let stream = response
                 .filter_map(|response| async move {
                     match response {
@@ -371,16 +510,51 @@ impl LanguageModel for OllamaLanguageModel {
         cx: &AsyncAppContext,
     ) -> BoxFuture<'static, Result<BoxStream<'static, Result<String>>>> {
         use ollama::{OllamaFunctionTool, OllamaTool};
+        let model = self.model.display_name().to_string();
+        let supports_tools = self.supports_tools(cx);
+
+        // Models without tool support are asked for the arguments as JSON
+        // instead, since a request with tools would fail or confuse them.
+        let mut json_request = self.to_ollama_request(request.clone(), cx);
+        json_request.stream = false;
+        json_request.messages.push(ChatMessage::User {
+            content: format!(
+                "Call the `{tool_name}` tool ({tool_description}) by replying with only a JSON \
+                 object of its arguments, matching this JSON schema:\n{schema}"
+            ),
+        });
+        let json_response = self.request_completion(json_request, cx);
+
         let function = OllamaFunctionTool {
             name: tool_name.clone(),
             description: Some(tool_description),
             parameters: Some(schema),
         };
         let tools = vec![OllamaTool::Function { function }];
-        let request = self.to_ollama_request(request).with_tools(tools);
+        let request = self.to_ollama_request(request, cx).with_tools(tools);
         let response = self.request_completion(request, cx);
         self.request_limiter
             .run(async move {
+                if !supports_tools.await {
+                    log::warn!("{model} doesn't support tools, asking for JSON instead");
+                    let ChatMessage::Assistant { content, .. } = json_response.await?.message
+                    else {
+                        bail!("message does not have an assistant role");
+                    };
+                    let Ok(arguments) =
+                        serde_json::from_str::<serde_json::Value>(strip_code_fence(&content))
+                    else {
+                        bail!(
+                            "{model} doesn't support tools, and didn't reply with the JSON \
+                             `{tool_name}` needs. Choose a model that supports tools to use \
+                             this feature."
+                        );
+                    };
+                    return Ok(
+                        futures::stream::once(async move { Ok(arguments.to_string()) }).boxed(),
+                    );
+                }
+
                 let response = response.await?;
                 let ChatMessage::Assistant { tool_calls, .. } = response.message else {
                     bail!("message does not have an assistant role");
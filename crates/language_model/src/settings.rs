@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use anyhow::Result;
 use gpui::AppContext;
@@ -23,6 +23,8 @@ use crate::{
 /// Initializes the language model settings.
 pub fn init(fs: Arc<dyn Fs>, cx: &mut AppContext) {
     AllLanguageModelSettings::register(cx);
+    // The Ollama provider honors the `ollama.enabled` kill switch.
+    ollama_completions::OllamaSettings::register(cx);
 
     if AllLanguageModelSettings::get_global(cx)
         .openai
@@ -151,6 +153,11 @@ pub struct AnthropicSettingsContentV1 {
 pub struct OllamaSettingsContent {
     pub api_url: Option<String>,
     pub available_models: Option<Vec<provider::ollama::AvailableModel>>,
+    /// A bearer token for servers behind an authenticating reverse proxy.
+    /// Falls back to the `OLLAMA_API_KEY` environment variable.
+    pub api_key: Option<String>,
+    /// How long to wait, in seconds, for a response to start streaming.
+    pub chat_timeout_secs: Option<u64>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
@@ -277,6 +284,17 @@ impl settings::Settings for AllLanguageModelSettings {
                 &mut settings.ollama.available_models,
                 ollama.as_ref().and_then(|s| s.available_models.clone()),
             );
+            merge(
+                &mut settings.ollama.api_key,
+                ollama.as_ref().and_then(|s| s.api_key.clone()).map(Some),
+            );
+            merge(
+                &mut settings.ollama.chat_timeout,
+                ollama
+                    .as_ref()
+                    .and_then(|s| s.chat_timeout_secs)
+                    .map(|secs| Some(Duration::from_secs(secs))),
+            );
 
             // OpenAI
             let (openai, upgraded) = match value.openai.clone().map(|s| s.upgrade()) {
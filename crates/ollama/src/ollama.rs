@@ -1,13 +1,65 @@
+mod ollama_cloud;
+mod ollama_embed;
+mod ollama_error;
+#[cfg(any(test, feature = "test-support"))]
+mod ollama_fake_server;
+mod ollama_grammar;
+mod ollama_ndjson;
+mod ollama_openai;
+mod ollama_pull;
+mod ollama_request_queue;
+mod ollama_rerank;
+mod ollama_token_usage;
+mod ollama_transform;
+mod ollama_unix_socket;
+
+pub use ollama_cloud::{
+    get_cloud_models, hosted_model_name, is_cloud_model, is_cloud_url, proxied_model_name,
+    OLLAMA_CLOUD_URL,
+};
+pub use ollama_embed::{embed, embed_batched, EmbedBatchLimits, EmbedRequest, EmbedResponse};
+pub use ollama_error::OllamaError;
+pub(crate) use ollama_error::{is_retryable, response_error, retry_after};
+#[cfg(any(test, feature = "test-support"))]
+pub use ollama_fake_server::{FakeOllamaServer, FakeRequest};
+pub use ollama_grammar::{JSON_GRAMMAR, UNIFIED_DIFF_GRAMMAR};
+pub use ollama_ndjson::{ndjson_stream, NdjsonDecoder};
+pub use ollama_openai::generate_openai;
+pub use ollama_pull::{pull_model_with_progress, LayerProgress, PullProgress, PULL_RETRY};
+pub use ollama_request_queue::{RequestPermit, RequestPriority, RequestQueue};
+pub use ollama_rerank::{rerank, RankedCandidate};
+pub use ollama_token_usage::{format_token_count, TokenUsage, TokenUsageKind, Tokens};
+pub use ollama_transform::{stream_transformation, transformation_request};
+pub use ollama_unix_socket::{api_endpoint, is_unix_socket_url, UnixSocketClient};
+
 use anyhow::{anyhow, Context, Result};
-use futures::{io::BufReader, stream::BoxStream, AsyncBufReadExt, AsyncReadExt, StreamExt};
-use http_client::{http, AsyncBody, HttpClient, Method, Request as HttpRequest};
+use chrono::{DateTime, Utc};
+use futures::{
+    future::{self, Either},
+    stream::BoxStream,
+    AsyncReadExt, Future, StreamExt,
+};
+use http_client::{http, AsyncBody, HttpClient, Method, Request as HttpRequest, Response};
+use rand::Rng as _;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::{value::RawValue, Value};
-use std::{convert::TryFrom, sync::Arc, time::Duration};
+use std::{collections::HashMap, convert::TryFrom, pin::pin, sync::Arc, time::Duration};
 
 pub const OLLAMA_API_URL: &str = "http://localhost:11434";
 
+/// The environment variable consulted for a bearer token when none is configured
+/// in settings.
+pub const OLLAMA_API_KEY_VAR: &str = "OLLAMA_API_KEY";
+
+/// Returns the configured bearer token, or the one in [`OLLAMA_API_KEY_VAR`].
+pub fn resolve_api_key(configured: Option<&str>) -> Option<String> {
+    configured
+        .map(ToString::to_string)
+        .or_else(|| std::env::var(OLLAMA_API_KEY_VAR).ok())
+        .filter(|api_key| !api_key.is_empty())
+}
+
 #[derive(Clone, Copy, Serialize, Deserialize, Debug, Eq, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum Role {
@@ -114,7 +166,7 @@ impl Model {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 #[serde(tag = "role", rename_all = "lowercase")]
 pub enum ChatMessage {
     Assistant {
@@ -129,13 +181,13 @@ pub enum ChatMessage {
     },
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 #[serde(rename_all = "lowercase")]
 pub enum OllamaToolCall {
     Function(OllamaFunctionCall),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct OllamaFunctionCall {
     pub name: String,
     pub arguments: Box<RawValue>,
@@ -161,7 +213,12 @@ pub struct ChatRequest {
     pub stream: bool,
     pub keep_alive: KeepAlive,
     pub options: Option<ChatOptions>,
+    /// Left out when empty, since some models fail on any `tools` field.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     pub tools: Vec<OllamaTool>,
+    /// Where the request waits its turn while the server is busy. Not sent.
+    #[serde(skip)]
+    pub priority: RequestPriority,
 }
 
 impl ChatRequest {
@@ -173,26 +230,139 @@ impl ChatRequest {
 }
 
 // https://github.com/ollama/ollama/blob/main/docs/modelfile.md#valid-parameters-and-values
-#[derive(Serialize, Default, Debug)]
+#[derive(Clone, Serialize, Deserialize, Default, Debug)]
 pub struct ChatOptions {
     pub num_ctx: Option<usize>,
+    /// The number of layers offloaded to the GPU; 0 runs on the CPU only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_gpu: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_thread: Option<u32>,
     pub num_predict: Option<isize>,
     pub stop: Option<Vec<String>>,
     pub temperature: Option<f32>,
     pub top_p: Option<f32>,
+    /// Mirostat sampling: 0 is off, 1 is Mirostat, 2 is Mirostat 2.0.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mirostat: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mirostat_eta: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mirostat_tau: Option<f32>,
+    /// How far back to look when penalizing repetition; 0 disables it and -1
+    /// uses the whole context window.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat_last_n: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub penalize_newline: Option<bool>,
+    /// How strongly tokens repeated within `repeat_last_n` are penalized; 1.0
+    /// is no penalty.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat_penalty: Option<f32>,
+    /// Penalizes tokens that already appeared, however often.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    /// Penalizes tokens by how often they already appeared.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    /// A GBNF grammar the output must follow, such as [`JSON_GRAMMAR`], for
+    /// llama.cpp-based servers that take one. Ollama ignores it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grammar: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct ChatResponseDelta {
-    #[allow(unused)]
     pub model: String,
     #[allow(unused)]
     pub created_at: String,
     pub message: ChatMessage,
     #[allow(unused)]
     pub done_reason: Option<String>,
+    pub done: bool,
+    /// The number of tokens in the prompt, sent with the last chunk.
+    #[serde(default)]
+    pub prompt_eval_count: Option<u64>,
+    /// The number of tokens generated, sent with the last chunk.
+    #[serde(default)]
+    pub eval_count: Option<u64>,
+}
+
+impl ChatResponseDelta {
+    fn record_token_usage(&self) {
+        if self.done {
+            TokenUsage::global().record(
+                &self.model,
+                TokenUsageKind::Chat,
+                self.prompt_eval_count,
+                self.eval_count,
+            );
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Debug)]
+pub struct GenerateRequest {
+    pub model: String,
+    pub prompt: String,
+    /// Text after the insertion point, used by models that support fill-in-the-middle.
+    pub suffix: Option<String>,
+    pub stream: bool,
+    pub keep_alive: KeepAlive,
+    pub options: Option<ChatOptions>,
+    /// Replaces the system message defined in the model's Modelfile.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    /// Sends the prompt as is, without applying the model's prompt template.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<bool>,
+    /// A JSON schema the response must follow.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<Value>,
+    /// The context returned with an earlier response, continued by `prompt`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<Vec<u32>>,
+    /// Where the request waits its turn while the server is busy. Not sent.
+    #[serde(skip)]
+    pub priority: RequestPriority,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GenerateResponse {
+    pub model: String,
+    #[allow(unused)]
+    pub created_at: String,
+    pub response: String,
+    pub done_reason: Option<String>,
     #[allow(unused)]
     pub done: bool,
+    /// The number of tokens in the prompt.
+    #[serde(default)]
+    pub prompt_eval_count: Option<u64>,
+    /// The number of tokens generated.
+    #[serde(default)]
+    pub eval_count: Option<u64>,
+    /// Nanoseconds spent generating the response.
+    #[serde(default)]
+    pub eval_duration: Option<u64>,
+    /// The prompt and response in the model's tokens, for continuing from
+    /// them in another request.
+    #[serde(default)]
+    pub context: Option<Vec<u32>>,
+}
+
+impl GenerateResponse {
+    /// Generation speed as reported by the server, excluding prompt processing.
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        let eval_count = self.eval_count?;
+        let eval_duration = self.eval_duration.filter(|duration| *duration > 0)?;
+        Some(eval_count as f64 / Duration::from_nanos(eval_duration).as_secs_f64())
+    }
+
+    /// Adds the tokens the response reports to the [`TokenUsage`] of `kind`.
+    pub fn record_token_usage(&self, kind: TokenUsageKind) {
+        TokenUsage::global().record(&self.model, kind, self.prompt_eval_count, self.eval_count);
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -217,6 +387,113 @@ pub struct LocalModel {
     pub details: ModelDetails,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct RunningModelsResponse {
+    pub models: Vec<RunningModel>,
+}
+
+/// A model loaded into memory, as listed by `/api/ps`.
+#[derive(Clone, Deserialize, Debug, PartialEq)]
+pub struct RunningModel {
+    pub name: String,
+    /// Bytes of memory the model takes up.
+    pub size: u64,
+    /// How many of those bytes are in video memory.
+    #[serde(default)]
+    pub size_vram: u64,
+    /// When the model will be unloaded unless it's used again.
+    pub expires_at: DateTime<Utc>,
+}
+
+impl RunningModel {
+    /// A summary of the memory the model uses and when it will be unloaded,
+    /// like "4.7 GB, 100% GPU, unloads in 4 min".
+    pub fn description(&self, now: DateTime<Utc>) -> String {
+        let size = format!("{:.1} GB", self.size as f64 / 1e9);
+        let processor = if self.size_vram == 0 {
+            "CPU".to_string()
+        } else {
+            let gpu_share = (self.size_vram as f64 / self.size.max(1) as f64 * 100.).round();
+            format!("{gpu_share}% GPU")
+        };
+        let remaining = self.expires_at - now;
+        let expiry = if remaining > chrono::Duration::days(365) {
+            "kept loaded".to_string()
+        } else if remaining < chrono::Duration::minutes(1) {
+            "unloads in under a minute".to_string()
+        } else if remaining < chrono::Duration::hours(1) {
+            format!("unloads in {} min", remaining.num_minutes())
+        } else {
+            format!("unloads in {} h", remaining.num_hours())
+        };
+        format!("{size}, {processor}, {expiry}")
+    }
+}
+
+/// The parts of an `/api/show` response used to size prompts and to check
+/// what the model supports.
+#[derive(Deserialize, Debug, Default)]
+pub struct ModelShowResponse {
+    /// The model's Modelfile parameters, one `name value` pair per line.
+    #[serde(default)]
+    pub parameters: String,
+    /// Architecture details, such as `llama.context_length`.
+    #[serde(default)]
+    pub model_info: HashMap<String, Value>,
+    /// What the model can do, such as `tools`. Only reported by newer servers.
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    /// The model's prompt template.
+    #[serde(default)]
+    pub template: String,
+}
+
+impl ModelShowResponse {
+    /// The context window the model was trained with.
+    pub fn context_length(&self) -> Option<usize> {
+        self.model_info
+            .iter()
+            .find(|(key, _)| key.ends_with(".context_length"))
+            .and_then(|(_, value)| value.as_u64())
+            .map(|length| length as usize)
+    }
+
+    /// The `num_ctx` set in the model's Modelfile, which Ollama uses instead
+    /// of its own default.
+    pub fn num_ctx(&self) -> Option<usize> {
+        self.parameters.lines().find_map(|line| {
+            let mut parts = line.split_whitespace();
+            (parts.next()? == "num_ctx").then_some(())?;
+            parts.next()?.parse().ok()
+        })
+    }
+
+    /// Whether the model can call tools, going by the capabilities the server
+    /// reports or, on older servers, whether its template renders tools.
+    pub fn supports_tools(&self) -> bool {
+        if self.capabilities.is_empty() {
+            self.template.contains(".Tools")
+        } else {
+            self.capabilities
+                .iter()
+                .any(|capability| capability == "tools")
+        }
+    }
+
+    /// Whether the model can fill in the middle, taking the text after the
+    /// cursor as a suffix, going by the capabilities the server reports or,
+    /// on older servers, whether its template renders a suffix.
+    pub fn supports_fim(&self) -> bool {
+        if self.capabilities.is_empty() {
+            self.template.contains(".Suffix")
+        } else {
+            self.capabilities
+                .iter()
+                .any(|capability| capability == "insert")
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ModelDetails {
     pub format: String,
@@ -229,59 +506,271 @@ pub struct ModelDetails {
 pub async fn complete(
     client: &dyn HttpClient,
     api_url: &str,
+    api_key: Option<&str>,
     request: ChatRequest,
+    timeout: Option<Duration>,
 ) -> Result<ChatResponseDelta> {
-    let uri = format!("{api_url}/api/chat");
+    let uri = api_endpoint(api_url, "api/chat");
     let request_builder = HttpRequest::builder()
         .method(Method::POST)
         .uri(uri)
         .header("Content-Type", "application/json");
+    let request_builder = authorize(request_builder, api_key);
 
+    let model = request.model.clone();
+    let priority = request.priority;
     let serialized_request = serde_json::to_string(&request)?;
     let request = request_builder.body(AsyncBody::from(serialized_request))?;
 
-    let mut response = client.send(request).await?;
-    if response.status().is_success() {
+    // Time spent waiting for a slot on the server doesn't count toward the
+    // timeout.
+    let _permit = RequestQueue::global().acquire(api_url, priority).await;
+    with_timeout(timeout, async {
+        let mut response = client.send(request).await?;
+        if response.status().is_success() {
+            let mut body = Vec::new();
+            response.body_mut().read_to_end(&mut body).await?;
+            let response_message: ChatResponseDelta = serde_json::from_slice(&body)?;
+            response_message.record_token_usage();
+            Ok(response_message)
+        } else {
+            let mut body = Vec::new();
+            response.body_mut().read_to_end(&mut body).await?;
+            let body_str = std::str::from_utf8(&body)?;
+            Err(response_error(
+                response.status(),
+                response.headers(),
+                body_str,
+                &model,
+            ))
+        }
+    })
+    .await
+}
+
+/// Controls how idempotent requests are retried after transient failures,
+/// i.e. connection errors and 5xx responses.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// The total number of attempts, including the first one.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(1 << retry.min(16))
+            .min(self.max_backoff);
+        // Jitter keeps concurrent requests from retrying in lockstep.
+        backoff.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+/// Attaches the bearer token expected by reverse proxies in front of Ollama.
+pub fn authorize(builder: http::request::Builder, api_key: Option<&str>) -> http::request::Builder {
+    match api_key {
+        Some(api_key) => builder.header("Authorization", format!("Bearer {api_key}")),
+        None => builder,
+    }
+}
+
+/// Fails with [`OllamaError::Timeout`] if `future` doesn't finish within
+/// `timeout`, when one is given.
+pub async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let Some(timeout) = timeout else {
+        return future.await;
+    };
+
+    match future::select(pin!(future), smol::Timer::after(timeout)).await {
+        Either::Left((result, _)) => result,
+        Either::Right(_) => Err(OllamaError::Timeout(timeout).into()),
+    }
+}
+
+/// Sends the request built by `build_request`, retrying transient failures
+/// according to `retry`. Only use this for requests that are safe to repeat.
+///
+/// A server that's rate-limiting requests is waited on for as long as its
+/// `Retry-After` header asks, as long as that's within the policy's longest
+/// backoff. Otherwise its response is returned right away.
+///
+/// Once attempts are exhausted, the last 5xx or 429 response is returned
+/// as-is so the caller can report its body, while connection errors are
+/// returned with the number of attempts made.
+async fn send_with_retry(
+    client: &dyn HttpClient,
+    retry: RetryPolicy,
+    build_request: impl Fn() -> Result<HttpRequest<AsyncBody>>,
+) -> Result<Response<AsyncBody>> {
+    let mut attempt = 1;
+    loop {
+        let (error, backoff) = match client.send(build_request()?).await {
+            Ok(response) if !is_retryable(response.status()) || attempt >= retry.max_attempts => {
+                return Ok(response);
+            }
+            Ok(response) => {
+                let backoff = match retry_after(response.headers()) {
+                    Some(retry_after) if retry_after > retry.max_backoff => return Ok(response),
+                    Some(retry_after) => retry_after,
+                    None => retry.backoff(attempt - 1),
+                };
+                (
+                    anyhow!("server responded with {}", response.status()),
+                    backoff,
+                )
+            }
+            Err(error) if attempt >= retry.max_attempts => {
+                return Err(error.context(OllamaError::ConnectionRefused { attempts: attempt }));
+            }
+            Err(error) => (error, retry.backoff(attempt - 1)),
+        };
+
+        log::debug!("retrying Ollama request in {backoff:?} after error: {error}");
+        smol::Timer::after(backoff).await;
+        attempt += 1;
+    }
+}
+
+pub async fn generate(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: Option<&str>,
+    request: GenerateRequest,
+    retry: RetryPolicy,
+    timeout: Option<Duration>,
+) -> Result<GenerateResponse> {
+    let uri = api_endpoint(api_url, "api/generate");
+    let serialized_request = serde_json::to_string(&request)?;
+
+    let _permit = RequestQueue::global()
+        .acquire(api_url, request.priority)
+        .await;
+    with_timeout(timeout, async {
+        let mut response = send_with_retry(client, retry, || {
+            let request_builder = HttpRequest::builder()
+                .method(Method::POST)
+                .uri(&uri)
+                .header("Content-Type", "application/json");
+            Ok(authorize(request_builder, api_key)
+                .body(AsyncBody::from(serialized_request.clone()))?)
+        })
+        .await?;
         let mut body = Vec::new();
         response.body_mut().read_to_end(&mut body).await?;
-        let response_message: ChatResponseDelta = serde_json::from_slice(&body)?;
-        Ok(response_message)
+
+        if response.status().is_success() {
+            let response: GenerateResponse = serde_json::from_slice(&body)
+                .map_err(|error| OllamaError::Decode(error.to_string()))?;
+            Ok(response)
+        } else {
+            let body_str = std::str::from_utf8(&body)?;
+            Err(response_error(
+                response.status(),
+                response.headers(),
+                body_str,
+                &request.model,
+            ))
+        }
+    })
+    .await
+}
+
+/// Streams a generated response, one chunk per token or so. The last chunk
+/// has `done` set and carries the statistics for the whole response.
+/// `timeout` bounds how long to wait for the server to start responding, not
+/// the duration of the whole stream.
+pub async fn stream_generate(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: Option<&str>,
+    request: GenerateRequest,
+    timeout: Option<Duration>,
+) -> Result<BoxStream<'static, Result<GenerateResponse>>> {
+    let uri = api_endpoint(api_url, "api/generate");
+    let request_builder = http::Request::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header("Content-Type", "application/json");
+    let request_builder = authorize(request_builder, api_key);
+
+    let model = request.model.clone();
+    let priority = request.priority;
+    let request = GenerateRequest {
+        stream: true,
+        ..request
+    };
+    let request = request_builder.body(AsyncBody::from(serde_json::to_string(&request)?))?;
+    let permit = RequestQueue::global().acquire(api_url, priority).await;
+    let mut response = with_timeout(timeout, client.send(request)).await?;
+    if response.status().is_success() {
+        Ok(ndjson_stream(response.into_body())
+            // Holds the server's slot until the whole response has been read.
+            .inspect(move |_| {
+                let _permit = &permit;
+            })
+            .boxed())
     } else {
-        let mut body = Vec::new();
-        response.body_mut().read_to_end(&mut body).await?;
-        let body_str = std::str::from_utf8(&body)?;
-        Err(anyhow!(
-            "Failed to connect to API: {} {}",
+        let mut body = String::new();
+        response.body_mut().read_to_string(&mut body).await?;
+
+        Err(response_error(
             response.status(),
-            body_str
+            response.headers(),
+            &body,
+            &model,
         ))
     }
 }
 
+/// Streams a chat response. `timeout` bounds how long to wait for the server
+/// to start responding, not the duration of the whole stream.
 pub async fn stream_chat_completion(
     client: &dyn HttpClient,
     api_url: &str,
+    api_key: Option<&str>,
     request: ChatRequest,
+    timeout: Option<Duration>,
 ) -> Result<BoxStream<'static, Result<ChatResponseDelta>>> {
-    let uri = format!("{api_url}/api/chat");
+    let uri = api_endpoint(api_url, "api/chat");
     let request_builder = http::Request::builder()
         .method(Method::POST)
         .uri(uri)
         .header("Content-Type", "application/json");
+    let request_builder = authorize(request_builder, api_key);
 
+    let model = request.model.clone();
+    let priority = request.priority;
     let request = request_builder.body(AsyncBody::from(serde_json::to_string(&request)?))?;
-    let mut response = client.send(request).await?;
+    let permit = RequestQueue::global().acquire(api_url, priority).await;
+    let mut response = with_timeout(timeout, client.send(request)).await?;
     if response.status().is_success() {
-        let reader = BufReader::new(response.into_body());
-
-        Ok(reader
-            .lines()
-            .filter_map(move |line| async move {
-                match line {
-                    Ok(line) => {
-                        Some(serde_json::from_str(&line).context("Unable to parse chat response"))
-                    }
-                    Err(e) => Some(Err(e.into())),
+        Ok(ndjson_stream(response.into_body())
+            // Holds the server's slot until the whole reply has been read.
+            .inspect(move |chunk| {
+                let _permit = &permit;
+                if let Ok(chunk) = chunk {
+                    chunk.record_token_usage();
                 }
             })
             .boxed())
@@ -289,10 +778,11 @@ pub async fn stream_chat_completion(
         let mut body = String::new();
         response.body_mut().read_to_string(&mut body).await?;
 
-        Err(anyhow!(
-            "Failed to connect to Ollama API: {} {}",
+        Err(response_error(
             response.status(),
-            body,
+            response.headers(),
+            &body,
+            &model,
         ))
     }
 }
@@ -300,49 +790,264 @@ pub async fn stream_chat_completion(
 pub async fn get_models(
     client: &dyn HttpClient,
     api_url: &str,
+    api_key: Option<&str>,
     _: Option<Duration>,
 ) -> Result<Vec<LocalModelListing>> {
-    let uri = format!("{api_url}/api/tags");
+    let uri = api_endpoint(api_url, "api/tags");
+    let mut response = send_with_retry(client, RetryPolicy::default(), || {
+        let request_builder = HttpRequest::builder()
+            .method(Method::GET)
+            .uri(&uri)
+            .header("Accept", "application/json");
+        Ok(authorize(request_builder, api_key).body(AsyncBody::default())?)
+    })
+    .await?;
+
+    let mut body = String::new();
+    response.body_mut().read_to_string(&mut body).await?;
+
+    if response.status().is_success() {
+        let response: LocalModelsResponse =
+            serde_json::from_str(&body).context("Unable to parse Ollama tag listing")?;
+
+        Ok(response.models)
+    } else {
+        Err(response_error(
+            response.status(),
+            response.headers(),
+            &body,
+            "",
+        ))
+    }
+}
+
+pub async fn show_model(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: Option<&str>,
+    model: &str,
+) -> Result<ModelShowResponse> {
+    let uri = api_endpoint(api_url, "api/show");
+    let serialized_request = serde_json::to_string(&serde_json::json!({ "model": model }))?;
+    let mut response =
+        send_with_retry(client, RetryPolicy::default(), || {
+            let request_builder = HttpRequest::builder()
+                .method(Method::POST)
+                .uri(&uri)
+                .header("Content-Type", "application/json");
+            Ok(authorize(request_builder, api_key)
+                .body(AsyncBody::from(serialized_request.clone()))?)
+        })
+        .await?;
+
+    let mut body = String::new();
+    response.body_mut().read_to_string(&mut body).await?;
+
+    if response.status().is_success() {
+        serde_json::from_str(&body).context("Unable to parse Ollama model details")
+    } else {
+        Err(response_error(
+            response.status(),
+            response.headers(),
+            &body,
+            model,
+        ))
+    }
+}
+
+/// The models the server currently has loaded into memory.
+pub async fn running_models(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: Option<&str>,
+) -> Result<Vec<RunningModel>> {
+    let uri = api_endpoint(api_url, "api/ps");
     let request_builder = HttpRequest::builder()
         .method(Method::GET)
         .uri(uri)
         .header("Accept", "application/json");
-
-    let request = request_builder.body(AsyncBody::default())?;
-
+    let request = authorize(request_builder, api_key).body(AsyncBody::default())?;
+    let _permit = RequestQueue::global()
+        .acquire(api_url, RequestPriority::UserInitiated)
+        .await;
     let mut response = client.send(request).await?;
 
     let mut body = String::new();
     response.body_mut().read_to_string(&mut body).await?;
 
     if response.status().is_success() {
-        let response: LocalModelsResponse =
-            serde_json::from_str(&body).context("Unable to parse Ollama tag listing")?;
-
+        let response: RunningModelsResponse =
+            serde_json::from_str(&body).context("Unable to parse Ollama's loaded models")?;
         Ok(response.models)
     } else {
-        Err(anyhow!(
-            "Failed to connect to Ollama API: {} {}",
+        Err(response_error(
+            response.status(),
+            response.headers(),
+            &body,
+            "",
+        ))
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct CreateModelRequest<'a> {
+    model: &'a str,
+    /// Older servers read the model's definition from the whole Modelfile,
+    /// newer ones from the fields below.
+    modelfile: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parameters: Option<&'a serde_json::Map<String, Value>>,
+    stream: bool,
+}
+
+/// Creates `model` on the server from the contents of a Modelfile, replacing
+/// any model with the same name.
+pub async fn create_model(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: Option<&str>,
+    model: &str,
+    modelfile: &str,
+) -> Result<()> {
+    let parsed = parse_modelfile(modelfile);
+    let request = CreateModelRequest {
+        model,
+        modelfile,
+        from: parsed.from.as_deref(),
+        system: parsed.system.as_deref(),
+        parameters: (!parsed.parameters.is_empty()).then_some(&parsed.parameters),
+        stream: false,
+    };
+    let uri = api_endpoint(api_url, "api/create");
+    let request_builder = HttpRequest::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header("Content-Type", "application/json");
+    let request = authorize(request_builder, api_key)
+        .body(AsyncBody::from(serde_json::to_string(&request)?))?;
+    let _permit = RequestQueue::global()
+        .acquire(api_url, RequestPriority::UserInitiated)
+        .await;
+    let mut response = client.send(request).await?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        let mut body = String::new();
+        response.body_mut().read_to_string(&mut body).await?;
+
+        Err(response_error(
             response.status(),
-            body,
+            response.headers(),
+            &body,
+            model,
+        ))
+    }
+}
+
+/// Downloads `model` from the Ollama library to the server. Without
+/// streaming, the request only returns once the download is complete, which
+/// can take minutes for large models; [`pull_model_with_progress`] reports
+/// how it's going instead.
+pub async fn pull_model(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: Option<&str>,
+    model: &str,
+) -> Result<()> {
+    let uri = api_endpoint(api_url, "api/pull");
+    let request_builder = HttpRequest::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header("Content-Type", "application/json");
+    let request = authorize(request_builder, api_key).body(AsyncBody::from(
+        serde_json::json!({ "model": model, "stream": false }).to_string(),
+    ))?;
+    let _permit = RequestQueue::global()
+        .acquire(api_url, RequestPriority::UserInitiated)
+        .await;
+    let mut response = client.send(request).await?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        let mut body = String::new();
+        response.body_mut().read_to_string(&mut body).await?;
+
+        Err(response_error(
+            response.status(),
+            response.headers(),
+            &body,
+            model,
+        ))
+    }
+}
+
+/// Asks the server to unload `model` from memory right away, freeing the
+/// memory it takes up for other models.
+pub async fn unload_model(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: Option<&str>,
+    model: &str,
+) -> Result<()> {
+    let uri = api_endpoint(api_url, "api/generate");
+    let request_builder = HttpRequest::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header("Content-Type", "application/json");
+    let request = authorize(request_builder, api_key).body(AsyncBody::from(
+        serde_json::to_string(&serde_json::json!({
+            "model": model,
+            "keep_alive": 0,
+        }))?,
+    ))?;
+    let _permit = RequestQueue::global()
+        .acquire(api_url, RequestPriority::UserInitiated)
+        .await;
+    let mut response = client.send(request).await?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        let mut body = String::new();
+        response.body_mut().read_to_string(&mut body).await?;
+
+        Err(response_error(
+            response.status(),
+            response.headers(),
+            &body,
+            model,
         ))
     }
 }
 
 /// Sends an empty request to Ollama to trigger loading the model
-pub async fn preload_model(client: Arc<dyn HttpClient>, api_url: &str, model: &str) -> Result<()> {
-    let uri = format!("{api_url}/api/generate");
-    let request = HttpRequest::builder()
+pub async fn preload_model(
+    client: Arc<dyn HttpClient>,
+    api_url: &str,
+    api_key: Option<&str>,
+    model: &str,
+) -> Result<()> {
+    let uri = api_endpoint(api_url, "api/generate");
+    let request_builder = HttpRequest::builder()
         .method(Method::POST)
         .uri(uri)
-        .header("Content-Type", "application/json")
-        .body(AsyncBody::from(serde_json::to_string(
-            &serde_json::json!({
-                "model": model,
-                "keep_alive": "15m",
-            }),
-        )?))?;
+        .header("Content-Type", "application/json");
+    let request = authorize(request_builder, api_key).body(AsyncBody::from(
+        serde_json::to_string(&serde_json::json!({
+            "model": model,
+            "keep_alive": "15m",
+        }))?,
+    ))?;
 
+    let _permit = RequestQueue::global()
+        .acquire(api_url, RequestPriority::Background)
+        .await;
     let mut response = client.send(request).await?;
 
     if response.status().is_success() {
@@ -351,10 +1056,195 @@ pub async fn preload_model(client: Arc<dyn HttpClient>, api_url: &str, model: &s
         let mut body = String::new();
         response.body_mut().read_to_string(&mut body).await?;
 
-        Err(anyhow!(
-            "Failed to connect to Ollama API: {} {}",
+        Err(response_error(
             response.status(),
-            body,
+            response.headers(),
+            &body,
+            model,
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_response_tokens_per_second() {
+        let response: GenerateResponse = serde_json::from_value(serde_json::json!({
+            "model": "qwen2.5-coder:1.5b",
+            "created_at": "2024-10-01T00:00:00Z",
+            "response": "a + b",
+            "done": true,
+            "prompt_eval_count": 26,
+            "eval_count": 40,
+            "eval_duration": 500_000_000,
+        }))
+        .unwrap();
+        assert_eq!(response.tokens_per_second(), Some(80.));
+
+        let response: GenerateResponse = serde_json::from_value(serde_json::json!({
+            "model": "qwen2.5-coder:1.5b",
+            "created_at": "2024-10-01T00:00:00Z",
+            "response": "a + b",
+            "done": true,
+        }))
+        .unwrap();
+        assert_eq!(response.tokens_per_second(), None);
+    }
+
+    #[test]
+    fn test_running_models_response() {
+        let response: RunningModelsResponse = serde_json::from_value(serde_json::json!({
+            "models": [
+                {
+                    "name": "qwen2.5-coder:7b",
+                    "model": "qwen2.5-coder:7b",
+                    "size": 5_700_000_000u64,
+                    "digest": "2b0496514337",
+                    "details": {},
+                    "expires_at": "2024-10-01T12:04:30.5-07:00",
+                    "size_vram": 2_850_000_000u64
+                },
+                {
+                    "name": "llama3.2:1b",
+                    "model": "llama3.2:1b",
+                    "size": 1_300_000_000u64,
+                    "digest": "baf6a787fdff",
+                    "details": {},
+                    "expires_at": "2318-01-01T00:00:00Z",
+                    "size_vram": 0
+                }
+            ]
+        }))
+        .unwrap();
+        let now = "2024-10-01T19:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(
+            response.models[0].description(now),
+            "5.7 GB, 50% GPU, unloads in 4 min"
+        );
+        assert_eq!(
+            response.models[1].description(now),
+            "1.3 GB, CPU, kept loaded"
+        );
+    }
+
+    #[test]
+    fn test_model_show_response() {
+        let response: ModelShowResponse = serde_json::from_value(serde_json::json!({
+            "parameters": "stop \"<|endoftext|>\"\nnum_ctx 8192",
+            "model_info": {
+                "general.architecture": "qwen2",
+                "qwen2.context_length": 32768,
+            },
+        }))
+        .unwrap();
+        assert_eq!(response.context_length(), Some(32768));
+        assert_eq!(response.num_ctx(), Some(8192));
+
+        let response: ModelShowResponse = serde_json::from_value(serde_json::json!({})).unwrap();
+        assert_eq!(response.context_length(), None);
+        assert_eq!(response.num_ctx(), None);
+        assert!(!response.supports_tools());
+    }
+
+    #[test]
+    fn test_model_tool_support() {
+        let response: ModelShowResponse = serde_json::from_value(serde_json::json!({
+            "template": "{{- if .Tools }}{{ .Tools }}{{ end }}{{ .Prompt }}",
+            "capabilities": ["completion"],
+        }))
+        .unwrap();
+        assert!(!response.supports_tools());
+
+        let response: ModelShowResponse = serde_json::from_value(serde_json::json!({
+            "capabilities": ["completion", "tools"],
+        }))
+        .unwrap();
+        assert!(response.supports_tools());
+
+        let response: ModelShowResponse = serde_json::from_value(serde_json::json!({
+            "template": "{{- if .Tools }}{{ .Tools }}{{ end }}{{ .Prompt }}",
+        }))
+        .unwrap();
+        assert!(response.supports_tools());
+    }
+
+    #[test]
+    fn test_model_fim_support() {
+        let response: ModelShowResponse = serde_json::from_value(serde_json::json!({
+            "capabilities": ["completion", "insert"],
+        }))
+        .unwrap();
+        assert!(response.supports_fim());
+
+        let response: ModelShowResponse = serde_json::from_value(serde_json::json!({
+            "template": "{{ if .Suffix }}<|fim_prefix|>{{ .Prompt }}{{ end }}",
+            "capabilities": ["completion"],
+        }))
+        .unwrap();
+        assert!(!response.supports_fim());
+
+        let response: ModelShowResponse = serde_json::from_value(serde_json::json!({
+            "template": "{{ if .Suffix }}<|fim_prefix|>{{ .Prompt }}{{ end }}",
+        }))
+        .unwrap();
+        assert!(response.supports_fim());
+    }
+
+    #[test]
+    fn test_chat_request_omits_unset_fields() {
+        let request = ChatRequest {
+            model: "qwen2.5-coder:7b".into(),
+            messages: Vec::new(),
+            stream: true,
+            keep_alive: KeepAlive::default(),
+            options: Some(ChatOptions {
+                num_ctx: Some(8192),
+                repeat_penalty: Some(1.1),
+                ..Default::default()
+            }),
+            tools: Vec::new(),
+            priority: RequestPriority::UserInitiated,
+        };
+        let json = serde_json::to_value(&request).unwrap();
+        assert!(json.get("tools").is_none());
+        assert!(json.get("priority").is_none());
+
+        // Unset options are left out, so the model's own defaults apply.
+        let options = json["options"].as_object().unwrap();
+        assert_eq!(options["num_ctx"], 8192);
+        assert!(options.contains_key("repeat_penalty"));
+        for option in [
+            "num_gpu",
+            "num_thread",
+            "mirostat",
+            "mirostat_eta",
+            "mirostat_tau",
+            "repeat_last_n",
+            "penalize_newline",
+            "presence_penalty",
+            "frequency_penalty",
+            "grammar",
+        ] {
+            assert!(!options.contains_key(option), "{option} was serialized");
+        }
+    }
+
+    #[test]
+    fn test_retry_backoff_is_capped_and_jittered() {
+        let retry = RetryPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+        };
+
+        for _ in 0..100 {
+            let first = retry.backoff(0);
+            assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+
+            let later = retry.backoff(8);
+            assert!(later >= Duration::from_millis(500) && later <= Duration::from_secs(1));
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use crate::{get_models, LocalModelListing};
+use anyhow::Result;
+use http_client::{HttpClient, Uri};
+
+/// Where Ollama's hosted models are served from. Requests to it need an API
+/// key from ollama.com.
+pub const OLLAMA_CLOUD_URL: &str = "https://ollama.com";
+
+/// Whether `url` is Ollama's hosted service rather than a server of its own.
+pub fn is_cloud_url(url: &str) -> bool {
+    url.parse::<Uri>()
+        .ok()
+        .and_then(|uri| uri.host().map(str::to_string))
+        .map_or(false, |host| {
+            host == "ollama.com" || host == "www.ollama.com"
+        })
+}
+
+/// Whether `model` names a hosted model that a local server proxies to
+/// ollama.com, such as `gpt-oss:120b-cloud` or `kimi-k2:cloud`.
+pub fn is_cloud_model(model: &str) -> bool {
+    model
+        .rsplit_once(':')
+        .map_or(false, |(_, tag)| tag == "cloud" || tag.ends_with("-cloud"))
+}
+
+/// The name ollama.com itself knows a proxied hosted model by, e.g.
+/// `gpt-oss:120b` for `gpt-oss:120b-cloud`. Other models are returned as is.
+pub fn hosted_model_name(model: &str) -> &str {
+    if !is_cloud_model(model) {
+        return model;
+    }
+    model
+        .strip_suffix("-cloud")
+        .or_else(|| model.strip_suffix(":cloud"))
+        .unwrap_or(model)
+}
+
+/// The name a local server proxies the hosted model `model` by, the inverse
+/// of [`hosted_model_name`].
+pub fn proxied_model_name(model: &str) -> String {
+    if is_cloud_model(model) {
+        model.to_string()
+    } else if model.contains(':') {
+        format!("{model}-cloud")
+    } else {
+        format!("{model}:cloud")
+    }
+}
+
+/// Lists the models hosted on ollama.com, by the names a local server
+/// signed in to ollama.com proxies them by.
+pub async fn get_cloud_models(
+    client: &dyn HttpClient,
+    api_key: Option<&str>,
+) -> Result<Vec<LocalModelListing>> {
+    let mut models = get_models(client, OLLAMA_CLOUD_URL, api_key, None).await?;
+    for model in &mut models {
+        model.name = proxied_model_name(&model.name);
+    }
+    Ok(models)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cloud_model_names() {
+        assert!(is_cloud_model("gpt-oss:120b-cloud"));
+        assert!(is_cloud_model("kimi-k2:cloud"));
+        assert!(!is_cloud_model("qwen2.5-coder:1.5b"));
+        assert!(!is_cloud_model("cloud"));
+        assert!(!is_cloud_model("my-cloud"));
+
+        assert_eq!(hosted_model_name("gpt-oss:120b-cloud"), "gpt-oss:120b");
+        assert_eq!(hosted_model_name("kimi-k2:cloud"), "kimi-k2");
+        assert_eq!(
+            hosted_model_name("qwen2.5-coder:1.5b"),
+            "qwen2.5-coder:1.5b"
+        );
+
+        assert_eq!(proxied_model_name("gpt-oss:120b"), "gpt-oss:120b-cloud");
+        assert_eq!(proxied_model_name("kimi-k2"), "kimi-k2:cloud");
+        assert_eq!(proxied_model_name("kimi-k2:cloud"), "kimi-k2:cloud");
+
+        assert!(is_cloud_url("https://ollama.com"));
+        assert!(is_cloud_url("https://ollama.com/api"));
+        assert!(!is_cloud_url("http://localhost:11434"));
+        assert!(!is_cloud_url("https://ollama.company.internal"));
+    }
+}
@@ -0,0 +1,203 @@
+use crate::{
+    api_endpoint, authorize, send_with_retry, KeepAlive, RequestPriority, RequestQueue, RetryPolicy,
+};
+use anyhow::{anyhow, Context as _, Result};
+use futures::{stream, AsyncReadExt as _, StreamExt as _, TryStreamExt as _};
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// A request to `/api/embed`, which embeds several inputs in one call.
+#[derive(Serialize, Debug)]
+pub struct EmbedRequest {
+    pub model: String,
+    pub input: Vec<String>,
+    /// Whether the server cuts inputs down to the model's context window
+    /// instead of failing the request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncate: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<KeepAlive>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EmbedResponse {
+    pub model: String,
+    /// One embedding per input, in the same order.
+    pub embeddings: Vec<Vec<f32>>,
+}
+
+/// How [`embed_batched`] splits inputs into requests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EmbedBatchLimits {
+    /// The most inputs sent in one request.
+    pub max_inputs: usize,
+    /// Longer inputs are cut down to this many bytes, on a character boundary.
+    pub max_input_len: usize,
+    /// The most bytes of input sent in one request, so a batch of long chunks
+    /// doesn't take so long that the request times out.
+    pub max_batch_len: usize,
+    /// How many requests are sent at once. Further batches wait for one of
+    /// them to finish, on top of the server's limit in [`RequestQueue`].
+    pub max_in_flight: usize,
+}
+
+impl Default for EmbedBatchLimits {
+    fn default() -> Self {
+        Self {
+            max_inputs: 32,
+            max_input_len: 8 * 1024,
+            max_batch_len: 64 * 1024,
+            max_in_flight: 2,
+        }
+    }
+}
+
+/// Embeds every input of `request` in a single call.
+pub async fn embed(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: Option<&str>,
+    request: EmbedRequest,
+) -> Result<EmbedResponse> {
+    let uri = api_endpoint(api_url, "api/embed");
+    let input_count = request.input.len();
+    let serialized_request = serde_json::to_string(&request)?;
+
+    let _permit = RequestQueue::global()
+        .acquire(api_url, RequestPriority::Background)
+        .await;
+    let mut response =
+        send_with_retry(client, RetryPolicy::default(), || {
+            let request_builder = HttpRequest::builder()
+                .method(Method::POST)
+                .uri(&uri)
+                .header("Content-Type", "application/json");
+            Ok(authorize(request_builder, api_key)
+                .body(AsyncBody::from(serialized_request.clone()))?)
+        })
+        .await?;
+
+    let mut body = String::new();
+    response.body_mut().read_to_string(&mut body).await?;
+
+    if response.status().is_success() {
+        let response: EmbedResponse =
+            serde_json::from_str(&body).context("Unable to parse Ollama embeddings")?;
+        if response.embeddings.len() != input_count {
+            return Err(anyhow!(
+                "Ollama returned {} embeddings for {input_count} inputs",
+                response.embeddings.len()
+            ));
+        }
+        Ok(response)
+    } else {
+        Err(anyhow!(
+            "Failed to connect to Ollama API: {} {}",
+            response.status(),
+            body,
+        ))
+    }
+}
+
+/// Embeds any number of inputs with `model`, split into batches within
+/// `limits`. Returns one embedding per input, in the same order.
+pub async fn embed_batched(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: Option<&str>,
+    model: &str,
+    inputs: &[&str],
+    limits: EmbedBatchLimits,
+) -> Result<Vec<Vec<f32>>> {
+    let inputs = inputs
+        .iter()
+        .map(|input| truncate_to_len(input, limits.max_input_len))
+        .collect::<Vec<_>>();
+    let batches = embedding_batches(&inputs, limits);
+
+    let responses = stream::iter(batches)
+        .map(|batch| {
+            let request = EmbedRequest {
+                model: model.to_string(),
+                input: inputs[batch]
+                    .iter()
+                    .map(|input| input.to_string())
+                    .collect(),
+                truncate: Some(true),
+                keep_alive: None,
+            };
+            embed(client, api_url, api_key, request)
+        })
+        .buffered(limits.max_in_flight.max(1))
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    Ok(responses
+        .into_iter()
+        .flat_map(|response| response.embeddings)
+        .collect())
+}
+
+/// Splits `inputs` into consecutive runs that each fit within `limits`. An
+/// input longer than the batch limit goes in a batch of its own.
+fn embedding_batches(inputs: &[&str], limits: EmbedBatchLimits) -> Vec<Range<usize>> {
+    let max_inputs = limits.max_inputs.max(1);
+    let mut batches = Vec::new();
+    let mut start = 0;
+    let mut batch_len = 0;
+    for (ix, input) in inputs.iter().enumerate() {
+        let full = ix - start == max_inputs || batch_len + input.len() > limits.max_batch_len;
+        if full && ix > start {
+            batches.push(start..ix);
+            start = ix;
+            batch_len = 0;
+        }
+        batch_len += input.len();
+    }
+    if start < inputs.len() {
+        batches.push(start..inputs.len());
+    }
+    batches
+}
+
+fn truncate_to_len(text: &str, max_len: usize) -> &str {
+    if text.len() <= max_len {
+        return text;
+    }
+    let mut end = max_len;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedding_batches() {
+        let limits = EmbedBatchLimits {
+            max_inputs: 3,
+            max_input_len: 100,
+            max_batch_len: 10,
+            max_in_flight: 1,
+        };
+        assert_eq!(embedding_batches(&[], limits), Vec::<Range<usize>>::new());
+        assert_eq!(
+            embedding_batches(&["a", "b", "c", "d", "e", "f", "g"], limits),
+            [0..3, 3..6, 6..7]
+        );
+        // Batches end before the input that would take them over the length
+        // limit, and an input over the limit on its own is sent alone.
+        assert_eq!(
+            embedding_batches(&["aaaa", "bbbb", "cccc", "dddddddddddd", "e"], limits),
+            [0..2, 2..3, 3..4, 4..5]
+        );
+
+        assert_eq!(truncate_to_len("hello", 10), "hello");
+        assert_eq!(truncate_to_len("hello", 3), "hel");
+        assert_eq!(truncate_to_len("héllo", 2), "h");
+    }
+}
@@ -0,0 +1,306 @@
+use crate::is_cloud_model;
+use chrono::{DateTime, Utc};
+use http_client::{http::HeaderMap, StatusCode};
+use serde::Deserialize;
+use std::time::Duration;
+
+/// The ways a request to Ollama can fail that callers handle differently.
+/// Other failures are plain [`anyhow::Error`]s. Since these are often
+/// attached as context, find them with [`OllamaError::of`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OllamaError {
+    /// No server could be connected to, after every attempt.
+    ConnectionRefused { attempts: u32 },
+    /// The server doesn't have the requested model.
+    ModelNotFound { model: String },
+    /// The prompt didn't fit in the model's context window.
+    ContextExceeded,
+    /// The request didn't finish within its timeout.
+    Timeout(Duration),
+    /// The server, or a proxy in front of it, is too busy to take requests,
+    /// and may have said how long to wait before trying again.
+    Throttled { retry_after: Option<Duration> },
+    /// The server, or a proxy in front of it, rejected the API key.
+    Unauthorized,
+    /// A local server was asked for a hosted model without being signed in to
+    /// ollama.com, which can be done by visiting `signin_url`.
+    SignInRequired { signin_url: Option<String> },
+    /// The ollama.com account has used up its allowance of hosted model
+    /// requests for now, in the server's words. Retrying won't help until
+    /// the allowance resets or the plan is upgraded.
+    UsageLimitReached(String),
+    /// The server's response couldn't be parsed.
+    Decode(String),
+}
+
+impl std::fmt::Display for OllamaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OllamaError::ConnectionRefused { attempts } => {
+                write!(
+                    f,
+                    "Failed to connect to Ollama API after {attempts} attempts"
+                )
+            }
+            OllamaError::ModelNotFound { model } => {
+                write!(f, "Ollama doesn't have the model '{model}'")
+            }
+            OllamaError::ContextExceeded => {
+                write!(f, "The prompt doesn't fit in the model's context window")
+            }
+            OllamaError::Timeout(timeout) => {
+                write!(f, "Ollama request timed out after {timeout:?}")
+            }
+            OllamaError::Throttled {
+                retry_after: Some(retry_after),
+            } => write!(f, "Ollama is busy, retry after {retry_after:?}"),
+            OllamaError::Throttled { retry_after: None } => write!(f, "Ollama is busy"),
+            OllamaError::Unauthorized => write!(f, "The Ollama server rejected the API key"),
+            OllamaError::SignInRequired { .. } => {
+                write!(f, "Sign in to ollama.com to use Ollama's hosted models")
+            }
+            OllamaError::UsageLimitReached(message) => {
+                write!(f, "Ollama cloud usage limit reached: {message}")
+            }
+            OllamaError::Decode(error) => write!(f, "Unable to parse Ollama's response: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for OllamaError {}
+
+impl OllamaError {
+    /// The Ollama error behind `error`, if it has one.
+    pub fn of(error: &anyhow::Error) -> Option<&Self> {
+        error.downcast_ref()
+    }
+
+    /// Whether the user can do something about the error, so it's worth
+    /// telling them about rather than only logging it.
+    pub fn is_actionable(&self) -> bool {
+        matches!(
+            self,
+            OllamaError::ModelNotFound { .. }
+                | OllamaError::ContextExceeded
+                | OllamaError::Unauthorized
+                | OllamaError::SignInRequired { .. }
+                | OllamaError::UsageLimitReached(_)
+        )
+    }
+
+    /// Whether the error is shown to the user: the actionable ones, and a
+    /// server that can't be reached at all, which is usually one that isn't
+    /// running.
+    pub fn is_notable(&self) -> bool {
+        self.is_actionable() || matches!(self, OllamaError::ConnectionRefused { .. })
+    }
+
+    /// A name for the kind of error, the same whatever its details, under
+    /// which it's rate limited and muted.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            OllamaError::ConnectionRefused { .. } => "connection_refused",
+            OllamaError::ModelNotFound { .. } => "model_not_found",
+            OllamaError::ContextExceeded => "context_exceeded",
+            OllamaError::Timeout(_) => "timeout",
+            OllamaError::Throttled { .. } => "throttled",
+            OllamaError::Unauthorized => "unauthorized",
+            OllamaError::SignInRequired { .. } => "sign_in_required",
+            OllamaError::UsageLimitReached(_) => "usage_limit_reached",
+            OllamaError::Decode(_) => "decode",
+        }
+    }
+}
+
+/// Whether a request that got a response with `status` may succeed if sent
+/// again later.
+pub(crate) fn is_retryable(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// How long the server asked to wait before the next request, going by a
+/// `Retry-After` header in seconds or as a date.
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get("Retry-After")?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// The error for a response with an unsuccessful `status`, as an
+/// [`OllamaError`] when its status, headers and body say which one.
+pub(crate) fn response_error(
+    status: StatusCode,
+    headers: &HeaderMap,
+    body: &str,
+    model: &str,
+) -> anyhow::Error {
+    #[derive(Deserialize)]
+    struct ErrorBody {
+        error: String,
+        /// Sent by a local server that isn't signed in to ollama.com when
+        /// asked for a hosted model.
+        signin_url: Option<String>,
+    }
+
+    let (original_message, signin_url) = serde_json::from_str::<ErrorBody>(body)
+        .map(|body| (body.error, body.signin_url))
+        .unwrap_or_else(|_| (body.to_string(), None));
+    let message = original_message.to_lowercase();
+    let error = match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+            if signin_url.is_some() || is_cloud_model(model) =>
+        {
+            Some(OllamaError::SignInRequired { signin_url })
+        }
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Some(OllamaError::Unauthorized),
+        // ollama.com turns away requests over the account's allowance with a
+        // 429 as well, but those won't succeed after a short wait.
+        StatusCode::PAYMENT_REQUIRED | StatusCode::TOO_MANY_REQUESTS
+            if status == StatusCode::PAYMENT_REQUIRED || message.contains("usage limit") =>
+        {
+            Some(OllamaError::UsageLimitReached(original_message))
+        }
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => {
+            Some(OllamaError::Throttled {
+                retry_after: retry_after(headers),
+            })
+        }
+        StatusCode::NOT_FOUND if message.contains("model") && message.contains("not found") => {
+            Some(OllamaError::ModelNotFound {
+                model: model.to_string(),
+            })
+        }
+        _ if ["context length", "context size", "context window"]
+            .iter()
+            .any(|phrase| message.contains(phrase)) =>
+        {
+            Some(OllamaError::ContextExceeded)
+        }
+        _ => None,
+    };
+    let details = anyhow::anyhow!("Failed to connect to Ollama API: {status} {body}");
+    match error {
+        Some(error) => details.context(error),
+        None => details,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_response_error() {
+        let headers = HeaderMap::new();
+        let error = response_error(
+            StatusCode::NOT_FOUND,
+            &headers,
+            r#"{"error":"model \"qwen2.5-coder:7b\" not found, try pulling it first"}"#,
+            "qwen2.5-coder:7b",
+        );
+        assert_eq!(
+            OllamaError::of(&error),
+            Some(&OllamaError::ModelNotFound {
+                model: "qwen2.5-coder:7b".into()
+            })
+        );
+        // The server's own message is kept for the logs.
+        assert!(format!("{error:?}").contains("try pulling it first"));
+
+        let error = response_error(StatusCode::UNAUTHORIZED, &headers, "", "codellama");
+        assert_eq!(OllamaError::of(&error), Some(&OllamaError::Unauthorized));
+
+        let error = response_error(
+            StatusCode::BAD_REQUEST,
+            &headers,
+            r#"{"error":"This model's maximum context length is 4096 tokens"}"#,
+            "codellama",
+        );
+        assert_eq!(OllamaError::of(&error), Some(&OllamaError::ContextExceeded));
+
+        // A 404 from a proxy that doesn't know the route isn't about the model.
+        let error = response_error(
+            StatusCode::NOT_FOUND,
+            &headers,
+            "404 page not found",
+            "codellama",
+        );
+        assert_eq!(OllamaError::of(&error), None);
+        let error = response_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &headers,
+            "oops",
+            "codellama",
+        );
+        assert_eq!(OllamaError::of(&error), None);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Retry-After", "30".parse().unwrap());
+        let error = response_error(
+            StatusCode::TOO_MANY_REQUESTS,
+            &headers,
+            "slow down",
+            "codellama",
+        );
+        assert_eq!(
+            OllamaError::of(&error),
+            Some(&OllamaError::Throttled {
+                retry_after: Some(Duration::from_secs(30))
+            })
+        );
+
+        let error = response_error(
+            StatusCode::TOO_MANY_REQUESTS,
+            &headers,
+            r#"{"error":"you've reached your hourly usage limit, please upgrade to continue"}"#,
+            "gpt-oss:120b",
+        );
+        assert_eq!(
+            OllamaError::of(&error),
+            Some(&OllamaError::UsageLimitReached(
+                "you've reached your hourly usage limit, please upgrade to continue".into()
+            ))
+        );
+
+        let error = response_error(
+            StatusCode::UNAUTHORIZED,
+            &headers,
+            r#"{"error":"unauthorized","signin_url":"https://ollama.com/connect?name=host"}"#,
+            "gpt-oss:120b-cloud",
+        );
+        assert_eq!(
+            OllamaError::of(&error),
+            Some(&OllamaError::SignInRequired {
+                signin_url: Some("https://ollama.com/connect?name=host".into())
+            })
+        );
+        let error = response_error(StatusCode::UNAUTHORIZED, &headers, "", "kimi-k2:cloud");
+        assert_eq!(
+            OllamaError::of(&error),
+            Some(&OllamaError::SignInRequired { signin_url: None })
+        );
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert("Retry-After", " 120 ".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(120)));
+        // Dates in the past mean the request can be retried right away.
+        headers.insert(
+            "Retry-After",
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+        headers.insert("Retry-After", "soon".parse().unwrap());
+        assert_eq!(retry_after(&headers), None);
+    }
+}
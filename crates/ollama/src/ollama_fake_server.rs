@@ -0,0 +1,515 @@
+use anyhow::{anyhow, Result};
+use futures::{channel::oneshot, AsyncReadExt as _};
+use http_client::{AsyncBody, FakeHttpClient, HttpClient, Request, Response, StatusCode};
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+const CREATED_AT: &str = "2024-10-01T00:00:00Z";
+/// The layers of every model pulled, with their sizes.
+pub(crate) const PULLED_LAYERS: &[(&str, u64)] = &[
+    (
+        "sha256:60e05f2100071479f596b964f89f510f057ce397ea22f2833a0cfe029bfc2463",
+        986_000_000,
+    ),
+    (
+        "sha256:66b9ea09bd5b7099cbb4fc820f31b575c0366fa439b08245566692c6784e281e",
+        68,
+    ),
+];
+
+/// A stand-in for an Ollama server, for tests. It answers the parts of the
+/// API Zed uses, `/api/generate`, `/api/chat`, `/api/tags`, `/api/show`,
+/// `/api/embed` and `/api/pull`, streamed or not, from canned responses, and records the requests it was
+/// sent. It's reached through [`Self::http_client`], so nothing listens on a
+/// port.
+#[derive(Clone)]
+pub struct FakeOllamaServer {
+    state: Arc<Mutex<State>>,
+}
+
+type CompletionHandler = Box<dyn Fn(&Value) -> String + Send + Sync>;
+
+struct State {
+    models: Vec<String>,
+    context_length: usize,
+    supports_fim: bool,
+    completion: CompletionHandler,
+    chat_reply: String,
+    unreachable: bool,
+    /// While responses are held, the senders that release them.
+    held: Option<Vec<oneshot::Sender<()>>>,
+    /// How many more pulls end partway through their first layer.
+    interrupted_pulls: usize,
+    /// How much of the first layer earlier, interrupted pulls downloaded.
+    pulled_bytes: u64,
+    requests: Vec<FakeRequest>,
+    in_flight: usize,
+}
+
+/// A request the server was sent.
+#[derive(Clone, Debug)]
+pub struct FakeRequest {
+    pub path: String,
+    pub body: Value,
+}
+
+impl Default for FakeOllamaServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FakeOllamaServer {
+    /// A server with `qwen2.5-coder:1.5b` installed, which can fill in the
+    /// middle and answers every completion with nothing.
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                models: vec!["qwen2.5-coder:1.5b".into()],
+                context_length: 4096,
+                supports_fim: true,
+                completion: Box::new(|_| String::new()),
+                chat_reply: String::new(),
+                unreachable: false,
+                held: None,
+                interrupted_pulls: 0,
+                pulled_bytes: 0,
+                requests: Vec::new(),
+                in_flight: 0,
+            })),
+        }
+    }
+
+    /// A client whose requests go to this server, whatever their URL.
+    pub fn http_client(&self) -> Arc<dyn HttpClient> {
+        let server = self.clone();
+        FakeHttpClient::create(move |request| {
+            let server = server.clone();
+            async move { server.handle(request).await }
+        })
+    }
+
+    /// Replaces the installed models. Requests for any other model are
+    /// answered the way Ollama answers them, with a 404.
+    pub fn set_models(&self, models: &[&str]) {
+        self.state.lock().models = models.iter().map(ToString::to_string).collect();
+    }
+
+    /// Sets what `/api/show` reports about every model.
+    pub fn set_model_info(&self, context_length: usize, supports_fim: bool) {
+        let mut state = self.state.lock();
+        state.context_length = context_length;
+        state.supports_fim = supports_fim;
+    }
+
+    /// Answers every `/api/generate` request with `text`.
+    pub fn set_completion(&self, text: &str) {
+        let text = text.to_string();
+        self.complete_with(move |_| text.clone());
+    }
+
+    /// Answers each `/api/generate` request with what `handler` returns for
+    /// its body.
+    pub fn complete_with(&self, handler: impl Fn(&Value) -> String + Send + Sync + 'static) {
+        self.state.lock().completion = Box::new(handler);
+    }
+
+    /// Answers every `/api/chat` request with `text`.
+    pub fn set_chat_reply(&self, text: &str) {
+        self.state.lock().chat_reply = text.to_string();
+    }
+
+    /// Fails every request to connect, as if the server weren't running.
+    pub fn set_unreachable(&self, unreachable: bool) {
+        self.state.lock().unreachable = unreachable;
+    }
+
+    /// Keeps `/api/generate` and `/api/chat` requests waiting for their
+    /// responses until [`Self::release_responses`], so tests can act while
+    /// they're in flight.
+    pub fn hold_responses(&self) {
+        self.state.lock().held.get_or_insert_with(Vec::new);
+    }
+
+    /// Answers the held requests, and later ones right away.
+    pub fn release_responses(&self) {
+        let held = self.state.lock().held.take();
+        for sender in held.into_iter().flatten() {
+            sender.send(()).ok();
+        }
+    }
+
+    /// Ends the next `count` responses to `/api/pull` halfway through the
+    /// first layer, as if the connection dropped. Like Ollama, the server
+    /// keeps what it downloaded, and the next pull starts from there.
+    pub fn interrupt_pulls(&self, count: usize) {
+        self.state.lock().interrupted_pulls = count;
+    }
+
+    /// The bodies of the requests sent to `path`, such as `/api/generate`,
+    /// oldest first.
+    pub fn requests(&self, path: &str) -> Vec<Value> {
+        self.state
+            .lock()
+            .requests
+            .iter()
+            .filter(|request| request.path == path)
+            .map(|request| request.body.clone())
+            .collect()
+    }
+
+    /// How many requests are waiting for a response. Requests whose client
+    /// gave up on them, by dropping them, aren't counted.
+    pub fn in_flight(&self) -> usize {
+        self.state.lock().in_flight
+    }
+
+    async fn handle(&self, mut request: Request<AsyncBody>) -> Result<Response<AsyncBody>> {
+        let path = request.uri().path().to_string();
+        let mut body = String::new();
+        request.body_mut().read_to_string(&mut body).await?;
+        let body = if body.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_str(&body)?
+        };
+
+        let released = {
+            let mut state = self.state.lock();
+            if state.unreachable {
+                return Err(anyhow!("Connection refused"));
+            }
+            state.requests.push(FakeRequest {
+                path: path.clone(),
+                body: body.clone(),
+            });
+            let generates = path == "/api/generate" || path == "/api/chat";
+            state.held.as_mut().filter(|_| generates).map(|held| {
+                let (sender, receiver) = oneshot::channel();
+                held.push(sender);
+                receiver
+            })
+        };
+        let _in_flight = InFlight::new(&self.state);
+        if let Some(released) = released {
+            released.await.ok();
+        }
+
+        let mut state = self.state.lock();
+        let model = body["model"].as_str().unwrap_or_default();
+        let installed = state.models.iter().any(|installed| installed == model);
+        // Ollama streams unless asked not to.
+        let stream = body["stream"].as_bool().unwrap_or(true);
+        match path.as_str() {
+            "/api/tags" => {
+                let models = state
+                    .models
+                    .iter()
+                    .map(|name| {
+                        json!({
+                            "name": name,
+                            "modified_at": CREATED_AT,
+                            "size": 0,
+                            "digest": "",
+                            "details": {
+                                "format": "gguf",
+                                "family": "qwen2",
+                                "families": null,
+                                "parameter_size": "1.5B",
+                                "quantization_level": "Q4_K_M",
+                            },
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                json_response(StatusCode::OK, json!({ "models": models }))
+            }
+            "/api/show" | "/api/generate" | "/api/chat" if !installed => json_response(
+                StatusCode::NOT_FOUND,
+                json!({ "error": format!("model \"{model}\" not found, try pulling it first") }),
+            ),
+            "/api/show" => {
+                let mut capabilities = vec!["completion"];
+                if state.supports_fim {
+                    capabilities.push("insert");
+                }
+                json_response(
+                    StatusCode::OK,
+                    json!({
+                        "parameters": "",
+                        "model_info": { "qwen2.context_length": state.context_length },
+                        "capabilities": capabilities,
+                        "template": "",
+                    }),
+                )
+            }
+            "/api/generate" => {
+                // Loading and unloading requests have no prompt.
+                let text = if body["prompt"].as_str().map_or(true, str::is_empty) {
+                    String::new()
+                } else {
+                    (state.completion)(&body)
+                };
+                let chunks = text
+                    .split_inclusive(' ')
+                    .map(|chunk| json!({ "response": chunk }))
+                    .collect::<Vec<_>>();
+                generated_response(model, json!({ "response": text }), chunks, stream)
+            }
+            "/api/chat" => {
+                let message = |content: &str| json!({ "role": "assistant", "content": content });
+                let chunks = state
+                    .chat_reply
+                    .split_inclusive(' ')
+                    .map(|chunk| json!({ "message": message(chunk) }))
+                    .collect::<Vec<_>>();
+                let whole = json!({ "message": message(&state.chat_reply) });
+                generated_response(model, whole, chunks, stream)
+            }
+            "/api/pull" => {
+                let (first_layer, first_layer_size) = PULLED_LAYERS[0];
+                let layer = |digest: &str, total: u64, completed: u64| {
+                    json!({
+                        "status": format!("pulling {}", &digest[7..19]),
+                        "digest": digest,
+                        "total": total,
+                        "completed": completed,
+                    })
+                };
+                let mut lines = vec![
+                    json!({ "status": "pulling manifest" }),
+                    layer(first_layer, first_layer_size, state.pulled_bytes),
+                ];
+                if state.interrupted_pulls > 0 {
+                    state.interrupted_pulls -= 1;
+                    state.pulled_bytes = first_layer_size / 2;
+                    lines.push(layer(first_layer, first_layer_size, state.pulled_bytes));
+                } else {
+                    for (digest, size) in PULLED_LAYERS {
+                        lines.push(layer(digest, *size, *size));
+                    }
+                    lines.extend([
+                        json!({ "status": "verifying sha256 digest" }),
+                        json!({ "status": "writing manifest" }),
+                        json!({ "status": "success" }),
+                    ]);
+                    if !installed {
+                        state.models.push(model.to_string());
+                    }
+                }
+                if !stream && state.models.iter().any(|installed| installed == model) {
+                    return json_response(StatusCode::OK, json!({ "status": "success" }));
+                }
+                let body = lines
+                    .into_iter()
+                    .map(|line| line.to_string() + "\n")
+                    .collect::<String>();
+                Ok(Response::builder()
+                    .status(StatusCode::OK)
+                    .body(AsyncBody::from(body))?)
+            }
+            "/api/embed" => {
+                // Counting letters is enough for texts with words in common
+                // to come out similar.
+                let embeddings = body["input"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|input| {
+                        let mut counts = [0.; 26];
+                        for byte in input.as_str().unwrap_or_default().bytes() {
+                            if byte.is_ascii_alphabetic() {
+                                counts[(byte.to_ascii_lowercase() - b'a') as usize] += 1.;
+                            }
+                        }
+                        counts
+                    })
+                    .collect::<Vec<[f32; 26]>>();
+                json_response(
+                    StatusCode::OK,
+                    json!({ "model": model, "embeddings": embeddings }),
+                )
+            }
+            "/api/ps" => json_response(StatusCode::OK, json!({ "models": [] })),
+            "/api/version" => json_response(StatusCode::OK, json!({ "version": "0.5.0" })),
+            _ => Ok(Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(AsyncBody::from("404 page not found"))?),
+        }
+    }
+}
+
+/// Counts a request as in flight until it's answered or dropped.
+struct InFlight(Arc<Mutex<State>>);
+
+impl InFlight {
+    fn new(state: &Arc<Mutex<State>>) -> Self {
+        state.lock().in_flight += 1;
+        Self(state.clone())
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.lock().in_flight -= 1;
+    }
+}
+
+fn json_response(status: StatusCode, body: Value) -> Result<Response<AsyncBody>> {
+    Ok(Response::builder()
+        .status(status)
+        .body(AsyncBody::from(body.to_string()))?)
+}
+
+/// A generated response: `whole` at once, or `chunks` one per line followed
+/// by a last line marking the end, with the fields every response has added.
+fn generated_response(
+    model: &str,
+    whole: Value,
+    chunks: Vec<Value>,
+    stream: bool,
+) -> Result<Response<AsyncBody>> {
+    let with_fields = |mut value: Value, done: bool| {
+        value["model"] = json!(model);
+        value["created_at"] = json!(CREATED_AT);
+        value["done"] = json!(done);
+        if done {
+            value["done_reason"] = json!("stop");
+        }
+        value
+    };
+    if !stream {
+        return json_response(StatusCode::OK, with_fields(whole, true));
+    }
+    let mut last = whole;
+    if let Some(fields) = last.as_object_mut() {
+        for value in fields.values_mut() {
+            match value {
+                Value::String(text) => text.clear(),
+                Value::Object(message) => {
+                    message.insert("content".into(), json!(""));
+                }
+                _ => {}
+            }
+        }
+    }
+    let body = chunks
+        .into_iter()
+        .map(|chunk| with_fields(chunk, false))
+        .chain([with_fields(last, true)])
+        .map(|line| line.to_string() + "\n")
+        .collect::<String>();
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(AsyncBody::from(body))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        generate, get_models, show_model, stream_chat_completion, ChatMessage, ChatRequest,
+        GenerateRequest, KeepAlive, OllamaError, RequestPriority, RetryPolicy,
+    };
+    use futures::StreamExt as _;
+
+    const API_URL: &str = "http://localhost:11434";
+
+    fn generate_request(model: &str) -> GenerateRequest {
+        GenerateRequest {
+            model: model.into(),
+            prompt: "fn main() {".into(),
+            suffix: Some("}".into()),
+            stream: false,
+            keep_alive: KeepAlive::default(),
+            options: None,
+            system: None,
+            raw: None,
+            format: None,
+            context: None,
+            priority: RequestPriority::Automatic,
+        }
+    }
+
+    #[test]
+    fn test_fake_server() {
+        let server = FakeOllamaServer::new();
+        server.set_models(&["qwen2.5-coder:1.5b", "codellama:7b"]);
+        server.set_model_info(8192, false);
+        server.complete_with(|request| format!("// {}", request["model"].as_str().unwrap()));
+        server.set_chat_reply("Hello there");
+        let client = server.http_client();
+
+        let models = smol::block_on(get_models(client.as_ref(), API_URL, None, None)).unwrap();
+        assert_eq!(models.len(), 2);
+        assert_eq!(models[1].name, "codellama:7b");
+
+        let show = smol::block_on(show_model(client.as_ref(), API_URL, None, "codellama:7b"));
+        let show = show.unwrap();
+        assert_eq!(show.context_length(), Some(8192));
+        assert!(!show.supports_fim());
+
+        let error =
+            smol::block_on(show_model(client.as_ref(), API_URL, None, "llama3:8b")).unwrap_err();
+        assert_eq!(
+            OllamaError::of(&error),
+            Some(&OllamaError::ModelNotFound {
+                model: "llama3:8b".into()
+            })
+        );
+
+        let response = smol::block_on(generate(
+            client.as_ref(),
+            API_URL,
+            None,
+            generate_request("codellama:7b"),
+            RetryPolicy::default(),
+            None,
+        ))
+        .unwrap();
+        assert_eq!(response.response, "// codellama:7b");
+        assert_eq!(server.requests("/api/generate")[0]["suffix"], "}");
+
+        let error = smol::block_on(generate(
+            client.as_ref(),
+            API_URL,
+            None,
+            generate_request("llama3:8b"),
+            RetryPolicy::default(),
+            None,
+        ))
+        .unwrap_err();
+        assert_eq!(
+            OllamaError::of(&error),
+            Some(&OllamaError::ModelNotFound {
+                model: "llama3:8b".into()
+            })
+        );
+
+        let request = ChatRequest {
+            model: "qwen2.5-coder:1.5b".into(),
+            messages: vec![ChatMessage::User {
+                content: "Hi".into(),
+            }],
+            stream: true,
+            keep_alive: KeepAlive::default(),
+            options: None,
+            tools: Vec::new(),
+            priority: RequestPriority::UserInitiated,
+        };
+        let reply = smol::block_on(async {
+            let mut stream = stream_chat_completion(client.as_ref(), API_URL, None, request, None)
+                .await
+                .unwrap();
+            let mut reply = String::new();
+            while let Some(delta) = stream.next().await {
+                if let ChatMessage::Assistant { content, .. } = delta.unwrap().message {
+                    reply.push_str(&content);
+                }
+            }
+            reply
+        });
+        assert_eq!(reply, "Hello there");
+        assert_eq!(server.in_flight(), 0);
+    }
+}
@@ -0,0 +1,82 @@
+/// A GBNF grammar for [`ChatOptions::grammar`](crate::ChatOptions::grammar)
+/// matching any JSON object, with whitespace allowed only where it's
+/// conventional.
+pub const JSON_GRAMMAR: &str = r#"root ::= object
+value ::= object | array | string | number | ("true" | "false" | "null") ws
+object ::= "{" ws ( string ":" ws value ( "," ws string ":" ws value )* )? "}" ws
+array ::= "[" ws ( value ( "," ws value )* )? "]" ws
+string ::= "\"" ( [^"\\\x7F\x00-\x1F] | "\\" ( ["\\/bfnrt] | "u" [0-9a-fA-F]{4} ) )* "\"" ws
+number ::= "-"? ( [0-9] | [1-9] [0-9]{0,15} ) ( "." [0-9]+ )? ( [eE] [-+]? [0-9] [0-9]{0,15} )? ws
+ws ::= | " " | "\n" [ \t]{0,20}
+"#;
+
+/// A GBNF grammar matching a unified diff of one or more files, as produced
+/// by `diff -u`, or by `git diff` without its extended headers.
+pub const UNIFIED_DIFF_GRAMMAR: &str = r#"root ::= file+
+file ::= "--- " path "\n" "+++ " path "\n" hunk+
+path ::= [^\n]+
+hunk ::= "@@ -" range " +" range " @@" [^\n]* "\n" line+
+range ::= [0-9]+ ( "," [0-9]+ )?
+line ::= [ +-] [^\n]* "\n" | "\\ No newline at end of file\n"
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    /// The rules a grammar defines and the rule names it refers to, skipping
+    /// literals, character classes, repetition counts and comments.
+    fn rule_names(grammar: &str) -> (BTreeSet<String>, BTreeSet<String>) {
+        let mut defined = BTreeSet::new();
+        let mut referenced = BTreeSet::new();
+        let mut chars = grammar.chars().peekable();
+        while let Some(c) = chars.next() {
+            let close = match c {
+                '"' => Some('"'),
+                '[' => Some(']'),
+                '{' => Some('}'),
+                '#' => Some('\n'),
+                _ => None,
+            };
+            if let Some(close) = close {
+                while let Some(c) = chars.next() {
+                    if c == '\\' {
+                        chars.next();
+                    } else if c == close {
+                        break;
+                    }
+                }
+            } else if c.is_ascii_alphabetic() {
+                let mut name = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                        break;
+                    }
+                    name.push(c);
+                    chars.next();
+                }
+                while chars.next_if(|c| *c == ' ').is_some() {}
+                if chars.peek() == Some(&':') {
+                    defined.insert(name);
+                } else {
+                    referenced.insert(name);
+                }
+            }
+        }
+        (defined, referenced)
+    }
+
+    #[test]
+    fn test_grammars_define_their_rules() {
+        for grammar in [JSON_GRAMMAR, UNIFIED_DIFF_GRAMMAR] {
+            let (defined, referenced) = rule_names(grammar);
+            assert!(defined.contains("root"), "no root rule in:\n{grammar}");
+            let undefined = referenced.difference(&defined).collect::<Vec<_>>();
+            assert!(
+                undefined.is_empty(),
+                "{undefined:?} undefined in:\n{grammar}"
+            );
+        }
+    }
+}
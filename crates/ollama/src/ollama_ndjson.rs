@@ -0,0 +1,157 @@
+use crate::OllamaError;
+use anyhow::{anyhow, Result};
+use futures::{
+    stream::{self, BoxStream},
+    AsyncRead, AsyncReadExt as _, StreamExt as _,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::collections::VecDeque;
+
+/// How many bytes of a streamed response are read at a time.
+const READ_CHUNK_SIZE: usize = 8192;
+
+/// Splits a streamed response, one JSON value per line, into values as its
+/// bytes arrive. A value may be split across any number of chunks, even in
+/// the middle of a character. Blank lines, which some proxies send to keep
+/// the connection open, are skipped, and an `{"error": ...}` object, which
+/// Ollama sends in place of the next value when generation fails partway,
+/// becomes an error.
+#[derive(Default)]
+pub struct NdjsonDecoder {
+    buffer: Vec<u8>,
+}
+
+impl NdjsonDecoder {
+    /// Adds `chunk`, returning the values on the lines it completes.
+    pub fn push<T: DeserializeOwned>(&mut self, chunk: &[u8]) -> Vec<Result<T>> {
+        let mut values = Vec::new();
+        let mut start = self.buffer.len();
+        self.buffer.extend_from_slice(chunk);
+        while let Some(end) = self.buffer[start..].iter().position(|byte| *byte == b'\n') {
+            let line = self.buffer.drain(..start + end + 1).collect::<Vec<_>>();
+            values.extend(parse_line(&line));
+            start = 0;
+        }
+        values
+    }
+
+    /// The value on the last line, once the response has ended without a
+    /// line break after it.
+    pub fn finish<T: DeserializeOwned>(&mut self) -> Option<Result<T>> {
+        parse_line(&std::mem::take(&mut self.buffer))
+    }
+}
+
+fn parse_line<T: DeserializeOwned>(line: &[u8]) -> Option<Result<T>> {
+    #[derive(Deserialize)]
+    struct StreamError {
+        error: String,
+    }
+
+    let line = line.trim_ascii();
+    if line.is_empty() {
+        return None;
+    }
+    if let Ok(StreamError { error }) = serde_json::from_slice(line) {
+        return Some(Err(anyhow!(
+            "Ollama stopped responding with an error: {error}"
+        )));
+    }
+    Some(
+        serde_json::from_slice(line).map_err(|error| OllamaError::Decode(error.to_string()).into()),
+    )
+}
+
+/// Reads `body` as newline-delimited JSON, yielding each value as soon as
+/// its line is complete. See [`NdjsonDecoder`].
+pub fn ndjson_stream<T: DeserializeOwned + Send + 'static>(
+    body: impl AsyncRead + Unpin + Send + 'static,
+) -> BoxStream<'static, Result<T>> {
+    struct State<R, T> {
+        body: R,
+        decoder: NdjsonDecoder,
+        chunk: Vec<u8>,
+        values: VecDeque<Result<T>>,
+        ended: bool,
+    }
+
+    let state = State {
+        body,
+        decoder: NdjsonDecoder::default(),
+        chunk: vec![0; READ_CHUNK_SIZE],
+        values: VecDeque::new(),
+        ended: false,
+    };
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(value) = state.values.pop_front() {
+                return Some((value, state));
+            }
+            if state.ended {
+                return None;
+            }
+            match state.body.read(&mut state.chunk).await {
+                Ok(0) => {
+                    state.ended = true;
+                    state.values.extend(state.decoder.finish());
+                }
+                Ok(len) => {
+                    let values = state.decoder.push(&state.chunk[..len]);
+                    state.values.extend(values);
+                }
+                Err(error) => {
+                    state.ended = true;
+                    state.values.push_back(Err(error.into()));
+                }
+            }
+        }
+    })
+    .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::io::Cursor;
+    use serde_json::Value;
+
+    #[test]
+    fn test_decoder() {
+        let mut decoder = NdjsonDecoder::default();
+        let values = decoder.push::<Value>(b"{\"response\":\"a\"}\n{\"resp");
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[0].as_ref().unwrap()["response"], "a");
+
+        // The rest of a value, a keep-alive line, and half of a character.
+        let values = decoder.push::<Value>(b"onse\":\"b\"}\r\n\n{\"response\":\"\xc3");
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[0].as_ref().unwrap()["response"], "b");
+
+        let values = decoder.push::<Value>(b"\xa9\"}\n{\"error\":\"out of memory\"}\n");
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[0].as_ref().unwrap()["response"], "é");
+        let error = values[1].as_ref().unwrap_err().to_string();
+        assert!(error.contains("out of memory"), "{error}");
+
+        assert!(decoder.finish::<Value>().is_none());
+        decoder.push::<Value>(b"{\"done\":true}");
+        assert_eq!(decoder.finish::<Value>().unwrap().unwrap()["done"], true);
+
+        let values = decoder.push::<Value>(b"not json\n");
+        assert!(matches!(
+            OllamaError::of(values[0].as_ref().unwrap_err()),
+            Some(OllamaError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn test_ndjson_stream() {
+        let body = "{\"n\":1}\n\n{\"n\":2}\n{\"n\":3}";
+        let values = smol::block_on(
+            ndjson_stream::<Value>(Cursor::new(body.as_bytes().to_vec()))
+                .map(|value| value.unwrap()["n"].as_u64().unwrap())
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(values, [1, 2, 3]);
+    }
+}
@@ -0,0 +1,240 @@
+use crate::{
+    api_endpoint, authorize, response_error, send_with_retry, with_timeout, GenerateRequest,
+    GenerateResponse, OllamaError, RequestQueue, RetryPolicy, JSON_GRAMMAR,
+};
+use anyhow::{Context as _, Result};
+use futures::AsyncReadExt as _;
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// A request to an OpenAI-compatible `/v1/completions` endpoint.
+#[derive(Serialize, Debug, PartialEq)]
+struct CompletionRequest {
+    model: String,
+    prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suffix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<isize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f32>,
+    /// llama.cpp's server takes this as an extension, like `grammar`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    repeat_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop: Option<Vec<String>>,
+    /// A GBNF grammar, which llama.cpp's server accepts as an extension.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    grammar: Option<String>,
+    stream: bool,
+}
+
+impl From<GenerateRequest> for CompletionRequest {
+    /// Options without an equivalent in the OpenAI API, like the context
+    /// window and the system message, are left out. Structured output falls
+    /// back to the JSON grammar, which keeps the response valid JSON though
+    /// not necessarily in the requested shape.
+    fn from(request: GenerateRequest) -> Self {
+        let options = request.options.unwrap_or_default();
+        let grammar = options
+            .grammar
+            .or_else(|| request.format.is_some().then(|| JSON_GRAMMAR.to_string()));
+        Self {
+            model: request.model,
+            prompt: request.prompt,
+            suffix: request.suffix.filter(|suffix| !suffix.is_empty()),
+            max_tokens: options.num_predict,
+            temperature: options.temperature,
+            top_p: options.top_p,
+            presence_penalty: options.presence_penalty,
+            frequency_penalty: options.frequency_penalty,
+            repeat_penalty: options.repeat_penalty,
+            stop: options.stop,
+            grammar,
+            stream: false,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct CompletionResponse {
+    model: String,
+    choices: Vec<CompletionChoice>,
+    #[serde(default)]
+    usage: Option<CompletionUsage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CompletionChoice {
+    text: String,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CompletionUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+}
+
+impl CompletionResponse {
+    fn into_generate_response(self) -> Result<GenerateResponse> {
+        let choice = self
+            .choices
+            .into_iter()
+            .next()
+            .context("completion response has no choices")?;
+        Ok(GenerateResponse {
+            model: self.model,
+            created_at: String::new(),
+            response: choice.text,
+            // OpenAI's "stop" and "length" mean the same as Ollama's.
+            done_reason: choice.finish_reason,
+            done: true,
+            prompt_eval_count: self.usage.as_ref().map(|usage| usage.prompt_tokens),
+            eval_count: self.usage.as_ref().map(|usage| usage.completion_tokens),
+            eval_duration: None,
+            context: None,
+        })
+    }
+}
+
+/// Like [`crate::generate`], but for servers that only speak OpenAI's
+/// `/v1/completions` API, such as vLLM, llama.cpp's server or Ollama's
+/// compatibility layer. The request and response are translated to and from
+/// Ollama's format, so callers don't need to know the difference.
+pub async fn generate_openai(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: Option<&str>,
+    request: GenerateRequest,
+    retry: RetryPolicy,
+    timeout: Option<Duration>,
+) -> Result<GenerateResponse> {
+    let uri = api_endpoint(api_url, "v1/completions");
+    let model = request.model.clone();
+    let priority = request.priority;
+    let serialized_request = serde_json::to_string(&CompletionRequest::from(request))?;
+
+    with_timeout(timeout, async {
+        let _permit = RequestQueue::global().acquire(api_url, priority).await;
+        let mut response = send_with_retry(client, retry, || {
+            let request_builder = HttpRequest::builder()
+                .method(Method::POST)
+                .uri(&uri)
+                .header("Content-Type", "application/json");
+            Ok(authorize(request_builder, api_key)
+                .body(AsyncBody::from(serialized_request.clone()))?)
+        })
+        .await?;
+        let mut body = Vec::new();
+        response.body_mut().read_to_end(&mut body).await?;
+
+        if response.status().is_success() {
+            serde_json::from_slice::<CompletionResponse>(&body)
+                .map_err(|error| OllamaError::Decode(error.to_string()))?
+                .into_generate_response()
+        } else {
+            let body_str = std::str::from_utf8(&body)?;
+            Err(response_error(
+                response.status(),
+                response.headers(),
+                body_str,
+                &model,
+            ))
+        }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChatOptions, KeepAlive, RequestPriority};
+
+    #[test]
+    fn test_completion_request_from_generate_request() {
+        let request = GenerateRequest {
+            model: "qwen2.5-coder:1.5b".into(),
+            prompt: "fn main() {".into(),
+            suffix: Some("}".into()),
+            stream: false,
+            keep_alive: KeepAlive::default(),
+            options: Some(ChatOptions {
+                num_ctx: Some(4096),
+                num_predict: Some(128),
+                stop: Some(vec!["\n".into()]),
+                temperature: Some(0.2),
+                repeat_penalty: Some(1.1),
+                presence_penalty: Some(0.5),
+                ..Default::default()
+            }),
+            system: None,
+            raw: None,
+            format: None,
+            context: None,
+            priority: RequestPriority::Automatic,
+        };
+        assert_eq!(
+            serde_json::to_value(CompletionRequest::from(request)).unwrap(),
+            serde_json::json!({
+                "model": "qwen2.5-coder:1.5b",
+                "prompt": "fn main() {",
+                "suffix": "}",
+                "max_tokens": 128,
+                "temperature": 0.2f32,
+                "presence_penalty": 0.5f32,
+                "repeat_penalty": 1.1f32,
+                "stop": ["\n"],
+                "stream": false,
+            })
+        );
+
+        let request = GenerateRequest {
+            model: "qwen2.5-coder:1.5b".into(),
+            prompt: "Rate this".into(),
+            suffix: None,
+            stream: false,
+            keep_alive: KeepAlive::default(),
+            options: None,
+            system: None,
+            raw: None,
+            format: Some(serde_json::json!({ "type": "object" })),
+            context: None,
+            priority: RequestPriority::Automatic,
+        };
+        assert_eq!(
+            CompletionRequest::from(request).grammar.as_deref(),
+            Some(JSON_GRAMMAR)
+        );
+    }
+
+    #[test]
+    fn test_completion_response_into_generate_response() {
+        let response: CompletionResponse = serde_json::from_str(
+            r#"{
+                "id": "cmpl-1",
+                "object": "text_completion",
+                "model": "qwen2.5-coder:1.5b",
+                "choices": [{ "index": 0, "text": "println!()", "finish_reason": "length" }],
+                "usage": { "prompt_tokens": 12, "completion_tokens": 4, "total_tokens": 16 }
+            }"#,
+        )
+        .unwrap();
+        let response = response.into_generate_response().unwrap();
+        assert_eq!(response.response, "println!()");
+        assert_eq!(response.done_reason.as_deref(), Some("length"));
+        assert_eq!(response.prompt_eval_count, Some(12));
+        assert_eq!(response.eval_count, Some(4));
+
+        let response: CompletionResponse =
+            serde_json::from_str(r#"{ "model": "m", "choices": [] }"#).unwrap();
+        assert!(response.into_generate_response().is_err());
+    }
+}
@@ -0,0 +1,331 @@
+use crate::{api_endpoint, authorize, is_retryable, response_error, NdjsonDecoder, RetryPolicy};
+use anyhow::{anyhow, Result};
+use futures::AsyncReadExt as _;
+use http_client::{http, AsyncBody, HttpClient, Method};
+use serde::Deserialize;
+use std::time::Duration;
+
+/// How many bytes of the response are read at a time.
+const READ_CHUNK_SIZE: usize = 8192;
+
+/// How pulls are started again after the connection drops. Ollama keeps the
+/// parts of layers it has downloaded, so each attempt picks up where the
+/// last one stopped.
+pub const PULL_RETRY: RetryPolicy = RetryPolicy {
+    max_attempts: 5,
+    initial_backoff: Duration::from_secs(1),
+    max_backoff: Duration::from_secs(30),
+};
+
+/// A line of `/api/pull`'s streamed response.
+#[derive(Deserialize, Debug)]
+struct PullStatus {
+    status: String,
+    digest: Option<String>,
+    total: Option<u64>,
+    completed: Option<u64>,
+}
+
+/// How far along a pull is, layer by layer.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PullProgress {
+    /// What the server last said it's doing, like "pulling manifest".
+    pub status: String,
+    /// The model's layers, in the order the server started on them.
+    pub layers: Vec<LayerProgress>,
+    /// Whether the server checked the layers against their digests.
+    verified: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LayerProgress {
+    pub digest: String,
+    pub completed: u64,
+    pub total: u64,
+}
+
+impl PullProgress {
+    fn update(&mut self, status: PullStatus) -> Result<()> {
+        if let Some(digest) = status.digest {
+            if !is_sha256_digest(&digest) {
+                return Err(anyhow!(
+                    "the server reported a malformed layer digest: {digest}"
+                ));
+            }
+            let completed = status.completed.unwrap_or_default();
+            let total = status.total.unwrap_or_default();
+            match self.layers.iter_mut().find(|layer| layer.digest == digest) {
+                Some(layer) => {
+                    layer.completed = completed;
+                    layer.total = total.max(layer.total);
+                }
+                None => self.layers.push(LayerProgress {
+                    digest,
+                    completed,
+                    total,
+                }),
+            }
+        }
+        if status.status.starts_with("verifying") {
+            self.verified = true;
+        }
+        self.status = status.status;
+        Ok(())
+    }
+
+    pub fn completed_bytes(&self) -> u64 {
+        self.layers.iter().map(|layer| layer.completed).sum()
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.layers.iter().map(|layer| layer.total).sum()
+    }
+
+    /// Checks that a pull the server reported as successful downloaded every
+    /// layer in full, and verified their digests.
+    fn check_complete(&self) -> Result<()> {
+        if let Some(layer) = self
+            .layers
+            .iter()
+            .find(|layer| layer.completed < layer.total)
+        {
+            return Err(anyhow!(
+                "layer {} stopped at {} of {} bytes",
+                layer.digest,
+                layer.completed,
+                layer.total
+            ));
+        }
+        if !self.layers.is_empty() && !self.verified {
+            return Err(anyhow!("the server didn't verify the layers' digests"));
+        }
+        Ok(())
+    }
+}
+
+fn is_sha256_digest(digest: &str) -> bool {
+    digest.strip_prefix("sha256:").map_or(false, |hex| {
+        hex.len() == 64 && hex.bytes().all(|byte| byte.is_ascii_hexdigit())
+    })
+}
+
+enum PullError {
+    /// The pull stopped partway, and may be picked up again.
+    Interrupted(anyhow::Error),
+    Failed(anyhow::Error),
+}
+
+/// Downloads `model` to the server like [`crate::pull_model`], calling
+/// `on_progress` as the server reports it. When the connection drops, the
+/// pull is started again according to `retry`.
+pub async fn pull_model_with_progress(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: Option<&str>,
+    model: &str,
+    retry: RetryPolicy,
+    mut on_progress: impl FnMut(&PullProgress),
+) -> Result<()> {
+    let mut progress = PullProgress::default();
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let error = match pull_attempt(
+            client,
+            api_url,
+            api_key,
+            model,
+            &mut progress,
+            &mut on_progress,
+        )
+        .await
+        {
+            Ok(()) => return progress.check_complete(),
+            Err(PullError::Failed(error)) => return Err(error),
+            Err(PullError::Interrupted(error)) if attempt >= retry.max_attempts => {
+                return Err(error)
+            }
+            Err(PullError::Interrupted(error)) => error,
+        };
+        let backoff = retry.backoff(attempt - 1);
+        log::info!("resuming the pull of {model} in {backoff:?} after error: {error}");
+        progress.status = "resuming".into();
+        on_progress(&progress);
+        smol::Timer::after(backoff).await;
+    }
+}
+
+async fn pull_attempt(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: Option<&str>,
+    model: &str,
+    progress: &mut PullProgress,
+    on_progress: &mut impl FnMut(&PullProgress),
+) -> Result<(), PullError> {
+    let request_builder = http::Request::builder()
+        .method(Method::POST)
+        .uri(api_endpoint(api_url, "api/pull"))
+        .header("Content-Type", "application/json");
+    let request = authorize(request_builder, api_key)
+        .body(AsyncBody::from(
+            serde_json::json!({ "model": model, "stream": true }).to_string(),
+        ))
+        .map_err(|error| PullError::Failed(error.into()))?;
+    let mut response = client.send(request).await.map_err(PullError::Interrupted)?;
+    if !response.status().is_success() {
+        let mut body = String::new();
+        response.body_mut().read_to_string(&mut body).await.ok();
+        let error = response_error(response.status(), response.headers(), &body, model);
+        return Err(if is_retryable(response.status()) {
+            PullError::Interrupted(error)
+        } else {
+            PullError::Failed(error)
+        });
+    }
+
+    let mut body = response.into_body();
+    let mut decoder = NdjsonDecoder::default();
+    let mut chunk = vec![0; READ_CHUNK_SIZE];
+    let mut downloading = false;
+    loop {
+        let len = body
+            .read(&mut chunk)
+            .await
+            .map_err(|error| PullError::Interrupted(error.into()))?;
+        let statuses = if len == 0 {
+            decoder.finish::<PullStatus>().into_iter().collect()
+        } else {
+            decoder.push::<PullStatus>(&chunk[..len])
+        };
+        for status in statuses {
+            // Errors before any layer is downloaded, like an unknown model,
+            // won't go away. Later ones are the server's own download
+            // failing, which another attempt picks up from.
+            let status = status.map_err(|error| {
+                if downloading {
+                    PullError::Interrupted(error)
+                } else {
+                    PullError::Failed(error)
+                }
+            })?;
+            downloading |= status.digest.is_some();
+            let success = status.status == "success";
+            progress.update(status).map_err(PullError::Failed)?;
+            on_progress(progress);
+            if success {
+                return Ok(());
+            }
+        }
+        if len == 0 {
+            return Err(PullError::Interrupted(anyhow!(
+                "the connection closed before the pull finished"
+            )));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_models, ollama_fake_server::PULLED_LAYERS, FakeOllamaServer};
+
+    const API_URL: &str = "http://localhost:11434";
+    const DIGEST: &str = "sha256:60e05f2100071479f596b964f89f510f057ce397ea22f2833a0cfe029bfc2463";
+
+    fn status(status: &str, digest: Option<&str>, completed: u64, total: u64) -> PullStatus {
+        PullStatus {
+            status: status.into(),
+            digest: digest.map(Into::into),
+            total: Some(total),
+            completed: Some(completed),
+        }
+    }
+
+    #[test]
+    fn test_pull_progress() {
+        let mut progress = PullProgress::default();
+        progress
+            .update(status("pulling 60e05f210007", Some(DIGEST), 0, 100))
+            .unwrap();
+        progress
+            .update(status("pulling 60e05f210007", Some(DIGEST), 40, 100))
+            .unwrap();
+        assert_eq!(progress.layers.len(), 1);
+        assert_eq!(progress.completed_bytes(), 40);
+        assert_eq!(progress.total_bytes(), 100);
+        assert!(progress.check_complete().is_err());
+
+        progress
+            .update(status("pulling 60e05f210007", Some(DIGEST), 100, 100))
+            .unwrap();
+        // Finished layers still need the server to check their digests.
+        assert!(progress.check_complete().is_err());
+        progress
+            .update(status("verifying sha256 digest", None, 0, 0))
+            .unwrap();
+        progress.check_complete().unwrap();
+
+        assert!(progress
+            .update(status("pulling", Some("sha256:60e05f"), 0, 100))
+            .is_err());
+        assert!(progress
+            .update(status(
+                "pulling",
+                Some(&DIGEST.replace("sha256", "md5")),
+                0,
+                100
+            ))
+            .is_err());
+    }
+
+    #[test]
+    fn test_resumed_pull() {
+        let server = FakeOllamaServer::new();
+        server.interrupt_pulls(2);
+        let client = server.http_client();
+        let retry = RetryPolicy {
+            initial_backoff: Duration::from_millis(1),
+            ..PULL_RETRY
+        };
+
+        let mut updates = Vec::new();
+        smol::block_on(pull_model_with_progress(
+            client.as_ref(),
+            API_URL,
+            None,
+            "codellama:7b",
+            retry,
+            |progress| updates.push(progress.clone()),
+        ))
+        .unwrap();
+        assert_eq!(server.requests("/api/pull").len(), 3);
+        assert_eq!(updates.iter().filter(|u| u.status == "resuming").count(), 2);
+        // The second attempt started from what the first one downloaded.
+        let (_, first_layer_size) = PULLED_LAYERS[0];
+        let resumed = updates.iter().position(|u| u.status == "resuming").unwrap();
+        assert_eq!(
+            updates[resumed + 2].layers[0].completed,
+            first_layer_size / 2
+        );
+        let last = updates.last().unwrap();
+        assert_eq!(last.status, "success");
+        assert_eq!(last.layers.len(), PULLED_LAYERS.len());
+        assert_eq!(last.completed_bytes(), last.total_bytes());
+
+        let models = smol::block_on(get_models(client.as_ref(), API_URL, None, None)).unwrap();
+        assert!(models.iter().any(|model| model.name == "codellama:7b"));
+
+        // Once every attempt is used up, the pull fails.
+        server.interrupt_pulls(PULL_RETRY.max_attempts as usize);
+        let result = smol::block_on(pull_model_with_progress(
+            client.as_ref(),
+            API_URL,
+            None,
+            "llama3:8b",
+            retry,
+            |_| {},
+        ));
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,220 @@
+use futures::channel::oneshot;
+use parking_lot::Mutex;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, OnceLock},
+};
+
+/// How many requests each server is sent at once, unless configured otherwise.
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 2;
+
+static QUEUE: OnceLock<Arc<RequestQueue>> = OnceLock::new();
+
+/// Which requests go first when a server is busy. Requests of the same
+/// priority are sent in the order they were made.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RequestPriority {
+    /// Something the user explicitly asked for, like a chat message, an
+    /// inline assist or a completion requested with a keystroke.
+    UserInitiated,
+    /// Something requested on the user's behalf as they type, like an inline
+    /// completion.
+    #[default]
+    Automatic,
+    /// Something nobody is waiting for, like a prefetched completion or
+    /// loading a model ahead of time.
+    Background,
+}
+
+/// Limits how many requests each server handles at once, so a burst of inline
+/// completions alongside the assistant doesn't make Ollama thrash between
+/// loading models. Requests beyond the limit wait their turn, with
+/// user-initiated ones ahead of automatic ones, and those ahead of
+/// background ones.
+pub struct RequestQueue {
+    state: Mutex<QueueState>,
+}
+
+struct QueueState {
+    max_concurrent: usize,
+    servers: HashMap<String, ServerQueue>,
+}
+
+#[derive(Default)]
+struct ServerQueue {
+    running: usize,
+    waiting: VecDeque<(RequestPriority, oneshot::Sender<RequestPermit>)>,
+}
+
+/// Allows a request to be sent to a server. The next waiting request is let
+/// through when it's dropped.
+pub struct RequestPermit {
+    queue: Arc<RequestQueue>,
+    api_url: String,
+}
+
+impl RequestQueue {
+    pub fn new(max_concurrent: usize) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(QueueState {
+                max_concurrent: max_concurrent.max(1),
+                servers: HashMap::default(),
+            }),
+        })
+    }
+
+    /// The queue shared by every request to Ollama.
+    pub fn global() -> Arc<Self> {
+        QUEUE
+            .get_or_init(|| Self::new(DEFAULT_MAX_CONCURRENT_REQUESTS))
+            .clone()
+    }
+
+    pub fn set_max_concurrent(self: &Arc<Self>, max_concurrent: usize) {
+        let urls = {
+            let mut state = self.state.lock();
+            state.max_concurrent = max_concurrent.max(1);
+            state.servers.keys().cloned().collect::<Vec<_>>()
+        };
+        // A higher limit lets waiting requests through right away.
+        for url in urls {
+            self.start_waiting(&url);
+        }
+    }
+
+    /// Waits until a request may be sent to `api_url`. Dropping the returned
+    /// future gives up the place in the queue.
+    pub async fn acquire(
+        self: &Arc<Self>,
+        api_url: &str,
+        priority: RequestPriority,
+    ) -> RequestPermit {
+        let rx = {
+            let mut state = self.state.lock();
+            let max_concurrent = state.max_concurrent;
+            let server = state.servers.entry(api_url.to_string()).or_default();
+            if server.running < max_concurrent && server.waiting.is_empty() {
+                server.running += 1;
+                return RequestPermit {
+                    queue: self.clone(),
+                    api_url: api_url.to_string(),
+                };
+            }
+            let (tx, rx) = oneshot::channel();
+            let ix = server
+                .waiting
+                .iter()
+                .position(|(waiting, _)| *waiting > priority)
+                .unwrap_or(server.waiting.len());
+            server.waiting.insert(ix, (priority, tx));
+            rx
+        };
+        // The sender is only dropped along with the queue, which is never.
+        rx.await.expect("request queue dropped")
+    }
+
+    /// Hands free slots to the requests waiting the longest, highest priority
+    /// first. Requests that gave up waiting are skipped; a permit sent to one
+    /// that gives up later is dropped with its receiver, freeing the slot again.
+    fn start_waiting(self: &Arc<Self>, api_url: &str) {
+        let mut state = self.state.lock();
+        let max_concurrent = state.max_concurrent;
+        let Some(server) = state.servers.get_mut(api_url) else {
+            return;
+        };
+        let mut permits = Vec::new();
+        while server.running < max_concurrent {
+            let Some((_, tx)) = server.waiting.pop_front() else {
+                break;
+            };
+            if tx.is_canceled() {
+                continue;
+            }
+            server.running += 1;
+            permits.push((
+                tx,
+                RequestPermit {
+                    queue: self.clone(),
+                    api_url: api_url.to_string(),
+                },
+            ));
+        }
+        if server.running == 0 && server.waiting.is_empty() {
+            state.servers.remove(api_url);
+        }
+        drop(state);
+        // Sending outside the lock, since a permit that can't be delivered
+        // is dropped, which locks the queue to release its slot.
+        for (tx, permit) in permits {
+            tx.send(permit).ok();
+        }
+    }
+
+    #[cfg(test)]
+    fn running(&self, api_url: &str) -> usize {
+        self.state
+            .lock()
+            .servers
+            .get(api_url)
+            .map_or(0, |server| server.running)
+    }
+}
+
+impl Drop for RequestPermit {
+    fn drop(&mut self) {
+        if let Some(server) = self.queue.state.lock().servers.get_mut(&self.api_url) {
+            server.running -= 1;
+        }
+        self.queue.start_waiting(&self.api_url);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{future::FutureExt as _, pin_mut};
+
+    #[test]
+    fn test_request_queue() {
+        let queue = RequestQueue::new(1);
+        let url = "http://localhost:11434";
+
+        let first = queue
+            .acquire(url, RequestPriority::Background)
+            .now_or_never();
+        assert!(first.is_some());
+        assert_eq!(queue.running(url), 1);
+
+        let background = queue.acquire(url, RequestPriority::Background);
+        let automatic = queue.acquire(url, RequestPriority::Automatic);
+        let user_initiated = queue.acquire(url, RequestPriority::UserInitiated);
+        pin_mut!(background, automatic, user_initiated);
+        assert!((&mut background).now_or_never().is_none());
+        assert!((&mut automatic).now_or_never().is_none());
+        assert!((&mut user_initiated).now_or_never().is_none());
+
+        // The user-initiated request goes first even though it came last,
+        // then the automatic one.
+        drop(first);
+        let second = (&mut user_initiated).now_or_never();
+        assert!(second.is_some());
+        assert!((&mut automatic).now_or_never().is_none());
+        assert!((&mut background).now_or_never().is_none());
+
+        drop(second);
+        let third = (&mut automatic).now_or_never();
+        assert!(third.is_some());
+        assert!((&mut background).now_or_never().is_none());
+
+        drop(third);
+        let fourth = (&mut background).now_or_never();
+        assert!(fourth.is_some());
+        assert_eq!(queue.running(url), 1);
+        drop(fourth);
+        assert_eq!(queue.running(url), 0);
+
+        // Other servers aren't held up.
+        let other = queue.acquire("http://gpu-box:11434", RequestPriority::Background);
+        assert!(other.now_or_never().is_some());
+    }
+}
@@ -0,0 +1,226 @@
+use crate::{
+    api_endpoint, authorize, generate, send_with_retry, ChatOptions, GenerateRequest, KeepAlive,
+    RequestPriority, RequestQueue, RetryPolicy,
+};
+use anyhow::{anyhow, Context as _, Result};
+use futures::{future, AsyncReadExt as _};
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest, StatusCode};
+use serde::{Deserialize, Serialize};
+
+/// Asks the model how relevant a candidate is when the server has no
+/// `/api/rerank` endpoint.
+const RERANK_PROMPT: &str = "Rate how relevant the document is to the query, \
+    from 0 (unrelated) to 10 (exactly what the query is looking for). \
+    Respond with only a JSON object like {\"score\": 7}.";
+
+/// A candidate's position in the list passed to [`rerank`], and how relevant
+/// the model found it, from 0 to 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RankedCandidate {
+    pub index: usize,
+    pub score: f32,
+}
+
+#[derive(Serialize, Debug)]
+struct RerankRequest<'a> {
+    model: &'a str,
+    query: &'a str,
+    documents: &'a [&'a str],
+}
+
+#[derive(Deserialize, Debug)]
+struct RerankResponse {
+    results: Vec<RerankResult>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RerankResult {
+    index: usize,
+    relevance_score: f32,
+}
+
+/// Orders `candidates` by their relevance to `query`, most relevant first,
+/// according to `model`.
+///
+/// Servers with an `/api/rerank` endpoint score every candidate in one
+/// request. Otherwise each candidate is scored by asking `model` to rate it,
+/// which works with any model but costs a request per candidate.
+pub async fn rerank(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: Option<&str>,
+    model: &str,
+    query: &str,
+    candidates: &[&str],
+) -> Result<Vec<RankedCandidate>> {
+    if candidates.is_empty() {
+        return Ok(Vec::new());
+    }
+    let scores = match rerank_endpoint(client, api_url, api_key, model, query, candidates).await? {
+        Some(scores) => scores,
+        None => {
+            log::debug!("Ollama has no rerank endpoint, scoring candidates with {model}");
+            let scores = candidates
+                .iter()
+                .map(|candidate| prompt_score(client, api_url, api_key, model, query, candidate));
+            future::try_join_all(scores).await?
+        }
+    };
+    Ok(ranked(scores))
+}
+
+/// Scores the candidates with `/api/rerank`, or returns `None` when the
+/// server doesn't have it.
+async fn rerank_endpoint(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: Option<&str>,
+    model: &str,
+    query: &str,
+    candidates: &[&str],
+) -> Result<Option<Vec<f32>>> {
+    let uri = api_endpoint(api_url, "api/rerank");
+    let serialized_request = serde_json::to_string(&RerankRequest {
+        model,
+        query,
+        documents: candidates,
+    })?;
+    let _permit = RequestQueue::global()
+        .acquire(api_url, RequestPriority::Background)
+        .await;
+    let mut response =
+        send_with_retry(client, RetryPolicy::default(), || {
+            let request_builder = HttpRequest::builder()
+                .method(Method::POST)
+                .uri(&uri)
+                .header("Content-Type", "application/json");
+            Ok(authorize(request_builder, api_key)
+                .body(AsyncBody::from(serialized_request.clone()))?)
+        })
+        .await?;
+    if matches!(
+        response.status(),
+        StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+    ) {
+        return Ok(None);
+    }
+
+    let mut body = String::new();
+    response.body_mut().read_to_string(&mut body).await?;
+
+    if response.status().is_success() {
+        let response: RerankResponse =
+            serde_json::from_str(&body).context("Unable to parse Ollama rerank response")?;
+        let mut scores = vec![0.; candidates.len()];
+        for result in response.results {
+            if let Some(score) = scores.get_mut(result.index) {
+                *score = result.relevance_score;
+            }
+        }
+        Ok(Some(scores))
+    } else {
+        Err(anyhow!(
+            "Failed to connect to Ollama API: {} {}",
+            response.status(),
+            body,
+        ))
+    }
+}
+
+async fn prompt_score(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: Option<&str>,
+    model: &str,
+    query: &str,
+    candidate: &str,
+) -> Result<f32> {
+    let request = GenerateRequest {
+        model: model.to_string(),
+        prompt: format!("Query: {query}\n\nDocument:\n{candidate}"),
+        suffix: None,
+        stream: false,
+        // Ollama's own default, since reranking happens now and then.
+        keep_alive: KeepAlive::Duration("5m".into()),
+        options: Some(ChatOptions {
+            temperature: Some(0.),
+            num_predict: Some(16),
+            ..Default::default()
+        }),
+        system: Some(RERANK_PROMPT.to_string()),
+        raw: None,
+        format: Some(serde_json::json!({
+            "type": "object",
+            "properties": { "score": { "type": "integer", "minimum": 0, "maximum": 10 } },
+            "required": ["score"],
+        })),
+        context: None,
+        priority: RequestPriority::Automatic,
+    };
+    let response = generate(
+        client,
+        api_url,
+        api_key,
+        request,
+        RetryPolicy::default(),
+        None,
+    )
+    .await?;
+    Ok(parse_score(&response.response).unwrap_or_else(|| {
+        log::debug!(
+            "unexpected relevance score from {model}: {}",
+            response.response
+        );
+        0.
+    }))
+}
+
+/// Reads a 0-10 rating from the model's reply, as a score from 0 to 1.
+fn parse_score(response: &str) -> Option<f32> {
+    #[derive(Deserialize)]
+    struct Score {
+        score: f32,
+    }
+
+    let score = match serde_json::from_str::<Score>(response.trim()) {
+        Ok(score) => score.score,
+        // Models without structured output may answer with just the number.
+        Err(_) => response.trim().parse().ok()?,
+    };
+    Some((score / 10.).clamp(0., 1.))
+}
+
+/// Orders candidates by score, keeping the original order between ties.
+fn ranked(scores: Vec<f32>) -> Vec<RankedCandidate> {
+    let mut ranked = scores
+        .into_iter()
+        .enumerate()
+        .map(|(index, score)| RankedCandidate { index, score })
+        .collect::<Vec<_>>();
+    ranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_score() {
+        assert_eq!(parse_score("{\"score\": 7}"), Some(0.7));
+        assert_eq!(parse_score(" {\"score\": 10.0}\n"), Some(1.));
+        assert_eq!(parse_score("{\"score\": 42}"), Some(1.));
+        assert_eq!(parse_score("3"), Some(0.3));
+        assert_eq!(parse_score("quite relevant"), None);
+    }
+
+    #[test]
+    fn test_ranked() {
+        let ranked = ranked(vec![0.2, 0.9, 0.2, 0.5]);
+        assert_eq!(
+            ranked.iter().map(|c| c.index).collect::<Vec<_>>(),
+            [1, 3, 0, 2]
+        );
+        assert_eq!(ranked[0].score, 0.9);
+    }
+}
@@ -0,0 +1,188 @@
+use parking_lot::Mutex;
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    sync::{Arc, OnceLock},
+};
+
+static USAGE: OnceLock<Arc<TokenUsage>> = OnceLock::new();
+
+/// What a model's tokens were spent on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenUsageKind {
+    /// Inline completions, including prefetched and cycled suggestions.
+    Completion,
+    /// Chat replies, from the assistant or any other chat request.
+    Chat,
+}
+
+/// The tokens each model read and generated since Zed started, as reported
+/// by Ollama at the end of each response. Kept apart from the performance
+/// report so requests made without an app context can be counted too. Only
+/// kept in memory, and never sent anywhere.
+#[derive(Default)]
+pub struct TokenUsage {
+    models: Mutex<BTreeMap<String, ModelTokenUsage>>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct ModelTokenUsage {
+    completion: Tokens,
+    chat: Tokens,
+}
+
+/// Token counts for one kind of request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Tokens {
+    pub prompt: u64,
+    pub generated: u64,
+}
+
+impl Tokens {
+    pub fn total(&self) -> u64 {
+        self.prompt + self.generated
+    }
+
+    fn add(&mut self, other: Tokens) {
+        self.prompt += other.prompt;
+        self.generated += other.generated;
+    }
+}
+
+impl TokenUsage {
+    /// The usage shared by every request to Ollama.
+    pub fn global() -> Arc<Self> {
+        USAGE.get_or_init(Arc::default).clone()
+    }
+
+    /// Adds the tokens `model` read and generated for a response. Servers
+    /// that don't report a count are taken to have used none.
+    pub fn record(
+        &self,
+        model: &str,
+        kind: TokenUsageKind,
+        prompt: Option<u64>,
+        generated: Option<u64>,
+    ) {
+        if prompt.is_none() && generated.is_none() {
+            return;
+        }
+        let tokens = Tokens {
+            prompt: prompt.unwrap_or(0),
+            generated: generated.unwrap_or(0),
+        };
+        let mut models = self.models.lock();
+        let usage = models.entry(model.to_string()).or_default();
+        match kind {
+            TokenUsageKind::Completion => usage.completion.add(tokens),
+            TokenUsageKind::Chat => usage.chat.add(tokens),
+        }
+    }
+
+    /// The tokens spent on `kind` across every model.
+    pub fn total(&self, kind: TokenUsageKind) -> Tokens {
+        let mut total = Tokens::default();
+        for usage in self.models.lock().values() {
+            total.add(match kind {
+                TokenUsageKind::Completion => usage.completion,
+                TokenUsageKind::Chat => usage.chat,
+            });
+        }
+        total
+    }
+
+    /// The usage as Markdown, with a row per model.
+    pub fn markdown(&self) -> String {
+        usage_markdown(&self.models.lock())
+    }
+}
+
+fn usage_markdown(models: &BTreeMap<String, ModelTokenUsage>) -> String {
+    let mut markdown = String::from("## Token Usage\n\n");
+    if models.is_empty() {
+        markdown.push_str("No tokens have been reported by Ollama yet.\n");
+        return markdown;
+    }
+
+    markdown.push_str(
+        "| Model | Completion prompt | Completion generated | Chat prompt | Chat generated |\n\
+         | --- | ---: | ---: | ---: | ---: |\n",
+    );
+    let mut total = ModelTokenUsage::default();
+    for (model, usage) in models {
+        writeln!(
+            markdown,
+            "| {model} | {} | {} | {} | {} |",
+            usage.completion.prompt,
+            usage.completion.generated,
+            usage.chat.prompt,
+            usage.chat.generated,
+        )
+        .ok();
+        total.completion.add(usage.completion);
+        total.chat.add(usage.chat);
+    }
+    writeln!(
+        markdown,
+        "| **Total** | {} | {} | {} | {} |",
+        total.completion.prompt,
+        total.completion.generated,
+        total.chat.prompt,
+        total.chat.generated,
+    )
+    .ok();
+    markdown
+}
+
+/// A token count for showing in menus, like `950` or `12.3k`.
+pub fn format_token_count(count: u64) -> String {
+    match count {
+        0..=999 => count.to_string(),
+        1_000..=999_999 => format!("{:.1}k", count as f64 / 1_000.0),
+        _ => format!("{:.1}M", count as f64 / 1_000_000.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_usage() {
+        let usage = TokenUsage::default();
+        assert!(usage
+            .markdown()
+            .ends_with("No tokens have been reported by Ollama yet.\n"));
+
+        let model = "qwen2.5-coder:1.5b";
+        usage.record(model, TokenUsageKind::Completion, Some(500), Some(20));
+        usage.record(model, TokenUsageKind::Completion, Some(480), None);
+        usage.record(model, TokenUsageKind::Chat, Some(1200), Some(300));
+        usage.record("llama3.2", TokenUsageKind::Chat, Some(40), Some(60));
+        // Responses without counts, like errors, aren't recorded at all.
+        usage.record("codellama:7b", TokenUsageKind::Completion, None, None);
+
+        assert_eq!(
+            usage.total(TokenUsageKind::Completion),
+            Tokens {
+                prompt: 980,
+                generated: 20
+            }
+        );
+        assert_eq!(usage.total(TokenUsageKind::Chat).total(), 1600);
+        assert!(usage.markdown().ends_with(
+            "| Model | Completion prompt | Completion generated | Chat prompt | Chat generated |\n\
+             | --- | ---: | ---: | ---: | ---: |\n\
+             | llama3.2 | 0 | 0 | 40 | 60 |\n\
+             | qwen2.5-coder:1.5b | 980 | 20 | 1200 | 300 |\n\
+             | **Total** | 980 | 20 | 1240 | 360 |\n"
+        ));
+    }
+
+    #[test]
+    fn test_format_token_count() {
+        assert_eq!(format_token_count(950), "950");
+        assert_eq!(format_token_count(12_345), "12.3k");
+        assert_eq!(format_token_count(2_500_000), "2.5M");
+    }
+}
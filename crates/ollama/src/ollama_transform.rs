@@ -0,0 +1,204 @@
+use crate::{
+    stream_chat_completion, ChatMessage, ChatOptions, ChatRequest, KeepAlive, RequestPriority,
+};
+use anyhow::Result;
+use futures::{future, stream, stream::BoxStream, StreamExt as _};
+use http_client::HttpClient;
+
+/// Asks for `code` to be rewritten following `instruction`.
+pub fn transformation_request(
+    model: String,
+    instruction: &str,
+    code: &str,
+    language_name: &str,
+    options: ChatOptions,
+    keep_alive: KeepAlive,
+) -> ChatRequest {
+    ChatRequest {
+        model,
+        messages: vec![
+            ChatMessage::System {
+                content: format!(
+                    "{instruction}\nRespond with only the rewritten code, without explanations."
+                ),
+            },
+            ChatMessage::User {
+                content: format!("```{language_name}\n{code}\n```"),
+            },
+        ],
+        stream: true,
+        keep_alive,
+        options: Some(options),
+        tools: Vec::new(),
+        priority: RequestPriority::UserInitiated,
+    }
+}
+
+/// Streams the replacement text for a [`transformation_request`], with the
+/// Markdown code fence models tend to add removed as it arrives.
+pub async fn stream_transformation(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: Option<&str>,
+    request: ChatRequest,
+) -> Result<BoxStream<'static, Result<String>>> {
+    let chunks = stream_chat_completion(client, api_url, api_key, request, None).await?;
+    let mut filter = CodeFenceFilter::default();
+    Ok(chunks
+        .map(Some)
+        .chain(stream::once(future::ready(None)))
+        .filter_map(move |chunk| {
+            let text = match chunk {
+                Some(Ok(chunk)) => {
+                    let (ChatMessage::User { content }
+                    | ChatMessage::Assistant { content, .. }
+                    | ChatMessage::System { content }) = chunk.message;
+                    Ok(filter.push(&content))
+                }
+                Some(Err(error)) => Err(error),
+                None => Ok(filter.finish()),
+            };
+            future::ready(match text {
+                Ok(text) if text.is_empty() => None,
+                text => Some(text),
+            })
+        })
+        .boxed())
+}
+
+/// Removes a Markdown code fence around text that arrives in pieces. Text is
+/// passed on as soon as it can't be part of the fence, except for a line break
+/// at the end, which is held back until more text follows it.
+#[derive(Default)]
+struct CodeFenceFilter {
+    pending: String,
+    /// Whether enough of the text was seen to tell if it opens with a fence.
+    started: bool,
+    fenced: bool,
+    /// Set once the closing fence was seen; anything after it is dropped.
+    done: bool,
+    /// Whether part of the current line was already passed on.
+    mid_line: bool,
+    line_break: bool,
+}
+
+impl CodeFenceFilter {
+    fn push(&mut self, chunk: &str) -> String {
+        let mut output = String::new();
+        if self.done {
+            return output;
+        }
+        self.pending.push_str(chunk);
+        if !self.started {
+            let start = self.pending.trim_start();
+            if start.starts_with("```") {
+                let Some(newline) = self.pending.find('\n') else {
+                    return output;
+                };
+                self.pending.drain(..=newline);
+                self.fenced = true;
+            } else if "```".starts_with(start) {
+                return output;
+            } else {
+                self.pending = self.pending.trim_start_matches('\n').to_string();
+            }
+            self.started = true;
+        }
+
+        while let Some(newline) = self.pending.find('\n') {
+            let line = &self.pending[..newline];
+            if self.is_closing_fence(line) {
+                self.done = true;
+                self.pending.clear();
+                return output;
+            }
+            if self.line_break {
+                output.push('\n');
+            }
+            output.push_str(line);
+            self.pending.drain(..=newline);
+            self.line_break = true;
+            self.mid_line = false;
+        }
+        let could_be_fence =
+            self.fenced && !self.mid_line && "```".starts_with(self.pending.trim_start());
+        if !self.pending.is_empty() && !could_be_fence {
+            if self.line_break {
+                output.push('\n');
+                self.line_break = false;
+            }
+            output.push_str(&self.pending);
+            self.pending.clear();
+            self.mid_line = true;
+        }
+        output
+    }
+
+    /// The text held back at the end of the response.
+    fn finish(&mut self) -> String {
+        let pending = std::mem::take(&mut self.pending);
+        if self.done || self.is_closing_fence(&pending) {
+            return String::new();
+        }
+        if !self.started {
+            return if pending.trim_start().starts_with("```") {
+                String::new()
+            } else {
+                pending
+            };
+        }
+        if pending.is_empty() {
+            return pending;
+        }
+        if self.line_break {
+            format!("\n{pending}")
+        } else {
+            pending
+        }
+    }
+
+    fn is_closing_fence(&self, line: &str) -> bool {
+        self.fenced && !self.mid_line && line.trim() == "```"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(chunks: &[&str]) -> String {
+        let mut filter = CodeFenceFilter::default();
+        let mut text = chunks
+            .iter()
+            .map(|chunk| filter.push(chunk))
+            .collect::<String>();
+        text.push_str(&filter.finish());
+        text
+    }
+
+    #[test]
+    fn test_code_fence_filter() {
+        assert_eq!(
+            filter(&["```rust\nfn a() {\n", "    1\n}\n``", "`\n"]),
+            "fn a() {\n    1\n}"
+        );
+        assert_eq!(
+            filter(&["`", "``py", "thon\nprint(1)", "\n```"]),
+            "print(1)"
+        );
+        assert_eq!(
+            filter(&["let x", " = 1;\nlet y = 2;\n"]),
+            "let x = 1;\nlet y = 2;"
+        );
+        assert_eq!(filter(&["\n", "    x += 1", ";"]), "    x += 1;");
+        assert_eq!(filter(&["``"]), "``");
+
+        // Text is passed on as soon as it can't be part of the fence.
+        let mut filter = CodeFenceFilter::default();
+        assert_eq!(filter.push("```\nlet"), "let");
+        assert_eq!(filter.push(" x;\n  "), " x;");
+        assert_eq!(filter.push("y;\n"), "\n  y;");
+        assert_eq!(filter.push("```\nExplanation"), "");
+        assert_eq!(filter.finish(), "");
+    }
+}
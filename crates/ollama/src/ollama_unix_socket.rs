@@ -0,0 +1,299 @@
+use anyhow::{anyhow, Context as _, Result};
+use futures::{future::BoxFuture, ready, AsyncBufRead, AsyncRead};
+use http_client::{AsyncBody, HttpClient, Request, Response, Uri};
+use std::{
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+const UNIX_SCHEME: &str = "unix://";
+
+/// Whether `api_url` names a unix domain socket, like `unix:///run/ollama.sock`.
+pub fn is_unix_socket_url(api_url: &str) -> bool {
+    api_url.starts_with(UNIX_SCHEME)
+}
+
+/// The URL of `path` on the API at `api_url`. URLs can't name a socket as
+/// their host, so for a `unix://` server the socket's path is hex-encoded
+/// into the host, where [`UnixSocketClient`] decodes it from.
+pub fn api_endpoint(api_url: &str, path: &str) -> String {
+    match api_url.strip_prefix(UNIX_SCHEME) {
+        Some(socket_path) => {
+            let host = socket_path
+                .bytes()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<String>();
+            format!("{UNIX_SCHEME}{host}/{path}")
+        }
+        None => format!("{api_url}/{path}"),
+    }
+}
+
+/// Sends requests for `unix://` URLs over the socket they name, speaking
+/// HTTP/1.1 with a new connection per request, and everything else with
+/// `fallback`.
+pub struct UnixSocketClient {
+    fallback: Arc<dyn HttpClient>,
+}
+
+impl UnixSocketClient {
+    pub fn new(fallback: Arc<dyn HttpClient>) -> Self {
+        Self { fallback }
+    }
+}
+
+impl HttpClient for UnixSocketClient {
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    fn send(&self, request: Request<AsyncBody>) -> BoxFuture<'static, Result<Response<AsyncBody>>> {
+        if request.uri().scheme_str() == Some("unix") {
+            Box::pin(send_over_socket(request))
+        } else {
+            self.fallback.send(request)
+        }
+    }
+
+    fn proxy(&self) -> Option<&Uri> {
+        self.fallback.proxy()
+    }
+}
+
+#[cfg(not(unix))]
+async fn send_over_socket(_: Request<AsyncBody>) -> Result<Response<AsyncBody>> {
+    Err(anyhow!("unix sockets aren't supported on this platform"))
+}
+
+#[cfg(unix)]
+async fn send_over_socket(request: Request<AsyncBody>) -> Result<Response<AsyncBody>> {
+    use futures::{io::BufReader, AsyncBufReadExt as _, AsyncReadExt as _, AsyncWriteExt as _};
+
+    let socket_path = socket_path(request.uri())?;
+    let (parts, mut body) = request.into_parts();
+    let mut body_bytes = Vec::new();
+    body.read_to_end(&mut body_bytes).await?;
+
+    let path = parts.uri.path_and_query().map_or("/", |path| path.as_str());
+    let mut head = format!(
+        "{} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n",
+        parts.method,
+        body_bytes.len()
+    );
+    for (name, value) in &parts.headers {
+        head.push_str(&format!("{name}: {}\r\n", value.to_str()?));
+    }
+    head.push_str("\r\n");
+
+    let mut stream = smol::net::unix::UnixStream::connect(&socket_path)
+        .await
+        .with_context(|| format!("connecting to {socket_path}"))?;
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&body_bytes).await?;
+    stream.flush().await?;
+
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).await?;
+    let status = line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok())
+        .with_context(|| format!("invalid status line from {socket_path}: {line:?}"))?;
+
+    let mut response = Response::builder().status(status);
+    let mut content_length = None;
+    let mut chunked = false;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return Err(anyhow!("{socket_path} closed the connection mid-response"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = header
+            .split_once(':')
+            .with_context(|| format!("invalid header from {socket_path}: {header:?}"))?;
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value.parse::<u64>().ok();
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            chunked = value.to_ascii_lowercase().contains("chunked");
+        }
+        response = response.header(name, value);
+    }
+
+    let body = if chunked {
+        AsyncBody::from_reader(ChunkedReader::new(reader))
+    } else if let Some(content_length) = content_length {
+        AsyncBody::from_reader(reader.take(content_length))
+    } else {
+        AsyncBody::from_reader(reader)
+    };
+    Ok(response.body(body)?)
+}
+
+/// The socket path hex-encoded into the host by [`api_endpoint`].
+#[cfg_attr(not(unix), allow(dead_code))]
+fn socket_path(uri: &Uri) -> Result<String> {
+    let host = uri.host().context("missing socket path")?;
+    let bytes = (0..host.len())
+        .step_by(2)
+        .map(|i| {
+            host.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>()
+        .with_context(|| format!("invalid socket path in {uri}"))?;
+    Ok(String::from_utf8(bytes)?)
+}
+
+/// Decodes a body sent with `Transfer-Encoding: chunked`, which is how Ollama
+/// streams its responses.
+#[cfg_attr(not(unix), allow(dead_code))]
+struct ChunkedReader<R> {
+    reader: R,
+    state: ChunkState,
+    line: Vec<u8>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ChunkState {
+    Size,
+    Data(u64),
+    DataEnd,
+    Trailers,
+    Done,
+}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+impl<R> ChunkedReader<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            state: ChunkState::Size,
+            line: Vec::new(),
+        }
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncRead for ChunkedReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        loop {
+            match this.state {
+                ChunkState::Done => return Poll::Ready(Ok(0)),
+                ChunkState::Data(remaining) => {
+                    let available = ready!(Pin::new(&mut this.reader).poll_fill_buf(cx))?;
+                    if available.is_empty() {
+                        return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                    }
+                    let len = available
+                        .len()
+                        .min(buf.len())
+                        .min(remaining.try_into().unwrap_or(usize::MAX));
+                    buf[..len].copy_from_slice(&available[..len]);
+                    Pin::new(&mut this.reader).consume(len);
+                    this.state = if remaining == len as u64 {
+                        ChunkState::DataEnd
+                    } else {
+                        ChunkState::Data(remaining - len as u64)
+                    };
+                    return Poll::Ready(Ok(len));
+                }
+                ChunkState::Size | ChunkState::DataEnd | ChunkState::Trailers => {
+                    ready!(poll_line(&mut this.reader, &mut this.line, cx))?;
+                    let line = String::from_utf8_lossy(&this.line).trim().to_string();
+                    this.line.clear();
+                    this.state = match this.state {
+                        ChunkState::Size => {
+                            let size = line.split(';').next().unwrap_or_default().trim();
+                            match u64::from_str_radix(size, 16) {
+                                Ok(0) => ChunkState::Trailers,
+                                Ok(size) => ChunkState::Data(size),
+                                Err(_) => {
+                                    return Poll::Ready(Err(io::Error::new(
+                                        io::ErrorKind::InvalidData,
+                                        format!("invalid chunk size {size:?}"),
+                                    )))
+                                }
+                            }
+                        }
+                        ChunkState::DataEnd => ChunkState::Size,
+                        _ if line.is_empty() => ChunkState::Done,
+                        state => state,
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// Reads up to and including the next line break into `line`.
+fn poll_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    line: &mut Vec<u8>,
+    cx: &mut Context<'_>,
+) -> Poll<io::Result<()>> {
+    loop {
+        let available = ready!(Pin::new(&mut *reader).poll_fill_buf(cx))?;
+        if available.is_empty() {
+            return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+        }
+        match available.iter().position(|byte| *byte == b'\n') {
+            Some(end) => {
+                line.extend_from_slice(&available[..=end]);
+                Pin::new(&mut *reader).consume(end + 1);
+                return Poll::Ready(Ok(()));
+            }
+            None => {
+                let len = available.len();
+                line.extend_from_slice(available);
+                Pin::new(&mut *reader).consume(len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{
+        io::{BufReader, Cursor},
+        AsyncReadExt as _,
+    };
+
+    #[test]
+    fn test_api_endpoint() {
+        assert_eq!(
+            api_endpoint("http://localhost:11434", "api/generate"),
+            "http://localhost:11434/api/generate"
+        );
+        let url = api_endpoint("unix:///run/ollama.sock", "api/generate");
+        let uri = url.parse::<Uri>().unwrap();
+        assert_eq!(uri.scheme_str(), Some("unix"));
+        assert_eq!(uri.path(), "/api/generate");
+        assert_eq!(socket_path(&uri).unwrap(), "/run/ollama.sock");
+    }
+
+    #[test]
+    fn test_chunked_reader() {
+        let body = "7\r\n{\"a\":1}\r\n1;ext=1\r\n\n\r\n0\r\nTrailer: x\r\n\r\nleftover";
+        // A tiny buffer makes lines and chunks arrive in pieces.
+        let mut reader = ChunkedReader::new(BufReader::with_capacity(3, Cursor::new(body)));
+        let mut decoded = String::new();
+        smol::block_on(reader.read_to_string(&mut decoded)).unwrap();
+        assert_eq!(decoded, "{\"a\":1}\n");
+
+        let mut reader = ChunkedReader::new(Cursor::new("zz\r\n"));
+        assert!(smol::block_on(reader.read_to_string(&mut String::new())).is_err());
+    }
+}
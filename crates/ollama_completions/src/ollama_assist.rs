@@ -0,0 +1,224 @@
+use crate::{
+    chat_editor, http_client_for, project_layout_context, transform_range, ChatThread, Generations,
+    OllamaSettings,
+};
+use anyhow::Result;
+use editor::{Editor, EditorMode, MultiBufferSnapshot, ToOffset as _, ToPoint as _};
+use futures::StreamExt as _;
+use gpui::{AppContext, Entity as _, ViewContext};
+use language::Point;
+use ollama::{stream_chat_completion, ChatMessage, ChatRequest, RequestPriority};
+use std::ops::Range;
+use zed_actions::ollama::{DocumentSymbol, ExplainSelection, RewriteSelection};
+
+pub fn init(cx: &mut AppContext) {
+    cx.observe_new_views(|editor: &mut Editor, cx: &mut ViewContext<Editor>| {
+        if editor.mode() != EditorMode::Full {
+            return;
+        }
+
+        editor
+            .register_action(cx.listener(|editor, _: &ExplainSelection, cx| {
+                assist(editor, AssistKind::Explain, cx)
+            }))
+            .detach();
+        editor
+            .register_action(cx.listener(|editor, _: &RewriteSelection, cx| {
+                assist(editor, AssistKind::Rewrite, cx)
+            }))
+            .detach();
+        editor
+            .register_action(cx.listener(|editor, _: &DocumentSymbol, cx| {
+                assist(editor, AssistKind::Document, cx)
+            }))
+            .detach();
+    })
+    .detach();
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AssistKind {
+    Explain,
+    Rewrite,
+    Document,
+}
+
+impl AssistKind {
+    fn instruction(&self) -> &'static str {
+        match self {
+            AssistKind::Explain => "Explain what the following code does. Be concise.",
+            AssistKind::Rewrite => {
+                "Rewrite the following code to be clearer and more idiomatic without changing its behavior."
+            }
+            AssistKind::Document => {
+                "Write a documentation comment for the following code in the idiomatic style of its language. \
+                Respond with only the comment."
+            }
+        }
+    }
+}
+
+/// Sends the selection (or the innermost symbol around the cursor when nothing
+/// is selected) to the configured Ollama model.
+fn assist(editor: &mut Editor, kind: AssistKind, cx: &mut ViewContext<Editor>) {
+    let snapshot = editor.buffer().read(cx).snapshot(cx);
+    let Some(range) = selection_or_symbol(editor, &snapshot, cx) else {
+        return;
+    };
+
+    let code = snapshot.text_for_range(range.clone()).collect::<String>();
+    let language_name = snapshot
+        .language_at(range.start)
+        .map(|language| language.name().0.to_string())
+        .unwrap_or_default();
+    let settings = OllamaSettings::for_file(snapshot.file_at(range.start), cx);
+    let server = OllamaSettings::server_for_file(snapshot.file_at(range.start), cx);
+    if !settings.enabled {
+        return;
+    }
+    let mut request = ChatRequest {
+        model: settings.resolve_model(&settings.model).to_string(),
+        messages: vec![
+            ChatMessage::System {
+                content: kind.instruction().to_string(),
+            },
+            ChatMessage::User {
+                content: format!("```{language_name}\n{code}\n```"),
+            },
+        ],
+        stream: true,
+        keep_alive: settings.keep_alive_for(&settings.model),
+        options: Some(settings.chat_options(&settings.model)),
+        tools: Vec::new(),
+        priority: RequestPriority::UserInitiated,
+    };
+    if let Some(redactor) = settings.secret_redactor() {
+        redactor.redact_chat_request(&mut request);
+    }
+    let http_client = http_client_for(server, &cx.http_client());
+    let api_url = server.api_url.clone();
+    let api_key = server.effective_api_key();
+
+    match kind {
+        AssistKind::Explain => {
+            // Explanations open as a chat, so follow-up questions can be asked
+            // and the conversation can be picked up again later.
+            let Some(workspace) = editor.workspace() else {
+                return;
+            };
+            let project = workspace.read(cx).project().clone();
+            let mut messages = request.messages;
+            let layout = project_layout_context(
+                project.read(cx),
+                snapshot
+                    .file_at(range.start)
+                    .map(|file| file.path().as_ref()),
+                settings,
+                settings.chat_project_layout_tokens,
+                cx,
+            );
+            if let Some(ChatMessage::System { content }) = messages.first_mut() {
+                if !layout.is_empty() {
+                    content.push_str("\n\n");
+                    content.push_str(&layout);
+                }
+            }
+            let thread = ChatThread::new(request.model, request.options, messages);
+            let chat_editor = chat_editor(thread, project, true, cx);
+            workspace.update(cx, |workspace, cx| {
+                workspace.add_item_to_active_pane(Box::new(chat_editor), None, true, cx);
+            });
+        }
+        AssistKind::Rewrite => {
+            let anchor_range =
+                snapshot.anchor_after(range.start)..snapshot.anchor_before(range.end);
+            let task = transform_range(editor, anchor_range, kind.instruction(), cx);
+            Generations::track(Some(cx.view().entity_id()), task, cx);
+        }
+        AssistKind::Document => {
+            let anchor_range =
+                snapshot.anchor_after(range.start)..snapshot.anchor_before(range.end);
+            let indent = leading_whitespace(&snapshot, range.start);
+
+            let task = cx.spawn(|editor, mut cx| async move {
+                let response =
+                    collect_response(http_client.as_ref(), &api_url, api_key.as_deref(), request)
+                        .await?;
+                let response = strip_code_fence(&response);
+
+                editor.update(&mut cx, |editor, cx| {
+                    let snapshot = editor.buffer().read(cx).snapshot(cx);
+                    let row = anchor_range.start.to_point(&snapshot).row;
+                    let line_start = snapshot.anchor_before(Point::new(row, 0));
+                    let comment = response
+                        .lines()
+                        .map(|line| format!("{indent}{line}\n"))
+                        .collect::<String>();
+                    editor.edit([(line_start..line_start, comment)], cx);
+                })
+            });
+            Generations::track(Some(cx.view().entity_id()), task, cx);
+        }
+    }
+}
+
+/// The newest selection, or the innermost symbol around the cursor when
+/// nothing is selected.
+pub(crate) fn selection_or_symbol(
+    editor: &Editor,
+    snapshot: &MultiBufferSnapshot,
+    cx: &mut ViewContext<Editor>,
+) -> Option<Range<usize>> {
+    let range = editor.selections.newest::<usize>(cx).range();
+    if !range.is_empty() {
+        return Some(range);
+    }
+    let (_, symbols) = snapshot.symbols_containing(range.start, None)?;
+    let symbol = symbols.into_iter().last()?;
+    Some(symbol.range.start.to_offset(snapshot)..symbol.range.end.to_offset(snapshot))
+}
+
+async fn collect_response(
+    client: &dyn http_client::HttpClient,
+    api_url: &str,
+    api_key: Option<&str>,
+    request: ChatRequest,
+) -> Result<String> {
+    let mut chunks = stream_chat_completion(client, api_url, api_key, request, None).await?;
+    let mut response = String::new();
+    while let Some(chunk) = chunks.next().await {
+        response.push_str(&message_content(chunk?.message));
+    }
+    Ok(response)
+}
+
+pub(crate) fn message_content(message: ChatMessage) -> String {
+    match message {
+        ChatMessage::User { content }
+        | ChatMessage::Assistant { content, .. }
+        | ChatMessage::System { content } => content,
+    }
+}
+
+fn leading_whitespace(snapshot: &MultiBufferSnapshot, offset: usize) -> String {
+    let row = offset.to_point(snapshot).row;
+    snapshot
+        .chars_at(Point::new(row, 0))
+        .take_while(|c| *c == ' ' || *c == '\t')
+        .collect()
+}
+
+/// Models tend to wrap code in a markdown fence even when asked not to.
+pub fn strip_code_fence(text: &str) -> &str {
+    let text = text.trim();
+    let Some(rest) = text.strip_prefix("```") else {
+        return text;
+    };
+    let Some((_, body)) = rest.split_once('\n') else {
+        return text;
+    };
+    body.trim_end()
+        .strip_suffix("```")
+        .unwrap_or(body)
+        .trim_end()
+}
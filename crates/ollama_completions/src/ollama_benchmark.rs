@@ -0,0 +1,229 @@
+use crate::{ApiFormat, EndpointPool, COMPLETION_TEMPERATURE};
+use anyhow::{anyhow, Result};
+use http_client::HttpClient;
+use ollama::{ChatOptions, GenerateRequest, KeepAlive, RequestPriority, RetryPolicy};
+use std::{
+    fmt::Write as _,
+    time::{Duration, Instant},
+};
+
+/// How many timed requests each model is sent.
+pub const BENCHMARK_RUNS: usize = 5;
+/// Tokens generated per request, so every model does the same amount of work.
+const BENCHMARK_TOKENS: isize = 64;
+/// How long a single request may take, including loading the model.
+const BENCHMARK_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// The code before the cursor in the benchmark's fill-in-the-middle prompt.
+const PREFIX: &str = "use std::collections::HashMap;
+
+/// Counts how often each word occurs in `text`, ignoring case.
+pub fn word_frequencies(text: &str) -> HashMap<String, usize> {
+    let mut frequencies = HashMap::new();
+    for word in text.split_whitespace() {
+        ";
+/// The code after the cursor in the benchmark's fill-in-the-middle prompt.
+const SUFFIX: &str = "
+    }
+    frequencies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_word_frequencies() {
+        let frequencies = word_frequencies(\"a b A\");
+        assert_eq!(frequencies[\"a\"], 2);
+    }
+}
+";
+
+/// How one model fared in the benchmark.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelBenchmark {
+    pub model: String,
+    /// The error of a failed run, which ends the model's benchmark.
+    pub result: Result<BenchmarkStats, String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BenchmarkStats {
+    pub p50_latency: Duration,
+    pub p95_latency: Duration,
+    /// Generation speed reported by the server, averaged over the runs.
+    pub tokens_per_second: Option<f64>,
+}
+
+/// Sends the same fill-in-the-middle request to `model` `runs` times and
+/// measures how long it takes to respond. An untimed request first loads the
+/// model, so the time to load it from disk doesn't skew the results.
+pub async fn benchmark_model(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: Option<&str>,
+    api_format: ApiFormat,
+    model: &str,
+    runs: usize,
+) -> ModelBenchmark {
+    let result = run_benchmark(client, api_url, api_key, api_format, model, runs)
+        .await
+        .map_err(|error| error.to_string());
+    ModelBenchmark {
+        model: model.to_string(),
+        result,
+    }
+}
+
+async fn run_benchmark(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: Option<&str>,
+    api_format: ApiFormat,
+    model: &str,
+    runs: usize,
+) -> Result<BenchmarkStats> {
+    let pool = EndpointPool::default();
+    let endpoints = [api_url.to_string()];
+    let request = GenerateRequest {
+        model: model.to_string(),
+        prompt: PREFIX.to_string(),
+        suffix: Some(SUFFIX.to_string()),
+        stream: false,
+        keep_alive: KeepAlive::default(),
+        options: Some(ChatOptions {
+            num_predict: Some(BENCHMARK_TOKENS),
+            temperature: Some(COMPLETION_TEMPERATURE),
+            ..Default::default()
+        }),
+        system: None,
+        raw: None,
+        format: None,
+        context: None,
+        priority: RequestPriority::UserInitiated,
+    };
+    let generate = |request| {
+        pool.generate(
+            client,
+            &endpoints,
+            api_key,
+            api_format,
+            request,
+            RetryPolicy::default().with_max_attempts(1),
+            Some(BENCHMARK_TIMEOUT),
+        )
+    };
+
+    generate(request.clone()).await?;
+    let mut latencies = Vec::with_capacity(runs);
+    let mut speeds = Vec::with_capacity(runs);
+    for _ in 0..runs {
+        let start = Instant::now();
+        let response = generate(request.clone()).await?;
+        latencies.push(start.elapsed());
+        speeds.extend(response.tokens_per_second());
+    }
+    latencies.sort();
+    Ok(BenchmarkStats {
+        p50_latency: percentile(&latencies, 0.5).ok_or_else(|| anyhow!("no runs"))?,
+        p95_latency: percentile(&latencies, 0.95).ok_or_else(|| anyhow!("no runs"))?,
+        tokens_per_second: (!speeds.is_empty())
+            .then(|| speeds.iter().sum::<f64>() / speeds.len() as f64),
+    })
+}
+
+/// The nearest-rank percentile of `sorted`.
+fn percentile(sorted: &[Duration], fraction: f64) -> Option<Duration> {
+    let rank = (fraction * sorted.len() as f64).ceil() as usize;
+    sorted.get(rank.saturating_sub(1)).copied()
+}
+
+/// A Markdown report of the benchmark, with a row for each model measured so
+/// far and the ones still to go.
+pub fn benchmark_report(
+    api_url: &str,
+    runs: usize,
+    results: &[ModelBenchmark],
+    pending: &[String],
+) -> String {
+    let mut report = format!(
+        "# Ollama Model Benchmark\n\n\
+         Each model completed the same fill-in-the-middle prompt {runs} times on {api_url}, \
+         after one untimed request to load it.\n\n\
+         | Model | p50 latency | p95 latency | Tokens/sec |\n\
+         | --- | ---: | ---: | ---: |\n"
+    );
+    for benchmark in results {
+        match &benchmark.result {
+            Ok(stats) => {
+                let tokens_per_second = stats
+                    .tokens_per_second
+                    .map_or("-".to_string(), |speed| format!("{speed:.1}"));
+                writeln!(
+                    report,
+                    "| {} | {} ms | {} ms | {tokens_per_second} |",
+                    benchmark.model,
+                    stats.p50_latency.as_millis(),
+                    stats.p95_latency.as_millis()
+                )
+                .ok();
+            }
+            Err(error) => {
+                let error = error.lines().next().unwrap_or_default();
+                writeln!(report, "| {} | failed: {error} | | |", benchmark.model).ok();
+            }
+        }
+    }
+    for model in pending {
+        writeln!(report, "| {model} | running… | | |").ok();
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_benchmark_report() {
+        let millis = Duration::from_millis;
+        let latencies = [120, 95, 300, 101, 110].map(millis);
+        let mut sorted = latencies.to_vec();
+        sorted.sort();
+        assert_eq!(percentile(&sorted, 0.5), Some(millis(110)));
+        assert_eq!(percentile(&sorted, 0.95), Some(millis(300)));
+        assert_eq!(percentile(&[], 0.5), None);
+
+        let results = [
+            ModelBenchmark {
+                model: "qwen2.5-coder:1.5b".into(),
+                result: Ok(BenchmarkStats {
+                    p50_latency: millis(110),
+                    p95_latency: millis(300),
+                    tokens_per_second: Some(84.26),
+                }),
+            },
+            ModelBenchmark {
+                model: "nomic-embed-text".into(),
+                result: Err("\"nomic-embed-text\" does not support generate\nmore".into()),
+            },
+        ];
+        assert_eq!(
+            benchmark_report(
+                "http://localhost:11434",
+                5,
+                &results,
+                &["llama3.2:1b".into()]
+            ),
+            "# Ollama Model Benchmark\n\n\
+             Each model completed the same fill-in-the-middle prompt 5 times on \
+             http://localhost:11434, after one untimed request to load it.\n\n\
+             | Model | p50 latency | p95 latency | Tokens/sec |\n\
+             | --- | ---: | ---: | ---: |\n\
+             | qwen2.5-coder:1.5b | 110 ms | 300 ms | 84.3 |\n\
+             | nomic-embed-text | failed: \"nomic-embed-text\" does not support generate | | |\n\
+             | llama3.2:1b | running… | | |\n"
+        );
+    }
+}
@@ -0,0 +1,73 @@
+use gpui::{AppContext, Global};
+use parking_lot::Mutex;
+use std::{collections::VecDeque, sync::Arc};
+use text::BufferSnapshot;
+
+/// Snapshots of at most this many buffers are kept to diff against.
+const MAX_TRACKED_BUFFERS: usize = 32;
+
+#[derive(Default)]
+struct GlobalBufferSnapshots(Arc<BufferSnapshots>);
+
+impl Global for GlobalBufferSnapshots {}
+
+/// The last seen snapshot of each recently edited buffer with Ollama
+/// completions, so the edits made since can be found when it's next seen.
+/// The [`EditHistory`](crate::EditHistory) and the
+/// [`EditMemory`](crate::EditMemory) are both given the edits found here.
+#[derive(Default)]
+pub struct BufferSnapshots {
+    snapshots: Mutex<VecDeque<BufferSnapshot>>,
+}
+
+pub(crate) fn init(cx: &mut AppContext) {
+    cx.set_global(GlobalBufferSnapshots::default());
+}
+
+impl BufferSnapshots {
+    pub fn global(cx: &AppContext) -> Arc<Self> {
+        cx.global::<GlobalBufferSnapshots>().0.clone()
+    }
+
+    /// Remembers `snapshot` in place of the buffer's last seen one, which is
+    /// returned. `None` the first time the buffer is seen, or once it's been
+    /// forgotten for other buffers.
+    pub fn observe(&self, snapshot: &BufferSnapshot) -> Option<BufferSnapshot> {
+        let mut snapshots = self.snapshots.lock();
+        let previous = match snapshots
+            .iter()
+            .position(|previous| previous.remote_id() == snapshot.remote_id())
+        {
+            Some(ix) => snapshots.remove(ix),
+            None => None,
+        };
+        if snapshots.len() == MAX_TRACKED_BUFFERS {
+            snapshots.pop_front();
+        }
+        snapshots.push_back(snapshot.clone());
+        previous
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use text::{Buffer, BufferId};
+
+    #[test]
+    fn test_buffer_snapshots() {
+        let snapshots = BufferSnapshots::default();
+        let mut buffer = Buffer::new(0, BufferId::new(1).unwrap(), "a".into());
+        assert!(snapshots.observe(&buffer.snapshot()).is_none());
+        buffer.edit([(1..1, "b")]);
+        let previous = snapshots.observe(&buffer.snapshot()).unwrap();
+        assert_eq!(previous.text(), "a");
+
+        // The least recently seen buffers are forgotten first.
+        for id in 2..=MAX_TRACKED_BUFFERS as u64 + 1 {
+            let other = Buffer::new(0, BufferId::new(id).unwrap(), String::new());
+            snapshots.observe(&other.snapshot());
+        }
+        assert!(snapshots.observe(&buffer.snapshot()).is_none());
+    }
+}
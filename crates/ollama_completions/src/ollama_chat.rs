@@ -0,0 +1,195 @@
+use crate::{
+    http_client_for, message_content, parse_chat, ChatHistory, ChatThread, Generations,
+    OllamaSettings, ASSISTANT_HEADING, USER_HEADING,
+};
+use chrono::Utc;
+use editor::{actions::MoveToEnd, Editor, MultiBuffer};
+use futures::StreamExt as _;
+use gpui::{
+    AsyncWindowContext, Context as _, Entity as _, Model, View, ViewContext, VisualContext as _,
+    WindowContext,
+};
+use ollama::{stream_chat_completion, ChatMessage, ChatRequest, RequestPriority};
+use project::Project;
+use settings::Settings as _;
+use std::{cell::RefCell, path::Path, rc::Rc};
+use zed_actions::ollama::{ExportChat, SendChatMessage};
+
+/// Opens `thread` in a Markdown editor where the conversation can be edited
+/// and continued with `ollama: send chat message`. The model's reply is
+/// streamed in below the last message, and the thread is saved to
+/// [`ChatHistory`] after every exchange. `ollama: export chat` saves it to a
+/// file.
+///
+/// When `send` is true, the thread's last message is sent right away.
+pub fn chat_editor(
+    thread: ChatThread,
+    project: Model<Project>,
+    send: bool,
+    cx: &mut WindowContext,
+) -> View<Editor> {
+    let markdown = project.read(cx).languages().language_for_name("Markdown");
+    let text = thread.markdown();
+    let buffer = project.update(cx, |project, cx| {
+        project.create_local_buffer(&text, None, cx)
+    });
+    let multibuffer = cx.new_model(|cx| {
+        MultiBuffer::singleton(buffer.clone(), cx).with_title("Ollama Chat".into())
+    });
+    let thread = Rc::new(RefCell::new(thread));
+    let editor = cx.new_view(|cx| {
+        let mut editor = Editor::for_multibuffer(multibuffer, Some(project), true, cx);
+        editor
+            .register_action(cx.listener({
+                let thread = thread.clone();
+                move |editor, _: &SendChatMessage, cx| send_message(editor, &thread, cx)
+            }))
+            .detach();
+        editor
+            .register_action(cx.listener({
+                let thread = thread.clone();
+                move |editor, _: &ExportChat, cx| export_chat(editor, &thread, cx)
+            }))
+            .detach();
+        editor.move_to_end(&MoveToEnd, cx);
+        editor
+    });
+
+    cx.spawn(|mut cx| async move {
+        let markdown = markdown.await?;
+        buffer.update(&mut cx, |buffer, cx| {
+            buffer.set_language(Some(markdown), cx)
+        })
+    })
+    .detach_and_log_err(cx);
+
+    if send {
+        editor.update(cx, |editor, cx| send_message(editor, &thread, cx));
+    }
+    editor
+}
+
+/// Sends the conversation as it reads in the editor, if it ends in a message
+/// from the user. While a reply is streaming in, the conversation ends in the
+/// model's message, so it can't be sent twice.
+fn send_message(
+    editor: &mut Editor,
+    thread: &Rc<RefCell<ChatThread>>,
+    cx: &mut ViewContext<Editor>,
+) {
+    let Some(buffer) = editor.buffer().read(cx).as_singleton() else {
+        return;
+    };
+    let messages = parse_chat(&buffer.read(cx).text());
+    if !matches!(messages.last(), Some(ChatMessage::User { content }) if !content.is_empty()) {
+        return;
+    }
+    let settings = OllamaSettings::get_global(cx);
+    let mut request = {
+        let mut thread = thread.borrow_mut();
+        thread.messages = messages;
+        thread.updated_at = Utc::now();
+        ChatHistory::global(cx).save(thread.clone(), cx);
+        ChatRequest {
+            model: thread.model.clone(),
+            messages: thread.messages.clone(),
+            stream: true,
+            keep_alive: settings.keep_alive_for(&thread.model),
+            options: thread.options.clone(),
+            tools: Vec::new(),
+            priority: RequestPriority::UserInitiated,
+        }
+    };
+    // The thread keeps the messages as written; only what's sent is redacted.
+    if let Some(redactor) = settings.secret_redactor() {
+        redactor.redact_chat_request(&mut request);
+    }
+    let http_client = http_client_for(settings, &cx.http_client());
+    let api_url = settings.api_url.clone();
+    let api_key = settings.effective_api_key();
+    let thread = thread.clone();
+
+    let task = cx.spawn(|editor, mut cx| async move {
+        let append = |text: String, cx: &mut AsyncWindowContext| {
+            buffer.update(cx, |buffer, cx| {
+                let end = buffer.len();
+                buffer.edit([(end..end, text)], None, cx);
+            })
+        };
+        append(format!("\n\n{ASSISTANT_HEADING}\n\n"), &mut cx)?;
+
+        let mut chunks = stream_chat_completion(
+            http_client.as_ref(),
+            &api_url,
+            api_key.as_deref(),
+            request,
+            None,
+        )
+        .await?;
+        let mut reply = String::new();
+        while let Some(chunk) = chunks.next().await {
+            let text = message_content(chunk?.message);
+            reply.push_str(&text);
+            append(text, &mut cx)?;
+        }
+        append(format!("\n\n{USER_HEADING}\n\n"), &mut cx)?;
+
+        editor.update(&mut cx, |editor, cx| {
+            let mut thread = thread.borrow_mut();
+            thread.messages.push(ChatMessage::Assistant {
+                content: reply.trim().to_string(),
+                tool_calls: None,
+            });
+            thread.updated_at = Utc::now();
+            ChatHistory::global(cx).save(thread.clone(), cx);
+            editor.move_to_end(&MoveToEnd, cx);
+        })
+    });
+    Generations::track(Some(cx.view().entity_id()), task, cx);
+}
+
+/// Saves the conversation as it reads in the editor to a file the user picks,
+/// as JSON when its name ends in `.json` and as Markdown otherwise.
+fn export_chat(
+    editor: &mut Editor,
+    thread: &Rc<RefCell<ChatThread>>,
+    cx: &mut ViewContext<Editor>,
+) {
+    let (Some(buffer), Some(project)) = (
+        editor.buffer().read(cx).as_singleton(),
+        editor.project().cloned(),
+    ) else {
+        return;
+    };
+    let mut thread = thread.borrow().clone();
+    thread.messages = parse_chat(&buffer.read(cx).text());
+    // The empty message left for a reply isn't part of the conversation.
+    if matches!(thread.messages.last(), Some(ChatMessage::User { content }) if content.is_empty()) {
+        thread.messages.pop();
+    }
+    let project = project.read(cx);
+    let fs = project.fs().clone();
+    let directory = project
+        .visible_worktrees(cx)
+        .next()
+        .map(|worktree| worktree.read(cx).abs_path().to_path_buf())
+        .unwrap_or_else(|| Path::new("").into());
+    let path = cx.prompt_for_new_path(&directory);
+
+    cx.background_executor()
+        .spawn(async move {
+            let Some(path) = path.await?? else {
+                return Ok(());
+            };
+            let text = if path
+                .extension()
+                .map_or(false, |extension| extension == "json")
+            {
+                thread.export_json()?
+            } else {
+                thread.export_markdown()
+            };
+            fs.atomic_write(path, text).await
+        })
+        .detach_and_log_err(cx);
+}
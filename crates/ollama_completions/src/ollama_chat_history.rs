@@ -0,0 +1,426 @@
+use crate::message_content;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use db::kvp::KEY_VALUE_STORE;
+use gpui::{AppContext, Global};
+use ollama::{ChatMessage, ChatOptions, OllamaToolCall};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Write as _, sync::Arc};
+use util::ResultExt as _;
+
+const HISTORY_KEY: &str = "ollama_chat_history";
+/// How many conversations are kept. The least recently used go first.
+const MAX_THREADS: usize = 100;
+
+pub(crate) const SYSTEM_HEADING: &str = "## System";
+pub(crate) const USER_HEADING: &str = "## User";
+pub(crate) const ASSISTANT_HEADING: &str = "## Assistant";
+/// Opens the block of JSON after a message from the model, recording the
+/// tools it called.
+const TOOL_CALLS_FENCE: &str = "```tool_calls";
+
+/// What exported Markdown says about the conversation, above its messages.
+const MODEL_LABEL: &str = "- Model: ";
+const OPTIONS_LABEL: &str = "- Options: ";
+const CREATED_LABEL: &str = "- Created: ";
+
+struct GlobalChatHistory(Arc<ChatHistory>);
+
+impl Global for GlobalChatHistory {}
+
+/// Conversations with Ollama models, kept in Zed's local database so they can
+/// be picked up again after a restart.
+#[derive(Default)]
+pub struct ChatHistory {
+    threads: Mutex<Vec<ChatThread>>,
+}
+
+/// A conversation with a model, and the options it was run with.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChatThread {
+    pub id: u64,
+    pub model: String,
+    pub options: Option<ChatOptions>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub messages: Vec<ChatMessage>,
+}
+
+pub(crate) fn init(cx: &mut AppContext) {
+    let history = Arc::new(ChatHistory::default());
+    cx.set_global(GlobalChatHistory(history.clone()));
+    cx.background_executor()
+        .spawn(async move {
+            // Conversations may have been saved while loading, and those are
+            // newer than the saved copies.
+            if let Some(saved) = KEY_VALUE_STORE.read_kvp(HISTORY_KEY)? {
+                let saved: Vec<ChatThread> = serde_json::from_str(&saved)?;
+                let mut threads = history.threads.lock();
+                for thread in saved {
+                    if !threads.iter().any(|existing| existing.id == thread.id) {
+                        threads.push(thread);
+                    }
+                }
+            }
+            anyhow::Ok(())
+        })
+        .detach_and_log_err(cx);
+}
+
+impl ChatHistory {
+    pub fn global(cx: &AppContext) -> Arc<Self> {
+        cx.global::<GlobalChatHistory>().0.clone()
+    }
+
+    /// The saved conversations, most recently used first.
+    pub fn threads(&self) -> Vec<ChatThread> {
+        let mut threads = self.threads.lock().clone();
+        threads.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+        threads
+    }
+
+    /// Adds or updates `thread`, and saves the history in the background.
+    pub fn save(&self, thread: ChatThread, cx: &AppContext) {
+        let saved = {
+            let mut threads = self.threads.lock();
+            threads.retain(|existing| existing.id != thread.id);
+            threads.push(thread);
+            threads.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+            threads.truncate(MAX_THREADS);
+            serde_json::to_string(&*threads)
+        };
+        let Some(saved) = saved.log_err() else {
+            return;
+        };
+        cx.background_executor()
+            .spawn(async move {
+                KEY_VALUE_STORE
+                    .write_kvp(HISTORY_KEY.to_string(), saved)
+                    .await
+            })
+            .detach_and_log_err(cx);
+    }
+}
+
+impl ChatThread {
+    pub fn new(model: String, options: Option<ChatOptions>, messages: Vec<ChatMessage>) -> Self {
+        let now = Utc::now();
+        Self {
+            id: rand::random(),
+            model,
+            options,
+            created_at: now,
+            updated_at: now,
+            messages,
+        }
+    }
+
+    /// A copy of this conversation that is saved separately from it.
+    pub fn branch(&self) -> Self {
+        Self {
+            id: rand::random(),
+            created_at: Utc::now(),
+            ..self.clone()
+        }
+    }
+
+    /// The first line of the conversation's first message from the user,
+    /// skipping the opening of a code block.
+    pub fn title(&self) -> String {
+        self.messages
+            .iter()
+            .find_map(|message| match message {
+                ChatMessage::User { content } => content
+                    .lines()
+                    .map(str::trim)
+                    .find(|line| !line.is_empty() && !line.starts_with("```"))
+                    .map(ToString::to_string),
+                _ => None,
+            })
+            .unwrap_or_else(|| "Untitled Chat".to_string())
+    }
+
+    /// The conversation as Markdown, with a heading before each message. The
+    /// text ends in an empty message from the user, ready for a reply.
+    pub fn markdown(&self) -> String {
+        let mut markdown = String::new();
+        self.write_messages(&mut markdown);
+        if !matches!(self.messages.last(), Some(ChatMessage::User { .. })) {
+            write!(markdown, "{USER_HEADING}\n\n").ok();
+        }
+        markdown
+    }
+
+    /// The conversation as Markdown to archive or share: its title, the model
+    /// and options it was run with, and its messages. [`import_chat`] reads
+    /// it back.
+    pub fn export_markdown(&self) -> String {
+        let mut markdown = format!("# {}\n\n{MODEL_LABEL}{}\n", self.title(), self.model);
+        if let Some(options) = self
+            .options
+            .as_ref()
+            .and_then(|options| serde_json::to_string(options).log_err())
+        {
+            writeln!(markdown, "{OPTIONS_LABEL}{options}").ok();
+        }
+        writeln!(
+            markdown,
+            "{CREATED_LABEL}{}\n",
+            self.created_at.to_rfc3339()
+        )
+        .ok();
+        self.write_messages(&mut markdown);
+        markdown.truncate(markdown.trim_end().len());
+        markdown.push('\n');
+        markdown
+    }
+
+    /// The conversation as JSON, the way it's saved to the history.
+    pub fn export_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Writes each message under its heading, followed by the tools the
+    /// model called, if any.
+    fn write_messages(&self, markdown: &mut String) {
+        for message in &self.messages {
+            let heading = match message {
+                ChatMessage::System { .. } => SYSTEM_HEADING,
+                ChatMessage::User { .. } => USER_HEADING,
+                ChatMessage::Assistant { .. } => ASSISTANT_HEADING,
+            };
+            let content = message_content(message.clone());
+            write!(markdown, "{heading}\n\n{}\n\n", content.trim()).ok();
+            if let ChatMessage::Assistant {
+                tool_calls: Some(tool_calls),
+                ..
+            } = message
+            {
+                if let Some(json) = serde_json::to_string_pretty(tool_calls).log_err() {
+                    write!(markdown, "{TOOL_CALLS_FENCE}\n{json}\n```\n\n").ok();
+                }
+            }
+        }
+    }
+}
+
+/// Reads a conversation exported with [`ChatThread::export_markdown`] or
+/// [`ChatThread::export_json`], or any Markdown with chat headings. It gets
+/// an id of its own, so continuing it leaves the conversation it came from
+/// as it was. Markdown that doesn't say which model it was run with is
+/// given `default_model`.
+pub fn import_chat(text: &str, default_model: &str) -> Result<ChatThread> {
+    let mut thread = if text.trim_start().starts_with('{') {
+        serde_json::from_str::<ChatThread>(text)?
+    } else {
+        let mut thread = ChatThread::new(default_model.to_string(), None, parse_chat(text));
+        for line in text.lines().take_while(|line| heading(line).is_none()) {
+            if let Some(model) = line.strip_prefix(MODEL_LABEL) {
+                thread.model = model.trim().to_string();
+            } else if let Some(options) = line.strip_prefix(OPTIONS_LABEL) {
+                thread.options = Some(serde_json::from_str(options)?);
+            } else if let Some(created_at) = line.strip_prefix(CREATED_LABEL) {
+                thread.created_at = DateTime::parse_from_rfc3339(created_at.trim())?.into();
+            }
+        }
+        thread
+    };
+    if thread.messages.is_empty() {
+        return Err(anyhow!("there are no chat messages in it"));
+    }
+    thread.id = rand::random();
+    thread.updated_at = Utc::now();
+    Ok(thread)
+}
+
+fn heading(line: &str) -> Option<&'static str> {
+    [SYSTEM_HEADING, USER_HEADING, ASSISTANT_HEADING]
+        .into_iter()
+        .find(|heading| line.trim_end() == *heading)
+}
+
+/// Reads the messages back from a conversation's Markdown, as edited by the
+/// user. Text before the first heading is ignored.
+pub fn parse_chat(markdown: &str) -> Vec<ChatMessage> {
+    let mut messages = Vec::new();
+    let mut current: Option<(&str, String)> = None;
+    for line in markdown.lines() {
+        match (heading(line), current.as_mut()) {
+            (Some(heading), _) => {
+                messages.extend(current.take().map(chat_message));
+                current = Some((heading, String::new()));
+            }
+            (None, Some((_, content))) => {
+                content.push_str(line);
+                content.push('\n');
+            }
+            (None, None) => {}
+        }
+    }
+    messages.extend(current.map(chat_message));
+    messages
+}
+
+fn chat_message((heading, content): (&str, String)) -> ChatMessage {
+    let content = content.trim().to_string();
+    match heading {
+        SYSTEM_HEADING => ChatMessage::System { content },
+        ASSISTANT_HEADING => {
+            let (content, tool_calls) = split_tool_calls(content);
+            ChatMessage::Assistant {
+                content,
+                tool_calls,
+            }
+        }
+        _ => ChatMessage::User { content },
+    }
+}
+
+/// Separates the tool calls at the end of a message from the model from its
+/// text. Blocks that don't parse are left in the text.
+fn split_tool_calls(content: String) -> (String, Option<Vec<OllamaToolCall>>) {
+    let tool_calls = content
+        .rsplit_once(TOOL_CALLS_FENCE)
+        .filter(|(text, _)| text.is_empty() || text.ends_with('\n'))
+        .and_then(|(text, block)| {
+            let json = block.trim_end().strip_suffix("```")?;
+            let tool_calls = serde_json::from_str(json).ok()?;
+            Some((text.trim_end().to_string(), tool_calls))
+        });
+    match tool_calls {
+        Some((text, tool_calls)) => (text, Some(tool_calls)),
+        None => (content, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ollama::OllamaFunctionCall;
+    use serde_json::value::RawValue;
+
+    #[test]
+    fn test_chat_markdown_round_trip() {
+        let thread = ChatThread::new(
+            "qwen2.5-coder:7b".into(),
+            None,
+            vec![
+                ChatMessage::System {
+                    content: "Explain what the following code does.".into(),
+                },
+                ChatMessage::User {
+                    content: "```rust\nfn main() {}\n```".into(),
+                },
+                ChatMessage::Assistant {
+                    content: "It does nothing.\n\n### In short\n\nNothing.".into(),
+                    tool_calls: None,
+                },
+            ],
+        );
+        assert_eq!(thread.title(), "fn main() {}");
+
+        let markdown = thread.markdown();
+        assert!(markdown.ends_with("Nothing.\n\n## User\n\n"));
+
+        let mut edited = markdown.clone();
+        edited.push_str("Why?\n");
+        let messages = parse_chat(&edited);
+        assert_eq!(messages.len(), 4);
+        assert_eq!(
+            messages
+                .iter()
+                .map(|message| message_content(message.clone()))
+                .collect::<Vec<_>>(),
+            [
+                "Explain what the following code does.",
+                "```rust\nfn main() {}\n```",
+                "It does nothing.\n\n### In short\n\nNothing.",
+                "Why?",
+            ]
+        );
+        assert!(matches!(messages[2], ChatMessage::Assistant { .. }));
+        assert!(matches!(messages[3], ChatMessage::User { .. }));
+
+        let branch = thread.branch();
+        assert_ne!(branch.id, thread.id);
+        assert_eq!(branch.messages.len(), thread.messages.len());
+    }
+
+    #[test]
+    fn test_chat_export_and_import() {
+        let arguments = RawValue::from_string(r#"{"path":"src/main.rs"}"#.into()).unwrap();
+        let mut thread = ChatThread::new(
+            "qwen2.5-coder:7b".into(),
+            Some(ChatOptions {
+                temperature: Some(0.5),
+                ..Default::default()
+            }),
+            vec![
+                ChatMessage::User {
+                    content: "What's in main.rs?".into(),
+                },
+                ChatMessage::Assistant {
+                    content: "Let me look.".into(),
+                    tool_calls: Some(vec![OllamaToolCall::Function(OllamaFunctionCall {
+                        name: "read_file".into(),
+                        arguments,
+                    })]),
+                },
+                ChatMessage::Assistant {
+                    content: "An empty `main`.".into(),
+                    tool_calls: None,
+                },
+            ],
+        );
+        thread.created_at = DateTime::parse_from_rfc3339("2024-10-01T09:30:00Z")
+            .unwrap()
+            .into();
+
+        let markdown = thread.export_markdown();
+        assert!(
+            markdown.starts_with("# What's in main.rs?\n\n- Model: qwen2.5-coder:7b\n- Options: {")
+        );
+        assert!(markdown.contains("Let me look.\n\n```tool_calls\n"));
+        assert!(markdown.ends_with("An empty `main`.\n"));
+
+        for exported in [markdown, thread.export_json().unwrap()] {
+            let imported = import_chat(&exported, "llama3.2").unwrap();
+            assert_ne!(imported.id, thread.id);
+            assert_eq!(imported.model, "qwen2.5-coder:7b");
+            assert_eq!(imported.created_at, thread.created_at);
+            assert_eq!(
+                imported
+                    .options
+                    .as_ref()
+                    .and_then(|options| options.temperature),
+                Some(0.5)
+            );
+            assert_eq!(imported.messages.len(), 3);
+            let ChatMessage::Assistant {
+                content,
+                tool_calls: Some(tool_calls),
+            } = &imported.messages[1]
+            else {
+                panic!("expected the model's tool calls");
+            };
+            assert_eq!(content, "Let me look.");
+            let OllamaToolCall::Function(call) = &tool_calls[0];
+            assert_eq!(call.name, "read_file");
+            assert_eq!(call.arguments.get(), r#"{"path":"src/main.rs"}"#);
+            assert!(matches!(
+                imported.messages[2],
+                ChatMessage::Assistant {
+                    tool_calls: None,
+                    ..
+                }
+            ));
+        }
+
+        // Markdown written by hand works too, with the model it's given.
+        let imported = import_chat("## User\n\nHello\n", "llama3.2").unwrap();
+        assert_eq!(imported.model, "llama3.2");
+        assert_eq!(imported.messages.len(), 1);
+        assert!(import_chat("No chat here.", "llama3.2").is_err());
+    }
+}
@@ -0,0 +1,245 @@
+use crate::{
+    count_tokens, head_within, http_client_for, message_content, ContextWindows, Generations,
+    OllamaSettings,
+};
+use anyhow::{anyhow, Context as _};
+use editor::{Editor, EditorMode};
+use futures::StreamExt as _;
+use git::repository::GitRepository;
+use gpui::{AppContext, Entity as _, ViewContext};
+use language::File;
+use ollama::{
+    stream_chat_completion, ChatMessage, ChatOptions, ChatRequest, KeepAlive, RequestPriority,
+};
+use project::Project;
+use std::sync::Arc;
+use zed_actions::ollama::GenerateCommitMessage;
+
+/// The maximum number of tokens generated for a commit message.
+const MAX_MESSAGE_TOKENS: usize = 256;
+/// The file Git has the commit message written in.
+const COMMIT_MESSAGE_FILE_NAME: &str = "COMMIT_EDITMSG";
+
+pub(crate) fn init(cx: &mut AppContext) {
+    cx.observe_new_views(|editor: &mut Editor, cx: &mut ViewContext<Editor>| {
+        if editor.mode() != EditorMode::Full {
+            return;
+        }
+
+        editor
+            .register_action(cx.listener(|editor, _: &GenerateCommitMessage, cx| {
+                generate_commit_message(editor, cx)
+            }))
+            .detach();
+    })
+    .detach();
+}
+
+/// Streams a commit message for the changes staged in the repository of the
+/// editor's file into the editor at the cursor, such as when it's editing
+/// `COMMIT_EDITMSG` for `git commit`.
+fn generate_commit_message(editor: &mut Editor, cx: &mut ViewContext<Editor>) {
+    let Some(project) = editor.project().cloned() else {
+        return;
+    };
+    let Some(buffer) = editor.buffer().read(cx).as_singleton() else {
+        return;
+    };
+    let Some(file) = buffer.read(cx).file().cloned() else {
+        return;
+    };
+    let Some(repo) = file_repository(project.read(cx), &file, cx) else {
+        return;
+    };
+    let settings = OllamaSettings::for_file(Some(&file), cx);
+    let server = OllamaSettings::server_for_file(Some(&file), cx);
+    if !settings.enabled {
+        return;
+    }
+
+    let model_or_profile = settings
+        .commit_message_model
+        .as_deref()
+        .unwrap_or(&settings.model);
+    let model = settings.resolve_model(model_or_profile).to_string();
+    let prompt = settings.commit_message_prompt.clone();
+    let options = settings.runtime_options(model_or_profile);
+    let keep_alive = settings.keep_alive_for(model_or_profile);
+    let http_client = http_client_for(server, &cx.http_client());
+    let api_url = server.preferred_endpoint(cx);
+    let context_tokens = ContextWindows::global(cx).context_tokens(
+        server,
+        &api_url,
+        model_or_profile,
+        &http_client,
+        cx,
+    );
+    let api_key = server.effective_api_key();
+    let redactor = settings.secret_redactor();
+    let cursor = editor.selections.newest::<usize>(cx).head();
+    let cursor = editor.buffer().read(cx).snapshot(cx).anchor_after(cursor);
+
+    let task = cx.spawn(|editor, mut cx| async move {
+        let diff = cx
+            .background_executor()
+            .spawn(async move { repo.staged_diff() })
+            .await
+            .context("failed to read the staged changes")?;
+        if diff.trim().is_empty() {
+            return Err(anyhow!("there are no staged changes to describe"));
+        }
+
+        let mut request = commit_message_request(model, &prompt, &diff, context_tokens, options);
+        request.keep_alive = keep_alive;
+        if let Some(redactor) = &redactor {
+            redactor.redact_chat_request(&mut request);
+        }
+        let mut chunks = stream_chat_completion(
+            http_client.as_ref(),
+            &api_url,
+            api_key.as_deref(),
+            request,
+            None,
+        )
+        .await?;
+        while let Some(chunk) = chunks.next().await {
+            let text = message_content(chunk?.message);
+            // The cursor anchor is biased to the right, so it stays after
+            // the text inserted so far.
+            editor.update(&mut cx, |editor, cx| {
+                editor.edit([(cursor..cursor, text)], cx);
+            })?;
+        }
+        anyhow::Ok(())
+    });
+    Generations::track(Some(cx.view().entity_id()), task, cx);
+}
+
+/// The local Git repository `file` is in.
+pub fn file_repository(
+    project: &Project,
+    file: &Arc<dyn File>,
+    cx: &AppContext,
+) -> Option<Arc<dyn GitRepository>> {
+    project
+        .worktree_for_id(file.worktree_id(cx), cx)
+        .and_then(|worktree| worktree.read(cx).as_local()?.local_git_repo(file.path()))
+}
+
+/// Whether `file` is the message of a commit being made with `git commit`.
+pub(crate) fn is_commit_message(file: Option<&Arc<dyn File>>) -> bool {
+    file.and_then(|file| file.path().file_name())
+        .is_some_and(|name| name == COMMIT_MESSAGE_FILE_NAME)
+}
+
+/// The staged `diff` as comments in a commit message, the way `git commit
+/// --verbose` shows it, cut off at a whole line after `budget` tokens. It
+/// leads inline completion prompts for commit messages.
+pub(crate) fn staged_changes_context(diff: &str, budget: usize) -> String {
+    if diff.trim().is_empty() {
+        return String::new();
+    }
+    let mut context = "# Changes to be committed:\n".to_string();
+    let mut tokens = count_tokens(&context);
+    for line in diff.lines() {
+        let line = format!("# {line}\n");
+        tokens += count_tokens(&line);
+        if tokens > budget {
+            break;
+        }
+        context.push_str(&line);
+    }
+    context
+}
+
+/// Asks for a commit message describing `diff`, which is cut off at a whole
+/// line when it doesn't fit in the context window along with the prompt and
+/// the message.
+fn commit_message_request(
+    model: String,
+    prompt: &str,
+    diff: &str,
+    context_tokens: usize,
+    options: ChatOptions,
+) -> ChatRequest {
+    let budget = context_tokens.saturating_sub(count_tokens(prompt) + MAX_MESSAGE_TOKENS);
+    let (len, _) = head_within(diff, budget);
+    let mut content = diff[..len].to_string();
+    if len < diff.len() {
+        content.push_str("[The rest of the diff was left out]\n");
+    }
+
+    ChatRequest {
+        model,
+        messages: vec![
+            ChatMessage::System {
+                content: prompt.to_string(),
+            },
+            ChatMessage::User { content },
+        ],
+        stream: true,
+        keep_alive: KeepAlive::default(),
+        options: Some(ChatOptions {
+            num_predict: Some(MAX_MESSAGE_TOKENS as isize),
+            ..options
+        }),
+        tools: Vec::new(),
+        priority: RequestPriority::UserInitiated,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_staged_changes_context() {
+        let diff = "--- a/src/lib.rs\n+++ b/src/lib.rs\n+let x = 1;\n";
+        assert_eq!(
+            staged_changes_context(diff, 1000),
+            "# Changes to be committed:\n# --- a/src/lib.rs\n# +++ b/src/lib.rs\n# +let x = 1;\n"
+        );
+        let budget =
+            count_tokens("# Changes to be committed:\n") + count_tokens("# --- a/src/lib.rs\n");
+        assert_eq!(
+            staged_changes_context(diff, budget),
+            "# Changes to be committed:\n# --- a/src/lib.rs\n"
+        );
+        assert_eq!(staged_changes_context("\n", 1000), "");
+    }
+
+    #[test]
+    fn test_commit_message_request() {
+        let prompt = "Write a commit message.";
+        let line = "+let x = 1;\n";
+        let diff = line.repeat(10);
+
+        let request = commit_message_request(
+            "llama3".into(),
+            prompt,
+            &diff,
+            10_000,
+            ChatOptions::default(),
+        );
+        let ChatMessage::User { content } = &request.messages[1] else {
+            panic!("expected the diff as a user message");
+        };
+        assert_eq!(content, &diff);
+
+        let context_tokens = count_tokens(prompt) + MAX_MESSAGE_TOKENS + 3 * count_tokens(line);
+        let request = commit_message_request(
+            "llama3".into(),
+            prompt,
+            &diff,
+            context_tokens,
+            ChatOptions::default(),
+        );
+        let ChatMessage::User { content } = &request.messages[1] else {
+            panic!("expected the diff as a user message");
+        };
+        assert_eq!(
+            content,
+            &format!("{}[The rest of the diff was left out]\n", line.repeat(3))
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use db::kvp::KEY_VALUE_STORE;
+use gpui::{AppContext, Global};
+use ollama::GenerateRequest;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{collections::VecDeque, sync::Arc};
+use util::ResultExt as _;
+use zed_actions::ollama::ClearCompletionCache;
+
+const CACHE_KEY: &str = "ollama_completion_cache";
+
+struct GlobalPersistentCache(Arc<PersistentCache>);
+
+impl Global for GlobalPersistentCache {}
+
+/// Suggestions the model made, kept in Zed's local database so reopening a
+/// project doesn't ask the model again for prompts it has already answered.
+/// Entries are keyed by a hash of the request, so the prompt itself, and the
+/// code in it, isn't stored.
+#[derive(Default)]
+pub struct PersistentCache {
+    entries: Mutex<VecDeque<CacheEntry>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct CacheEntry {
+    key: String,
+    text: String,
+}
+
+pub(crate) fn init(cx: &mut AppContext) {
+    let cache = Arc::new(PersistentCache::default());
+    cx.set_global(GlobalPersistentCache(cache.clone()));
+    cx.on_action(|_: &ClearCompletionCache, cx| PersistentCache::global(cx).clear(cx));
+    cx.background_executor()
+        .spawn(async move {
+            // Suggestions made while loading are newer than the saved ones.
+            if let Some(saved) = KEY_VALUE_STORE.read_kvp(CACHE_KEY)? {
+                let saved: Vec<CacheEntry> = serde_json::from_str(&saved)?;
+                let mut entries = cache.entries.lock();
+                for entry in saved.into_iter().rev() {
+                    if !entries.iter().any(|existing| existing.key == entry.key) {
+                        entries.push_front(entry);
+                    }
+                }
+            }
+            anyhow::Ok(())
+        })
+        .detach_and_log_err(cx);
+}
+
+impl PersistentCache {
+    pub fn global(cx: &AppContext) -> Arc<Self> {
+        cx.global::<GlobalPersistentCache>().0.clone()
+    }
+
+    /// The suggestion made earlier for the request hashed to `key`.
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.entries
+            .lock()
+            .iter()
+            .rev()
+            .find(|entry| entry.key == key)
+            .map(|entry| entry.text.clone())
+    }
+
+    /// Remembers `text` as the suggestion for the request hashed to `key`,
+    /// dropping the oldest entries beyond `capacity`, and saves the cache in
+    /// the background.
+    pub fn insert(&self, key: String, text: String, capacity: usize, cx: &AppContext) {
+        let saved = {
+            let mut entries = self.entries.lock();
+            insert_bounded(&mut entries, CacheEntry { key, text }, capacity);
+            serde_json::to_string(&*entries)
+        };
+        let Some(saved) = saved.log_err() else {
+            return;
+        };
+        cx.background_executor()
+            .spawn(async move {
+                KEY_VALUE_STORE
+                    .write_kvp(CACHE_KEY.to_string(), saved)
+                    .await
+            })
+            .detach_and_log_err(cx);
+    }
+
+    /// Forgets every cached suggestion, here and in the database.
+    pub fn clear(&self, cx: &AppContext) {
+        self.entries.lock().clear();
+        cx.background_executor()
+            .spawn(async move { KEY_VALUE_STORE.delete_kvp(CACHE_KEY.to_string()).await })
+            .detach_and_log_err(cx);
+    }
+}
+
+fn insert_bounded(entries: &mut VecDeque<CacheEntry>, entry: CacheEntry, capacity: usize) {
+    entries.retain(|existing| existing.key != entry.key);
+    entries.push_back(entry);
+    while entries.len() > capacity {
+        entries.pop_front();
+    }
+}
+
+/// A hash of everything in `request` that decides the model's answer. The
+/// context of an earlier response is left out, since it only saves the
+/// server from reading the prompt again.
+pub fn request_key(request: &GenerateRequest) -> String {
+    let fields = (
+        &request.model,
+        &request.prompt,
+        &request.suffix,
+        &request.system,
+        request.raw,
+        &request.options,
+        &request.format,
+    );
+    let digest = Sha256::digest(serde_json::to_vec(&fields).unwrap_or_default());
+    digest.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ollama::{KeepAlive, RequestPriority};
+
+    fn entry(key: &str) -> CacheEntry {
+        CacheEntry {
+            key: key.into(),
+            text: format!("{key} text"),
+        }
+    }
+
+    #[test]
+    fn test_insert_bounded() {
+        let mut entries = VecDeque::new();
+        insert_bounded(&mut entries, entry("a"), 2);
+        insert_bounded(&mut entries, entry("b"), 2);
+        insert_bounded(&mut entries, entry("a"), 2);
+        insert_bounded(&mut entries, entry("c"), 2);
+        assert_eq!(entries, [entry("a"), entry("c")]);
+
+        insert_bounded(&mut entries, entry("d"), 0);
+        assert!(entries.is_empty());
+    }
+
+    #[test]
+    fn test_request_key() {
+        let request = GenerateRequest {
+            model: "qwen2.5-coder:1.5b".into(),
+            prompt: "fn main() {".into(),
+            suffix: Some("}".into()),
+            stream: false,
+            keep_alive: KeepAlive::default(),
+            options: None,
+            system: None,
+            raw: None,
+            format: None,
+            context: None,
+            priority: RequestPriority::Automatic,
+        };
+        let key = request_key(&request);
+        assert_eq!(key.len(), 64);
+        assert!(!key.contains("main"));
+        assert_eq!(
+            request_key(&GenerateRequest {
+                context: Some(vec![1, 2, 3]),
+                ..request.clone()
+            }),
+            key
+        );
+        assert_ne!(
+            request_key(&GenerateRequest {
+                prompt: "fn test() {".into(),
+                ..request
+            }),
+            key
+        );
+    }
+}
@@ -0,0 +1,2935 @@
+use crate::{
+    collect_stream, cursor_line_diagnostics, duplicates_following_code, edit_prediction_request,
+    edit_repair_request, fallback_completion, file_header, file_repository, git_changes_context,
+    has_min_prefix, http_client_for, indent_columns, is_commit_message, is_mid_identifier,
+    name_after_typed, parse_predicted_edit, postprocess, prefetch_positions,
+    staged_changes_context, substitute_model, trace, AcceptedFormatting, AdaptiveDebounce,
+    ApiFormat, BufferSnapshots, ComparisonStrategy, CompletionCache, CompletionMode,
+    CompletionStats, CompletionTrigger, Connectivity, ContextWindows, DisabledBuffers, EditHistory,
+    EditMemory, EditMemoryRequest, EndpointPool, ErrorReports, ExtensionContextRequest,
+    FallbackSource, Fingerprint, GenerationSpeed, ImportsContextRequest, InsertionContext,
+    LoggedRequest, ModelSubstitutes, OllamaSettings, PerformanceReport, PersistentCache,
+    Postprocessor, PowerState, PredictedEdit, Prefetched, PrefetchedSuggestion, PromptBuilder,
+    PromptContext, RenameTarget, RequestId, RequestLog, RequestOutcome, ReusableContext,
+    SchemaContextRequest, SecretRedactor, TemperatureSchedule, TraceEvent, UnstagedHunk,
+    VerbatimCheck, VerbatimSuggestions, MAX_VERBATIM_CANDIDATES, PREFETCH_DELAY,
+};
+use anyhow::Result;
+use client::telemetry::Telemetry;
+use editor::{
+    AcceptedCompletionFormat, CompletionMetadata, CompletionProposal, Direction, InlayProposal,
+    InlineCompletionProvider, PartialAccept,
+};
+use futures::{
+    future::{self, Either},
+    pin_mut, Future, FutureExt as _, StreamExt as _,
+};
+use gpui::{AppContext, AsyncAppContext, EntityId, Model, ModelContext, Task, WeakModel};
+use http_client::HttpClient;
+use language::{
+    language_settings::{all_language_settings, language_settings},
+    Anchor, Buffer, BufferSnapshot, Point,
+};
+use ollama::{
+    stream_generate, ChatOptions, GenerateRequest, GenerateResponse, OllamaError, RequestPriority,
+    RetryPolicy, TokenUsageKind,
+};
+use project::{
+    search::{SearchQuery, SearchResult},
+    InlayHint, InlayHintLabel, InlayHintTooltip, Project, ResolveState,
+};
+use settings::Settings as _;
+use std::{
+    collections::HashSet,
+    ops::Range,
+    path::Path,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use telemetry_events::{
+    InlineCompletionAcceptance, InlineCompletionEvent, InlineCompletionMetrics,
+};
+use text::{Rope, ToOffset, ToPoint};
+use util::paths::PathMatcher;
+
+/// At most this many other changed files are listed in the prompt.
+const MAX_CHANGED_FILES: usize = 20;
+/// The sampling temperature of the first suggestion shown at the cursor,
+/// unless `completion_options` or `temperature_schedule` sets one.
+pub(crate) const COMPLETION_TEMPERATURE: f32 = 0.2;
+/// How many extra suggestions are requested the first time the user cycles.
+const CYCLING_CANDIDATES: usize = 3;
+/// Alternative suggestions are sampled hotter so they actually differ.
+const CYCLING_TEMPERATURE: f32 = 0.8;
+/// How many follow-up requests may continue a suggestion cut short by the
+/// token limit.
+const MAX_CONTINUATIONS: usize = 2;
+/// Sent instead of a code system prompt when completing prose.
+const PROSE_SYSTEM_PROMPT: &str = "You continue the user's writing. Reply with only the text \
+that comes next, in the same language, voice and formatting, without commentary.";
+/// Prose suggestions don't run on past the end of the paragraph.
+const PROSE_STOP: &str = "\n\n";
+/// Sent instead of a code system prompt when completing a commit message.
+const COMMIT_SYSTEM_PROMPT: &str = "You write Git commit messages. The changes being committed \
+are shown as comments. Reply with only the text that comes next in the message: a summary line \
+of at most 50 characters in the imperative mood, then a blank line and a short explanation of \
+what changed and why, wrapped at 72 characters.";
+/// Commit message suggestions stop at Git's comment lines.
+const COMMIT_STOP: &str = "\n#";
+/// How much of the staged changes a commit message prompt may show.
+const MAX_COMMIT_DIFF_TOKENS: usize = 1024;
+
+pub struct OllamaCompletionProvider {
+    http_client: Arc<dyn HttpClient>,
+    /// The user's settings as of the last change, so a change can drop what
+    /// was requested with the previous ones. Their model is used for requests
+    /// without a file. Files use the model from their own settings, which a
+    /// project can set.
+    settings: OllamaSettings,
+    project: Option<WeakModel<Project>>,
+    buffer_id: Option<EntityId>,
+    file_extension: Option<String>,
+    /// The language at the cursor when the suggestions were requested.
+    language: Option<String>,
+    cycled: bool,
+    /// Whether the next request goes to the comparison model when
+    /// alternating between models.
+    comparison_turn: bool,
+    completions: Vec<OllamaCompletion>,
+    active_completion_index: usize,
+    pending_refresh: Task<Result<()>>,
+    /// Whether `pending_refresh` is still waiting on the model.
+    refreshing: bool,
+    /// The request `pending_refresh` is making, traced as cancelled if it's
+    /// dropped before it finishes.
+    request_id: Option<RequestId>,
+    pending_cycling_refresh: Task<Result<()>>,
+    telemetry: Option<Arc<Telemetry>>,
+    cache: CompletionCache,
+    debounce: AdaptiveDebounce,
+    /// The context of the last suggestion, for continuing from it when the
+    /// user types past it.
+    reusable_context: Option<ReusableContext>,
+    /// The rename editor's buffer, which holds the new name, and the symbol
+    /// being renamed, when this provider also suggests names in one.
+    rename: Option<(EntityId, RenameTarget)>,
+    /// Set when a suggestion is accepted, so the refresh that follows asks
+    /// for the next one right away if `chain_after_accept` is on.
+    chain_next: bool,
+    /// Suggestions requested ahead of time for where the cursor is likely to
+    /// go next, shown without a request when it gets there.
+    prefetched: Prefetched,
+    pending_prefetch: Task<Result<()>>,
+}
+
+struct OllamaCompletion {
+    position: Anchor,
+    text: String,
+    /// For a predicted edit, the text `text` replaces, which may be away from
+    /// the cursor. Otherwise `text` is inserted at the cursor.
+    edit_range: Option<Range<Anchor>>,
+    /// A file elsewhere in the project that already contains most of this
+    /// text.
+    verbatim_source: Option<Arc<Path>>,
+    /// Set when the suggestion was made locally because the server was unreachable.
+    fallback: Option<FallbackSource>,
+    /// The model that made the suggestion, for acceptance statistics. `None`
+    /// for fallback suggestions.
+    model: Option<String>,
+    /// Shown as the suggestion's source, and reported with accept and
+    /// discard events when the user has opted in.
+    metrics: Option<InlineCompletionMetrics>,
+    /// The request that made the suggestion, whose trace records whether it
+    /// was accepted. `None` for fallback suggestions and names.
+    request_id: Option<RequestId>,
+}
+
+impl OllamaCompletion {
+    fn metadata(&self) -> CompletionMetadata {
+        CompletionMetadata {
+            model: self.model.clone(),
+            prompt_tokens: self
+                .metrics
+                .as_ref()
+                .and_then(|metrics| metrics.prompt_tokens),
+            completion_tokens: self
+                .metrics
+                .as_ref()
+                .and_then(|metrics| metrics.completion_tokens),
+            latency: self
+                .metrics
+                .as_ref()
+                .map(|metrics| Duration::from_millis(metrics.latency_ms)),
+            ..CompletionMetadata::new(OllamaCompletionProvider::name())
+        }
+    }
+}
+
+impl OllamaCompletionProvider {
+    pub fn new(http_client: Arc<dyn HttpClient>, settings: OllamaSettings) -> Self {
+        Self {
+            http_client,
+            settings,
+            project: None,
+            buffer_id: None,
+            file_extension: None,
+            language: None,
+            cycled: false,
+            comparison_turn: false,
+            completions: Vec::new(),
+            active_completion_index: 0,
+            pending_refresh: Task::ready(Ok(())),
+            refreshing: false,
+            request_id: None,
+            pending_cycling_refresh: Task::ready(Ok(())),
+            telemetry: None,
+            cache: CompletionCache::default(),
+            debounce: AdaptiveDebounce::default(),
+            reusable_context: None,
+            rename: None,
+            chain_next: false,
+            prefetched: Prefetched::default(),
+            pending_prefetch: Task::ready(Ok(())),
+        }
+    }
+
+    pub fn with_telemetry(mut self, telemetry: Arc<Telemetry>) -> Self {
+        self.telemetry = Some(telemetry);
+        self
+    }
+
+    pub fn with_project(mut self, project: &Model<Project>) -> Self {
+        self.project = Some(project.downgrade());
+        self
+    }
+
+    /// Applies changed settings, such as another server, model or options,
+    /// to new requests. Suggestions made with the previous settings are
+    /// discarded and requests still in flight are dropped, since they may
+    /// have gone to a server or model that's no longer used.
+    pub fn set_settings(&mut self, settings: OllamaSettings, cx: &mut ModelContext<Self>) {
+        if settings == self.settings {
+            return;
+        }
+        self.settings = settings;
+        self.cycled = false;
+        self.reusable_context = None;
+        self.prefetched.clear();
+        self.completions.clear();
+        self.active_completion_index = 0;
+        self.cancel_refresh(cx);
+        self.pending_cycling_refresh = Task::ready(Ok(()));
+        cx.notify();
+    }
+
+    /// Drops the pending refresh, tracing its request as cancelled if it was
+    /// still waiting on the model, along with any prefetching.
+    fn cancel_refresh(&mut self, cx: &mut AppContext) {
+        if let Some(request_id) = self.request_id.take().filter(|_| self.refreshing) {
+            trace(request_id, TraceEvent::Cancelled, cx);
+        }
+        self.pending_refresh = Task::ready(Ok(()));
+        self.pending_prefetch = Task::ready(Ok(()));
+        self.refreshing = false;
+    }
+
+    /// Whether a suggestion is still being requested for the last refresh.
+    pub fn is_refreshing(&self) -> bool {
+        self.refreshing
+    }
+
+    /// Where accept and discard events are reported, unless the user has
+    /// turned them off for Ollama.
+    fn telemetry(&self, cx: &AppContext) -> Option<&Arc<Telemetry>> {
+        self.telemetry
+            .as_ref()
+            .filter(|_| OllamaSettings::get_global(cx).report_completion_events)
+    }
+
+    /// Reports the active suggestion as accepted, in full or in part, or as
+    /// discarded when `acceptance` is `None`, along with where it was among
+    /// the candidates cycled through.
+    fn report_completion_event(
+        &self,
+        acceptance: Option<InlineCompletionAcceptance>,
+        cx: &AppContext,
+    ) {
+        let (Some(telemetry), Some(completion)) = (self.telemetry(cx), self.active_completion())
+        else {
+            return;
+        };
+        telemetry.report_inline_completion(InlineCompletionEvent {
+            provider: Self::name().to_string(),
+            suggestion_accepted: acceptance.is_some(),
+            file_extension: self.file_extension.clone(),
+            metrics: completion
+                .metrics
+                .clone()
+                .filter(|_| OllamaSettings::get_global(cx).report_performance_metrics),
+            acceptance,
+            cycled: self.cycled,
+            candidate_index: Some(self.active_completion_index),
+            candidate_count: Some(self.completions.len()),
+        });
+    }
+
+    fn active_completion(&self) -> Option<&OllamaCompletion> {
+        self.completions.get(self.active_completion_index)
+    }
+
+    /// The suggestion showing, if it's `text` at `cursor_offset`. A new
+    /// suggestion that's the same updates it in place, so the editor isn't
+    /// asked to show it again and doesn't redraw it.
+    fn showing_mut(
+        &mut self,
+        buffer: &Model<Buffer>,
+        snapshot: &BufferSnapshot,
+        cursor_offset: usize,
+        text: &str,
+        edit_range: Option<&Range<Anchor>>,
+    ) -> Option<&mut OllamaCompletion> {
+        if Some(buffer.entity_id()) != self.buffer_id {
+            return None;
+        }
+        let offsets =
+            |range: &Range<Anchor>| range.start.to_offset(snapshot)..range.end.to_offset(snapshot);
+        self.completions
+            .get_mut(self.active_completion_index)
+            .filter(|completion| {
+                completion.text == text
+                    && completion.fallback.is_none()
+                    && completion.position.to_offset(snapshot) == cursor_offset
+                    && completion.edit_range.as_ref().map(offsets) == edit_range.map(offsets)
+            })
+    }
+
+    fn push_completion(
+        &mut self,
+        position: Anchor,
+        text: String,
+        edit_range: Option<Range<Anchor>>,
+        model: String,
+        metrics: Option<InlineCompletionMetrics>,
+        request_id: Option<RequestId>,
+    ) {
+        if self
+            .completions
+            .iter()
+            .any(|completion| completion.text == text)
+        {
+            return;
+        }
+        self.completions.push(OllamaCompletion {
+            position,
+            text,
+            edit_range,
+            verbatim_source: None,
+            fallback: None,
+            model: Some(model),
+            metrics,
+            request_id,
+        });
+    }
+
+    /// Replaces the suggestions with those for a new request in `buffer`,
+    /// which are pushed next.
+    fn show_suggestion(
+        &mut self,
+        buffer: &Model<Buffer>,
+        language: Option<String>,
+        cx: &mut ModelContext<Self>,
+    ) {
+        self.cycled = false;
+        self.pending_cycling_refresh = Task::ready(Ok(()));
+        self.completions.clear();
+        self.active_completion_index = 0;
+        self.buffer_id = Some(buffer.entity_id());
+        self.language = language;
+        self.file_extension = buffer.read(cx).file().and_then(|file| {
+            Some(
+                Path::new(file.file_name(cx))
+                    .extension()?
+                    .to_str()?
+                    .to_string(),
+            )
+        });
+    }
+
+    fn is_rename_buffer(&self, buffer: &Model<Buffer>) -> bool {
+        self.rename
+            .as_ref()
+            .is_some_and(|(buffer_id, _)| *buffer_id == buffer.entity_id())
+    }
+
+    /// Reads what the context placed at the start of a completion prompt for
+    /// the cursor at `cursor_offset` is made from: the file's Git changes, the
+    /// signatures of the files it imports, what its JSON schema allows there,
+    /// recent edits and the diagnostics on the cursor's line. Requests for the
+    /// first suggestion, prefetched ones and cycled ones all use this, so they
+    /// see the same prompt. The buffer's edits since it was last seen are
+    /// recorded here, so they're recorded in the order they were made.
+    fn prompt_context(
+        &self,
+        settings: &OllamaSettings,
+        buffer: &Buffer,
+        snapshot: &BufferSnapshot,
+        cursor_offset: usize,
+        cx: &AppContext,
+    ) -> PromptContextRequest {
+        observe_edits(settings, snapshot, cx);
+        let disabled_paths = settings.disabled_paths();
+        PromptContextRequest {
+            snapshot: snapshot.clone(),
+            cursor_offset,
+            git: GitContextRequest::new(settings, buffer, snapshot, self.project.as_ref(), cx),
+            imports: imports_request(settings, snapshot, self.project.as_ref(), cx),
+            schema: schema_request(
+                settings,
+                snapshot,
+                cursor_offset,
+                self.project.as_ref(),
+                &self.http_client,
+                &disabled_paths,
+                cx,
+            ),
+            recent_edits: (settings.recent_edits_tokens > 0)
+                .then(|| (EditHistory::global(cx), settings.recent_edits_tokens)),
+            include_diagnostics: settings.include_diagnostics,
+            disabled_paths,
+        }
+    }
+
+    /// Suggests how to finish the name typed in a rename editor, from how the
+    /// symbol being renamed is used.
+    fn refresh_rename(
+        &mut self,
+        buffer: Model<Buffer>,
+        cursor_position: Anchor,
+        debounce: bool,
+        cx: &mut ModelContext<Self>,
+    ) {
+        let Some((_, rename)) = self.rename.clone() else {
+            return;
+        };
+        let snapshot = buffer.read(cx).snapshot();
+        // Until something's typed, the old name is selected and there's
+        // nothing to complete.
+        if snapshot.text() == rename.old_name {
+            return;
+        }
+        let cursor_offset = cursor_position.to_offset(&snapshot);
+        if debounce && self.type_through(&buffer, &snapshot, cursor_offset, cx) {
+            return;
+        }
+        let typed = snapshot
+            .text_for_range(0..cursor_offset)
+            .collect::<String>();
+        let settings = OllamaSettings::get_global(cx);
+        let mut request = rename.request(
+            &typed,
+            settings.resolve_model(&self.settings.model),
+            settings.runtime_options(&self.settings.model),
+            settings.keep_alive_for(&self.settings.model),
+        );
+        redact_request(settings.secret_redactor().as_ref(), &mut request);
+        let cursor_position = snapshot.anchor_before(cursor_offset);
+        let http_client = http_client_for(settings, &self.http_client);
+        let endpoints = settings.endpoints();
+        let api_key = settings.effective_api_key();
+        let api_format = settings.api_format;
+        let pool = EndpointPool::global(cx);
+        let retry = RetryPolicy::default().with_max_attempts(settings.max_request_attempts);
+        let timeout = Duration::from_secs(settings.completion_timeout_secs);
+        let delay = debounce.then(|| Duration::from_millis(settings.debounce_min_ms));
+
+        self.cancel_refresh(cx);
+        self.refreshing = true;
+        self.pending_refresh = cx.spawn(|this, mut cx| async move {
+            if let Some(delay) = delay {
+                cx.background_executor().timer(delay).await;
+            }
+            let response = pool
+                .generate(
+                    http_client.as_ref(),
+                    &endpoints,
+                    api_key.as_deref(),
+                    api_format,
+                    request,
+                    retry,
+                    Some(timeout),
+                )
+                .await;
+            let stale = buffer.read_with(&cx, |buffer, _| {
+                buffer.version().changed_since(snapshot.version())
+            })?;
+            this.update(&mut cx, |this, cx| {
+                this.refreshing = false;
+                let rest = response.as_ref().ok().and_then(|response| {
+                    name_after_typed(&response.response, &typed)
+                        .map(|rest| (rest.to_string(), response.model.clone()))
+                });
+                if let Some((rest, model)) = rest.filter(|_| !stale) {
+                    this.cycled = false;
+                    this.completions.clear();
+                    this.active_completion_index = 0;
+                    this.buffer_id = Some(buffer.entity_id());
+                    this.language = None;
+                    this.push_completion(cursor_position, rest, None, model, None, None);
+                }
+                cx.notify();
+            })?;
+            response.map(drop)
+        });
+    }
+
+    /// Shows the part of a streamed suggestion generated so far in place of
+    /// the displayed suggestions, unless the buffer was edited since the
+    /// request was made.
+    fn show_partial(
+        &mut self,
+        buffer: &Model<Buffer>,
+        snapshot: &BufferSnapshot,
+        position: Anchor,
+        text: String,
+        model: String,
+        request_id: RequestId,
+        cx: &mut ModelContext<Self>,
+    ) {
+        if buffer.read(cx).version().changed_since(snapshot.version()) {
+            return;
+        }
+        self.cycled = false;
+        self.pending_cycling_refresh = Task::ready(Ok(()));
+        self.completions.clear();
+        self.active_completion_index = 0;
+        self.buffer_id = Some(buffer.entity_id());
+        self.push_completion(position, text, None, model, None, Some(request_id));
+        cx.notify();
+    }
+
+    /// Carries the active suggestion on past text typed since it was made
+    /// that matches its start, like accepting that much of it, so typing
+    /// along with a suggestion doesn't replace it with a new request.
+    /// Returns false once the typed text diverges from the suggestion.
+    fn type_through(
+        &mut self,
+        buffer: &Model<Buffer>,
+        snapshot: &BufferSnapshot,
+        cursor_offset: usize,
+        cx: &mut ModelContext<Self>,
+    ) -> bool {
+        if Some(buffer.entity_id()) != self.buffer_id {
+            return false;
+        }
+        let Some(completion) = self.active_completion() else {
+            return false;
+        };
+        if completion.edit_range.is_some() {
+            return false;
+        }
+        let Some(remainder) = typed_since(completion, snapshot, cursor_offset)
+            .filter(|typed| !typed.is_empty())
+            .and_then(|typed| remaining_after_typing(&completion.text, &typed))
+            .map(ToString::to_string)
+        else {
+            return false;
+        };
+
+        let Some(mut completion) = self.completions.drain(..).nth(self.active_completion_index)
+        else {
+            return false;
+        };
+        self.active_completion_index = 0;
+        self.cycled = false;
+        self.pending_cycling_refresh = Task::ready(Ok(()));
+        completion.text = remainder;
+        completion.position = snapshot.anchor_before(cursor_offset);
+        self.completions.push(completion);
+        cx.notify();
+        true
+    }
+
+    /// Once the cursor has rested at `cursor_offset` for a while, asks for
+    /// suggestions at the places it's likely to go next, one at a time so
+    /// they never hold more than one of the server's slots, and keeps them
+    /// to show as soon as the cursor gets there.
+    fn prefetch(
+        &mut self,
+        buffer: Model<Buffer>,
+        snapshot: BufferSnapshot,
+        cursor_offset: usize,
+        cx: &mut ModelContext<Self>,
+    ) {
+        let settings = OllamaSettings::for_file(snapshot.file(), cx);
+        let server = OllamaSettings::server_for_file(snapshot.file(), cx);
+        if !settings.prefetch_suggestions
+            || settings.edit_prediction
+            || settings.completion_trigger == CompletionTrigger::Manual
+            || !PowerState::global(cx).allows_prefetch(settings.low_power_mode)
+            || is_commit_message(snapshot.file())
+            || buffer.read(cx).version().changed_since(snapshot.version())
+        {
+            return;
+        }
+        let buffer_id = buffer.entity_id();
+        let version = snapshot.version().clone();
+        let positions = prefetch_positions(&snapshot, cursor_offset)
+            .into_iter()
+            .filter(|offset| {
+                !self.prefetched.contains(buffer_id, &version, *offset)
+                    && completions_allowed(
+                        buffer.read(cx),
+                        snapshot.anchor_before(*offset),
+                        self.project.as_ref(),
+                        cx,
+                    )
+            })
+            .collect::<Vec<_>>();
+        if positions.is_empty() {
+            return;
+        }
+
+        let path = snapshot.file().map_or("untitled".into(), |file| {
+            file.path().to_string_lossy().into_owned()
+        });
+        let http_client = http_client_for(server, &self.http_client);
+        let redactor = settings.secret_redactor().map(Arc::new);
+        let api_url = server.preferred_endpoint(cx);
+        let mut prefetches = Vec::new();
+        for offset in positions {
+            let language = language_name(&snapshot, offset);
+            let model_or_profile = settings
+                .model_for(language.as_deref())
+                .unwrap_or(&settings.model);
+            let context_tokens = ContextWindows::global(cx).context_tokens(
+                server,
+                &api_url,
+                model_or_profile,
+                &http_client,
+                cx,
+            );
+            let model = substitute_model(&api_url, settings.resolve_model(model_or_profile), cx);
+            let builder = prompt_builder(
+                settings,
+                model,
+                settings.completion_options(model_or_profile),
+                context_tokens,
+                ContextWindows::global(cx).supports_fim(&api_url, model),
+                language.as_deref(),
+                &snapshot,
+                offset,
+            )
+            .with_keep_alive(settings.keep_alive_for(model_or_profile));
+            let prompt_context =
+                self.prompt_context(settings, buffer.read(cx), &snapshot, offset, cx);
+            let extension_context = ExtensionContextRequest::new(
+                settings,
+                &snapshot,
+                offset,
+                language.as_deref(),
+                &path,
+                self.project.as_ref(),
+                cx,
+            );
+            let insertion_settings = InsertionSettings::read(&snapshot, offset, model, cx);
+            prefetches.push((
+                offset,
+                language,
+                builder,
+                prompt_context,
+                extension_context,
+                insertion_settings,
+            ));
+        }
+        let file_header_tokens = settings.file_header_tokens;
+        let endpoints = server.endpoints();
+        let api_key = server.effective_api_key();
+        let api_format = server.api_format;
+        let pool = EndpointPool::global(cx);
+        let connectivity = Connectivity::global(cx);
+        let retry = RetryPolicy::default().with_max_attempts(settings.max_request_attempts);
+        let timeout = Duration::from_secs(settings.completion_timeout_secs);
+
+        self.pending_prefetch = cx.spawn(|this, mut cx| async move {
+            cx.background_executor().timer(PREFETCH_DELAY).await;
+            for (
+                offset,
+                language,
+                builder,
+                prompt_context,
+                extension_context,
+                insertion_settings,
+            ) in prefetches
+            {
+                // Prefetching is never worth waking a server that's down or
+                // asking for a break.
+                if let Some(connectivity) = &connectivity {
+                    if connectivity.read_with(&cx, |connectivity, _| {
+                        connectivity.is_offline() || connectivity.is_throttled()
+                    })? {
+                        return Ok(());
+                    }
+                }
+                let extension_context = match extension_context {
+                    Some(request) => request.run(cx.background_executor().clone()).await,
+                    None => String::new(),
+                };
+                let (request, insertion) = cx
+                    .background_executor()
+                    .spawn({
+                        let snapshot = snapshot.clone();
+                        let path = path.clone();
+                        let redactor = redactor.clone();
+                        async move {
+                            let prompt = prompt_context_with_header(
+                                &builder,
+                                &snapshot,
+                                offset,
+                                language.as_deref().unwrap_or_default(),
+                                &path,
+                                prompt_context.build() + &extension_context,
+                                file_header_tokens,
+                            );
+                            let mut request = builder.fim_request(&prompt);
+                            request.priority = RequestPriority::Background;
+                            let insertion =
+                                insertion_settings.insertion_context(&snapshot, offset, &request);
+                            builder.apply_template(&mut request, &prompt);
+                            redact_request(redactor.as_deref(), &mut request);
+                            (request, insertion)
+                        }
+                    })
+                    .await;
+
+                let request_id = RequestId::next();
+                let sent = TraceEvent::Sent {
+                    model: request.model.clone(),
+                };
+                cx.update(|cx| {
+                    trace(request_id, TraceEvent::Requested { debounced: true }, cx);
+                    trace(request_id, sent, cx);
+                })?;
+                let start = Instant::now();
+                let response = pool
+                    .generate(
+                        http_client.as_ref(),
+                        &endpoints,
+                        api_key.as_deref(),
+                        api_format,
+                        request,
+                        retry,
+                        Some(timeout),
+                    )
+                    .await;
+                let latency = start.elapsed();
+                // Nobody asked for these suggestions, so failures aren't
+                // reported. The next refresh runs into them if they last.
+                if let Ok(response) = &response {
+                    response.record_token_usage(TokenUsageKind::Completion);
+                }
+                let (outcome, text) = match response {
+                    Ok(response) => match completion_text(&response, &insertion) {
+                        Ok(text) => (
+                            RequestOutcome::Prefetched(text.clone()),
+                            Some((text, response.model)),
+                        ),
+                        Err(outcome) => (outcome, None),
+                    },
+                    Err(error) => {
+                        log::debug!("prefetching an Ollama completion failed: {error:?}");
+                        (RequestOutcome::Failed(error.to_string()), None)
+                    }
+                };
+                let failed = matches!(outcome, RequestOutcome::Failed(_));
+                cx.update(|cx| {
+                    trace(
+                        request_id,
+                        TraceEvent::Finished {
+                            outcome: outcome.describe(),
+                            latency,
+                        },
+                        cx,
+                    )
+                })?;
+                if failed {
+                    return Ok(());
+                }
+                if let Some((text, model)) = text {
+                    this.update(&mut cx, |this, _| {
+                        this.prefetched.insert(PrefetchedSuggestion {
+                            buffer_id,
+                            version: version.clone(),
+                            offset,
+                            text,
+                            model,
+                            request_id,
+                        });
+                    })?;
+                }
+            }
+            Ok(())
+        });
+    }
+
+    /// Replaces the displayed suggestions with one made without the model.
+    fn show_fallback(
+        &mut self,
+        buffer: &Model<Buffer>,
+        position: Anchor,
+        prefix: &str,
+        cx: &mut ModelContext<Self>,
+    ) {
+        self.completions.clear();
+        self.active_completion_index = 0;
+        if let Some((text, source)) = fallback_completion(&self.cache, prefix) {
+            self.buffer_id = Some(buffer.entity_id());
+            self.completions.push(OllamaCompletion {
+                position,
+                text,
+                edit_range: None,
+                verbatim_source: None,
+                fallback: Some(source),
+                model: None,
+                metrics: None,
+                request_id: None,
+            });
+        }
+        cx.notify();
+    }
+}
+
+impl InlineCompletionProvider for OllamaCompletionProvider {
+    fn name() -> &'static str {
+        "ollama"
+    }
+
+    fn is_enabled(&self, buffer: &Model<Buffer>, cursor_position: Anchor, cx: &AppContext) -> bool {
+        if self.is_rename_buffer(buffer) {
+            return true;
+        }
+        let buffer = buffer.read(cx);
+        let ollama_settings = OllamaSettings::for_file(buffer.file(), cx);
+        if ollama_settings.completion_trigger == CompletionTrigger::Manual
+            || PowerState::global(cx).manual_only(ollama_settings.low_power_mode)
+            || !completions_allowed(buffer, cursor_position, self.project.as_ref(), cx)
+        {
+            return false;
+        }
+        let snapshot = buffer.snapshot();
+        let cursor_offset = cursor_position.to_offset(&snapshot);
+        let language = language_name(&snapshot, cursor_offset);
+        has_min_prefix(
+            &snapshot,
+            cursor_offset,
+            ollama_settings.min_prefix_chars,
+            ollama_settings.min_prefix_scope,
+        ) && !(ollama_settings.skip_mid_identifier_for(language.as_deref())
+            && is_mid_identifier(&snapshot, cursor_offset))
+    }
+
+    fn refresh(
+        &mut self,
+        buffer: Model<Buffer>,
+        cursor_position: Anchor,
+        mut debounce: bool,
+        cx: &mut ModelContext<Self>,
+    ) {
+        let chain_next = std::mem::take(&mut self.chain_next);
+        // Explicit requests aren't debounced. Completions chained after an
+        // accepted one may not be either, but nobody asked for those.
+        let priority = if debounce || chain_next {
+            RequestPriority::Automatic
+        } else {
+            RequestPriority::UserInitiated
+        };
+        if self.is_rename_buffer(&buffer) {
+            self.refresh_rename(buffer, cursor_position, debounce, cx);
+            return;
+        }
+        // Explicit requests skip `is_enabled`, so this is checked again
+        // before anything from the buffer is sent.
+        if !completions_allowed(buffer.read(cx), cursor_position, self.project.as_ref(), cx) {
+            return;
+        }
+        let snapshot = buffer.read(cx).snapshot();
+        let settings = OllamaSettings::for_file(snapshot.file(), cx);
+        let server = OllamaSettings::server_for_file(snapshot.file(), cx);
+        let cursor_offset = cursor_position.to_offset(&snapshot);
+        if chain_next && settings.chain_after_accept {
+            debounce = false;
+        }
+        if debounce && self.type_through(&buffer, &snapshot, cursor_offset, cx) {
+            return;
+        }
+        self.cancel_refresh(cx);
+        if let Some(prefetched) =
+            self.prefetched
+                .take(buffer.entity_id(), snapshot.version(), cursor_offset)
+        {
+            PerformanceReport::global(cx).record_cache_hit(&prefetched.model);
+            self.show_suggestion(&buffer, language_name(&snapshot, cursor_offset), cx);
+            self.push_completion(
+                snapshot.anchor_before(cursor_offset),
+                prefetched.text,
+                None,
+                prefetched.model,
+                None,
+                Some(prefetched.request_id),
+            );
+            cx.notify();
+            self.prefetch(buffer, snapshot, cursor_offset, cx);
+            return;
+        }
+        let request_id = RequestId::next();
+        self.request_id = Some(request_id);
+        trace(
+            request_id,
+            TraceEvent::Requested {
+                debounced: debounce,
+            },
+            cx,
+        );
+        // The editor's cursor moves along with text typed at it. The
+        // suggestion's position mustn't, so what's typed after it can be
+        // compared with the suggestion.
+        let cursor_position = snapshot.anchor_before(cursor_offset);
+        let language = language_name(&snapshot, cursor_offset);
+        let language_model = settings.model_for(language.as_deref());
+        let comparison_model = settings
+            .comparison_model
+            .as_deref()
+            .filter(|_| language_model.is_none());
+        let mut race_model = None;
+        let mut draft_model = None;
+        let mut alternate_model = None;
+        if let Some(comparison_model) = comparison_model {
+            match settings.comparison_strategy {
+                ComparisonStrategy::Alternate => {
+                    if self.comparison_turn {
+                        alternate_model = Some(comparison_model);
+                    }
+                    self.comparison_turn = !self.comparison_turn;
+                }
+                ComparisonStrategy::Race => race_model = Some(comparison_model),
+                ComparisonStrategy::Speculative => draft_model = Some(comparison_model),
+            }
+        }
+        let model_or_profile = alternate_model
+            .or(language_model)
+            .unwrap_or(&settings.model);
+        let http_client = http_client_for(server, &self.http_client);
+        // Context windows and substitutes are looked up on the server the
+        // request is sent to first.
+        let api_url = server.preferred_endpoint(cx);
+        let context_tokens = ContextWindows::global(cx).context_tokens(
+            server,
+            &api_url,
+            model_or_profile,
+            &http_client,
+            cx,
+        );
+        let options = settings.completion_options(model_or_profile);
+        let keep_alive = settings.keep_alive_for(model_or_profile);
+        let requested_model = settings.resolve_model(model_or_profile);
+        // Until a model the server was missing is pulled, an installed one
+        // stands in for it.
+        let model = substitute_model(&api_url, requested_model, cx);
+        let requested_model = requested_model.to_string();
+        // Commit messages are written from the staged changes, which are
+        // read along with the rest of the prompt, instead of the context
+        // code gets.
+        let commit_repository = is_commit_message(snapshot.file())
+            .then(|| {
+                let project = self.project.as_ref()?.upgrade()?;
+                file_repository(project.read(cx), snapshot.file()?, cx)
+            })
+            .flatten();
+        let prompt_context = commit_repository
+            .is_none()
+            .then(|| self.prompt_context(settings, buffer.read(cx), &snapshot, cursor_offset, cx));
+        let edit_memory = commit_repository
+            .is_none()
+            .then(|| edit_memory_request(settings, &snapshot, cursor_offset, cx))
+            .flatten();
+        let path = snapshot.file().map_or("untitled".into(), |file| {
+            file.path().to_string_lossy().into_owned()
+        });
+        let extension_context = commit_repository
+            .is_none()
+            .then(|| {
+                ExtensionContextRequest::new(
+                    settings,
+                    &snapshot,
+                    cursor_offset,
+                    language.as_deref(),
+                    &path,
+                    self.project.as_ref(),
+                    cx,
+                )
+            })
+            .flatten();
+        let builder = prompt_builder(
+            settings,
+            model,
+            options.clone(),
+            context_tokens,
+            ContextWindows::global(cx).supports_fim(&api_url, model),
+            language.as_deref(),
+            &snapshot,
+            cursor_offset,
+        )
+        .with_keep_alive(keep_alive.clone());
+        let predict_edits = settings.edit_prediction;
+        let reuse_context = !predict_edits
+            && race_model.is_none()
+            && draft_model.is_none()
+            && server.api_format == ApiFormat::Ollama;
+        let reusable_context = self.reusable_context.clone().filter(|_| reuse_context);
+        // Streaming, like reusing context, needs a single request to Ollama's
+        // own API whose response is shown as it is.
+        let stream_suggestions = settings.stream_suggestions && reuse_context;
+        let insertion_settings = InsertionSettings::read(&snapshot, cursor_offset, model, cx);
+        let redactor = settings.secret_redactor();
+        let request_log = RequestLog::global(cx).filter(|log| log.read(cx).is_recording());
+        let race_model =
+            race_model.map(|race_model| settings.resolve_model(race_model).to_string());
+        let draft_model =
+            draft_model.map(|draft_model| settings.resolve_model(draft_model).to_string());
+        let model = model.to_string();
+        let system_prompt = settings.system_prompt_for(&model).map(ToString::to_string);
+        let file_header_tokens = settings.file_header_tokens;
+        let cache_entries = settings.completion_cache_entries;
+        // Building the prompt goes through the text around the cursor, which
+        // is too slow for the main thread in large files.
+        let build_request = {
+            let snapshot = snapshot.clone();
+            let language = language.clone();
+            move |edit_memory: String, extension_context: String| {
+                let prompt_context = match commit_repository {
+                    Some(repository) => staged_changes_context(
+                        &repository.staged_diff().unwrap_or_default(),
+                        MAX_COMMIT_DIFF_TOKENS,
+                    ),
+                    None => {
+                        prompt_context
+                            .map(PromptContextRequest::build)
+                            .unwrap_or_default()
+                            + &edit_memory
+                            + &extension_context
+                    }
+                };
+                let prompt = prompt_context_with_header(
+                    &builder,
+                    &snapshot,
+                    cursor_offset,
+                    language.as_deref().unwrap_or_default(),
+                    &path,
+                    prompt_context,
+                    file_header_tokens,
+                );
+                let mut request = if predict_edits {
+                    let mut request = edit_prediction_request(
+                        &model,
+                        &snapshot,
+                        &path,
+                        cursor_offset,
+                        &prompt.extra_context,
+                        options,
+                    );
+                    request.system = system_prompt;
+                    request.keep_alive = keep_alive;
+                    request
+                } else {
+                    builder.fim_request(&prompt)
+                };
+                let prefix = request.prompt.clone();
+                let insertion =
+                    insertion_settings.insertion_context(&snapshot, cursor_offset, &request);
+                if !predict_edits {
+                    builder.apply_template(&mut request, &prompt);
+                }
+                let cache_key =
+                    (!predict_edits && cache_entries > 0).then(|| request_key(&request));
+                if let Some(context) = &reusable_context {
+                    context.reuse(&snapshot, cursor_offset, &mut request, context_tokens);
+                }
+                redact_request(redactor.as_ref(), &mut request);
+                request.priority = priority;
+                (request, prefix, insertion, cache_key)
+            }
+        };
+        let speculative_grace = Duration::from_millis(settings.speculative_grace_ms);
+        let project = self.project.clone();
+        let endpoints = server.endpoints();
+        let api_key = server.effective_api_key();
+        let api_format = server.api_format;
+        let pool = EndpointPool::global(cx);
+        let connectivity = Connectivity::global(cx);
+        let error_reports = ErrorReports::global(cx);
+        let substitutes = ModelSubstitutes::global(cx);
+        let verbatim_check = VerbatimCheck::new(settings);
+        let continue_truncated = settings.continue_truncated && !predict_edits;
+        let offline_fallback = settings.offline_fallback;
+        let persistent_cache = PersistentCache::global(cx);
+        let performance = PerformanceReport::global(cx);
+        let retry = RetryPolicy::default().with_max_attempts(settings.max_request_attempts);
+        let timeout = Duration::from_secs(settings.completion_timeout_secs);
+        let delay = debounce.then(|| {
+            let now = Instant::now();
+            self.debounce.record_keystroke(now);
+            self.debounce.delay(
+                now,
+                Duration::from_millis(settings.debounce_min_ms),
+                Duration::from_millis(settings.debounce_max_ms),
+            )
+        });
+        let prefetch_buffer = buffer.clone();
+        let prefetch_snapshot = snapshot.clone();
+
+        let refresh = cx.spawn(|this, mut cx| async move {
+            if let Some(delay) = delay {
+                cx.background_executor().timer(delay).await;
+            }
+
+            // Extensions and the edit memory are asked at the same time.
+            let executor = cx.background_executor().clone();
+            let extension_context = extension_context.map(|request| request.run(executor.clone()));
+            let edit_memory = edit_memory.map(|request| {
+                request.run(
+                    http_client.clone(),
+                    api_url.clone(),
+                    api_key.clone(),
+                    executor.clone(),
+                )
+            });
+            let (extension_context, edit_memory) = future::join(
+                future::OptionFuture::from(extension_context),
+                future::OptionFuture::from(edit_memory),
+            )
+            .await;
+            let (request, prefix, insertion, cache_key) = executor
+                .spawn(async move {
+                    build_request(
+                        edit_memory.unwrap_or_default(),
+                        extension_context.unwrap_or_default(),
+                    )
+                })
+                .await;
+            let sent_request = reuse_context.then(|| request.clone());
+            let logged_request = request_log.map(|log| (log, request.clone()));
+            // The same request may have been answered before, perhaps in an
+            // earlier session, and its suggestion is shown without asking
+            // the model again.
+            if let Some(text) = cache_key
+                .as_deref()
+                .and_then(|key| persistent_cache.get(key))
+            {
+                performance.record_cache_hit(&request.model);
+                let stale = buffer.read_with(&cx, |buffer, _| {
+                    buffer.version().changed_since(snapshot.version())
+                })?;
+                let outcome = if stale {
+                    RequestOutcome::Stale
+                } else {
+                    RequestOutcome::Cached(text.clone())
+                };
+                log_request(
+                    request_id,
+                    &logged_request,
+                    None,
+                    Duration::ZERO,
+                    outcome,
+                    &mut cx,
+                );
+                if !stale {
+                    this.update(&mut cx, |this, cx| {
+                        this.cache.insert(&prefix, text.clone());
+                        if let Some(showing) =
+                            this.showing_mut(&buffer, &snapshot, cursor_offset, &text, None)
+                        {
+                            showing.model = Some(request.model);
+                            showing.request_id = Some(request_id);
+                            return;
+                        }
+                        this.show_suggestion(&buffer, language, cx);
+                        this.push_completion(
+                            cursor_position,
+                            text.clone(),
+                            None,
+                            request.model,
+                            None,
+                            Some(request_id),
+                        );
+                        cx.notify();
+                    })?;
+                    return annotate_verbatim_source(
+                        &this,
+                        project,
+                        &buffer,
+                        &text,
+                        verbatim_check,
+                        &mut cx,
+                    )
+                    .await;
+                }
+                return Ok(());
+            }
+            let race_request = race_model.map(|model| GenerateRequest {
+                model,
+                ..request.clone()
+            });
+            let draft_request = draft_model.map(|model| GenerateRequest {
+                model,
+                ..request.clone()
+            });
+            let continuation_request = continue_truncated.then(|| request.clone());
+            let repair_request = predict_edits.then(|| request.clone());
+
+            // While the servers are unreachable or asking for a break, typing
+            // shouldn't send a request per keystroke. An explicit request
+            // still goes out, and brings completions back if it gets through.
+            if let Some(connectivity) = connectivity.as_ref().filter(|_| delay.is_some()) {
+                if connectivity.read_with(&cx, |connectivity, _| {
+                    connectivity.is_offline() || connectivity.is_throttled()
+                })? {
+                    cx.update(|cx| trace(request_id, TraceEvent::Skipped, cx))?;
+                    if offline_fallback {
+                        this.update(&mut cx, |this, cx| {
+                            this.show_fallback(&buffer, cursor_position, &prefix, cx);
+                        })?;
+                    }
+                    return Ok(());
+                }
+            }
+
+            let start = Instant::now();
+            let requested_model = request.model.clone();
+            performance.record_request(&requested_model);
+            let sent = TraceEvent::Sent {
+                model: requested_model.clone(),
+            };
+            this.update(&mut cx, |this, cx| {
+                this.debounce.record_request(start);
+                trace(request_id, sent, cx);
+            })?;
+            let generate = |request| {
+                pool.generate(
+                    http_client.as_ref(),
+                    &endpoints,
+                    api_key.as_deref(),
+                    api_format,
+                    request,
+                    retry,
+                    Some(timeout),
+                )
+            };
+            let mut verification = None;
+            let response = match (race_request, draft_request) {
+                (Some(race_request), _) => {
+                    // A response that post-processing would throw away
+                    // doesn't win the race.
+                    let usable = |response: &GenerateResponse| {
+                        suggestion(
+                            response,
+                            predict_edits,
+                            &snapshot,
+                            cursor_offset,
+                            &insertion,
+                        )
+                        .is_ok()
+                    };
+                    first_usable(generate(request), generate(race_request), usable).await
+                }
+                (None, Some(draft_request)) => {
+                    let (response, pending) =
+                        speculate(generate(request), generate(draft_request)).await;
+                    verification = pending;
+                    response
+                }
+                (None, None) if stream_suggestions => {
+                    let url = pool
+                        .ranked(&endpoints)
+                        .into_iter()
+                        .next()
+                        .unwrap_or_default();
+                    let mut shown = String::new();
+                    let stream = stream_generate(
+                        http_client.as_ref(),
+                        &url,
+                        api_key.as_deref(),
+                        request,
+                        Some(timeout),
+                    )
+                    .await;
+                    let response = match stream {
+                        Ok(stream) => {
+                            collect_stream(stream, |partial| {
+                                // Only updates that add to what's shown are
+                                // shown, so the editor can append them.
+                                let Ok((text, _)) = suggestion(
+                                    partial,
+                                    false,
+                                    &snapshot,
+                                    cursor_offset,
+                                    &insertion,
+                                ) else {
+                                    return;
+                                };
+                                if text.len() <= shown.len() || !text.starts_with(&shown) {
+                                    return;
+                                }
+                                shown = text.clone();
+                                this.update(&mut cx, |this, cx| {
+                                    this.show_partial(
+                                        &buffer,
+                                        &snapshot,
+                                        cursor_position,
+                                        text,
+                                        partial.model.clone(),
+                                        request_id,
+                                        cx,
+                                    );
+                                })
+                                .ok();
+                            })
+                            .await
+                        }
+                        Err(error) => Err(error),
+                    };
+                    match &response {
+                        Ok(_) => pool.record_success(&url, start.elapsed()),
+                        Err(_) => pool.record_failure(&url),
+                    }
+                    response
+                }
+                (None, None) => generate(request).await,
+            };
+            if let Some(connectivity) = &connectivity {
+                // Any answer, even an error status, means the server is up.
+                connectivity.update(&mut cx, |connectivity, cx| {
+                    match response.as_ref().map_err(OllamaError::of) {
+                        Err(Some(OllamaError::ConnectionRefused { .. })) => {
+                            connectivity.record_unreachable(cx)
+                        }
+                        Err(Some(OllamaError::Throttled { retry_after })) => {
+                            connectivity.record_throttled(*retry_after, cx)
+                        }
+                        Err(Some(OllamaError::UsageLimitReached(_))) => {
+                            connectivity.record_throttled(None, cx)
+                        }
+                        Err(Some(OllamaError::Timeout(_))) => {}
+                        _ => connectivity.record_success(cx),
+                    }
+                })?;
+            }
+            let response = match response {
+                Ok(response) => response,
+                Err(error) if matches!(OllamaError::of(&error), Some(OllamaError::Timeout(_))) => {
+                    // A slow model isn't an error worth reporting; drop the
+                    // stale suggestion so the next keystroke can try again.
+                    log::debug!("{error}");
+                    performance.record_timeout(&requested_model);
+                    log_request(
+                        request_id,
+                        &logged_request,
+                        None,
+                        start.elapsed(),
+                        RequestOutcome::TimedOut,
+                        &mut cx,
+                    );
+                    this.update(&mut cx, |this, cx| {
+                        this.debounce.record_latency(start.elapsed());
+                        this.completions.clear();
+                        cx.notify();
+                    })?;
+                    return Ok(());
+                }
+                Err(error) => {
+                    // Errors the user can fix are shown to them instead.
+                    match OllamaError::of(&error) {
+                        Some(error) if error.is_actionable() => log::warn!("{error}"),
+                        Some(
+                            OllamaError::ConnectionRefused { .. } | OllamaError::Throttled { .. },
+                        ) => {
+                            log::debug!("Ollama completion request failed: {error:?}")
+                        }
+                        _ => log::error!("Ollama completion request failed: {error:?}"),
+                    }
+                    log_request(
+                        request_id,
+                        &logged_request,
+                        None,
+                        start.elapsed(),
+                        RequestOutcome::Failed(error.to_string()),
+                        &mut cx,
+                    );
+                    // A missing model is only reported when no installed one
+                    // can stand in for it.
+                    let missing_model = matches!(
+                        OllamaError::of(&error),
+                        Some(OllamaError::ModelNotFound { .. })
+                    );
+                    match (&substitutes, &error_reports) {
+                        (Some(substitutes), _) if missing_model => {
+                            substitutes.update(&mut cx, |substitutes, cx| {
+                                substitutes.find_substitute(
+                                    http_client.clone(),
+                                    api_url.clone(),
+                                    api_key.clone(),
+                                    requested_model.clone(),
+                                    error,
+                                    cx,
+                                )
+                            })?
+                        }
+                        (_, Some(error_reports)) => error_reports
+                            .update(&mut cx, |reports, cx| reports.report(&error, cx))?,
+                        _ => {}
+                    }
+                    let stale = buffer.read_with(&cx, |buffer, _| {
+                        buffer.version().changed_since(snapshot.version())
+                    })?;
+                    if offline_fallback && !stale {
+                        this.update(&mut cx, |this, cx| {
+                            this.show_fallback(&buffer, cursor_position, &prefix, cx);
+                        })?;
+                    }
+                    return Ok(());
+                }
+            };
+
+            let latency = start.elapsed();
+            if let Some(error_reports) = &error_reports {
+                error_reports.update(&mut cx, |reports, cx| reports.record_success(cx))?;
+            }
+            this.update(&mut cx, |this, cx| {
+                this.debounce.record_latency(latency);
+                CompletionStats::global(cx).record_latency(&response.model, latency, cx);
+                performance.record_latency(&response.model, latency);
+                response.record_token_usage(TokenUsageKind::Completion);
+                if let Some(speed) = GenerationSpeed::global(cx) {
+                    speed.update(cx, |speed, cx| speed.record(&response, latency, cx));
+                }
+                this.reusable_context = sent_request.and_then(|request| {
+                    ReusableContext::new(&snapshot, cursor_offset, &request, &response)
+                });
+            })?;
+
+            // A draft is compared with the verifying model's suggestion, so
+            // it's left as it is.
+            let response = match continuation_request {
+                Some(request) if verification.is_none() && is_truncated(&response) => {
+                    continue_generation(response, request, generate).await
+                }
+                _ => response,
+            };
+            let response = match repair_request {
+                Some(request) if verification.is_none() => {
+                    repair_predicted_edit(response, request, generate).await
+                }
+                _ => response,
+            };
+
+            let (text, edit_range) = match suggestion(
+                &response,
+                predict_edits,
+                &snapshot,
+                cursor_offset,
+                &insertion,
+            ) {
+                Ok(suggestion) => suggestion,
+                Err(outcome) => {
+                    log_request(
+                        request_id,
+                        &logged_request,
+                        Some(&response),
+                        latency,
+                        outcome,
+                        &mut cx,
+                    );
+                    return Ok(());
+                }
+            };
+            let metrics = Some(completion_metrics(&response, latency));
+            let model = response.model.clone();
+
+            // The suggestion was made for the text at the time of the request.
+            // If the buffer was edited since, it would no longer fit there.
+            if buffer.read_with(&cx, |buffer, _| {
+                buffer.version().changed_since(snapshot.version())
+            })? {
+                log_request(
+                    request_id,
+                    &logged_request,
+                    Some(&response),
+                    latency,
+                    RequestOutcome::Stale,
+                    &mut cx,
+                );
+                return Ok(());
+            }
+            log_request(
+                request_id,
+                &logged_request,
+                Some(&response),
+                latency,
+                RequestOutcome::Shown(text.clone()),
+                &mut cx,
+            );
+
+            this.update(&mut cx, |this, cx| {
+                if edit_range.is_none() {
+                    this.cache.insert(&prefix, text.clone());
+                }
+                if let Some(key) = cache_key.clone().filter(|_| edit_range.is_none()) {
+                    persistent_cache.insert(key, text.clone(), cache_entries, cx);
+                }
+                if let Some(showing) = this.showing_mut(
+                    &buffer,
+                    &snapshot,
+                    cursor_offset,
+                    &text,
+                    edit_range.as_ref(),
+                ) {
+                    showing.model = Some(model);
+                    showing.metrics = metrics;
+                    showing.request_id = Some(request_id);
+                    return;
+                }
+                this.show_suggestion(&buffer, language, cx);
+                this.push_completion(
+                    cursor_position,
+                    text.clone(),
+                    edit_range,
+                    model,
+                    metrics,
+                    Some(request_id),
+                );
+                cx.notify();
+            })?;
+
+            // A draft stays up until the verifying model answers, or until the
+            // grace window runs out, which also cancels its request.
+            let mut text = text;
+            if let Some(verification) = verification {
+                let response =
+                    match future::select(verification, smol::Timer::after(speculative_grace)).await
+                    {
+                        Either::Left((Ok(response), _)) => Some(response),
+                        Either::Left((Err(error), _)) => {
+                            log::debug!("verifying completion request failed: {error:?}");
+                            None
+                        }
+                        Either::Right(_) => {
+                            log::debug!("verifying model didn't answer within the grace window");
+                            None
+                        }
+                    };
+                if let Some(response) = response {
+                    let latency = start.elapsed();
+                    let verified = suggestion(
+                        &response,
+                        predict_edits,
+                        &snapshot,
+                        cursor_offset,
+                        &insertion,
+                    )
+                    .ok()
+                    .filter(|(verified, _)| differs_meaningfully(&text, verified));
+                    let stale = buffer.read_with(&cx, |buffer, _| {
+                        buffer.version().changed_since(snapshot.version())
+                    })?;
+                    if let Some((verified, edit_range)) = verified.filter(|_| !stale) {
+                        let replaced = this.update(&mut cx, |this, cx| {
+                            CompletionStats::global(cx).record_latency(
+                                &response.model,
+                                latency,
+                                cx,
+                            );
+                            // Leave the draft alone once the user has moved on
+                            // from it, by cycling or dismissing it.
+                            let showing_draft = !this.cycled
+                                && this.completions.len() == 1
+                                && this.completions[0].text == text;
+                            if showing_draft {
+                                this.completions.clear();
+                                if edit_range.is_none() {
+                                    this.cache.insert(&prefix, verified.clone());
+                                }
+                                if let Some(key) =
+                                    cache_key.clone().filter(|_| edit_range.is_none())
+                                {
+                                    persistent_cache.insert(
+                                        key,
+                                        verified.clone(),
+                                        cache_entries,
+                                        cx,
+                                    );
+                                }
+                                let metrics = Some(completion_metrics(&response, latency));
+                                this.push_completion(
+                                    cursor_position,
+                                    verified.clone(),
+                                    edit_range,
+                                    response.model.clone(),
+                                    metrics,
+                                    Some(request_id),
+                                );
+                                cx.notify();
+                            }
+                            showing_draft
+                        })?;
+                        if replaced {
+                            log_request(
+                                request_id,
+                                &logged_request,
+                                Some(&response),
+                                latency,
+                                RequestOutcome::Shown(verified.clone()),
+                                &mut cx,
+                            );
+                            text = verified;
+                        }
+                    }
+                }
+            }
+
+            annotate_verbatim_source(&this, project, &buffer, &text, verbatim_check, &mut cx).await
+        });
+        self.refreshing = true;
+        self.pending_refresh = cx.spawn(|this, mut cx| async move {
+            let result = refresh.await;
+            this.update(&mut cx, |this, cx| {
+                this.refreshing = false;
+                this.request_id = None;
+                this.prefetch(prefetch_buffer, prefetch_snapshot, cursor_offset, cx);
+                cx.notify();
+            })?;
+            result
+        });
+    }
+
+    fn cycle(
+        &mut self,
+        buffer: Model<Buffer>,
+        cursor_position: Anchor,
+        direction: Direction,
+        cx: &mut ModelContext<Self>,
+    ) {
+        if self.is_rename_buffer(&buffer) {
+            return;
+        }
+        if self.cycled {
+            match direction {
+                Direction::Prev => {
+                    self.active_completion_index = if self.active_completion_index == 0 {
+                        self.completions.len().saturating_sub(1)
+                    } else {
+                        self.active_completion_index - 1
+                    };
+                }
+                Direction::Next => {
+                    if self.completions.is_empty() {
+                        self.active_completion_index = 0
+                    } else {
+                        self.active_completion_index =
+                            (self.active_completion_index + 1) % self.completions.len();
+                    }
+                }
+            }
+
+            cx.notify();
+            return;
+        }
+
+        if !completions_allowed(buffer.read(cx), cursor_position, self.project.as_ref(), cx) {
+            return;
+        }
+        let snapshot = buffer.read(cx).snapshot();
+        let settings = OllamaSettings::for_file(snapshot.file(), cx);
+        let server = OllamaSettings::server_for_file(snapshot.file(), cx);
+        let cursor_offset = cursor_position.to_offset(&snapshot);
+        // Anchored like the suggestions from `refresh`.
+        let cursor_position = snapshot.anchor_before(cursor_offset);
+        let language = language_name(&snapshot, cursor_offset);
+        let model_or_profile = settings
+            .model_for(language.as_deref())
+            .unwrap_or(&settings.model);
+        let http_client = http_client_for(server, &self.http_client);
+        let api_url = server.preferred_endpoint(cx);
+        let context_tokens = ContextWindows::global(cx).context_tokens(
+            server,
+            &api_url,
+            model_or_profile,
+            &http_client,
+            cx,
+        );
+        let options = settings.completion_options(model_or_profile);
+        let keep_alive = settings.keep_alive_for(model_or_profile);
+        let model = substitute_model(&api_url, settings.resolve_model(model_or_profile), cx);
+        let prompt_context =
+            self.prompt_context(settings, buffer.read(cx), &snapshot, cursor_offset, cx);
+        let path = snapshot.file().map_or("untitled".into(), |file| {
+            file.path().to_string_lossy().into_owned()
+        });
+        let builder = prompt_builder(
+            settings,
+            model,
+            options,
+            context_tokens,
+            ContextWindows::global(cx).supports_fim(&api_url, model),
+            language.as_deref(),
+            &snapshot,
+            cursor_offset,
+        )
+        .with_temperature(CYCLING_TEMPERATURE)
+        .with_keep_alive(keep_alive);
+        let insertion_settings = InsertionSettings::read(&snapshot, cursor_offset, model, cx);
+        let redactor = settings.secret_redactor();
+        let file_header_tokens = settings.file_header_tokens;
+        // Like `refresh`'s, the prompt is built in the background.
+        let build_requests = {
+            let snapshot = snapshot.clone();
+            move || {
+                let prompt = prompt_context_with_header(
+                    &builder,
+                    &snapshot,
+                    cursor_offset,
+                    language.as_deref().unwrap_or_default(),
+                    &path,
+                    prompt_context.build(),
+                    file_header_tokens,
+                );
+                // Cycling is always asked for, so its requests go ahead of others.
+                let mut requests = (0..CYCLING_CANDIDATES)
+                    .map(|_| GenerateRequest {
+                        priority: RequestPriority::UserInitiated,
+                        ..builder.fim_request(&prompt)
+                    })
+                    .collect::<Vec<_>>();
+                let insertion =
+                    insertion_settings.insertion_context(&snapshot, cursor_offset, &requests[0]);
+                for request in &mut requests {
+                    builder.apply_template(request, &prompt);
+                    redact_request(redactor.as_ref(), request);
+                }
+                (requests, insertion)
+            }
+        };
+        let project = self.project.clone();
+        let endpoints = server.endpoints();
+        let api_key = server.effective_api_key();
+        let api_format = server.api_format;
+        let pool = EndpointPool::global(cx);
+        let verbatim_check = VerbatimCheck::new(settings);
+        let error_reports = ErrorReports::global(cx);
+        let retry = RetryPolicy::default().with_max_attempts(settings.max_request_attempts);
+        let timeout = Duration::from_secs(settings.completion_timeout_secs);
+        let version = snapshot.version().clone();
+        let model = model.to_string();
+        // Each alternative is a request of its own, traced from when it's sent.
+        let request_ids = (0..CYCLING_CANDIDATES)
+            .map(|_| {
+                let request_id = RequestId::next();
+                trace(request_id, TraceEvent::Requested { debounced: false }, cx);
+                trace(
+                    request_id,
+                    TraceEvent::Sent {
+                        model: model.clone(),
+                    },
+                    cx,
+                );
+                request_id
+            })
+            .collect::<Vec<_>>();
+
+        self.pending_cycling_refresh = cx.spawn(|this, mut cx| async move {
+            let (requests, insertion) = cx
+                .background_executor()
+                .spawn(async move { build_requests() })
+                .await;
+            let responses = future::join_all(requests.into_iter().map(|request| async {
+                let start = Instant::now();
+                let response = pool
+                    .generate(
+                        http_client.as_ref(),
+                        &endpoints,
+                        api_key.as_deref(),
+                        api_format,
+                        request,
+                        retry,
+                        Some(timeout),
+                    )
+                    .await;
+                (response, start.elapsed())
+            }))
+            .await;
+
+            let mut candidates = Vec::new();
+            let mut finished = Vec::new();
+            for ((response, latency), request_id) in responses.into_iter().zip(request_ids) {
+                if let Ok(response) = &response {
+                    response.record_token_usage(TokenUsageKind::Completion);
+                }
+                let outcome = match response {
+                    Ok(response) => match completion_text(&response, &insertion) {
+                        Ok(text) => {
+                            let metrics = Some(completion_metrics(&response, latency));
+                            candidates.push((text.clone(), response.model, metrics, request_id));
+                            RequestOutcome::Shown(text)
+                        }
+                        Err(outcome) => outcome,
+                    },
+                    Err(error)
+                        if matches!(OllamaError::of(&error), Some(OllamaError::Timeout(_))) =>
+                    {
+                        log::debug!("{error}");
+                        RequestOutcome::TimedOut
+                    }
+                    Err(error) => {
+                        log::error!("Ollama completion request failed: {error:?}");
+                        if let Some(error_reports) = &error_reports {
+                            error_reports
+                                .update(&mut cx, |reports, cx| reports.report(&error, cx))?;
+                        }
+                        RequestOutcome::Failed(error.to_string())
+                    }
+                };
+                finished.push((
+                    request_id,
+                    TraceEvent::Finished {
+                        outcome: outcome.describe(),
+                        latency,
+                    },
+                ));
+            }
+            cx.update(|cx| {
+                for (request_id, event) in finished {
+                    trace(request_id, event, cx);
+                }
+            })?;
+            if let Some(error_reports) = error_reports.as_ref().filter(|_| !candidates.is_empty()) {
+                error_reports.update(&mut cx, |reports, cx| reports.record_success(cx))?;
+            }
+            let texts = candidates
+                .iter()
+                .map(|(text, _, _, _)| text.clone())
+                .collect::<Vec<_>>();
+            if buffer.read_with(&cx, |buffer, _| buffer.version().changed_since(&version))? {
+                return Ok(());
+            }
+
+            this.update(&mut cx, |this, cx| {
+                this.cycled = true;
+                for (text, model, metrics, request_id) in candidates {
+                    this.push_completion(
+                        cursor_position,
+                        text,
+                        None,
+                        model,
+                        metrics,
+                        Some(request_id),
+                    );
+                }
+                this.cycle(buffer.clone(), cursor_position, direction, cx);
+            })?;
+
+            for text in texts {
+                annotate_verbatim_source(
+                    &this,
+                    project.clone(),
+                    &buffer,
+                    &text,
+                    verbatim_check,
+                    &mut cx,
+                )
+                .await?;
+            }
+
+            Ok(())
+        });
+    }
+
+    fn accept(&mut self, cx: &mut ModelContext<Self>) {
+        if let Some(completion) = self.active_completion() {
+            if let Some(request_id) = completion.request_id {
+                trace(request_id, TraceEvent::Accepted, cx);
+            }
+            if let Some(model) = completion.model.as_deref() {
+                CompletionStats::global(cx).record(model, self.language.as_deref(), true, cx);
+            }
+        }
+        self.report_completion_event(Some(InlineCompletionAcceptance::Full), cx);
+        self.cancel_refresh(cx);
+        self.pending_cycling_refresh = Task::ready(Ok(()));
+        self.completions.clear();
+        self.active_completion_index = 0;
+        self.chain_next = true;
+    }
+
+    fn accepted_format(&self, buffer: &Model<Buffer>, cx: &AppContext) -> AcceptedCompletionFormat {
+        match OllamaSettings::for_file(buffer.read(cx).file(), cx).format_accepted {
+            AcceptedFormatting::Off => AcceptedCompletionFormat::None,
+            AcceptedFormatting::Indent => AcceptedCompletionFormat::Indent,
+            AcceptedFormatting::Format => AcceptedCompletionFormat::Format,
+        }
+    }
+
+    fn accept_partial(
+        &mut self,
+        accepted_text: &str,
+        kind: PartialAccept,
+        buffer: &Model<Buffer>,
+        cursor_position: Anchor,
+        cx: &mut ModelContext<Self>,
+    ) -> bool {
+        if Some(buffer.entity_id()) != self.buffer_id {
+            return false;
+        }
+        let Some(completion) = self
+            .active_completion()
+            .filter(|completion| completion.edit_range.is_none())
+        else {
+            return false;
+        };
+        // The accepted text follows whatever was typed of the suggestion
+        // before, which the suggestion shown had already left out.
+        let snapshot = buffer.read(cx).snapshot();
+        let Some(typed) = typed_since(completion, &snapshot, cursor_position.to_offset(&snapshot))
+            .filter(|typed| typed.ends_with(accepted_text) && completion.text.starts_with(typed))
+        else {
+            return false;
+        };
+        let remainder = remaining_after_typing(&completion.text, &typed).map(ToString::to_string);
+
+        let acceptance = match kind {
+            PartialAccept::Word => InlineCompletionAcceptance::Word,
+            PartialAccept::Line => InlineCompletionAcceptance::Line,
+        };
+        self.report_completion_event(Some(acceptance), cx);
+        let mut completion = self.completions.swap_remove(self.active_completion_index);
+        self.completions.clear();
+        self.active_completion_index = 0;
+        self.cycled = false;
+        self.pending_cycling_refresh = Task::ready(Ok(()));
+        // Nothing is left to propose once the rest of it has been accepted.
+        let Some(remainder) = remainder else {
+            return false;
+        };
+
+        // Keep proposing the rest of the suggestion from the new cursor
+        // position, so accepting it piece by piece needs no new requests.
+        completion.text = remainder;
+        completion.position = snapshot.anchor_before(cursor_position);
+        self.completions.push(completion);
+        cx.notify();
+        true
+    }
+
+    fn discard(
+        &mut self,
+        should_report_inline_completion_event: bool,
+        cx: &mut ModelContext<Self>,
+    ) {
+        if let Some(completion) = self
+            .active_completion()
+            .filter(|_| should_report_inline_completion_event)
+        {
+            if let Some(request_id) = completion.request_id {
+                trace(request_id, TraceEvent::Discarded, cx);
+            }
+            if let Some(model) = completion.model.as_deref() {
+                CompletionStats::global(cx).record(model, self.language.as_deref(), false, cx);
+            }
+            self.report_completion_event(None, cx);
+        }
+
+        self.cancel_refresh(cx);
+        self.pending_cycling_refresh = Task::ready(Ok(()));
+        // Edits discard the suggestion without reporting it. It's kept in
+        // case the edit typed what it suggests, and replaced by the next
+        // suggestion otherwise.
+        if should_report_inline_completion_event {
+            self.completions.clear();
+            self.active_completion_index = 0;
+            self.chain_next = false;
+        }
+    }
+
+    fn discard_pending(&mut self, cx: &mut ModelContext<Self>) {
+        self.discard(false, cx);
+        // Unlike after an edit, the suggestion won't be typed through, and
+        // neither will the ones prefetched for where the cursor was going.
+        self.completions.clear();
+        self.active_completion_index = 0;
+        self.chain_next = false;
+        self.prefetched.clear();
+        cx.notify();
+    }
+
+    fn start_rename(
+        &mut self,
+        rename_buffer: &Model<Buffer>,
+        buffer: &Model<Buffer>,
+        range: Range<Anchor>,
+        cx: &mut ModelContext<Self>,
+    ) -> bool {
+        let buffer = buffer.read(cx);
+        if !completions_allowed(buffer, range.start, self.project.as_ref(), cx) {
+            return false;
+        }
+        let snapshot = buffer.snapshot();
+        self.rename = Some((
+            rename_buffer.entity_id(),
+            RenameTarget::new(
+                &snapshot,
+                range.start.to_offset(&snapshot)..range.end.to_offset(&snapshot),
+            ),
+        ));
+        true
+    }
+
+    fn active_completion_text<'a>(
+        &'a self,
+        buffer: &Model<Buffer>,
+        cursor_position: Anchor,
+        cx: &'a AppContext,
+    ) -> Option<CompletionProposal> {
+        if Some(buffer.entity_id()) != self.buffer_id {
+            return None;
+        }
+
+        let completion = self.active_completion()?;
+        let snapshot = buffer.read(cx).snapshot();
+        let typed = typed_since(completion, &snapshot, cursor_position.to_offset(&snapshot))?;
+
+        if let Some(range) = completion.edit_range.clone() {
+            if !typed.is_empty() {
+                return None;
+            }
+            let mut inlays = vec![InlayProposal::Suggestion(
+                range.end,
+                completion.text.as_str().into(),
+            )];
+            let row = range.start.to_point(&snapshot).row;
+            if row != cursor_position.to_point(&snapshot).row {
+                inlays.push(end_of_line_hint(
+                    &snapshot,
+                    cursor_position,
+                    format!("edit at line {}", row + 1),
+                    format!("Accept to apply the predicted edit at line {}", row + 1),
+                ));
+            }
+            return Some(CompletionProposal {
+                inlays,
+                text: completion.text.as_str().into(),
+                delete_range: Some(range),
+                metadata: Some(completion.metadata()),
+            });
+        }
+
+        // Until the next refresh carries the suggestion on, what's been typed
+        // of it is left out.
+        let text = if typed.is_empty() {
+            completion.text.as_str()
+        } else {
+            remaining_after_typing(&completion.text, &typed)?
+        };
+        let mut inlays = vec![InlayProposal::Suggestion(cursor_position, text.into())];
+        if self.completions.len() > 1 {
+            inlays.push(end_of_line_hint(
+                &snapshot,
+                cursor_position,
+                format!(
+                    "{}/{}",
+                    self.active_completion_index + 1,
+                    self.completions.len()
+                ),
+                format!(
+                    "Suggestion {} of {}",
+                    self.active_completion_index + 1,
+                    self.completions.len()
+                ),
+            ));
+        }
+        if let Some(fallback) = completion.fallback {
+            inlays.push(end_of_line_hint(
+                &snapshot,
+                cursor_position,
+                fallback.label().to_string(),
+                fallback.tooltip().to_string(),
+            ));
+        }
+        let lines = text.lines().count();
+        let preview_min_lines =
+            OllamaSettings::for_file(buffer.read(cx).file(), cx).preview_min_lines;
+        if preview_min_lines > 0 && lines >= preview_min_lines {
+            inlays.push(end_of_line_hint(
+                &snapshot,
+                cursor_position,
+                format!("{lines} lines, ollama: preview suggestion"),
+                "Run `ollama: preview suggestion` to insert it highlighted, with buttons to keep \
+                or discard it"
+                    .to_string(),
+            ));
+        }
+        if let Some(source) = completion.verbatim_source.as_ref() {
+            inlays.push(end_of_line_hint(
+                &snapshot,
+                cursor_position,
+                format!("verbatim from {}", source.display()),
+                format!("Most of this suggestion is already in {}", source.display()),
+            ));
+        }
+
+        Some(CompletionProposal {
+            inlays,
+            text: text.into(),
+            delete_range: None,
+            metadata: Some(completion.metadata()),
+        })
+    }
+}
+
+/// The text between where `completion` was made and the cursor, or `None`
+/// when the cursor is before it or further past it than it reaches.
+fn typed_since(
+    completion: &OllamaCompletion,
+    snapshot: &BufferSnapshot,
+    cursor_offset: usize,
+) -> Option<String> {
+    if !completion.position.is_valid(snapshot) {
+        return None;
+    }
+    let start = completion.position.to_offset(snapshot);
+    if start > cursor_offset || cursor_offset - start > completion.text.len() {
+        return None;
+    }
+    Some(snapshot.text_for_range(start..cursor_offset).collect())
+}
+
+/// What's left of `suggestion` once `typed` has been typed at its start, if
+/// it was typed along with the suggestion and more than whitespace is left.
+fn remaining_after_typing<'a>(suggestion: &'a str, typed: &str) -> Option<&'a str> {
+    let remainder = suggestion.strip_prefix(typed)?;
+    (!remainder.trim().is_empty()).then_some(remainder)
+}
+
+/// Whether Ollama may be sent the buffer to complete at the cursor, however
+/// the request comes about: Ollama, the file's path and its language all have
+/// to be enabled, both in Ollama's settings and in the editor's, and the
+/// buffer can't have been turned off in `project`'s workspace.
+fn completions_allowed(
+    buffer: &Buffer,
+    cursor_position: Anchor,
+    project: Option<&WeakModel<Project>>,
+    cx: &AppContext,
+) -> bool {
+    let file = buffer.file();
+    let path = file.map(|file| file.path().as_ref());
+    let language = buffer.language_at(cursor_position);
+    let language_name = language.as_ref().map(|language| language.name());
+    OllamaSettings::for_file(file, cx)
+        .completions_enabled_for(path, language_name.as_ref().map(|name| name.0.as_ref()))
+        && all_language_settings(file, cx).inline_completions_enabled(language.as_ref(), path, cx)
+        && !DisabledBuffers::global(cx).is_some_and(|disabled| {
+            disabled.is_disabled(buffer, project.map(|project| project.entity_id()), cx)
+        })
+}
+
+/// Whether the completion at the cursor should stay on the cursor's line.
+pub(crate) fn is_single_line(
+    mode: CompletionMode,
+    snapshot: &text::BufferSnapshot,
+    cursor_offset: usize,
+) -> bool {
+    match mode {
+        CompletionMode::Auto => is_mid_statement(snapshot, cursor_offset),
+        CompletionMode::SingleLine => true,
+        CompletionMode::MultiLine => false,
+    }
+}
+
+/// The temperature a suggestion at `cursor_offset` is sampled at, as
+/// `schedule` sets it for where the cursor is on its line.
+pub(crate) fn scheduled_temperature(
+    schedule: &TemperatureSchedule,
+    snapshot: &text::BufferSnapshot,
+    cursor_offset: usize,
+) -> f32 {
+    let cursor = snapshot.offset_to_point(cursor_offset);
+    let line_end = Point::new(cursor.row, snapshot.line_len(cursor.row));
+    let has_code = |range: Range<Point>| {
+        snapshot
+            .text_for_range(range)
+            .any(|chunk| !chunk.trim().is_empty())
+    };
+    let temperature = if has_code(cursor..line_end) {
+        schedule.mid_line
+    } else if has_code(Point::new(cursor.row, 0)..cursor) {
+        schedule.end_of_line
+    } else {
+        schedule.blank_line
+    };
+    temperature.unwrap_or(COMPLETION_TEMPERATURE)
+}
+
+/// Whether generation stopped at the token limit rather than at a natural
+/// end, leaving the suggestion cut short.
+fn is_truncated(response: &GenerateResponse) -> bool {
+    response.done_reason.as_deref() == Some("length") && !response.response.is_empty()
+}
+
+/// Asks for the rest of a truncated suggestion by sending `request` again
+/// with what was generated so far appended to its prompt, up to
+/// `MAX_CONTINUATIONS` times, and stitches the parts into one response. A
+/// failed continuation leaves the suggestion as far as it got.
+async fn continue_generation<F, R>(
+    mut response: GenerateResponse,
+    request: GenerateRequest,
+    generate: F,
+) -> GenerateResponse
+where
+    F: Fn(GenerateRequest) -> R,
+    R: Future<Output = Result<GenerateResponse>>,
+{
+    for _ in 0..MAX_CONTINUATIONS {
+        if !is_truncated(&response) {
+            break;
+        }
+        let continuation = GenerateRequest {
+            model: response.model.clone(),
+            prompt: format!("{}{}", request.prompt, response.response),
+            ..request.clone()
+        };
+        match generate(continuation).await {
+            Ok(next) => response = stitch_responses(response, next),
+            Err(error) => {
+                log::debug!("continuing a truncated completion failed: {error:?}");
+                break;
+            }
+        }
+    }
+    response
+}
+
+/// Gives the model one chance to fix a predicted edit that doesn't match the
+/// schema, such as malformed or cut-off JSON, by sending `request` again with
+/// its response and what's wrong with it. Returns the original response when
+/// it's valid or the repair fails.
+async fn repair_predicted_edit<F, R>(
+    response: GenerateResponse,
+    request: GenerateRequest,
+    generate: F,
+) -> GenerateResponse
+where
+    F: Fn(GenerateRequest) -> R,
+    R: Future<Output = Result<GenerateResponse>>,
+{
+    let Err(error) = PredictedEdit::validate(&response.response) else {
+        return response;
+    };
+    log::debug!("repairing an invalid predicted edit: {error}");
+    let repair = edit_repair_request(
+        &GenerateRequest {
+            model: response.model.clone(),
+            ..request
+        },
+        &response.response,
+        &error.to_string(),
+    );
+    match generate(repair).await {
+        Ok(repaired) => {
+            repaired.record_token_usage(TokenUsageKind::Completion);
+            repaired
+        }
+        Err(error) => {
+            log::debug!("repairing a predicted edit failed: {error:?}");
+            response
+        }
+    }
+}
+
+/// One response from a response and its continuation.
+fn stitch_responses(first: GenerateResponse, next: GenerateResponse) -> GenerateResponse {
+    let sum = |a: Option<u64>, b: Option<u64>| match (a, b) {
+        (Some(a), Some(b)) => Some(a + b),
+        (a, b) => a.or(b),
+    };
+    GenerateResponse {
+        response: first.response + &next.response,
+        prompt_eval_count: first.prompt_eval_count,
+        eval_count: sum(first.eval_count, next.eval_count),
+        eval_duration: sum(first.eval_duration, next.eval_duration),
+        ..next
+    }
+}
+
+/// The result of whichever future first succeeds with a `usable` result,
+/// dropping the other, which cancels its request. If neither does, the result
+/// of the one that finished last is returned.
+async fn first_usable<T>(
+    a: impl Future<Output = Result<T>>,
+    b: impl Future<Output = Result<T>>,
+    usable: impl Fn(&T) -> bool,
+) -> Result<T> {
+    pin_mut!(a, b);
+    let (first, other) = match future::select(a, b).await {
+        Either::Left((first, other)) => (first, other.left_future()),
+        Either::Right((first, other)) => (first, other.right_future()),
+    };
+    match first {
+        Ok(result) if usable(&result) => Ok(result),
+        Ok(_) => {
+            log::debug!("first of the raced completion requests had no usable suggestion");
+            other.await
+        }
+        Err(error) => {
+            log::debug!("first of the raced completion requests failed: {error:?}");
+            other.await
+        }
+    }
+}
+
+/// The first answer from either model. When the draft model answers first,
+/// the verifying model's request is returned along with it, still running,
+/// so its answer can replace the draft.
+async fn speculate<T, V: Future<Output = Result<T>>>(
+    verify: V,
+    draft: impl Future<Output = Result<T>>,
+) -> (Result<T>, Option<Pin<Box<V>>>) {
+    let mut verify = Box::pin(verify);
+    pin_mut!(draft);
+    match future::select(verify.as_mut(), draft).await {
+        Either::Left((Ok(result), _)) => (Ok(result), None),
+        Either::Left((Err(error), draft)) => {
+            log::debug!("verifying completion request failed: {error:?}");
+            (draft.await, None)
+        }
+        Either::Right((Ok(result), _)) => (Ok(result), Some(verify)),
+        Either::Right((Err(error), _)) => {
+            log::debug!("draft completion request failed: {error:?}");
+            (verify.await, None)
+        }
+    }
+}
+
+/// Whether a verified suggestion says something other than the draft, beyond
+/// differences in whitespace.
+fn differs_meaningfully(draft: &str, verified: &str) -> bool {
+    !draft.split_whitespace().eq(verified.split_whitespace())
+}
+
+/// The suggestion in a response, or the outcome to log when there is none.
+fn suggestion(
+    response: &GenerateResponse,
+    predict_edits: bool,
+    snapshot: &BufferSnapshot,
+    cursor_offset: usize,
+    insertion: &InsertionContext,
+) -> Result<(String, Option<Range<Anchor>>), RequestOutcome> {
+    if predict_edits {
+        let (range, text) = parse_predicted_edit(&response.response, snapshot, cursor_offset)
+            .ok_or(RequestOutcome::Unparseable)?;
+        let range = snapshot.anchor_before(range.start)..snapshot.anchor_after(range.end);
+        Ok((text, Some(range)))
+    } else {
+        let text = completion_text(response, insertion)?;
+        Ok((text, None))
+    }
+}
+
+/// The prompt builder for a completion at the cursor. Prose gets its own
+/// template and system prompt, and stops at the end of the paragraph. For a
+/// model that can't fill in the middle, the text before the cursor is sent
+/// raw for it to continue, since it would echo back the special tokens of a
+/// fill-in-the-middle prompt, unless a prompt template says otherwise.
+#[allow(clippy::too_many_arguments)]
+fn prompt_builder(
+    settings: &OllamaSettings,
+    model: &str,
+    options: ChatOptions,
+    context_tokens: usize,
+    supports_fim: bool,
+    language: Option<&str>,
+    snapshot: &BufferSnapshot,
+    cursor_offset: usize,
+) -> PromptBuilder {
+    let temperature = options.temperature.unwrap_or_else(|| {
+        scheduled_temperature(&settings.temperature_schedule, snapshot, cursor_offset)
+    });
+    let builder = PromptBuilder::new(model, options, context_tokens)
+        .with_temperature(temperature)
+        .with_single_line(is_single_line(
+            settings.completion_mode_for(language),
+            snapshot,
+            cursor_offset,
+        ))
+        .with_budget(settings.prompt_budget)
+        .with_raw(settings.raw || !supports_fim)
+        .with_max_chars(settings.max_prefix_chars, settings.max_suffix_chars);
+    if is_commit_message(snapshot.file()) {
+        return builder
+            .with_system_prompt(Some(COMMIT_SYSTEM_PROMPT))
+            .with_template(settings.prompt_template_for(model))
+            .with_stop(vec![COMMIT_STOP.to_string()]);
+    }
+    let language_template = settings.language_prompt_template(language);
+    let language_stop = settings.stop_for(language);
+    if !settings.is_prose(language) {
+        return builder
+            .with_system_prompt(settings.system_prompt_for(model))
+            .with_template(language_template.or(settings.prompt_template_for(model)))
+            .with_stop(language_stop);
+    }
+    // The general system prompt is written for code, so only one meant for
+    // this model replaces the prose one.
+    let system_prompt = settings
+        .system_prompts
+        .get(model)
+        .map_or(PROSE_SYSTEM_PROMPT, String::as_str);
+    let mut stop = vec![PROSE_STOP.to_string()];
+    stop.extend(language_stop);
+    builder
+        .with_system_prompt(Some(system_prompt))
+        .with_template(
+            language_template
+                .or(settings.prose_prompt_template.as_deref())
+                .or(settings.prompt_template_for(model)),
+        )
+        .with_stop(stop)
+}
+
+/// The text around the cursor, led by the file's header when the cursor is
+/// too far from the top of the file for the prefix to include it.
+fn prompt_context_with_header(
+    builder: &PromptBuilder,
+    snapshot: &BufferSnapshot,
+    cursor_offset: usize,
+    language: &str,
+    path: &str,
+    extra_context: String,
+    file_header_tokens: usize,
+) -> PromptContext {
+    let mut prompt = builder.prompt_context(snapshot, cursor_offset, language, path, extra_context);
+    prompt.header = file_header(
+        snapshot,
+        cursor_offset - prompt.prefix.len(),
+        file_header_tokens,
+    );
+    prompt
+}
+
+/// The name of the language at the cursor, which per-language settings are
+/// keyed by.
+fn language_name(snapshot: &BufferSnapshot, cursor_offset: usize) -> Option<String> {
+    snapshot
+        .language_at(cursor_offset)
+        .map(|language| language.name().0.to_string())
+}
+
+/// Whether the cursor is in the middle of a statement, where a block of code
+/// would be out of place: there's text after it on the line, or the line so
+/// far doesn't end where a new line would naturally start.
+fn is_mid_statement(snapshot: &text::BufferSnapshot, cursor_offset: usize) -> bool {
+    let cursor = snapshot.offset_to_point(cursor_offset);
+    let line_end = Point::new(cursor.row, snapshot.line_len(cursor.row));
+    let after = snapshot
+        .text_for_range(cursor..line_end)
+        .collect::<String>();
+    if !after.trim().is_empty() {
+        return true;
+    }
+    let before = snapshot
+        .text_for_range(Point::new(cursor.row, 0)..cursor)
+        .collect::<String>();
+    let before = before.trim_end();
+    !before.is_empty() && !before.ends_with(['{', '(', '[', ':', ';', ','])
+}
+
+/// Records the edits made to the buffer since it was last seen in the
+/// [`EditHistory`] and the [`EditMemory`], for whichever are enabled.
+fn observe_edits(settings: &OllamaSettings, snapshot: &BufferSnapshot, cx: &AppContext) {
+    let Some(previous) = BufferSnapshots::global(cx).observe(snapshot) else {
+        return;
+    };
+    let path = snapshot.file().map(|file| file.path().clone());
+    if settings.recent_edits_tokens > 0 {
+        EditHistory::global(cx).record(&previous, snapshot, path.clone());
+    }
+    if settings.edit_memory_tokens > 0 && settings.edit_memory_snippets > 0 {
+        EditMemory::global(cx).record(&previous, snapshot, path);
+    }
+}
+
+/// Asks the [`EditMemory`] for the code it remembers that's most like the
+/// code at the cursor, to be placed at the start of the prompt. `None` when
+/// disabled or the language has no line comments.
+fn edit_memory_request(
+    settings: &OllamaSettings,
+    snapshot: &BufferSnapshot,
+    cursor_offset: usize,
+    cx: &AppContext,
+) -> Option<EditMemoryRequest> {
+    if settings.edit_memory_tokens == 0 || settings.edit_memory_snippets == 0 {
+        return None;
+    }
+    let comment_prefix = line_comment_prefix(snapshot, cursor_offset)?;
+    EditMemory::global(cx).request(settings, snapshot, cursor_offset, comment_prefix)
+}
+
+/// Replaces secrets in the text sent with `request`, unless turned off. This
+/// comes after the insertion context is taken from the request, so suggestions
+/// are still post-processed against the real text.
+fn redact_request(redactor: Option<&SecretRedactor>, request: &mut GenerateRequest) {
+    if let Some(redactor) = redactor {
+        redactor.redact_request(request);
+    }
+}
+
+/// The context placed at the start of a completion prompt, read on the main
+/// thread by [`OllamaCompletionProvider::prompt_context`] so it can be put
+/// together in the background.
+struct PromptContextRequest {
+    snapshot: BufferSnapshot,
+    cursor_offset: usize,
+    git: Option<GitContextRequest>,
+    /// The imported files, when their signatures are included, and their
+    /// budget.
+    imports: Option<(ImportsContextRequest, usize)>,
+    schema: Option<SchemaContextRequest>,
+    /// The edit history, when recent edits are included, and their budget.
+    recent_edits: Option<(Arc<EditHistory>, usize)>,
+    include_diagnostics: bool,
+    disabled_paths: PathMatcher,
+}
+
+impl PromptContextRequest {
+    /// The context, in the language's line comments. Empty when the language
+    /// has no line comments.
+    fn build(self) -> String {
+        let Some(comment_prefix) = line_comment_prefix(&self.snapshot, self.cursor_offset) else {
+            return String::new();
+        };
+        let mut context = String::new();
+        if let Some(git) = &self.git {
+            context += &git.build(
+                &self.snapshot,
+                self.cursor_offset,
+                &comment_prefix,
+                &self.disabled_paths,
+            );
+        }
+        if let Some((imports, budget)) = &self.imports {
+            context += &imports.run(&comment_prefix, *budget, &self.disabled_paths);
+        }
+        if let Some(schema) = &self.schema {
+            context += &schema.run(&comment_prefix);
+        }
+        if let Some((history, budget)) = &self.recent_edits {
+            context += &history.prompt_context(
+                self.snapshot.remote_id(),
+                self.cursor_offset,
+                &comment_prefix,
+                *budget,
+                &self.disabled_paths,
+            );
+        }
+        if self.include_diagnostics {
+            context +=
+                &cursor_line_diagnostics(&self.snapshot, self.cursor_offset, &comment_prefix);
+        }
+        context
+    }
+}
+
+/// The file's unstaged Git changes and its worktree, read on the main thread.
+struct GitContextRequest {
+    path: Arc<Path>,
+    diff_base: Option<Rope>,
+    worktree: Option<worktree::Snapshot>,
+    budget: usize,
+}
+
+impl GitContextRequest {
+    /// `None` when disabled or the file isn't in a project.
+    fn new(
+        settings: &OllamaSettings,
+        buffer: &Buffer,
+        snapshot: &BufferSnapshot,
+        project: Option<&WeakModel<Project>>,
+        cx: &AppContext,
+    ) -> Option<Self> {
+        if settings.git_diff_tokens == 0 {
+            return None;
+        }
+        let file = snapshot.file()?;
+        let worktree = project
+            .and_then(|project| project.upgrade())
+            .and_then(|project| project.read(cx).worktree_for_id(file.worktree_id(cx), cx))
+            .map(|worktree| worktree.read(cx).snapshot());
+        Some(Self {
+            path: file.path().clone(),
+            diff_base: buffer.diff_base().cloned(),
+            worktree,
+            budget: settings.git_diff_tokens,
+        })
+    }
+
+    /// Summarizes the file's unstaged changes and the other changed files in
+    /// its worktree, leaving out those matching `disabled_paths`.
+    fn build(
+        &self,
+        snapshot: &BufferSnapshot,
+        cursor_offset: usize,
+        comment_prefix: &str,
+        disabled_paths: &PathMatcher,
+    ) -> String {
+        let hunks = self
+            .diff_base
+            .as_ref()
+            .map(|diff_base| {
+                snapshot
+                    .git_diff_hunks_intersecting_range(Anchor::MIN..Anchor::MAX)
+                    .map(|hunk| UnstagedHunk {
+                        row: hunk.row_range.start,
+                        old_text: diff_base
+                            .chunks_in_range(hunk.diff_base_byte_range)
+                            .collect(),
+                        new_text: snapshot.text_for_range(hunk.buffer_range).collect(),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let changed_files = self
+            .worktree
+            .as_ref()
+            .map(|worktree| {
+                worktree
+                    .files(false, 0)
+                    .filter(|entry| entry.path != self.path)
+                    .filter(|entry| !disabled_paths.is_match(&entry.path))
+                    .filter_map(|entry| Some((entry.path.clone(), entry.git_status?)))
+                    .take(MAX_CHANGED_FILES)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        git_changes_context(
+            &self.path,
+            &hunks,
+            &changed_files,
+            snapshot.offset_to_point(cursor_offset).row,
+            comment_prefix,
+            self.budget,
+        )
+    }
+}
+
+/// What's needed to summarize the public signatures of the project's files
+/// the buffer imports, and their budget. `None` when disabled or the file
+/// isn't in a project.
+fn imports_request(
+    settings: &OllamaSettings,
+    snapshot: &BufferSnapshot,
+    project: Option<&WeakModel<Project>>,
+    cx: &AppContext,
+) -> Option<(ImportsContextRequest, usize)> {
+    if settings.imported_signatures_tokens == 0 {
+        return None;
+    }
+    let project = project?.upgrade()?;
+    let request = ImportsContextRequest::new(snapshot, project.read(cx), cx)?;
+    Some((request, settings.imported_signatures_tokens))
+}
+
+/// What's needed to describe what the file's JSON schema allows at the
+/// cursor, for configuration files whose language has `schema_context` on.
+fn schema_request(
+    settings: &OllamaSettings,
+    snapshot: &BufferSnapshot,
+    cursor_offset: usize,
+    project: Option<&WeakModel<Project>>,
+    http_client: &Arc<dyn HttpClient>,
+    disabled_paths: &PathMatcher,
+    cx: &AppContext,
+) -> Option<SchemaContextRequest> {
+    let project = project?.upgrade()?;
+    SchemaContextRequest::new(
+        settings,
+        snapshot,
+        cursor_offset,
+        project.read(cx),
+        http_client.clone(),
+        disabled_paths,
+        cx,
+    )
+}
+
+/// The prefix of line comments in the language at the cursor.
+fn line_comment_prefix(snapshot: &BufferSnapshot, cursor_offset: usize) -> Option<Arc<str>> {
+    snapshot
+        .language_scope_at(cursor_offset)
+        .and_then(|scope| scope.line_comment_prefixes().first().cloned())
+}
+
+/// Describes where a suggestion for `request` will be inserted, so it can be
+/// post-processed.
+pub(crate) fn insertion_context(
+    snapshot: &text::BufferSnapshot,
+    cursor_offset: usize,
+    request: &GenerateRequest,
+    tab_size: u32,
+    hard_tabs: bool,
+) -> InsertionContext {
+    let row = snapshot.offset_to_point(cursor_offset).row;
+    let line = snapshot
+        .text_for_range(Point::new(row, 0)..Point::new(row, snapshot.line_len(row)))
+        .collect::<String>();
+    let single_line = request
+        .options
+        .as_ref()
+        .and_then(|options| options.stop.as_ref())
+        .map_or(false, |stop| stop.iter().any(|stop| stop == "\n"));
+    InsertionContext {
+        suffix: request.suffix.clone().unwrap_or_default(),
+        line_indent: indent_columns(&line, tab_size),
+        tab_size,
+        hard_tabs,
+        single_line,
+        max_lines: 0,
+        postprocessors: Postprocessor::DEFAULT_CHAIN.to_vec(),
+    }
+}
+
+/// The settings that shape the insertion context at the cursor, read ahead of
+/// time so the context itself can be built off the main thread.
+struct InsertionSettings {
+    tab_size: u32,
+    hard_tabs: bool,
+    max_lines: usize,
+    postprocessors: Vec<Postprocessor>,
+}
+
+impl InsertionSettings {
+    fn read(snapshot: &BufferSnapshot, cursor_offset: usize, model: &str, cx: &AppContext) -> Self {
+        let language = snapshot.language_at(cursor_offset).map(|l| l.name());
+        let ollama_settings = OllamaSettings::for_file(snapshot.file(), cx);
+        let mut postprocessors = ollama_settings.postprocessors_for(model).to_vec();
+        if ollama_settings.is_prose(language.as_ref().map(|language| language.0.as_ref()))
+            && !postprocessors.contains(&Postprocessor::EndAtSentence)
+        {
+            postprocessors.push(Postprocessor::EndAtSentence);
+        }
+        let settings = language_settings(language, snapshot.file(), cx);
+        Self {
+            tab_size: settings.tab_size.get(),
+            hard_tabs: settings.hard_tabs,
+            max_lines: ollama_settings.max_suggestion_lines,
+            postprocessors,
+        }
+    }
+
+    fn insertion_context(
+        &self,
+        snapshot: &BufferSnapshot,
+        cursor_offset: usize,
+        request: &GenerateRequest,
+    ) -> InsertionContext {
+        let mut context = insertion_context(
+            snapshot,
+            cursor_offset,
+            request,
+            self.tab_size,
+            self.hard_tabs,
+        );
+        context.max_lines = self.max_lines;
+        context.postprocessors = self.postprocessors.clone();
+        context
+    }
+}
+
+/// The suggestion to show for a response, once it has been post-processed.
+/// Suggestions of only whitespace, or that only repeat the code after the
+/// cursor, are left out.
+pub(crate) fn completion_text(
+    response: &GenerateResponse,
+    insertion: &InsertionContext,
+) -> Result<String, RequestOutcome> {
+    let text = postprocess(&response.response, insertion);
+    if text.trim().is_empty() {
+        Err(RequestOutcome::Empty)
+    } else if duplicates_following_code(&text, &insertion.suffix) {
+        Err(RequestOutcome::Duplicate)
+    } else {
+        Ok(text)
+    }
+}
+
+/// Traces what became of a request, and adds it to the request log if the
+/// log was open when the request was sent.
+fn log_request(
+    request_id: RequestId,
+    logged_request: &Option<(Model<RequestLog>, GenerateRequest)>,
+    response: Option<&GenerateResponse>,
+    latency: Duration,
+    outcome: RequestOutcome,
+    cx: &mut AsyncAppContext,
+) {
+    let finished = TraceEvent::Finished {
+        outcome: outcome.describe(),
+        latency,
+    };
+    cx.update(|cx| trace(request_id, finished, cx)).ok();
+    let Some((log, request)) = logged_request else {
+        return;
+    };
+    let entry = LoggedRequest {
+        id: request_id,
+        time: chrono::Local::now().time(),
+        request: request.clone(),
+        response: response.map(|response| response.response.clone()),
+        latency,
+        outcome,
+    };
+    log.update(cx, |log, cx| log.record(entry, cx)).ok();
+}
+
+/// Performance figures for a response, for telemetry.
+fn completion_metrics(response: &GenerateResponse, latency: Duration) -> InlineCompletionMetrics {
+    InlineCompletionMetrics {
+        model: response.model.clone(),
+        latency_ms: latency.as_millis() as u64,
+        prompt_tokens: response.prompt_eval_count,
+        completion_tokens: response.eval_count,
+        tokens_per_second: response.tokens_per_second(),
+    }
+}
+
+/// Records where in the project a long suggestion was copied from, or hides
+/// it when `verbatim_suggestions` says to.
+async fn annotate_verbatim_source(
+    this: &WeakModel<OllamaCompletionProvider>,
+    project: Option<WeakModel<Project>>,
+    buffer: &Model<Buffer>,
+    text: &str,
+    check: VerbatimCheck,
+    cx: &mut AsyncAppContext,
+) -> Result<()> {
+    if !check.applies_to(text) {
+        return Ok(());
+    }
+    let Some(project) = project.and_then(|project| project.upgrade()) else {
+        return Ok(());
+    };
+
+    let source = find_verbatim_source(project, buffer, text, check.min_similarity, cx).await?;
+    if let Some(source) = source {
+        this.update(cx, |this, cx| {
+            let Some(ix) = this
+                .completions
+                .iter()
+                .position(|completion| completion.text == text)
+            else {
+                return;
+            };
+            match check.action {
+                VerbatimSuggestions::Annotate => {
+                    this.completions[ix].verbatim_source = Some(source);
+                }
+                VerbatimSuggestions::Suppress => {
+                    log::debug!("Hiding a suggestion copied from {}", source.display());
+                    this.completions.remove(ix);
+                    if this.active_completion_index > ix {
+                        this.active_completion_index -= 1;
+                    }
+                    this.active_completion_index = this
+                        .active_completion_index
+                        .min(this.completions.len().saturating_sub(1));
+                }
+            }
+            cx.notify();
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Searches the project (including unsaved open buffers) for another file
+/// that contains at least `min_similarity` of `text`'s lines. Only files
+/// containing one of its longest lines are compared with it.
+async fn find_verbatim_source(
+    project: Model<Project>,
+    origin: &Model<Buffer>,
+    text: &str,
+    min_similarity: f32,
+    cx: &mut AsyncAppContext,
+) -> Result<Option<Arc<Path>>> {
+    let Some(fingerprint) = Fingerprint::new(text) else {
+        return Ok(None);
+    };
+    let mut compared = HashSet::default();
+
+    for line in fingerprint.search_lines() {
+        let query = SearchQuery::text(
+            line,
+            false,
+            true,
+            false,
+            PathMatcher::default(),
+            PathMatcher::default(),
+            None,
+        )?;
+        let mut results = project.update(cx, |project, cx| project.search(query, cx))?;
+
+        while let Some(result) = results.next().await {
+            let SearchResult::Buffer { buffer, ranges } = result else {
+                continue;
+            };
+            if &buffer == origin || ranges.is_empty() || !compared.insert(buffer.entity_id()) {
+                continue;
+            }
+            let (path, similarity) = buffer.read_with(cx, |buffer, _| {
+                (
+                    buffer.file().map(|f| f.path().clone()),
+                    fingerprint.similarity(&buffer.text()),
+                )
+            })?;
+            if let Some(path) = path.filter(|_| similarity >= min_similarity) {
+                return Ok(Some(path));
+            }
+            if compared.len() >= MAX_VERBATIM_CANDIDATES {
+                return Ok(None);
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Builds a hint placed at the end of the cursor's line, so it trails the
+/// suggestion instead of splitting it.
+fn end_of_line_hint(
+    snapshot: &BufferSnapshot,
+    cursor_position: Anchor,
+    label: String,
+    tooltip: String,
+) -> InlayProposal {
+    let mut point = cursor_position.to_point(snapshot);
+    point.column = snapshot.line_len(point.row);
+    let position = snapshot.anchor_after(point);
+
+    InlayProposal::Hint(
+        position,
+        InlayHint {
+            position,
+            label: InlayHintLabel::String(label),
+            kind: None,
+            padding_left: true,
+            padding_right: false,
+            tooltip: Some(InlayHintTooltip::String(tooltip)),
+            resolve_state: ResolveState::Resolved,
+        },
+    )
+}
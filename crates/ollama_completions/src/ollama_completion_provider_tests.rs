@@ -0,0 +1,576 @@
+use crate::{
+    Connectivity, ErrorReports, LowPowerMode, ModelSubstituted, ModelSubstitutes,
+    OllamaCompletionProvider, OllamaSamplingOptions, OllamaSettings, OllamaSettingsContent,
+    PREFETCH_DELAY,
+};
+use editor::{InlineCompletionProvider as _, PartialAccept};
+use gpui::{Context as _, Model, TestAppContext};
+use language::{Anchor, Buffer};
+use ollama::{FakeOllamaServer, OllamaError};
+use settings::{Settings as _, SettingsStore};
+use std::{cell::RefCell, rc::Rc};
+
+const TEXT: &str = "fn add(a: i32, b: i32) -> i32 {\n    \n}\n";
+/// The end of the indentation inside `add`.
+const CURSOR: usize = 36;
+
+fn init_test(cx: &mut TestAppContext, f: impl FnOnce(&mut OllamaSettingsContent)) {
+    cx.update(|cx| {
+        let store = SettingsStore::test(cx);
+        cx.set_global(store);
+        theme::init(theme::LoadThemes::JustBase, cx);
+        language::init(cx);
+        editor::init_settings(cx);
+        project::Project::init_settings(cx);
+        crate::init(cx);
+        SettingsStore::update_global(cx, |store, cx| {
+            store.update_user_settings::<OllamaSettings>(cx, |settings| {
+                // Suggestions saved by other tests mustn't stand in for the
+                // server's, and failed requests shouldn't wait to be retried.
+                settings.completion_cache_entries = Some(0);
+                settings.max_request_attempts = Some(1);
+                f(settings);
+            });
+        });
+    });
+}
+
+fn build(
+    server: &FakeOllamaServer,
+    cx: &mut TestAppContext,
+) -> (Model<OllamaCompletionProvider>, Model<Buffer>) {
+    let settings = cx.update(|cx| OllamaSettings::get_global(cx).clone());
+    let provider = cx.new_model(|_| OllamaCompletionProvider::new(server.http_client(), settings));
+    let buffer = cx.new_model(|cx| Buffer::local(TEXT, cx));
+    (provider, buffer)
+}
+
+fn anchor(buffer: &Model<Buffer>, offset: usize, cx: &mut TestAppContext) -> Anchor {
+    buffer.read_with(cx, |buffer, _| buffer.anchor_before(offset))
+}
+
+fn refresh(
+    provider: &Model<OllamaCompletionProvider>,
+    buffer: &Model<Buffer>,
+    offset: usize,
+    debounce: bool,
+    cx: &mut TestAppContext,
+) {
+    let cursor = anchor(buffer, offset, cx);
+    provider.update(cx, |provider, cx| {
+        provider.refresh(buffer.clone(), cursor, debounce, cx)
+    });
+    cx.executor().run_until_parked();
+}
+
+fn shown(
+    provider: &Model<OllamaCompletionProvider>,
+    buffer: &Model<Buffer>,
+    offset: usize,
+    cx: &mut TestAppContext,
+) -> Option<String> {
+    let cursor = anchor(buffer, offset, cx);
+    cx.update(|cx| {
+        provider
+            .read(cx)
+            .active_completion_text(buffer, cursor, cx)
+            .map(|proposal| proposal.text.to_string())
+    })
+}
+
+#[gpui::test]
+async fn test_refresh_and_accept(cx: &mut TestAppContext) {
+    init_test(cx, |_| {});
+    let server = FakeOllamaServer::new();
+    server.set_completion("a + b");
+    let (provider, buffer) = build(&server, cx);
+
+    refresh(&provider, &buffer, CURSOR, false, cx);
+    assert_eq!(
+        shown(&provider, &buffer, CURSOR, cx).as_deref(),
+        Some("a + b")
+    );
+    let requests = server.requests("/api/generate");
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0]["model"], "qwen2.5-coder:1.5b");
+    assert!(requests[0]["prompt"]
+        .as_str()
+        .unwrap()
+        .ends_with("fn add(a: i32, b: i32) -> i32 {\n    "));
+    assert_eq!(requests[0]["suffix"], "\n}\n");
+    // The model's context window was looked up along the way.
+    assert_eq!(server.requests("/api/show").len(), 1);
+
+    provider.update(cx, |provider, cx| provider.accept(cx));
+    assert_eq!(shown(&provider, &buffer, CURSOR, cx), None);
+}
+
+#[gpui::test]
+async fn test_completion_metadata(cx: &mut TestAppContext) {
+    init_test(cx, |_| {});
+    let server = FakeOllamaServer::new();
+    server.set_completion("a + b");
+    let (provider, buffer) = build(&server, cx);
+
+    refresh(&provider, &buffer, CURSOR, false, cx);
+    let cursor = anchor(&buffer, CURSOR, cx);
+    let metadata = cx.update(|cx| {
+        provider
+            .read(cx)
+            .active_completion_text(&buffer, cursor, cx)
+            .and_then(|proposal| proposal.metadata)
+            .unwrap()
+    });
+    assert_eq!(metadata.provider, "ollama");
+    assert_eq!(metadata.model.as_deref(), Some("qwen2.5-coder:1.5b"));
+    assert!(metadata.latency.is_some());
+    assert!(metadata
+        .label()
+        .starts_with("ollama · qwen2.5-coder:1.5b · "));
+}
+
+#[gpui::test]
+async fn test_discard(cx: &mut TestAppContext) {
+    init_test(cx, |_| {});
+    let server = FakeOllamaServer::new();
+    server.set_completion("a + b");
+    let (provider, buffer) = build(&server, cx);
+
+    refresh(&provider, &buffer, CURSOR, false, cx);
+    // Edits discard the suggestion without reporting it, and keep it in case
+    // they typed what it suggests.
+    provider.update(cx, |provider, cx| provider.discard(false, cx));
+    assert_eq!(
+        shown(&provider, &buffer, CURSOR, cx).as_deref(),
+        Some("a + b")
+    );
+
+    provider.update(cx, |provider, cx| provider.discard(true, cx));
+    assert_eq!(shown(&provider, &buffer, CURSOR, cx), None);
+}
+
+#[gpui::test]
+async fn test_type_through(cx: &mut TestAppContext) {
+    init_test(cx, |_| {});
+    let server = FakeOllamaServer::new();
+    server.set_completion("a + b");
+    let (provider, buffer) = build(&server, cx);
+
+    refresh(&provider, &buffer, CURSOR, false, cx);
+    buffer.update(cx, |buffer, cx| {
+        buffer.edit([(CURSOR..CURSOR, "a ")], None, cx)
+    });
+    refresh(&provider, &buffer, CURSOR + 2, true, cx);
+    assert_eq!(
+        shown(&provider, &buffer, CURSOR + 2, cx).as_deref(),
+        Some("+ b")
+    );
+    // Typing what was suggested doesn't ask the model again.
+    assert_eq!(server.requests("/api/generate").len(), 1);
+}
+
+#[gpui::test]
+async fn test_cancel(cx: &mut TestAppContext) {
+    init_test(cx, |_| {});
+    let server = FakeOllamaServer::new();
+    server.set_completion("a + b");
+    server.hold_responses();
+    let (provider, buffer) = build(&server, cx);
+
+    refresh(&provider, &buffer, CURSOR, false, cx);
+    assert_eq!(server.in_flight(), 1);
+    assert!(provider.read_with(cx, |provider, _| provider.is_refreshing()));
+
+    // A newer refresh replaces the pending one, dropping its request.
+    refresh(&provider, &buffer, CURSOR, false, cx);
+    assert_eq!(server.requests("/api/generate").len(), 2);
+    assert_eq!(server.in_flight(), 1);
+
+    provider.update(cx, |provider, cx| provider.discard(true, cx));
+    cx.executor().run_until_parked();
+    assert_eq!(server.in_flight(), 0);
+    assert!(!provider.read_with(cx, |provider, _| provider.is_refreshing()));
+
+    server.release_responses();
+    cx.executor().run_until_parked();
+    assert_eq!(shown(&provider, &buffer, CURSOR, cx), None);
+}
+
+#[gpui::test]
+async fn test_discard_pending(cx: &mut TestAppContext) {
+    init_test(cx, |_| {});
+    let server = FakeOllamaServer::new();
+    server.set_completion("a + b");
+    let (provider, buffer) = build(&server, cx);
+
+    refresh(&provider, &buffer, CURSOR, false, cx);
+    assert_eq!(
+        shown(&provider, &buffer, CURSOR, cx).as_deref(),
+        Some("a + b")
+    );
+
+    // Unlike discarding for an edit, the suggestion isn't kept.
+    provider.update(cx, |provider, cx| provider.discard_pending(cx));
+    assert_eq!(shown(&provider, &buffer, CURSOR, cx), None);
+
+    // Nor does a suggestion still being requested show up later.
+    server.hold_responses();
+    refresh(&provider, &buffer, CURSOR, false, cx);
+    assert_eq!(server.in_flight(), 1);
+    provider.update(cx, |provider, cx| provider.discard_pending(cx));
+    cx.executor().run_until_parked();
+    assert_eq!(server.in_flight(), 0);
+    assert!(!provider.read_with(cx, |provider, _| provider.is_refreshing()));
+
+    server.release_responses();
+    cx.executor().run_until_parked();
+    assert_eq!(shown(&provider, &buffer, CURSOR, cx), None);
+}
+
+#[gpui::test]
+async fn test_stale_response(cx: &mut TestAppContext) {
+    init_test(cx, |_| {});
+    let server = FakeOllamaServer::new();
+    server.set_completion("a + b");
+    server.hold_responses();
+    let (provider, buffer) = build(&server, cx);
+
+    refresh(&provider, &buffer, CURSOR, false, cx);
+    buffer.update(cx, |buffer, cx| {
+        buffer.edit([(CURSOR..CURSOR, "b")], None, cx)
+    });
+    server.release_responses();
+    cx.executor().run_until_parked();
+    // The suggestion was for text that has since changed.
+    assert_eq!(shown(&provider, &buffer, CURSOR + 1, cx), None);
+}
+
+#[gpui::test]
+async fn test_streamed_suggestion(cx: &mut TestAppContext) {
+    init_test(cx, |settings| settings.stream_suggestions = Some(true));
+    let server = FakeOllamaServer::new();
+    server.set_completion("let sum = a + b;\n    sum");
+    let (provider, buffer) = build(&server, cx);
+
+    refresh(&provider, &buffer, CURSOR, false, cx);
+    assert_eq!(server.requests("/api/generate")[0]["stream"], true);
+    assert_eq!(
+        shown(&provider, &buffer, CURSOR, cx).as_deref(),
+        Some("let sum = a + b;\n    sum")
+    );
+}
+
+#[gpui::test]
+async fn test_model_not_found(cx: &mut TestAppContext) {
+    init_test(cx, |settings| settings.model = Some("codellama:7b".into()));
+    let server = FakeOllamaServer::new();
+    // Nothing installed can stand in for the missing model.
+    server.set_models(&["llama3.2:latest"]);
+    server.set_completion("a + b");
+    let (provider, buffer) = build(&server, cx);
+
+    refresh(&provider, &buffer, CURSOR, false, cx);
+    assert_eq!(shown(&provider, &buffer, CURSOR, cx), None);
+    let error = cx.update(|cx| {
+        let reports = ErrorReports::global(cx).unwrap();
+        reports.read(cx).current().cloned()
+    });
+    assert_eq!(
+        error,
+        Some(OllamaError::ModelNotFound {
+            model: "codellama:7b".into()
+        })
+    );
+
+    // Once the model is there, the error clears with the next suggestion.
+    server.set_models(&["codellama:7b"]);
+    refresh(&provider, &buffer, CURSOR, false, cx);
+    assert_eq!(
+        shown(&provider, &buffer, CURSOR, cx).as_deref(),
+        Some("a + b")
+    );
+    let error = cx.update(|cx| {
+        let reports = ErrorReports::global(cx).unwrap();
+        reports.read(cx).current().cloned()
+    });
+    assert_eq!(error, None);
+}
+
+#[gpui::test]
+async fn test_missing_model_substitute(cx: &mut TestAppContext) {
+    init_test(cx, |settings| {
+        settings.model = Some("qwen2.5-coder:7b".into())
+    });
+    let server = FakeOllamaServer::new();
+    server.set_completion("a + b");
+    let (provider, buffer) = build(&server, cx);
+    let substituted = Rc::new(RefCell::new(Vec::new()));
+    cx.update(|cx| {
+        let substitutes = ModelSubstitutes::global(cx).unwrap();
+        let substituted = substituted.clone();
+        cx.subscribe(&substitutes, move |_, event: &ModelSubstituted, _| {
+            substituted.borrow_mut().push(event.clone())
+        })
+        .detach();
+    });
+
+    // The first request finds the model missing, and a smaller one of the
+    // same family stands in for it from then on.
+    refresh(&provider, &buffer, CURSOR, false, cx);
+    assert_eq!(shown(&provider, &buffer, CURSOR, cx), None);
+    let events = substituted.borrow().clone();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].missing, "qwen2.5-coder:7b");
+    assert_eq!(events[0].substitute, "qwen2.5-coder:1.5b");
+    let error = cx.update(|cx| {
+        let reports = ErrorReports::global(cx).unwrap();
+        reports.read(cx).current().cloned()
+    });
+    assert_eq!(error, None);
+
+    refresh(&provider, &buffer, CURSOR, false, cx);
+    assert_eq!(
+        shown(&provider, &buffer, CURSOR, cx).as_deref(),
+        Some("a + b")
+    );
+    let requests = server.requests("/api/generate");
+    assert_eq!(requests.last().unwrap()["model"], "qwen2.5-coder:1.5b");
+
+    // Once it's pulled, the configured model is used again.
+    server.set_models(&["qwen2.5-coder:1.5b", "qwen2.5-coder:7b"]);
+    cx.update(|cx| {
+        ModelSubstitutes::global(cx)
+            .unwrap()
+            .update(cx, |substitutes, _| substitutes.forget("qwen2.5-coder:7b"))
+    });
+    refresh(&provider, &buffer, CURSOR, false, cx);
+    let requests = server.requests("/api/generate");
+    assert_eq!(requests.last().unwrap()["model"], "qwen2.5-coder:7b");
+    assert_eq!(substituted.borrow().len(), 1);
+}
+
+#[gpui::test]
+async fn test_temperature_schedule(cx: &mut TestAppContext) {
+    init_test(cx, |_| {});
+    let server = FakeOllamaServer::new();
+    let (provider, buffer) = build(&server, cx);
+    let temperature = |server: &FakeOllamaServer| {
+        let requests = server.requests("/api/generate");
+        requests.last().unwrap()["options"]["temperature"]
+            .as_f64()
+            .unwrap() as f32
+    };
+
+    // On the blank line inside `add`, where a whole block may follow.
+    refresh(&provider, &buffer, CURSOR, false, cx);
+    assert_eq!(temperature(&server), 0.4);
+    // After the opening brace at the end of the first line.
+    refresh(&provider, &buffer, 31, false, cx);
+    assert_eq!(temperature(&server), 0.2);
+    // Between `add(` and its parameters.
+    refresh(&provider, &buffer, 7, false, cx);
+    assert_eq!(temperature(&server), 0.1);
+
+    // A temperature in `completion_options` is used wherever the cursor is.
+    cx.update(|cx| {
+        SettingsStore::update_global(cx, |store, cx| {
+            store.update_user_settings::<OllamaSettings>(cx, |settings| {
+                settings.completion_options = Some(OllamaSamplingOptions {
+                    temperature: Some(0.3),
+                    ..Default::default()
+                });
+            });
+        });
+    });
+    refresh(&provider, &buffer, CURSOR, false, cx);
+    assert_eq!(temperature(&server), 0.3);
+}
+
+#[gpui::test]
+async fn test_unreachable_server(cx: &mut TestAppContext) {
+    init_test(cx, |_| {});
+    let server = FakeOllamaServer::new();
+    server.set_unreachable(true);
+    let (provider, buffer) = build(&server, cx);
+
+    for _ in 0..3 {
+        refresh(&provider, &buffer, CURSOR, false, cx);
+    }
+    assert_eq!(shown(&provider, &buffer, CURSOR, cx), None);
+    let offline = cx.update(|cx| {
+        let connectivity = Connectivity::global(cx).unwrap();
+        connectivity.read(cx).is_offline()
+    });
+    assert!(offline);
+    let error = cx.update(|cx| {
+        let reports = ErrorReports::global(cx).unwrap();
+        reports.read(cx).current().cloned()
+    });
+    assert_eq!(error, Some(OllamaError::ConnectionRefused { attempts: 1 }));
+}
+
+#[gpui::test]
+async fn test_prefetch(cx: &mut TestAppContext) {
+    init_test(cx, |settings| settings.prefetch_suggestions = Some(true));
+    let server = FakeOllamaServer::new();
+    server.set_completion("a + b");
+    let (provider, buffer) = build(&server, cx);
+
+    // From the middle of the signature, the cursor is likely to go to the
+    // end of its line or to the blank line in the body.
+    let in_signature = TEXT.find("i32)").unwrap();
+    refresh(&provider, &buffer, in_signature, false, cx);
+    assert_eq!(server.requests("/api/generate").len(), 1);
+    cx.executor().advance_clock(PREFETCH_DELAY);
+    cx.executor().run_until_parked();
+    let requests = server.requests("/api/generate");
+    assert_eq!(requests.len(), 3);
+    assert_eq!(requests[2]["suffix"], "\n}\n");
+
+    refresh(&provider, &buffer, CURSOR, true, cx);
+    assert_eq!(
+        shown(&provider, &buffer, CURSOR, cx).as_deref(),
+        Some("a + b")
+    );
+    assert_eq!(server.requests("/api/generate").len(), 3);
+
+    // An edit makes what was prefetched stale.
+    refresh(&provider, &buffer, in_signature, false, cx);
+    cx.executor().advance_clock(PREFETCH_DELAY);
+    cx.executor().run_until_parked();
+    let end = TEXT.len();
+    buffer.update(cx, |buffer, cx| buffer.edit([(end..end, "\n")], None, cx));
+    refresh(&provider, &buffer, CURSOR, false, cx);
+    assert_eq!(server.requests("/api/generate").len(), 6);
+}
+
+#[gpui::test]
+async fn test_no_prefetch_in_low_power_mode(cx: &mut TestAppContext) {
+    init_test(cx, |settings| {
+        settings.prefetch_suggestions = Some(true);
+        settings.low_power_mode = Some(LowPowerMode::On);
+    });
+    let server = FakeOllamaServer::new();
+    server.set_completion("a + b");
+    let (provider, buffer) = build(&server, cx);
+
+    refresh(&provider, &buffer, TEXT.find("i32)").unwrap(), false, cx);
+    cx.executor().advance_clock(PREFETCH_DELAY);
+    cx.executor().run_until_parked();
+    assert_eq!(server.requests("/api/generate").len(), 1);
+}
+
+#[gpui::test]
+async fn test_accept_line_by_line(cx: &mut TestAppContext) {
+    init_test(cx, |_| {});
+    let server = FakeOllamaServer::new();
+    server.set_completion("let sum = a + b;\n    sum");
+    let (provider, buffer) = build(&server, cx);
+
+    refresh(&provider, &buffer, CURSOR, false, cx);
+    buffer.update(cx, |buffer, cx| {
+        buffer.edit([(CURSOR..CURSOR, "let ")], None, cx)
+    });
+    refresh(&provider, &buffer, CURSOR + 4, true, cx);
+    assert_eq!(
+        shown(&provider, &buffer, CURSOR + 4, cx).as_deref(),
+        Some("sum = a + b;\n    sum")
+    );
+
+    // What's typed of the suggestion before accepting its first line
+    // doesn't lose the lines after it.
+    let line_end = CURSOR + "let sum = a + b;".len();
+    let accept_line = |accepted: &str, cursor: usize, cx: &mut TestAppContext| {
+        let start = cursor - accepted.len();
+        buffer.update(cx, |buffer, cx| {
+            buffer.edit([(start..start, accepted)], None, cx)
+        });
+        let cursor = anchor(&buffer, cursor, cx);
+        provider.update(cx, |provider, cx| {
+            provider.accept_partial(accepted, PartialAccept::Line, &buffer, cursor, cx)
+        })
+    };
+    assert!(accept_line("sum = a + b;", line_end, cx));
+    assert_eq!(
+        shown(&provider, &buffer, line_end, cx).as_deref(),
+        Some("\n    sum")
+    );
+
+    // Nothing is left once the last line is accepted.
+    let end = line_end + "\n    sum".len();
+    assert!(!accept_line("\n    sum", end, cx));
+    assert_eq!(server.requests("/api/generate").len(), 1);
+}
+
+#[gpui::test]
+async fn test_rejected_partial_accept(cx: &mut TestAppContext) {
+    init_test(cx, |_| {});
+    let server = FakeOllamaServer::new();
+    server.set_completion("let sum = a + b;\n    sum");
+    let (provider, buffer) = build(&server, cx);
+    refresh(&provider, &buffer, CURSOR, false, cx);
+
+    // Text that isn't what's been typed of the suggestion isn't accepted
+    // from it, and the suggestion is still shown.
+    buffer.update(cx, |buffer, cx| {
+        buffer.edit([(CURSOR..CURSOR, "let x")], None, cx)
+    });
+    let cursor = anchor(&buffer, CURSOR + 5, cx);
+    assert!(!provider.update(cx, |provider, cx| {
+        provider.accept_partial("let x", PartialAccept::Word, &buffer, cursor, cx)
+    }));
+    buffer.update(cx, |buffer, cx| {
+        buffer.edit([(CURSOR..CURSOR + 5, "")], None, cx)
+    });
+    assert_eq!(
+        shown(&provider, &buffer, CURSOR, cx).as_deref(),
+        Some("let sum = a + b;\n    sum")
+    );
+
+    // Nor is text accepted in another buffer.
+    let other = cx.new_model(|cx| Buffer::local(TEXT, cx));
+    let cursor = anchor(&other, CURSOR, cx);
+    assert!(!provider.update(cx, |provider, cx| {
+        provider.accept_partial("let ", PartialAccept::Word, &other, cursor, cx)
+    }));
+    assert_eq!(
+        shown(&provider, &buffer, CURSOR, cx).as_deref(),
+        Some("let sum = a + b;\n    sum")
+    );
+}
+
+#[gpui::test]
+async fn test_repair_predicted_edit(cx: &mut TestAppContext) {
+    init_test(cx, |settings| settings.edit_prediction = Some(true));
+    let server = FakeOllamaServer::new();
+    // The first answer is cut off, the repaired one is complete.
+    server.complete_with(|request| {
+        let prompt = request["prompt"].as_str().unwrap();
+        if prompt.contains("Your previous response was") {
+            r#"{"anchor": "", "range": {"start_line": 2, "end_line": 2},
+                "replacement": "    a + b", "confidence": 0.9}"#
+                .into()
+        } else {
+            r#"{"anchor": "", "range": {"start_line": 2"#.into()
+        }
+    });
+    let (provider, buffer) = build(&server, cx);
+
+    refresh(&provider, &buffer, CURSOR, false, cx);
+    assert_eq!(
+        shown(&provider, &buffer, CURSOR, cx).as_deref(),
+        Some("a + b")
+    );
+    let requests = server.requests("/api/generate");
+    assert_eq!(requests.len(), 2);
+    assert!(requests[1]["prompt"]
+        .as_str()
+        .unwrap()
+        .contains("It can't be used, because the response isn't a valid edit object"));
+    assert_eq!(requests[1]["format"], requests[0]["format"]);
+
+    // A response that's still invalid isn't repaired again.
+    server.set_completion("not json");
+    refresh(&provider, &buffer, CURSOR, false, cx);
+    assert_eq!(server.requests("/api/generate").len(), 4);
+}
@@ -0,0 +1,204 @@
+mod ollama_assist;
+mod ollama_benchmark;
+mod ollama_buffer_snapshots;
+mod ollama_chat;
+mod ollama_chat_history;
+mod ollama_commit_message;
+mod ollama_completion_cache;
+mod ollama_completion_provider;
+#[cfg(test)]
+mod ollama_completion_provider_tests;
+mod ollama_context;
+mod ollama_context_reuse;
+mod ollama_debounce;
+mod ollama_diagnostics;
+mod ollama_disabled_buffers;
+mod ollama_docstring;
+mod ollama_edit_history;
+mod ollama_edit_memory;
+mod ollama_edit_prediction;
+mod ollama_endpoints;
+mod ollama_error_reports;
+mod ollama_eval;
+mod ollama_extension_context;
+mod ollama_fallback;
+mod ollama_file_header;
+mod ollama_generations;
+mod ollama_git_context;
+mod ollama_imports;
+mod ollama_model_substitute;
+mod ollama_modelfile;
+mod ollama_notebook;
+mod ollama_offline;
+mod ollama_performance;
+mod ollama_postprocess;
+mod ollama_power;
+mod ollama_prefetch;
+mod ollama_preview;
+mod ollama_project_layout;
+mod ollama_prompt;
+mod ollama_prompt_template;
+mod ollama_provider_chain;
+mod ollama_redact;
+mod ollama_rename;
+mod ollama_request_log;
+mod ollama_schema_context;
+mod ollama_settings;
+mod ollama_slash_command;
+mod ollama_speed;
+mod ollama_stats;
+mod ollama_stop;
+mod ollama_streaming;
+mod ollama_suggestion;
+mod ollama_terminal;
+mod ollama_test_generation;
+mod ollama_tls;
+mod ollama_tokens;
+mod ollama_transform;
+mod ollama_trigger;
+mod ollama_verbatim;
+mod ollama_warm_up;
+
+pub use ollama_assist::strip_code_fence;
+pub(crate) use ollama_assist::{message_content, selection_or_symbol};
+pub use ollama_benchmark::{
+    benchmark_model, benchmark_report, BenchmarkStats, ModelBenchmark, BENCHMARK_RUNS,
+};
+pub use ollama_buffer_snapshots::BufferSnapshots;
+pub use ollama_chat::chat_editor;
+pub use ollama_chat_history::{import_chat, parse_chat, ChatHistory, ChatThread};
+pub(crate) use ollama_chat_history::{ASSISTANT_HEADING, USER_HEADING};
+pub use ollama_commit_message::file_repository;
+pub(crate) use ollama_commit_message::{is_commit_message, staged_changes_context};
+pub use ollama_completion_cache::{request_key, PersistentCache};
+pub use ollama_completion_provider::*;
+pub(crate) use ollama_context::{prompt_window, split_prompt_window, TEMPLATE_TOKENS};
+pub use ollama_context::{ContextWindows, DEFAULT_NUM_CTX};
+pub(crate) use ollama_context_reuse::ReusableContext;
+pub use ollama_debounce::AdaptiveDebounce;
+pub(crate) use ollama_diagnostics::cursor_line_diagnostics;
+pub use ollama_disabled_buffers::DisabledBuffers;
+pub use ollama_edit_history::EditHistory;
+pub use ollama_edit_memory::EditMemory;
+pub(crate) use ollama_edit_memory::EditMemoryRequest;
+pub(crate) use ollama_edit_prediction::{
+    edit_prediction_request, edit_repair_request, parse_predicted_edit,
+};
+pub use ollama_edit_prediction::{LineRange, PredictedEdit};
+pub(crate) use ollama_endpoints::ping;
+pub use ollama_endpoints::EndpointPool;
+pub use ollama_error_reports::ErrorReports;
+pub use ollama_eval::{
+    parse_fixtures, run_completion, run_eval, CompletionRun, EvalFixture, EvalMatch, EvalReport,
+    EvalResult, CURSOR_MARKER,
+};
+pub(crate) use ollama_extension_context::ExtensionContextRequest;
+pub use ollama_extension_context::{
+    CompletionContextProvider, CompletionContextProviders, ExtensionCompletionContextProvider,
+};
+pub use ollama_fallback::*;
+pub(crate) use ollama_file_header::{file_header, is_import_kind};
+pub use ollama_generations::Generations;
+pub(crate) use ollama_git_context::{git_changes_context, UnstagedHunk};
+pub use ollama_imports::ImportedSignatures;
+pub(crate) use ollama_imports::{normalize, ImportsContextRequest};
+pub(crate) use ollama_model_substitute::substitute_model;
+pub use ollama_model_substitute::{ModelSubstituted, ModelSubstitutes};
+pub use ollama_modelfile::{parse_modelfile, variant_modelfile, Modelfile};
+pub use ollama_offline::Connectivity;
+pub use ollama_performance::PerformanceReport;
+pub use ollama_postprocess::*;
+pub use ollama_power::PowerState;
+pub use ollama_prefetch::PREFETCH_DELAY;
+pub(crate) use ollama_prefetch::{prefetch_positions, Prefetched, PrefetchedSuggestion};
+pub(crate) use ollama_project_layout::project_layout_context;
+pub(crate) use ollama_prompt::{PromptBuilder, PromptContext, MAX_COMPLETION_TOKENS};
+pub(crate) use ollama_prompt_template::{apply_prompt_template, render_placeholders};
+pub use ollama_provider_chain::ChainedCompletionProvider;
+pub use ollama_redact::{SecretRedactor, REDACTED};
+pub(crate) use ollama_rename::{name_after_typed, RenameTarget};
+pub(crate) use ollama_request_log::trace;
+pub use ollama_request_log::{
+    LoggedRequest, RequestId, RequestLog, RequestOutcome, TraceEvent, TracedEvent,
+};
+pub use ollama_schema_context::JsonSchemas;
+pub(crate) use ollama_schema_context::SchemaContextRequest;
+pub use ollama_settings::*;
+pub use ollama_slash_command::SlashCommandInputs;
+pub use ollama_speed::GenerationSpeed;
+pub use ollama_stats::{AcceptanceCounts, CompletionStats, LatencyTotals};
+pub use ollama_stop::builtin_stop;
+pub(crate) use ollama_streaming::collect_stream;
+pub(crate) use ollama_suggestion::PendingSuggestion;
+pub use ollama_tls::http_client_for;
+pub use ollama_tokens::count_tokens;
+pub(crate) use ollama_tokens::{count_line_tokens, head_within, tail_within};
+pub use ollama_transform::{replace_range, transform_range};
+pub(crate) use ollama_trigger::{has_min_prefix, is_mid_identifier};
+pub(crate) use ollama_verbatim::{Fingerprint, VerbatimCheck, MAX_VERBATIM_CANDIDATES};
+pub use ollama_warm_up::{heartbeat, warm_up};
+
+use gpui::AppContext;
+use ollama::RequestQueue;
+use settings::{Settings as _, SettingsStore};
+
+/// Whether Ollama features may talk to a server at all. Everything that sends
+/// requests on its own initiative checks this first.
+pub fn is_enabled(cx: &AppContext) -> bool {
+    OllamaSettings::get_global(cx).enabled
+}
+
+/// The error returned for requests attempted while `ollama.enabled` is off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OllamaDisabled;
+
+impl std::fmt::Display for OllamaDisabled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Ollama is turned off by the `ollama.enabled` setting")
+    }
+}
+
+impl std::error::Error for OllamaDisabled {}
+
+pub fn init(cx: &mut AppContext) {
+    OllamaSettings::register(cx);
+    ollama_endpoints::init(cx);
+    ollama_offline::init(cx);
+    ollama_error_reports::init(cx);
+    ollama_model_substitute::init(cx);
+    init_request_queue(cx);
+    ollama_context::init(cx);
+    ollama_power::init(cx);
+    ollama_buffer_snapshots::init(cx);
+    ollama_edit_history::init(cx);
+    ollama_edit_memory::init(cx);
+    ollama_imports::init(cx);
+    ollama_schema_context::init(cx);
+    ollama_stats::init(cx);
+    ollama_performance::init(cx);
+    ollama_extension_context::init(cx);
+    ollama_completion_cache::init(cx);
+    ollama_speed::init(cx);
+    ollama_generations::init(cx);
+    ollama_trigger::init(cx);
+    ollama_disabled_buffers::init(cx);
+    ollama_request_log::init(cx);
+    ollama_chat_history::init(cx);
+    ollama_assist::init(cx);
+    ollama_commit_message::init(cx);
+    ollama_docstring::init(cx);
+    ollama_preview::init(cx);
+    ollama_rename::init(cx);
+    ollama_test_generation::init(cx);
+    ollama_terminal::init(cx);
+    ollama_notebook::init(cx);
+}
+
+fn init_request_queue(cx: &mut AppContext) {
+    let queue = RequestQueue::global();
+    queue.set_max_concurrent(OllamaSettings::get_global(cx).max_concurrent_requests);
+    cx.observe_global::<SettingsStore>(move |cx| {
+        queue.set_max_concurrent(OllamaSettings::get_global(cx).max_concurrent_requests);
+    })
+    .detach();
+}
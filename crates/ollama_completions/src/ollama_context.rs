@@ -0,0 +1,270 @@
+use crate::{count_line_tokens, head_within, tail_within, ApiFormat, OllamaSettings, PromptBudget};
+use gpui::{AppContext, Global};
+use http_client::HttpClient;
+use ollama::show_model;
+use parking_lot::Mutex;
+use std::{collections::HashMap, sync::Arc};
+
+/// The context window Ollama uses when neither settings nor the model's
+/// Modelfile set `num_ctx`.
+pub const DEFAULT_NUM_CTX: usize = 2048;
+/// Tokens set aside for the special tokens of the fill-in-the-middle template.
+pub(crate) const TEMPLATE_TOKENS: usize = 32;
+
+#[derive(Default)]
+struct GlobalContextWindows(Arc<ContextWindows>);
+
+impl Global for GlobalContextWindows {}
+
+/// The context window of each completion model, and whether it can fill in
+/// the middle, looked up from each server the first time the model is used
+/// with it. Servers can run the same model with different settings.
+#[derive(Default)]
+pub struct ContextWindows {
+    /// By server and model. `None` while a lookup is in flight.
+    windows: Mutex<HashMap<(String, String), Option<ModelWindow>>>,
+}
+
+#[derive(Clone, Copy)]
+struct ModelWindow {
+    tokens: usize,
+    supports_fim: bool,
+}
+
+pub(crate) fn init(cx: &mut AppContext) {
+    cx.set_global(GlobalContextWindows::default());
+}
+
+impl ContextWindows {
+    pub fn global(cx: &AppContext) -> Arc<Self> {
+        cx.global::<GlobalContextWindows>().0.clone()
+    }
+
+    /// How many tokens `model`, a model or profile name, reads: `num_ctx` from
+    /// the profile or settings, else what the server at `api_url` reported for
+    /// the model. Until the server has answered, Ollama's default is assumed
+    /// and a lookup is started in the background. OpenAI-compatible servers
+    /// can't be asked, so the default is used.
+    pub fn context_tokens(
+        self: &Arc<Self>,
+        settings: &OllamaSettings,
+        api_url: &str,
+        model: &str,
+        http_client: &Arc<dyn HttpClient>,
+        cx: &AppContext,
+    ) -> usize {
+        if let Some(num_ctx) = settings.runtime_options(model).num_ctx {
+            return num_ctx;
+        }
+        if settings.api_format == ApiFormat::OpenAi {
+            return DEFAULT_NUM_CTX;
+        }
+        let key = (
+            api_url.to_string(),
+            settings.resolve_model(model).to_string(),
+        );
+        {
+            let mut windows = self.windows.lock();
+            match windows.get(&key) {
+                Some(window) => return window.map_or(DEFAULT_NUM_CTX, |window| window.tokens),
+                None => windows.insert(key.clone(), None),
+            };
+        }
+
+        let this = self.clone();
+        let http_client = http_client.clone();
+        let api_key = settings.effective_api_key();
+        cx.background_executor()
+            .spawn(async move {
+                let (api_url, model) = &key;
+                match show_model(http_client.as_ref(), api_url, api_key.as_deref(), model).await {
+                    Ok(response) => {
+                        let num_ctx = response.num_ctx().unwrap_or(DEFAULT_NUM_CTX);
+                        let window = ModelWindow {
+                            tokens: num_ctx.min(response.context_length().unwrap_or(num_ctx)),
+                            supports_fim: response.supports_fim(),
+                        };
+                        if !window.supports_fim {
+                            log::info!(
+                                "{model} can't fill in the middle, so completions only continue \
+                                the text before the cursor"
+                            );
+                        }
+                        this.windows.lock().insert(key, Some(window));
+                    }
+                    Err(error) => {
+                        log::debug!(
+                            "Failed to look up the context window of {model} on {api_url}: \
+                            {error:?}"
+                        );
+                        this.windows.lock().remove(&key);
+                    }
+                }
+            })
+            .detach();
+        DEFAULT_NUM_CTX
+    }
+
+    /// Forgets what the server said about each model, so they're looked up
+    /// again, from the servers now in the settings, when next used.
+    pub(crate) fn clear(&self) {
+        self.windows.lock().clear();
+    }
+
+    /// Whether `model`, a model name, can take the text after the cursor as a
+    /// suffix on the server at `api_url`, once the server has said so. Models
+    /// are assumed to until then, and are only looked up by
+    /// [`Self::context_tokens`].
+    pub fn supports_fim(&self, api_url: &str, model: &str) -> bool {
+        self.windows
+            .lock()
+            .get(&(api_url.to_string(), model.to_string()))
+            .copied()
+            .flatten()
+            .map_or(true, |window| window.supports_fim)
+    }
+}
+
+/// How many bytes at the end of `prefix` and the start of `suffix` fit in the
+/// prompt, given the model's context window and the tokens reserved for the
+/// completion. When both don't fit, the token budget is split between them in
+/// proportion, and whole lines farthest from the cursor are dropped first.
+pub(crate) fn prompt_window(
+    prefix: &str,
+    suffix: &str,
+    context_tokens: usize,
+    completion_tokens: usize,
+) -> (usize, usize) {
+    let budget = context_tokens.saturating_sub(completion_tokens + TEMPLATE_TOKENS);
+    let prefix_tokens = count_line_tokens(prefix);
+    let suffix_tokens = count_line_tokens(suffix);
+    let total = prefix_tokens + suffix_tokens;
+    if total <= budget {
+        return (prefix.len(), suffix.len());
+    }
+    let (prefix_len, used) = tail_within(prefix, budget * prefix_tokens / total);
+    // Whatever the prefix leaves unused goes to the suffix.
+    let (suffix_len, _) = head_within(suffix, budget - used);
+    (prefix_len, suffix_len)
+}
+
+/// Like [`prompt_window`], but also fitting `extra_context`, with the token
+/// budget shared between the three according to `budget`. Returns how many
+/// bytes at the start of `extra_context`, the end of `prefix` and the start
+/// of `suffix` fit. What one part doesn't need of its share goes to the
+/// others, and `None` is returned when `budget` leaves nothing for the text
+/// around the cursor.
+pub(crate) fn split_prompt_window(
+    prefix: &str,
+    suffix: &str,
+    extra_context: &str,
+    context_tokens: usize,
+    completion_tokens: usize,
+    budget: PromptBudget,
+) -> Option<(usize, usize, usize)> {
+    let prefix_share = budget.prefix.max(0.);
+    let suffix_share = budget.suffix.max(0.);
+    let context_share = budget.context.max(0.);
+    let window_share = prefix_share + suffix_share;
+    if window_share <= 0. || !(window_share + context_share).is_finite() {
+        return None;
+    }
+
+    let tokens = context_tokens.saturating_sub(completion_tokens + TEMPLATE_TOKENS);
+    let (context_len, context_used) = head_within(
+        extra_context,
+        (tokens as f32 * context_share / (window_share + context_share)) as usize,
+    );
+    let tokens = tokens - context_used;
+    let suffix_tokens = count_line_tokens(suffix);
+    let prefix_tokens = if suffix_tokens as f32 <= tokens as f32 * suffix_share / window_share {
+        tokens - suffix_tokens
+    } else {
+        (tokens as f32 * prefix_share / window_share) as usize
+    };
+    let (prefix_len, used) = tail_within(prefix, prefix_tokens);
+    let (suffix_len, _) = head_within(suffix, tokens - used);
+    Some((context_len, prefix_len, suffix_len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::count_tokens;
+
+    #[test]
+    fn test_prompt_window() {
+        let line = "let x = 1;\n";
+        let line_tokens = count_tokens(line);
+        let prefix = line.repeat(40);
+        let suffix = line.repeat(10);
+
+        let context = |lines: usize| lines * line_tokens + TEMPLATE_TOKENS + 128;
+        assert_eq!(
+            prompt_window(&prefix, &suffix, context(50), 128),
+            (prefix.len(), suffix.len())
+        );
+        // Split 4:1 between the text before and after the cursor.
+        assert_eq!(
+            prompt_window(&prefix, &suffix, context(10), 128),
+            (8 * line.len(), 2 * line.len())
+        );
+        assert_eq!(
+            prompt_window(&prefix, "", context(10), 128),
+            (10 * line.len(), 0)
+        );
+        assert_eq!(prompt_window(&prefix, &suffix, 100, 128), (0, 0));
+    }
+
+    #[test]
+    fn test_split_prompt_window() {
+        let line = "let x = 1;\n";
+        let line_tokens = count_tokens(line);
+        let prefix = line.repeat(40);
+        let suffix = line.repeat(40);
+        let extra_context = "// edited\n".repeat(40);
+        let context_line_tokens = count_tokens("// edited\n");
+        let budget = |prefix, suffix, context| PromptBudget {
+            prefix,
+            suffix,
+            context,
+        };
+
+        let context = |tokens: usize| tokens + TEMPLATE_TOKENS + 128;
+        let tokens = 20 * line_tokens.max(context_line_tokens);
+        let (context_len, prefix_len, suffix_len) = split_prompt_window(
+            &prefix,
+            &suffix,
+            &extra_context,
+            context(tokens),
+            128,
+            budget(70., 20., 10.),
+        )
+        .unwrap();
+        assert_eq!(
+            context_len,
+            tokens / 10 / context_line_tokens * "// edited\n".len()
+        );
+        assert!(prefix_len > 2 * suffix_len);
+        assert_eq!(prefix_len % line.len(), 0);
+
+        // A short suffix leaves the rest of its share to the prefix.
+        let short_suffix = line.repeat(1);
+        let (_, prefix_len, suffix_len) = split_prompt_window(
+            &prefix,
+            &short_suffix,
+            "",
+            context(10 * line_tokens),
+            128,
+            budget(50., 50., 0.),
+        )
+        .unwrap();
+        assert_eq!((prefix_len, suffix_len), (9 * line.len(), line.len()));
+
+        // The text around the cursor needs some share.
+        assert_eq!(
+            split_prompt_window(&prefix, &suffix, "", 1000, 128, budget(0., 0., 1.)),
+            None
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use crate::count_tokens;
+use ollama::{GenerateRequest, GenerateResponse};
+use text::{Anchor, BufferSnapshot, ToOffset as _};
+
+/// The `context` Ollama returned for the last suggestion, which encodes the
+/// prompt and the response in the model's tokens. Sending it back with only
+/// the text typed since lets the server skip processing the prompt again.
+///
+/// The server puts the decoded context in front of the new prompt, so this
+/// only works for plain continuations: a fill-in-the-middle prompt would end
+/// up after the previous one instead of replacing it.
+#[derive(Clone)]
+pub(crate) struct ReusableContext {
+    snapshot: BufferSnapshot,
+    /// Where the cursor was when the context's prompt was sent.
+    cursor: Anchor,
+    model: String,
+    system: Option<String>,
+    response: String,
+    tokens: Vec<u32>,
+}
+
+impl ReusableContext {
+    /// The context of `response`, if `request` is a plain continuation sent
+    /// at `cursor_offset` in `snapshot`.
+    pub fn new(
+        snapshot: &BufferSnapshot,
+        cursor_offset: usize,
+        request: &GenerateRequest,
+        response: &GenerateResponse,
+    ) -> Option<Self> {
+        if !is_continuation(request) {
+            return None;
+        }
+        Some(Self {
+            snapshot: snapshot.clone(),
+            cursor: snapshot.anchor_before(cursor_offset),
+            model: request.model.clone(),
+            system: request.system.clone(),
+            response: response.response.clone(),
+            tokens: response.context.clone()?,
+        })
+    }
+
+    /// Replaces `request`'s prompt with the text typed since this context's
+    /// request and attaches the context, if the text before the cursor only
+    /// grew by the previous response followed by more typing. `context_tokens`
+    /// bounds how long the reused context may get.
+    pub fn reuse(
+        &self,
+        snapshot: &BufferSnapshot,
+        cursor_offset: usize,
+        request: &mut GenerateRequest,
+        context_tokens: usize,
+    ) -> bool {
+        if snapshot.remote_id() != self.snapshot.remote_id()
+            || !is_continuation(request)
+            || request.model != self.model
+            || request.system != self.system
+        {
+            return false;
+        }
+        let previous_cursor = self.cursor.to_offset(snapshot);
+        if previous_cursor > cursor_offset
+            || snapshot
+                .edits_since::<usize>(self.snapshot.version())
+                .any(|edit| edit.new.start < previous_cursor)
+        {
+            return false;
+        }
+        let typed = snapshot
+            .text_for_range(previous_cursor..cursor_offset)
+            .collect::<String>();
+        let Some(continuation) = typed.strip_prefix(&self.response) else {
+            return false;
+        };
+        if continuation.is_empty() {
+            return false;
+        }
+        let num_predict = request
+            .options
+            .as_ref()
+            .and_then(|options| options.num_predict)
+            .unwrap_or_default()
+            .max(0) as usize;
+        if self.tokens.len() + count_tokens(continuation) + num_predict > context_tokens {
+            return false;
+        }
+        request.prompt = continuation.to_string();
+        request.context = Some(self.tokens.clone());
+        true
+    }
+}
+
+/// Whether `request` continues its prompt, with nothing after the cursor and
+/// the model's own template applied.
+fn is_continuation(request: &GenerateRequest) -> bool {
+    request.suffix.as_deref().map_or(true, str::is_empty)
+        && request.raw.is_none()
+        && request.format.is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ollama::{ChatOptions, KeepAlive, RequestPriority};
+    use text::{Buffer, BufferId};
+
+    #[test]
+    fn test_reusable_context() {
+        let mut buffer = Buffer::new(0, BufferId::new(1).unwrap(), "fn main() {\n    let".into());
+        let request = |prompt: &str| GenerateRequest {
+            model: "qwen2.5-coder:1.5b".into(),
+            prompt: prompt.into(),
+            suffix: Some(String::new()),
+            stream: false,
+            keep_alive: KeepAlive::default(),
+            options: Some(ChatOptions {
+                num_predict: Some(8),
+                ..Default::default()
+            }),
+            system: None,
+            raw: None,
+            format: None,
+            context: None,
+            priority: RequestPriority::Automatic,
+        };
+        let response: GenerateResponse = serde_json::from_value(serde_json::json!({
+            "model": "qwen2.5-coder:1.5b",
+            "created_at": "2024-10-01T12:00:00Z",
+            "response": " x",
+            "done": true,
+            "context": [1, 2, 3, 4],
+        }))
+        .unwrap();
+        let first = buffer.snapshot();
+        let cursor = first.len();
+        let context =
+            ReusableContext::new(&first, cursor, &request(&first.text()), &response).unwrap();
+
+        // The user accepted the suggestion and kept typing.
+        buffer.edit([(cursor..cursor, " x = 1;")]);
+        let snapshot = buffer.snapshot();
+        let mut continued = request(&snapshot.text());
+        assert!(context.reuse(&snapshot, snapshot.len(), &mut continued, 100));
+        assert_eq!(continued.prompt, " = 1;");
+        assert_eq!(continued.context, Some(vec![1, 2, 3, 4]));
+
+        // Not when the context would outgrow the window.
+        assert!(!context.reuse(&snapshot, snapshot.len(), &mut request(""), 10));
+
+        // Not when the user typed something other than the suggestion.
+        let mut buffer = Buffer::new(0, BufferId::new(1).unwrap(), first.text());
+        buffer.edit([(cursor..cursor, " y")]);
+        let snapshot = buffer.snapshot();
+        assert!(!context.reuse(&snapshot, snapshot.len(), &mut request(""), 100));
+
+        // Not when the text before the previous cursor changed.
+        let mut buffer = Buffer::new(0, BufferId::new(1).unwrap(), first.text());
+        buffer.edit([(cursor..cursor, " x = 1;")]);
+        buffer.edit([(0..0, "// main\n")]);
+        let snapshot = buffer.snapshot();
+        assert!(!context.reuse(&snapshot, snapshot.len(), &mut request(""), 100));
+
+        // Never for fill-in-the-middle requests.
+        let mut fill_in = request("");
+        fill_in.suffix = Some("\n}".into());
+        assert!(ReusableContext::new(&first, cursor, &fill_in, &response).is_none());
+    }
+}
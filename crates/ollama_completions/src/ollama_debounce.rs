@@ -0,0 +1,92 @@
+use std::time::{Duration, Instant};
+
+/// Keystrokes further apart than this are treated as a pause rather than
+/// part of the user's typing rhythm.
+const MAX_TYPING_INTERVAL: Duration = Duration::from_secs(1);
+/// Weight given to the newest sample in the smoothed averages.
+const SMOOTHING: f64 = 0.3;
+/// How much longer than the typical gap between keystrokes to wait, so a
+/// request isn't sent in the middle of a burst of typing.
+const TYPING_SLACK: f64 = 1.5;
+
+/// Stretches the delay before a completion request to fit how fast the user
+/// is typing and how long the model has been taking to answer, so slow models
+/// aren't sent requests faster than they can serve them.
+#[derive(Debug, Default)]
+pub struct AdaptiveDebounce {
+    last_keystroke: Option<Instant>,
+    typing_interval: Option<Duration>,
+    last_request: Option<Instant>,
+    latency: Option<Duration>,
+}
+
+impl AdaptiveDebounce {
+    pub fn record_keystroke(&mut self, now: Instant) {
+        if let Some(last) = self.last_keystroke.replace(now) {
+            let interval = now.saturating_duration_since(last);
+            if interval <= MAX_TYPING_INTERVAL {
+                self.typing_interval = Some(smooth(self.typing_interval, interval));
+            }
+        }
+    }
+
+    pub fn record_request(&mut self, now: Instant) {
+        self.last_request = Some(now);
+    }
+
+    pub fn record_latency(&mut self, latency: Duration) {
+        self.latency = Some(smooth(self.latency, latency));
+    }
+
+    /// How long to wait before sending a request, between `min` and `max`.
+    pub fn delay(&self, now: Instant, min: Duration, max: Duration) -> Duration {
+        let typing = self
+            .typing_interval
+            .map_or(Duration::ZERO, |interval| interval.mul_f64(TYPING_SLACK));
+        // Leave at least one typical response time between requests.
+        let model = match (self.latency, self.last_request) {
+            (Some(latency), Some(last_request)) => {
+                latency.saturating_sub(now.saturating_duration_since(last_request))
+            }
+            _ => Duration::ZERO,
+        };
+        typing.max(model).clamp(min, max.max(min))
+    }
+}
+
+fn smooth(previous: Option<Duration>, sample: Duration) -> Duration {
+    match previous {
+        Some(previous) => previous.mul_f64(1. - SMOOTHING) + sample.mul_f64(SMOOTHING),
+        None => sample,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay_adapts_to_typing_and_latency() {
+        let min = Duration::from_millis(75);
+        let max = Duration::from_secs(1);
+        let start = Instant::now();
+        let mut debounce = AdaptiveDebounce::default();
+        assert_eq!(debounce.delay(start, min, max), min);
+
+        debounce.record_keystroke(start);
+        debounce.record_keystroke(start + Duration::from_millis(200));
+        let now = start + Duration::from_millis(200);
+        assert_eq!(debounce.delay(now, min, max), Duration::from_millis(300));
+
+        debounce.record_request(now);
+        debounce.record_latency(Duration::from_millis(800));
+        assert_eq!(debounce.delay(now, min, max), Duration::from_millis(800));
+        assert_eq!(
+            debounce.delay(now + Duration::from_millis(600), min, max),
+            Duration::from_millis(300)
+        );
+
+        debounce.record_latency(Duration::from_secs(10));
+        assert_eq!(debounce.delay(now, min, max), max);
+    }
+}
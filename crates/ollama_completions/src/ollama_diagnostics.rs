@@ -0,0 +1,75 @@
+use language::{BufferSnapshot, Point};
+use lsp::DiagnosticSeverity;
+use std::fmt::Write as _;
+
+/// More diagnostics than this on the cursor's line are left out.
+const MAX_DIAGNOSTICS: usize = 4;
+
+/// The errors and warnings on the cursor's line as line comments, so the model
+/// proposes a fix for them rather than continuing the text as is. Empty when
+/// the line has none.
+pub(crate) fn cursor_line_diagnostics(
+    snapshot: &BufferSnapshot,
+    cursor_offset: usize,
+    comment_prefix: &str,
+) -> String {
+    let row = snapshot.offset_to_point(cursor_offset).row;
+    let line = Point::new(row, 0)..Point::new(row, snapshot.line_len(row));
+    let diagnostics = snapshot
+        .diagnostics_in_range::<_, Point>(line, false)
+        .filter(|entry| entry.diagnostic.is_primary)
+        .filter_map(|entry| {
+            let severity = match entry.diagnostic.severity {
+                DiagnosticSeverity::ERROR => "error",
+                DiagnosticSeverity::WARNING => "warning",
+                _ => return None,
+            };
+            Some((severity, entry.diagnostic.message))
+        })
+        .take(MAX_DIAGNOSTICS)
+        .collect::<Vec<_>>();
+    diagnostics_comment(comment_prefix, row, &diagnostics)
+}
+
+fn diagnostics_comment(comment_prefix: &str, row: u32, diagnostics: &[(&str, String)]) -> String {
+    if diagnostics.is_empty() {
+        return String::new();
+    }
+    let mut comment = format!("{comment_prefix}Fix the problems on line {}:\n", row + 1);
+    for (severity, message) in diagnostics {
+        let mut lines = message.lines();
+        let first_line = lines.next().unwrap_or_default();
+        writeln!(comment, "{comment_prefix}{severity}: {first_line}").ok();
+        for line in lines {
+            writeln!(comment, "{comment_prefix}  {line}").ok();
+        }
+    }
+    comment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnostics_comment() {
+        assert_eq!(diagnostics_comment("// ", 4, &[]), "");
+        assert_eq!(
+            diagnostics_comment(
+                "// ",
+                11,
+                &[
+                    (
+                        "error",
+                        "mismatched types\nexpected `u32`, found `usize`".into()
+                    ),
+                    ("warning", "unused variable: `x`".into()),
+                ]
+            ),
+            "// Fix the problems on line 12:\n\
+             // error: mismatched types\n\
+             //   expected `u32`, found `usize`\n\
+             // warning: unused variable: `x`\n"
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use db::kvp::KEY_VALUE_STORE;
+use editor::{Editor, EditorMode};
+use gpui::{AppContext, EntityId, Global};
+use language::{Buffer, BufferId, File};
+use parking_lot::Mutex;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+};
+use util::ResultExt as _;
+use workspace::Workspace;
+use zed_actions::ollama::ToggleBufferCompletions;
+
+const DISABLED_PATHS_KEY_PREFIX: &str = "ollama_disabled_paths";
+
+#[derive(Default)]
+struct GlobalDisabledBuffers(Arc<DisabledBuffers>);
+
+impl Global for GlobalDisabledBuffers {}
+
+/// Files and buffers Ollama completions were turned off for with `ollama:
+/// toggle buffer completions`, whatever the settings say. Files are
+/// remembered by path for the workspace they were turned off in, in Zed's
+/// local database. Buffers without a file, or outside a saved workspace, are
+/// only remembered until Zed quits.
+#[derive(Default)]
+pub struct DisabledBuffers {
+    /// The files turned off in each open workspace, by the workspace's project.
+    workspaces: Mutex<HashMap<EntityId, WorkspaceFiles>>,
+    buffers: Mutex<HashSet<BufferId>>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct WorkspaceFiles {
+    /// What the files are saved as in the database.
+    key: String,
+    paths: BTreeSet<PathBuf>,
+    /// The files toggled before the saved ones were loaded, to be toggled
+    /// again on top of them. `None` once they're loaded.
+    toggled_while_loading: Option<Vec<PathBuf>>,
+}
+
+pub(crate) fn init(cx: &mut AppContext) {
+    cx.set_global(GlobalDisabledBuffers::default());
+
+    cx.observe_new_views(|workspace: &mut Workspace, cx| {
+        let (Some(disabled), Some(workspace_id)) =
+            (DisabledBuffers::global(cx), workspace.database_id())
+        else {
+            return;
+        };
+        let project = workspace.project().entity_id();
+        let key = format!("{DISABLED_PATHS_KEY_PREFIX}-{}", i64::from(workspace_id));
+        disabled
+            .workspaces
+            .lock()
+            .insert(project, WorkspaceFiles::loading(key.clone()));
+        cx.background_executor()
+            .spawn({
+                let disabled = disabled.clone();
+                async move {
+                    let saved = match KEY_VALUE_STORE.read_kvp(&key)? {
+                        Some(saved) => serde_json::from_str(&saved)?,
+                        None => BTreeSet::new(),
+                    };
+                    let save = disabled
+                        .workspaces
+                        .lock()
+                        .get_mut(&project)
+                        .and_then(|files| files.finish_loading(saved));
+                    if let Some(paths) = save {
+                        KEY_VALUE_STORE.write_kvp(key, paths).await?;
+                    }
+                    anyhow::Ok(())
+                }
+            })
+            .detach_and_log_err(cx);
+        cx.on_release(move |_, _, _| {
+            disabled.workspaces.lock().remove(&project);
+        })
+        .detach();
+    })
+    .detach();
+
+    cx.observe_new_views(|editor: &mut Editor, cx| {
+        if editor.mode() != EditorMode::Full {
+            return;
+        }
+        editor
+            .register_action(cx.listener(|editor, _: &ToggleBufferCompletions, cx| {
+                let cursor = editor.selections.newest_anchor().head();
+                let Some((buffer, _)) = editor
+                    .buffer()
+                    .read(cx)
+                    .text_anchor_for_position(cursor, cx)
+                else {
+                    return;
+                };
+                let project = editor.project().map(|project| project.entity_id());
+                if let Some(disabled) = DisabledBuffers::global(cx) {
+                    disabled.toggle(buffer.read(cx), project, cx);
+                }
+                // Hides a suggestion that's showing, or asks for one.
+                editor.refresh_inline_completion(false, false, cx);
+            }))
+            .detach();
+    })
+    .detach();
+}
+
+impl DisabledBuffers {
+    pub fn global(cx: &AppContext) -> Option<Arc<Self>> {
+        cx.try_global::<GlobalDisabledBuffers>()
+            .map(|disabled| disabled.0.clone())
+    }
+
+    /// Whether completions were turned off for `buffer`, or its file in the
+    /// workspace of `project`.
+    pub fn is_disabled(&self, buffer: &Buffer, project: Option<EntityId>, cx: &AppContext) -> bool {
+        if let Some((file, project)) = buffer.file().zip(project) {
+            if let Some(files) = self.workspaces.lock().get(&project) {
+                return files.paths.contains(&file_key(file.as_ref(), cx));
+            }
+        }
+        self.buffers.lock().contains(&buffer.remote_id())
+    }
+
+    /// Turns completions for `buffer` off, or back on, returning whether
+    /// they're now off. Files in a saved workspace are saved in the
+    /// background.
+    pub fn toggle(&self, buffer: &Buffer, project: Option<EntityId>, cx: &AppContext) -> bool {
+        if let Some((file, project)) = buffer.file().zip(project) {
+            let mut workspaces = self.workspaces.lock();
+            if let Some(files) = workspaces.get_mut(&project) {
+                let (disabled, save) = files.toggle(file_key(file.as_ref(), cx));
+                if let Some(paths) = save {
+                    let key = files.key.clone();
+                    cx.background_executor()
+                        .spawn(async move { KEY_VALUE_STORE.write_kvp(key, paths).await })
+                        .detach_and_log_err(cx);
+                }
+                return disabled;
+            }
+        }
+        let mut buffers = self.buffers.lock();
+        let id = buffer.remote_id();
+        if buffers.remove(&id) {
+            false
+        } else {
+            buffers.insert(id);
+            true
+        }
+    }
+}
+
+impl WorkspaceFiles {
+    fn loading(key: String) -> Self {
+        Self {
+            key,
+            paths: BTreeSet::new(),
+            toggled_while_loading: Some(Vec::new()),
+        }
+    }
+
+    /// Turns `path` off, or back on, returning whether it's now off and what
+    /// to save. Nothing is saved until the saved files are loaded, so they
+    /// aren't overwritten.
+    fn toggle(&mut self, path: PathBuf) -> (bool, Option<String>) {
+        let disabled = !self.paths.remove(&path);
+        if disabled {
+            self.paths.insert(path.clone());
+        }
+        if let Some(toggled) = self.toggled_while_loading.as_mut() {
+            toggled.push(path);
+            return (disabled, None);
+        }
+        (disabled, serde_json::to_string(&self.paths).log_err())
+    }
+
+    /// Replaces the files with the `saved` ones, then toggles those toggled
+    /// in the meantime again, returning what to save if any were.
+    fn finish_loading(&mut self, saved: BTreeSet<PathBuf>) -> Option<String> {
+        let toggled = self.toggled_while_loading.take()?;
+        self.paths = saved;
+        if toggled.is_empty() {
+            return None;
+        }
+        for path in toggled {
+            if !self.paths.remove(&path) {
+                self.paths.insert(path);
+            }
+        }
+        serde_json::to_string(&self.paths).log_err()
+    }
+}
+
+/// The path a file is remembered by: its absolute path when it's on this
+/// machine, or its path including the worktree's name otherwise.
+fn file_key(file: &dyn File, cx: &AppContext) -> PathBuf {
+    match file.as_local() {
+        Some(file) => file.abs_path(cx),
+        None => file.full_path(cx),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gpui::{Context as _, TestAppContext};
+
+    #[gpui::test]
+    fn test_toggle_untitled_buffer(cx: &mut TestAppContext) {
+        let disabled = DisabledBuffers::default();
+        let buffer = cx.new_model(|cx| Buffer::local("fn main() {}\n", cx));
+        let other = cx.new_model(|cx| Buffer::local("", cx));
+
+        cx.update(|cx| {
+            assert!(!disabled.is_disabled(buffer.read(cx), None, cx));
+            assert!(disabled.toggle(buffer.read(cx), None, cx));
+            assert!(disabled.is_disabled(buffer.read(cx), None, cx));
+            assert!(!disabled.is_disabled(other.read(cx), None, cx));
+            assert!(!disabled.toggle(buffer.read(cx), None, cx));
+            assert!(!disabled.is_disabled(buffer.read(cx), None, cx));
+        });
+    }
+
+    #[test]
+    fn test_toggle_while_loading() {
+        let mut files = WorkspaceFiles::loading("ollama_disabled_paths-1".into());
+        // Nothing is saved before the saved files are loaded.
+        assert_eq!(files.toggle("/project/a.rs".into()), (true, None));
+        assert_eq!(files.toggle("/project/b.rs".into()), (true, None));
+
+        let saved = BTreeSet::from(["/project/b.rs".into(), "/project/c.rs".into()]);
+        assert_eq!(
+            files.finish_loading(saved),
+            Some(r#"["/project/a.rs","/project/c.rs"]"#.to_string())
+        );
+        assert_eq!(
+            files.toggle("/project/c.rs".into()),
+            (false, Some(r#"["/project/a.rs"]"#.to_string()))
+        );
+
+        // Loading without anything toggled in the meantime saves nothing.
+        let mut files = WorkspaceFiles::loading("ollama_disabled_paths-2".into());
+        assert_eq!(
+            files.finish_loading(BTreeSet::from(["/project/d.rs".into()])),
+            None
+        );
+        assert!(files.paths.contains(&PathBuf::from("/project/d.rs")));
+    }
+}
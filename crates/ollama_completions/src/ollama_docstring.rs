@@ -0,0 +1,319 @@
+use crate::{
+    http_client_for, strip_code_fence, EndpointPool, Generations, OllamaSettings, PendingSuggestion,
+};
+use anyhow::{Context as _, Result};
+use editor::{
+    Anchor, CodeActionProvider, Editor, EditorMode, ExcerptId, ToOffset as _, ToPoint as _,
+};
+use gpui::{AppContext, Entity as _, Model, Task, ViewContext, WeakView, WindowContext};
+use language::{Buffer, BufferSnapshot, Point};
+use ollama::{ChatOptions, GenerateRequest, RequestPriority, RetryPolicy};
+use project::{CodeAction, ProjectTransaction};
+use std::{ops::Range, sync::Arc, time::Duration};
+use zed_actions::ollama::GenerateDocstring;
+
+/// The most tokens generated for a documentation comment.
+const MAX_DOCSTRING_TOKENS: isize = 256;
+
+const SYSTEM_PROMPT: &str = "You write documentation comments. You're given a function. \
+Reply with only a documentation comment for it in the idiomatic style of its language, \
+including the comment markers, to be placed directly above the function. Don't repeat the \
+function itself.";
+
+const CODE_ACTION_TITLE: &str = "Generate Docstring";
+
+/// Syntax nodes that are documented as a function across the bundled grammars.
+const FUNCTION_KINDS: &[&str] = &[
+    "function_item",
+    "function_signature_item",
+    "function_definition",
+    "function_declaration",
+    "generator_function_declaration",
+    "method_definition",
+    "method_declaration",
+    "constructor_declaration",
+];
+
+/// Function expressions, which are only documented when assigned to a variable.
+const FUNCTION_EXPRESSION_KINDS: &[&str] = &["arrow_function", "function_expression"];
+
+/// Nodes wrapping a function whose documentation goes above the wrapper.
+const WRAPPER_KINDS: &[&str] = &["decorated_definition", "export_statement"];
+
+/// Siblings before a function that its documentation goes above.
+const ATTRIBUTE_KINDS: &[&str] = &["attribute_item", "decorator"];
+
+pub(crate) fn init(cx: &mut AppContext) {
+    cx.observe_new_views(|editor: &mut Editor, cx: &mut ViewContext<Editor>| {
+        if editor.mode() != EditorMode::Full {
+            return;
+        }
+
+        editor
+            .register_action(cx.listener(|editor, _: &GenerateDocstring, cx| {
+                let position = editor.selections.newest_anchor().head();
+                let task = generate_docstring(editor, position, cx);
+                Generations::track(Some(cx.view().entity_id()), task, cx);
+            }))
+            .detach();
+        editor.push_code_action_provider(
+            Arc::new(DocstringCodeActions {
+                editor: cx.view().downgrade(),
+            }),
+            cx,
+        );
+    })
+    .detach();
+}
+
+/// Offers to document the function around the cursor.
+struct DocstringCodeActions {
+    editor: WeakView<Editor>,
+}
+
+impl CodeActionProvider for DocstringCodeActions {
+    fn code_actions(
+        &self,
+        buffer: &Model<Buffer>,
+        range: Range<text::Anchor>,
+        cx: &mut WindowContext,
+    ) -> Task<Result<Vec<CodeAction>>> {
+        let buffer = buffer.read(cx);
+        let settings = OllamaSettings::for_file(buffer.file(), cx);
+        if !settings.enabled {
+            return Task::ready(Ok(Vec::new()));
+        }
+
+        let snapshot = buffer.snapshot();
+        let Some(function) = enclosing_function(&snapshot, range.start.to_offset(&snapshot)) else {
+            return Task::ready(Ok(Vec::new()));
+        };
+        Task::ready(Ok(vec![CodeAction {
+            server_id: language::LanguageServerId(0),
+            range: snapshot.anchor_before(function.start)..snapshot.anchor_after(function.end),
+            lsp_action: lsp::CodeAction {
+                title: CODE_ACTION_TITLE.into(),
+                ..Default::default()
+            },
+        }]))
+    }
+
+    fn apply_code_action(
+        &self,
+        _buffer: Model<Buffer>,
+        action: CodeAction,
+        excerpt_id: ExcerptId,
+        _push_to_history: bool,
+        cx: &mut WindowContext,
+    ) -> Task<Result<ProjectTransaction>> {
+        let editor = self.editor.clone();
+        cx.spawn(|mut cx| async move {
+            let task = editor.update(&mut cx, |editor, cx| {
+                let snapshot = editor.buffer().read(cx).snapshot(cx);
+                let position = snapshot
+                    .anchor_in_excerpt(excerpt_id, action.range.start)
+                    .context("invalid range")?;
+                anyhow::Ok(generate_docstring(editor, position, cx))
+            })??;
+            task.await?;
+            // The comment is left for review rather than applied as part of
+            // a project transaction.
+            Ok(ProjectTransaction::default())
+        })
+    }
+}
+
+/// Asks the completion model to document the function around `position`, and
+/// inserts the comment above it as a highlighted suggestion that can be
+/// accepted or discarded.
+fn generate_docstring(
+    editor: &mut Editor,
+    position: Anchor,
+    cx: &mut ViewContext<Editor>,
+) -> Task<Result<()>> {
+    let snapshot = editor.buffer().read(cx).snapshot(cx);
+    let offset = position.to_offset(&snapshot);
+    let Some(excerpt) = snapshot.excerpt_containing(offset..offset) else {
+        return Task::ready(Ok(()));
+    };
+    let buffer = excerpt.buffer();
+    let Some(function) = enclosing_function(buffer, excerpt.map_offset_to_buffer(offset)) else {
+        return Task::ready(Ok(()));
+    };
+    let code = buffer.text_for_range(function.clone()).collect::<String>();
+    let language_name = buffer
+        .language_at(function.start)
+        .map(|language| language.name().0.to_string())
+        .unwrap_or_default();
+    let function = excerpt.map_range_from_buffer(function);
+    let function_start = snapshot.anchor_after(function.start);
+
+    let settings = OllamaSettings::for_file(buffer.file(), cx);
+    let server = OllamaSettings::server_for_file(buffer.file(), cx);
+    if !settings.enabled {
+        return Task::ready(Ok(()));
+    }
+    let mut request = GenerateRequest {
+        model: settings.resolve_model(&settings.model).to_string(),
+        prompt: format!("```{language_name}\n{code}\n```"),
+        suffix: None,
+        stream: false,
+        keep_alive: settings.keep_alive_for(&settings.model),
+        options: Some(ChatOptions {
+            num_predict: Some(MAX_DOCSTRING_TOKENS),
+            ..settings.runtime_options(&settings.model)
+        }),
+        system: Some(SYSTEM_PROMPT.to_string()),
+        raw: None,
+        format: None,
+        context: None,
+        priority: RequestPriority::UserInitiated,
+    };
+    if let Some(redactor) = settings.secret_redactor() {
+        redactor.redact_request(&mut request);
+    }
+    let http_client = http_client_for(server, &cx.http_client());
+    let endpoints = server.endpoints();
+    let api_key = server.effective_api_key();
+    let api_format = server.api_format;
+    let retry = RetryPolicy::default().with_max_attempts(settings.max_request_attempts);
+    let timeout = Duration::from_secs(settings.completion_timeout_secs);
+    let pool = EndpointPool::global(cx);
+
+    cx.spawn(|editor, mut cx| async move {
+        let response = pool
+            .generate(
+                http_client.as_ref(),
+                &endpoints,
+                api_key.as_deref(),
+                api_format,
+                request,
+                retry,
+                Some(timeout),
+            )
+            .await?;
+        let lines = docstring_lines(&response.response, &code);
+        if lines.is_empty() {
+            return Ok(());
+        }
+
+        editor.update(&mut cx, |editor, cx| {
+            let snapshot = editor.buffer().read(cx).snapshot(cx);
+            let row = function_start.to_point(&snapshot).row;
+            let indent = snapshot
+                .chars_at(Point::new(row, 0))
+                .take_while(|c| *c == ' ' || *c == '\t')
+                .collect::<String>();
+            let comment = lines
+                .iter()
+                .map(|line| format!("{indent}{line}\n"))
+                .collect::<String>();
+            let line_start = snapshot.point_to_offset(Point::new(row, 0));
+            editor.edit([(line_start..line_start, comment.as_str())], cx);
+
+            let snapshot = editor.buffer().read(cx).snapshot(cx);
+            let end = line_start + comment.len();
+            let suggestion = PendingSuggestion {
+                inserted: snapshot.anchor_after(line_start)..snapshot.anchor_before(end),
+                highlighted: snapshot.anchor_after(line_start)..snapshot.anchor_before(end - 1),
+            };
+            suggestion.highlight(editor, cx);
+            suggestion.offer("Suggested documentation", editor, cx);
+        })
+    })
+}
+
+/// The range of the innermost function around `offset`, starting at any
+/// attributes or decorators attached to it.
+fn enclosing_function(snapshot: &BufferSnapshot, offset: usize) -> Option<Range<usize>> {
+    let layer = snapshot.syntax_layer_at(offset)?;
+    let mut node = layer.node().descendant_for_byte_range(offset, offset)?;
+    let mut function = loop {
+        if FUNCTION_KINDS.contains(&node.kind()) {
+            break node;
+        }
+        if FUNCTION_EXPRESSION_KINDS.contains(&node.kind()) {
+            if let Some(declaration) = node
+                .parent()
+                .filter(|parent| parent.kind() == "variable_declarator")
+                .and_then(|declarator| declarator.parent())
+            {
+                break declaration;
+            }
+        }
+        node = node.parent()?;
+    };
+    while let Some(wrapper) = function
+        .parent()
+        .filter(|parent| WRAPPER_KINDS.contains(&parent.kind()))
+    {
+        function = wrapper;
+    }
+
+    let mut start = function.start_byte();
+    let mut sibling = function.prev_sibling();
+    while let Some(attribute) = sibling.filter(|node| ATTRIBUTE_KINDS.contains(&node.kind())) {
+        start = attribute.start_byte();
+        sibling = attribute.prev_sibling();
+    }
+    Some(start..function.end_byte())
+}
+
+/// The comment lines from the model's reply, without indentation. Anything
+/// from the first line of the function on is dropped, since models often
+/// repeat the function after its documentation.
+fn docstring_lines(response: &str, function: &str) -> Vec<String> {
+    let first_line = function
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    let lines = strip_code_fence(response)
+        .lines()
+        .take_while(|line| first_line.is_empty() || line.trim() != first_line)
+        .collect::<Vec<_>>();
+    let indent = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let mut lines = lines
+        .into_iter()
+        .map(|line| line.get(indent..).unwrap_or("").trim_end().to_string())
+        .skip_while(|line| line.is_empty())
+        .collect::<Vec<_>>();
+    while lines.last().map_or(false, |line| line.is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_docstring_lines() {
+        let function = "pub fn add(a: i32, b: i32) -> i32 {\n    a + b\n}";
+        assert_eq!(
+            docstring_lines("/// Adds two numbers.\n", function),
+            vec!["/// Adds two numbers."]
+        );
+        assert_eq!(
+            docstring_lines(
+                "```rust\n    /// Adds `a` and `b`.\n    ///\n    /// Wraps on overflow.\n    pub fn add(a: i32, b: i32) -> i32 {\n        a + b\n    }\n```",
+                function
+            ),
+            vec!["/// Adds `a` and `b`.", "///", "/// Wraps on overflow."]
+        );
+        assert_eq!(
+            docstring_lines(
+                "\n/**\n * Greets someone.\n */\n\n",
+                "function greet(name) {}"
+            ),
+            vec!["/**", " * Greets someone.", " */"]
+        );
+        assert!(docstring_lines("pub fn add(a: i32, b: i32) -> i32 {", function).is_empty());
+    }
+}
@@ -0,0 +1,260 @@
+use crate::count_tokens;
+use gpui::{AppContext, Global};
+use parking_lot::Mutex;
+use std::{collections::VecDeque, fmt::Write as _, ops::Range, path::Path, sync::Arc};
+use text::{BufferId, BufferSnapshot, Edit};
+use util::paths::PathMatcher;
+
+/// How many recent edits are remembered.
+const MAX_EDITS: usize = 16;
+/// Longer old or new text in an edit is cut off after this many lines.
+const MAX_HUNK_LINES: usize = 8;
+
+#[derive(Default)]
+struct GlobalEditHistory(Arc<EditHistory>);
+
+impl Global for GlobalEditHistory {}
+
+/// The user's most recent edits across all buffers with Ollama completions,
+/// summarized in prompts so the model can predict where the change goes next.
+#[derive(Default)]
+pub struct EditHistory {
+    state: Mutex<EditHistoryState>,
+}
+
+#[derive(Default)]
+struct EditHistoryState {
+    edits: VecDeque<RecentEdit>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct RecentEdit {
+    buffer_id: BufferId,
+    path: Option<Arc<Path>>,
+    /// The edited text's range in the buffer after the edit.
+    range: Range<usize>,
+    row: u32,
+    old_text: String,
+    new_text: String,
+}
+
+pub(crate) fn init(cx: &mut AppContext) {
+    cx.set_global(GlobalEditHistory::default());
+}
+
+impl EditHistory {
+    pub fn global(cx: &AppContext) -> Arc<Self> {
+        cx.global::<GlobalEditHistory>().0.clone()
+    }
+
+    /// Records the edits made to the buffer since the `previous` snapshot of
+    /// it. Consecutive edits to the same spot, like typing a word, are merged.
+    pub fn record(
+        &self,
+        previous: &BufferSnapshot,
+        snapshot: &BufferSnapshot,
+        path: Option<Arc<Path>>,
+    ) {
+        let mut state = self.state.lock();
+        for edit in snapshot.edits_since::<usize>(previous.version()) {
+            state.push(previous, snapshot, edit, path.clone());
+        }
+    }
+
+    /// Summarizes the recent edits as line comments, newest last, in at most
+    /// `budget` tokens. The edit in progress at the cursor is left out, since
+    /// the model already sees it, as are edits to files matching
+    /// `disabled_paths`.
+    pub fn prompt_context(
+        &self,
+        buffer_id: BufferId,
+        cursor_offset: usize,
+        comment_prefix: &str,
+        budget: usize,
+        disabled_paths: &PathMatcher,
+    ) -> String {
+        let state = self.state.lock();
+        let mut edits = state.edits.iter().rev().peekable();
+        if edits.peek().is_some_and(|edit| {
+            edit.buffer_id == buffer_id
+                && edit.range.start <= cursor_offset
+                && cursor_offset <= edit.range.end
+        }) {
+            edits.next();
+        }
+
+        let header = format!("{comment_prefix}Recent edits:\n");
+        let mut remaining = budget.saturating_sub(count_tokens(&header));
+        let mut hunks = Vec::new();
+        for edit in edits {
+            if edit
+                .path
+                .as_ref()
+                .is_some_and(|path| disabled_paths.is_match(path))
+            {
+                continue;
+            }
+            let hunk = edit.format(comment_prefix);
+            let tokens = count_tokens(&hunk);
+            if tokens > remaining {
+                break;
+            }
+            remaining -= tokens;
+            hunks.push(hunk);
+        }
+        if hunks.is_empty() {
+            return String::new();
+        }
+
+        hunks.reverse();
+        header + &hunks.concat()
+    }
+}
+
+impl EditHistoryState {
+    fn push(
+        &mut self,
+        previous: &BufferSnapshot,
+        snapshot: &BufferSnapshot,
+        edit: Edit<usize>,
+        path: Option<Arc<Path>>,
+    ) {
+        if let Some(last) = self
+            .edits
+            .back_mut()
+            .filter(|last| last.buffer_id == snapshot.remote_id())
+        {
+            if edit.old.start <= last.range.end && last.range.start <= edit.old.end {
+                // The edit touches the last one: widen it to cover both. Text
+                // outside the last edit's range is still the original text.
+                let start = edit.old.start.min(last.range.start);
+                let old_end = edit.old.end.max(last.range.end);
+                let mut old_text = previous
+                    .text_for_range(start..last.range.start)
+                    .collect::<String>();
+                old_text.push_str(&last.old_text);
+                old_text.extend(previous.text_for_range(last.range.end..old_end));
+                let end = old_end + edit.new.len() - edit.old.len();
+
+                last.old_text = old_text;
+                last.new_text = snapshot.text_for_range(start..end).collect();
+                last.range = start..end;
+                last.row = snapshot.offset_to_point(start).row;
+                if last.old_text == last.new_text {
+                    self.edits.pop_back();
+                }
+                return;
+            }
+        }
+
+        if self.edits.len() == MAX_EDITS {
+            self.edits.pop_front();
+        }
+        self.edits.push_back(RecentEdit {
+            buffer_id: snapshot.remote_id(),
+            path,
+            row: snapshot.offset_to_point(edit.new.start).row,
+            old_text: previous.text_for_range(edit.old.clone()).collect(),
+            new_text: snapshot.text_for_range(edit.new.clone()).collect(),
+            range: edit.new,
+        });
+    }
+}
+
+impl RecentEdit {
+    fn format(&self, comment_prefix: &str) -> String {
+        let path = self
+            .path
+            .as_ref()
+            .map_or("untitled".into(), |path| path.to_string_lossy());
+        let mut hunk = format!("{comment_prefix}{path}:{}\n", self.row + 1);
+        for (sign, text) in [("-", &self.old_text), ("+", &self.new_text)] {
+            if text.is_empty() {
+                continue;
+            }
+            let lines = text.lines().collect::<Vec<_>>();
+            for line in lines.iter().take(MAX_HUNK_LINES) {
+                writeln!(hunk, "{comment_prefix}{sign} {line}").ok();
+            }
+            if lines.len() > MAX_HUNK_LINES {
+                writeln!(hunk, "{comment_prefix}{sign} ...").ok();
+            }
+        }
+        hunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use text::Buffer;
+
+    #[test]
+    fn test_edit_history() {
+        let history = EditHistory::default();
+        let path: Option<Arc<Path>> = Some(Path::new("src/lib.rs").into());
+        let mut buffer = Buffer::new(
+            0,
+            BufferId::new(1).unwrap(),
+            "fn a() {}\nfn b() {}\n".into(),
+        );
+        let mut previous = buffer.snapshot();
+        let mut record = |buffer: &Buffer| {
+            history.record(&previous, &buffer.snapshot(), path.clone());
+            previous = buffer.snapshot();
+        };
+
+        // Typing a word a character at a time is recorded as a single edit.
+        for (ix, c) in "pub ".chars().enumerate() {
+            buffer.edit([(ix..ix, c.to_string())]);
+            record(&buffer);
+        }
+        buffer.edit([(17..18, "c")]);
+        record(&buffer);
+
+        assert_eq!(
+            history.prompt_context(
+                BufferId::new(2).unwrap(),
+                0,
+                "// ",
+                1000,
+                &PathMatcher::default()
+            ),
+            "// Recent edits:\n\
+             // src/lib.rs:1\n\
+             // + pub \n\
+             // src/lib.rs:2\n\
+             // - b\n\
+             // + c\n"
+        );
+        // The edit at the cursor is left out.
+        assert_eq!(
+            history.prompt_context(
+                BufferId::new(1).unwrap(),
+                18,
+                "// ",
+                1000,
+                &PathMatcher::default()
+            ),
+            "// Recent edits:\n\
+             // src/lib.rs:1\n\
+             // + pub \n"
+        );
+        assert_eq!(
+            history.prompt_context(
+                BufferId::new(2).unwrap(),
+                0,
+                "// ",
+                0,
+                &PathMatcher::default()
+            ),
+            ""
+        );
+        // Edits to disabled files are left out.
+        let disabled_paths = PathMatcher::new(&["src/**".to_string()]).unwrap();
+        assert_eq!(
+            history.prompt_context(BufferId::new(2).unwrap(), 0, "// ", 1000, &disabled_paths),
+            ""
+        );
+    }
+}
@@ -0,0 +1,526 @@
+use crate::{count_tokens, OllamaSettings};
+use anyhow::{anyhow, Result};
+use futures::future::{self, Either};
+use gpui::{AppContext, BackgroundExecutor, Global};
+use http_client::HttpClient;
+use ollama::{embed_batched, EmbedBatchLimits};
+use parking_lot::Mutex;
+use std::{
+    collections::VecDeque, fmt::Write as _, iter, ops::Range, path::Path, sync::Arc, time::Duration,
+};
+use text::{BufferId, BufferSnapshot, Point};
+
+/// How many edited regions are remembered. The oldest are forgotten first.
+const MAX_REGIONS: usize = 64;
+/// How many lines around an edit are remembered with it.
+const CONTEXT_LINES: u32 = 3;
+/// Longer regions are cut off after this many bytes.
+const MAX_REGION_LEN: usize = 1024;
+/// How many lines before the cursor the remembered code is compared with.
+const QUERY_LINES: u32 = 8;
+/// Regions less similar to the code at the cursor than this are left out,
+/// however few are found.
+const MIN_SIMILARITY: f32 = 0.5;
+/// How long finding related code may hold up a completion request. Slower
+/// lookups finish in the background, so their embeddings are ready next time.
+const RETRIEVAL_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Default)]
+struct GlobalEditMemory(Arc<EditMemory>);
+
+impl Global for GlobalEditMemory {}
+
+/// The code around the user's recent edits, across all buffers with Ollama
+/// completions, indexed by embedding so completions can be shown the parts
+/// most like the code at the cursor. This gives the model a memory of
+/// related code the user worked on earlier in the session.
+#[derive(Default)]
+pub struct EditMemory {
+    state: Mutex<EditMemoryState>,
+}
+
+#[derive(Default)]
+struct EditMemoryState {
+    regions: VecDeque<EditedRegion>,
+    next_id: usize,
+}
+
+#[derive(Clone, Debug)]
+struct EditedRegion {
+    id: usize,
+    buffer_id: BufferId,
+    path: Option<Arc<Path>>,
+    /// The lines the region spans, as they were when it was last edited.
+    rows: Range<u32>,
+    text: String,
+    /// The embedding of `text`, and the model that made it.
+    embedding: Option<(String, Arc<[f32]>)>,
+}
+
+pub(crate) fn init(cx: &mut AppContext) {
+    cx.set_global(GlobalEditMemory::default());
+}
+
+impl EditMemory {
+    pub fn global(cx: &AppContext) -> Arc<Self> {
+        cx.global::<GlobalEditMemory>().0.clone()
+    }
+
+    /// Remembers the code around the edits made to the buffer since the
+    /// `previous` snapshot of it. An edit near one remembered earlier replaces
+    /// it, so typing in one place is remembered once.
+    pub fn record(
+        &self,
+        previous: &BufferSnapshot,
+        snapshot: &BufferSnapshot,
+        path: Option<Arc<Path>>,
+    ) {
+        let mut state = self.state.lock();
+        for edit in snapshot.edits_since::<usize>(previous.version()) {
+            let start = snapshot.offset_to_point(edit.new.start).row;
+            let end = snapshot.offset_to_point(edit.new.end).row;
+            state.remember(snapshot, start..end + 1, path.clone());
+        }
+    }
+
+    /// What's needed to find the remembered code most like the lines before
+    /// the cursor, or `None` when there's nothing to choose from. Regions
+    /// around the cursor are left out, since the model already sees them, as
+    /// are those in files matching `disabled_globs`.
+    pub(crate) fn request(
+        self: &Arc<Self>,
+        settings: &OllamaSettings,
+        snapshot: &BufferSnapshot,
+        cursor_offset: usize,
+        comment_prefix: Arc<str>,
+    ) -> Option<EditMemoryRequest> {
+        let cursor = snapshot.offset_to_point(cursor_offset);
+        let disabled_paths = settings.disabled_paths();
+        let mut candidates = self
+            .state
+            .lock()
+            .regions
+            .iter()
+            .filter(|region| {
+                !(region.buffer_id == snapshot.remote_id() && region.rows.contains(&cursor.row))
+                    && !region
+                        .path
+                        .as_ref()
+                        .is_some_and(|path| disabled_paths.is_match(path))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return None;
+        }
+        let query_start = Point::new(cursor.row.saturating_sub(QUERY_LINES), 0);
+        let mut query = snapshot
+            .text_for_range(query_start..cursor)
+            .collect::<String>();
+        if query.trim().is_empty() {
+            return None;
+        }
+        // The regions are embedded by the server, so they're redacted like
+        // prompts are.
+        if let Some(redactor) = settings.secret_redactor() {
+            query = redactor.redact(&query).into_owned();
+            for candidate in &mut candidates {
+                candidate.text = redactor.redact(&candidate.text).into_owned();
+            }
+        }
+        Some(EditMemoryRequest {
+            memory: self.clone(),
+            query,
+            candidates,
+            comment_prefix,
+            model: settings.edit_memory_model.clone(),
+            max_snippets: settings.edit_memory_snippets,
+            max_tokens: settings.edit_memory_tokens,
+        })
+    }
+
+    /// Keeps the embeddings made for regions that are still remembered.
+    fn store_embeddings(&self, regions: &[EditedRegion]) {
+        let mut state = self.state.lock();
+        for region in regions {
+            if let Some(remembered) = state
+                .regions
+                .iter_mut()
+                .find(|remembered| remembered.id == region.id)
+            {
+                remembered.embedding = region.embedding.clone();
+            }
+        }
+    }
+}
+
+impl EditMemoryState {
+    fn remember(&mut self, snapshot: &BufferSnapshot, rows: Range<u32>, path: Option<Arc<Path>>) {
+        let end_row = (rows.end + CONTEXT_LINES).min(snapshot.max_point().row + 1);
+        let rows = rows.start.saturating_sub(CONTEXT_LINES)..end_row;
+        self.regions.retain(|region| {
+            region.buffer_id != snapshot.remote_id()
+                || region.rows.end <= rows.start
+                || rows.end <= region.rows.start
+        });
+
+        let last_row = rows.end - 1;
+        let text = snapshot
+            .text_for_range(
+                Point::new(rows.start, 0)..Point::new(last_row, snapshot.line_len(last_row)),
+            )
+            .collect::<String>();
+        if text.trim().is_empty() {
+            return;
+        }
+        let mut len = text.len().min(MAX_REGION_LEN);
+        while !text.is_char_boundary(len) {
+            len -= 1;
+        }
+
+        if self.regions.len() == MAX_REGIONS {
+            self.regions.pop_front();
+        }
+        self.next_id += 1;
+        self.regions.push_back(EditedRegion {
+            id: self.next_id,
+            buffer_id: snapshot.remote_id(),
+            path,
+            rows,
+            text: text[..len].to_string(),
+            embedding: None,
+        });
+    }
+}
+
+impl EditedRegion {
+    fn embedding_for(&self, model: &str) -> Option<&[f32]> {
+        self.embedding
+            .as_ref()
+            .filter(|(embedded_with, _)| embedded_with == model)
+            .map(|(_, embedding)| embedding.as_ref())
+    }
+
+    fn format(&self, comment_prefix: &str) -> String {
+        let path = self
+            .path
+            .as_ref()
+            .map_or("untitled".into(), |path| path.to_string_lossy());
+        let mut snippet = format!("{comment_prefix}{path}:{}\n", self.rows.start + 1);
+        for line in self.text.lines() {
+            writeln!(snippet, "{comment_prefix}{line}").ok();
+        }
+        snippet
+    }
+}
+
+/// A search of the [`EditMemory`] for code related to the code at the cursor,
+/// read on the main thread so it can run in the background.
+pub(crate) struct EditMemoryRequest {
+    memory: Arc<EditMemory>,
+    query: String,
+    candidates: Vec<EditedRegion>,
+    comment_prefix: Arc<str>,
+    model: String,
+    max_snippets: usize,
+    max_tokens: usize,
+}
+
+impl EditMemoryRequest {
+    /// Embeds the query, and any region not embedded yet, with the server at
+    /// `api_url`, and returns the regions most like the query as line
+    /// comments. Empty when the server didn't answer in time.
+    pub async fn run(
+        self,
+        client: Arc<dyn HttpClient>,
+        api_url: String,
+        api_key: Option<String>,
+        executor: BackgroundExecutor,
+    ) -> String {
+        let task = executor.spawn(async move {
+            self.retrieve(client.as_ref(), &api_url, api_key.as_deref())
+                .await
+        });
+        let timer = executor.timer(RETRIEVAL_TIMEOUT);
+        match future::select(task, timer).await {
+            Either::Left((Ok(context), _)) => context,
+            Either::Left((Err(error), _)) => {
+                log::debug!("finding related edits failed: {error:#}");
+                String::new()
+            }
+            Either::Right((_, task)) => {
+                log::debug!("finding related edits timed out");
+                task.detach();
+                String::new()
+            }
+        }
+    }
+
+    async fn retrieve(
+        mut self,
+        client: &dyn HttpClient,
+        api_url: &str,
+        api_key: Option<&str>,
+    ) -> Result<String> {
+        let unembedded = self
+            .candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, region)| region.embedding_for(&self.model).is_none())
+            .map(|(ix, _)| ix)
+            .collect::<Vec<_>>();
+        let inputs = iter::once(self.query.as_str())
+            .chain(
+                unembedded
+                    .iter()
+                    .map(|&ix| self.candidates[ix].text.as_str()),
+            )
+            .collect::<Vec<_>>();
+        let mut embeddings = embed_batched(
+            client,
+            api_url,
+            api_key,
+            &self.model,
+            &inputs,
+            EmbedBatchLimits::default(),
+        )
+        .await?
+        .into_iter();
+        let query = embeddings
+            .next()
+            .ok_or_else(|| anyhow!("the server didn't embed the query"))?;
+        for (ix, embedding) in unembedded.into_iter().zip(embeddings) {
+            self.candidates[ix].embedding = Some((self.model.clone(), embedding.into()));
+        }
+        self.memory.store_embeddings(&self.candidates);
+
+        Ok(related_code(
+            &query,
+            &self.model,
+            &self.candidates,
+            &self.comment_prefix,
+            self.max_snippets,
+            self.max_tokens,
+        ))
+    }
+}
+
+/// The `max_snippets` regions most similar to `query`, most similar first,
+/// as line comments in at most `max_tokens`.
+fn related_code(
+    query: &[f32],
+    model: &str,
+    regions: &[EditedRegion],
+    comment_prefix: &str,
+    max_snippets: usize,
+    max_tokens: usize,
+) -> String {
+    let mut scored = regions
+        .iter()
+        .filter_map(|region| {
+            let similarity = cosine_similarity(query, region.embedding_for(model)?);
+            (similarity >= MIN_SIMILARITY).then_some((similarity, region))
+        })
+        .collect::<Vec<_>>();
+    scored.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+    let header = format!("{comment_prefix}Related code you edited earlier:\n");
+    let mut remaining = max_tokens.saturating_sub(count_tokens(&header));
+    let mut snippets = String::new();
+    for (_, region) in scored.into_iter().take(max_snippets) {
+        let snippet = region.format(comment_prefix);
+        let tokens = count_tokens(&snippet);
+        // A shorter, less similar snippet may still fit.
+        if tokens > remaining {
+            continue;
+        }
+        remaining -= tokens;
+        snippets.push_str(&snippet);
+    }
+    if snippets.is_empty() {
+        return String::new();
+    }
+    header + &snippets
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.;
+    }
+    let dot = a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0. {
+        0.
+    } else {
+        dot / norms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gpui::TestAppContext;
+    use ollama::FakeOllamaServer;
+    use text::Buffer;
+
+    const MATH: &str = "\
+use std::ops::Mul;
+
+fn multiply(a: i32, b: i32) -> i32 {
+    a * b
+}
+
+fn square(a: i32) -> i32 {
+    multiply(a, a)
+}
+
+fn main() {
+    println!(\"{}\", square(4));
+}
+";
+
+    fn regions(memory: &EditMemory) -> Vec<(Range<u32>, String)> {
+        memory
+            .state
+            .lock()
+            .regions
+            .iter()
+            .map(|region| (region.rows.clone(), region.text.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_remembered_regions() {
+        let memory = EditMemory::default();
+        let path: Option<Arc<Path>> = Some(Path::new("src/math.rs").into());
+        let mut buffer = Buffer::new(0, BufferId::new(1).unwrap(), MATH.into());
+        let mut previous = buffer.snapshot();
+        let mut record = |buffer: &Buffer| {
+            memory.record(&previous, &buffer.snapshot(), path.clone());
+            previous = buffer.snapshot();
+        };
+        record(&buffer);
+        assert!(regions(&memory).is_empty());
+
+        // The lines around an edit are remembered with it.
+        let offset = MATH.find("a * b").unwrap();
+        buffer.edit([(offset..offset + 5, "a.mul(b)")]);
+        record(&buffer);
+        let offset = buffer.text().find("println").unwrap();
+        buffer.edit([(offset..offset + 7, "print")]);
+        record(&buffer);
+        assert_eq!(
+            regions(&memory)
+                .iter()
+                .map(|(rows, _)| rows.clone())
+                .collect::<Vec<_>>(),
+            [0..7, 8..14]
+        );
+        assert!(regions(&memory)[0].1.contains("    a.mul(b)\n"));
+
+        // Editing near a remembered region replaces it.
+        let offset = buffer.text().find("a.mul(b)").unwrap();
+        buffer.edit([(offset..offset, "// Multiplies.\n    ")]);
+        record(&buffer);
+        let remembered = regions(&memory);
+        assert_eq!(remembered.len(), 2);
+        assert_eq!(remembered[0].0, 8..14);
+        assert!(remembered[1].1.contains("// Multiplies."));
+    }
+
+    #[test]
+    fn test_related_code() {
+        let region = |id: usize, text: &str, embedding: &[f32]| EditedRegion {
+            id,
+            buffer_id: BufferId::new(1).unwrap(),
+            path: Some(Path::new("src/math.rs").into()),
+            rows: id as u32 * 10..id as u32 * 10 + 2,
+            text: text.into(),
+            embedding: Some(("nomic-embed-text".into(), embedding.into())),
+        };
+        let regions = [
+            region(1, "fn add() {}", &[1., 0., 0.]),
+            region(2, "fn multiply() {}", &[0.8, 0.6, 0.]),
+            region(3, "struct Config;", &[0., 0., 1.]),
+        ];
+        let query = [0.6, 0.8, 0.];
+        assert_eq!(
+            related_code(&query, "nomic-embed-text", &regions, "// ", 3, 1000),
+            "// Related code you edited earlier:\n\
+             // src/math.rs:21\n\
+             // fn multiply() {}\n\
+             // src/math.rs:11\n\
+             // fn add() {}\n"
+        );
+        assert_eq!(
+            related_code(&query, "nomic-embed-text", &regions, "// ", 1, 1000),
+            "// Related code you edited earlier:\n\
+             // src/math.rs:21\n\
+             // fn multiply() {}\n"
+        );
+        // Embeddings made with another model aren't comparable.
+        assert_eq!(
+            related_code(&query, "all-minilm", &regions, "// ", 3, 1000),
+            ""
+        );
+        assert_eq!(
+            related_code(&query, "nomic-embed-text", &regions, "// ", 3, 0),
+            ""
+        );
+        assert_eq!(cosine_similarity(&[1., 0.], &[0., 0.]), 0.);
+        assert_eq!(cosine_similarity(&[1., 0.], &[1., 0., 0.]), 0.);
+    }
+
+    #[gpui::test]
+    async fn test_edit_memory_request(cx: &mut TestAppContext) {
+        let server = FakeOllamaServer::new();
+        let memory = Arc::new(EditMemory::default());
+        let mut buffer = Buffer::new(0, BufferId::new(1).unwrap(), MATH.into());
+        let previous = buffer.snapshot();
+        let offset = MATH.find("a * b").unwrap();
+        buffer.edit([(offset..offset + 5, "a.mul(b)")]);
+        memory.record(&previous, &buffer.snapshot(), None);
+
+        let other = Buffer::new(
+            0,
+            BufferId::new(2).unwrap(),
+            "fn cube(a: i32) -> i32 {\n    multiply(a, square(a))\n}\n".into(),
+        );
+        let snapshot = other.snapshot();
+        let request = |memory: &Arc<EditMemory>| EditMemoryRequest {
+            memory: memory.clone(),
+            query: snapshot.text(),
+            candidates: memory.state.lock().regions.iter().cloned().collect(),
+            comment_prefix: "// ".into(),
+            model: "nomic-embed-text".into(),
+            max_snippets: 3,
+            max_tokens: 1000,
+        };
+
+        let context = request(&memory)
+            .run(
+                server.http_client(),
+                "http://localhost:11434".into(),
+                None,
+                cx.executor(),
+            )
+            .await;
+        assert!(context.starts_with("// Related code you edited earlier:\n// untitled:1\n"));
+        assert!(context.contains("//     a.mul(b)\n"));
+        let embedded = server.requests("/api/embed");
+        assert_eq!(embedded[0]["input"].as_array().unwrap().len(), 2);
+
+        // Regions already embedded aren't sent again.
+        request(&memory)
+            .run(
+                server.http_client(),
+                "http://localhost:11434".into(),
+                None,
+                cx.executor(),
+            )
+            .await;
+        let embedded = server.requests("/api/embed");
+        assert_eq!(embedded[1]["input"].as_array().unwrap().len(), 1);
+    }
+}
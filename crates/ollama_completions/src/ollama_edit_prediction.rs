@@ -0,0 +1,408 @@
+use anyhow::{anyhow, Result};
+use ollama::{ChatOptions, GenerateRequest, KeepAlive, RequestPriority};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{fmt::Write as _, ops::Range};
+use text::{Bias, BufferSnapshot, LineEnding, Point};
+
+/// How many lines above and below the cursor the model sees.
+const EXCERPT_CONTEXT_LINES: u32 = 20;
+/// The maximum number of tokens generated for a predicted edit.
+const MAX_PREDICTION_TOKENS: isize = 256;
+const CURSOR_MARKER: &str = "<|cursor|>";
+/// Edits the model is less sure of than this aren't proposed.
+const MIN_CONFIDENCE: f32 = 0.25;
+
+/// The edit the model predicts, as a range of whole lines to replace.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PredictedEdit {
+    /// The text of the first line to replace, for finding it when the model
+    /// miscounted the lines.
+    pub anchor: String,
+    pub range: LineRange,
+    pub replacement: String,
+    /// How likely the model thinks the edit is, from 0.0 to 1.0.
+    pub confidence: f32,
+}
+
+/// Lines of the excerpt the model was shown, counting from 1.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LineRange {
+    /// The first line to replace. 0 means no edit.
+    pub start_line: u32,
+    /// The last line to replace, inclusive.
+    pub end_line: u32,
+}
+
+impl PredictedEdit {
+    /// The JSON schema responses are constrained to.
+    fn schema() -> Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "anchor": { "type": "string" },
+                "range": {
+                    "type": "object",
+                    "properties": {
+                        "start_line": { "type": "integer", "minimum": 0 },
+                        "end_line": { "type": "integer", "minimum": 0 },
+                    },
+                    "required": ["start_line", "end_line"],
+                },
+                "replacement": { "type": "string" },
+                "confidence": { "type": "number", "minimum": 0, "maximum": 1 },
+            },
+            "required": ["anchor", "range", "replacement", "confidence"],
+        })
+    }
+
+    /// Parses a response, checking it against [`Self::schema`]. Servers
+    /// that ignore `format`, or output cut off at the token limit, can
+    /// produce anything; the error describes what's wrong in terms the
+    /// model can fix.
+    pub fn validate(response: &str) -> Result<Self> {
+        let edit = serde_json::from_str::<Self>(response.trim())
+            .map_err(|error| anyhow!("the response isn't a valid edit object: {error}"))?;
+        if !(0.0..=1.0).contains(&edit.confidence) {
+            return Err(anyhow!(
+                "confidence is {}, but must be between 0 and 1",
+                edit.confidence
+            ));
+        }
+        let LineRange {
+            start_line,
+            end_line,
+        } = edit.range;
+        if start_line > 0 && end_line < start_line {
+            return Err(anyhow!(
+                "range ends at line {end_line}, before it starts at line {start_line}"
+            ));
+        }
+        Ok(edit)
+    }
+}
+
+/// Builds a request asking the model for the next edit the user is likely to
+/// make near the cursor, answered as a [`PredictedEdit`] in JSON.
+pub(crate) fn edit_prediction_request(
+    model: &str,
+    snapshot: &BufferSnapshot,
+    path: &str,
+    cursor_offset: usize,
+    recent_edits: &str,
+    options: ChatOptions,
+) -> GenerateRequest {
+    let rows = excerpt_rows(snapshot, cursor_offset);
+    // The marker goes between characters, never inside one.
+    let cursor = snapshot.offset_to_point(snapshot.clip_offset(cursor_offset, Bias::Left));
+    let mut excerpt = String::new();
+    for row in rows.clone() {
+        let mut line = snapshot
+            .text_for_range(Point::new(row, 0)..Point::new(row, snapshot.line_len(row)))
+            .collect::<String>();
+        if row == cursor.row {
+            line.insert_str(cursor.column as usize, CURSOR_MARKER);
+        }
+        writeln!(excerpt, "{}| {line}", row + 1).ok();
+    }
+
+    let prompt = format!(
+        "You predict the next edit a programmer will make to {path}, based on their recent \
+        edits and the code around their cursor, which is marked {CURSOR_MARKER}.\n\n\
+        {recent_edits}\n\
+        Lines {}-{} of {path}:\n{excerpt}\n\
+        Respond with the text of the first line to replace as the anchor, the range of lines \
+        to replace from start_line to end_line, inclusive, the text to replace them with, \
+        without line numbers, and your confidence in the edit from 0 to 1. If no edit is \
+        needed, respond with start_line 0.",
+        rows.start + 1,
+        rows.end,
+    );
+
+    GenerateRequest {
+        model: model.to_string(),
+        prompt,
+        suffix: None,
+        stream: false,
+        keep_alive: KeepAlive::default(),
+        options: Some(ChatOptions {
+            num_predict: Some(MAX_PREDICTION_TOKENS),
+            temperature: Some(0.),
+            ..options
+        }),
+        system: None,
+        raw: None,
+        format: Some(PredictedEdit::schema()),
+        context: None,
+        priority: RequestPriority::Automatic,
+    }
+}
+
+/// Asks the model to fix a `response` to `request` that failed validation
+/// with `error`, by showing it what it said and what's wrong with it.
+pub(crate) fn edit_repair_request(
+    request: &GenerateRequest,
+    response: &str,
+    error: &str,
+) -> GenerateRequest {
+    GenerateRequest {
+        prompt: format!(
+            "{}\n\nYour previous response was:\n{response}\n\n\
+            It can't be used, because {error}. Respond again with only a JSON object with \
+            anchor, range, replacement and confidence.",
+            request.prompt
+        ),
+        context: None,
+        ..request.clone()
+    }
+}
+
+/// The rows shown to the model around the cursor.
+fn excerpt_rows(snapshot: &BufferSnapshot, cursor_offset: usize) -> Range<u32> {
+    let row = snapshot.offset_to_point(cursor_offset).row;
+    row.saturating_sub(EXCERPT_CONTEXT_LINES)
+        ..(row + EXCERPT_CONTEXT_LINES + 1).min(snapshot.max_point().row + 1)
+}
+
+/// Turns the model's response into the smallest edit that makes the change:
+/// the range of text to replace and what to replace it with. Returns `None`
+/// when the response is invalid, the model predicted no edit or isn't
+/// confident in it, or the edit is outside of the lines it was shown.
+pub(crate) fn parse_predicted_edit(
+    response: &str,
+    snapshot: &BufferSnapshot,
+    cursor_offset: usize,
+) -> Option<(Range<usize>, String)> {
+    let edit = PredictedEdit::validate(response).ok()?;
+    if edit.range.start_line == 0 || edit.confidence < MIN_CONFIDENCE {
+        return None;
+    }
+    let rows = excerpt_rows(snapshot, cursor_offset);
+    let (start_row, end_row) = anchored_rows(&edit, snapshot, rows.clone())?;
+    if !rows.contains(&start_row) || !rows.contains(&end_row) {
+        return None;
+    }
+
+    let start = snapshot.point_to_offset(Point::new(start_row, 0));
+    let end = snapshot.point_to_offset(Point::new(end_row, snapshot.line_len(end_row)));
+    let old_text = snapshot.text_for_range(start..end).collect::<String>();
+    let mut replacement = edit.replacement;
+    LineEnding::normalize(&mut replacement);
+    let new_text = replacement.strip_suffix('\n').unwrap_or(&replacement);
+    let (range, text) = minimal_edit(&old_text, new_text);
+    if range.is_empty() && text.is_empty() {
+        return None;
+    }
+    Some((start + range.start..start + range.end, text.to_string()))
+}
+
+/// The rows `edit` replaces. Models often miscount lines, so when the anchor
+/// isn't the text of the first line, the range is moved to the nearest line
+/// of the excerpt that is. An edit whose anchor isn't in the excerpt at all
+/// is dropped, since it's unclear where it goes.
+fn anchored_rows(
+    edit: &PredictedEdit,
+    snapshot: &BufferSnapshot,
+    rows: Range<u32>,
+) -> Option<(u32, u32)> {
+    let (start_row, end_row) = (edit.range.start_line - 1, edit.range.end_line - 1);
+    let anchor = edit.anchor.trim();
+    let is_anchor = |row: u32| {
+        let line = Point::new(row, 0)..Point::new(row, snapshot.line_len(row));
+        snapshot.text_for_range(line).collect::<String>().trim() == anchor
+    };
+    if anchor.is_empty() || (rows.contains(&start_row) && is_anchor(start_row)) {
+        return Some((start_row, end_row));
+    }
+    let anchor_row = rows
+        .filter(|row| is_anchor(*row))
+        .min_by_key(|row| row.abs_diff(start_row))?;
+    Some((anchor_row, end_row - start_row + anchor_row))
+}
+
+/// Trims the text `old` and `new` have in common at either end, returning the
+/// range of `old` that changes and the text that replaces it.
+fn minimal_edit<'a>(old: &str, new: &'a str) -> (Range<usize>, &'a str) {
+    let prefix = old
+        .char_indices()
+        .zip(new.chars())
+        .find(|((_, a), b)| a != b)
+        .map_or(old.len().min(new.len()), |((ix, _), _)| ix);
+    let prefix = (0..=prefix)
+        .rev()
+        .find(|ix| old.is_char_boundary(*ix) && new.is_char_boundary(*ix))
+        .unwrap_or(0);
+    let suffix = old[prefix..]
+        .chars()
+        .rev()
+        .zip(new[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum::<usize>();
+    (prefix..old.len() - suffix, &new[prefix..new.len() - suffix])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use text::{Buffer, BufferId};
+
+    #[test]
+    fn test_minimal_edit() {
+        assert_eq!(minimal_edit("let x = 1;", "let x = 2;"), (8..9, "2"));
+        assert_eq!(minimal_edit("foo(a)", "foo(a, b)"), (5..5, ", b"));
+        assert_eq!(minimal_edit("same", "same"), (4..4, ""));
+        assert_eq!(minimal_edit("", "new"), (0..0, "new"));
+    }
+
+    #[test]
+    fn test_parse_predicted_edit() {
+        let buffer = Buffer::new(
+            0,
+            BufferId::new(1).unwrap(),
+            "fn a(x: i32) {}\nfn b(x: i32) {}\n".into(),
+        );
+        let snapshot = buffer.snapshot();
+        let edit = |anchor: &str, start_line, end_line, replacement: &str, confidence| {
+            serde_json::json!({
+                "anchor": anchor,
+                "range": { "start_line": start_line, "end_line": end_line },
+                "replacement": replacement,
+                "confidence": confidence,
+            })
+            .to_string()
+        };
+        assert_eq!(
+            parse_predicted_edit(
+                &edit("fn b(x: i32) {}", 2, 2, "fn b(x: i64) {}\n", 0.9),
+                &snapshot,
+                0,
+            ),
+            Some((25..27, "64".to_string()))
+        );
+        // The anchor wins over miscounted lines.
+        assert_eq!(
+            parse_predicted_edit(
+                &edit("fn b(x: i32) {}", 1, 1, "fn b(x: i64) {}", 0.9),
+                &snapshot,
+                0,
+            ),
+            Some((25..27, "64".to_string()))
+        );
+        assert_eq!(
+            parse_predicted_edit(&edit("fn c() {}", 1, 1, "fn c() {}", 0.9), &snapshot, 0),
+            None
+        );
+        assert_eq!(
+            parse_predicted_edit(
+                &edit("fn b(x: i32) {}", 2, 2, "fn b(x: i64) {}", 0.1),
+                &snapshot,
+                0,
+            ),
+            None
+        );
+        assert_eq!(
+            parse_predicted_edit(&edit("", 0, 0, "", 0.9), &snapshot, 0),
+            None
+        );
+        assert_eq!(
+            parse_predicted_edit(&edit("", 40, 41, "x", 0.9), &snapshot, 0),
+            None
+        );
+        assert_eq!(parse_predicted_edit("not json", &snapshot, 0), None);
+
+        // Multi-byte text and Windows line endings in the replacement still
+        // make an edit of whole characters.
+        let text = "let s = \"日本\";\nlet t = \"🦀\";\n";
+        let snapshot = Buffer::new(0, BufferId::new(1).unwrap(), text.into()).snapshot();
+        let crab = text.find('🦀').unwrap();
+        assert_eq!(
+            parse_predicted_edit(
+                r#"{"anchor": "let t = \"🦀\";", "range": {"start_line": 2, "end_line": 2},
+                    "replacement": "let t = \"🦀🦀\";\r\n", "confidence": 0.8}"#,
+                &snapshot,
+                0,
+            ),
+            Some((crab + 4..crab + 4, "🦀".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_validate_predicted_edit() {
+        let edit = PredictedEdit::validate(
+            r#" {"anchor": "x", "range": {"start_line": 3, "end_line": 4},
+                "replacement": "y", "confidence": 0.5} "#,
+        )
+        .unwrap();
+        assert_eq!(
+            edit.range,
+            LineRange {
+                start_line: 3,
+                end_line: 4
+            }
+        );
+
+        let error = |response: &str| PredictedEdit::validate(response).unwrap_err().to_string();
+        // Cut off at the token limit.
+        assert!(error(r#"{"anchor": "x", "range": {"start_line": 3"#)
+            .starts_with("the response isn't a valid edit object"));
+        // The old format, without an anchor or confidence.
+        assert!(
+            error(r#"{"start_line": 2, "end_line": 2, "replacement": ""}"#)
+                .starts_with("the response isn't a valid edit object")
+        );
+        assert_eq!(
+            error(
+                r#"{"anchor": "x", "range": {"start_line": 3, "end_line": 4},
+                    "replacement": "y", "confidence": 80}"#
+            ),
+            "confidence is 80, but must be between 0 and 1"
+        );
+        assert_eq!(
+            error(
+                r#"{"anchor": "x", "range": {"start_line": 4, "end_line": 3},
+                    "replacement": "y", "confidence": 0.5}"#
+            ),
+            "range ends at line 3, before it starts at line 4"
+        );
+    }
+
+    #[test]
+    fn test_edit_repair_request() {
+        let text = "let s = 1;\n";
+        let snapshot = Buffer::new(0, BufferId::new(1).unwrap(), text.into()).snapshot();
+        let request = edit_prediction_request(
+            "qwen2.5-coder:1.5b",
+            &snapshot,
+            "main.rs",
+            0,
+            "",
+            ChatOptions::default(),
+        );
+        let repair = edit_repair_request(&request, "{\"anchor\":", "it's cut off");
+        assert!(repair.prompt.starts_with(&request.prompt));
+        assert!(repair.prompt.contains(
+            "Your previous response was:\n{\"anchor\":\n\nIt can't be used, because it's cut off."
+        ));
+        assert_eq!(repair.format, request.format);
+    }
+
+    #[test]
+    fn test_edit_prediction_request_multi_byte() {
+        let text = "let s = \"日本\";\n";
+        let snapshot = Buffer::new(0, BufferId::new(1).unwrap(), text.into()).snapshot();
+        // An offset inside `本` puts the marker before it.
+        let inside = text.find('本').unwrap() + 1;
+        let request = edit_prediction_request(
+            "qwen2.5-coder:1.5b",
+            &snapshot,
+            "main.rs",
+            inside,
+            "",
+            ChatOptions::default(),
+        );
+        assert!(request.prompt.contains("1| let s = \"日<|cursor|>本\";"));
+    }
+}
@@ -0,0 +1,229 @@
+use crate::{http_client_for, ApiFormat, OllamaSettings};
+use anyhow::{anyhow, Result};
+use futures::future;
+use gpui::{AppContext, Global};
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use ollama::{
+    api_endpoint, authorize, generate, generate_openai, with_timeout, GenerateRequest,
+    GenerateResponse, OllamaError, RetryPolicy,
+};
+use parking_lot::Mutex;
+use settings::Settings as _;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// How often known servers are probed while more than one is in use.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// How long a server has to answer a health check.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+/// Weight given to the newest sample in a server's smoothed latency.
+const LATENCY_SMOOTHING: f64 = 0.3;
+
+struct GlobalEndpointPool(Arc<EndpointPool>);
+
+impl Global for GlobalEndpointPool {}
+
+/// Tracks the health and latency of every Ollama server completions have been
+/// routed to, so requests go to the fastest healthy one and fail over when it
+/// stops responding.
+#[derive(Default)]
+pub struct EndpointPool {
+    endpoints: Mutex<HashMap<String, EndpointState>>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct EndpointState {
+    healthy: bool,
+    latency: Option<Duration>,
+    /// The token last used with this server, reused for health checks.
+    api_key: Option<String>,
+    /// The API the server was last spoken to with.
+    api_format: ApiFormat,
+}
+
+pub(crate) fn init(cx: &mut AppContext) {
+    let pool = Arc::new(EndpointPool::default());
+    cx.set_global(GlobalEndpointPool(pool.clone()));
+
+    cx.spawn(|cx| async move {
+        loop {
+            smol::Timer::after(HEALTH_CHECK_INTERVAL).await;
+            if pool.len() > 1 {
+                let Ok(http_client) = cx.update(|cx| {
+                    let settings = OllamaSettings::get_global(cx);
+                    settings
+                        .enabled
+                        .then(|| http_client_for(settings, &cx.http_client()))
+                }) else {
+                    break;
+                };
+                if let Some(http_client) = http_client {
+                    pool.check_health(http_client.as_ref()).await;
+                }
+            }
+        }
+    })
+    .detach();
+}
+
+impl EndpointPool {
+    pub fn global(cx: &AppContext) -> Arc<Self> {
+        cx.global::<GlobalEndpointPool>().0.clone()
+    }
+
+    fn len(&self) -> usize {
+        self.endpoints.lock().len()
+    }
+
+    /// Orders `urls` by preference: healthy servers first, fastest first, then
+    /// servers that haven't been used yet, and servers that are known to be
+    /// down last so there is still something to try when all of them are.
+    pub fn ranked(&self, urls: &[String]) -> Vec<String> {
+        let endpoints = self.endpoints.lock();
+        let mut ranked = urls.to_vec();
+        ranked.dedup();
+        ranked.sort_by_key(|url| match endpoints.get(url) {
+            Some(state) if state.healthy => (0, state.latency.unwrap_or(Duration::MAX)),
+            Some(_) => (2, Duration::ZERO),
+            None => (1, Duration::ZERO),
+        });
+        ranked
+    }
+
+    pub fn record_success(&self, url: &str, latency: Duration) {
+        let mut endpoints = self.endpoints.lock();
+        let state = endpoints.entry(url.to_string()).or_default();
+        state.healthy = true;
+        state.latency = Some(match state.latency {
+            Some(previous) => {
+                previous.mul_f64(1. - LATENCY_SMOOTHING) + latency.mul_f64(LATENCY_SMOOTHING)
+            }
+            None => latency,
+        });
+    }
+
+    pub fn record_failure(&self, url: &str) {
+        let mut endpoints = self.endpoints.lock();
+        let state = endpoints.entry(url.to_string()).or_default();
+        state.healthy = false;
+    }
+
+    /// Probes every known server and updates its health and latency.
+    pub async fn check_health(&self, client: &dyn HttpClient) {
+        let endpoints = self
+            .endpoints
+            .lock()
+            .iter()
+            .map(|(url, state)| (url.clone(), state.api_key.clone(), state.api_format))
+            .collect::<Vec<_>>();
+        future::join_all(
+            endpoints
+                .iter()
+                .map(|(url, api_key, api_format)| async move {
+                    let start = Instant::now();
+                    match ping(client, url, api_key.as_deref(), *api_format).await {
+                        Ok(()) => self.record_success(url, start.elapsed()),
+                        Err(error) => {
+                            log::debug!("Ollama server {url} failed its health check: {error}");
+                            self.record_failure(url);
+                        }
+                    }
+                }),
+        )
+        .await;
+    }
+
+    /// Sends `request` to the best of `urls`, moving on to the next server
+    /// whenever one fails. A timeout is returned as-is, since the time budget
+    /// for the completion has already been spent.
+    pub async fn generate(
+        &self,
+        client: &dyn HttpClient,
+        urls: &[String],
+        api_key: Option<&str>,
+        api_format: ApiFormat,
+        request: GenerateRequest,
+        retry: RetryPolicy,
+        timeout: Option<Duration>,
+    ) -> Result<GenerateResponse> {
+        let mut last_error = None;
+        for url in self.ranked(urls) {
+            {
+                let mut endpoints = self.endpoints.lock();
+                let state = endpoints.entry(url.clone()).or_default();
+                state.api_key = api_key.map(ToString::to_string);
+                state.api_format = api_format;
+            }
+
+            let start = Instant::now();
+            let result = match api_format {
+                ApiFormat::Ollama => {
+                    generate(client, &url, api_key, request.clone(), retry, timeout).await
+                }
+                ApiFormat::OpenAi => {
+                    generate_openai(client, &url, api_key, request.clone(), retry, timeout).await
+                }
+            };
+            match result {
+                Ok(response) => {
+                    self.record_success(&url, start.elapsed());
+                    return Ok(response);
+                }
+                Err(error) => {
+                    self.record_failure(&url);
+                    if matches!(OllamaError::of(&error), Some(OllamaError::Timeout(_))) {
+                        return Err(error);
+                    }
+                    log::debug!("Ollama server {url} failed, trying the next one: {error}");
+                    last_error = Some(error);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow!("No Ollama servers are configured")))
+    }
+}
+
+pub(crate) async fn ping(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: Option<&str>,
+    api_format: ApiFormat,
+) -> Result<()> {
+    let path = match api_format {
+        ApiFormat::Ollama => "api/version",
+        ApiFormat::OpenAi => "v1/models",
+    };
+    let request_builder = HttpRequest::builder()
+        .method(Method::GET)
+        .uri(api_endpoint(api_url, path));
+    let request = authorize(request_builder, api_key).body(AsyncBody::default())?;
+    let response = with_timeout(Some(HEALTH_CHECK_TIMEOUT), client.send(request)).await?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(anyhow!("unexpected status {}", response.status()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ranked_prefers_fast_healthy_endpoints() {
+        let pool = EndpointPool::default();
+        let urls = ["a", "b", "c", "d"].map(String::from);
+
+        pool.record_success("a", Duration::from_millis(300));
+        pool.record_success("b", Duration::from_millis(50));
+        pool.record_failure("c");
+        assert_eq!(pool.ranked(&urls), ["b", "a", "d", "c"]);
+
+        pool.record_failure("b");
+        assert_eq!(pool.ranked(&urls), ["a", "d", "b", "c"]);
+    }
+}
@@ -0,0 +1,138 @@
+use db::kvp::KEY_VALUE_STORE;
+use gpui::{AppContext, Context as _, EventEmitter, Global, Model, ModelContext};
+use ollama::OllamaError;
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+use util::ResultExt as _;
+
+const MUTED_ERRORS_KEY: &str = "ollama_muted_errors";
+/// How long after an error is shown before another of the same kind may be.
+const NOTIFICATION_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+struct GlobalErrorReports(Model<ErrorReports>);
+
+impl Global for GlobalErrorReports {}
+
+/// Passes on notable Ollama errors to be shown to the user, as
+/// [`OllamaError`] events. Each is emitted once until a request succeeds
+/// again, and an error of the same kind at most once every ten minutes, so a
+/// broken setup is pointed out once rather than on every keystroke. Kinds the
+/// user has muted are never emitted, though they're still kept as the
+/// current error for the status bar.
+#[derive(Default)]
+pub struct ErrorReports {
+    current: Option<OllamaError>,
+    shown_at: HashMap<&'static str, Instant>,
+    muted: HashSet<String>,
+}
+
+impl EventEmitter<OllamaError> for ErrorReports {}
+
+pub(crate) fn init(cx: &mut AppContext) {
+    let reports = cx.new_model(|_| ErrorReports::default());
+    cx.set_global(GlobalErrorReports(reports.clone()));
+    cx.spawn(|mut cx| async move {
+        let saved = cx
+            .background_executor()
+            .spawn(async { KEY_VALUE_STORE.read_kvp(MUTED_ERRORS_KEY) })
+            .await?;
+        if let Some(saved) = saved {
+            let muted: Vec<String> = serde_json::from_str(&saved)?;
+            reports.update(&mut cx, |reports, _| reports.muted.extend(muted))?;
+        }
+        anyhow::Ok(())
+    })
+    .detach_and_log_err(cx);
+}
+
+impl ErrorReports {
+    pub fn global(cx: &AppContext) -> Option<Model<Self>> {
+        cx.try_global::<GlobalErrorReports>()
+            .map(|reports| reports.0.clone())
+    }
+
+    /// Reports `error` if it's notable and wasn't just reported.
+    pub fn report(&mut self, error: &anyhow::Error, cx: &mut ModelContext<Self>) {
+        let Some(error) = OllamaError::of(error).filter(|error| error.is_notable()) else {
+            return;
+        };
+        if self.current.as_ref() == Some(error) {
+            return;
+        }
+        self.current = Some(error.clone());
+        cx.notify();
+        if self.should_show(error.kind(), Instant::now()) {
+            cx.emit(error.clone());
+        }
+    }
+
+    pub fn record_success(&mut self, cx: &mut ModelContext<Self>) {
+        if self.current.take().is_some() {
+            cx.notify();
+        }
+    }
+
+    /// Forgets the current error and when each kind was last shown, for when
+    /// the settings point at other servers. Muted kinds stay muted.
+    pub fn reset(&mut self, cx: &mut ModelContext<Self>) {
+        self.shown_at.clear();
+        self.record_success(cx);
+    }
+
+    /// The error requests have been failing with since the last success.
+    pub fn current(&self) -> Option<&OllamaError> {
+        self.current.as_ref()
+    }
+
+    /// Stops showing errors of `kind`, now and in later sessions.
+    pub fn mute(&mut self, kind: &str, cx: &mut ModelContext<Self>) {
+        if !self.muted.insert(kind.to_string()) {
+            return;
+        }
+        let mut muted = self.muted.iter().collect::<Vec<_>>();
+        muted.sort();
+        let Some(saved) = serde_json::to_string(&muted).log_err() else {
+            return;
+        };
+        cx.background_executor()
+            .spawn(async move {
+                KEY_VALUE_STORE
+                    .write_kvp(MUTED_ERRORS_KEY.to_string(), saved)
+                    .await
+            })
+            .detach_and_log_err(cx);
+    }
+
+    fn should_show(&mut self, kind: &'static str, now: Instant) -> bool {
+        if self.muted.contains(kind) {
+            return false;
+        }
+        if let Some(shown_at) = self.shown_at.get(kind) {
+            if now.saturating_duration_since(*shown_at) < NOTIFICATION_INTERVAL {
+                return false;
+            }
+        }
+        self.shown_at.insert(kind, now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_show() {
+        let mut reports = ErrorReports::default();
+        let start = Instant::now();
+        assert!(reports.should_show("model_not_found", start));
+        assert!(!reports.should_show("model_not_found", start + Duration::from_secs(60)));
+        assert!(reports.should_show("connection_refused", start + Duration::from_secs(60)));
+        assert!(reports.should_show("model_not_found", start + NOTIFICATION_INTERVAL));
+
+        reports.muted.insert("unauthorized".into());
+        assert!(!reports.should_show("unauthorized", start));
+    }
+}
@@ -0,0 +1,415 @@
+use crate::{
+    completion_text, insertion_context, is_single_line, scheduled_temperature, EndpointPool,
+    OllamaSettings, PromptBuilder, DEFAULT_NUM_CTX,
+};
+use anyhow::{anyhow, Context as _, Result};
+use http_client::HttpClient;
+use ollama::RetryPolicy;
+use serde::Deserialize;
+use std::{
+    fmt::Write as _,
+    time::{Duration, Instant},
+};
+use text::{Buffer, BufferId};
+
+/// Marks where the cursor is in a fixture's text.
+pub const CURSOR_MARKER: &str = "<|cursor|>";
+
+/// The outcome of a headless completion run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompletionRun {
+    /// The suggestion the editor would display, if any.
+    pub proposal: Option<String>,
+    pub model: String,
+    /// How much text before and after the cursor was sent.
+    pub prefix_len: usize,
+    pub suffix_len: usize,
+    /// Time spent waiting on the server, including retries and failover.
+    pub latency: Duration,
+    pub done_reason: Option<String>,
+}
+
+/// Runs the inline completion pipeline outside of the editor, for evaluation
+/// scripts that need to exercise the same request construction, retry,
+/// failover and post-processing as the shipping provider.
+///
+/// `cursor_offset` is a byte offset into `text` once its line endings have been
+/// normalized to `\n`. Suggestions are indented with four spaces per level.
+pub async fn run_completion(
+    client: &dyn HttpClient,
+    text: &str,
+    cursor_offset: usize,
+    settings: &OllamaSettings,
+) -> Result<CompletionRun> {
+    let snapshot = Buffer::new(0, BufferId::new(1)?, text.to_string()).snapshot();
+    let single_line = is_single_line(settings.completion_mode_for(None), &snapshot, cursor_offset);
+    let model = settings.resolve_model(&settings.model);
+    let options = settings.runtime_options(&settings.model);
+    let context_tokens = options.num_ctx.unwrap_or(DEFAULT_NUM_CTX);
+    let temperature = options.temperature.unwrap_or_else(|| {
+        scheduled_temperature(&settings.temperature_schedule, &snapshot, cursor_offset)
+    });
+    let builder = PromptBuilder::new(model, options, context_tokens)
+        .with_temperature(temperature)
+        .with_single_line(single_line)
+        .with_system_prompt(settings.system_prompt_for(model))
+        .with_template(settings.prompt_template_for(model))
+        .with_raw(settings.raw)
+        .with_keep_alive(settings.keep_alive_for(&settings.model))
+        .with_budget(settings.prompt_budget)
+        .with_max_chars(settings.max_prefix_chars, settings.max_suffix_chars);
+    let prompt = builder.prompt_context(&snapshot, cursor_offset, "", "untitled", String::new());
+    let mut request = builder.fim_request(&prompt);
+    let prefix_len = request.prompt.len();
+    let suffix_len = request.suffix.as_ref().map_or(0, |suffix| suffix.len());
+    let mut insertion = insertion_context(&snapshot, cursor_offset, &request, 4, false);
+    insertion.postprocessors = settings.postprocessors_for(model).to_vec();
+    builder.apply_template(&mut request, &prompt);
+
+    let pool = EndpointPool::default();
+    let retry = RetryPolicy::default().with_max_attempts(settings.max_request_attempts);
+    let timeout = Duration::from_secs(settings.completion_timeout_secs);
+    let start = Instant::now();
+    let response = pool
+        .generate(
+            client,
+            &settings.endpoints(),
+            settings.effective_api_key().as_deref(),
+            settings.api_format,
+            request,
+            retry,
+            Some(timeout),
+        )
+        .await?;
+
+    Ok(CompletionRun {
+        proposal: completion_text(&response, &insertion).ok(),
+        model: response.model.clone(),
+        prefix_len,
+        suffix_len,
+        latency: start.elapsed(),
+        done_reason: response.done_reason.clone(),
+    })
+}
+
+/// A completion the model is expected to make. Corpora are JSON lines files
+/// with one fixture per line, e.g.
+/// `{"name": "add", "text": "fn add(a: i32) -> i32 { <|cursor|> }", "expected": "a + 1"}`.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct EvalFixture {
+    pub name: String,
+    /// The file's text, with [`CURSOR_MARKER`] where the completion is requested.
+    pub text: String,
+    pub expected: String,
+}
+
+impl EvalFixture {
+    /// The text without the cursor marker, and the cursor's offset in it.
+    pub fn text_and_cursor(&self) -> Result<(String, usize)> {
+        let cursor = self
+            .text
+            .find(CURSOR_MARKER)
+            .ok_or_else(|| anyhow!("fixture {:?} has no {CURSOR_MARKER}", self.name))?;
+        let text = self.text.replacen(CURSOR_MARKER, "", 1);
+        if text.contains(CURSOR_MARKER) {
+            return Err(anyhow!(
+                "fixture {:?} has more than one {CURSOR_MARKER}",
+                self.name
+            ));
+        }
+        Ok((text, cursor))
+    }
+}
+
+/// Parses a JSON lines corpus of fixtures, skipping blank lines.
+pub fn parse_fixtures(corpus: &str) -> Result<Vec<EvalFixture>> {
+    corpus
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(ix, line)| {
+            let fixture: EvalFixture = serde_json::from_str(line)
+                .with_context(|| format!("invalid fixture on line {}", ix + 1))?;
+            fixture.text_and_cursor()?;
+            Ok(fixture)
+        })
+        .collect()
+}
+
+/// How a suggestion compares to the expected completion, ignoring trailing
+/// whitespace.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EvalMatch {
+    Exact,
+    /// One is a prefix of the other: the model stopped early or kept going.
+    Prefix,
+    Miss,
+}
+
+impl EvalMatch {
+    pub fn compare(proposal: Option<&str>, expected: &str) -> Self {
+        let proposal = proposal.unwrap_or_default().trim_end();
+        let expected = expected.trim_end();
+        if proposal == expected {
+            Self::Exact
+        } else if !proposal.is_empty()
+            && !expected.is_empty()
+            && (expected.starts_with(proposal) || proposal.starts_with(expected))
+        {
+            Self::Prefix
+        } else {
+            Self::Miss
+        }
+    }
+}
+
+/// How the model did on one fixture.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvalResult {
+    pub name: String,
+    /// The run, or the error of a failed request, which counts as a miss.
+    pub run: Result<CompletionRun, String>,
+    pub matched: EvalMatch,
+}
+
+/// The results of replaying a corpus against one model.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvalReport {
+    pub model: String,
+    pub results: Vec<EvalResult>,
+}
+
+impl EvalReport {
+    /// The fraction of fixtures completed exactly as expected.
+    pub fn exact_rate(&self) -> f64 {
+        self.rate(|matched| matched == EvalMatch::Exact)
+    }
+
+    /// The fraction of fixtures whose suggestion matched at least as a prefix.
+    pub fn prefix_rate(&self) -> f64 {
+        self.rate(|matched| matched != EvalMatch::Miss)
+    }
+
+    fn rate(&self, predicate: impl Fn(EvalMatch) -> bool) -> f64 {
+        if self.results.is_empty() {
+            return 0.;
+        }
+        let matches = self
+            .results
+            .iter()
+            .filter(|result| predicate(result.matched))
+            .count();
+        matches as f64 / self.results.len() as f64
+    }
+
+    /// A Markdown summary of the rates, and a row for each fixture that
+    /// didn't match exactly.
+    pub fn markdown(&self) -> String {
+        let mut report = format!(
+            "# Ollama Completion Eval
+
+             {} fixtures against {}: {:.1}% exact, {:.1}% prefix.
+",
+            self.results.len(),
+            self.model,
+            self.exact_rate() * 100.,
+            self.prefix_rate() * 100.
+        );
+        let mismatches = self
+            .results
+            .iter()
+            .filter(|result| result.matched != EvalMatch::Exact)
+            .collect::<Vec<_>>();
+        if mismatches.is_empty() {
+            return report;
+        }
+        report.push_str("\n| Fixture | Match | Suggestion |\n| --- | --- | --- |\n");
+        for result in mismatches {
+            let suggestion = match &result.run {
+                Ok(run) => format!("{:?}", run.proposal.as_deref().unwrap_or_default()),
+                Err(error) => format!("failed: {}", error.lines().next().unwrap_or_default()),
+            };
+            writeln!(
+                report,
+                "| {} | {:?} | {} |",
+                result.name,
+                result.matched,
+                suggestion.replace('|', "\\|")
+            )
+            .ok();
+        }
+        report
+    }
+}
+
+/// Replays `fixtures` one at a time against the model configured in
+/// `settings`, through the same pipeline as [`run_completion`], so changes to
+/// prompt templates and post-processing can be measured before shipping.
+pub async fn run_eval(
+    client: &dyn HttpClient,
+    fixtures: &[EvalFixture],
+    settings: &OllamaSettings,
+) -> EvalReport {
+    let mut results = Vec::with_capacity(fixtures.len());
+    for fixture in fixtures {
+        let run = match fixture.text_and_cursor() {
+            Ok((text, cursor)) => run_completion(client, &text, cursor, settings).await,
+            Err(error) => Err(error),
+        }
+        .map_err(|error| error.to_string());
+        let matched = match &run {
+            Ok(run) => EvalMatch::compare(run.proposal.as_deref(), &fixture.expected),
+            Err(_) => EvalMatch::Miss,
+        };
+        results.push(EvalResult {
+            name: fixture.name.clone(),
+            run,
+            matched,
+        });
+    }
+    EvalReport {
+        model: settings.resolve_model(&settings.model).to_string(),
+        results,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CompletionMode;
+    use futures::AsyncReadExt as _;
+    use gpui::TestAppContext;
+    use http_client::{AsyncBody, FakeHttpClient, Response};
+    use settings::{Settings as _, SettingsStore};
+
+    /// The default settings, with suggestions that may span lines and failed
+    /// requests that aren't retried.
+    fn settings(cx: &mut TestAppContext) -> OllamaSettings {
+        cx.update(|cx| {
+            let store = SettingsStore::test(cx);
+            cx.set_global(store);
+            OllamaSettings::register(cx);
+            SettingsStore::update_global(cx, |store, cx| {
+                store.update_user_settings::<OllamaSettings>(cx, |settings| {
+                    settings.completion_mode = Some(CompletionMode::MultiLine);
+                    settings.max_request_attempts = Some(1);
+                });
+            });
+            OllamaSettings::get_global(cx).clone()
+        })
+    }
+
+    #[gpui::test]
+    fn test_run_completion(cx: &mut TestAppContext) {
+        let client = FakeHttpClient::create(|mut request| async move {
+            let mut body = String::new();
+            request.body_mut().read_to_string(&mut body).await?;
+            let request: serde_json::Value = serde_json::from_str(&body)?;
+            assert_eq!(request["prompt"], "fn add(a: i32, b: i32) -> i32 {\n    ");
+            assert_eq!(request["suffix"], "\n}\n");
+
+            Ok(Response::builder().status(200).body(AsyncBody::from(
+                serde_json::json!({
+                    "model": "qwen2.5-coder:1.5b",
+                    "created_at": "2024-10-01T00:00:00Z",
+                    "response": "a + b  \n",
+                    "done_reason": "stop",
+                    "done": true,
+                })
+                .to_string(),
+            ))?)
+        });
+        let settings = settings(cx);
+        let text = "fn add(a: i32, b: i32) -> i32 {\n    \n}\n";
+
+        let run = smol::block_on(run_completion(client.as_ref(), text, 36, &settings)).unwrap();
+        assert_eq!(run.proposal.as_deref(), Some("a + b"));
+        assert_eq!(run.model, "qwen2.5-coder:1.5b");
+        assert_eq!(run.done_reason.as_deref(), Some("stop"));
+        assert_eq!((run.prefix_len, run.suffix_len), (36, 3));
+    }
+
+    #[gpui::test]
+    fn test_run_eval(cx: &mut TestAppContext) {
+        let client = FakeHttpClient::create(|mut request| async move {
+            let mut body = String::new();
+            request.body_mut().read_to_string(&mut body).await?;
+            let request: serde_json::Value = serde_json::from_str(&body)?;
+            let prompt = request["prompt"].as_str().unwrap_or_default();
+            let response = if prompt.starts_with("fn add") {
+                "a + b"
+            } else if prompt.starts_with("fn sub") {
+                "a"
+            } else {
+                "a / b"
+            };
+
+            Ok(Response::builder().status(200).body(AsyncBody::from(
+                serde_json::json!({
+                    "model": "qwen2.5-coder:1.5b",
+                    "created_at": "2024-10-01T00:00:00Z",
+                    "response": response,
+                    "done_reason": "stop",
+                    "done": true,
+                })
+                .to_string(),
+            ))?)
+        });
+        let corpus = r#"
+{"name": "add", "text": "fn add(a: i32, b: i32) -> i32 {\n    <|cursor|>\n}\n", "expected": "a + b"}
+{"name": "sub", "text": "fn sub(a: i32, b: i32) -> i32 {\n    <|cursor|>\n}\n", "expected": "a - b\n"}
+
+{"name": "mul", "text": "fn mul(a: i32, b: i32) -> i32 {\n    <|cursor|>\n}\n", "expected": "a * b"}
+"#;
+        let fixtures = parse_fixtures(corpus).unwrap();
+        assert_eq!(fixtures.len(), 3);
+        assert_eq!(
+            fixtures[0].text_and_cursor().unwrap(),
+            ("fn add(a: i32, b: i32) -> i32 {\n    \n}\n".to_string(), 36)
+        );
+
+        let report = smol::block_on(run_eval(client.as_ref(), &fixtures, &settings(cx)));
+        let matches = report
+            .results
+            .iter()
+            .map(|result| result.matched)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            matches,
+            [EvalMatch::Exact, EvalMatch::Prefix, EvalMatch::Miss]
+        );
+        assert_eq!(
+            report.markdown(),
+            "# Ollama Completion Eval\n\n\
+             3 fixtures against qwen2.5-coder:1.5b: 33.3% exact, 66.7% prefix.\n\n\
+             | Fixture | Match | Suggestion |\n\
+             | --- | --- | --- |\n\
+             | sub | Prefix | \"a\" |\n\
+             | mul | Miss | \"a / b\" |\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_fixtures() {
+        assert!(
+            parse_fixtures(r#"{"name": "a", "text": "x", "expected": "y"}"#)
+                .unwrap_err()
+                .to_string()
+                .contains("has no <|cursor|>")
+        );
+        assert!(parse_fixtures(
+            r#"{"name": "a", "text": "<|cursor|><|cursor|>", "expected": "y"}"#
+        )
+        .is_err());
+        assert_eq!(
+            parse_fixtures("\n{\"name\": 1}").unwrap_err().to_string(),
+            "invalid fixture on line 2"
+        );
+        assert_eq!(EvalMatch::compare(None, ""), EvalMatch::Exact);
+        assert_eq!(EvalMatch::compare(None, "a"), EvalMatch::Miss);
+        assert_eq!(
+            EvalMatch::compare(Some("a + b;"), "a + b"),
+            EvalMatch::Prefix
+        );
+    }
+}
@@ -0,0 +1,434 @@
+use crate::{head_within, OllamaSettings};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use extension::{CompletionContext, Extension, WorktreeDelegate};
+use fs::Fs;
+use futures::future::{self, Either};
+use gpui::{AppContext, BackgroundExecutor, Global, WeakModel};
+use language::{BufferSnapshot, LanguageName};
+use parking_lot::RwLock;
+use project::Project;
+use std::{
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use text::Bias;
+use util::paths::PathMatcher;
+
+/// Answers longer than this are cut before their tokens are counted.
+const MAX_ANSWER_BYTES: usize = 64 * 1024;
+
+/// Adds text to the prompt of inline completion requests, like an extension
+/// that knows a framework's conventions.
+#[async_trait]
+pub trait CompletionContextProvider: Send + Sync {
+    fn id(&self) -> Arc<str>;
+
+    /// Whether the provider is asked for completions in `language`.
+    fn supports_language(&self, language: Option<&str>) -> bool;
+
+    async fn context(
+        &self,
+        context: CompletionContext,
+        worktree: Option<Arc<dyn WorktreeDelegate>>,
+    ) -> Result<String>;
+}
+
+/// A completion context provider declared in an extension's manifest.
+pub struct ExtensionCompletionContextProvider {
+    extension: Arc<dyn Extension>,
+    id: Arc<str>,
+    /// The languages it's asked about. Empty for all of them.
+    languages: Vec<LanguageName>,
+}
+
+impl ExtensionCompletionContextProvider {
+    pub fn new(extension: Arc<dyn Extension>, id: Arc<str>, languages: Vec<LanguageName>) -> Self {
+        Self {
+            extension,
+            id,
+            languages,
+        }
+    }
+}
+
+#[async_trait]
+impl CompletionContextProvider for ExtensionCompletionContextProvider {
+    fn id(&self) -> Arc<str> {
+        self.id.clone()
+    }
+
+    fn supports_language(&self, language: Option<&str>) -> bool {
+        self.languages.is_empty()
+            || language.map_or(false, |language| {
+                self.languages
+                    .iter()
+                    .any(|name| name.0.as_ref() == language)
+            })
+    }
+
+    async fn context(
+        &self,
+        context: CompletionContext,
+        worktree: Option<Arc<dyn WorktreeDelegate>>,
+    ) -> Result<String> {
+        self.extension
+            .provide_completion_context(self.id.clone(), context, worktree)
+            .await
+    }
+}
+
+struct RegisteredProvider {
+    provider: Arc<dyn CompletionContextProvider>,
+    /// Set while the provider is answering. A provider still busy with an
+    /// earlier request, perhaps one that timed out, isn't asked again.
+    running: Arc<AtomicBool>,
+}
+
+struct GlobalCompletionContextProviders(Arc<CompletionContextProviders>);
+
+impl Global for GlobalCompletionContextProviders {}
+
+/// The completion context providers registered by extensions.
+#[derive(Default)]
+pub struct CompletionContextProviders {
+    providers: RwLock<Vec<RegisteredProvider>>,
+}
+
+pub(crate) fn init(cx: &mut AppContext) {
+    cx.set_global(GlobalCompletionContextProviders(Arc::default()));
+}
+
+impl CompletionContextProviders {
+    pub fn global(cx: &AppContext) -> Arc<Self> {
+        cx.global::<GlobalCompletionContextProviders>().0.clone()
+    }
+
+    /// Adds `provider`, replacing any registered with the same ID.
+    pub fn register(&self, provider: Arc<dyn CompletionContextProvider>) {
+        let mut providers = self.providers.write();
+        providers.retain(|registered| registered.provider.id() != provider.id());
+        providers.push(RegisteredProvider {
+            provider,
+            running: Arc::default(),
+        });
+    }
+
+    pub fn remove(&self, id: &Arc<str>) {
+        self.providers
+            .write()
+            .retain(|registered| &registered.provider.id() != id);
+    }
+
+    /// The providers for `language` that aren't busy answering.
+    fn available_for(
+        &self,
+        language: Option<&str>,
+    ) -> Vec<(Arc<dyn CompletionContextProvider>, Arc<AtomicBool>)> {
+        self.providers
+            .read()
+            .iter()
+            .filter(|registered| {
+                registered.provider.supports_language(language)
+                    && !registered.running.load(Ordering::Acquire)
+            })
+            .map(|registered| (registered.provider.clone(), registered.running.clone()))
+            .collect()
+    }
+}
+
+/// What's needed to ask extensions for an inline completion's context, read
+/// on the main thread so they can be asked in the background.
+pub(crate) struct ExtensionContextRequest {
+    providers: Vec<(Arc<dyn CompletionContextProvider>, Arc<AtomicBool>)>,
+    context: CompletionContext,
+    worktree: Option<Arc<dyn WorktreeDelegate>>,
+    max_tokens: usize,
+    timeout: Duration,
+}
+
+impl ExtensionContextRequest {
+    /// Returns `None` when no extension is to be asked.
+    pub fn new(
+        settings: &OllamaSettings,
+        snapshot: &BufferSnapshot,
+        cursor_offset: usize,
+        language: Option<&str>,
+        path: &str,
+        project: Option<&WeakModel<Project>>,
+        cx: &AppContext,
+    ) -> Option<Self> {
+        if settings.extension_context_tokens == 0 {
+            return None;
+        }
+        let providers = CompletionContextProviders::global(cx).available_for(language);
+        if providers.is_empty() {
+            return None;
+        }
+        let cursor_offset = snapshot.clip_offset(cursor_offset, Bias::Left);
+        let prefix_start = snapshot.clip_offset(
+            cursor_offset.saturating_sub(settings.max_prefix_chars),
+            Bias::Right,
+        );
+        let suffix_end = snapshot.clip_offset(
+            (cursor_offset + settings.max_suffix_chars).min(snapshot.len()),
+            Bias::Left,
+        );
+        let context = CompletionContext {
+            path: path.to_string(),
+            language: language.unwrap_or_default().to_string(),
+            prefix: snapshot
+                .text_for_range(prefix_start..cursor_offset)
+                .collect(),
+            suffix: snapshot.text_for_range(cursor_offset..suffix_end).collect(),
+        };
+        Some(Self {
+            providers,
+            context,
+            worktree: completion_worktree(settings, snapshot, project, cx),
+            max_tokens: settings.extension_context_tokens,
+            timeout: Duration::from_millis(settings.extension_context_timeout_ms),
+        })
+    }
+
+    /// Asks every provider at once, and joins the answers that came in time,
+    /// in the order the providers were registered, within the token budget.
+    pub async fn run(self, executor: BackgroundExecutor) -> String {
+        let answers = future::join_all(self.providers.into_iter().map(|(provider, running)| {
+            running.store(true, Ordering::Release);
+            let context = self.context.clone();
+            let worktree = self.worktree.clone();
+            let id = provider.id();
+            let task = executor.spawn(async move {
+                let answer = provider.context(context, worktree).await;
+                running.store(false, Ordering::Release);
+                answer
+            });
+            let timer = executor.timer(self.timeout);
+            async move {
+                match future::select(task, timer).await {
+                    Either::Left((Ok(answer), _)) => Some(answer),
+                    Either::Left((Err(error), _)) => {
+                        log::debug!("completion context provider {id} failed: {error:#}");
+                        None
+                    }
+                    Either::Right((_, task)) => {
+                        log::debug!("completion context provider {id} timed out");
+                        task.detach();
+                        None
+                    }
+                }
+            }
+        }))
+        .await;
+
+        let mut context = String::new();
+        let mut remaining = self.max_tokens;
+        for answer in answers.into_iter().flatten() {
+            let mut len = answer.len().min(MAX_ANSWER_BYTES);
+            while !answer.is_char_boundary(len) {
+                len -= 1;
+            }
+            let (len, tokens) = head_within(&answer[..len], remaining);
+            if answer[..len].trim().is_empty() {
+                continue;
+            }
+            context.push_str(&answer[..len]);
+            if !context.ends_with('\n') {
+                context.push('\n');
+            }
+            remaining -= tokens;
+        }
+        context
+    }
+}
+
+/// The worktree of the file being completed, for providers to read other
+/// files from. Only local worktrees are shared.
+fn completion_worktree(
+    settings: &OllamaSettings,
+    snapshot: &BufferSnapshot,
+    project: Option<&WeakModel<Project>>,
+    cx: &AppContext,
+) -> Option<Arc<dyn WorktreeDelegate>> {
+    let file = snapshot.file()?;
+    let project = project?.upgrade()?;
+    let project = project.read(cx);
+    let worktree = project.worktree_for_id(file.worktree_id(cx), cx)?;
+    let worktree = worktree.read(cx);
+    if !worktree.is_local() {
+        return None;
+    }
+    Some(Arc::new(CompletionWorktree {
+        id: worktree.id().to_proto(),
+        root: worktree.abs_path(),
+        fs: project.fs().clone(),
+        disabled_paths: settings.disabled_paths(),
+    }))
+}
+
+struct CompletionWorktree {
+    id: u64,
+    root: Arc<Path>,
+    fs: Arc<dyn Fs>,
+    /// Files matching `disabled_globs` can't be read.
+    disabled_paths: PathMatcher,
+}
+
+#[async_trait]
+impl WorktreeDelegate for CompletionWorktree {
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn root_path(&self) -> String {
+        self.root.to_string_lossy().to_string()
+    }
+
+    async fn read_text_file(&self, path: PathBuf) -> Result<String> {
+        if !path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(anyhow!(
+                "{} is not a relative path inside the worktree",
+                path.display()
+            ));
+        }
+        if self.disabled_paths.is_match(&path) {
+            return Err(anyhow!("{} can't be read", path.display()));
+        }
+        self.fs.load(&self.root.join(path)).await
+    }
+
+    async fn which(&self, _binary_name: String) -> Option<String> {
+        None
+    }
+
+    async fn shell_env(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::count_tokens;
+    use gpui::TestAppContext;
+
+    enum Answer {
+        Text(&'static str),
+        Error,
+        Never,
+    }
+
+    struct FakeProvider {
+        id: &'static str,
+        answer: Answer,
+    }
+
+    #[async_trait]
+    impl CompletionContextProvider for FakeProvider {
+        fn id(&self) -> Arc<str> {
+            self.id.into()
+        }
+
+        fn supports_language(&self, language: Option<&str>) -> bool {
+            language == Some("Rust")
+        }
+
+        async fn context(
+            &self,
+            _context: CompletionContext,
+            _worktree: Option<Arc<dyn WorktreeDelegate>>,
+        ) -> Result<String> {
+            match self.answer {
+                Answer::Text(text) => Ok(text.to_string()),
+                Answer::Error => Err(anyhow!("no context")),
+                Answer::Never => future::pending().await,
+            }
+        }
+    }
+
+    fn request(
+        providers: &CompletionContextProviders,
+        max_tokens: usize,
+    ) -> ExtensionContextRequest {
+        ExtensionContextRequest {
+            providers: providers.available_for(Some("Rust")),
+            context: CompletionContext {
+                path: "src/main.rs".into(),
+                language: "Rust".into(),
+                prefix: "fn main() {\n    ".into(),
+                suffix: "\n}\n".into(),
+            },
+            worktree: None,
+            max_tokens,
+            timeout: Duration::from_millis(250),
+        }
+    }
+
+    #[test]
+    fn test_register_and_remove() {
+        let providers = CompletionContextProviders::default();
+        let provider = |id, text| {
+            Arc::new(FakeProvider {
+                id,
+                answer: Answer::Text(text),
+            })
+        };
+        providers.register(provider("axum", "// Handlers return impl IntoResponse.\n"));
+        providers.register(provider("axum", "// Handlers are async.\n"));
+        providers.register(provider("serde", "// Derive Serialize.\n"));
+        assert_eq!(providers.available_for(Some("Rust")).len(), 2);
+        assert!(providers.available_for(Some("Python")).is_empty());
+
+        providers.remove(&"axum".into());
+        let available = providers.available_for(Some("Rust"));
+        assert_eq!(available.len(), 1);
+        assert_eq!(available[0].0.id().as_ref(), "serde");
+    }
+
+    #[gpui::test]
+    async fn test_run(cx: &mut TestAppContext) {
+        let providers = CompletionContextProviders::default();
+        for (id, answer) in [
+            (
+                "axum",
+                Answer::Text("// Handlers return impl IntoResponse."),
+            ),
+            ("broken", Answer::Error),
+            ("slow", Answer::Never),
+            ("serde", Answer::Text("// Derive Serialize.\n")),
+        ] {
+            providers.register(Arc::new(FakeProvider { id, answer }));
+        }
+
+        let executor = cx.executor();
+        let task = executor.spawn(request(&providers, 512).run(executor.clone()));
+        executor.run_until_parked();
+        executor.advance_clock(Duration::from_millis(250));
+        assert_eq!(
+            task.await,
+            "// Handlers return impl IntoResponse.\n// Derive Serialize.\n"
+        );
+
+        // The provider that timed out is still busy, and isn't asked again.
+        let available = providers.available_for(Some("Rust"));
+        assert_eq!(available.len(), 3);
+        assert!(available
+            .iter()
+            .all(|(provider, _)| provider.id().as_ref() != "slow"));
+
+        // Answers share the token budget, in the order they were registered.
+        let budget = count_tokens("// Handlers return impl IntoResponse.\n");
+        let task = executor.spawn(request(&providers, budget).run(executor.clone()));
+        executor.run_until_parked();
+        executor.advance_clock(Duration::from_millis(250));
+        assert_eq!(task.await, "// Handlers return impl IntoResponse.\n");
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::VecDeque;
+
+/// How many recent suggestions are remembered for use while offline.
+const CACHE_CAPACITY: usize = 64;
+/// How much of the text before the cursor identifies a cached suggestion.
+const CACHE_KEY_CHARS: usize = 256;
+/// Partial identifiers shorter than this aren't worth completing locally.
+const MIN_IDENTIFIER_PREFIX: usize = 2;
+
+/// Where a suggestion shown while the server is unreachable came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FallbackSource {
+    /// A suggestion the model made earlier for the same text.
+    Cache,
+    /// A continuation guessed from the surrounding text.
+    Heuristic,
+}
+
+impl FallbackSource {
+    pub fn label(&self) -> &'static str {
+        match self {
+            FallbackSource::Cache => "offline: cached",
+            FallbackSource::Heuristic => "offline: guessed",
+        }
+    }
+
+    pub fn tooltip(&self) -> &'static str {
+        match self {
+            FallbackSource::Cache => {
+                "Ollama is unreachable. This is a suggestion the model made earlier for the same code."
+            }
+            FallbackSource::Heuristic => {
+                "Ollama is unreachable. This suggestion was guessed from nearby text, not generated by the model."
+            }
+        }
+    }
+}
+
+/// Remembers the model's most recent suggestions, keyed by the text that
+/// preceded the cursor when they were made.
+#[derive(Default)]
+pub struct CompletionCache {
+    entries: VecDeque<(String, String)>,
+}
+
+impl CompletionCache {
+    pub fn insert(&mut self, prefix: &str, text: String) {
+        let key = cache_key(prefix);
+        self.entries.retain(|(existing, _)| existing != key);
+        if self.entries.len() == CACHE_CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((key.to_string(), text));
+    }
+
+    pub fn get(&self, prefix: &str) -> Option<&str> {
+        let key = cache_key(prefix);
+        self.entries
+            .iter()
+            .rev()
+            .find(|(existing, _)| existing == key)
+            .map(|(_, text)| text.as_str())
+    }
+}
+
+fn cache_key(prefix: &str) -> &str {
+    let mut start = prefix.len().saturating_sub(CACHE_KEY_CHARS);
+    while !prefix.is_char_boundary(start) {
+        start += 1;
+    }
+    &prefix[start..]
+}
+
+/// Picks a suggestion without the model: a cached one for the same text if
+/// there is one, otherwise a guess from the text before the cursor.
+pub fn fallback_completion(
+    cache: &CompletionCache,
+    prefix: &str,
+) -> Option<(String, FallbackSource)> {
+    if let Some(text) = cache.get(prefix) {
+        return Some((text.to_string(), FallbackSource::Cache));
+    }
+    heuristic_completion(prefix).map(|text| (text, FallbackSource::Heuristic))
+}
+
+/// Completes the identifier under the cursor from a longer one used earlier,
+/// or else repeats the rest of the previous line when the current line has
+/// started out the same way.
+pub fn heuristic_completion(prefix: &str) -> Option<String> {
+    identifier_continuation(prefix).or_else(|| previous_line_continuation(prefix))
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn identifier_continuation(prefix: &str) -> Option<String> {
+    let partial_start = prefix
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_identifier_char(*c))
+        .last()
+        .map(|(ix, _)| ix)?;
+    let partial = &prefix[partial_start..];
+    if partial.chars().count() < MIN_IDENTIFIER_PREFIX {
+        return None;
+    }
+
+    prefix[..partial_start]
+        .split(|c: char| !is_identifier_char(c))
+        .rev()
+        .find(|identifier| identifier.len() > partial.len() && identifier.starts_with(partial))
+        .map(|identifier| identifier[partial.len()..].to_string())
+}
+
+fn previous_line_continuation(prefix: &str) -> Option<String> {
+    let (before, current_line) = prefix.rsplit_once('\n')?;
+    if current_line.trim().is_empty() {
+        return None;
+    }
+    let previous_line = before.rsplit('\n').next()?;
+    let rest = previous_line.strip_prefix(current_line)?;
+    if rest.trim().is_empty() {
+        None
+    } else {
+        Some(rest.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heuristic_completion() {
+        assert_eq!(
+            heuristic_completion("let request_builder = foo();\nreq").as_deref(),
+            Some("uest_builder")
+        );
+        assert_eq!(
+            heuristic_completion("    .arg(\"--verbose\")\n    .arg(").as_deref(),
+            Some("\"--verbose\")")
+        );
+        assert_eq!(heuristic_completion("fn main() {\n    "), None);
+        assert_eq!(heuristic_completion("x"), None);
+    }
+
+    #[test]
+    fn test_cache_prefers_latest_entry() {
+        let mut cache = CompletionCache::default();
+        cache.insert("fn main() {", "}".into());
+        cache.insert("fn main() {", "\n    todo!()\n}".into());
+        assert_eq!(
+            fallback_completion(&cache, "fn main() {"),
+            Some(("\n    todo!()\n}".into(), FallbackSource::Cache))
+        );
+        assert_eq!(cache.get("fn other() {"), None);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::head_within;
+use language::{BufferSnapshot, Point};
+
+/// How a top-level syntax node is shown in the file header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum HeaderItem {
+    /// Imports are shown whole, since every name in them matters.
+    Whole,
+    /// Other declarations are shown by their first line, which names them
+    /// and, for functions, gives their signature.
+    FirstLine,
+    Skip,
+}
+
+/// Node kinds, across tree-sitter grammars, that bring names into scope.
+const IMPORT_KINDS: &[&str] = &[
+    "import",
+    "use_declaration",
+    "extern_crate",
+    "include",
+    "package",
+    "using_directive",
+    "namespace_use",
+];
+
+/// Whether nodes of `kind` bring names into scope.
+pub(crate) fn is_import_kind(kind: &str) -> bool {
+    IMPORT_KINDS.iter().any(|import| kind.contains(import))
+}
+
+fn header_item(kind: &str) -> HeaderItem {
+    if is_import_kind(kind) {
+        HeaderItem::Whole
+    } else if kind.contains("comment") || kind.contains("attribute") || kind == "ERROR" {
+        HeaderItem::Skip
+    } else {
+        HeaderItem::FirstLine
+    }
+}
+
+/// The file's imports, followed by the first line of each of its other
+/// top-level declarations, up to `budget` tokens. Only the part of the file
+/// before `end`, where the prompt's prefix starts, is included, so the model
+/// knows the names in scope when the cursor is far from the top of the file.
+pub(crate) fn file_header(snapshot: &BufferSnapshot, end: usize, budget: usize) -> String {
+    if budget == 0 || end == 0 {
+        return String::new();
+    }
+    let Some(layer) = snapshot.syntax_layers().next() else {
+        return String::new();
+    };
+    let root = layer.node();
+    let mut cursor = root.walk();
+    let mut imports = String::new();
+    let mut declarations = String::new();
+    for node in root.named_children(&mut cursor) {
+        if node.end_byte() > end {
+            break;
+        }
+        match header_item(node.kind()) {
+            HeaderItem::Whole => {
+                imports.extend(snapshot.text_for_range(node.byte_range()));
+                imports.push('\n');
+            }
+            HeaderItem::FirstLine => {
+                let row = snapshot.offset_to_point(node.start_byte()).row;
+                let line = snapshot
+                    .text_for_range(Point::new(row, 0)..Point::new(row, snapshot.line_len(row)))
+                    .collect::<String>();
+                declarations.push_str(line.trim_end());
+                declarations.push('\n');
+            }
+            HeaderItem::Skip => {}
+        }
+    }
+
+    // Imports come first, so they're the last to be cut.
+    let mut header = imports + &declarations;
+    let (len, _) = head_within(&header, budget);
+    header.truncate(len);
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_item() {
+        for kind in [
+            "use_declaration",
+            "import_statement",
+            "import_from_statement",
+            "preproc_include",
+            "package_clause",
+            "using_directive",
+        ] {
+            assert_eq!(header_item(kind), HeaderItem::Whole, "{kind}");
+        }
+        for kind in [
+            "function_item",
+            "struct_item",
+            "class_definition",
+            "const_item",
+        ] {
+            assert_eq!(header_item(kind), HeaderItem::FirstLine, "{kind}");
+        }
+        for kind in ["line_comment", "attribute_item", "ERROR"] {
+            assert_eq!(header_item(kind), HeaderItem::Skip, "{kind}");
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use anyhow::Result;
+use editor::{actions::Cancel, Editor};
+use gpui::{AppContext, Context as _, Entity as _, EntityId, Global, Model, ModelContext, Task};
+use util::ResultExt as _;
+use zed_actions::ollama::StopGenerating;
+
+struct GlobalGenerations(Model<Generations>);
+
+impl Global for GlobalGenerations {}
+
+/// The requests streaming a response into the editor, so a slow model can be
+/// stopped from the status bar, or with Escape in the editor it writes to.
+#[derive(Default)]
+pub struct Generations {
+    next_id: usize,
+    active: Vec<Generation>,
+}
+
+struct Generation {
+    id: usize,
+    /// The editor the response is written to.
+    editor: Option<EntityId>,
+    _task: Task<()>,
+}
+
+pub(crate) fn init(cx: &mut AppContext) {
+    let generations = cx.new_model(|_| Generations::default());
+    cx.set_global(GlobalGenerations(generations));
+
+    cx.on_action(|_: &StopGenerating, cx| {
+        if let Some(generations) = Generations::global(cx) {
+            generations.update(cx, |generations, cx| generations.stop_all(cx));
+        }
+    });
+    cx.observe_new_views(|editor: &mut Editor, cx| {
+        editor
+            .register_action(cx.listener(|_, _: &Cancel, cx| {
+                let editor = cx.view().entity_id();
+                let stopped = Generations::global(cx).map_or(false, |generations| {
+                    generations.update(cx, |generations, cx| generations.stop_in(editor, cx))
+                });
+                if !stopped {
+                    cx.propagate();
+                }
+            }))
+            .detach();
+    })
+    .detach();
+}
+
+impl Generations {
+    pub fn global(cx: &AppContext) -> Option<Model<Self>> {
+        cx.try_global::<GlobalGenerations>()
+            .map(|generations| generations.0.clone())
+    }
+
+    /// Runs `task`, which streams a response into `editor`, until it finishes
+    /// or is stopped. Stopping drops the task, which cancels the request and
+    /// keeps the text written so far.
+    pub fn track(editor: Option<EntityId>, task: Task<Result<()>>, cx: &mut AppContext) {
+        match Self::global(cx) {
+            Some(generations) => {
+                generations.update(cx, |generations, cx| generations.insert(editor, task, cx))
+            }
+            None => task.detach_and_log_err(cx),
+        }
+    }
+
+    fn insert(
+        &mut self,
+        editor: Option<EntityId>,
+        task: Task<Result<()>>,
+        cx: &mut ModelContext<Self>,
+    ) {
+        let id = self.next_id;
+        self.next_id += 1;
+        let task = cx.spawn(|this, mut cx| async move {
+            task.await.log_err();
+            this.update(&mut cx, |this, cx| {
+                this.active.retain(|generation| generation.id != id);
+                cx.notify();
+            })
+            .ok();
+        });
+        self.active.push(Generation {
+            id,
+            editor,
+            _task: task,
+        });
+        cx.notify();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.active.is_empty()
+    }
+
+    pub fn stop_all(&mut self, cx: &mut ModelContext<Self>) {
+        if !self.active.is_empty() {
+            self.active.clear();
+            cx.notify();
+        }
+    }
+
+    /// Stops the generations writing to `editor`, returning whether there
+    /// were any.
+    pub fn stop_in(&mut self, editor: EntityId, cx: &mut ModelContext<Self>) -> bool {
+        let len = self.active.len();
+        self.active
+            .retain(|generation| generation.editor != Some(editor));
+        if self.active.len() == len {
+            return false;
+        }
+        cx.notify();
+        true
+    }
+}
@@ -0,0 +1,137 @@
+use crate::count_tokens;
+use git::repository::GitFileStatus;
+use std::{fmt::Write as _, path::Path, sync::Arc};
+
+/// Longer old or new text in a hunk is cut off after this many lines.
+const MAX_HUNK_LINES: usize = 8;
+
+/// A change to the file that hasn't been staged yet.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct UnstagedHunk {
+    /// The first row of the hunk in the buffer.
+    pub row: u32,
+    pub old_text: String,
+    pub new_text: String,
+}
+
+/// Summarizes the file's unstaged changes and lists the other changed files in
+/// its worktree, as line comments in at most `budget` tokens. Hunks closest to
+/// the cursor are kept when not all of them fit.
+pub(crate) fn git_changes_context(
+    path: &Path,
+    hunks: &[UnstagedHunk],
+    changed_files: &[(Arc<Path>, GitFileStatus)],
+    cursor_row: u32,
+    comment_prefix: &str,
+    budget: usize,
+) -> String {
+    let mut context = String::new();
+    let mut remaining = budget;
+
+    if !changed_files.is_empty() {
+        let files = changed_files
+            .iter()
+            .map(|(path, status)| {
+                let status = match status {
+                    GitFileStatus::Added => "added",
+                    GitFileStatus::Modified => "modified",
+                    GitFileStatus::Conflict => "conflicted",
+                };
+                format!("{} ({status})", path.to_string_lossy())
+            })
+            .collect::<Vec<_>>();
+        let summary = format!(
+            "{comment_prefix}Other changed files: {}\n",
+            files.join(", ")
+        );
+        let tokens = count_tokens(&summary);
+        if tokens <= remaining {
+            remaining -= tokens;
+            context.push_str(&summary);
+        }
+    }
+
+    let path = path.to_string_lossy();
+    let header = format!("{comment_prefix}Unstaged changes to {path}:\n");
+    remaining = remaining.saturating_sub(count_tokens(&header));
+    let mut nearest = hunks.iter().collect::<Vec<_>>();
+    nearest.sort_by_key(|hunk| hunk.row.abs_diff(cursor_row));
+    let mut kept = Vec::new();
+    for hunk in nearest {
+        let text = format_hunk(hunk, &path, comment_prefix);
+        let tokens = count_tokens(&text);
+        if tokens > remaining {
+            break;
+        }
+        remaining -= tokens;
+        kept.push((hunk.row, text));
+    }
+    if !kept.is_empty() {
+        kept.sort_by_key(|(row, _)| *row);
+        context.push_str(&header);
+        for (_, text) in kept {
+            context.push_str(&text);
+        }
+    }
+    context
+}
+
+fn format_hunk(hunk: &UnstagedHunk, path: &str, comment_prefix: &str) -> String {
+    let mut text = format!("{comment_prefix}{path}:{}\n", hunk.row + 1);
+    for (sign, lines) in [("-", &hunk.old_text), ("+", &hunk.new_text)] {
+        let lines = lines.lines().collect::<Vec<_>>();
+        for line in lines.iter().take(MAX_HUNK_LINES) {
+            writeln!(text, "{comment_prefix}{sign} {line}").ok();
+        }
+        if lines.len() > MAX_HUNK_LINES {
+            writeln!(text, "{comment_prefix}{sign} ...").ok();
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_git_changes_context() {
+        let path = Path::new("src/lib.rs");
+        let hunks = [
+            UnstagedHunk {
+                row: 2,
+                old_text: "fn a() {}\n".into(),
+                new_text: "fn a(x: i32) {}\n".into(),
+            },
+            UnstagedHunk {
+                row: 40,
+                old_text: String::new(),
+                new_text: "fn b() {}\n".into(),
+            },
+        ];
+        let changed_files: [(Arc<Path>, _); 1] =
+            [(Path::new("src/main.rs").into(), GitFileStatus::Modified)];
+
+        assert_eq!(
+            git_changes_context(path, &hunks, &changed_files, 0, "// ", 1000),
+            "// Other changed files: src/main.rs (modified)\n\
+             // Unstaged changes to src/lib.rs:\n\
+             // src/lib.rs:3\n\
+             // - fn a() {}\n\
+             // + fn a(x: i32) {}\n\
+             // src/lib.rs:41\n\
+             // + fn b() {}\n"
+        );
+
+        // When not every hunk fits, the one nearest the cursor is kept.
+        let budget = count_tokens("// Unstaged changes to src/lib.rs:\n")
+            + count_tokens("// src/lib.rs:41\n// + fn b() {}\n");
+        assert_eq!(
+            git_changes_context(path, &hunks, &[], 39, "// ", budget),
+            "// Unstaged changes to src/lib.rs:\n\
+             // src/lib.rs:41\n\
+             // + fn b() {}\n"
+        );
+        assert_eq!(git_changes_context(path, &[], &[], 0, "// ", 1000), "");
+    }
+}
@@ -0,0 +1,748 @@
+use crate::{count_tokens, head_within, is_import_kind};
+use fs::Fs;
+use gpui::{AppContext, BackgroundExecutor, Global};
+use language::BufferSnapshot;
+use parking_lot::Mutex;
+use project::Project;
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fmt::Write as _,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use util::paths::PathMatcher;
+
+/// At most this many imported files are summarized in a prompt.
+const MAX_IMPORTED_FILES: usize = 8;
+/// Files larger than this are assumed to be generated and are skipped.
+const MAX_FILE_BYTES: usize = 512 * 1024;
+/// Signatures read from disk are read again once they're this old.
+const REREAD_AFTER: Duration = Duration::from_secs(60);
+
+/// Extensions tried, in order, for JavaScript and TypeScript imports that
+/// leave theirs out.
+const SCRIPT_EXTENSIONS: &[&str] = &["ts", "tsx", "js", "jsx", "mjs", "cjs"];
+
+#[derive(Default)]
+struct GlobalImportedSignatures(Arc<ImportedSignatures>);
+
+impl Global for GlobalImportedSignatures {}
+
+/// The public signatures of files imported by the files being edited, read
+/// from disk the first time they're imported. Files open in Zed are read from
+/// their buffers instead, so unsaved changes show.
+#[derive(Default)]
+pub struct ImportedSignatures {
+    /// `None` while a file is being read.
+    files: Mutex<HashMap<PathBuf, Option<(String, Instant)>>>,
+}
+
+pub(crate) fn init(cx: &mut AppContext) {
+    cx.set_global(GlobalImportedSignatures::default());
+}
+
+impl ImportedSignatures {
+    pub fn global(cx: &AppContext) -> Arc<Self> {
+        cx.global::<GlobalImportedSignatures>().0.clone()
+    }
+
+    /// The signatures in the file at `abs_path`, as last read. Files not
+    /// read yet, or read a while ago, are read in the background for later
+    /// requests.
+    fn signatures(
+        self: &Arc<Self>,
+        abs_path: PathBuf,
+        fs: Arc<dyn Fs>,
+        executor: &BackgroundExecutor,
+    ) -> Option<String> {
+        let stale = {
+            let mut files = self.files.lock();
+            match files.get(&abs_path) {
+                Some(Some((signatures, read_at))) if read_at.elapsed() < REREAD_AFTER => {
+                    return Some(signatures.clone());
+                }
+                Some(None) => return None,
+                _ => {}
+            }
+            files.insert(abs_path.clone(), None).flatten()
+        };
+
+        let this = self.clone();
+        executor
+            .spawn(async move {
+                match fs.load(&abs_path).await {
+                    Ok(text) => {
+                        let signatures = if text.len() > MAX_FILE_BYTES {
+                            String::new()
+                        } else {
+                            public_signatures(&text)
+                        };
+                        this.files
+                            .lock()
+                            .insert(abs_path, Some((signatures, Instant::now())));
+                    }
+                    Err(error) => {
+                        log::debug!("Failed to read {abs_path:?} for its signatures: {error:?}");
+                        this.files.lock().remove(&abs_path);
+                    }
+                }
+            })
+            .detach();
+        stale.map(|(signatures, _)| signatures)
+    }
+}
+
+/// The files a buffer imports may be in, read on the main thread so their
+/// signatures can be summarized in the background.
+pub(crate) struct ImportsContextRequest {
+    snapshot: BufferSnapshot,
+    worktree: worktree::Snapshot,
+    is_local: bool,
+    /// The text of the worktree's open buffers, read instead of their files
+    /// so unsaved changes show.
+    open_buffers: HashMap<Arc<Path>, text::BufferSnapshot>,
+    fs: Arc<dyn Fs>,
+    cache: Arc<ImportedSignatures>,
+    executor: BackgroundExecutor,
+}
+
+impl ImportsContextRequest {
+    /// `None` when the buffer's file isn't in `project`.
+    pub fn new(snapshot: &BufferSnapshot, project: &Project, cx: &AppContext) -> Option<Self> {
+        let file = snapshot.file()?;
+        let worktree = project.worktree_for_id(file.worktree_id(cx), cx)?;
+        let worktree = worktree.read(cx);
+        let open_buffers = project
+            .opened_buffers(cx)
+            .into_iter()
+            .filter_map(|buffer| {
+                let buffer = buffer.read(cx);
+                let file = buffer.file()?;
+                (file.worktree_id(cx) == worktree.id())
+                    .then(|| (file.path().clone(), buffer.text_snapshot()))
+            })
+            .collect();
+        Some(Self {
+            snapshot: snapshot.clone(),
+            worktree: worktree.snapshot(),
+            is_local: worktree.is_local(),
+            open_buffers,
+            fs: project.fs().clone(),
+            cache: ImportedSignatures::global(cx),
+            executor: cx.background_executor().clone(),
+        })
+    }
+
+    /// The public signatures of the project's files that the buffer imports,
+    /// in the language's line comments, in at most `budget` tokens, so the
+    /// model knows the APIs about to be called. Files are taken in the order
+    /// they're imported, and files matching `disabled_paths` are never read.
+    pub fn run(&self, comment_prefix: &str, budget: usize, disabled_paths: &PathMatcher) -> String {
+        let Some(file) = self.snapshot.file() else {
+            return String::new();
+        };
+
+        let mut context = String::new();
+        let mut remaining = budget;
+        let imported = imported_paths(&self.snapshot, file.path());
+        for path in readable_imports(imported, file.path(), disabled_paths)
+            .filter(|path| {
+                self.worktree
+                    .entry_for_path(path)
+                    .map_or(false, |entry| entry.is_file())
+            })
+            .take(MAX_IMPORTED_FILES)
+        {
+            let signatures = if let Some(buffer) = self.open_buffers.get(path.as_path()) {
+                public_signatures(&buffer.text())
+            } else if self.is_local {
+                let Ok(abs_path) = self.worktree.absolutize(&path) else {
+                    continue;
+                };
+                let Some(signatures) =
+                    self.cache
+                        .signatures(abs_path, self.fs.clone(), &self.executor)
+                else {
+                    continue;
+                };
+                signatures
+            } else {
+                continue;
+            };
+            if signatures.is_empty() {
+                continue;
+            }
+
+            let header = format!("{comment_prefix}Signatures in {}:\n", path.display());
+            let mut text = header.clone();
+            for line in signatures.lines() {
+                writeln!(text, "{comment_prefix}{line}").ok();
+            }
+            let tokens = count_tokens(&text);
+            if tokens > remaining {
+                // The first signatures of a file that doesn't fit whole still help.
+                let (len, _) = head_within(&text, remaining);
+                if len > header.len() {
+                    context.push_str(&text[..len]);
+                }
+                break;
+            }
+            remaining -= tokens;
+            context.push_str(&text);
+        }
+        context
+    }
+}
+
+/// The imported files that may be summarized for the file at `path`: all but
+/// the file itself and those that must never be sent to the server.
+fn readable_imports<'a>(
+    imported: Vec<PathBuf>,
+    path: &'a Path,
+    disabled_paths: &'a PathMatcher,
+) -> impl Iterator<Item = PathBuf> + 'a {
+    imported
+        .into_iter()
+        .filter(move |imported| imported.as_path() != path && !disabled_paths.is_match(imported))
+}
+
+/// The files, relative to the worktree, that the imports in `snapshot` may
+/// refer to. Only imports of the project's own files can be resolved, so
+/// imports of packages are left out. Candidates that don't exist are
+/// included, to be filtered out against the worktree.
+fn imported_paths(snapshot: &BufferSnapshot, path: &Path) -> Vec<PathBuf> {
+    let Some(layer) = snapshot.syntax_layers().next() else {
+        return Vec::new();
+    };
+    let root = layer.node();
+    let mut cursor = root.walk();
+    let mut paths = Vec::new();
+    for node in root.named_children(&mut cursor) {
+        // Rust's `mod foo;` brings in a file as much as an import does.
+        let is_module_file =
+            node.kind() == "mod_item" && node.child_by_field_name("body").is_none();
+        if !is_import_kind(node.kind()) && !is_module_file {
+            continue;
+        }
+        let import = snapshot
+            .text_for_range(node.byte_range())
+            .collect::<String>();
+        for candidate in import_candidates(&import, path) {
+            if !paths.contains(&candidate) {
+                paths.push(candidate);
+            }
+        }
+    }
+    paths
+}
+
+/// The files, relative to the worktree, that `import`, an import statement in
+/// the file at `path`, may refer to, most likely first.
+fn import_candidates(import: &str, path: &Path) -> Vec<PathBuf> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    match path.extension().and_then(OsStr::to_str).unwrap_or("") {
+        "rs" => rust_candidates(import, path),
+        "py" => python_candidates(import, dir),
+        extension => quoted_candidates(import, dir, extension),
+    }
+}
+
+/// Candidates for `mod` items and `use` declarations of `crate`, `self` or
+/// `super` paths in Rust.
+fn rust_candidates(import: &str, path: &Path) -> Vec<PathBuf> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let is_module_root = matches!(
+        path.file_name().and_then(OsStr::to_str),
+        Some("mod.rs" | "lib.rs" | "main.rs")
+    );
+    // The directory a module's own submodules are in.
+    let module_dir = if is_module_root {
+        dir.to_path_buf()
+    } else {
+        dir.join(path.file_stem().unwrap_or_default())
+    };
+    let module_files = |dir: &Path, segments: &[&str]| {
+        let mut candidates = Vec::new();
+        let mut module = dir.to_path_buf();
+        for segment in segments {
+            module.push(segment);
+            candidates.push(module.with_extension("rs"));
+            candidates.push(module.join("mod.rs"));
+        }
+        candidates
+    };
+
+    let import = import.trim();
+    let import = visibility(import).unwrap_or(import);
+    if let Some(name) = import
+        .strip_prefix("mod ")
+        .and_then(|rest| rest.strip_suffix(';'))
+    {
+        return module_files(&module_dir, &[name.trim()]);
+    }
+    let Some(tree) = import
+        .strip_prefix("use ")
+        .and_then(|rest| rest.strip_suffix(';'))
+    else {
+        return Vec::new();
+    };
+    let segments = tree
+        .split("::")
+        .map(str::trim)
+        .take_while(|segment| segment.chars().all(|c| c == '_' || c.is_alphanumeric()))
+        .collect::<Vec<_>>();
+    let (base, segments) = match segments.split_first() {
+        Some((&"crate", rest)) => {
+            // The crate's root module is the `src` directory of its package.
+            let Some(src) = path
+                .ancestors()
+                .find(|ancestor| ancestor.file_name() == Some(OsStr::new("src")))
+            else {
+                return Vec::new();
+            };
+            (src.to_path_buf(), rest)
+        }
+        Some((&"self", rest)) => (module_dir, rest),
+        Some((&"super", rest)) => {
+            let parent = if is_module_root {
+                dir.parent().unwrap_or(Path::new(""))
+            } else {
+                dir
+            };
+            (parent.to_path_buf(), rest)
+        }
+        _ => return Vec::new(),
+    };
+    module_files(&base, segments)
+}
+
+/// Candidates for relative imports, and absolute imports of the project's own
+/// packages, in Python.
+fn python_candidates(import: &str, dir: &Path) -> Vec<PathBuf> {
+    let import = import.trim();
+    let module = if let Some(rest) = import.strip_prefix("from ") {
+        rest.split_whitespace().next()
+    } else if let Some(rest) = import.strip_prefix("import ") {
+        rest.split([',', ' ']).next()
+    } else {
+        None
+    };
+    // `from . import name` would take knowing which names are modules.
+    let Some(module) = module.filter(|module| !module.trim_start_matches('.').is_empty()) else {
+        return Vec::new();
+    };
+
+    let dots = module.len() - module.trim_start_matches('.').len();
+    let mut bases = Vec::new();
+    if dots == 0 {
+        bases.push(PathBuf::new());
+        bases.push(dir.to_path_buf());
+    } else {
+        let mut base = dir.to_path_buf();
+        for _ in 1..dots {
+            if !base.pop() {
+                return Vec::new();
+            }
+        }
+        bases.push(base);
+    }
+
+    let module = module.trim_start_matches('.');
+    let mut candidates = Vec::new();
+    for base in bases {
+        let module = base.join(module.replace('.', "/"));
+        if module.as_os_str().is_empty() {
+            continue;
+        }
+        candidates.push(module.with_extension("py"));
+        candidates.push(module.join("__init__.py"));
+    }
+    candidates
+}
+
+/// Candidates for imports that name a file in quotes, relative to the
+/// importing file: `./` and `../` paths in JavaScript and TypeScript, and
+/// `#include "..."` in C and C++.
+fn quoted_candidates(import: &str, dir: &Path, extension: &str) -> Vec<PathBuf> {
+    let Some(start) = import.find(['"', '\'', '`']) else {
+        return Vec::new();
+    };
+    let quote = import[start..].chars().next().unwrap_or('"');
+    let Some(len) = import[start + 1..].find(quote) else {
+        return Vec::new();
+    };
+    let specifier = &import[start + 1..start + 1 + len];
+    let is_relative = specifier.starts_with("./") || specifier.starts_with("../");
+    let is_include = import.trim_start().starts_with("#include");
+    if !is_relative && !is_include {
+        return Vec::new();
+    }
+    let Some(module) = normalize(&dir.join(specifier)) else {
+        return Vec::new();
+    };
+    if is_include {
+        return vec![module];
+    }
+
+    // Imports usually leave the extension out, so the importing file's is
+    // tried first. Names like `user.service` have a dot without one.
+    let mut candidates = Vec::new();
+    if module.extension().is_some() {
+        candidates.push(module.clone());
+    }
+    let mut extensions = vec![extension];
+    extensions.extend(SCRIPT_EXTENSIONS.iter().filter(|ext| **ext != extension));
+    candidates.extend(
+        extensions
+            .iter()
+            .map(|extension| with_added_extension(&module, extension)),
+    );
+    candidates.extend(
+        extensions
+            .iter()
+            .map(|extension| module.join("index").with_extension(extension)),
+    );
+    candidates
+}
+
+fn with_added_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    path.into()
+}
+
+/// `path` with `.` and `..` resolved, or `None` when it leaves the worktree.
+pub(crate) fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(normalized)
+}
+
+/// Keywords that start a declaration, after any visibility.
+const DECLARATION_KEYWORDS: &[&str] = &[
+    "fn",
+    "struct",
+    "enum",
+    "union",
+    "trait",
+    "type",
+    "const",
+    "static",
+    "async",
+    "unsafe",
+    "extern",
+    "def",
+    "class",
+    "function",
+    "interface",
+    "abstract",
+    "let",
+    "var",
+    "func",
+    "default",
+];
+
+/// The first line of each declaration in `text` other files can use: the
+/// top-level declarations marked `pub` or `export`, in languages that mark
+/// them, and those whose names don't start with `_` otherwise. Public
+/// methods one level into an `impl` block or class are included under its
+/// first line.
+fn public_signatures(text: &str) -> String {
+    let marks_visibility = text
+        .lines()
+        .any(|line| visibility(line).is_some() && !line.starts_with(char::is_whitespace));
+
+    let mut signatures = String::new();
+    // The first line of the `impl` block or class being read, until one of
+    // its methods is included, and the indentation of its members.
+    let mut container: Option<(Option<&str>, Option<usize>)> = None;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        let indent = line.len() - line.trim_start().len();
+        if indent == 0 {
+            container = None;
+            let public = is_public_declaration(trimmed, marks_visibility);
+            if public {
+                signatures.push_str(line.trim_end());
+                signatures.push('\n');
+            }
+            if is_container(trimmed) && (public || trimmed.starts_with("impl")) {
+                container = Some(((!public).then_some(line.trim_end()), None));
+            }
+            continue;
+        }
+
+        let Some((pending_header, member_indent)) = container.as_mut() else {
+            continue;
+        };
+        let member_indent = *member_indent.get_or_insert(indent);
+        if indent != member_indent || !is_public_method(trimmed, marks_visibility) {
+            continue;
+        }
+        if let Some(header) = pending_header.take() {
+            signatures.push_str(header);
+            signatures.push('\n');
+        }
+        signatures.push_str(line.trim_end());
+        signatures.push('\n');
+    }
+    signatures
+}
+
+/// What's left of `line` after the `pub` or `export` that starts it.
+fn visibility(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    if let Some(rest) = line.strip_prefix("export ") {
+        return Some(rest);
+    }
+    let rest = line.strip_prefix("pub")?;
+    if let Some(rest) = rest.strip_prefix(' ') {
+        Some(rest)
+    } else if rest.starts_with('(') {
+        // `pub(crate)` and friends aren't visible outside the crate, but are
+        // to the file's neighbours, which are the ones importing it.
+        rest.split_once(") ").map(|(_, rest)| rest)
+    } else {
+        None
+    }
+}
+
+fn is_public_declaration(line: &str, marks_visibility: bool) -> bool {
+    let declaration = match visibility(line) {
+        Some(rest) => rest,
+        None if marks_visibility => return false,
+        None => line,
+    };
+    let mut words = declaration.split_whitespace();
+    let Some(keyword) = words.next() else {
+        return false;
+    };
+    if !DECLARATION_KEYWORDS.contains(&keyword) {
+        return false;
+    }
+    let name = words.next().unwrap_or("");
+    marks_visibility || !name.starts_with('_')
+}
+
+fn is_public_method(line: &str, marks_visibility: bool) -> bool {
+    if marks_visibility {
+        return visibility(line)
+            .and_then(|rest| rest.split_whitespace().next())
+            .map_or(false, |keyword| DECLARATION_KEYWORDS.contains(&keyword));
+    }
+    let Some(rest) = line
+        .strip_prefix("def ")
+        .or_else(|| line.strip_prefix("async def "))
+    else {
+        return false;
+    };
+    // Constructors matter to callers, even though they start with `_`.
+    !rest.starts_with('_') || rest.starts_with("__init__")
+}
+
+fn is_container(line: &str) -> bool {
+    let declaration = visibility(line).unwrap_or(line);
+    declaration.starts_with("impl ")
+        || declaration.starts_with("impl<")
+        || declaration.starts_with("class ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import_candidates() {
+        let path = Path::new("crates/ollama/src/ollama_prompt.rs");
+        assert_eq!(
+            import_candidates("mod tests;", Path::new("src/lib.rs")),
+            [PathBuf::from("src/tests.rs"), "src/tests/mod.rs".into()]
+        );
+        assert_eq!(
+            import_candidates(
+                "use crate::ollama_tokens::{count_tokens, head_within};",
+                path
+            ),
+            [
+                PathBuf::from("crates/ollama/src/ollama_tokens.rs"),
+                "crates/ollama/src/ollama_tokens/mod.rs".into(),
+            ]
+        );
+        assert_eq!(
+            import_candidates("use super::PromptBudget;", path),
+            [
+                PathBuf::from("crates/ollama/src/PromptBudget.rs"),
+                "crates/ollama/src/PromptBudget/mod.rs".into(),
+            ]
+        );
+        assert!(import_candidates("use std::sync::Arc;", path).is_empty());
+
+        assert_eq!(
+            import_candidates("from .models import User", Path::new("app/views.py")),
+            [
+                PathBuf::from("app/models.py"),
+                "app/models/__init__.py".into()
+            ]
+        );
+        assert_eq!(
+            import_candidates(
+                "from ..core.db import connect",
+                Path::new("app/api/views.py")
+            ),
+            [
+                PathBuf::from("app/core/db.py"),
+                "app/core/db/__init__.py".into()
+            ]
+        );
+        assert_eq!(
+            import_candidates("import utils", Path::new("app/main.py")),
+            [
+                PathBuf::from("utils.py"),
+                "utils/__init__.py".into(),
+                "app/utils.py".into(),
+                "app/utils/__init__.py".into(),
+            ]
+        );
+
+        let candidates = import_candidates(
+            "import { api } from '../lib/api';",
+            Path::new("src/app/page.tsx"),
+        );
+        assert_eq!(candidates[0], PathBuf::from("src/lib/api.tsx"));
+        assert!(candidates.contains(&PathBuf::from("src/lib/api.ts")));
+        assert!(candidates.contains(&PathBuf::from("src/lib/api/index.tsx")));
+        assert!(
+            import_candidates("import React from 'react';", Path::new("src/app.tsx")).is_empty()
+        );
+        assert_eq!(
+            import_candidates("#include \"util.h\"", Path::new("src/main.c")),
+            [PathBuf::from("src/util.h")]
+        );
+        assert!(import_candidates("#include <stdio.h>", Path::new("src/main.c")).is_empty());
+        assert!(import_candidates("import x from '../../x';", Path::new("a.ts")).is_empty());
+    }
+
+    #[test]
+    fn test_readable_imports() {
+        let path = Path::new("src/app.ts");
+        let disabled_paths = PathMatcher::new(&["**/secrets/**".to_string()]).unwrap();
+        let imported = [
+            "import { KEY } from './secrets/keys';",
+            "import { api } from './api';",
+            "import { self } from './app';",
+        ]
+        .iter()
+        .flat_map(|import| import_candidates(import, path))
+        .collect::<Vec<_>>();
+        assert!(imported.contains(&PathBuf::from("src/secrets/keys.ts")));
+
+        let readable = readable_imports(imported, path, &disabled_paths).collect::<Vec<_>>();
+        assert!(readable.contains(&PathBuf::from("src/api.ts")));
+        assert!(!readable.contains(&PathBuf::from("src/app.ts")));
+        assert!(readable.iter().all(|path| !path.starts_with("src/secrets")));
+    }
+
+    #[test]
+    fn test_public_signatures() {
+        let rust = "\
+use std::sync::Arc;
+
+/// Docs.
+pub struct Builder {
+    model: String,
+}
+
+fn helper() {}
+
+impl Builder {
+    pub fn new(model: &str) -> Self {
+        let inner = 1;
+    }
+
+    fn private(&self) {}
+}
+
+impl Drop for Builder {
+    fn drop(&mut self) {}
+}
+
+pub(crate) fn build() -> Builder {
+";
+        assert_eq!(
+            public_signatures(rust),
+            "\
+pub struct Builder {
+impl Builder {
+    pub fn new(model: &str) -> Self {
+pub(crate) fn build() -> Builder {
+"
+        );
+
+        let python = "\
+import os
+
+def connect(url):
+    pass
+
+def _retry():
+    pass
+
+class User:
+    def __init__(self, name):
+        self.name = name
+
+    def _hash(self):
+        pass
+
+    def save(self):
+        pass
+";
+        assert_eq!(
+            public_signatures(python),
+            "\
+def connect(url):
+class User:
+    def __init__(self, name):
+    def save(self):
+"
+        );
+
+        let typescript = "\
+import { z } from 'zod';
+
+export function fetchUser(id: string): Promise<User> {
+  return get(id);
+}
+
+function get(id: string) {}
+
+export interface User {
+  name: string;
+}
+";
+        assert_eq!(
+            public_signatures(typescript),
+            "\
+export function fetchUser(id: string): Promise<User> {
+export interface User {
+"
+        );
+    }
+}
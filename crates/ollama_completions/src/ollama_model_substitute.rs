@@ -0,0 +1,275 @@
+use crate::ErrorReports;
+use gpui::{AppContext, Context as _, EventEmitter, Global, Model, ModelContext};
+use http_client::HttpClient;
+use ollama::{get_models, LocalModelListing};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+struct GlobalModelSubstitutes(Model<ModelSubstitutes>);
+
+impl Global for GlobalModelSubstitutes {}
+
+/// Completions for `missing`, which the server at `api_url` doesn't have,
+/// now go to `substitute` instead.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelSubstituted {
+    pub api_url: String,
+    pub missing: String,
+    pub substitute: String,
+}
+
+/// Installed models that stand in for configured ones a server doesn't
+/// have, so completions keep coming until the configured model is pulled.
+/// Each substitution is emitted as a [`ModelSubstituted`] event, for the user
+/// to be told about it.
+#[derive(Default)]
+pub struct ModelSubstitutes {
+    /// Substitutes by server and the model they stand in for.
+    substitutes: HashMap<(String, String), String>,
+    /// Missing models whose server is being asked what it has instead.
+    pending: HashSet<(String, String)>,
+}
+
+impl EventEmitter<ModelSubstituted> for ModelSubstitutes {}
+
+pub(crate) fn init(cx: &mut AppContext) {
+    let substitutes = cx.new_model(|_| ModelSubstitutes::default());
+    cx.set_global(GlobalModelSubstitutes(substitutes));
+}
+
+impl ModelSubstitutes {
+    pub fn global(cx: &AppContext) -> Option<Model<Self>> {
+        cx.try_global::<GlobalModelSubstitutes>()
+            .map(|substitutes| substitutes.0.clone())
+    }
+
+    /// What requests for `model` go to on the server at `api_url`.
+    pub fn resolve<'a>(&'a self, api_url: &str, model: &'a str) -> &'a str {
+        self.substitutes
+            .get(&(api_url.to_string(), model.to_string()))
+            .map_or(model, String::as_str)
+    }
+
+    /// Looks for an installed model to use in place of `model`, which a
+    /// request to the server at `api_url` just failed with `error` for.
+    /// When there's none, `error` is reported like any other.
+    pub(crate) fn find_substitute(
+        &mut self,
+        http_client: Arc<dyn HttpClient>,
+        api_url: String,
+        api_key: Option<String>,
+        model: String,
+        error: anyhow::Error,
+        cx: &mut ModelContext<Self>,
+    ) {
+        let key = (api_url, model);
+        if !self.pending.insert(key.clone()) {
+            return;
+        }
+        cx.spawn(|this, mut cx| async move {
+            let (api_url, model) = &key;
+            let installed = get_models(http_client.as_ref(), api_url, api_key.as_deref(), None)
+                .await
+                .map_err(|lookup_error| {
+                    log::debug!("Failed to list the models of {api_url}: {lookup_error:?}");
+                });
+            this.update(&mut cx, |this, cx| {
+                this.pending.remove(&key);
+                let substitute = match &installed {
+                    // It was pulled in the meantime.
+                    Ok(installed) if installed.iter().any(|m| same_model(&m.name, model)) => {
+                        this.substitutes.remove(&key);
+                        return;
+                    }
+                    Ok(installed) => closest_installed(model, installed),
+                    Err(()) => None,
+                };
+                let Some(substitute) = substitute.map(ToString::to_string) else {
+                    this.substitutes.remove(&key);
+                    if let Some(error_reports) = ErrorReports::global(cx) {
+                        error_reports.update(cx, |reports, cx| reports.report(&error, cx));
+                    }
+                    return;
+                };
+                if this.substitutes.get(&key) == Some(&substitute) {
+                    return;
+                }
+                log::info!("{api_url} doesn't have {model}, so completions use {substitute}");
+                this.substitutes.insert(key.clone(), substitute.clone());
+                cx.emit(ModelSubstituted {
+                    api_url: key.0.clone(),
+                    missing: key.1.clone(),
+                    substitute,
+                });
+            })
+        })
+        .detach();
+    }
+
+    /// Stops substituting for `model`, on every server, for when it's been
+    /// pulled.
+    pub fn forget(&mut self, model: &str) {
+        self.substitutes
+            .retain(|(_, missing), _| !same_model(missing, model));
+    }
+
+    /// Forgets every substitute, for when the settings point at other
+    /// servers.
+    pub(crate) fn reset(&mut self) {
+        self.substitutes.clear();
+    }
+}
+
+/// `model`, or what stands in for it on the server at `api_url`.
+pub(crate) fn substitute_model<'a>(api_url: &str, model: &'a str, cx: &'a AppContext) -> &'a str {
+    match ModelSubstitutes::global(cx) {
+        Some(substitutes) => substitutes.read(cx).resolve(api_url, model),
+        None => model,
+    }
+}
+
+/// Whether two model names are the same model, taking a name without a tag
+/// to mean the "latest" one, like Ollama does.
+fn same_model(a: &str, b: &str) -> bool {
+    fn with_tag(name: &str) -> (&str, &str) {
+        name.split_once(':').unwrap_or((name, "latest"))
+    }
+    with_tag(a) == with_tag(b)
+}
+
+/// The installed model closest to `missing`: the largest of the same family
+/// that's smaller than it, or failing that, the smallest code model.
+pub(crate) fn closest_installed<'a>(
+    missing: &str,
+    installed: &'a [LocalModelListing],
+) -> Option<&'a str> {
+    let (family, tag) = missing.split_once(':').unwrap_or((missing, "latest"));
+    let same_family = installed.iter().filter(|listing| {
+        listing
+            .name
+            .split_once(':')
+            .map_or(listing.name.as_str(), |(name, _)| name)
+            == family
+    });
+    let closest = match parameter_count(tag) {
+        Some(missing_parameters) => same_family
+            .filter_map(|listing| Some((listing_parameters(listing)?, listing)))
+            .filter(|(parameters, _)| *parameters < missing_parameters)
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, listing)| listing),
+        // Without a size to compare with, any of the family will do.
+        None => same_family.min_by_key(|listing| listing.size),
+    };
+    closest
+        .or_else(|| {
+            installed
+                .iter()
+                .filter(|listing| is_code_model(&listing.name))
+                .min_by_key(|listing| listing.size)
+        })
+        .map(|listing| listing.name.as_str())
+}
+
+/// How many parameters a model has, from its tag where that says, like
+/// "1.5b" or "7b-instruct", else from what the server reports.
+fn listing_parameters(listing: &LocalModelListing) -> Option<f64> {
+    listing
+        .name
+        .split_once(':')
+        .and_then(|(_, tag)| parameter_count(tag))
+        .or_else(|| parameter_count(&listing.details.parameter_size))
+}
+
+/// The parameter count in a tag or size like "7b", "1.5B" or "500m".
+fn parameter_count(text: &str) -> Option<f64> {
+    text.split(['-', '_']).find_map(|part| {
+        let part = part.to_ascii_lowercase();
+        let (number, scale) = match part.strip_suffix('b') {
+            Some(number) => (number, 1e9),
+            None => (part.strip_suffix('m')?, 1e6),
+        };
+        number.parse::<f64>().ok().map(|number| number * scale)
+    })
+}
+
+fn is_code_model(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    name.contains("code") && !name.contains("embed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ollama::ModelDetails;
+
+    fn listing(name: &str, parameter_size: &str, size: u64) -> LocalModelListing {
+        LocalModelListing {
+            name: name.into(),
+            modified_at: String::new(),
+            size,
+            digest: String::new(),
+            details: ModelDetails {
+                format: "gguf".into(),
+                family: String::new(),
+                families: None,
+                parameter_size: parameter_size.into(),
+                quantization_level: "Q4_K_M".into(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_closest_installed() {
+        let installed = [
+            listing("qwen2.5-coder:1.5b", "1.5B", 986_000_000),
+            listing("qwen2.5-coder:7b-instruct-q8_0", "7.6B", 8_100_000_000),
+            listing("qwen2.5-coder:32b", "32.8B", 19_900_000_000),
+            listing("starcoder2:3b", "3B", 1_700_000_000),
+            listing("nomic-embed-code:latest", "137M", 274_000_000),
+            listing("llama3.2:latest", "3.2B", 2_000_000_000),
+        ];
+        // The largest smaller model of the same family.
+        assert_eq!(
+            closest_installed("qwen2.5-coder:14b", &installed),
+            Some("qwen2.5-coder:7b-instruct-q8_0")
+        );
+        assert_eq!(
+            closest_installed("qwen2.5-coder:3b", &installed),
+            Some("qwen2.5-coder:1.5b")
+        );
+        // Without a smaller one, the smallest code model that isn't for
+        // embeddings.
+        assert_eq!(
+            closest_installed("qwen2.5-coder:0.5b", &installed),
+            Some("qwen2.5-coder:1.5b")
+        );
+        assert_eq!(
+            closest_installed("codellama:7b", &installed),
+            Some("qwen2.5-coder:1.5b")
+        );
+        // Untagged names match any size of the family.
+        assert_eq!(
+            closest_installed("llama3.2", &installed[5..]),
+            Some("llama3.2:latest")
+        );
+        assert_eq!(
+            closest_installed("starcoder2", &installed),
+            Some("starcoder2:3b")
+        );
+        assert_eq!(closest_installed("codellama:7b", &installed[5..]), None);
+    }
+
+    #[test]
+    fn test_parameter_count() {
+        assert_eq!(parameter_count("7b"), Some(7e9));
+        assert_eq!(parameter_count("1.5B"), Some(1.5e9));
+        assert_eq!(parameter_count("7b-instruct-q4_K_M"), Some(7e9));
+        assert_eq!(parameter_count("500m"), Some(5e8));
+        assert_eq!(parameter_count("latest"), None);
+        assert_eq!(parameter_count("q4_K_M"), None);
+        assert!(same_model("codellama", "codellama:latest"));
+        assert!(!same_model("codellama:7b", "codellama"));
+    }
+}
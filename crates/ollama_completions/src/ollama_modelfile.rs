@@ -0,0 +1,211 @@
+use crate::{OllamaSettings, COMPLETION_TEMPERATURE};
+use serde_json::{Map, Value};
+use std::fmt::Write as _;
+
+/// The comment that names the model a Modelfile creates. Modelfiles don't
+/// name their own model, so the editor keeps it in this comment.
+const NAME_COMMENT: &str = "# Name:";
+
+/// The system message of new variants when none is configured.
+const DEFAULT_VARIANT_SYSTEM_PROMPT: &str =
+    "You are a code completion engine. Output only the code to insert at the cursor, without explanations.";
+
+/// The parts of a Modelfile that `/api/create` takes as separate fields.
+/// Newer servers ignore the Modelfile itself and only read these.
+#[derive(Debug, Default, PartialEq)]
+pub struct Modelfile {
+    /// From the `# Name:` comment.
+    pub name: Option<String>,
+    pub from: Option<String>,
+    pub system: Option<String>,
+    pub parameters: Map<String, Value>,
+}
+
+/// A Modelfile for a completion-tuned variant of the configured model, with
+/// the runtime options and system prompt from settings built in, for the user
+/// to edit before creating it.
+pub fn variant_modelfile(settings: &OllamaSettings) -> String {
+    let base = settings.resolve_model(&settings.model);
+    let options = settings.runtime_options(&settings.model);
+    let system = settings
+        .system_prompt_for(base)
+        .unwrap_or(DEFAULT_VARIANT_SYSTEM_PROMPT);
+
+    let mut modelfile = format!(
+        "# A variant of {base} tuned for completions. Edit it, then run\n\
+         # `ollama: create model` to create it on the server.\n\
+         {NAME_COMMENT} {}\n\
+         FROM {base}\n\
+         PARAMETER temperature {COMPLETION_TEMPERATURE}\n",
+        variant_name(base)
+    );
+    let parameters = [
+        ("num_ctx", options.num_ctx.map(|value| value.to_string())),
+        ("num_gpu", options.num_gpu.map(|value| value.to_string())),
+        (
+            "num_thread",
+            options.num_thread.map(|value| value.to_string()),
+        ),
+        ("mirostat", options.mirostat.map(|value| value.to_string())),
+        (
+            "mirostat_eta",
+            options.mirostat_eta.map(|value| value.to_string()),
+        ),
+        (
+            "mirostat_tau",
+            options.mirostat_tau.map(|value| value.to_string()),
+        ),
+        (
+            "repeat_last_n",
+            options.repeat_last_n.map(|value| value.to_string()),
+        ),
+        (
+            "penalize_newline",
+            options.penalize_newline.map(|value| value.to_string()),
+        ),
+    ];
+    for (name, value) in parameters {
+        if let Some(value) = value {
+            writeln!(modelfile, "PARAMETER {name} {value}").ok();
+        }
+    }
+    writeln!(modelfile, "SYSTEM \"\"\"{system}\"\"\"").ok();
+    modelfile
+}
+
+/// `base` with a `-zed` suffix on its tag, like `qwen2.5-coder:7b-zed`.
+fn variant_name(base: &str) -> String {
+    match base.split_once(':') {
+        Some((name, tag)) => format!("{name}:{tag}-zed"),
+        None => format!("{base}:zed"),
+    }
+}
+
+/// Reads the name, `FROM`, `SYSTEM` and `PARAMETER` lines of a Modelfile.
+/// Other instructions are only understood by servers that take the whole
+/// Modelfile.
+pub fn parse_modelfile(text: &str) -> Modelfile {
+    let mut modelfile = Modelfile::default();
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        let line = line.trim();
+        if let Some(name) = line.strip_prefix(NAME_COMMENT) {
+            modelfile.name = Some(name.trim().to_string()).filter(|name| !name.is_empty());
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        let (instruction, argument) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let argument = argument.trim();
+        match instruction.to_ascii_uppercase().as_str() {
+            "FROM" => modelfile.from = Some(argument.to_string()),
+            "SYSTEM" => modelfile.system = Some(quoted_argument(argument, &mut lines)),
+            "PARAMETER" => {
+                let (name, value) = argument
+                    .split_once(char::is_whitespace)
+                    .unwrap_or((argument, ""));
+                let value = parameter_value(value.trim());
+                if name == "stop" {
+                    if let Value::Array(stops) = modelfile
+                        .parameters
+                        .entry(name)
+                        .or_insert_with(|| Value::Array(Vec::new()))
+                    {
+                        stops.push(value);
+                    }
+                } else {
+                    modelfile.parameters.insert(name.to_string(), value);
+                }
+            }
+            _ => {}
+        }
+    }
+    modelfile
+}
+
+/// An argument that may be in quotes, or in triple quotes spanning several
+/// lines.
+fn quoted_argument<'a>(argument: &str, lines: &mut impl Iterator<Item = &'a str>) -> String {
+    if let Some(rest) = argument.strip_prefix("\"\"\"") {
+        let mut text = rest.to_string();
+        while !text.contains("\"\"\"") {
+            let Some(line) = lines.next() else {
+                break;
+            };
+            text.push('\n');
+            text.push_str(line);
+        }
+        text.split("\"\"\"").next().unwrap_or_default().to_string()
+    } else {
+        argument
+            .strip_prefix('"')
+            .and_then(|argument| argument.strip_suffix('"'))
+            .unwrap_or(argument)
+            .to_string()
+    }
+}
+
+/// A parameter's value as JSON: numbers and booleans as such, anything else
+/// as a string.
+fn parameter_value(value: &str) -> Value {
+    if let Ok(number) = value.parse::<i64>() {
+        Value::from(number)
+    } else if let Ok(number) = value.parse::<f64>() {
+        Value::from(number)
+    } else if let Ok(flag) = value.parse::<bool>() {
+        Value::from(flag)
+    } else {
+        Value::from(
+            value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .unwrap_or(value),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_modelfile() {
+        let modelfile = parse_modelfile(
+            "# A comment\n\
+             # Name: qwen2.5-coder:7b-zed\n\
+             FROM qwen2.5-coder:7b\n\
+             parameter temperature 0.2\n\
+             PARAMETER num_ctx 8192\n\
+             PARAMETER stop \"<|endoftext|>\"\n\
+             PARAMETER stop \"<|fim_pad|>\"\n\
+             TEMPLATE {{ .Prompt }}\n\
+             SYSTEM \"\"\"Complete code.\n\
+             Only code.\"\"\"\n",
+        );
+        assert_eq!(
+            modelfile,
+            Modelfile {
+                name: Some("qwen2.5-coder:7b-zed".into()),
+                from: Some("qwen2.5-coder:7b".into()),
+                system: Some("Complete code.\nOnly code.".into()),
+                parameters: json!({
+                    "temperature": 0.2,
+                    "num_ctx": 8192,
+                    "stop": ["<|endoftext|>", "<|fim_pad|>"],
+                })
+                .as_object()
+                .unwrap()
+                .clone(),
+            }
+        );
+        assert_eq!(
+            parse_modelfile("FROM llama3.2\nSYSTEM \"Be brief.\"").system,
+            Some("Be brief.".into())
+        );
+
+        assert_eq!(variant_name("qwen2.5-coder:7b"), "qwen2.5-coder:7b-zed");
+        assert_eq!(variant_name("codellama"), "codellama:zed");
+    }
+}
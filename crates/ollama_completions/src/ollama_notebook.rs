@@ -0,0 +1,200 @@
+use crate::{replace_range, Generations, OllamaSettings};
+use editor::{Editor, EditorMode, ToPoint as _};
+use gpui::{AppContext, Entity as _, ViewContext};
+use language::Point;
+use ollama::{ChatMessage, ChatRequest, RequestPriority};
+use repl::notebook_cells;
+use std::ops::Range;
+use zed_actions::ollama::{FixCell, GenerateCell};
+
+/// How many cells before the current one are sent as context.
+const MAX_EARLIER_CELLS: usize = 8;
+/// Outputs longer than this are cut down to their end, where errors and
+/// results usually are.
+const MAX_OUTPUT_CHARS: usize = 2000;
+
+pub(crate) fn init(cx: &mut AppContext) {
+    cx.observe_new_views(|editor: &mut Editor, cx: &mut ViewContext<Editor>| {
+        if editor.mode() != EditorMode::Full {
+            return;
+        }
+
+        editor
+            .register_action(cx.listener(|editor, _: &GenerateCell, cx| {
+                write_cell(editor, CellTask::Generate, cx)
+            }))
+            .detach();
+        editor
+            .register_action(
+                cx.listener(|editor, _: &FixCell, cx| write_cell(editor, CellTask::Fix, cx)),
+            )
+            .detach();
+    })
+    .detach();
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CellTask {
+    /// Write the cell from the comments or partial code in it.
+    Generate,
+    /// Rewrite the cell so it no longer fails.
+    Fix,
+}
+
+impl CellTask {
+    fn instruction(&self) -> &'static str {
+        match self {
+            CellTask::Generate => {
+                "You write code for a notebook cell. You're given the notebook's earlier cells \
+                with their outputs, then the current cell, which may hold comments describing \
+                what it should do or code to finish. Write the current cell, building on the \
+                variables and imports of the earlier cells. Respond with only the cell's code."
+            }
+            CellTask::Fix => {
+                "You fix code in a notebook cell. You're given the notebook's earlier cells with \
+                their outputs, then the current cell and what it output when run. Rewrite the \
+                current cell so it works, changing as little as possible. Respond with only the \
+                cell's code."
+            }
+        }
+    }
+}
+
+/// Replaces the code of the cell at the cursor with the model's, streaming it
+/// in, using the earlier cells and their outputs as context.
+fn write_cell(editor: &mut Editor, task: CellTask, cx: &mut ViewContext<Editor>) {
+    let snapshot = editor.buffer().read(cx).snapshot(cx);
+    let cursor = editor.selections.newest_anchor().head().to_point(&snapshot);
+    let cells = notebook_cells(editor, cx);
+    let Some(ix) = cells
+        .iter()
+        .position(|cell| cell.range.start.row <= cursor.row && cursor.row <= cell.range.end.row)
+    else {
+        return;
+    };
+    let cell = &cells[ix];
+    let settings = OllamaSettings::for_file(snapshot.file_at(cell.range.start), cx);
+    if !settings.enabled {
+        return;
+    }
+
+    let language_name = snapshot
+        .language_at(cell.code_range.start)
+        .map(|language| language.code_fence_block_name().to_string())
+        .unwrap_or_default();
+    let text = |range: &Range<Point>| snapshot.text_for_range(range.clone()).collect::<String>();
+    let earlier = cells[ix.saturating_sub(MAX_EARLIER_CELLS)..ix]
+        .iter()
+        .map(|cell| (text(&cell.code_range), cell.output.clone()))
+        .collect::<Vec<_>>();
+    let request = ChatRequest {
+        model: settings.resolve_model(&settings.model).to_string(),
+        messages: vec![
+            ChatMessage::System {
+                content: task.instruction().to_string(),
+            },
+            ChatMessage::User {
+                content: cell_prompt(
+                    &language_name,
+                    &earlier,
+                    &text(&cell.code_range),
+                    cell.output.as_deref(),
+                ),
+            },
+        ],
+        stream: true,
+        keep_alive: settings.keep_alive_for(&settings.model),
+        options: Some(settings.runtime_options(&settings.model)),
+        tools: Vec::new(),
+        priority: RequestPriority::UserInitiated,
+    };
+
+    let mut code_range = cell.code_range.clone();
+    if code_range.is_empty() {
+        // Give the new code a line of its own, after the cell's marker and
+        // before whatever follows the cell.
+        editor.edit([(code_range.start..code_range.start, "\n")], cx);
+        if code_range.start.column > 0 {
+            code_range =
+                Point::new(code_range.start.row + 1, 0)..Point::new(code_range.start.row + 1, 0);
+        }
+    }
+    let snapshot = editor.buffer().read(cx).snapshot(cx);
+    let range = snapshot.anchor_after(code_range.start)..snapshot.anchor_before(code_range.end);
+    let task = replace_range(editor, range, request, cx);
+    Generations::track(Some(cx.view().entity_id()), task, cx);
+}
+
+/// The earlier cells with their outputs, followed by the current cell and,
+/// when it was run, its output.
+fn cell_prompt(
+    language_name: &str,
+    earlier: &[(String, Option<String>)],
+    code: &str,
+    output: Option<&str>,
+) -> String {
+    let mut prompt = String::new();
+    for (ix, (code, output)) in earlier.iter().enumerate() {
+        prompt.push_str(&format!(
+            "In [{}]:\n```{language_name}\n{code}\n```\n",
+            ix + 1
+        ));
+        if let Some(output) = output.as_deref().filter(|output| !output.trim().is_empty()) {
+            prompt.push_str(&format!(
+                "Out [{}]:\n```\n{}\n```\n",
+                ix + 1,
+                output_tail(output)
+            ));
+        }
+        prompt.push('\n');
+    }
+    prompt.push_str(&format!("Current cell:\n```{language_name}\n{code}\n```\n"));
+    if let Some(output) = output.filter(|output| !output.trim().is_empty()) {
+        prompt.push_str(&format!("Its output:\n```\n{}\n```\n", output_tail(output)));
+    }
+    prompt
+}
+
+fn output_tail(output: &str) -> &str {
+    let output = output.trim_end();
+    if output.len() <= MAX_OUTPUT_CHARS {
+        return output;
+    }
+    let mut start = output.len() - MAX_OUTPUT_CHARS;
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+    &output[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cell_prompt() {
+        let earlier = vec![
+            ("import math".to_string(), None),
+            (
+                "x = math.pi\nprint(x)".to_string(),
+                Some("3.14159\n".to_string()),
+            ),
+        ];
+        assert_eq!(
+            cell_prompt(
+                "python",
+                &earlier,
+                "print(y * 2)",
+                Some("NameError: name 'y' is not defined\n")
+            ),
+            "In [1]:\n```python\nimport math\n```\n\n\
+            In [2]:\n```python\nx = math.pi\nprint(x)\n```\nOut [2]:\n```\n3.14159\n```\n\n\
+            Current cell:\n```python\nprint(y * 2)\n```\n\
+            Its output:\n```\nNameError: name 'y' is not defined\n```\n"
+        );
+
+        let long_output = format!("{}Traceback", "x".repeat(MAX_OUTPUT_CHARS));
+        assert_eq!(output_tail(&long_output).len(), MAX_OUTPUT_CHARS);
+        assert!(output_tail(&long_output).ends_with("Traceback"));
+    }
+}
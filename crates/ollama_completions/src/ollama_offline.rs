@@ -0,0 +1,209 @@
+use crate::{
+    http_client_for, ping, ApiFormat, ContextWindows, ErrorReports, ModelSubstitutes,
+    OllamaSettings,
+};
+use gpui::{AppContext, Context as _, Global, Model, ModelContext, Task};
+use settings::{Settings as _, SettingsStore};
+use std::time::Duration;
+
+/// Requests in a row that have to fail to connect before the servers are
+/// taken to be offline.
+const FAILURES_BEFORE_OFFLINE: u32 = 3;
+/// How long to wait before checking whether an offline server is back.
+const FIRST_PROBE_DELAY: Duration = Duration::from_secs(5);
+/// The longest wait between checks, reached by doubling the first one.
+const MAX_PROBE_DELAY: Duration = Duration::from_secs(5 * 60);
+/// How long to pause when a busy server doesn't say when to come back.
+const DEFAULT_THROTTLE: Duration = Duration::from_secs(10);
+
+struct GlobalConnectivity(Model<Connectivity>);
+
+impl Global for GlobalConnectivity {}
+
+/// Whether the Ollama servers can be reached. Once a few requests in a row
+/// fail to connect, automatic completions stop sending requests, and the
+/// servers are checked at growing intervals until one of them answers.
+///
+/// A server that's rate-limiting requests pauses automatic completions too,
+/// for as long as it asked.
+#[derive(Default)]
+pub struct Connectivity {
+    consecutive_failures: u32,
+    /// Checks for the servers coming back, while they're offline.
+    probe: Option<Task<()>>,
+    /// Ends the pause, while a server is throttling requests.
+    throttle: Option<Task<()>>,
+}
+
+pub(crate) fn init(cx: &mut AppContext) {
+    let connectivity = cx.new_model(|_| Connectivity::default());
+    cx.set_global(GlobalConnectivity(connectivity.clone()));
+
+    // What was learned about the old servers doesn't apply to new ones, so
+    // pointing the settings elsewhere starts over.
+    let mut servers = Servers::from(OllamaSettings::get_global(cx));
+    cx.observe_global::<SettingsStore>(move |cx| {
+        let new_servers = Servers::from(OllamaSettings::get_global(cx));
+        if new_servers == servers {
+            return;
+        }
+        servers = new_servers;
+        connectivity.update(cx, |connectivity, cx| connectivity.reset(cx));
+        if let Some(error_reports) = ErrorReports::global(cx) {
+            error_reports.update(cx, |reports, cx| reports.reset(cx));
+        }
+        ContextWindows::global(cx).clear();
+        if let Some(substitutes) = ModelSubstitutes::global(cx) {
+            substitutes.update(cx, |substitutes, _| substitutes.reset());
+        }
+    })
+    .detach();
+}
+
+/// The settings that decide which servers requests go to, and how.
+#[derive(PartialEq)]
+struct Servers {
+    endpoints: Vec<String>,
+    api_key: Option<String>,
+    api_format: ApiFormat,
+}
+
+impl From<&OllamaSettings> for Servers {
+    fn from(settings: &OllamaSettings) -> Self {
+        Self {
+            endpoints: settings.endpoints(),
+            api_key: settings.effective_api_key(),
+            api_format: settings.api_format,
+        }
+    }
+}
+
+impl Connectivity {
+    pub fn global(cx: &AppContext) -> Option<Model<Self>> {
+        cx.try_global::<GlobalConnectivity>()
+            .map(|connectivity| connectivity.0.clone())
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.probe.is_some()
+    }
+
+    pub fn is_throttled(&self) -> bool {
+        self.throttle.is_some()
+    }
+
+    pub fn record_success(&mut self, cx: &mut ModelContext<Self>) {
+        self.consecutive_failures = 0;
+        if self.probe.take().is_some() {
+            log::info!("Ollama is reachable again, resuming completions");
+            cx.notify();
+        }
+        if self.throttle.take().is_some() {
+            cx.notify();
+        }
+    }
+
+    /// Forgets failed and throttled requests, resuming completions, for when
+    /// the settings point at other servers.
+    pub fn reset(&mut self, cx: &mut ModelContext<Self>) {
+        self.consecutive_failures = 0;
+        let probing = self.probe.take().is_some();
+        let throttled = self.throttle.take().is_some();
+        if probing || throttled {
+            cx.notify();
+        }
+    }
+
+    /// Records a server turning requests away until `retry_after` has
+    /// passed, pausing automatic completions until then.
+    pub fn record_throttled(&mut self, retry_after: Option<Duration>, cx: &mut ModelContext<Self>) {
+        let pause = retry_after.unwrap_or(DEFAULT_THROTTLE).min(MAX_PROBE_DELAY);
+        log::info!("Ollama is rate-limiting requests, pausing completions for {pause:?}");
+        self.throttle = Some(cx.spawn(|this, mut cx| async move {
+            smol::Timer::after(pause).await;
+            this.update(&mut cx, |this, cx| {
+                this.throttle = None;
+                cx.notify();
+            })
+            .ok();
+        }));
+        cx.notify();
+    }
+
+    /// Records a request that couldn't connect to any server.
+    pub fn record_unreachable(&mut self, cx: &mut ModelContext<Self>) {
+        self.consecutive_failures += 1;
+        if self.consecutive_failures >= FAILURES_BEFORE_OFFLINE && self.probe.is_none() {
+            log::warn!("Ollama is unreachable, pausing completions until it responds again");
+            self.probe = Some(self.spawn_probe(false, cx));
+            cx.notify();
+        }
+    }
+
+    /// Checks for the servers right away instead of waiting for the next
+    /// scheduled check.
+    pub fn retry_now(&mut self, cx: &mut ModelContext<Self>) {
+        if self.probe.is_some() {
+            self.probe = Some(self.spawn_probe(true, cx));
+        }
+    }
+
+    fn spawn_probe(&self, check_now: bool, cx: &mut ModelContext<Self>) -> Task<()> {
+        cx.spawn(|this, mut cx| async move {
+            let mut attempt = 0;
+            let mut delay = if check_now {
+                Duration::ZERO
+            } else {
+                probe_delay(0)
+            };
+            loop {
+                smol::Timer::after(delay).await;
+                delay = probe_delay(attempt);
+                attempt += 1;
+
+                let Ok((http_client, endpoints, api_key, api_format)) = cx.update(|cx| {
+                    let settings = OllamaSettings::get_global(cx);
+                    (
+                        http_client_for(settings, &cx.http_client()),
+                        settings.endpoints(),
+                        settings.effective_api_key(),
+                        settings.api_format,
+                    )
+                }) else {
+                    return;
+                };
+                for url in &endpoints {
+                    let result =
+                        ping(http_client.as_ref(), url, api_key.as_deref(), api_format).await;
+                    if result.is_ok() {
+                        this.update(&mut cx, |this, cx| this.record_success(cx))
+                            .ok();
+                        return;
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// The wait after check number `attempt` fails, doubling each time up to
+/// [`MAX_PROBE_DELAY`].
+fn probe_delay(attempt: u32) -> Duration {
+    FIRST_PROBE_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_PROBE_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_delay() {
+        assert_eq!(probe_delay(0), Duration::from_secs(5));
+        assert_eq!(probe_delay(1), Duration::from_secs(10));
+        assert_eq!(probe_delay(5), Duration::from_secs(160));
+        assert_eq!(probe_delay(6), MAX_PROBE_DELAY);
+        assert_eq!(probe_delay(40), MAX_PROBE_DELAY);
+    }
+}
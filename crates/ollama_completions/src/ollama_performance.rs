@@ -0,0 +1,173 @@
+use gpui::{AppContext, Global};
+use parking_lot::Mutex;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write as _,
+    sync::Arc,
+    time::Duration,
+};
+
+/// How many of each model's latest response times the percentiles are taken
+/// from.
+const MAX_SAMPLES: usize = 10_000;
+
+struct GlobalPerformanceReport(Arc<PerformanceReport>);
+
+impl Global for GlobalPerformanceReport {}
+
+/// How quickly each model answered inline completion requests since Zed
+/// started, how often it timed out, and how often a suggestion came from the
+/// cache instead. Only kept in memory, and never sent anywhere.
+#[derive(Default)]
+pub struct PerformanceReport {
+    models: Mutex<BTreeMap<String, ModelPerformance>>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct ModelPerformance {
+    /// Response times in milliseconds, oldest first.
+    latencies: VecDeque<u64>,
+    /// Requests sent to the model, whether it answered or not.
+    requests: u64,
+    timeouts: u64,
+    /// Suggestions shown from the cache or a prefetch, without a request.
+    cache_hits: u64,
+}
+
+pub(crate) fn init(cx: &mut AppContext) {
+    cx.set_global(GlobalPerformanceReport(Arc::default()));
+}
+
+impl PerformanceReport {
+    pub fn global(cx: &AppContext) -> Arc<Self> {
+        cx.global::<GlobalPerformanceReport>().0.clone()
+    }
+
+    /// Counts a request sent to `model`.
+    pub fn record_request(&self, model: &str) {
+        self.update(model, |performance| performance.requests += 1);
+    }
+
+    /// Adds how long `model` took to answer a request.
+    pub fn record_latency(&self, model: &str, latency: Duration) {
+        self.update(model, |performance| {
+            if performance.latencies.len() == MAX_SAMPLES {
+                performance.latencies.pop_front();
+            }
+            performance.latencies.push_back(latency.as_millis() as u64);
+        });
+    }
+
+    /// Counts a request to `model` that timed out.
+    pub fn record_timeout(&self, model: &str) {
+        self.update(model, |performance| performance.timeouts += 1);
+    }
+
+    /// Counts a suggestion from `model` shown without asking it again.
+    pub fn record_cache_hit(&self, model: &str) {
+        self.update(model, |performance| performance.cache_hits += 1);
+    }
+
+    fn update(&self, model: &str, f: impl FnOnce(&mut ModelPerformance)) {
+        f(self.models.lock().entry(model.to_string()).or_default());
+    }
+
+    /// The report as Markdown, with a row per model.
+    pub fn markdown(&self) -> String {
+        report_markdown(&self.models.lock())
+    }
+}
+
+fn report_markdown(models: &BTreeMap<String, ModelPerformance>) -> String {
+    let mut markdown = String::from(
+        "# Ollama Performance\n\n\
+         Inline completion requests since Zed started. These figures stay on \
+         this machine.\n\n",
+    );
+    if models.is_empty() {
+        markdown.push_str("No Ollama completions have been requested yet.\n");
+        return markdown;
+    }
+
+    markdown.push_str(
+        "| Model | Requests | p50 | p90 | p99 | Timeout rate | Cache hit rate |\n\
+         | --- | ---: | ---: | ---: | ---: | ---: | ---: |\n",
+    );
+    for (model, performance) in models {
+        let mut latencies = performance.latencies.iter().copied().collect::<Vec<_>>();
+        latencies.sort_unstable();
+        let latency = |percentile| {
+            nearest_rank(&latencies, percentile).map_or("-".to_string(), |ms| format!("{ms} ms"))
+        };
+        let timeout_rate = rate(performance.timeouts, performance.requests);
+        let cache_hit_rate = rate(
+            performance.cache_hits,
+            performance.cache_hits + performance.requests,
+        );
+        writeln!(
+            markdown,
+            "| {model} | {} | {} | {} | {} | {timeout_rate} | {cache_hit_rate} |",
+            performance.requests,
+            latency(50),
+            latency(90),
+            latency(99),
+        )
+        .ok();
+    }
+    markdown
+}
+
+/// The smallest of `sorted` at or above `percentile` percent of them.
+fn nearest_rank(sorted: &[u64], percentile: usize) -> Option<u64> {
+    let rank = (percentile * sorted.len()).div_ceil(100);
+    sorted.get(rank.saturating_sub(1)).copied()
+}
+
+fn rate(count: u64, total: u64) -> String {
+    if total == 0 {
+        "-".to_string()
+    } else {
+        format!("{:.1}%", count as f64 / total as f64 * 100.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nearest_rank() {
+        let latencies = (1..=100).collect::<Vec<_>>();
+        assert_eq!(nearest_rank(&latencies, 50), Some(50));
+        assert_eq!(nearest_rank(&latencies, 90), Some(90));
+        assert_eq!(nearest_rank(&latencies, 99), Some(99));
+        assert_eq!(nearest_rank(&[120, 300, 900], 50), Some(300));
+        assert_eq!(nearest_rank(&[120, 300, 900], 99), Some(900));
+        assert_eq!(nearest_rank(&[], 50), None);
+    }
+
+    #[test]
+    fn test_report_markdown() {
+        let report = PerformanceReport::default();
+        assert!(report
+            .markdown()
+            .ends_with("No Ollama completions have been requested yet.\n"));
+
+        for ms in [300, 100, 200] {
+            report.record_request("qwen2.5-coder:1.5b");
+            report.record_latency("qwen2.5-coder:1.5b", Duration::from_millis(ms));
+        }
+        report.record_request("qwen2.5-coder:1.5b");
+        report.record_timeout("qwen2.5-coder:1.5b");
+        report.record_cache_hit("qwen2.5-coder:1.5b");
+        report.record_cache_hit("codellama:7b");
+
+        let markdown = report.markdown();
+        assert!(markdown.ends_with(
+            "| Model | Requests | p50 | p90 | p99 | Timeout rate | Cache hit rate |\n\
+             | --- | ---: | ---: | ---: | ---: | ---: | ---: |\n\
+             | codellama:7b | 0 | - | - | - | - | 100.0% |\n\
+             | qwen2.5-coder:1.5b | 4 | 200 ms | 300 ms | 300 ms | 25.0% | 20.0% |\n"
+        ));
+    }
+}
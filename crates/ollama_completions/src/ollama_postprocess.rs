@@ -0,0 +1,586 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use text::LineEnding;
+
+/// A step that cleans up a raw model response before it's shown. Suggestions
+/// are passed through a chain of these in the configured order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Postprocessor {
+    /// Drops a leading `<think>` block and cuts the suggestion off at the
+    /// first control token the model printed, like `<|endoftext|>`.
+    StripSpecialTokens,
+    /// Removes a Markdown code fence around the suggestion.
+    StripCodeFence,
+    /// Removes trailing whitespace.
+    TrimWhitespace,
+    /// Cuts off the end of the suggestion where it repeats the text after the
+    /// cursor.
+    TrimSuffixOverlap,
+    /// Cuts the suggestion off where it closes a bracket opened before the
+    /// cursor.
+    ClampBrackets,
+    /// Keeps only the first line in single-line mode, and at most
+    /// `max_suggestion_lines` lines otherwise.
+    TruncateLines,
+    /// Rewrites indentation to match the buffer.
+    Reindent,
+    /// Cuts the suggestion off after the first sentence it finishes. Added
+    /// to the chain for prose.
+    EndAtSentence,
+}
+
+impl Postprocessor {
+    /// Every step, in the order they run by default.
+    pub const DEFAULT_CHAIN: [Postprocessor; 7] = [
+        Postprocessor::StripSpecialTokens,
+        Postprocessor::StripCodeFence,
+        Postprocessor::TrimWhitespace,
+        Postprocessor::TrimSuffixOverlap,
+        Postprocessor::ClampBrackets,
+        Postprocessor::TruncateLines,
+        Postprocessor::Reindent,
+    ];
+
+    pub fn apply(&self, text: &str, context: &InsertionContext) -> String {
+        match self {
+            Postprocessor::StripSpecialTokens => strip_special_tokens(text).to_string(),
+            Postprocessor::StripCodeFence => strip_completion_fence(text).to_string(),
+            Postprocessor::TrimWhitespace => text.trim_end().to_string(),
+            Postprocessor::TrimSuffixOverlap => {
+                trim_suffix_overlap(text, &context.suffix).to_string()
+            }
+            Postprocessor::ClampBrackets => {
+                clamp_to_bracket_balance(text, &context.suffix).to_string()
+            }
+            Postprocessor::TruncateLines => {
+                let max_lines = if context.single_line {
+                    1
+                } else {
+                    context.max_lines
+                };
+                truncate_lines(text, max_lines).to_string()
+            }
+            Postprocessor::Reindent => reindent(text, context),
+            Postprocessor::EndAtSentence => end_at_sentence(text).to_string(),
+        }
+    }
+}
+
+/// What post-processing needs to know about where a suggestion is inserted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InsertionContext {
+    /// The text after the cursor that was sent with the request.
+    pub suffix: String,
+    /// The indentation of the cursor's line, in columns.
+    pub line_indent: u32,
+    pub tab_size: u32,
+    pub hard_tabs: bool,
+    /// Whether the suggestion must stay on the cursor's line.
+    pub single_line: bool,
+    /// The most lines a suggestion may span, or 0 for no limit.
+    pub max_lines: usize,
+    /// The steps the suggestion goes through, in order.
+    pub postprocessors: Vec<Postprocessor>,
+}
+
+impl Default for InsertionContext {
+    fn default() -> Self {
+        Self {
+            suffix: String::new(),
+            line_indent: 0,
+            tab_size: 4,
+            hard_tabs: false,
+            single_line: false,
+            max_lines: 0,
+            postprocessors: Postprocessor::DEFAULT_CHAIN.to_vec(),
+        }
+    }
+}
+
+/// Cleans up a raw model response so it can be shown at the cursor, by
+/// running it through the context's postprocessors. Windows line endings,
+/// which models trained on such files print, become the `\n` that buffers
+/// use, so the suggestion compares equal to the code around the cursor.
+pub fn postprocess(text: &str, context: &InsertionContext) -> String {
+    let mut text = text.to_string();
+    LineEnding::normalize(&mut text);
+    context
+        .postprocessors
+        .iter()
+        .fold(text, |text, postprocessor| {
+            postprocessor.apply(&text, context)
+        })
+}
+
+/// Drops a `<think>` block at the start of the response, which reasoning
+/// models print before answering, and cuts the response off at the first
+/// control token, which models that don't stop on their own go on past.
+pub fn strip_special_tokens(text: &str) -> &str {
+    let mut text = text;
+    if let Some(rest) = text.trim_start().strip_prefix("<think>") {
+        text = match rest.split_once("</think>") {
+            Some((_, answer)) => answer.trim_start_matches(['\r', '\n']),
+            // Still thinking when the response ended, so there's no answer.
+            None => "",
+        };
+    }
+    let end = text
+        .match_indices('<')
+        .map(|(ix, _)| ix)
+        .find(|ix| is_special_token(&text[*ix..]))
+        .unwrap_or(text.len());
+    &text[..end]
+}
+
+/// Whether `text` starts with a token like `<|endoftext|>`, `<fim_middle>`,
+/// `<EOT>` or `</s>`.
+fn is_special_token(text: &str) -> bool {
+    fn is_name(name: &str) -> bool {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '\u{2581}')
+    }
+
+    if text.starts_with("<EOT>") || text.starts_with("</s>") {
+        return true;
+    }
+    if let Some(rest) = text.strip_prefix("<fim_") {
+        return rest
+            .split_once('>')
+            .map_or(false, |(name, _)| is_name(name));
+    }
+    for (open, close) in [("<|", "|>"), ("<\u{ff5c}", "\u{ff5c}>")] {
+        if let Some(rest) = text.strip_prefix(open) {
+            return rest
+                .split_once(close)
+                .map_or(false, |(name, _)| is_name(name));
+        }
+    }
+    false
+}
+
+/// Removes a Markdown code fence around a suggestion. Unlike
+/// [`crate::strip_code_fence`], whitespace before the fence is kept when
+/// there's no fence, since it's part of the suggestion.
+pub fn strip_completion_fence(text: &str) -> &str {
+    let Some(rest) = text.trim_start().strip_prefix("```") else {
+        return text;
+    };
+    let Some((_, body)) = rest.split_once('\n') else {
+        return text;
+    };
+    match body
+        .find("\n```")
+        .or_else(|| body.starts_with("```").then_some(0))
+    {
+        Some(end) => &body[..end],
+        None => body,
+    }
+}
+
+/// Cuts off the part of a suggestion that repeats the text already after the
+/// cursor, which the model tends to regenerate (closing brackets, the rest of
+/// the line, the next statement) and which would be duplicated on accept.
+pub fn trim_suffix_overlap<'a>(text: &'a str, suffix: &str) -> &'a str {
+    let text = truncate_at_following_line(text, suffix);
+    trim_rest_of_line(text, suffix)
+}
+
+/// When a later line of the suggestion is the next non-blank line after the
+/// cursor, the model has started regenerating existing code; keep what comes
+/// before it. Lines of only brackets and punctuation are too common to tell
+/// apart, so they aren't matched.
+fn truncate_at_following_line<'a>(text: &'a str, suffix: &str) -> &'a str {
+    let Some(next_line) = suffix
+        .lines()
+        .skip(1)
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .filter(|line| line.chars().any(char::is_alphanumeric))
+    else {
+        return text;
+    };
+
+    let mut line_start = match text.find('\n') {
+        Some(ix) => ix + 1,
+        None => return text,
+    };
+    while line_start < text.len() {
+        let line_end = text[line_start..]
+            .find('\n')
+            .map_or(text.len(), |ix| line_start + ix);
+        if text[line_start..line_end].trim() == next_line {
+            return text[..line_start].trim_end();
+        }
+        line_start = line_end + 1;
+    }
+    text
+}
+
+/// Drops the end of the suggestion when it matches the start of the rest of
+/// the cursor's line, e.g. a closing `)` that's already there.
+fn trim_rest_of_line<'a>(text: &'a str, suffix: &str) -> &'a str {
+    let rest_of_line = suffix.split('\n').next().unwrap_or_default().trim_end();
+    let overlap = (1..=rest_of_line.len().min(text.len()))
+        .rev()
+        .filter(|len| rest_of_line.is_char_boundary(*len))
+        .find(|len| text.ends_with(&rest_of_line[..*len]));
+    match overlap {
+        Some(len) => &text[..text.len() - len],
+        None => text,
+    }
+}
+
+/// Whether the suggestion only repeats the code right after the cursor, so
+/// accepting it would duplicate that code. Each of its lines has to match the
+/// next non-blank line after the cursor in order, ignoring whitespace, which
+/// catches copies the model has formatted slightly differently. Suggestions of
+/// only brackets and punctuation are too common to tell apart, so they never
+/// count as duplicates.
+pub fn duplicates_following_code(text: &str, suffix: &str) -> bool {
+    fn normalize(line: &str) -> String {
+        line.chars().filter(|c| !c.is_whitespace()).collect()
+    }
+
+    if !text.chars().any(char::is_alphanumeric) {
+        return false;
+    }
+    let mut following = suffix
+        .lines()
+        .map(normalize)
+        .filter(|line| !line.is_empty());
+    text.lines()
+        .map(normalize)
+        .filter(|line| !line.is_empty())
+        .all(|line| following.next().map_or(false, |next| next == line))
+}
+
+/// Cuts the suggestion off before the first bracket it closes that was opened
+/// before the cursor, when the suffix already closes that bracket. That's where
+/// the model has run past the end of the enclosing block, and keeping the rest
+/// would leave the brackets unbalanced. Brackets in string literals are
+/// ignored.
+pub fn clamp_to_bracket_balance<'a>(text: &'a str, suffix: &str) -> &'a str {
+    let suffix_closes_outer = bracket_events(suffix)
+        .scan(0i32, |depth, (_, opens)| {
+            *depth += if opens { 1 } else { -1 };
+            Some(*depth)
+        })
+        .any(|depth| depth < 0);
+    if !suffix_closes_outer {
+        return text;
+    }
+
+    let mut depth = 0i32;
+    for (ix, opens) in bracket_events(text) {
+        depth += if opens { 1 } else { -1 };
+        if depth < 0 {
+            return text[..ix].trim_end();
+        }
+    }
+    text
+}
+
+/// The offsets of the brackets in `text` outside of string literals, and
+/// whether each one opens.
+fn bracket_events(text: &str) -> impl Iterator<Item = (usize, bool)> + '_ {
+    let mut quote = None;
+    let mut escaped = false;
+    text.char_indices().filter_map(move |(ix, c)| {
+        if let Some(open_quote) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == open_quote || (c == '\n' && open_quote != '`') {
+                quote = None;
+            }
+            return None;
+        }
+        match c {
+            '"' | '`' => {
+                quote = Some(c);
+                None
+            }
+            '(' | '[' | '{' => Some((ix, true)),
+            ')' | ']' | '}' => Some((ix, false)),
+            _ => None,
+        }
+    })
+}
+
+/// Keeps at most `max_lines` lines of the suggestion. 0 keeps them all.
+pub fn truncate_lines(text: &str, max_lines: usize) -> &str {
+    if max_lines == 0 {
+        return text;
+    }
+    match text.match_indices('\n').nth(max_lines - 1) {
+        Some((ix, _)) => text[..ix].trim_end(),
+        None => text,
+    }
+}
+
+/// Cuts prose off after the first sentence that ends in it, along with any
+/// closing quotes or brackets. Text that doesn't finish a sentence is kept
+/// whole, and so are periods inside words or numbers.
+pub fn end_at_sentence(text: &str) -> &str {
+    let mut chars = text.char_indices().peekable();
+    while let Some((ix, c)) = chars.next() {
+        if !matches!(c, '.' | '!' | '?') {
+            continue;
+        }
+        let mut end = ix + c.len_utf8();
+        while let Some(&(ix, c)) = chars.peek() {
+            if !matches!(c, '"' | '\'' | ')' | ']' | '\u{201d}' | '\u{2019}') {
+                break;
+            }
+            end = ix + c.len_utf8();
+            chars.next();
+        }
+        match chars.peek() {
+            Some((_, c)) if c.is_whitespace() => return &text[..end],
+            None => return text,
+            Some(_) => {}
+        }
+    }
+    text
+}
+
+/// Rewrites the leading whitespace of every line after the first to use the
+/// buffer's tabs or spaces. When the model flattened its output to column 0
+/// even though the cursor's line is indented, the lines are moved under the
+/// cursor's line.
+pub fn reindent(text: &str, context: &InsertionContext) -> String {
+    let tab_size = context.tab_size.max(1);
+    let mut lines = text.split('\n');
+    let mut result = lines.next().unwrap_or_default().to_string();
+    let rest = lines
+        .map(|line| {
+            let content = line.trim_start();
+            let width = (!content.is_empty()).then(|| indent_columns(line, tab_size));
+            (content, width)
+        })
+        .collect::<Vec<_>>();
+
+    let flattened = context.line_indent > 0
+        && rest.iter().all(|(_, width)| width.unwrap_or(0) == 0)
+        && rest
+            .iter()
+            .any(|(content, _)| !content.is_empty() && !starts_with_closer(content));
+
+    for (content, width) in rest {
+        result.push('\n');
+        let Some(mut width) = width else {
+            continue;
+        };
+        if flattened {
+            width = if starts_with_closer(content) {
+                context.line_indent.saturating_sub(tab_size)
+            } else {
+                context.line_indent
+            };
+        }
+        result.push_str(&indent_string(width, tab_size, context.hard_tabs));
+        result.push_str(content);
+    }
+    result
+}
+
+/// The width of a line's leading whitespace, with tabs advancing to the next
+/// tab stop.
+pub fn indent_columns(line: &str, tab_size: u32) -> u32 {
+    let mut columns = 0;
+    for c in line.chars() {
+        match c {
+            ' ' => columns += 1,
+            '\t' => columns += tab_size - columns % tab_size,
+            _ => break,
+        }
+    }
+    columns
+}
+
+fn indent_string(columns: u32, tab_size: u32, hard_tabs: bool) -> String {
+    if hard_tabs {
+        let tabs = "\t".repeat((columns / tab_size) as usize);
+        tabs + &" ".repeat((columns % tab_size) as usize)
+    } else {
+        " ".repeat(columns as usize)
+    }
+}
+
+fn starts_with_closer(line: &str) -> bool {
+    line.starts_with([')', ']', '}'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trim_suffix_overlap() {
+        assert_eq!(trim_suffix_overlap("a, b)", ");\n"), "a, b");
+        assert_eq!(trim_suffix_overlap("a, b);", ");\n"), "a, b");
+        assert_eq!(trim_suffix_overlap("a, b", ");\n"), "a, b");
+        assert_eq!(
+            trim_suffix_overlap("let y = 2;\n    return x + y;\n}", "\n    return x + y;\n}"),
+            "let y = 2;"
+        );
+        assert_eq!(
+            trim_suffix_overlap("if x {\n        y();\n    }", "\n}\n"),
+            "if x {\n        y();\n    }"
+        );
+        assert_eq!(
+            trim_suffix_overlap("let x = 1;\n    let y = 2;", "\n    x + y\n}"),
+            "let x = 1;\n    let y = 2;"
+        );
+        assert_eq!(trim_suffix_overlap(")", ")"), "");
+    }
+
+    #[test]
+    fn test_duplicates_following_code() {
+        let suffix = "\n    return x + y;\n}\n\nfn other() {}\n";
+        assert!(duplicates_following_code("return x + y;", suffix));
+        assert!(duplicates_following_code("return x+y;\n}", suffix));
+        assert!(duplicates_following_code(
+            "return x + y;\n}\n\nfn other() {}",
+            suffix
+        ));
+        assert!(!duplicates_following_code("return x - y;", suffix));
+        assert!(!duplicates_following_code(
+            "let z = 3;\n    return x + y;",
+            suffix
+        ));
+        assert!(!duplicates_following_code(
+            "return x + y;\n}\n\nfn other() {}\nfn third() {}",
+            suffix
+        ));
+        assert!(!duplicates_following_code("}", "\n}\n"));
+        assert!(!duplicates_following_code("x", ""));
+    }
+
+    #[test]
+    fn test_clamp_to_bracket_balance() {
+        assert_eq!(
+            clamp_to_bracket_balance("a();\n}\n\nfn b() {\n}", "\n}\n"),
+            "a();"
+        );
+        assert_eq!(
+            clamp_to_bracket_balance("x, (y, z)), w)", ");"),
+            "x, (y, z)"
+        );
+        // Brackets the suffix doesn't close yet may be closed.
+        assert_eq!(clamp_to_bracket_balance("a();\n}", "\n"), "a();\n}");
+        assert_eq!(
+            clamp_to_bracket_balance("if x {\n    y();\n}", "\n}\n"),
+            "if x {\n    y();\n}"
+        );
+        assert_eq!(
+            clamp_to_bracket_balance("format!(\"{}) \\\"}\", x)", ");"),
+            "format!(\"{}) \\\"}\", x)"
+        );
+    }
+
+    #[test]
+    fn test_truncate_lines() {
+        assert_eq!(truncate_lines("a\nb\nc", 0), "a\nb\nc");
+        assert_eq!(truncate_lines("a\nb\nc", 2), "a\nb");
+        assert_eq!(truncate_lines("a\nb\nc", 3), "a\nb\nc");
+    }
+
+    #[test]
+    fn test_end_at_sentence() {
+        assert_eq!(
+            end_at_sentence("the lazy dog. Then it ran off"),
+            "the lazy dog."
+        );
+        assert_eq!(end_at_sentence("done!\n\nNext"), "done!");
+        assert_eq!(
+            end_at_sentence("he said \"stop.\" Then"),
+            "he said \"stop.\""
+        );
+        assert_eq!(
+            end_at_sentence("costs 3.50 (e.g.x) and more"),
+            "costs 3.50 (e.g.x) and more"
+        );
+        assert_eq!(end_at_sentence("Wait... what"), "Wait...");
+        assert_eq!(end_at_sentence("no end in sight"), "no end in sight");
+    }
+
+    #[test]
+    fn test_strip_special_tokens() {
+        assert_eq!(strip_special_tokens("x + 1;<|endoftext|>"), "x + 1;");
+        assert_eq!(strip_special_tokens("a()\n<fim_middle>b()"), "a()\n");
+        assert_eq!(strip_special_tokens("x</s>"), "x");
+        assert_eq!(
+            strip_special_tokens("<think>\nIt needs a sum.\n</think>\n\na + b"),
+            "a + b"
+        );
+        assert_eq!(strip_special_tokens("<think>unfinished"), "");
+        // Operators and generics that merely look similar are kept.
+        assert_eq!(strip_special_tokens("x <| f"), "x <| f");
+        assert_eq!(strip_special_tokens("Vec<u8>"), "Vec<u8>");
+    }
+
+    #[test]
+    fn test_strip_completion_fence() {
+        assert_eq!(
+            strip_completion_fence("```rust\nlet x = 1;\n```"),
+            "let x = 1;"
+        );
+        assert_eq!(strip_completion_fence("```\nlet x = 1;"), "let x = 1;");
+        assert_eq!(strip_completion_fence("  let x = 1;"), "  let x = 1;");
+    }
+
+    #[test]
+    fn test_postprocess_chain() {
+        let context = InsertionContext {
+            suffix: ");\n".into(),
+            ..Default::default()
+        };
+        assert_eq!(postprocess("a, b);<|endoftext|>\n", &context), "a, b");
+
+        let context = InsertionContext {
+            postprocessors: vec![Postprocessor::StripSpecialTokens],
+            ..context
+        };
+        assert_eq!(postprocess("a, b);<|endoftext|>\n", &context), "a, b);");
+
+        // Windows line endings are normalized before the suffix is compared,
+        // so the closing lines it repeats are still trimmed.
+        let context = InsertionContext {
+            suffix: "\n}\n".into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            postprocess("let s = \"héllo 世界 🦀\";\r\n}\r\n", &context),
+            "let s = \"héllo 世界 🦀\";"
+        );
+    }
+
+    #[test]
+    fn test_reindent() {
+        let spaces = InsertionContext {
+            line_indent: 4,
+            ..Default::default()
+        };
+        assert_eq!(
+            reindent("if x {\n\t\ty();\n\t}", &spaces),
+            "if x {\n        y();\n    }"
+        );
+        assert_eq!(reindent("a();\nb();\n}", &spaces), "a();\n    b();\n}");
+
+        let tabs = InsertionContext {
+            line_indent: 4,
+            hard_tabs: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            reindent("if x {\n        y();\n\n    }", &tabs),
+            "if x {\n\t\ty();\n\n\t}"
+        );
+        assert_eq!(reindent("x\n}", &tabs), "x\n}");
+    }
+}
@@ -0,0 +1,201 @@
+use crate::{LowPowerMode, OllamaSettings};
+use gpui::{AppContext, Global};
+use settings::Settings as _;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// How often the power source is checked while `low_power_mode` is "auto".
+const POWER_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+struct GlobalPowerState(Arc<PowerState>);
+
+impl Global for GlobalPowerState {}
+
+/// Whether the machine is running on battery or in the system's low power
+/// mode, checked periodically so automatic completions can be paused and
+/// suggestions aren't prefetched.
+#[derive(Default)]
+pub struct PowerState {
+    low_power: AtomicBool,
+}
+
+pub(crate) fn init(cx: &mut AppContext) {
+    let state = Arc::new(PowerState::default());
+    cx.set_global(GlobalPowerState(state.clone()));
+
+    cx.spawn(|cx| async move {
+        loop {
+            let Ok((mode, prefetch)) = cx.update(|cx| {
+                let settings = OllamaSettings::get_global(cx);
+                (
+                    settings.enabled.then_some(settings.low_power_mode),
+                    settings.enabled && settings.prefetch_suggestions,
+                )
+            }) else {
+                break;
+            };
+            if mode == Some(LowPowerMode::Auto) || prefetch {
+                let low_power = cx
+                    .background_executor()
+                    .spawn(is_low_power())
+                    .await
+                    .unwrap_or(false);
+                let changed = state.low_power.swap(low_power, Ordering::Relaxed) != low_power;
+                if changed && mode == Some(LowPowerMode::Auto) {
+                    log::info!(
+                        "{} automatic Ollama completions",
+                        if low_power { "Pausing" } else { "Resuming" }
+                    );
+                }
+            }
+            smol::Timer::after(POWER_CHECK_INTERVAL).await;
+        }
+    })
+    .detach();
+}
+
+impl PowerState {
+    pub fn global(cx: &AppContext) -> Arc<Self> {
+        cx.global::<GlobalPowerState>().0.clone()
+    }
+
+    /// Whether completions should only be requested when asked for, given
+    /// `mode` from settings.
+    pub fn manual_only(&self, mode: LowPowerMode) -> bool {
+        match mode {
+            LowPowerMode::Off => false,
+            LowPowerMode::On => true,
+            LowPowerMode::Auto => self.low_power.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Whether suggestions may be requested ahead of time, which they never
+    /// are on battery or in low power mode, whatever `mode` is.
+    pub fn allows_prefetch(&self, mode: LowPowerMode) -> bool {
+        mode != LowPowerMode::On && !self.low_power.load(Ordering::Relaxed)
+    }
+}
+
+/// Whether the machine is unplugged or in low power mode. `None` where this
+/// can't be detected.
+#[cfg(target_os = "macos")]
+async fn is_low_power() -> Option<bool> {
+    let output = smol::process::Command::new("pmset")
+        .arg("-g")
+        .output()
+        .await
+        .ok()?;
+    let settings = String::from_utf8_lossy(&output.stdout);
+    let output = smol::process::Command::new("pmset")
+        .args(["-g", "batt"])
+        .output()
+        .await
+        .ok()?;
+    let battery = String::from_utf8_lossy(&output.stdout);
+    Some(pmset_low_power(&settings, &battery))
+}
+
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+async fn is_low_power() -> Option<bool> {
+    let mut supplies = Vec::new();
+    for entry in std::fs::read_dir("/sys/class/power_supply").ok()?.flatten() {
+        let read = |name: &str| {
+            std::fs::read_to_string(entry.path().join(name))
+                .map(|value| value.trim().to_string())
+                .unwrap_or_default()
+        };
+        supplies.push(PowerSupply {
+            kind: read("type"),
+            online: read("online"),
+            status: read("status"),
+        });
+    }
+    let platform_profile =
+        std::fs::read_to_string("/sys/firmware/acpi/platform_profile").unwrap_or_default();
+    Some(sysfs_low_power(&supplies, platform_profile.trim()))
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "freebsd")))]
+async fn is_low_power() -> Option<bool> {
+    None
+}
+
+/// The output of `pmset -g` and `pmset -g batt` says the Mac is on battery or
+/// in Low Power Mode.
+#[cfg_attr(not(any(target_os = "macos", test)), allow(dead_code))]
+fn pmset_low_power(settings: &str, battery: &str) -> bool {
+    let low_power_mode = settings.lines().any(|line| {
+        let mut words = line.split_whitespace();
+        words.next() == Some("lowpowermode") && words.next() == Some("1")
+    });
+    low_power_mode || battery.contains("'Battery Power'")
+}
+
+/// An entry of `/sys/class/power_supply`.
+#[cfg_attr(
+    not(any(target_os = "linux", target_os = "freebsd", test)),
+    allow(dead_code)
+)]
+struct PowerSupply {
+    kind: String,
+    online: String,
+    status: String,
+}
+
+/// No charger is connected while a battery discharges, or the firmware's
+/// power profile is set to save power.
+#[cfg_attr(
+    not(any(target_os = "linux", target_os = "freebsd", test)),
+    allow(dead_code)
+)]
+fn sysfs_low_power(supplies: &[PowerSupply], platform_profile: &str) -> bool {
+    if platform_profile == "low-power" {
+        return true;
+    }
+    let plugged_in = supplies
+        .iter()
+        .any(|supply| supply.kind != "Battery" && supply.online == "1");
+    let discharging = supplies
+        .iter()
+        .any(|supply| supply.kind == "Battery" && supply.status == "Discharging");
+    discharging && !plugged_in
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_low_power_detection() {
+        let battery = "Now drawing from 'Battery Power'\n -InternalBattery-0 (id=1234)\t80%; discharging; 5:12 remaining present: true\n";
+        let ac = "Now drawing from 'AC Power'\n -InternalBattery-0 (id=1234)\t80%; charging; 0:40 remaining present: true\n";
+        let settings = "System-wide power settings:\nCurrently in use:\n lowpowermode         0\n sleep                1\n";
+        assert!(pmset_low_power(settings, battery));
+        assert!(!pmset_low_power(settings, ac));
+        assert!(pmset_low_power(
+            &settings.replace("lowpowermode         0", "lowpowermode         1"),
+            ac
+        ));
+
+        let supply = |kind: &str, online: &str, status: &str| PowerSupply {
+            kind: kind.into(),
+            online: online.into(),
+            status: status.into(),
+        };
+        let unplugged = [
+            supply("Mains", "0", ""),
+            supply("Battery", "", "Discharging"),
+        ];
+        let plugged_in = [supply("Mains", "1", ""), supply("Battery", "", "Charging")];
+        assert!(sysfs_low_power(&unplugged, "balanced"));
+        assert!(!sysfs_low_power(&plugged_in, "balanced"));
+        assert!(sysfs_low_power(&plugged_in, "low-power"));
+        // Desktops have no battery.
+        assert!(!sysfs_low_power(&[supply("Mains", "1", "")], ""));
+    }
+}
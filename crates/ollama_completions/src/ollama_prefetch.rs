@@ -0,0 +1,157 @@
+use crate::RequestId;
+use gpui::EntityId;
+use language::Point;
+use std::{collections::VecDeque, time::Duration};
+
+/// How long the cursor has to rest before suggestions are prefetched.
+pub const PREFETCH_DELAY: Duration = Duration::from_millis(750);
+/// How many prefetched suggestions are kept. The oldest are dropped first.
+const MAX_PREFETCHED: usize = 4;
+/// How far below the cursor a blank line is looked for.
+const MAX_BLANK_LINE_DISTANCE: u32 = 20;
+
+/// A suggestion requested ahead of time for where the cursor may go next.
+pub(crate) struct PrefetchedSuggestion {
+    pub buffer_id: EntityId,
+    /// The buffer's version when the suggestion was requested. Any edit since
+    /// makes it stale.
+    pub version: clock::Global,
+    pub offset: usize,
+    pub text: String,
+    pub model: String,
+    pub request_id: RequestId,
+}
+
+/// The most recently prefetched suggestions, shown without asking the model
+/// when the cursor lands where one was made.
+#[derive(Default)]
+pub(crate) struct Prefetched {
+    suggestions: VecDeque<PrefetchedSuggestion>,
+}
+
+impl Prefetched {
+    pub fn insert(&mut self, suggestion: PrefetchedSuggestion) {
+        self.suggestions.retain(|existing| {
+            existing.buffer_id != suggestion.buffer_id || existing.offset != suggestion.offset
+        });
+        self.suggestions.push_back(suggestion);
+        while self.suggestions.len() > MAX_PREFETCHED {
+            self.suggestions.pop_front();
+        }
+    }
+
+    /// Whether a suggestion for `offset` was prefetched in `version` of the
+    /// buffer.
+    pub fn contains(&self, buffer_id: EntityId, version: &clock::Global, offset: usize) -> bool {
+        self.suggestions.iter().any(|suggestion| {
+            suggestion.buffer_id == buffer_id
+                && suggestion.offset == offset
+                && !version.changed_since(&suggestion.version)
+        })
+    }
+
+    /// Removes and returns the suggestion prefetched for `offset`, if the
+    /// buffer hasn't been edited since. Stale suggestions for the buffer are
+    /// dropped along the way.
+    pub fn take(
+        &mut self,
+        buffer_id: EntityId,
+        version: &clock::Global,
+        offset: usize,
+    ) -> Option<PrefetchedSuggestion> {
+        self.suggestions.retain(|suggestion| {
+            suggestion.buffer_id != buffer_id || !version.changed_since(&suggestion.version)
+        });
+        let ix = self.suggestions.iter().position(|suggestion| {
+            suggestion.buffer_id == buffer_id && suggestion.offset == offset
+        })?;
+        self.suggestions.remove(ix)
+    }
+
+    pub fn clear(&mut self) {
+        self.suggestions.clear();
+    }
+}
+
+/// Where the cursor is likely to go next from `cursor_offset`: the end of its
+/// line, when there's code after it, and the next blank line below it, after
+/// that line's indentation. Positions are offsets, nearest first.
+pub(crate) fn prefetch_positions(
+    snapshot: &text::BufferSnapshot,
+    cursor_offset: usize,
+) -> Vec<usize> {
+    let cursor = snapshot.offset_to_point(cursor_offset);
+    let mut positions = Vec::new();
+    let line_end = Point::new(cursor.row, snapshot.line_len(cursor.row));
+    if line_end != cursor {
+        positions.push(snapshot.point_to_offset(line_end));
+    }
+    let last_row = snapshot
+        .max_point()
+        .row
+        .min(cursor.row + MAX_BLANK_LINE_DISTANCE);
+    if let Some(row) = (cursor.row + 1..=last_row).find(|row| snapshot.is_line_blank(*row)) {
+        positions.push(snapshot.point_to_offset(Point::new(row, snapshot.line_len(row))));
+    }
+    positions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use text::{Buffer, BufferId};
+
+    fn snapshot(text: &str) -> text::BufferSnapshot {
+        Buffer::new(0, BufferId::new(1).unwrap(), text.into()).snapshot()
+    }
+
+    #[test]
+    fn test_prefetch_positions() {
+        let text = "fn main() {\n    let x = 1;\n    \n}\n";
+        let snapshot = snapshot(text);
+        let in_statement = text.find("1;").unwrap();
+        let blank_line_end = text.find("    \n}").unwrap() + 4;
+        assert_eq!(
+            prefetch_positions(&snapshot, in_statement),
+            [in_statement + 2, blank_line_end]
+        );
+        // At the end of its line, only the blank line is left.
+        assert_eq!(
+            prefetch_positions(&snapshot, in_statement + 2),
+            [blank_line_end]
+        );
+        // On the blank line, only the lines below it count.
+        assert_eq!(prefetch_positions(&snapshot, blank_line_end), [text.len()]);
+        assert!(prefetch_positions(&snapshot, text.len()).is_empty());
+    }
+
+    #[test]
+    fn test_prefetched() {
+        let buffer_id = EntityId::from(1);
+        let mut version = clock::Global::new();
+        let suggestion = |offset, version: &clock::Global| PrefetchedSuggestion {
+            buffer_id,
+            version: version.clone(),
+            offset,
+            text: format!("at {offset}"),
+            model: "qwen2.5-coder:1.5b".into(),
+            request_id: RequestId::next(),
+        };
+
+        let mut prefetched = Prefetched::default();
+        for offset in 0..=MAX_PREFETCHED {
+            prefetched.insert(suggestion(offset, &version));
+        }
+        assert!(!prefetched.contains(buffer_id, &version, 0));
+        assert!(prefetched.contains(buffer_id, &version, 1));
+        let taken = prefetched.take(buffer_id, &version, 1).unwrap();
+        assert_eq!(taken.text, "at 1");
+        assert!(prefetched.take(buffer_id, &version, 1).is_none());
+
+        // An edit makes every suggestion for the buffer stale.
+        version.observe(clock::Lamport::new(0));
+        assert!(!prefetched.contains(buffer_id, &version, 2));
+        assert!(prefetched.take(buffer_id, &version, 2).is_none());
+        assert!(prefetched.suggestions.is_empty());
+    }
+}
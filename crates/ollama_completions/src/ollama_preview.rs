@@ -0,0 +1,59 @@
+use crate::PendingSuggestion;
+use editor::{actions::AcceptInlineCompletion, Editor, EditorMode, ToOffset as _};
+use gpui::{AppContext, ViewContext};
+use zed_actions::ollama::PreviewSuggestion;
+
+pub(crate) fn init(cx: &mut AppContext) {
+    cx.observe_new_views(|editor: &mut Editor, cx| {
+        if editor.mode() != EditorMode::Full {
+            return;
+        }
+        editor
+            .register_action(
+                cx.listener(|editor, _: &PreviewSuggestion, cx| preview_suggestion(editor, cx)),
+            )
+            .detach();
+    })
+    .detach();
+}
+
+/// Inserts the inline completion at the cursor highlighted like an added
+/// hunk, with buttons to keep or discard it, since long suggestions are hard
+/// to read as ghost text.
+fn preview_suggestion(editor: &mut Editor, cx: &mut ViewContext<Editor>) {
+    if !editor.has_active_inline_completion(cx) {
+        cx.propagate();
+        return;
+    }
+    let snapshot = editor.buffer().read(cx).snapshot(cx);
+    let start = editor
+        .selections
+        .newest_anchor()
+        .head()
+        .to_offset(&snapshot);
+    let len = snapshot.len();
+    editor.accept_inline_completion(&AcceptInlineCompletion, cx);
+
+    let snapshot = editor.buffer().read(cx).snapshot(cx);
+    let end = editor
+        .selections
+        .newest_anchor()
+        .head()
+        .to_offset(&snapshot);
+    // Predicted edits replace text elsewhere, so they're left applied as
+    // they are rather than offered for discarding.
+    if end <= start || snapshot.len().checked_sub(len) != Some(end - start) {
+        return;
+    }
+    let highlighted_end = if snapshot.reversed_chars_at(end).next() == Some('\n') {
+        end - 1
+    } else {
+        end
+    };
+    let suggestion = PendingSuggestion {
+        inserted: snapshot.anchor_after(start)..snapshot.anchor_before(end),
+        highlighted: snapshot.anchor_after(start)..snapshot.anchor_before(highlighted_end),
+    };
+    suggestion.highlight(editor, cx);
+    suggestion.offer("Suggested completion", editor, cx);
+}
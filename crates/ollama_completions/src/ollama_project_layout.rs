@@ -0,0 +1,125 @@
+use crate::{count_tokens, OllamaSettings};
+use gpui::AppContext;
+use project::Project;
+use std::path::{Path, PathBuf};
+
+/// The most entries looked at in each worktree, so large projects don't
+/// hold up opening a chat.
+const MAX_ENTRIES: usize = 10_000;
+
+/// Describes where `path` sits in the project, with an outline of the
+/// project's files in at most `budget` tokens, for a chat's system message.
+/// Files matching `disabled_globs` and ignored files are left out. Empty when
+/// `budget` is 0.
+pub(crate) fn project_layout_context(
+    project: &Project,
+    path: Option<&Path>,
+    settings: &OllamaSettings,
+    budget: usize,
+    cx: &AppContext,
+) -> String {
+    if budget == 0 {
+        return String::new();
+    }
+    let disabled_paths = settings.disabled_paths();
+    let mut entries = Vec::new();
+    for worktree in project.visible_worktrees(cx) {
+        let worktree = worktree.read(cx);
+        let root = Path::new(worktree.root_name());
+        let root_is_dir = worktree.root_entry().map_or(true, |entry| entry.is_dir());
+        entries.push((root.to_path_buf(), root_is_dir));
+        entries.extend(
+            worktree
+                .entries(false, 0)
+                .filter(|entry| !entry.path.as_os_str().is_empty())
+                .filter(|entry| !disabled_paths.is_match(&entry.path))
+                .take(MAX_ENTRIES)
+                .map(|entry| (root.join(&entry.path), entry.is_dir())),
+        );
+    }
+
+    let mut context = String::new();
+    if let Some(path) = path {
+        context.push_str(&format!("The user is working on {}.\n", path.display()));
+    }
+    let budget = budget.saturating_sub(count_tokens(&context));
+    let layout = layout_outline(&entries, budget);
+    if !layout.is_empty() {
+        context.push_str(&format!("The project's files:\n```\n{layout}```\n"));
+    }
+    context
+}
+
+/// An indented outline of `entries`, given in depth-first order, with a
+/// trailing `/` on directories. When they don't all fit in `budget` tokens,
+/// the ones nearest the top of the tree are kept.
+fn layout_outline(entries: &[(PathBuf, bool)], budget: usize) -> String {
+    let line = |(path, is_dir): &(PathBuf, bool)| {
+        let depth = path.components().count();
+        let name = path
+            .file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy();
+        let slash = if *is_dir { "/" } else { "" };
+        format!("{}{name}{slash}\n", "  ".repeat(depth.saturating_sub(1)))
+    };
+
+    let mut by_depth = (0..entries.len()).collect::<Vec<_>>();
+    by_depth.sort_by_key(|&ix| entries[ix].0.components().count());
+    let mut included = vec![false; entries.len()];
+    let mut tokens = 0;
+    for ix in by_depth {
+        let line_tokens = count_tokens(&line(&entries[ix]));
+        if tokens + line_tokens > budget {
+            break;
+        }
+        tokens += line_tokens;
+        included[ix] = true;
+    }
+
+    entries
+        .iter()
+        .zip(included)
+        .filter(|(_, included)| *included)
+        .map(|(entry, _)| line(entry))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_outline() {
+        let entries = [
+            ("zed", true),
+            ("zed/Cargo.toml", false),
+            ("zed/crates", true),
+            ("zed/crates/ollama", true),
+            ("zed/crates/ollama/src", true),
+            ("zed/crates/ollama/src/ollama.rs", false),
+            ("zed/docs", true),
+        ]
+        .map(|(path, is_dir)| (PathBuf::from(path), is_dir));
+        assert_eq!(
+            layout_outline(&entries, 1000),
+            "zed/\n  Cargo.toml\n  crates/\n    ollama/\n      src/\n        ollama.rs\n  docs/\n"
+        );
+
+        // Deeper entries are the first to go.
+        let budget = entries[..3]
+            .iter()
+            .chain(&entries[6..])
+            .map(|(path, is_dir)| {
+                let depth = path.components().count() - 1;
+                let name = path.file_name().unwrap().to_string_lossy();
+                let slash = if *is_dir { "/" } else { "" };
+                count_tokens(&format!("{}{name}{slash}\n", "  ".repeat(depth)))
+            })
+            .sum();
+        assert_eq!(
+            layout_outline(&entries, budget),
+            "zed/\n  Cargo.toml\n  crates/\n  docs/\n"
+        );
+    }
+}
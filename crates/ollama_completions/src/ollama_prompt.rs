@@ -0,0 +1,520 @@
+use crate::{
+    apply_prompt_template, count_tokens, prompt_window, split_prompt_window, PromptBudget,
+    COMPLETION_TEMPERATURE,
+};
+use ollama::{ChatOptions, GenerateRequest, KeepAlive, RequestPriority};
+use text::{Bias, BufferSnapshot, LineEnding};
+
+/// How much text before the cursor is sent to the model, unless settings
+/// say otherwise.
+const MAX_PREFIX_CHARS: usize = 4096;
+/// How much text after the cursor is sent to the model, unless settings say
+/// otherwise.
+const MAX_SUFFIX_CHARS: usize = 1024;
+/// The maximum number of tokens generated for a single suggestion.
+pub(crate) const MAX_COMPLETION_TOKENS: isize = 128;
+
+/// Everything a completion prompt is built from, taken from the buffer ahead
+/// of time so building the prompt doesn't need it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct PromptContext {
+    /// The text before the cursor.
+    pub prefix: String,
+    /// The text after the cursor.
+    pub suffix: String,
+    pub language: String,
+    /// The file's path in its worktree, or "untitled".
+    pub path: String,
+    /// The file's imports and top-level declarations from before the prefix,
+    /// placed ahead of it.
+    pub header: String,
+    /// Recent edits, Git changes and diagnostics, as comments in the file's
+    /// language.
+    pub extra_context: String,
+}
+
+/// Builds fill-in-the-middle requests from a [`PromptContext`], fitting the
+/// text around the cursor into the model's context window alongside the
+/// system prompt, template and extra context. It owns what it's built from,
+/// so it can be sent to a background thread.
+#[derive(Clone, Debug)]
+pub(crate) struct PromptBuilder {
+    model: String,
+    options: ChatOptions,
+    temperature: f32,
+    single_line: bool,
+    stop: Vec<String>,
+    context_tokens: usize,
+    system_prompt: Option<String>,
+    template: Option<String>,
+    raw: bool,
+    keep_alive: KeepAlive,
+    budget: Option<PromptBudget>,
+    max_prefix_chars: usize,
+    max_suffix_chars: usize,
+}
+
+impl PromptBuilder {
+    /// `options` carries the options from settings, and `context_tokens` is
+    /// the size of the model's context window. A temperature and token limit
+    /// in `options` replace the defaults for completions.
+    pub fn new(model: &str, options: ChatOptions, context_tokens: usize) -> Self {
+        Self {
+            model: model.to_string(),
+            temperature: options.temperature.unwrap_or(COMPLETION_TEMPERATURE),
+            options,
+            single_line: false,
+            stop: Vec::new(),
+            context_tokens,
+            system_prompt: None,
+            template: None,
+            raw: false,
+            keep_alive: KeepAlive::default(),
+            budget: None,
+            max_prefix_chars: MAX_PREFIX_CHARS,
+            max_suffix_chars: MAX_SUFFIX_CHARS,
+        }
+    }
+
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = temperature;
+        self
+    }
+
+    /// Single-line requests stop generating at the first line break.
+    pub fn with_single_line(mut self, single_line: bool) -> Self {
+        self.single_line = single_line;
+        self
+    }
+
+    /// Sequences that stop generation, on top of the line break for
+    /// single-line requests.
+    pub fn with_stop(mut self, stop: Vec<String>) -> Self {
+        self.stop = stop;
+        self
+    }
+
+    pub fn with_system_prompt(mut self, system_prompt: Option<&str>) -> Self {
+        self.system_prompt = system_prompt.map(ToString::to_string);
+        self
+    }
+
+    /// A prompt template replacing the model's fill-in-the-middle template.
+    pub fn with_template(mut self, template: Option<&str>) -> Self {
+        self.template = template.map(ToString::to_string);
+        self
+    }
+
+    /// Sends the prompt without the model's template, so only the text
+    /// before the cursor is sent for the model to continue. Requests with a
+    /// template are always sent raw.
+    pub fn with_raw(mut self, raw: bool) -> Self {
+        self.raw = raw;
+        self
+    }
+
+    pub fn with_keep_alive(mut self, keep_alive: KeepAlive) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// Shares the context window between the prefix, suffix and extra
+    /// context in these proportions, instead of including the extra context
+    /// whole and splitting the rest by length.
+    pub fn with_budget(mut self, budget: Option<PromptBudget>) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Limits how many bytes of text before and after the cursor are taken
+    /// from the buffer. Less may be sent when it doesn't fit the context
+    /// window.
+    pub fn with_max_chars(mut self, max_prefix_chars: usize, max_suffix_chars: usize) -> Self {
+        self.max_prefix_chars = max_prefix_chars;
+        self.max_suffix_chars = max_suffix_chars;
+        self
+    }
+
+    /// Takes the text around `cursor_offset`, up to the most that's sent.
+    /// Every range is clipped to whole characters, so an offset inside a
+    /// multi-byte character moves to its start instead of splitting it.
+    /// Extra context read from files on disk may have Windows line endings,
+    /// which are converted to the buffer's `\n`.
+    pub fn prompt_context(
+        &self,
+        snapshot: &BufferSnapshot,
+        cursor_offset: usize,
+        language: &str,
+        path: &str,
+        mut extra_context: String,
+    ) -> PromptContext {
+        let cursor_offset = snapshot.clip_offset(cursor_offset, Bias::Left);
+        LineEnding::normalize(&mut extra_context);
+        let prefix_start = snapshot.clip_offset(
+            cursor_offset.saturating_sub(self.max_prefix_chars),
+            Bias::Left,
+        );
+        let suffix_end = snapshot.clip_offset(
+            (cursor_offset + self.max_suffix_chars).min(snapshot.len()),
+            Bias::Right,
+        );
+        PromptContext {
+            prefix: snapshot
+                .text_for_range(prefix_start..cursor_offset)
+                .collect(),
+            suffix: snapshot.text_for_range(cursor_offset..suffix_end).collect(),
+            language: language.to_string(),
+            path: path.to_string(),
+            header: String::new(),
+            extra_context,
+        }
+    }
+
+    /// The finished request, with the template applied if there is one.
+    pub fn build(&self, context: &PromptContext) -> GenerateRequest {
+        let mut request = self.fim_request(context);
+        self.apply_template(&mut request, context);
+        request
+    }
+
+    /// The request before the template is applied, with the prefix and
+    /// suffix in their own fields. Without a template, the extra context goes
+    /// at the start of the prefix.
+    pub fn fim_request(&self, context: &PromptContext) -> GenerateRequest {
+        let (extra_context_len, prefix_len, suffix_len) = self.window(context);
+        let extra_context = &context.extra_context[..extra_context_len];
+        let prefix = &context.prefix[context.prefix.len() - prefix_len..];
+        let suffix = &context.suffix[..suffix_len];
+        let mut stop = self.stop.clone();
+        if self.single_line {
+            stop.insert(0, "\n".to_string());
+        }
+        let prompt = if self.template.is_none() {
+            format!("{extra_context}{}{prefix}", context.header)
+        } else {
+            format!("{}{prefix}", context.header)
+        };
+
+        GenerateRequest {
+            model: self.model.clone(),
+            prompt,
+            suffix: Some(suffix.to_string()),
+            stream: false,
+            keep_alive: self.keep_alive.clone(),
+            options: Some(ChatOptions {
+                num_predict: self.options.num_predict.or(Some(MAX_COMPLETION_TOKENS)),
+                stop: (!stop.is_empty()).then_some(stop),
+                temperature: Some(self.temperature),
+                ..self.options.clone()
+            }),
+            system: self.system_prompt.clone(),
+            raw: None,
+            format: None,
+            context: None,
+            priority: RequestPriority::Automatic,
+        }
+    }
+
+    /// Renders the template, if there is one, into a request from
+    /// [`Self::fim_request`].
+    pub fn apply_template(&self, request: &mut GenerateRequest, context: &PromptContext) {
+        if let Some(template) = &self.template {
+            let (extra_context_len, _, _) = self.window(context);
+            apply_prompt_template(
+                request,
+                template,
+                &context.extra_context[..extra_context_len],
+                &context.language,
+                &context.path,
+            );
+        } else if self.raw {
+            // Ollama only passes the suffix and system prompt to the model
+            // through its template.
+            request.suffix = None;
+            request.system = None;
+            request.raw = Some(true);
+        }
+    }
+
+    /// How many bytes at the start of the extra context, the end of the
+    /// prefix and the start of the suffix fit in the context window.
+    fn window(&self, context: &PromptContext) -> (usize, usize, usize) {
+        let reserved_tokens = count_tokens(&context.header)
+            + self.system_prompt.as_deref().map_or(0, count_tokens)
+            + self.template.as_deref().map_or(0, count_tokens);
+        let context_tokens = self.context_tokens.saturating_sub(reserved_tokens);
+        let split = self.budget.and_then(|budget| {
+            split_prompt_window(
+                &context.prefix,
+                &context.suffix,
+                &context.extra_context,
+                context_tokens,
+                self.completion_tokens(),
+                budget,
+            )
+        });
+        split.unwrap_or_else(|| {
+            let (prefix_len, suffix_len) = prompt_window(
+                &context.prefix,
+                &context.suffix,
+                context_tokens.saturating_sub(count_tokens(&context.extra_context)),
+                self.completion_tokens(),
+            );
+            (context.extra_context.len(), prefix_len, suffix_len)
+        })
+    }
+
+    /// How many tokens of the context window are left for the suggestion.
+    fn completion_tokens(&self) -> usize {
+        self.options
+            .num_predict
+            .and_then(|num_predict| usize::try_from(num_predict).ok())
+            .unwrap_or(MAX_COMPLETION_TOKENS as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TEMPLATE_TOKENS;
+    use text::{Buffer, BufferId};
+
+    fn context() -> PromptContext {
+        PromptContext {
+            prefix: "fn main() {\n    let x = 1;\n    ".into(),
+            suffix: "\n}\n".into(),
+            language: "Rust".into(),
+            path: "src/main.rs".into(),
+            header: String::new(),
+            extra_context: "// Recently edited: src/lib.rs\n".into(),
+        }
+    }
+
+    #[test]
+    fn test_model_template() {
+        let context = context();
+        let request = PromptBuilder::new("qwen2.5-coder:1.5b", ChatOptions::default(), 8192)
+            .with_system_prompt(Some("Output only code."))
+            .build(&context);
+        assert_eq!(request.model, "qwen2.5-coder:1.5b");
+        assert_eq!(
+            request.prompt,
+            "// Recently edited: src/lib.rs\nfn main() {\n    let x = 1;\n    "
+        );
+        assert_eq!(request.suffix.as_deref(), Some("\n}\n"));
+        assert_eq!(request.system.as_deref(), Some("Output only code."));
+        assert_eq!(request.raw, None);
+        let options = request.options.unwrap();
+        assert_eq!(options.stop, None);
+        assert_eq!(options.num_predict, Some(MAX_COMPLETION_TOKENS));
+    }
+
+    #[test]
+    fn test_custom_template() {
+        let context = context();
+        let builder = PromptBuilder::new("qwen2.5-coder:1.5b", ChatOptions::default(), 8192)
+            .with_template(Some(
+                "<|fim_prefix|>// {path}\n{context}{prefix}<|fim_suffix|>{suffix}<|fim_middle|>",
+            ));
+        let request = builder.build(&context);
+        assert_eq!(
+            request.prompt,
+            "<|fim_prefix|>// src/main.rs\n// Recently edited: src/lib.rs\n\
+            fn main() {\n    let x = 1;\n    <|fim_suffix|>\n}\n<|fim_middle|>"
+        );
+        assert_eq!(request.suffix, None);
+        assert_eq!(request.raw, Some(true));
+
+        // The extra context goes where the template puts it, not before the
+        // prefix.
+        let request = builder.fim_request(&context);
+        assert_eq!(request.prompt, context.prefix);
+        assert_eq!(request.suffix.as_deref(), Some("\n}\n"));
+    }
+
+    #[test]
+    fn test_raw() {
+        let context = context();
+        let builder = PromptBuilder::new("starcoder2:3b", ChatOptions::default(), 8192)
+            .with_system_prompt(Some("Output only code."))
+            .with_raw(true);
+        let request = builder.build(&context);
+        assert_eq!(
+            request.prompt,
+            "// Recently edited: src/lib.rs\nfn main() {\n    let x = 1;\n    "
+        );
+        assert_eq!(request.suffix, None);
+        assert_eq!(request.system, None);
+        assert_eq!(request.raw, Some(true));
+
+        // Post-processing still sees the suffix.
+        let request = builder.fim_request(&context);
+        assert_eq!(request.suffix.as_deref(), Some("\n}\n"));
+    }
+
+    #[test]
+    fn test_header() {
+        let context = PromptContext {
+            header: "use std::sync::Arc;\nstruct Config {\n".into(),
+            ..context()
+        };
+        let request =
+            PromptBuilder::new("qwen2.5-coder:1.5b", ChatOptions::default(), 8192).build(&context);
+        assert_eq!(
+            request.prompt,
+            "// Recently edited: src/lib.rs\nuse std::sync::Arc;\nstruct Config {\n\
+            fn main() {\n    let x = 1;\n    "
+        );
+
+        // With a template, the header still leads the prefix.
+        let request = PromptBuilder::new("qwen2.5-coder:1.5b", ChatOptions::default(), 8192)
+            .with_template(Some("{prefix}<FILL>{suffix}"))
+            .fim_request(&context);
+        assert!(request.prompt.starts_with("use std::sync::Arc;\n"));
+    }
+
+    #[test]
+    fn test_single_line() {
+        let request = PromptBuilder::new("codellama:7b", ChatOptions::default(), 8192)
+            .with_single_line(true)
+            .with_temperature(0.8)
+            .build(&context());
+        let options = request.options.unwrap();
+        assert_eq!(options.stop, Some(vec!["\n".to_string()]));
+        assert_eq!(options.temperature, Some(0.8));
+
+        // Options from `completion_options` replace the defaults.
+        let options = ChatOptions {
+            temperature: Some(0.1),
+            top_p: Some(0.9),
+            num_predict: Some(32),
+            ..Default::default()
+        };
+        let request = PromptBuilder::new("codellama:7b", options, 8192).build(&context());
+        let options = request.options.unwrap();
+        assert_eq!(options.temperature, Some(0.1));
+        assert_eq!(options.top_p, Some(0.9));
+        assert_eq!(options.num_predict, Some(32));
+
+        let request = PromptBuilder::new("codellama:7b", ChatOptions::default(), 8192)
+            .with_single_line(true)
+            .with_stop(vec!["\n\n".to_string()])
+            .build(&context());
+        assert_eq!(
+            request.options.unwrap().stop,
+            Some(vec!["\n".to_string(), "\n\n".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_budget() {
+        let line = "let x = 1;\n";
+        let context = PromptContext {
+            prefix: line.repeat(40),
+            suffix: line.repeat(10),
+            ..Default::default()
+        };
+        let context_tokens =
+            10 * count_tokens(line) + TEMPLATE_TOKENS + MAX_COMPLETION_TOKENS as usize;
+        let request = PromptBuilder::new("codellama:7b", ChatOptions::default(), context_tokens)
+            .build(&context);
+        assert_eq!(request.prompt, line.repeat(8));
+        assert_eq!(request.suffix, Some(line.repeat(2)));
+
+        // The system prompt's tokens come out of the same budget.
+        let system_prompt = "Output only code.";
+        let request = PromptBuilder::new(
+            "codellama:7b",
+            ChatOptions::default(),
+            context_tokens + count_tokens(system_prompt),
+        )
+        .with_system_prompt(Some(system_prompt))
+        .build(&context);
+        assert_eq!(request.prompt, line.repeat(8));
+        assert_eq!(request.suffix, Some(line.repeat(2)));
+
+        // A budget shares the window in its proportions instead, and cuts
+        // the extra context down to its share.
+        let context = PromptContext {
+            extra_context: "// Recently edited: src/lib.rs\n".repeat(10),
+            ..context
+        };
+        let request = PromptBuilder::new("codellama:7b", ChatOptions::default(), context_tokens)
+            .with_budget(Some(PromptBudget {
+                prefix: 1.,
+                suffix: 1.,
+                context: 0.,
+            }))
+            .build(&context);
+        assert_eq!(request.prompt, line.repeat(5));
+        assert_eq!(request.suffix, Some(line.repeat(5)));
+    }
+
+    #[test]
+    fn test_max_chars() {
+        let text = "fn main() {\n    let x = 1;\n    let y = 2;\n}\n";
+        let snapshot = Buffer::new(0, BufferId::new(1).unwrap(), text.to_string()).snapshot();
+        let cursor_offset = text.find("let y").unwrap();
+        let builder = PromptBuilder::new("codellama:7b", ChatOptions::default(), 8192);
+        let context =
+            builder.prompt_context(&snapshot, cursor_offset, "Rust", "main.rs", "".into());
+        assert_eq!(context.prefix, &text[..cursor_offset]);
+        assert_eq!(context.suffix, &text[cursor_offset..]);
+
+        let context = builder.with_max_chars(15, 5).prompt_context(
+            &snapshot,
+            cursor_offset,
+            "Rust",
+            "main.rs",
+            "".into(),
+        );
+        assert_eq!(context.prefix, "let x = 1;\n    ");
+        assert_eq!(context.suffix, "let y");
+    }
+
+    #[test]
+    fn test_multi_byte() {
+        let text = "// 你好，世界\nlet crab = \"🦀\";\nlet s = \"日本語\";\n";
+        let snapshot = Buffer::new(0, BufferId::new(1).unwrap(), text.to_string()).snapshot();
+        let builder = PromptBuilder::new("codellama:7b", ChatOptions::default(), 8192);
+
+        // An offset inside a character is moved to its start.
+        let crab = text.find('🦀').unwrap();
+        let context = builder.prompt_context(
+            &snapshot,
+            crab + 2,
+            "Rust",
+            "main.rs",
+            "// a.rs\r\nfn a() {}\r\n".into(),
+        );
+        assert_eq!(context.prefix, &text[..crab]);
+        assert_eq!(context.suffix, &text[crab..]);
+        assert_eq!(context.extra_context, "// a.rs\nfn a() {}\n");
+
+        // Character limits that end inside a character keep it whole.
+        let cursor_offset = text.find("let s").unwrap();
+        let context = builder.with_max_chars(4, 11).prompt_context(
+            &snapshot,
+            cursor_offset,
+            "Rust",
+            "main.rs",
+            "".into(),
+        );
+        assert_eq!(context.prefix, "🦀\";\n");
+        assert_eq!(context.suffix, "let s = \"日");
+
+        // The prompt is cut at whole lines when it doesn't fit.
+        let line = "let s = \"日本語 🦀\";\n";
+        let context = PromptContext {
+            prefix: line.repeat(40),
+            suffix: line.repeat(10),
+            ..Default::default()
+        };
+        let context_tokens =
+            10 * count_tokens(line) + TEMPLATE_TOKENS + MAX_COMPLETION_TOKENS as usize;
+        let request = PromptBuilder::new("codellama:7b", ChatOptions::default(), context_tokens)
+            .build(&context);
+        assert_eq!(request.prompt, line.repeat(8));
+        assert_eq!(request.suffix, Some(line.repeat(2)));
+    }
+}
@@ -0,0 +1,105 @@
+use ollama::GenerateRequest;
+
+/// The values substituted for the placeholders of a prompt template.
+pub(crate) struct PromptValues<'a> {
+    pub prefix: &'a str,
+    pub suffix: &'a str,
+    /// Recent edits and other context, as comments in the file's language.
+    pub context: &'a str,
+    pub language: &'a str,
+    pub path: &'a str,
+}
+
+/// Replaces `{prefix}`, `{suffix}`, `{context}`, `{language}` and `{path}` in
+/// `template`. Other text in braces is left alone, and placeholders appearing
+/// in the substituted values aren't expanded again.
+pub(crate) fn render_prompt_template(template: &str, values: &PromptValues) -> String {
+    render_placeholders(
+        template,
+        &[
+            ("{prefix}", values.prefix),
+            ("{suffix}", values.suffix),
+            ("{context}", values.context),
+            ("{language}", values.language),
+            ("{path}", values.path),
+        ],
+    )
+}
+
+/// Replaces each of `placeholders` in `template` with its value, in one pass
+/// so placeholders appearing in the values aren't expanded again. Other text
+/// in braces is left alone.
+pub(crate) fn render_placeholders(template: &str, placeholders: &[(&str, &str)]) -> String {
+    let mut prompt = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(ix) = rest.find('{') {
+        prompt.push_str(&rest[..ix]);
+        rest = &rest[ix..];
+        match placeholders
+            .iter()
+            .find(|(placeholder, _)| rest.starts_with(placeholder))
+        {
+            Some((placeholder, value)) => {
+                prompt.push_str(value);
+                rest = &rest[placeholder.len()..];
+            }
+            None => {
+                prompt.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    prompt.push_str(rest);
+    prompt
+}
+
+/// Replaces a fill-in-the-middle request's prompt with `template`, rendered
+/// from the request's prefix and suffix. The result is sent verbatim, so the
+/// template must include the model's own special tokens.
+pub(crate) fn apply_prompt_template(
+    request: &mut GenerateRequest,
+    template: &str,
+    context: &str,
+    language: &str,
+    path: &str,
+) {
+    let suffix = request.suffix.take().unwrap_or_default();
+    request.prompt = render_prompt_template(
+        template,
+        &PromptValues {
+            prefix: &request.prompt,
+            suffix: &suffix,
+            context,
+            language,
+            path,
+        },
+    );
+    request.raw = Some(true);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_prompt_template() {
+        let values = PromptValues {
+            prefix: "fn main() {\n    ",
+            suffix: "\n}\n",
+            context: "",
+            language: "Rust",
+            path: "src/{suffix}.rs",
+        };
+        assert_eq!(
+            render_prompt_template(
+                "<|fim_prefix|>// {path} ({language})\n{context}{prefix}<|fim_suffix|>{suffix}<|fim_middle|>",
+                &values
+            ),
+            "<|fim_prefix|>// src/{suffix}.rs (Rust)\nfn main() {\n    <|fim_suffix|>\n}\n<|fim_middle|>"
+        );
+        assert_eq!(
+            render_prompt_template("{ {unknown} {prefix", &values),
+            "{ {unknown} {prefix"
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use crate::{Connectivity, OllamaCompletionProvider};
+use editor::{
+    AcceptedCompletionFormat, CompletionProposal, Direction, InlineCompletionProvider,
+    InlineCompletionProviderHandle, PartialAccept,
+};
+use gpui::{AppContext, Model, ModelContext, Subscription};
+use language::{Anchor, Buffer};
+use std::ops::Range;
+
+/// Serves inline completions from Ollama, and asks other providers instead
+/// when it comes back without a suggestion or its server is unreachable.
+pub struct ChainedCompletionProvider {
+    ollama: Model<OllamaCompletionProvider>,
+    /// The providers to ask next, in order. The first one that is enabled at
+    /// the cursor is used.
+    fallbacks: Vec<Box<dyn InlineCompletionProviderHandle>>,
+    /// The fallback the current suggestion comes from, if not Ollama.
+    source: Option<usize>,
+    /// Where Ollama was last asked for a suggestion, until it answers.
+    pending: Option<(Model<Buffer>, Anchor)>,
+    _subscriptions: Vec<Subscription>,
+}
+
+impl ChainedCompletionProvider {
+    pub fn new(ollama: Model<OllamaCompletionProvider>, cx: &mut ModelContext<Self>) -> Self {
+        let subscription = cx.observe(&ollama, |this, _, cx| {
+            this.check_ollama(cx);
+            cx.notify();
+        });
+        Self {
+            ollama,
+            fallbacks: Vec::new(),
+            source: None,
+            pending: None,
+            _subscriptions: vec![subscription],
+        }
+    }
+
+    pub fn add_fallback<T: InlineCompletionProvider>(
+        &mut self,
+        provider: Model<T>,
+        cx: &mut ModelContext<Self>,
+    ) {
+        self._subscriptions
+            .push(cx.observe(&provider, |_, _, cx| cx.notify()));
+        self.fallbacks.push(Box::new(provider));
+    }
+
+    fn source(&self) -> &dyn InlineCompletionProviderHandle {
+        match self.source {
+            Some(ix) => self.fallbacks[ix].as_ref(),
+            None => &self.ollama,
+        }
+    }
+
+    /// Moves on to the fallbacks once Ollama has finished the pending request
+    /// without a suggestion.
+    fn check_ollama(&mut self, cx: &mut ModelContext<Self>) {
+        let Some((buffer, cursor)) = self.pending.clone() else {
+            return;
+        };
+        if self
+            .ollama
+            .active_completion_text(&buffer, cursor, cx)
+            .is_some()
+        {
+            self.pending = None;
+        } else if !self.ollama.read(cx).is_refreshing() {
+            self.pending = None;
+            self.refresh_fallback(buffer, cursor, cx);
+        }
+    }
+
+    fn refresh_fallback(
+        &mut self,
+        buffer: Model<Buffer>,
+        cursor_position: Anchor,
+        cx: &mut ModelContext<Self>,
+    ) {
+        self.source = self
+            .fallbacks
+            .iter()
+            .position(|fallback| fallback.is_enabled(&buffer, cursor_position, cx));
+        if let Some(ix) = self.source {
+            // Ollama has already waited out the typing pause.
+            self.fallbacks[ix].refresh(buffer, cursor_position, false, cx);
+        }
+    }
+}
+
+impl InlineCompletionProvider for ChainedCompletionProvider {
+    fn name() -> &'static str {
+        OllamaCompletionProvider::name()
+    }
+
+    fn is_enabled(&self, buffer: &Model<Buffer>, cursor_position: Anchor, cx: &AppContext) -> bool {
+        self.ollama.is_enabled(buffer, cursor_position, cx)
+            || self
+                .fallbacks
+                .iter()
+                .any(|fallback| fallback.is_enabled(buffer, cursor_position, cx))
+    }
+
+    fn refresh(
+        &mut self,
+        buffer: Model<Buffer>,
+        cursor_position: Anchor,
+        debounce: bool,
+        cx: &mut ModelContext<Self>,
+    ) {
+        if let Some(ix) = self.source.take() {
+            self.fallbacks[ix].discard(false, cx);
+        }
+
+        // Explicit requests always go to Ollama, so they can bring it back.
+        let offline = debounce
+            && Connectivity::global(cx).map_or(false, |connectivity| {
+                let connectivity = connectivity.read(cx);
+                connectivity.is_offline() || connectivity.is_throttled()
+            });
+        let ask_ollama =
+            !offline && (!debounce || self.ollama.is_enabled(&buffer, cursor_position, cx));
+        if ask_ollama {
+            self.pending = Some((buffer.clone(), cursor_position));
+            self.ollama.refresh(buffer, cursor_position, debounce, cx);
+            self.check_ollama(cx);
+        } else {
+            self.pending = None;
+            self.ollama.discard(false, cx);
+            self.refresh_fallback(buffer, cursor_position, cx);
+        }
+    }
+
+    fn cycle(
+        &mut self,
+        buffer: Model<Buffer>,
+        cursor_position: Anchor,
+        direction: Direction,
+        cx: &mut ModelContext<Self>,
+    ) {
+        self.source().cycle(buffer, cursor_position, direction, cx);
+    }
+
+    fn accept(&mut self, cx: &mut ModelContext<Self>) {
+        self.source().accept(cx);
+    }
+
+    fn start_rename(
+        &mut self,
+        rename_buffer: &Model<Buffer>,
+        buffer: &Model<Buffer>,
+        range: Range<Anchor>,
+        cx: &mut ModelContext<Self>,
+    ) -> bool {
+        self.ollama.update(cx, |ollama, cx| {
+            ollama.start_rename(rename_buffer, buffer, range, cx)
+        })
+    }
+
+    fn accepted_format(&self, buffer: &Model<Buffer>, cx: &AppContext) -> AcceptedCompletionFormat {
+        self.source().accepted_format(buffer, cx)
+    }
+
+    fn accept_partial(
+        &mut self,
+        accepted_text: &str,
+        kind: PartialAccept,
+        buffer: &Model<Buffer>,
+        cursor_position: Anchor,
+        cx: &mut ModelContext<Self>,
+    ) -> bool {
+        self.source()
+            .accept_partial(accepted_text, kind, buffer, cursor_position, cx)
+    }
+
+    fn discard(
+        &mut self,
+        should_report_inline_completion_event: bool,
+        cx: &mut ModelContext<Self>,
+    ) {
+        self.pending = None;
+        self.ollama.discard(
+            should_report_inline_completion_event && self.source.is_none(),
+            cx,
+        );
+        if let Some(ix) = self.source {
+            self.fallbacks[ix].discard(should_report_inline_completion_event, cx);
+        }
+    }
+
+    fn discard_pending(&mut self, cx: &mut ModelContext<Self>) {
+        // Ollama finishing without a suggestion mustn't start the fallbacks.
+        self.pending = None;
+        self.ollama.discard_pending(cx);
+        if let Some(ix) = self.source.take() {
+            self.fallbacks[ix].discard_pending(cx);
+        }
+    }
+
+    fn active_completion_text<'a>(
+        &'a self,
+        buffer: &Model<Buffer>,
+        cursor_position: Anchor,
+        cx: &'a AppContext,
+    ) -> Option<CompletionProposal> {
+        self.source()
+            .active_completion_text(buffer, cursor_position, cx)
+    }
+}
@@ -0,0 +1,450 @@
+use crate::{http_client_for, EndpointPool, Generations, OllamaSettings, COMPLETION_TEMPERATURE};
+use anyhow::{anyhow, Result};
+use editor::{
+    actions::{Rename, ShowCompletions},
+    CompletionProvider, Editor, EditorMode,
+};
+use gpui::{AppContext, Model, Task, ViewContext};
+use language::{Buffer, BufferSnapshot, CharKind, CodeLabel, LanguageServerId, Point};
+use ollama::{ChatOptions, GenerateRequest, KeepAlive, RequestPriority, RetryPolicy};
+use parking_lot::RwLock;
+use project::Completion;
+use serde::Deserialize;
+use std::{ops::Range, sync::Arc, time::Duration};
+use text::{ToOffset as _, ToPoint as _};
+use zed_actions::ollama::SuggestNames;
+
+/// How many lines using the symbol are shown to the model.
+const MAX_USAGES: usize = 8;
+/// How many lines of the symbol's definition are shown to the model.
+const MAX_DEFINITION_LINES: u32 = 12;
+/// The most tokens generated for a name.
+const MAX_NAME_TOKENS: isize = 16;
+/// The most tokens generated for a list of names.
+const MAX_NAMES_TOKENS: isize = 128;
+/// How many suggested names are offered.
+const MAX_NAMES: usize = 5;
+
+const RENAME_SYSTEM_PROMPT: &str = "You name symbols in code. Given how a symbol is used, \
+    reply with only a clearer name for it that follows the code's naming conventions.";
+
+const SUGGEST_NAMES_SYSTEM_PROMPT: &str = "You name symbols in code. Given a symbol's \
+    definition and how it's used, suggest clearer names for it that follow the code's naming \
+    conventions, best first.";
+
+pub(crate) fn init(cx: &mut AppContext) {
+    cx.observe_new_views(|editor: &mut Editor, cx: &mut ViewContext<Editor>| {
+        if editor.mode() != EditorMode::Full {
+            return;
+        }
+
+        editor
+            .register_action(cx.listener(|editor, _: &SuggestNames, cx| {
+                let task = suggest_names(editor, cx);
+                Generations::track(Some(cx.view().entity_id()), task, cx);
+            }))
+            .detach();
+    })
+    .detach();
+}
+
+/// A symbol to be renamed, taken from its buffer when the rename started.
+#[derive(Clone, Debug)]
+pub(crate) struct RenameTarget {
+    pub old_name: String,
+    pub language: String,
+    /// The lines of the symbol's buffer that use it, nearest the renamed
+    /// occurrence first.
+    pub usages: Vec<String>,
+}
+
+impl RenameTarget {
+    pub fn new(snapshot: &BufferSnapshot, range: Range<usize>) -> Self {
+        let old_name = snapshot.text_for_range(range.clone()).collect::<String>();
+        let row = range.start.to_point(snapshot).row as usize;
+        let usages = usage_lines(&snapshot.text(), &old_name, row, MAX_USAGES);
+        let language = snapshot
+            .language_at(range.start)
+            .map(|language| language.name().0.to_string())
+            .unwrap_or_default();
+        Self {
+            old_name,
+            language,
+            usages,
+        }
+    }
+
+    /// Asks for a new name for the symbol that starts with `typed`.
+    pub fn request(
+        &self,
+        typed: &str,
+        model: &str,
+        options: ChatOptions,
+        keep_alive: KeepAlive,
+    ) -> GenerateRequest {
+        let mut prompt = self.describe(None);
+        prompt.push_str(&format!(
+            "Its new name starts with `{typed}`. Reply with only the new name."
+        ));
+
+        GenerateRequest {
+            model: model.to_string(),
+            prompt,
+            suffix: None,
+            stream: false,
+            keep_alive,
+            options: Some(ChatOptions {
+                num_predict: Some(MAX_NAME_TOKENS),
+                stop: Some(vec!["\n".to_string()]),
+                temperature: Some(COMPLETION_TEMPERATURE),
+                ..options
+            }),
+            system: Some(RENAME_SYSTEM_PROMPT.to_string()),
+            raw: None,
+            format: None,
+            context: None,
+            priority: RequestPriority::Automatic,
+        }
+    }
+
+    /// Asks for better names for the symbol, given the start of its
+    /// `definition`, as a ranked list.
+    pub fn names_request(
+        &self,
+        definition: Option<&str>,
+        model: &str,
+        options: ChatOptions,
+        keep_alive: KeepAlive,
+    ) -> GenerateRequest {
+        let mut prompt = self.describe(definition);
+        prompt.push_str(&format!(
+            "Suggest up to {MAX_NAMES} better names for `{}`, best first.",
+            self.old_name
+        ));
+
+        GenerateRequest {
+            model: model.to_string(),
+            prompt,
+            suffix: None,
+            stream: false,
+            keep_alive,
+            options: Some(ChatOptions {
+                num_predict: Some(MAX_NAMES_TOKENS),
+                ..options
+            }),
+            system: Some(SUGGEST_NAMES_SYSTEM_PROMPT.to_string()),
+            raw: None,
+            format: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "names": { "type": "array", "items": { "type": "string" } },
+                },
+                "required": ["names"],
+            })),
+            context: None,
+            priority: RequestPriority::UserInitiated,
+        }
+    }
+
+    fn describe(&self, definition: Option<&str>) -> String {
+        let mut prompt = String::new();
+        if !self.language.is_empty() {
+            prompt.push_str(&format!("Language: {}\n", self.language));
+        }
+        if let Some(definition) = definition {
+            prompt.push_str(&format!(
+                "The symbol `{}` is defined like this:\n```\n{definition}\n```\n",
+                self.old_name
+            ));
+        }
+        prompt.push_str(&format!(
+            "The symbol `{}` is used like this:\n```\n{}\n```\n",
+            self.old_name,
+            self.usages.join("\n"),
+        ));
+        prompt
+    }
+}
+
+/// Asks the model for better names for the symbol at the cursor, from its
+/// definition and how it's used, then starts renaming it with the names
+/// offered in the rename editor, best first. Picking one and confirming
+/// renames the symbol through its language server as usual.
+fn suggest_names(editor: &mut Editor, cx: &mut ViewContext<Editor>) -> Task<Result<()>> {
+    let Some(project) = editor.project().cloned() else {
+        return Task::ready(Ok(()));
+    };
+    let head = editor.selections.newest_anchor().head();
+    let Some((buffer, position)) = editor.buffer().read(cx).text_anchor_for_position(head, cx)
+    else {
+        return Task::ready(Ok(()));
+    };
+    let snapshot = buffer.read(cx).snapshot();
+    let settings = OllamaSettings::for_file(snapshot.file(), cx);
+    let server = OllamaSettings::server_for_file(snapshot.file(), cx);
+    if !settings.enabled {
+        return Task::ready(Ok(()));
+    }
+    let (range, kind) = snapshot.surrounding_word(position.to_offset(&snapshot));
+    if kind != Some(CharKind::Word) || range.is_empty() {
+        return Task::ready(Ok(()));
+    }
+    let target = RenameTarget::new(&snapshot, range);
+    let definition = project.update(cx, |project, cx| project.definition(&buffer, position, cx));
+
+    let model = settings.resolve_model(&settings.model).to_string();
+    let options = settings.runtime_options(&settings.model);
+    let keep_alive = settings.keep_alive_for(&settings.model);
+    let redactor = settings.secret_redactor();
+    let http_client = http_client_for(server, &cx.http_client());
+    let endpoints = server.endpoints();
+    let api_key = server.effective_api_key();
+    let api_format = server.api_format;
+    let retry = RetryPolicy::default().with_max_attempts(settings.max_request_attempts);
+    let timeout = Duration::from_secs(settings.completion_timeout_secs);
+    let pool = EndpointPool::global(cx);
+
+    cx.spawn(|editor, mut cx| async move {
+        // Without a definition, the usages alone are still worth asking about.
+        let link = definition
+            .await
+            .ok()
+            .and_then(|links| links.into_iter().next());
+        let definition = match link {
+            Some(link) => Some(link.target.buffer.read_with(&cx, |buffer, _| {
+                definition_lines(&buffer.snapshot(), link.target.range.start)
+            })?),
+            None => None,
+        };
+        let mut request = target.names_request(definition.as_deref(), &model, options, keep_alive);
+        if let Some(redactor) = &redactor {
+            redactor.redact_request(&mut request);
+        }
+        let response = pool
+            .generate(
+                http_client.as_ref(),
+                &endpoints,
+                api_key.as_deref(),
+                api_format,
+                request,
+                retry,
+                Some(timeout),
+            )
+            .await?;
+        let names = parse_names(&response.response, &target.old_name);
+        if names.is_empty() {
+            return Err(anyhow!("no names suggested for {}", target.old_name));
+        }
+
+        let rename = editor.update(&mut cx, |editor, cx| editor.rename(&Rename, cx))?;
+        let Some(rename) = rename else {
+            return Ok(());
+        };
+        rename.await?;
+        editor.update(&mut cx, |editor, cx| {
+            let Some(rename) = editor.pending_rename() else {
+                return;
+            };
+            rename.editor.clone().update(cx, |rename_editor, cx| {
+                rename_editor.set_completion_provider(Some(Box::new(NameSuggestions { names })));
+                // With the cursor at the start of the old name, every
+                // suggestion is listed, and typing still replaces it.
+                let len = rename_editor.buffer().read(cx).len(cx);
+                rename_editor
+                    .change_selections(None, cx, |selections| selections.select_ranges([len..0]));
+                rename_editor.show_completions(&ShowCompletions::default(), cx);
+            });
+        })
+    })
+}
+
+/// The first lines of the definition starting at `start`.
+fn definition_lines(snapshot: &BufferSnapshot, start: text::Anchor) -> String {
+    let start_row = start.to_point(snapshot).row;
+    let end_row = (start_row + MAX_DEFINITION_LINES - 1).min(snapshot.max_point().row);
+    snapshot
+        .text_for_range(Point::new(start_row, 0)..Point::new(end_row, snapshot.line_len(end_row)))
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct SuggestedNames {
+    names: Vec<String>,
+}
+
+/// The names in the model's response, in its order, without duplicates, the
+/// old name, or anything that isn't a single identifier.
+fn parse_names(response: &str, old_name: &str) -> Vec<String> {
+    let Ok(suggested) = serde_json::from_str::<SuggestedNames>(response) else {
+        return Vec::new();
+    };
+    let mut names = Vec::<String>::new();
+    for name in suggested.names {
+        let name = name.trim().trim_matches('`');
+        let is_identifier = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '_' | '$'));
+        if is_identifier && name != old_name && !names.iter().any(|other| other == name) {
+            names.push(name.to_string());
+        }
+    }
+    names.truncate(MAX_NAMES);
+    names
+}
+
+/// Offers the suggested names in a rename editor, replacing whatever name
+/// it holds.
+struct NameSuggestions {
+    names: Vec<String>,
+}
+
+impl CompletionProvider for NameSuggestions {
+    fn completions(
+        &self,
+        buffer: &Model<Buffer>,
+        _buffer_position: text::Anchor,
+        _: editor::CompletionContext,
+        cx: &mut ViewContext<Editor>,
+    ) -> Task<Result<Vec<Completion>>> {
+        let snapshot = buffer.read(cx).snapshot();
+        let old_range = snapshot.anchor_before(0)..snapshot.anchor_after(snapshot.len());
+        Task::ready(Ok(self
+            .names
+            .iter()
+            .map(|name| Completion {
+                old_range: old_range.clone(),
+                new_text: name.clone(),
+                label: CodeLabel::plain(name.clone(), None),
+                documentation: None,
+                server_id: LanguageServerId(0),
+                lsp_completion: Default::default(),
+                confirm: None,
+            })
+            .collect()))
+    }
+
+    fn resolve_completions(
+        &self,
+        _buffer: Model<Buffer>,
+        _completion_indices: Vec<usize>,
+        _completions: Arc<RwLock<Box<[Completion]>>>,
+        _cx: &mut ViewContext<Editor>,
+    ) -> Task<Result<bool>> {
+        Task::ready(Ok(false))
+    }
+
+    fn apply_additional_edits_for_completion(
+        &self,
+        _buffer: Model<Buffer>,
+        _completion: Completion,
+        _push_to_history: bool,
+        _cx: &mut ViewContext<Editor>,
+    ) -> Task<Result<Option<language::Transaction>>> {
+        Task::ready(Ok(None))
+    }
+
+    fn is_completion_trigger(
+        &self,
+        _buffer: &Model<Buffer>,
+        _position: text::Anchor,
+        _text: &str,
+        _trigger_in_words: bool,
+        _cx: &mut ViewContext<Editor>,
+    ) -> bool {
+        false
+    }
+
+    /// The names stay in the order the model ranked them.
+    fn sort_completions(&self) -> bool {
+        false
+    }
+}
+
+/// The lines of `text` that use `name` as a whole word, trimmed, up to `max`
+/// of them nearest `row`, in the order they appear.
+fn usage_lines(text: &str, name: &str, row: usize, max: usize) -> Vec<String> {
+    if name.is_empty() {
+        return Vec::new();
+    }
+    let mut usages = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| uses_word(line, name))
+        .collect::<Vec<_>>();
+    usages.sort_by_key(|(line_row, _)| line_row.abs_diff(row));
+    usages.truncate(max);
+    usages.sort_by_key(|(line_row, _)| *line_row);
+    usages
+        .into_iter()
+        .map(|(_, line)| line.trim().to_string())
+        .collect()
+}
+
+fn uses_word(line: &str, word: &str) -> bool {
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+    line.match_indices(word).any(|(ix, _)| {
+        let before = line[..ix].chars().next_back();
+        let after = line[ix + word.len()..].chars().next();
+        !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+    })
+}
+
+/// What the model's name adds to `typed`, when it suggested one that starts
+/// with it.
+pub(crate) fn name_after_typed<'a>(response: &'a str, typed: &str) -> Option<&'a str> {
+    let name = response
+        .split_whitespace()
+        .next()?
+        .trim_matches(|c: char| matches!(c, '`' | '"' | '\'' | '.' | ','));
+    name.strip_prefix(typed).filter(|rest| !rest.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage_lines() {
+        let text = "let count = 0;\n\
+            let counter = 1;\n\
+            fn f() {\n\
+            \x20   count += 1;\n\
+            }\n\
+            print(count);\n";
+        assert_eq!(
+            usage_lines(text, "count", 0, 8),
+            ["let count = 0;", "count += 1;", "print(count);"]
+        );
+        // The lines nearest the renamed occurrence are kept.
+        assert_eq!(
+            usage_lines(text, "count", 5, 2),
+            ["count += 1;", "print(count);"]
+        );
+    }
+
+    #[test]
+    fn test_name_after_typed() {
+        assert_eq!(name_after_typed("user_count", "user"), Some("_count"));
+        assert_eq!(name_after_typed("`userCount`\n", "user"), Some("Count"));
+        assert_eq!(name_after_typed("total", "user"), None);
+        assert_eq!(name_after_typed("user", "user"), None);
+        assert_eq!(name_after_typed("", "user"), None);
+    }
+
+    #[test]
+    fn test_parse_names() {
+        assert_eq!(
+            parse_names(
+                r#"{"names": ["active_users", "`online_users`", "active_users", "n", "user list", ""]}"#,
+                "n"
+            ),
+            ["active_users", "online_users"]
+        );
+        assert_eq!(
+            parse_names(r#"{"names": ["a", "b", "c", "d", "e", "f"]}"#, "x").len(),
+            MAX_NAMES
+        );
+        assert!(parse_names("active_users", "n").is_empty());
+    }
+}
@@ -0,0 +1,373 @@
+use chrono::NaiveTime;
+use gpui::{AppContext, Context as _, Global, Model, ModelContext};
+use ollama::GenerateRequest;
+use serde_json::Value;
+use std::{
+    collections::VecDeque,
+    fmt::{self, Write as _},
+    sync::atomic::{AtomicU64, Ordering::SeqCst},
+    time::Duration,
+};
+
+/// How many requests the log keeps.
+const MAX_LOGGED_REQUESTS: usize = 50;
+/// How many trace events the log keeps.
+const MAX_TRACE_EVENTS: usize = 200;
+
+struct GlobalRequestLog(Model<RequestLog>);
+
+impl Global for GlobalRequestLog {}
+
+/// The inline completion requests sent while the request log is open, for
+/// finding out why a model's suggestions are poor or never shown. Their
+/// prompts and responses are only recorded while it's open. A trace of what
+/// became of each request, without its text, is always kept, so problems can
+/// be looked into after they happen.
+#[derive(Default)]
+pub struct RequestLog {
+    entries: VecDeque<LoggedRequest>,
+    trace: VecDeque<TracedEvent>,
+    viewers: usize,
+}
+
+/// Identifies an inline completion request across the events in its trace,
+/// from being requested to its suggestion being accepted or discarded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RequestId(pub u64);
+
+impl RequestId {
+    pub fn next() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        Self(NEXT_ID.fetch_add(1, SeqCst))
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Something that happened to an inline completion request.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TraceEvent {
+    /// A suggestion was asked for, either by typing or explicitly.
+    Requested {
+        debounced: bool,
+    },
+    /// A newer request replaced this one before it finished.
+    Cancelled,
+    /// The request wasn't sent, since the server was offline or throttled.
+    Skipped,
+    Sent {
+        model: String,
+    },
+    /// The model answered, or didn't, with this outcome.
+    Finished {
+        outcome: String,
+        latency: Duration,
+    },
+    Accepted,
+    Discarded,
+}
+
+/// A trace event as recorded, displayed as `key=value` pairs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TracedEvent {
+    pub time: NaiveTime,
+    pub id: RequestId,
+    pub event: TraceEvent,
+}
+
+impl fmt::Display for TracedEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "request={} event=", self.id.0)?;
+        match &self.event {
+            TraceEvent::Requested { debounced } => write!(f, "requested debounced={debounced}"),
+            TraceEvent::Cancelled => write!(f, "cancelled"),
+            TraceEvent::Skipped => write!(f, "skipped reason=offline"),
+            TraceEvent::Sent { model } => write!(f, "sent model={model:?}"),
+            TraceEvent::Finished { outcome, latency } => write!(
+                f,
+                "finished outcome={outcome:?} latency_ms={}",
+                latency.as_millis()
+            ),
+            TraceEvent::Accepted => write!(f, "accepted"),
+            TraceEvent::Discarded => write!(f, "discarded"),
+        }
+    }
+}
+
+pub struct LoggedRequest {
+    pub id: RequestId,
+    pub time: NaiveTime,
+    /// The request as sent, after context was added and secrets redacted.
+    pub request: GenerateRequest,
+    /// The model's raw response, before post-processing.
+    pub response: Option<String>,
+    pub latency: Duration,
+    pub outcome: RequestOutcome,
+}
+
+/// What became of a request's response.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RequestOutcome {
+    /// Shown as this suggestion, after post-processing.
+    Shown(String),
+    /// Not sent, since the same request was answered with this suggestion
+    /// before.
+    Cached(String),
+    /// Kept as this suggestion for when the cursor reaches the place it was
+    /// prefetched for.
+    Prefetched(String),
+    /// Nothing but whitespace was left after post-processing.
+    Empty,
+    /// The suggestion repeated the code already after the cursor.
+    Duplicate,
+    /// The predicted edit couldn't be parsed or didn't apply to the buffer.
+    Unparseable,
+    /// The buffer was edited while the request was in flight.
+    Stale,
+    TimedOut,
+    Failed(String),
+}
+
+impl RequestOutcome {
+    /// A short description, with only the first line of an error.
+    pub fn describe(&self) -> String {
+        match self {
+            RequestOutcome::Shown(_) => "shown".to_string(),
+            RequestOutcome::Cached(_) => "shown from cache".to_string(),
+            RequestOutcome::Prefetched(_) => "prefetched".to_string(),
+            RequestOutcome::Empty => "filtered: empty after post-processing".to_string(),
+            RequestOutcome::Duplicate => "filtered: repeats the code after the cursor".to_string(),
+            RequestOutcome::Unparseable => "filtered: the predicted edit didn't apply".to_string(),
+            RequestOutcome::Stale => "filtered: the buffer changed while waiting".to_string(),
+            RequestOutcome::TimedOut => "timed out".to_string(),
+            RequestOutcome::Failed(error) => {
+                format!("failed: {}", error.lines().next().unwrap_or_default())
+            }
+        }
+    }
+}
+
+pub(crate) fn init(cx: &mut AppContext) {
+    let log = cx.new_model(|_| RequestLog::default());
+    cx.set_global(GlobalRequestLog(log));
+}
+
+/// Adds `event` to the trace of request `id`.
+pub(crate) fn trace(id: RequestId, event: TraceEvent, cx: &mut AppContext) {
+    if let Some(log) = RequestLog::global(cx) {
+        log.update(cx, |log, cx| log.trace(id, event, cx));
+    }
+}
+
+impl RequestLog {
+    pub fn global(cx: &AppContext) -> Option<Model<Self>> {
+        cx.try_global::<GlobalRequestLog>().map(|log| log.0.clone())
+    }
+
+    /// Whether the log is open anywhere, so requests should be recorded.
+    pub fn is_recording(&self) -> bool {
+        self.viewers > 0
+    }
+
+    pub fn add_viewer(&mut self) {
+        self.viewers += 1;
+    }
+
+    /// Forgets the recorded requests once the last viewer closes.
+    pub fn remove_viewer(&mut self) {
+        self.viewers = self.viewers.saturating_sub(1);
+        if self.viewers == 0 {
+            self.entries.clear();
+        }
+    }
+
+    pub fn record(&mut self, entry: LoggedRequest, cx: &mut ModelContext<Self>) {
+        if !self.is_recording() {
+            return;
+        }
+        if self.entries.len() == MAX_LOGGED_REQUESTS {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+        cx.notify();
+    }
+
+    /// Adds `event` to the trace of request `id`, and logs it at debug
+    /// level with the `ollama::trace` target.
+    pub fn trace(&mut self, id: RequestId, event: TraceEvent, cx: &mut ModelContext<Self>) {
+        let traced = TracedEvent {
+            time: chrono::Local::now().time(),
+            id,
+            event,
+        };
+        log::debug!(target: "ollama::trace", "{traced}");
+        if self.trace.len() == MAX_TRACE_EVENTS {
+            self.trace.pop_front();
+        }
+        self.trace.push_back(traced);
+        if self.is_recording() {
+            cx.notify();
+        }
+    }
+
+    /// The recorded requests as Markdown, newest first.
+    pub fn markdown(&self) -> String {
+        let mut markdown = format!(
+            "# Ollama Request Log\n\n\
+             Inline completion requests are recorded while this log is open. \
+             The latest {MAX_LOGGED_REQUESTS} are kept, newest first.\n"
+        );
+        for entry in self.entries.iter().rev() {
+            write_entry(&mut markdown, entry);
+        }
+        if !self.trace.is_empty() {
+            writeln!(
+                markdown,
+                "\n## Trace\n\n\
+                 The latest {MAX_TRACE_EVENTS} events in the life of each request, \
+                 including requests made while this log was closed, newest first.\n\n```"
+            )
+            .ok();
+            for traced in self.trace.iter().rev() {
+                writeln!(markdown, "{} {traced}", traced.time.format("%H:%M:%S%.3f")).ok();
+            }
+            writeln!(markdown, "```").ok();
+        }
+        markdown
+    }
+}
+
+fn write_entry(markdown: &mut String, entry: &LoggedRequest) {
+    let request = &entry.request;
+    writeln!(
+        markdown,
+        "\n## {} {} {} ({} ms, {})\n",
+        entry.time.format("%H:%M:%S"),
+        entry.id,
+        request.model,
+        entry.latency.as_millis(),
+        entry.outcome.describe()
+    )
+    .ok();
+    if let Some(Ok(Value::Object(mut options))) = request.options.as_ref().map(serde_json::to_value)
+    {
+        options.retain(|_, value| !value.is_null());
+        writeln!(markdown, "Options: `{}`\n", Value::Object(options)).ok();
+    }
+    let sections = [
+        ("System", request.system.as_deref()),
+        ("Prompt", Some(request.prompt.as_str())),
+        ("Suffix", request.suffix.as_deref()),
+        ("Response", entry.response.as_deref()),
+        (
+            "Suggestion",
+            match &entry.outcome {
+                RequestOutcome::Shown(text)
+                | RequestOutcome::Cached(text)
+                | RequestOutcome::Prefetched(text) => Some(text.as_str()),
+                _ => None,
+            },
+        ),
+    ];
+    for (title, text) in sections {
+        if let Some(text) = text {
+            writeln!(markdown, "{title}:\n\n````\n{text}\n````\n").ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ollama::{ChatOptions, KeepAlive, RequestPriority};
+
+    #[test]
+    fn test_request_log_markdown() {
+        let entry = |prompt: &str, outcome| LoggedRequest {
+            id: RequestId(7),
+            time: NaiveTime::from_hms_opt(9, 30, 5).unwrap(),
+            request: GenerateRequest {
+                model: "qwen2.5-coder:1.5b".into(),
+                prompt: prompt.into(),
+                suffix: Some("\n}".into()),
+                stream: false,
+                keep_alive: KeepAlive::default(),
+                options: Some(ChatOptions {
+                    num_predict: Some(128),
+                    ..Default::default()
+                }),
+                system: None,
+                raw: None,
+                format: None,
+                context: None,
+                priority: RequestPriority::Automatic,
+            },
+            response: Some("x + 1\n}".into()),
+            latency: Duration::from_millis(412),
+            outcome,
+        };
+        let mut log = RequestLog::default();
+        log.add_viewer();
+        log.entries.push_back(entry(
+            "fn f(x: i32) -> i32 {\n    ",
+            RequestOutcome::Shown("x + 1".into()),
+        ));
+        log.entries.push_back(entry(
+            "fn g() {",
+            RequestOutcome::Failed("refused\nmore".into()),
+        ));
+        let markdown = log.markdown();
+        let failed = markdown
+            .find("## 09:30:05 #7 qwen2.5-coder:1.5b (412 ms, failed: refused)")
+            .unwrap();
+        let shown = markdown
+            .find("## 09:30:05 #7 qwen2.5-coder:1.5b (412 ms, shown)")
+            .unwrap();
+        assert!(failed < shown);
+        assert!(markdown[shown..].contains(
+            "Options: `{\"num_predict\":128}`\n\n\
+             Prompt:\n\n````\nfn f(x: i32) -> i32 {\n    \n````\n\n\
+             Suffix:\n\n````\n\n}\n````\n\n\
+             Response:\n\n````\nx + 1\n}\n````\n\n\
+             Suggestion:\n\n````\nx + 1\n````\n"
+        ));
+
+        log.remove_viewer();
+        assert!(!log.is_recording());
+        assert!(log.entries.is_empty());
+    }
+
+    #[test]
+    fn test_traced_event_display() {
+        let traced = |event| {
+            TracedEvent {
+                time: NaiveTime::from_hms_opt(9, 30, 5).unwrap(),
+                id: RequestId(42),
+                event,
+            }
+            .to_string()
+        };
+        assert_eq!(
+            traced(TraceEvent::Requested { debounced: true }),
+            "request=42 event=requested debounced=true"
+        );
+        assert_eq!(
+            traced(TraceEvent::Sent {
+                model: "qwen2.5-coder:1.5b".into()
+            }),
+            "request=42 event=sent model=\"qwen2.5-coder:1.5b\""
+        );
+        assert_eq!(
+            traced(TraceEvent::Finished {
+                outcome: RequestOutcome::Failed("refused\nmore".into()).describe(),
+                latency: Duration::from_millis(412),
+            }),
+            "request=42 event=finished outcome=\"failed: refused\" latency_ms=412"
+        );
+        assert_eq!(traced(TraceEvent::Discarded), "request=42 event=discarded");
+    }
+}
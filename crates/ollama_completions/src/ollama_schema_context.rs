@@ -0,0 +1,1115 @@
+use crate::{count_tokens, normalize, OllamaSettings};
+use anyhow::{anyhow, Context as _, Result};
+use fs::Fs;
+use futures::AsyncReadExt as _;
+use gpui::{AppContext, BackgroundExecutor, Global};
+use http_client::{AsyncBody, HttpClient};
+use language::BufferSnapshot;
+use lsp::LanguageServerName;
+use parking_lot::Mutex;
+use project::{project_settings::ProjectSettings, Project};
+use regex::Regex;
+use serde_json::Value;
+use settings::{Settings as _, SettingsLocation};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock},
+    time::{Duration, Instant},
+};
+use text::Bias;
+use util::paths::PathMatcher;
+
+/// Schemas are loaded again once they're this old, in case they changed.
+const RELOAD_AFTER: Duration = Duration::from_secs(5 * 60);
+/// How much of the start of a file is searched for the schema it names.
+const DIRECTIVE_SCAN_BYTES: usize = 4096;
+/// The cursor's place in files larger than this isn't worked out.
+const MAX_SCAN_BYTES: usize = 256 * 1024;
+/// How many `$ref`s, and `allOf`, `anyOf` and `oneOf` branches, are followed
+/// in a row.
+const MAX_DEPTH: usize = 8;
+/// At most this many of a value's allowed values are listed.
+const MAX_ENUM_VALUES: usize = 16;
+/// Descriptions are cut off after this many characters.
+const MAX_DESCRIPTION_CHARS: usize = 120;
+
+/// Schemas for well-known configuration files, from the JSON Schema Store,
+/// used when a file doesn't name one and the language servers' settings
+/// don't give it one.
+const KNOWN_SCHEMAS: &[(&str, &str)] = &[
+    ("package.json", "https://json.schemastore.org/package.json"),
+    (
+        "tsconfig.json",
+        "https://json.schemastore.org/tsconfig.json",
+    ),
+    ("Cargo.toml", "https://json.schemastore.org/cargo.json"),
+    (
+        "pyproject.toml",
+        "https://json.schemastore.org/pyproject.json",
+    ),
+    (
+        ".github/workflows/*.yml",
+        "https://json.schemastore.org/github-workflow.json",
+    ),
+    (
+        ".github/workflows/*.yaml",
+        "https://json.schemastore.org/github-workflow.json",
+    ),
+    (
+        ".gitlab-ci.yml",
+        "https://json.schemastore.org/gitlab-ci.json",
+    ),
+];
+
+static JSON_DIRECTIVE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#""\$schema"\s*:\s*"([^"]+)""#).unwrap());
+static YAML_DIRECTIVE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"#\s*yaml-language-server\s*:\s*\$schema=(\S+)").unwrap());
+static TOML_DIRECTIVE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"#:schema\s+(\S+)").unwrap());
+
+#[derive(Default)]
+struct GlobalJsonSchemas(Arc<JsonSchemas>);
+
+impl Global for GlobalJsonSchemas {}
+
+/// The JSON schemas of the configuration files being edited, loaded in the
+/// background the first time a file needs one, by URL or path.
+#[derive(Default)]
+pub struct JsonSchemas {
+    schemas: Mutex<HashMap<String, LoadedSchema>>,
+}
+
+#[derive(Default)]
+struct LoadedSchema {
+    /// `None` until the schema is loaded, or when it couldn't be.
+    schema: Option<Arc<Value>>,
+    /// When loading the schema last finished.
+    loaded_at: Option<Instant>,
+    loading: bool,
+}
+
+pub(crate) fn init(cx: &mut AppContext) {
+    cx.set_global(GlobalJsonSchemas::default());
+}
+
+impl JsonSchemas {
+    pub fn global(cx: &AppContext) -> Arc<Self> {
+        cx.global::<GlobalJsonSchemas>().0.clone()
+    }
+
+    /// The schema from `source`, as last loaded. Schemas not loaded yet, or
+    /// loaded a while ago, are loaded in the background for later requests.
+    fn schema(
+        self: &Arc<Self>,
+        source: SchemaSource,
+        fs: Arc<dyn Fs>,
+        http_client: Arc<dyn HttpClient>,
+        executor: &BackgroundExecutor,
+    ) -> Option<Arc<Value>> {
+        let key = match &source {
+            SchemaSource::Inline(schema) => return Some(schema.clone()),
+            SchemaSource::Url(url) => url.clone(),
+            SchemaSource::Path(path) => path.to_string_lossy().into_owned(),
+        };
+        let stale = {
+            let mut schemas = self.schemas.lock();
+            let loaded = schemas.entry(key.clone()).or_default();
+            let fresh = loaded
+                .loaded_at
+                .is_some_and(|loaded_at| loaded_at.elapsed() < RELOAD_AFTER);
+            if fresh || loaded.loading {
+                return loaded.schema.clone();
+            }
+            loaded.loading = true;
+            loaded.schema.clone()
+        };
+
+        let this = self.clone();
+        executor
+            .spawn(async move {
+                let result = load_schema(&source, fs.as_ref(), http_client.as_ref()).await;
+                let mut schemas = this.schemas.lock();
+                let loaded = schemas.entry(key.clone()).or_default();
+                match result {
+                    Ok(schema) => loaded.schema = Some(Arc::new(schema)),
+                    Err(error) => log::debug!("Failed to load the JSON schema {key}: {error:?}"),
+                }
+                loaded.loaded_at = Some(Instant::now());
+                loaded.loading = false;
+            })
+            .detach();
+        stale
+    }
+}
+
+/// Where a file's schema comes from.
+#[derive(Clone, Debug, PartialEq)]
+enum SchemaSource {
+    /// Given in full in a language server's settings.
+    Inline(Arc<Value>),
+    Url(String),
+    Path(PathBuf),
+}
+
+async fn load_schema(
+    source: &SchemaSource,
+    fs: &dyn Fs,
+    http_client: &dyn HttpClient,
+) -> Result<Value> {
+    let text = match source {
+        SchemaSource::Inline(schema) => return Ok(schema.as_ref().clone()),
+        SchemaSource::Url(url) => {
+            let mut response = http_client.get(url, AsyncBody::default(), true).await?;
+            let mut body = String::new();
+            response.body_mut().read_to_string(&mut body).await?;
+            if !response.status().is_success() {
+                return Err(anyhow!("{url} responded with {}", response.status()));
+            }
+            body
+        }
+        SchemaSource::Path(path) => fs.load(path).await?,
+    };
+    serde_json::from_str(&text).context("parsing the schema")
+}
+
+/// The configuration languages whose files can have a JSON schema.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ConfigFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl ConfigFormat {
+    fn for_language(language: &str) -> Option<Self> {
+        match language {
+            "JSON" | "JSONC" => Some(Self::Json),
+            "YAML" => Some(Self::Yaml),
+            "TOML" => Some(Self::Toml),
+            _ => None,
+        }
+    }
+
+    /// The schema a file names itself, like `"$schema"` in JSON, a
+    /// `# yaml-language-server: $schema=` comment in YAML or a `#:schema`
+    /// comment in TOML.
+    fn directive(self, text: &str) -> Option<&str> {
+        let directive = match self {
+            Self::Json => &JSON_DIRECTIVE,
+            Self::Yaml => &YAML_DIRECTIVE,
+            Self::Toml => &TOML_DIRECTIVE,
+        };
+        Some(directive.captures(text)?.get(1)?.as_str())
+    }
+
+    /// The keys and list items leading from the top of the file to the
+    /// value at the end of `text`.
+    fn cursor_path(self, text: &str) -> Vec<PathSegment> {
+        match self {
+            Self::Json => json_path(text),
+            Self::Yaml => yaml_path(text),
+            Self::Toml => toml_path(text),
+        }
+    }
+}
+
+/// A step from a value into one of its children.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum PathSegment {
+    Key(String),
+    Item,
+}
+
+/// What's needed to describe the part of the file's JSON schema at the
+/// cursor, read on the main thread so the file can be searched in the
+/// background.
+pub(crate) struct SchemaContextRequest {
+    snapshot: BufferSnapshot,
+    cursor_offset: usize,
+    format: ConfigFormat,
+    path: Arc<Path>,
+    root: Arc<Path>,
+    /// The schema the language servers' settings give the file, if any.
+    configured: Option<SchemaSource>,
+    disabled_paths: PathMatcher,
+    budget: usize,
+    schemas: Arc<JsonSchemas>,
+    fs: Arc<dyn Fs>,
+    http_client: Arc<dyn HttpClient>,
+    executor: BackgroundExecutor,
+}
+
+impl SchemaContextRequest {
+    /// `None` unless the file is a JSON, YAML or TOML file in `project` whose
+    /// language has `schema_context` on, and the cursor isn't too far into it.
+    pub fn new(
+        settings: &OllamaSettings,
+        snapshot: &BufferSnapshot,
+        cursor_offset: usize,
+        project: &Project,
+        http_client: Arc<dyn HttpClient>,
+        disabled_paths: &PathMatcher,
+        cx: &AppContext,
+    ) -> Option<Self> {
+        if cursor_offset > MAX_SCAN_BYTES {
+            return None;
+        }
+        let language = snapshot
+            .language_at(cursor_offset)
+            .map(|language| language.name().0.to_string())?;
+        if !settings.schema_context_for(Some(&language)) {
+            return None;
+        }
+        let format = ConfigFormat::for_language(&language)?;
+        let file = snapshot.file()?;
+        let worktree = project.worktree_for_id(file.worktree_id(cx), cx)?;
+        let root = worktree.read(cx).abs_path();
+        let path = file.path().clone();
+        let location = SettingsLocation {
+            worktree_id: file.worktree_id(cx),
+            path: path.as_ref(),
+        };
+        let configured = configured_source(
+            format,
+            ProjectSettings::get(Some(location), cx),
+            &path,
+            &root,
+            disabled_paths,
+        );
+        Some(Self {
+            snapshot: snapshot.clone(),
+            cursor_offset,
+            format,
+            path,
+            root,
+            configured,
+            disabled_paths: disabled_paths.clone(),
+            budget: settings.schema_context_tokens,
+            schemas: JsonSchemas::global(cx),
+            fs: project.fs().clone(),
+            http_client,
+            executor: cx.background_executor().clone(),
+        })
+    }
+
+    /// The part of the file's JSON schema that describes the value at the
+    /// cursor, in the language's line comments. The schema is the one the
+    /// file names itself, else the one the JSON or YAML language server's
+    /// settings give it, else a well-known configuration file's. Schemas that
+    /// aren't loaded yet are loaded in the background, for later requests.
+    pub fn run(&self, comment_prefix: &str) -> String {
+        let snapshot = &self.snapshot;
+        let head_end = snapshot.clip_offset(DIRECTIVE_SCAN_BYTES.min(snapshot.len()), Bias::Left);
+        let head = snapshot.text_for_range(0..head_end).collect::<String>();
+        let directory = self.path.parent().unwrap_or(Path::new(""));
+        // A schema the file names that can't be read isn't swapped for another.
+        let source = match self.format.directive(&head) {
+            Some(reference) => {
+                schema_source(reference, directory, &self.root, &self.disabled_paths)
+            }
+            None => self.configured.clone().or_else(|| known_source(&self.path)),
+        };
+        let Some(source) = source else {
+            return String::new();
+        };
+        let Some(schema) = self.schemas.schema(
+            source,
+            self.fs.clone(),
+            self.http_client.clone(),
+            &self.executor,
+        ) else {
+            return String::new();
+        };
+
+        let text = snapshot
+            .text_for_range(0..self.cursor_offset)
+            .collect::<String>();
+        let segments = self.format.cursor_path(&text);
+        describe_at(&schema, &segments, comment_prefix, self.budget)
+    }
+}
+
+/// Where a schema named by `reference` is, relative paths being relative to
+/// `directory` in the worktree at `root`. As schemas are described to the
+/// model, local ones must be in the worktree and not match `disabled_paths`.
+fn schema_source(
+    reference: &str,
+    directory: &Path,
+    root: &Path,
+    disabled_paths: &PathMatcher,
+) -> Option<SchemaSource> {
+    if reference.starts_with("http://") || reference.starts_with("https://") {
+        return Some(SchemaSource::Url(reference.to_string()));
+    }
+    let path = match reference.strip_prefix("file://") {
+        Some(path) => Path::new(path).strip_prefix(root).ok()?.to_path_buf(),
+        None => directory.join(reference),
+    };
+    let path = normalize(&path)?;
+    if disabled_paths.is_match(&path) {
+        return None;
+    }
+    Some(SchemaSource::Path(root.join(path)))
+}
+
+/// The schema the JSON or YAML language server's settings give the file at
+/// `path`, with `json.schemas` or `yaml.schemas`.
+fn configured_source(
+    format: ConfigFormat,
+    settings: &ProjectSettings,
+    path: &Path,
+    root: &Path,
+    disabled_paths: &PathMatcher,
+) -> Option<SchemaSource> {
+    let (server, pointer) = match format {
+        ConfigFormat::Json => ("json-language-server", "/json/schemas"),
+        ConfigFormat::Yaml => ("yaml-language-server", "/yaml/schemas"),
+        ConfigFormat::Toml => return None,
+    };
+    let schemas = settings
+        .lsp
+        .get(&LanguageServerName::new_static(server))?
+        .settings
+        .as_ref()?
+        .pointer(pointer)?;
+    let any_match = |patterns: &Value| match patterns {
+        Value::String(pattern) => file_matches(pattern, path),
+        Value::Array(patterns) => patterns
+            .iter()
+            .filter_map(Value::as_str)
+            .any(|pattern| file_matches(pattern, path)),
+        _ => false,
+    };
+    match schemas {
+        // `[{ "fileMatch": ["*.json"], "url": "…" }]`, or with the schema
+        // itself under "schema".
+        Value::Array(schemas) => {
+            let schema = schemas
+                .iter()
+                .find(|schema| schema.get("fileMatch").is_some_and(any_match))?;
+            match schema.get("schema") {
+                Some(schema) => Some(SchemaSource::Inline(Arc::new(schema.clone()))),
+                None => schema_source(
+                    schema.get("url")?.as_str()?,
+                    Path::new(""),
+                    root,
+                    disabled_paths,
+                ),
+            }
+        }
+        // `{ "https://…": ["*.yml"] }`
+        Value::Object(schemas) => schemas
+            .iter()
+            .find(|(_, patterns)| any_match(patterns))
+            .and_then(|(reference, _)| {
+                schema_source(reference, Path::new(""), root, disabled_paths)
+            }),
+        _ => None,
+    }
+}
+
+fn known_source(path: &Path) -> Option<SchemaSource> {
+    KNOWN_SCHEMAS
+        .iter()
+        .find(|(pattern, _)| file_matches(pattern, path))
+        .map(|(_, url)| SchemaSource::Url(url.to_string()))
+}
+
+/// Whether the file at `path`, relative to its worktree, matches a schema's
+/// file pattern. Patterns without a slash match the file's name, like the
+/// language servers' do.
+fn file_matches(pattern: &str, path: &Path) -> bool {
+    let pattern = pattern
+        .trim_start_matches("./")
+        .trim_start_matches("**/")
+        .trim_start_matches('/');
+    let target = if pattern.contains('/') {
+        path
+    } else {
+        Path::new(path.file_name().unwrap_or_default())
+    };
+    if pattern.contains(['*', '?', '[', '{']) {
+        PathMatcher::new(&[pattern.to_string()]).is_ok_and(|matcher| matcher.is_match(target))
+    } else {
+        target == Path::new(pattern)
+    }
+}
+
+fn json_path(text: &str) -> Vec<PathSegment> {
+    enum Container {
+        Object { key: Option<String>, at_value: bool },
+        Array,
+    }
+
+    // Each container, with how it's reached from the one it's in.
+    let mut stack: Vec<(Option<PathSegment>, Container)> = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let mut string = String::new();
+                let mut closed = false;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => string.extend(chars.next()),
+                        '"' => {
+                            closed = true;
+                            break;
+                        }
+                        c => string.push(c),
+                    }
+                }
+                // A key still being typed doesn't lead anywhere yet.
+                if let Some((
+                    _,
+                    Container::Object {
+                        key,
+                        at_value: false,
+                    },
+                )) = stack.last_mut()
+                {
+                    if closed {
+                        *key = Some(string);
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = None;
+                for c in chars.by_ref() {
+                    if previous == Some('*') && c == '/' {
+                        break;
+                    }
+                    previous = Some(c);
+                }
+            }
+            ':' => {
+                if let Some((_, Container::Object { at_value, .. })) = stack.last_mut() {
+                    *at_value = true;
+                }
+            }
+            ',' => {
+                if let Some((_, Container::Object { key, at_value })) = stack.last_mut() {
+                    *key = None;
+                    *at_value = false;
+                }
+            }
+            '{' | '[' => {
+                let segment = match stack.last() {
+                    Some((
+                        _,
+                        Container::Object {
+                            key: Some(key),
+                            at_value: true,
+                        },
+                    )) => Some(PathSegment::Key(key.clone())),
+                    Some((_, Container::Array)) => Some(PathSegment::Item),
+                    _ => None,
+                };
+                let container = if c == '{' {
+                    Container::Object {
+                        key: None,
+                        at_value: false,
+                    }
+                } else {
+                    Container::Array
+                };
+                stack.push((segment, container));
+            }
+            '}' | ']' => {
+                stack.pop();
+            }
+            _ => {}
+        }
+    }
+
+    let mut path = stack
+        .iter()
+        .filter_map(|(segment, _)| segment.clone())
+        .collect::<Vec<_>>();
+    match stack.last() {
+        Some((
+            _,
+            Container::Object {
+                key: Some(key),
+                at_value: true,
+            },
+        )) => path.push(PathSegment::Key(key.clone())),
+        Some((_, Container::Array)) => path.push(PathSegment::Item),
+        _ => {}
+    }
+    path
+}
+
+fn yaml_path(text: &str) -> Vec<PathSegment> {
+    // Each key and list item, with the column it starts at.
+    let mut stack: Vec<(usize, PathSegment)> = Vec::new();
+    let (previous, current) = text.rsplit_once('\n').unwrap_or(("", text));
+    for line in previous.lines() {
+        let content = line.trim();
+        if content == "---" {
+            stack.clear();
+        } else if !content.is_empty() && !content.starts_with('#') {
+            push_yaml_line(&mut stack, line);
+        }
+    }
+    push_yaml_line(&mut stack, current);
+    stack.into_iter().map(|(_, segment)| segment).collect()
+}
+
+/// Replaces what `stack` holds at or beyond the line's indentation with the
+/// list items and key the line starts.
+fn push_yaml_line(stack: &mut Vec<(usize, PathSegment)>, line: &str) {
+    let mut column = line.len() - line.trim_start_matches(' ').len();
+    let mut content = &line[column..];
+    stack.retain(|(start, _)| *start < column);
+    while let Some(rest) = content
+        .strip_prefix("- ")
+        .or_else(|| (content == "-").then_some(""))
+    {
+        stack.push((column, PathSegment::Item));
+        let rest_start = rest.trim_start_matches(' ');
+        column += content.len() - rest_start.len();
+        content = rest_start;
+    }
+    let key_end = content
+        .find(": ")
+        .or_else(|| content.strip_suffix(':').map(str::len));
+    if let Some(key_end) = key_end {
+        let key = content[..key_end].trim().trim_matches(['"', '\'']);
+        if !key.is_empty() {
+            stack.push((column, PathSegment::Key(key.to_string())));
+        }
+    }
+}
+
+fn toml_path(text: &str) -> Vec<PathSegment> {
+    let (previous, current) = text.rsplit_once('\n').unwrap_or(("", text));
+    let mut path = Vec::new();
+    for line in previous.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("[[") {
+            if let Some((table, _)) = rest.split_once("]]") {
+                path = toml_keys(table);
+                path.push(PathSegment::Item);
+            }
+        } else if let Some(rest) = line.strip_prefix('[') {
+            if let Some((table, _)) = rest.split_once(']') {
+                path = toml_keys(table);
+            }
+        }
+    }
+    if let Some((key, _)) = current.split_once('=') {
+        path.extend(toml_keys(key));
+    }
+    path
+}
+
+/// The keys of a dotted key or table name, like `dependencies."serde"`.
+fn toml_keys(dotted: &str) -> Vec<PathSegment> {
+    dotted
+        .split('.')
+        .map(|key| key.trim().trim_matches(['"', '\'']))
+        .filter(|key| !key.is_empty())
+        .map(|key| PathSegment::Key(key.to_string()))
+        .collect()
+}
+
+/// Describes the part of `root` reached by `segments`, in comments starting
+/// with `comment_prefix`, in at most `max_tokens` tokens: its description,
+/// type and allowed values, then its properties, the required ones first.
+/// Empty when the schema doesn't say what's there.
+fn describe_at(
+    root: &Value,
+    segments: &[PathSegment],
+    comment_prefix: &str,
+    max_tokens: usize,
+) -> String {
+    // The file's own reference to its schema needs no description.
+    if segments.first() == Some(&PathSegment::Key("$schema".into())) {
+        return String::new();
+    }
+    let mut schema = root;
+    for segment in segments {
+        match child(root, schema, segment, 0) {
+            Some(child) => schema = child,
+            None => return String::new(),
+        }
+    }
+    let schema = resolve(root, schema);
+
+    let mut lines = Vec::new();
+    if let Some(description) = description(schema) {
+        lines.push(description);
+    }
+    if let Some(kind) = type_name(root, schema, 0) {
+        lines.push(format!("Type: {kind}"));
+    }
+    if let Some(values) = enum_values(schema) {
+        lines.push(format!("One of: {values}"));
+    }
+    let properties = properties(root, schema);
+    let properties_heading = (!properties.is_empty()).then(|| {
+        lines.push("Properties:".to_string());
+        lines.len() - 1
+    });
+    for (name, required, property) in properties {
+        let property = resolve(root, property);
+        let mut line = format!("  {name}");
+        if required {
+            line.push_str(" (required)");
+        }
+        if let Some(kind) = type_name(root, property, 0).or_else(|| enum_values(property)) {
+            line.push_str(": ");
+            line.push_str(&kind);
+        }
+        if let Some(description) = description(property) {
+            line.push_str(" - ");
+            line.push_str(&description);
+        }
+        lines.push(line);
+    }
+
+    let header = format!("{comment_prefix}Schema for {}:\n", path_label(segments));
+    let mut remaining = max_tokens.saturating_sub(count_tokens(&header));
+    let mut fitting = Vec::new();
+    for line in lines {
+        let line = format!("{comment_prefix}{line}\n");
+        let tokens = count_tokens(&line);
+        if tokens > remaining {
+            break;
+        }
+        remaining -= tokens;
+        fitting.push(line);
+    }
+    // A heading without a single property under it says nothing.
+    if properties_heading.is_some_and(|heading| fitting.len() == heading + 1) {
+        fitting.pop();
+    }
+    if fitting.is_empty() {
+        return String::new();
+    }
+    header + &fitting.concat()
+}
+
+/// Follows `$ref`s within the same schema, like `#/definitions/dependency`.
+fn resolve<'a>(root: &'a Value, mut schema: &'a Value) -> &'a Value {
+    for _ in 0..MAX_DEPTH {
+        let Some(pointer) = schema
+            .get("$ref")
+            .and_then(Value::as_str)
+            .and_then(|reference| reference.strip_prefix('#'))
+        else {
+            break;
+        };
+        match root.pointer(pointer) {
+            Some(target) => schema = target,
+            None => break,
+        }
+    }
+    schema
+}
+
+/// The schema of `segment` in a value `schema` describes.
+fn child<'a>(
+    root: &'a Value,
+    schema: &'a Value,
+    segment: &PathSegment,
+    depth: usize,
+) -> Option<&'a Value> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+    let schema = resolve(root, schema);
+    let direct = match segment {
+        PathSegment::Key(key) => schema
+            .get("properties")
+            .and_then(|properties| properties.get(key))
+            .or_else(|| {
+                schema
+                    .get("patternProperties")?
+                    .as_object()?
+                    .iter()
+                    .find(|(pattern, _)| {
+                        Regex::new(pattern).is_ok_and(|pattern| pattern.is_match(key))
+                    })
+                    .map(|(_, schema)| schema)
+            })
+            .or_else(|| {
+                schema
+                    .get("additionalProperties")
+                    .filter(|schema| schema.is_object())
+            }),
+        PathSegment::Item => match schema.get("items") {
+            Some(Value::Array(items)) => items.first(),
+            Some(items) => Some(items),
+            None => schema.get("prefixItems")?.as_array()?.first(),
+        },
+    };
+    direct.or_else(|| branches(schema).find_map(|branch| child(root, branch, segment, depth + 1)))
+}
+
+/// The schemas a value must or may also match, in `allOf`, `anyOf` and
+/// `oneOf`.
+fn branches(schema: &Value) -> impl Iterator<Item = &Value> {
+    ["allOf", "anyOf", "oneOf"]
+        .into_iter()
+        .filter_map(move |keyword| schema.get(keyword)?.as_array())
+        .flatten()
+}
+
+fn type_name(root: &Value, schema: &Value, depth: usize) -> Option<String> {
+    match schema.get("type") {
+        Some(Value::String(kind)) => return Some(kind.clone()),
+        Some(Value::Array(kinds)) => {
+            let kinds = kinds.iter().filter_map(Value::as_str).collect::<Vec<_>>();
+            return Some(kinds.join(" | "));
+        }
+        _ => {}
+    }
+    if schema.get("properties").is_some() {
+        return Some("object".to_string());
+    }
+    if schema.get("items").is_some() {
+        return Some("array".to_string());
+    }
+    if depth > 0 {
+        return None;
+    }
+    let mut kinds = Vec::new();
+    for branch in branches(schema) {
+        if let Some(kind) = type_name(root, resolve(root, branch), depth + 1) {
+            if !kinds.contains(&kind) {
+                kinds.push(kind);
+            }
+        }
+    }
+    (!kinds.is_empty()).then(|| kinds.join(" | "))
+}
+
+fn enum_values(schema: &Value) -> Option<String> {
+    let values = match (schema.get("enum"), schema.get("const")) {
+        (Some(Value::Array(values)), _) => values.iter().collect::<Vec<_>>(),
+        (_, Some(value)) => vec![value],
+        _ => return None,
+    };
+    let mut listed = values
+        .iter()
+        .take(MAX_ENUM_VALUES)
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>();
+    if values.len() > MAX_ENUM_VALUES {
+        listed.push("…".to_string());
+    }
+    Some(listed.join(", "))
+}
+
+/// The first line of the schema's description, or its title.
+fn description(schema: &Value) -> Option<String> {
+    let text = ["description", "markdownDescription", "title"]
+        .into_iter()
+        .find_map(|keyword| schema.get(keyword)?.as_str())?;
+    let line = text.lines().next()?.trim();
+    if line.is_empty() {
+        return None;
+    }
+    if line.chars().count() > MAX_DESCRIPTION_CHARS {
+        let truncated = line.chars().take(MAX_DESCRIPTION_CHARS).collect::<String>();
+        return Some(truncated + "…");
+    }
+    Some(line.to_string())
+}
+
+/// The properties of an object `schema` describes, including those of its
+/// branches, with whether each is required. Required ones come first.
+fn properties<'a>(root: &'a Value, schema: &'a Value) -> Vec<(&'a str, bool, &'a Value)> {
+    let mut properties = Vec::new();
+    let mut required = Vec::new();
+    for schema in
+        std::iter::once(schema).chain(branches(schema).map(|branch| resolve(root, branch)))
+    {
+        if let Some(names) = schema.get("required").and_then(Value::as_array) {
+            required.extend(names.iter().filter_map(Value::as_str));
+        }
+        let Some(own) = schema.get("properties").and_then(Value::as_object) else {
+            continue;
+        };
+        for (name, property) in own {
+            if !properties.iter().any(|(existing, _)| *existing == name) {
+                properties.push((name.as_str(), property));
+            }
+        }
+    }
+    let mut properties = properties
+        .into_iter()
+        .map(|(name, property)| (name, required.contains(&name), property))
+        .collect::<Vec<_>>();
+    properties.sort_by_key(|(_, required, _)| !required);
+    properties
+}
+
+/// The keys leading to a value, like `jobs.build.steps[]`.
+fn path_label(segments: &[PathSegment]) -> String {
+    if segments.is_empty() {
+        return "the top level".to_string();
+    }
+    let mut label = String::new();
+    for segment in segments {
+        match segment {
+            PathSegment::Key(key) => {
+                if !label.is_empty() {
+                    label.push('.');
+                }
+                label.push_str(key);
+            }
+            PathSegment::Item => label.push_str("[]"),
+        }
+    }
+    format!("`{label}`")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use PathSegment::{Item, Key};
+
+    fn key(key: &str) -> PathSegment {
+        Key(key.to_string())
+    }
+
+    #[test]
+    fn test_cursor_path() {
+        let json = "{\n  \"name\": \"app\",\n  \"scripts\": {\n    \"build\": \"tsc\",\n    \"";
+        assert_eq!(json_path(json), [key("scripts")]);
+        let json = "{ \"compilerOptions\": { \"lib\": [\"dom\", ";
+        assert_eq!(json_path(json), [key("compilerOptions"), key("lib"), Item]);
+        // Finished values and comments don't count.
+        let json = "{ // \"a\": {\n \"nested\": { \"done\": {} }, \"target\": ";
+        assert_eq!(json_path(json), [key("target")]);
+
+        let yaml = "\
+on: push
+jobs:
+  build:
+    runs-on: ubuntu-latest
+    steps:
+      - uses: actions/checkout@v4
+      - name: Test
+        ";
+        assert_eq!(
+            yaml_path(yaml),
+            [key("jobs"), key("build"), key("steps"), Item]
+        );
+        assert_eq!(
+            yaml_path("jobs:\n  build:\n    runs-on: "),
+            [key("jobs"), key("build"), key("runs-on")]
+        );
+        assert_eq!(yaml_path("jobs:\n  build: {}\non:\n  "), [key("on")]);
+
+        let toml = "[package]\nname = \"app\"\n\n[dependencies]\nserde = { version = ";
+        assert_eq!(toml_path(toml), [key("dependencies"), key("serde")]);
+        assert_eq!(toml_path("[[bin]]\n"), [key("bin"), Item]);
+        assert_eq!(
+            toml_path("[profile.release]\nopt-level = "),
+            [key("profile"), key("release"), key("opt-level")]
+        );
+    }
+
+    #[test]
+    fn test_schema_sources() {
+        assert!(file_matches("package.json", Path::new("web/package.json")));
+        assert!(!file_matches("package.json", Path::new("mypackage.json")));
+        assert!(file_matches(
+            ".github/workflows/*.yml",
+            Path::new(".github/workflows/ci.yml")
+        ));
+        assert!(file_matches(
+            "**/*.config.json",
+            Path::new("a/b.config.json")
+        ));
+
+        assert_eq!(
+            known_source(Path::new("crates/app/Cargo.toml")),
+            Some(SchemaSource::Url(
+                "https://json.schemastore.org/cargo.json".into()
+            ))
+        );
+        assert_eq!(
+            ConfigFormat::Yaml.directive("# yaml-language-server: $schema=../schema.json\n"),
+            Some("../schema.json")
+        );
+        let root = Path::new("/project");
+        let disabled_paths = PathMatcher::new(&["**/secrets/**".to_string()]).unwrap();
+        assert_eq!(
+            schema_source("../schema.json", Path::new("config"), root, &disabled_paths),
+            Some(SchemaSource::Path(PathBuf::from("/project/schema.json")))
+        );
+        assert_eq!(
+            schema_source(
+                "file:///project/schemas/app.json",
+                Path::new("config"),
+                root,
+                &disabled_paths
+            ),
+            Some(SchemaSource::Path(PathBuf::from(
+                "/project/schemas/app.json"
+            )))
+        );
+        // Local files outside the worktree, or that must never be sent, aren't
+        // read.
+        for reference in [
+            "../../schema.json",
+            "/etc/passwd",
+            "file:///home/me/.ssh/config",
+            "secrets/keys.json",
+        ] {
+            assert_eq!(
+                schema_source(reference, Path::new("config"), root, &disabled_paths),
+                None,
+                "{reference}"
+            );
+        }
+
+        let settings: ProjectSettings = serde_json::from_value(json!({
+            "lsp": {
+                "json-language-server": {
+                    "settings": {
+                        "json": {
+                            "schemas": [
+                                { "fileMatch": ["*.app.json"], "url": "schemas/app.json" },
+                                { "fileMatch": ["other.json"], "schema": { "type": "object" } }
+                            ]
+                        }
+                    }
+                },
+                "yaml-language-server": {
+                    "settings": {
+                        "yaml": { "schemas": { "https://example.com/deploy.json": "deploy/*.yml" } }
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        assert_eq!(
+            configured_source(
+                ConfigFormat::Json,
+                &settings,
+                Path::new("config/main.app.json"),
+                root,
+                &disabled_paths
+            ),
+            Some(SchemaSource::Path(PathBuf::from(
+                "/project/schemas/app.json"
+            )))
+        );
+        assert_eq!(
+            configured_source(
+                ConfigFormat::Json,
+                &settings,
+                Path::new("other.json"),
+                root,
+                &disabled_paths
+            ),
+            Some(SchemaSource::Inline(Arc::new(json!({ "type": "object" }))))
+        );
+        assert_eq!(
+            configured_source(
+                ConfigFormat::Yaml,
+                &settings,
+                Path::new("deploy/prod.yml"),
+                root,
+                &disabled_paths
+            ),
+            Some(SchemaSource::Url("https://example.com/deploy.json".into()))
+        );
+        assert_eq!(
+            configured_source(
+                ConfigFormat::Yaml,
+                &settings,
+                Path::new("ci.yml"),
+                root,
+                &disabled_paths
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_describe_at() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "description": "The package's name." },
+                "dependencies": {
+                    "type": "object",
+                    "additionalProperties": { "$ref": "#/definitions/dependency" }
+                }
+            },
+            "required": ["name"],
+            "definitions": {
+                "dependency": {
+                    "anyOf": [
+                        { "type": "string", "description": "A version requirement." },
+                        {
+                            "type": "object",
+                            "properties": {
+                                "version": { "type": "string" },
+                                "edition": { "enum": ["2015", "2018", "2021"] },
+                                "features": { "type": "array", "items": { "type": "string" } }
+                            },
+                            "required": ["version"]
+                        }
+                    ]
+                }
+            }
+        });
+
+        assert_eq!(
+            describe_at(&schema, &[key("dependencies"), key("serde")], "# ", 256),
+            "\
+# Schema for `dependencies.serde`:
+# Type: string | object
+# Properties:
+#   version (required): string
+#   edition: \"2015\", \"2018\", \"2021\"
+#   features: array
+"
+        );
+        assert_eq!(
+            describe_at(&schema, &[], "// ", 256),
+            "\
+// Schema for the top level:
+// Type: object
+// Properties:
+//   name (required): string - The package's name.
+//   dependencies: object
+"
+        );
+        assert_eq!(
+            describe_at(
+                &schema,
+                &[key("dependencies"), key("serde"), key("edition")],
+                "# ",
+                256
+            ),
+            "# Schema for `dependencies.serde.edition`:\n# One of: \"2015\", \"2018\", \"2021\"\n"
+        );
+        // What doesn't fit is left out, least important first.
+        assert_eq!(
+            describe_at(&schema, &[], "// ", 20),
+            "// Schema for the top level:\n// Type: object\n"
+        );
+        // Unknown keys have no schema.
+        assert_eq!(describe_at(&schema, &[key("unknown")], "# ", 256), "");
+        assert_eq!(describe_at(&schema, &[key("$schema")], "# ", 256), "");
+    }
+}
@@ -0,0 +1,1273 @@
+use crate::{builtin_stop, EndpointPool, Postprocessor, SecretRedactor};
+use anyhow::{anyhow, Result};
+use gpui::AppContext;
+use language::{language_settings::InlineCompletionProvider, File};
+use ollama::{hosted_model_name, is_cloud_url, resolve_api_key, ChatOptions, KeepAlive};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::{Settings, SettingsLocation, SettingsSources};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use util::{paths::PathMatcher, ResultExt as _};
+
+/// Settings for inline completions served by a local Ollama model.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct OllamaSettings {
+    pub enabled: bool,
+    pub disabled_globs: Vec<String>,
+    pub api_url: String,
+    pub api_urls: Vec<String>,
+    pub api_key: Option<String>,
+    pub api_format: ApiFormat,
+    pub local_server_for_remote_projects: bool,
+    pub model: String,
+    pub comparison_model: Option<String>,
+    pub comparison_strategy: ComparisonStrategy,
+    pub speculative_grace_ms: u64,
+    pub verbatim_check_min_chars: usize,
+    pub verbatim_suggestions: VerbatimSuggestions,
+    pub verbatim_similarity: f32,
+    pub max_request_attempts: u32,
+    pub completion_timeout_secs: u64,
+    pub max_concurrent_requests: usize,
+    pub offline_fallback: bool,
+    pub completion_cache_entries: usize,
+    pub fallback_providers: Vec<InlineCompletionProvider>,
+    pub ca_bundle_path: Option<PathBuf>,
+    pub accept_invalid_certs: bool,
+    pub proxy: Option<String>,
+    pub report_completion_events: bool,
+    pub report_performance_metrics: bool,
+    pub show_generation_speed: bool,
+    pub debounce_min_ms: u64,
+    pub debounce_max_ms: u64,
+    pub completion_mode: CompletionMode,
+    pub max_suggestion_lines: usize,
+    pub preview_min_lines: usize,
+    pub continue_truncated: bool,
+    pub stream_suggestions: bool,
+    pub chain_after_accept: bool,
+    pub prefetch_suggestions: bool,
+    pub languages: HashMap<String, OllamaLanguageSettings>,
+    pub prose_languages: Vec<String>,
+    pub prose_model: Option<String>,
+    pub prose_prompt_template: Option<String>,
+    pub warm_up: bool,
+    pub heartbeat_secs: u64,
+    pub low_power_mode: LowPowerMode,
+    pub completion_trigger: CompletionTrigger,
+    pub skip_mid_identifier: bool,
+    pub min_prefix_chars: usize,
+    pub min_prefix_scope: PrefixScope,
+    pub num_ctx: Option<usize>,
+    pub num_gpu: Option<u32>,
+    pub num_thread: Option<u32>,
+    pub mirostat: Option<u8>,
+    pub mirostat_eta: Option<f32>,
+    pub mirostat_tau: Option<f32>,
+    pub repeat_last_n: Option<i32>,
+    pub penalize_newline: Option<bool>,
+    pub completion_options: OllamaSamplingOptions,
+    pub temperature_schedule: TemperatureSchedule,
+    pub chat_options: OllamaSamplingOptions,
+    pub profiles: HashMap<String, OllamaModelProfile>,
+    pub recent_edits_tokens: usize,
+    pub edit_memory_tokens: usize,
+    pub edit_memory_model: String,
+    pub edit_memory_snippets: usize,
+    pub git_diff_tokens: usize,
+    pub file_header_tokens: usize,
+    pub imported_signatures_tokens: usize,
+    pub schema_context_tokens: usize,
+    pub extension_context_tokens: usize,
+    pub extension_context_timeout_ms: u64,
+    pub max_prefix_chars: usize,
+    pub max_suffix_chars: usize,
+    pub prompt_budget: Option<PromptBudget>,
+    pub include_diagnostics: bool,
+    pub redact_secrets: bool,
+    pub redaction_patterns: Vec<String>,
+    pub edit_prediction: bool,
+    pub terminal_suggestions: bool,
+    pub commit_message_model: Option<String>,
+    pub commit_message_prompt: String,
+    pub rerank_model: Option<String>,
+    pub chat_project_layout_tokens: usize,
+    pub slash_commands: HashMap<String, OllamaSlashCommand>,
+    pub system_prompt: Option<String>,
+    pub system_prompts: HashMap<String, String>,
+    pub prompt_template: Option<String>,
+    pub prompt_templates: HashMap<String, String>,
+    pub raw: bool,
+    pub postprocessors: Vec<Postprocessor>,
+    pub postprocessors_by_model: HashMap<String, Vec<Postprocessor>>,
+    pub format_accepted: AcceptedFormatting,
+}
+
+/// How much text a single inline completion may span.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CompletionMode {
+    /// Complete a single line when the cursor is in the middle of a statement,
+    /// and whole blocks otherwise.
+    #[default]
+    Auto,
+    /// Stop every completion at the end of the line.
+    SingleLine,
+    /// Allow completions spanning several lines.
+    MultiLine,
+}
+
+/// When inline completions are only requested on demand, to save power.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LowPowerMode {
+    /// While the machine runs on battery or in the system's low power mode.
+    #[default]
+    Auto,
+    /// Always.
+    On,
+    /// Never.
+    Off,
+}
+
+/// What requests an inline completion.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CompletionTrigger {
+    /// Typing, after a short pause.
+    #[default]
+    Automatic,
+    /// Only `ollama: request completion` or `editor: show inline completion`.
+    Manual,
+}
+
+/// Where the text counted by `min_prefix_chars` is taken from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PrefixScope {
+    /// Everything before the cursor.
+    #[default]
+    File,
+    /// The cursor's line, up to the cursor.
+    Line,
+}
+
+/// How completions are split between `model` and `comparison_model`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ComparisonStrategy {
+    /// Take turns, sending every other request to each model.
+    #[default]
+    Alternate,
+    /// Send every request to both models and show whichever answers first
+    /// with a usable suggestion, cancelling the other request.
+    Race,
+    /// Send every request to both models, showing `comparison_model`'s answer
+    /// as a draft as soon as it arrives. `model`'s answer replaces it if it
+    /// arrives within `speculative_grace_ms` and says something different.
+    Speculative,
+}
+
+/// What happens to a suggestion that mostly repeats a file elsewhere in the
+/// project.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum VerbatimSuggestions {
+    /// Show it, with the file it was copied from next to it.
+    #[default]
+    Annotate,
+    /// Hide it, for projects whose license headers or generated code a model
+    /// tends to reproduce.
+    Suppress,
+}
+
+/// What's done to a suggestion's text once it's accepted into the buffer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AcceptedFormatting {
+    /// Nothing; the suggestion is inserted as the model wrote it.
+    #[default]
+    Off,
+    /// Reindent the inserted lines following the language's rules.
+    Indent,
+    /// Run the buffer's formatter over the inserted text.
+    Format,
+}
+
+/// The API spoken by the servers used for inline completions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiFormat {
+    /// Ollama's own API.
+    #[default]
+    Ollama,
+    /// OpenAI's completions API, as served by vLLM, llama.cpp's server or
+    /// Ollama's compatibility layer.
+    #[serde(rename = "openai")]
+    OpenAi,
+}
+
+/// A named model and the runtime options to run it with.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct OllamaModelProfile {
+    /// The name of the model in Ollama, e.g. "qwen2.5-coder:7b".
+    pub model: String,
+    /// Overrides `num_ctx`.
+    pub num_ctx: Option<usize>,
+    /// Overrides `num_gpu`.
+    pub num_gpu: Option<u32>,
+    /// Overrides `num_thread`.
+    pub num_thread: Option<u32>,
+    /// Overrides `mirostat`.
+    pub mirostat: Option<u8>,
+    /// Overrides `mirostat_eta`.
+    pub mirostat_eta: Option<f32>,
+    /// Overrides `mirostat_tau`.
+    pub mirostat_tau: Option<f32>,
+    /// Overrides `repeat_last_n`.
+    pub repeat_last_n: Option<i32>,
+    /// Overrides `penalize_newline`.
+    pub penalize_newline: Option<bool>,
+    /// Overrides `completion_options`, option by option.
+    pub completion_options: Option<OllamaSamplingOptions>,
+    /// Overrides `chat_options`, option by option.
+    pub chat_options: Option<OllamaSamplingOptions>,
+    /// How long the model stays loaded after a request, in seconds or as a
+    /// duration like "10m". When unset, it stays loaded until another model
+    /// replaces it.
+    pub keep_alive: Option<KeepAlive>,
+}
+
+/// How a model samples its output, for one kind of request. See
+/// `completion_options` and `chat_options`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct OllamaSamplingOptions {
+    /// How random the output is. Lower values give more predictable text.
+    pub temperature: Option<f32>,
+    /// Only samples from the most likely tokens whose probabilities add up
+    /// to this.
+    pub top_p: Option<f32>,
+    /// The most tokens generated.
+    pub num_predict: Option<isize>,
+    /// How strongly recently generated tokens are penalized when repeated,
+    /// discouraging the model from reproducing long blocks like license
+    /// headers. 1.0 is no penalty.
+    pub repeat_penalty: Option<f32>,
+    /// Penalizes tokens that already appeared in the output, however often.
+    pub presence_penalty: Option<f32>,
+    /// Penalizes tokens by how often they already appeared in the output.
+    pub frequency_penalty: Option<f32>,
+}
+
+/// A slash command for the assistant panel whose prompt is sent to Ollama.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct OllamaSlashCommand {
+    /// Shown next to the command's name in the assistant panel.
+    pub description: Option<String>,
+    /// The prompt sent to the model. `{arguments}` is replaced with the text
+    /// typed after the command, `{path}` and `{file}` with the path and
+    /// contents of the active file, `{selection}` with the text selected in
+    /// it, and `{diff}` with the changes staged in its repository.
+    pub prompt: String,
+    /// The model or profile the prompt is sent to. When unset, `model` is
+    /// used.
+    pub model: Option<String>,
+    /// Replaces the system message defined in the model's Modelfile.
+    pub system: Option<String>,
+    /// Overrides the model's temperature.
+    pub temperature: Option<f32>,
+    /// Overrides the model's `top_p`.
+    pub top_p: Option<f32>,
+    /// The most tokens generated. When unset, the model decides when to stop.
+    pub num_predict: Option<isize>,
+}
+
+/// Temperatures inline completions are sampled at, by where the cursor is on
+/// its line. See `temperature_schedule`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TemperatureSchedule {
+    /// With code after the cursor, where a suggestion has to fit exactly
+    /// between what's on either side.
+    pub mid_line: Option<f32>,
+    /// At the end of a line with code on it, where a suggestion finishes it.
+    pub end_of_line: Option<f32>,
+    /// On a blank line, where a suggestion may be a whole block.
+    pub blank_line: Option<f32>,
+}
+
+/// Relative shares of a completion prompt's token budget. See
+/// `prompt_budget`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PromptBudget {
+    /// The share for the text before the cursor.
+    pub prefix: f32,
+    /// The share for the text after the cursor.
+    pub suffix: f32,
+    /// The share for recent edits, Git changes and diagnostics.
+    pub context: f32,
+}
+
+/// Ollama settings that can be overridden for a single language.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct OllamaLanguageSettings {
+    /// Set to false to turn off Ollama completions for this language, while
+    /// other languages and other completion providers are unaffected.
+    pub enabled: Option<bool>,
+    /// Globs matching files of this language that get no Ollama completions,
+    /// on top of `disabled_globs`, e.g. generated code under `gen/`.
+    #[serde(default)]
+    pub disabled_globs: Vec<String>,
+    /// Overrides `completion_mode` for this language.
+    pub completion_mode: Option<CompletionMode>,
+    /// Overrides `model` for this language.
+    pub model: Option<String>,
+    /// Overrides `skip_mid_identifier` for this language.
+    pub skip_mid_identifier: Option<bool>,
+    /// Overrides `prompt_template` and `prompt_templates` for this language.
+    pub prompt_template: Option<String>,
+    /// Sequences that end completions in this language, such as `"\n---"`
+    /// between YAML documents or `";"` after a SQL statement. They replace
+    /// the ones Zed has built in for the language, which end a suggestion
+    /// before the next top-level declaration; `[]` turns those off.
+    pub stop: Option<Vec<String>>,
+    /// Whether completions in this language's configuration files, such as
+    /// JSON, YAML or TOML, are shown the part of the file's JSON schema that
+    /// describes the value at the cursor, so they suggest valid keys and
+    /// values. Off unless turned on here.
+    pub schema_context: Option<bool>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct OllamaSettingsContent {
+    /// Whether Zed may send any requests to Ollama. Turning this off stops
+    /// inline completions, the assist actions, the assistant's Ollama models
+    /// and background health checks at once.
+    ///
+    /// Default: true
+    pub enabled: Option<bool>,
+    /// Globs matching files whose contents are never sent to Ollama, relative
+    /// to their worktree. Matching files get no inline completions, and edits
+    /// and Git changes to them are left out of other files' prompts.
+    ///
+    /// Default: ["**/.env*", "**/secrets/**", "**/*.pem", "**/*.key"]
+    pub disabled_globs: Option<Vec<String>>,
+    /// The URL of the Ollama server used for inline completions, or of its
+    /// unix socket, like `unix:///run/ollama.sock`. This can be overridden in
+    /// a project's `.zed/settings.json`.
+    ///
+    /// Default: "http://localhost:11434"
+    pub api_url: Option<String>,
+    /// Several Ollama servers to spread inline completions across. Requests go
+    /// to the fastest healthy server and fail over to the others when it stops
+    /// responding. When non-empty, this takes precedence over `api_url`.
+    ///
+    /// Default: []
+    pub api_urls: Option<Vec<String>>,
+    /// A token sent as `Authorization: Bearer <api_key>` with every request,
+    /// for servers behind an authenticating reverse proxy. When unset, the
+    /// `OLLAMA_API_KEY` environment variable is used instead, if present.
+    ///
+    /// Default: null
+    pub api_key: Option<String>,
+    /// The API the servers speak. "openai" sends completions to the
+    /// `/v1/completions` endpoint of OpenAI-compatible servers, such as vLLM
+    /// or llama.cpp's server, instead of Ollama's own API.
+    ///
+    /// Default: "ollama"
+    pub api_format: Option<ApiFormat>,
+    /// Whether files in remote projects, opened over SSH or shared by a
+    /// collaborator, are completed by the servers in your user settings. The
+    /// project's own `api_url`, `api_urls`, `api_key` and `api_format` name
+    /// servers as the remote machine reaches them, but requests are always
+    /// sent from this one.
+    ///
+    /// Default: true
+    pub local_server_for_remote_projects: Option<bool>,
+    /// The model used for inline completions. It should support fill-in-the-middle
+    /// prompts, such as "qwen2.5-coder" or "codellama:code". A model set in
+    /// project settings applies unless the user settings set one too.
+    ///
+    /// Default: "qwen2.5-coder:1.5b"
+    pub model: Option<String>,
+    /// A second model to compare with `model`. Inline completions are split
+    /// between the two following `comparison_strategy`, and `ollama: show
+    /// completion stats` reports how often each one's suggestions are
+    /// accepted and how fast it responds. Languages with their own model
+    /// aren't part of the comparison.
+    ///
+    /// Default: null
+    pub comparison_model: Option<String>,
+    /// How inline completions are split between `model` and
+    /// `comparison_model`: "alternate" to take turns, "race" to ask both
+    /// and show the first usable answer, or "speculative" to show
+    /// `comparison_model`'s answer as a draft until `model`'s arrives.
+    ///
+    /// Default: "alternate"
+    pub comparison_strategy: Option<ComparisonStrategy>,
+    /// With the "speculative" `comparison_strategy`, how long after the draft
+    /// is shown `model` may still replace it. Later answers are dropped.
+    ///
+    /// Default: 1000
+    pub speculative_grace_ms: Option<u64>,
+    /// Suggestions at least this many characters long are checked against the
+    /// rest of the project for text copied from another file, and annotated
+    /// or hidden as `verbatim_suggestions` says. Set to 0 to disable the
+    /// check.
+    ///
+    /// Default: 200
+    pub verbatim_check_min_chars: Option<usize>,
+    /// What happens to a suggestion that repeats a file elsewhere in the
+    /// project: "annotate" to show it with the file it was found in, or
+    /// "suppress" to hide it.
+    ///
+    /// Default: "annotate"
+    pub verbatim_suggestions: Option<VerbatimSuggestions>,
+    /// The share of a suggestion's lines, ignoring indentation and lines
+    /// without words, that must appear in another file for the suggestion to
+    /// count as copied from it. 1.0 only catches exact copies.
+    ///
+    /// Default: 0.8
+    pub verbatim_similarity: Option<f32>,
+    /// How many times a completion request is attempted when the server can't
+    /// be reached or responds with a server error, before giving up.
+    ///
+    /// Default: 3
+    pub max_request_attempts: Option<u32>,
+    /// How long to wait for an inline completion before giving up on it.
+    /// Suggestions that time out are discarded silently.
+    ///
+    /// Default: 10
+    pub completion_timeout_secs: Option<u64>,
+    /// How many requests to send to each server at once. Further requests
+    /// wait their turn, with the assistant, completions requested with a
+    /// keystroke and other explicit requests first, then completions as you
+    /// type, then prefetched completions, so the server doesn't have to
+    /// juggle too many of them. Match it to `OLLAMA_NUM_PARALLEL` on the server.
+    ///
+    /// Default: 2
+    pub max_concurrent_requests: Option<usize>,
+    /// Whether to keep suggesting while the server is unreachable, using earlier
+    /// suggestions for the same code or simple guesses from the surrounding
+    /// text. These are marked as not coming from the model.
+    ///
+    /// Default: false
+    pub offline_fallback: Option<bool>,
+    /// How many suggestions to keep in Zed's local database, so the same
+    /// prompt isn't sent to the model again after a restart. Entries are
+    /// keyed by a hash of the prompt, and only the suggestion is stored.
+    /// `0` turns the cache off.
+    ///
+    /// Default: 500
+    pub completion_cache_entries: Option<usize>,
+    /// Other inline completion providers to ask, in order, when Ollama has no
+    /// suggestion or its server is unreachable, e.g. `["copilot"]`. Each one
+    /// is only used while it's signed in and enabled for the file.
+    ///
+    /// Default: []
+    pub fallback_providers: Option<Vec<InlineCompletionProvider>>,
+    /// A PEM file of certificates to trust, in addition to the system's, when
+    /// connecting to an `https://` server that uses a private or self-signed CA.
+    ///
+    /// Default: null
+    pub ca_bundle_path: Option<PathBuf>,
+    /// Skips TLS certificate verification for Ollama servers. Prefer
+    /// `ca_bundle_path`; this is only meant for servers on a trusted network.
+    ///
+    /// Default: false
+    pub accept_invalid_certs: Option<bool>,
+    /// The proxy to connect to Ollama servers through, overriding the
+    /// editor's `proxy` setting and `HTTPS_PROXY`. Set to "" to connect
+    /// directly. When unset, the editor's proxy is used unless every server
+    /// is on this machine.
+    ///
+    /// Default: null
+    pub proxy: Option<String>,
+    /// Whether accepting or discarding an Ollama suggestion sends a telemetry
+    /// event. Set to false to keep Ollama completions out of telemetry while
+    /// leaving it on for everything else. Only sent when telemetry is enabled.
+    ///
+    /// Default: true
+    pub report_completion_events: Option<bool>,
+    /// Whether accept and discard telemetry for inline completions includes
+    /// the model, request latency, token counts and generation speed. Only
+    /// sent when telemetry is enabled.
+    ///
+    /// Default: false
+    pub report_performance_metrics: Option<bool>,
+    /// Whether the status bar shows how many tokens per second the completion
+    /// model has been generating lately, and how long the last suggestion
+    /// took.
+    ///
+    /// Default: true
+    pub show_generation_speed: Option<bool>,
+    /// The shortest time, in milliseconds, to wait after typing stops before
+    /// requesting a completion. The wait grows with the gaps between your
+    /// keystrokes and with how long the model takes to respond.
+    ///
+    /// Default: 75
+    pub debounce_min_ms: Option<u64>,
+    /// The longest time, in milliseconds, to wait before requesting a
+    /// completion, however slow the model has been.
+    ///
+    /// Default: 1000
+    pub debounce_max_ms: Option<u64>,
+    /// How much text a single inline completion may span: "single_line",
+    /// "multi_line", or "auto" to complete a single line in the middle of a
+    /// statement and whole blocks elsewhere.
+    ///
+    /// Default: "auto"
+    pub completion_mode: Option<CompletionMode>,
+    /// The most lines a multi-line suggestion may span. Longer suggestions
+    /// are cut off. Set to 0 for no limit.
+    ///
+    /// Default: 40
+    pub max_suggestion_lines: Option<usize>,
+    /// How many lines a suggestion must span for a hint to offer previewing
+    /// it with `ollama: preview suggestion`, which inserts it highlighted
+    /// with buttons to keep or discard it. Set to 0 to never show the hint.
+    ///
+    /// Default: 15
+    pub preview_min_lines: Option<usize>,
+    /// Whether to continue a suggestion that the model stopped generating
+    /// because it reached its token limit, with up to two follow-up requests
+    /// whose output is added to the suggestion, so long completions aren't
+    /// cut off mid-statement.
+    ///
+    /// Default: true
+    pub continue_truncated: Option<bool>,
+    /// Whether to show suggestions as the model generates them, rather than
+    /// once they're done. Only plain completions from Ollama's own API are
+    /// streamed; edit predictions, comparison models and OpenAI-compatible
+    /// servers wait for the whole response.
+    ///
+    /// Default: false
+    pub stream_suggestions: Option<bool>,
+    /// Whether to ask for the next suggestion as soon as one is accepted,
+    /// without waiting for a pause in typing, so a function can be written
+    /// by accepting suggestion after suggestion.
+    ///
+    /// Default: false
+    pub chain_after_accept: Option<bool>,
+    /// Whether to ask for suggestions ahead of time, while the cursor rests,
+    /// at the end of its line and on the next blank line below it, so they
+    /// show as soon as the cursor gets there. Never done on battery or in low
+    /// power mode.
+    ///
+    /// Default: false
+    pub prefetch_suggestions: Option<bool>,
+    /// Overrides for specific languages, keyed by language name. Each may set
+    /// its own `completion_mode`, `model` and `skip_mid_identifier`, e.g. a
+    /// small model for Markdown, turn Ollama off with `enabled`, or add
+    /// `disabled_globs` of its own.
+    ///
+    /// Default: {}
+    pub languages: Option<HashMap<String, OllamaLanguageSettings>>,
+    /// Languages written as prose rather than code. Completions in them are
+    /// asked to continue the text instead of the code, and stop at the end
+    /// of a sentence or paragraph.
+    ///
+    /// Default: ["Markdown", "Plain Text"]
+    pub prose_languages: Option<Vec<String>>,
+    /// The model used for prose, when it differs from the completion model.
+    /// A model set for the language under `languages` takes precedence.
+    ///
+    /// Default: null
+    pub prose_model: Option<String>,
+    /// The prompt template for prose, in the same format as
+    /// `prompt_template`. When unset, the completion model's template is
+    /// used.
+    ///
+    /// Default: null
+    pub prose_prompt_template: Option<String>,
+    /// Whether to ask the server to load the completion model as soon as
+    /// Ollama completions are turned on, so the first suggestion doesn't wait
+    /// for the model to load.
+    ///
+    /// Default: true
+    pub warm_up: Option<bool>,
+    /// How often, in seconds, to ask the server to keep the completion model
+    /// loaded while a Zed window has focus, so the first keystroke after a
+    /// pause doesn't wait for the model to load again. 0 turns this off.
+    ///
+    /// Default: 240
+    pub heartbeat_secs: Option<u64>,
+    /// When to stop requesting completions as you type, to save power.
+    /// Suggestions can still be requested with `editor: show inline
+    /// completion`. "auto" does this while the machine runs on battery or in
+    /// the system's low power mode, "on" always, and "off" never.
+    ///
+    /// Default: "auto"
+    pub low_power_mode: Option<LowPowerMode>,
+    /// Whether completions are requested as you type ("automatic"), or only
+    /// when asked for with `ollama: request completion` or `editor: show
+    /// inline completion` ("manual").
+    ///
+    /// Default: "automatic"
+    pub completion_trigger: Option<CompletionTrigger>,
+    /// Whether to hold back completions as you type while the cursor is in
+    /// the middle of an identifier, where a suggestion would split it.
+    /// Explicitly requested completions are unaffected. To also skip strings
+    /// or comments, use the language setting `inline_completions_disabled_in`.
+    ///
+    /// Default: true
+    pub skip_mid_identifier: Option<bool>,
+    /// How many non-whitespace characters there must be before the cursor
+    /// before completions are requested as you type, so empty files and
+    /// buffers that were just opened don't get suggestions out of nowhere.
+    /// Explicitly requested completions are unaffected. 0 requests them
+    /// anywhere.
+    ///
+    /// Default: 1
+    pub min_prefix_chars: Option<usize>,
+    /// Where `min_prefix_chars` are counted: everything before the cursor
+    /// ("file"), or only the cursor's line ("line").
+    ///
+    /// Default: "file"
+    pub min_prefix_scope: Option<PrefixScope>,
+    /// The size of the completion model's context window, in tokens. Smaller
+    /// windows use less memory. Prompts are trimmed to fit it. When unset, the
+    /// model's own default is used.
+    ///
+    /// Default: null
+    pub num_ctx: Option<usize>,
+    /// How many of the completion model's layers to load onto the GPU. Set to
+    /// 0 to run it on the CPU only. When unset, Ollama decides.
+    ///
+    /// Default: null
+    pub num_gpu: Option<u32>,
+    /// How many CPU threads the completion model may use. When unset, Ollama
+    /// decides.
+    ///
+    /// Default: null
+    pub num_thread: Option<u32>,
+    /// Whether the completion model samples with Mirostat, which keeps the
+    /// output's perplexity steady: 0 turns it off, 1 uses Mirostat and 2
+    /// Mirostat 2.0. When unset, the model's Modelfile decides.
+    ///
+    /// Default: null
+    pub mirostat: Option<u8>,
+    /// How quickly Mirostat adapts to the generated text. Lower values make
+    /// it adjust more slowly.
+    ///
+    /// Default: null
+    pub mirostat_eta: Option<f32>,
+    /// The perplexity Mirostat aims for. Lower values give more focused and
+    /// coherent text.
+    ///
+    /// Default: null
+    pub mirostat_tau: Option<f32>,
+    /// How many of the latest tokens the completion model looks at to avoid
+    /// repeating itself. 0 turns this off and -1 uses the whole context
+    /// window.
+    ///
+    /// Default: null
+    pub repeat_last_n: Option<i32>,
+    /// Whether line breaks count as repetition. Turning this off keeps the
+    /// model from avoiding new lines in code.
+    ///
+    /// Default: null
+    pub penalize_newline: Option<bool>,
+    /// How inline completions are sampled, e.g. `{ "temperature": 0.1 }`.
+    /// Unset options keep their defaults: a temperature from
+    /// `temperature_schedule`, which keeps suggestions close to what's most
+    /// likely, and at most 128 tokens.
+    ///
+    /// Default: {}
+    pub completion_options: Option<OllamaSamplingOptions>,
+    /// The temperature inline completions are sampled at when neither
+    /// `completion_options` nor the model's profile sets one, by where the
+    /// cursor is: `mid_line` with code after it, `end_of_line` after code,
+    /// and `blank_line` on an empty line. Unset positions use 0.2.
+    ///
+    /// Default: { "mid_line": 0.1, "end_of_line": 0.2, "blank_line": 0.4 }
+    pub temperature_schedule: Option<TemperatureSchedule>,
+    /// How the assistant, chats and slash commands are sampled, e.g.
+    /// `{ "temperature": 0.7 }`. Unset options are left to the model's
+    /// Modelfile, except the assistant's temperature, which is 1.0.
+    ///
+    /// Default: {}
+    pub chat_options: Option<OllamaSamplingOptions>,
+    /// Named models with their own runtime options, e.g. "fast" and "smart".
+    /// `model`, a language's `model`, `commit_message_model` and the
+    /// assistant can refer to a profile by name instead of naming a model, so
+    /// switching models only takes changing the profile.
+    ///
+    /// Default: {}
+    pub profiles: Option<HashMap<String, OllamaModelProfile>>,
+    /// How many tokens of the prompt may summarize your most recent edits,
+    /// across all open files, to help the model predict the next one. Set to
+    /// 0 to leave them out.
+    ///
+    /// Default: 256
+    pub recent_edits_tokens: Option<usize>,
+    /// How many tokens of the prompt may show code you edited earlier in the
+    /// session that's like the code before the cursor, found by comparing
+    /// their embeddings. This sends an embedding request with `/api/embed`
+    /// along with each completion request. Set to 0 to leave it out.
+    ///
+    /// Default: 0
+    pub edit_memory_tokens: Option<usize>,
+    /// The embedding model edited code and the code before the cursor are
+    /// compared with when `edit_memory_tokens` is set.
+    ///
+    /// Default: "nomic-embed-text"
+    pub edit_memory_model: Option<String>,
+    /// How many of the most similar snippets of edited code the prompt may
+    /// show when `edit_memory_tokens` is set.
+    ///
+    /// Default: 3
+    pub edit_memory_snippets: Option<usize>,
+    /// How many tokens of the prompt may show the current file's unstaged
+    /// Git changes and list the other changed files in its worktree, so
+    /// completions follow a refactor in progress. This sends the names of
+    /// those files to the server. Set to 0 to leave them out.
+    ///
+    /// Default: 0
+    pub git_diff_tokens: Option<usize>,
+    /// How many tokens of the prompt may show the file's imports and the
+    /// first line of its top-level declarations, when the cursor is too far
+    /// down for them to be in the text sent around it. Set to 0 to leave
+    /// them out.
+    ///
+    /// Default: 256
+    pub file_header_tokens: Option<usize>,
+    /// How many tokens of the prompt may show the public signatures of the
+    /// project's files the current file imports, so the model knows the
+    /// APIs about to be called. Set to 0 to leave them out.
+    ///
+    /// Default: 256
+    pub imported_signatures_tokens: Option<usize>,
+    /// How many tokens of the prompt may describe the keys and values the
+    /// file's JSON schema allows at the cursor, in languages with
+    /// `schema_context` on. Set to 0 to leave it out everywhere.
+    ///
+    /// Default: 256
+    pub schema_context_tokens: Option<usize>,
+    /// How many tokens of the prompt may come from extensions that provide
+    /// completion context, such as a framework's conventions or a schema the
+    /// file refers to. Set to 0 to leave it out.
+    ///
+    /// Default: 512
+    pub extension_context_tokens: Option<usize>,
+    /// How long extensions may take to provide completion context, in
+    /// milliseconds. Those that take longer are left out of the request.
+    ///
+    /// Default: 250
+    pub extension_context_timeout_ms: Option<u64>,
+    /// How many bytes of text before the cursor are sent with a completion
+    /// request, at most. Less is sent when it doesn't fit the model's context
+    /// window.
+    ///
+    /// Default: 4096
+    pub max_prefix_chars: Option<usize>,
+    /// How many bytes of text after the cursor are sent with a completion
+    /// request, at most.
+    ///
+    /// Default: 1024
+    pub max_suffix_chars: Option<usize>,
+    /// How the prompt's tokens are shared between the text before the cursor,
+    /// the text after it and the extra context (recent edits, Git changes and
+    /// diagnostics), e.g. `{ "prefix": 70, "suffix": 20, "context": 10 }`.
+    /// Only the proportions matter. Whatever one part doesn't need goes to
+    /// the others. When unset, the extra context is included whole and the
+    /// rest is split between the prefix and suffix by their lengths.
+    ///
+    /// Default: null
+    pub prompt_budget: Option<PromptBudget>,
+    /// Whether to include the errors and warnings on the cursor's line in
+    /// the prompt, so the model suggests a fix for them.
+    ///
+    /// Default: true
+    pub include_diagnostics: Option<bool>,
+    /// Whether to replace credentials in prompts with `[REDACTED]` before
+    /// they're sent, be they for completions, chats, rewrites or the terminal, so they don't end up in the server's
+    /// logs. Well-known token formats, quoted values assigned to names like
+    /// `password`, and long random-looking strings are replaced.
+    ///
+    /// Default: true
+    pub redact_secrets: Option<bool>,
+    /// Regular expressions matching more text to redact, when
+    /// `redact_secrets` is on. A pattern with a group named `secret` only has
+    /// that group replaced.
+    ///
+    /// Default: []
+    pub redaction_patterns: Option<Vec<String>>,
+    /// Whether to ask the model for the next edit you're likely to make,
+    /// which may replace text or be away from the cursor, instead of text to
+    /// insert at the cursor. This works best with instruction-tuned models
+    /// that support structured output.
+    ///
+    /// Default: false
+    pub edit_prediction: Option<bool>,
+    /// Whether to suggest how to finish the command being typed in the
+    /// terminal. The suggestion is based on the terminal's recent output,
+    /// which can contain secrets, so this is off unless turned on here.
+    ///
+    /// Default: false
+    pub terminal_suggestions: Option<bool>,
+    /// The model used by `ollama: generate commit message`. When unset, the
+    /// inline completion model is used.
+    ///
+    /// Default: null
+    pub commit_message_model: Option<String>,
+    /// The instructions sent along with the staged diff when generating a
+    /// commit message.
+    ///
+    /// Default: "Write a Git commit message for the following diff: ..."
+    pub commit_message_prompt: Option<String>,
+    /// The model that re-scores semantic search results, such as those of the
+    /// assistant's `/search` command, before the best ones are used. A model
+    /// served from `/api/rerank` scores them all at once; any other model is
+    /// asked to rate each result. When unset, results are used as found.
+    ///
+    /// Default: null
+    pub rerank_model: Option<String>,
+    /// How many tokens of a new chat's system message may outline the
+    /// project's files and say which file the conversation started from, so
+    /// the model can answer questions about where code belongs. Ignored
+    /// files and those matching `disabled_globs` are left out, and deeper
+    /// files are the first to go when the project doesn't fit. This sends
+    /// the names of the project's files to the server. Set to 0 to leave it
+    /// out.
+    ///
+    /// Default: 0
+    pub chat_project_layout_tokens: Option<usize>,
+    /// Slash commands for the assistant panel, keyed by name, that send a
+    /// prompt to Ollama and stream the model's answer into the conversation,
+    /// e.g. `"summarize-file": { "prompt": "Summarize {path}:\n{file}" }`.
+    ///
+    /// Default: {}
+    pub slash_commands: Option<HashMap<String, OllamaSlashCommand>>,
+    /// A system message sent with every inline completion request, replacing
+    /// the one in the model's Modelfile, e.g. "You are a code completion
+    /// engine. Output only code, without explanations." This keeps
+    /// chat-tuned models from answering in prose.
+    ///
+    /// Default: null
+    pub system_prompt: Option<String>,
+    /// System messages for specific models, keyed by model name, overriding
+    /// `system_prompt`.
+    ///
+    /// Default: {}
+    pub system_prompts: Option<HashMap<String, String>>,
+    /// The whole prompt of inline completion requests, sent as is instead of
+    /// using the model's fill-in-the-middle template. `{prefix}` and
+    /// `{suffix}` are replaced with the text before and after the cursor,
+    /// `{context}` with recent edits and Git changes, and `{language}` and
+    /// `{path}` with the file's language and path. The template must include
+    /// the model's special tokens, e.g.
+    /// "<|fim_prefix|>{context}{prefix}<|fim_suffix|>{suffix}<|fim_middle|>".
+    ///
+    /// Default: null
+    pub prompt_template: Option<String>,
+    /// Prompt templates for specific models, keyed by model name, overriding
+    /// `prompt_template`.
+    ///
+    /// Default: {}
+    pub prompt_templates: Option<HashMap<String, String>>,
+    /// Whether to send completion prompts without the model's template, as
+    /// plain text for the model to continue. Meant for base models that
+    /// weren't trained for fill-in-the-middle: the text after the cursor
+    /// and the system prompt aren't sent. Prompt templates are always sent
+    /// this way.
+    ///
+    /// Default: false
+    pub raw: Option<bool>,
+    /// The steps that clean up a suggestion before it's shown, in the order
+    /// they run: "strip_special_tokens", "strip_code_fence",
+    /// "trim_whitespace", "trim_suffix_overlap", "clamp_brackets",
+    /// "truncate_lines" and "reindent". Leave a step out to skip it.
+    /// "end_at_sentence" ends the suggestion with its first sentence, and is
+    /// always added for prose.
+    ///
+    /// Default: ["strip_special_tokens", "strip_code_fence", "trim_whitespace", "trim_suffix_overlap", "clamp_brackets", "truncate_lines", "reindent"]
+    pub postprocessors: Option<Vec<Postprocessor>>,
+    /// Postprocessors for specific models, keyed by model name, replacing
+    /// `postprocessors`.
+    ///
+    /// Default: {}
+    pub postprocessors_by_model: Option<HashMap<String, Vec<Postprocessor>>>,
+    /// What to do with an accepted suggestion once it's inserted: "off" to
+    /// leave it as is, "indent" to reindent its lines, or "format" to run
+    /// the language's formatter over it.
+    ///
+    /// Default: off
+    pub format_accepted: Option<AcceptedFormatting>,
+}
+
+impl OllamaSettings {
+    /// Returns the settings that apply to the given file, including any
+    /// overrides from the settings of the project it belongs to.
+    pub fn for_file<'a>(file: Option<&Arc<dyn File>>, cx: &'a AppContext) -> &'a Self {
+        let location = file.map(|f| SettingsLocation {
+            worktree_id: f.worktree_id(cx),
+            path: f.path().as_ref(),
+        });
+        Self::get(location, cx)
+    }
+
+    /// The settings naming the servers that completions in `file` are sent
+    /// to, along with their API key, format, TLS and proxy options. These are the
+    /// file's own settings, except for files in remote projects, which use
+    /// the user's unless `local_server_for_remote_projects` is off.
+    pub fn server_for_file<'a>(file: Option<&Arc<dyn File>>, cx: &'a AppContext) -> &'a Self {
+        let settings = Self::get_global(cx);
+        let is_remote = file.map_or(false, |file| file.as_local().is_none());
+        if is_remote && settings.local_server_for_remote_projects {
+            settings
+        } else {
+            Self::for_file(file, cx)
+        }
+    }
+
+    /// Matches the paths of files that must never be sent to the server.
+    /// Invalid globs are logged and ignored.
+    pub fn disabled_paths(&self) -> PathMatcher {
+        let globs = self
+            .disabled_globs
+            .iter()
+            .filter(|glob| {
+                PathMatcher::new(&[glob.to_string()])
+                    .map_err(|error| anyhow!("invalid glob {glob:?}: {error}"))
+                    .log_err()
+                    .is_some()
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        PathMatcher::new(&globs).unwrap_or_default()
+    }
+
+    /// The redactor for prompts, if secrets are to be redacted.
+    pub fn secret_redactor(&self) -> Option<SecretRedactor> {
+        self.redact_secrets
+            .then(|| SecretRedactor::new(&self.redaction_patterns))
+    }
+
+    /// Whether the file at `path`, relative to its worktree, must never be
+    /// sent to the server.
+    pub fn is_path_disabled(&self, path: &Path) -> bool {
+        self.disabled_paths().is_match(path)
+    }
+
+    /// Whether inline completions may be requested for a file at `path` in
+    /// `language`, taking the language's own overrides into account.
+    pub fn completions_enabled_for(&self, path: Option<&Path>, language: Option<&str>) -> bool {
+        if !self.enabled || path.map_or(false, |path| self.is_path_disabled(path)) {
+            return false;
+        }
+        let Some(overrides) = language.and_then(|language| self.languages.get(language)) else {
+            return true;
+        };
+        if overrides.enabled == Some(false) {
+            return false;
+        }
+        match path {
+            Some(path) if !overrides.disabled_globs.is_empty() => {
+                PathMatcher::new(&overrides.disabled_globs)
+                    .map_err(|error| anyhow!("invalid glob in {language:?} settings: {error}"))
+                    .log_err()
+                    .map_or(true, |matcher| !matcher.is_match(path))
+            }
+            _ => true,
+        }
+    }
+
+    /// The bearer token to send, from settings or else the environment.
+    pub fn effective_api_key(&self) -> Option<String> {
+        resolve_api_key(self.api_key.as_deref())
+    }
+
+    /// The completion mode for the given language.
+    pub fn completion_mode_for(&self, language: Option<&str>) -> CompletionMode {
+        language
+            .and_then(|language| self.languages.get(language)?.completion_mode)
+            .unwrap_or(self.completion_mode)
+    }
+
+    /// Whether to skip automatic completions in the middle of identifiers in
+    /// the given language.
+    pub fn skip_mid_identifier_for(&self, language: Option<&str>) -> bool {
+        language
+            .and_then(|language| self.languages.get(language)?.skip_mid_identifier)
+            .unwrap_or(self.skip_mid_identifier)
+    }
+
+    /// Whether completions in the given language are shown the part of the
+    /// file's JSON schema at the cursor.
+    pub fn schema_context_for(&self, language: Option<&str>) -> bool {
+        self.schema_context_tokens > 0
+            && language
+                .and_then(|language| self.languages.get(language)?.schema_context)
+                .unwrap_or(false)
+    }
+
+    /// The model configured for the given language, if it differs from the
+    /// default: the language's own, or the prose model for prose.
+    pub fn model_for(&self, language: Option<&str>) -> Option<&str> {
+        language
+            .and_then(|language| self.languages.get(language)?.model.as_deref())
+            .or_else(|| {
+                self.prose_model
+                    .as_deref()
+                    .filter(|_| self.is_prose(language))
+            })
+    }
+
+    /// Whether the given language is written as prose rather than code.
+    pub fn is_prose(&self, language: Option<&str>) -> bool {
+        language.map_or(false, |language| {
+            self.prose_languages.iter().any(|prose| prose == language)
+        })
+    }
+
+    /// The system message sent with completion requests to `model`, if any.
+    pub fn system_prompt_for(&self, model: &str) -> Option<&str> {
+        self.system_prompts
+            .get(model)
+            .or(self.system_prompt.as_ref())
+            .map(String::as_str)
+    }
+
+    /// The prompt template for inline completion requests to `model`, if any.
+    pub fn prompt_template_for(&self, model: &str) -> Option<&str> {
+        self.prompt_templates
+            .get(model)
+            .or(self.prompt_template.as_ref())
+            .map(String::as_str)
+    }
+
+    /// The prompt template for inline completions in the given language,
+    /// which takes precedence over the one for the model.
+    pub fn language_prompt_template(&self, language: Option<&str>) -> Option<&str> {
+        language.and_then(|language| self.languages.get(language)?.prompt_template.as_deref())
+    }
+
+    /// The stop sequences for inline completions in the given language: its
+    /// `stop` setting, or else the ones built in for it.
+    pub fn stop_for(&self, language: Option<&str>) -> Vec<String> {
+        let Some(language) = language else {
+            return Vec::new();
+        };
+        match self
+            .languages
+            .get(language)
+            .and_then(|settings| settings.stop.as_ref())
+        {
+            Some(stop) => stop.clone(),
+            None => builtin_stop(language)
+                .iter()
+                .map(|stop| stop.to_string())
+                .collect(),
+        }
+    }
+
+    /// The steps that clean up suggestions from `model`, in order.
+    pub fn postprocessors_for(&self, model: &str) -> &[Postprocessor] {
+        self.postprocessors_by_model
+            .get(model)
+            .unwrap_or(&self.postprocessors)
+    }
+
+    /// The model `model` refers to: the model of the profile by that name, or
+    /// else `model` itself. Hosted models are named the way ollama.com knows
+    /// them when it's the server, rather than the way a local server proxies
+    /// them.
+    pub fn resolve_model<'a>(&'a self, model: &'a str) -> &'a str {
+        let model = self
+            .profiles
+            .get(model)
+            .map_or(model, |profile| profile.model.as_str());
+        if self.endpoints().iter().all(|url| is_cloud_url(url)) {
+            hosted_model_name(model)
+        } else {
+            model
+        }
+    }
+
+    /// The runtime options sent with every request to `model`, overriding the
+    /// model's Modelfile. A profile's options take precedence over the
+    /// global ones.
+    pub fn runtime_options(&self, model: &str) -> ChatOptions {
+        let profile = self.profiles.get(model);
+        ChatOptions {
+            num_ctx: profile.and_then(|profile| profile.num_ctx).or(self.num_ctx),
+            num_gpu: profile.and_then(|profile| profile.num_gpu).or(self.num_gpu),
+            num_thread: profile
+                .and_then(|profile| profile.num_thread)
+                .or(self.num_thread),
+            mirostat: profile
+                .and_then(|profile| profile.mirostat)
+                .or(self.mirostat),
+            mirostat_eta: profile
+                .and_then(|profile| profile.mirostat_eta)
+                .or(self.mirostat_eta),
+            mirostat_tau: profile
+                .and_then(|profile| profile.mirostat_tau)
+                .or(self.mirostat_tau),
+            repeat_last_n: profile
+                .and_then(|profile| profile.repeat_last_n)
+                .or(self.repeat_last_n),
+            penalize_newline: profile
+                .and_then(|profile| profile.penalize_newline)
+                .or(self.penalize_newline),
+            ..Default::default()
+        }
+    }
+
+    /// The options inline completion requests to `model` are sent with: its
+    /// runtime options, sampled as `completion_options` say.
+    pub fn completion_options(&self, model: &str) -> ChatOptions {
+        let profile = self
+            .profiles
+            .get(model)
+            .and_then(|profile| profile.completion_options.as_ref());
+        with_sampling(
+            self.runtime_options(model),
+            profile,
+            &self.completion_options,
+        )
+    }
+
+    /// The options chat requests to `model` are sent with: its runtime
+    /// options, sampled as `chat_options` say.
+    pub fn chat_options(&self, model: &str) -> ChatOptions {
+        let profile = self
+            .profiles
+            .get(model)
+            .and_then(|profile| profile.chat_options.as_ref());
+        with_sampling(self.runtime_options(model), profile, &self.chat_options)
+    }
+
+    /// How long `model` stays loaded after a request.
+    pub fn keep_alive_for(&self, model: &str) -> KeepAlive {
+        self.profiles
+            .get(model)
+            .and_then(|profile| profile.keep_alive.clone())
+            .unwrap_or_default()
+    }
+
+    /// The servers inline completions may be sent to.
+    pub fn endpoints(&self) -> Vec<String> {
+        if self.api_urls.is_empty() {
+            vec![self.api_url.clone()]
+        } else {
+            self.api_urls.clone()
+        }
+    }
+
+    /// The server a request is sent to first: the one the [`EndpointPool`]
+    /// ranks best of [`Self::endpoints`].
+    pub fn preferred_endpoint(&self, cx: &AppContext) -> String {
+        EndpointPool::global(cx)
+            .ranked(&self.endpoints())
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| self.api_url.clone())
+    }
+}
+
+/// `options` sampled as a profile's `sampling` says, falling back to the
+/// global `default` option by option.
+fn with_sampling(
+    options: ChatOptions,
+    sampling: Option<&OllamaSamplingOptions>,
+    default: &OllamaSamplingOptions,
+) -> ChatOptions {
+    ChatOptions {
+        temperature: sampling
+            .and_then(|sampling| sampling.temperature)
+            .or(default.temperature),
+        top_p: sampling
+            .and_then(|sampling| sampling.top_p)
+            .or(default.top_p),
+        num_predict: sampling
+            .and_then(|sampling| sampling.num_predict)
+            .or(default.num_predict),
+        repeat_penalty: sampling
+            .and_then(|sampling| sampling.repeat_penalty)
+            .or(default.repeat_penalty),
+        presence_penalty: sampling
+            .and_then(|sampling| sampling.presence_penalty)
+            .or(default.presence_penalty),
+        frequency_penalty: sampling
+            .and_then(|sampling| sampling.frequency_penalty)
+            .or(default.frequency_penalty),
+        ..options
+    }
+}
+
+impl Settings for OllamaSettings {
+    const KEY: Option<&'static str> = Some("ollama");
+
+    type FileContent = OllamaSettingsContent;
+
+    /// Project settings take precedence over the user's, except for
+    /// `model`: a project can set the model its team standardizes on, but a
+    /// model the user chose for themselves wins.
+    fn load(sources: SettingsSources<Self::FileContent>, _: &mut AppContext) -> Result<Self> {
+        let mut settings: Self = sources.json_merge()?;
+        let user_model = [sources.user, sources.release_channel, sources.server]
+            .into_iter()
+            .flatten()
+            .filter_map(|content| content.model.clone())
+            .last();
+        if let Some(model) = user_model {
+            settings.model = model;
+        }
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use language::TestFile;
+    use settings::{LocalSettingsKind, SettingsStore, WorktreeId};
+    use std::path::Path;
+
+    #[gpui::test]
+    fn test_server_for_remote_file(cx: &mut AppContext) {
+        let store = SettingsStore::test(cx);
+        cx.set_global(store);
+        OllamaSettings::register(cx);
+        cx.update_global::<SettingsStore, _>(|store, cx| {
+            store
+                .set_local_settings(
+                    WorktreeId::from_usize(0),
+                    Path::new("").into(),
+                    LocalSettingsKind::Settings,
+                    Some(r#"{ "ollama": { "api_url": "http://gpu-box:11434" } }"#),
+                    cx,
+                )
+                .unwrap();
+        });
+        // Test files don't live on this machine, like those of a remote project.
+        let file: Arc<dyn File> = Arc::new(TestFile {
+            path: Path::new("src/main.rs").into(),
+            root_name: "project".into(),
+        });
+
+        assert_eq!(
+            OllamaSettings::for_file(Some(&file), cx).api_url,
+            "http://gpu-box:11434"
+        );
+        assert_eq!(
+            OllamaSettings::server_for_file(Some(&file), cx).api_url,
+            "http://localhost:11434"
+        );
+
+        cx.update_global::<SettingsStore, _>(|store, cx| {
+            store.update_user_settings::<OllamaSettings>(cx, |settings| {
+                settings.local_server_for_remote_projects = Some(false);
+            });
+        });
+        assert_eq!(
+            OllamaSettings::server_for_file(Some(&file), cx).api_url,
+            "http://gpu-box:11434"
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use crate::{render_placeholders, OllamaSettings, OllamaSlashCommand};
+use ollama::{ChatOptions, GenerateRequest, RequestPriority};
+
+/// What a custom slash command's prompt is filled in with.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SlashCommandInputs {
+    /// The text typed after the command.
+    pub arguments: String,
+    pub path: Option<String>,
+    pub file: Option<String>,
+    pub selection: Option<String>,
+    pub diff: Option<String>,
+}
+
+impl OllamaSlashCommand {
+    /// Whether the prompt refers to `placeholder`, e.g. "{diff}", so inputs
+    /// that are costly to gather can be skipped when it doesn't.
+    pub fn uses(&self, placeholder: &str) -> bool {
+        self.prompt.contains(placeholder)
+    }
+
+    /// A streaming request for the command's prompt, filled in with `inputs`.
+    pub fn request(
+        &self,
+        inputs: &SlashCommandInputs,
+        settings: &OllamaSettings,
+    ) -> GenerateRequest {
+        let model_or_profile = self.model.as_deref().unwrap_or(&settings.model);
+        let options = settings.chat_options(model_or_profile);
+        let mut request = GenerateRequest {
+            model: settings.resolve_model(model_or_profile).to_string(),
+            prompt: render_prompt(&self.prompt, inputs),
+            suffix: None,
+            stream: true,
+            keep_alive: settings.keep_alive_for(model_or_profile),
+            options: Some(ChatOptions {
+                temperature: self.temperature.or(options.temperature),
+                top_p: self.top_p.or(options.top_p),
+                num_predict: self.num_predict.or(options.num_predict),
+                ..options
+            }),
+            system: self.system.clone(),
+            raw: None,
+            format: None,
+            context: None,
+            priority: RequestPriority::UserInitiated,
+        };
+        if let Some(redactor) = settings.secret_redactor() {
+            redactor.redact_request(&mut request);
+        }
+        request
+    }
+}
+
+/// Replaces the placeholders in `template` with `inputs`. Inputs that aren't
+/// available become empty, and unknown placeholders are kept.
+fn render_prompt(template: &str, inputs: &SlashCommandInputs) -> String {
+    render_placeholders(
+        template,
+        &[
+            ("{arguments}", inputs.arguments.as_str()),
+            ("{path}", inputs.path.as_deref().unwrap_or_default()),
+            ("{file}", inputs.file.as_deref().unwrap_or_default()),
+            (
+                "{selection}",
+                inputs.selection.as_deref().unwrap_or_default(),
+            ),
+            ("{diff}", inputs.diff.as_deref().unwrap_or_default()),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_prompt() {
+        let inputs = SlashCommandInputs {
+            arguments: "briefly".into(),
+            path: Some("src/main.rs".into()),
+            file: Some("fn main() { println!(\"{diff}\"); }".into()),
+            selection: None,
+            diff: Some("+fn main() {}".into()),
+        };
+        assert_eq!(
+            render_prompt("Summarize {path} {arguments}:\n{file}", &inputs),
+            "Summarize src/main.rs briefly:\nfn main() { println!(\"{diff}\"); }"
+        );
+        assert_eq!(
+            render_prompt("Explain {diff}{selection} as {json} {", &inputs),
+            "Explain +fn main() {} as {json} {"
+        );
+        // Braces around placeholders are kept.
+        assert_eq!(
+            render_prompt("if (x) { {arguments}", &inputs),
+            "if (x) { briefly"
+        );
+    }
+}
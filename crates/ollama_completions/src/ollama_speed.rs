@@ -0,0 +1,93 @@
+use gpui::{AppContext, Context as _, Global, Model, ModelContext};
+use ollama::GenerateResponse;
+use std::{collections::VecDeque, time::Duration};
+
+/// How many of the latest suggestions the average speed is taken over.
+const SPEED_SAMPLES: usize = 10;
+
+struct GlobalGenerationSpeed(Model<GenerationSpeed>);
+
+impl Global for GlobalGenerationSpeed {}
+
+/// How fast the completion model has been generating lately, shown in the
+/// status bar so a model that no longer fits on the GPU is noticed right away.
+#[derive(Default)]
+pub struct GenerationSpeed {
+    /// Tokens per second of the latest suggestions, oldest first.
+    samples: VecDeque<f64>,
+    last_latency: Option<Duration>,
+}
+
+pub(crate) fn init(cx: &mut AppContext) {
+    let speed = cx.new_model(|_| GenerationSpeed::default());
+    cx.set_global(GlobalGenerationSpeed(speed));
+}
+
+impl GenerationSpeed {
+    pub fn global(cx: &AppContext) -> Option<Model<Self>> {
+        cx.try_global::<GlobalGenerationSpeed>()
+            .map(|speed| speed.0.clone())
+    }
+
+    /// Records a completion response that took `latency` to arrive.
+    pub fn record(
+        &mut self,
+        response: &GenerateResponse,
+        latency: Duration,
+        cx: &mut ModelContext<Self>,
+    ) {
+        self.push(response.tokens_per_second(), latency);
+        cx.notify();
+    }
+
+    fn push(&mut self, tokens_per_second: Option<f64>, latency: Duration) {
+        if let Some(tokens_per_second) = tokens_per_second {
+            if self.samples.len() == SPEED_SAMPLES {
+                self.samples.pop_front();
+            }
+            self.samples.push_back(tokens_per_second);
+        }
+        self.last_latency = Some(latency);
+    }
+
+    /// The mean generation speed over the latest suggestions.
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        (!self.samples.is_empty())
+            .then(|| self.samples.iter().sum::<f64>() / self.samples.len() as f64)
+    }
+
+    pub fn last_latency(&self) -> Option<Duration> {
+        self.last_latency
+    }
+
+    /// A short readout like "42 tok/s · 310 ms", once there's been a response.
+    pub fn label(&self) -> Option<String> {
+        let latency = self.last_latency?;
+        Some(match self.tokens_per_second() {
+            Some(speed) => format!("{speed:.0} tok/s · {} ms", latency.as_millis()),
+            None => format!("{} ms", latency.as_millis()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generation_speed() {
+        let mut speed = GenerationSpeed::default();
+        assert_eq!(speed.label(), None);
+
+        speed.push(None, Duration::from_millis(120));
+        assert_eq!(speed.label().as_deref(), Some("120 ms"));
+
+        for _ in 0..SPEED_SAMPLES {
+            speed.push(Some(10.0), Duration::from_millis(900));
+        }
+        speed.push(Some(30.0), Duration::from_millis(310));
+        assert_eq!(speed.samples.len(), SPEED_SAMPLES);
+        assert_eq!(speed.tokens_per_second(), Some(12.0));
+        assert_eq!(speed.label().as_deref(), Some("12 tok/s · 310 ms"));
+    }
+}
@@ -0,0 +1,265 @@
+use db::kvp::KEY_VALUE_STORE;
+use gpui::{AppContext, Global};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt::Write as _, sync::Arc, time::Duration};
+use util::ResultExt as _;
+
+const STATS_KEY: &str = "ollama_completion_stats";
+const LATENCY_KEY: &str = "ollama_completion_latency";
+/// Suggestions in files without a language are counted under this name.
+const PLAIN_TEXT: &str = "Plain Text";
+
+#[derive(Default)]
+struct GlobalCompletionStats(Arc<CompletionStats>);
+
+impl Global for GlobalCompletionStats {}
+
+/// How often each model's suggestions are accepted, per language, and how
+/// long each model takes to respond. Kept in Zed's local database and never
+/// sent anywhere, so models can be compared on your own code.
+#[derive(Default)]
+pub struct CompletionStats {
+    counts: Mutex<StatsByModel>,
+    latencies: Mutex<LatencyByModel>,
+}
+
+/// Counts by model name, then by language name.
+type StatsByModel = BTreeMap<String, BTreeMap<String, AcceptanceCounts>>;
+type LatencyByModel = BTreeMap<String, LatencyTotals>;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencyTotals {
+    pub responses: u64,
+    pub total_ms: u64,
+}
+
+impl LatencyTotals {
+    fn add(&mut self, other: LatencyTotals) {
+        self.responses += other.responses;
+        self.total_ms += other.total_ms;
+    }
+
+    /// The mean time to a response, if there were any.
+    pub fn average(&self) -> Option<Duration> {
+        (self.responses > 0).then(|| Duration::from_millis(self.total_ms / self.responses))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AcceptanceCounts {
+    pub accepted: u64,
+    pub discarded: u64,
+}
+
+impl AcceptanceCounts {
+    fn add(&mut self, other: AcceptanceCounts) {
+        self.accepted += other.accepted;
+        self.discarded += other.discarded;
+    }
+
+    /// The share of suggestions that were accepted, if any were shown.
+    pub fn acceptance_rate(&self) -> Option<f64> {
+        let total = self.accepted + self.discarded;
+        (total > 0).then(|| self.accepted as f64 / total as f64)
+    }
+}
+
+pub(crate) fn init(cx: &mut AppContext) {
+    let stats = Arc::new(CompletionStats::default());
+    cx.set_global(GlobalCompletionStats(stats.clone()));
+    cx.background_executor()
+        .spawn(async move {
+            // Suggestions may have been made, accepted or discarded while
+            // loading, so the saved totals are added to the current ones.
+            if let Some(saved) = KEY_VALUE_STORE.read_kvp(STATS_KEY)? {
+                let saved: StatsByModel = serde_json::from_str(&saved)?;
+                let mut counts = stats.counts.lock();
+                for (model, languages) in saved {
+                    let model_counts = counts.entry(model).or_default();
+                    for (language, saved_counts) in languages {
+                        model_counts.entry(language).or_default().add(saved_counts);
+                    }
+                }
+            }
+            if let Some(saved) = KEY_VALUE_STORE.read_kvp(LATENCY_KEY)? {
+                let saved: LatencyByModel = serde_json::from_str(&saved)?;
+                let mut latencies = stats.latencies.lock();
+                for (model, totals) in saved {
+                    latencies.entry(model).or_default().add(totals);
+                }
+            }
+            anyhow::Ok(())
+        })
+        .detach_and_log_err(cx);
+}
+
+impl CompletionStats {
+    pub fn global(cx: &AppContext) -> Arc<Self> {
+        cx.global::<GlobalCompletionStats>().0.clone()
+    }
+
+    /// Counts a suggestion from `model` as accepted or discarded, and saves
+    /// the counts in the background.
+    pub fn record(&self, model: &str, language: Option<&str>, accepted: bool, cx: &AppContext) {
+        let saved = {
+            let mut counts = self.counts.lock();
+            let language_counts = counts
+                .entry(model.to_string())
+                .or_default()
+                .entry(language.unwrap_or(PLAIN_TEXT).to_string())
+                .or_default();
+            if accepted {
+                language_counts.accepted += 1;
+            } else {
+                language_counts.discarded += 1;
+            }
+            serde_json::to_string(&*counts)
+        };
+        let Some(saved) = saved.log_err() else {
+            return;
+        };
+        cx.background_executor()
+            .spawn(async move {
+                KEY_VALUE_STORE
+                    .write_kvp(STATS_KEY.to_string(), saved)
+                    .await
+            })
+            .detach_and_log_err(cx);
+    }
+
+    /// Adds the time `model` took to make a suggestion to its average, and
+    /// saves the totals in the background.
+    pub fn record_latency(&self, model: &str, latency: Duration, cx: &AppContext) {
+        let saved = {
+            let mut latencies = self.latencies.lock();
+            latencies
+                .entry(model.to_string())
+                .or_default()
+                .add(LatencyTotals {
+                    responses: 1,
+                    total_ms: latency.as_millis() as u64,
+                });
+            serde_json::to_string(&*latencies)
+        };
+        let Some(saved) = saved.log_err() else {
+            return;
+        };
+        cx.background_executor()
+            .spawn(async move {
+                KEY_VALUE_STORE
+                    .write_kvp(LATENCY_KEY.to_string(), saved)
+                    .await
+            })
+            .detach_and_log_err(cx);
+    }
+
+    /// A Markdown table of the acceptance rate of each model, overall and
+    /// for each language, with each model's average latency.
+    pub fn summary(&self) -> String {
+        summary_table(&self.counts.lock(), &self.latencies.lock())
+    }
+}
+
+fn summary_table(counts: &StatsByModel, latencies: &LatencyByModel) -> String {
+    if counts.is_empty() {
+        return "No Ollama suggestions have been accepted or discarded yet.\n".to_string();
+    }
+
+    let mut table = String::from(
+        "| Model | Language | Accepted | Discarded | Acceptance rate | Average latency |\n\
+         | --- | --- | ---: | ---: | ---: | ---: |\n",
+    );
+    for (model, languages) in counts {
+        let mut total = AcceptanceCounts::default();
+        for counts in languages.values() {
+            total.add(*counts);
+        }
+        let latency = latencies
+            .get(model)
+            .and_then(LatencyTotals::average)
+            .map_or("-".to_string(), |latency| {
+                format!("{} ms", latency.as_millis())
+            });
+        write_row(&mut table, model, "**All**", total, &latency);
+        for (language, counts) in languages {
+            write_row(&mut table, "", language, *counts, "");
+        }
+    }
+    table
+}
+
+fn write_row(
+    table: &mut String,
+    model: &str,
+    language: &str,
+    counts: AcceptanceCounts,
+    latency: &str,
+) {
+    let rate = counts
+        .acceptance_rate()
+        .map_or("-".to_string(), |rate| format!("{:.1}%", rate * 100.0));
+    writeln!(
+        table,
+        "| {model} | {language} | {} | {} | {rate} | {latency} |",
+        counts.accepted, counts.discarded
+    )
+    .ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_table() {
+        assert_eq!(
+            summary_table(&StatsByModel::default(), &LatencyByModel::default()),
+            "No Ollama suggestions have been accepted or discarded yet.\n"
+        );
+
+        let mut counts = StatsByModel::default();
+        let qwen = counts.entry("qwen2.5-coder:1.5b".into()).or_default();
+        qwen.insert(
+            "Rust".into(),
+            AcceptanceCounts {
+                accepted: 3,
+                discarded: 1,
+            },
+        );
+        qwen.insert(
+            "Python".into(),
+            AcceptanceCounts {
+                accepted: 0,
+                discarded: 4,
+            },
+        );
+        counts.entry("codellama:7b".into()).or_default().insert(
+            "Rust".into(),
+            AcceptanceCounts {
+                accepted: 1,
+                discarded: 2,
+            },
+        );
+
+        let mut latencies = LatencyByModel::default();
+        latencies.insert(
+            "qwen2.5-coder:1.5b".into(),
+            LatencyTotals {
+                responses: 4,
+                total_ms: 1000,
+            },
+        );
+
+        assert_eq!(
+            summary_table(&counts, &latencies),
+            "| Model | Language | Accepted | Discarded | Acceptance rate | Average latency |\n\
+             | --- | --- | ---: | ---: | ---: | ---: |\n\
+             | codellama:7b | **All** | 1 | 2 | 33.3% | - |\n\
+             |  | Rust | 1 | 2 | 33.3% |  |\n\
+             | qwen2.5-coder:1.5b | **All** | 3 | 5 | 37.5% | 250 ms |\n\
+             |  | Python | 0 | 4 | 0.0% |  |\n\
+             |  | Rust | 3 | 1 | 75.0% |  |\n"
+        );
+    }
+}
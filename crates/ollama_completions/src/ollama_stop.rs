@@ -0,0 +1,53 @@
+/// Sequences that start the next top-level declaration in each language, so
+/// a suggestion ends with the function or class being written instead of
+/// going on to invent its siblings. Declarations nested in a class or `impl`
+/// block are indented, so they don't match.
+const BUILTIN_STOP: &[(&str, &[&str])] = &[
+    ("Elixir", &["\ndefmodule "]),
+    ("Go", &["\nfunc ", "\ntype "]),
+    ("JavaScript", &["\nfunction ", "\nclass ", "\nexport "]),
+    ("Lua", &["\nfunction ", "\nlocal function "]),
+    ("PHP", &["\nfunction ", "\nclass "]),
+    ("Python", &["\ndef ", "\nasync def ", "\nclass "]),
+    ("Ruby", &["\ndef ", "\nclass ", "\nmodule "]),
+    (
+        "Rust",
+        &["\nfn ", "\npub fn ", "\nimpl ", "\nimpl<", "\n#[cfg(test)]"],
+    ),
+    ("TSX", &["\nfunction ", "\nclass ", "\nexport "]),
+    ("TypeScript", &["\nfunction ", "\nclass ", "\nexport "]),
+    ("Zig", &["\nfn ", "\npub fn "]),
+];
+
+/// The stop sequences Zed uses for completions in `language` unless its
+/// `stop` setting replaces them.
+pub fn builtin_stop(language: &str) -> &'static [&'static str] {
+    BUILTIN_STOP
+        .iter()
+        .find(|(name, _)| *name == language)
+        .map_or(&[], |(_, stop)| stop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_stop() {
+        assert!(builtin_stop("Rust").contains(&"\nfn "));
+        assert!(builtin_stop("Python").contains(&"\ndef "));
+        assert!(builtin_stop("Markdown").is_empty());
+
+        // Every sequence starts a line, so code on the cursor's own line,
+        // or nested in a block, never ends a suggestion early.
+        for (language, stop) in BUILTIN_STOP {
+            for sequence in *stop {
+                let declaration = sequence.strip_prefix('\n').unwrap_or_default();
+                assert!(
+                    !declaration.is_empty() && !declaration.starts_with(char::is_whitespace),
+                    "{language}: {sequence:?}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use anyhow::{anyhow, Result};
+use futures::{stream::BoxStream, StreamExt};
+use ollama::GenerateResponse;
+use std::time::{Duration, Instant};
+
+/// How often a streamed suggestion is updated while it's generated. Tokens
+/// usually arrive faster than this, and laying out the suggestion again for
+/// each of them makes long suggestions stutter.
+const STREAM_UPDATE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Gathers the chunks of a streamed response, deciding when enough time has
+/// passed since the last update for the text so far to be shown.
+struct StreamCoalescer {
+    text: String,
+    shown_len: usize,
+    last_update: Option<Instant>,
+    interval: Duration,
+}
+
+impl StreamCoalescer {
+    fn new(interval: Duration) -> Self {
+        Self {
+            text: String::new(),
+            shown_len: 0,
+            last_update: None,
+            interval,
+        }
+    }
+
+    /// Adds `chunk`, returning the text so far when it's due to be shown:
+    /// as soon as there's any, then at most once per interval, and only when
+    /// something was added since it was last shown.
+    fn push(&mut self, chunk: &str, now: Instant) -> Option<&str> {
+        self.text.push_str(chunk);
+        if self.text.len() == self.shown_len
+            || self
+                .last_update
+                .is_some_and(|last_update| now.duration_since(last_update) < self.interval)
+        {
+            return None;
+        }
+        self.last_update = Some(now);
+        self.shown_len = self.text.len();
+        Some(&self.text)
+    }
+}
+
+/// Reads a streamed response to the end, calling `on_update` with the
+/// response so far whenever it's due to be shown, and returns the whole
+/// response with the statistics from its last chunk.
+pub(crate) async fn collect_stream(
+    mut stream: BoxStream<'static, Result<GenerateResponse>>,
+    mut on_update: impl FnMut(&GenerateResponse),
+) -> Result<GenerateResponse> {
+    let mut coalescer = StreamCoalescer::new(STREAM_UPDATE_INTERVAL);
+    while let Some(chunk) = stream.next().await {
+        let mut chunk = chunk?;
+        if chunk.done {
+            chunk.response = coalescer.text + &chunk.response;
+            return Ok(chunk);
+        }
+        if let Some(text) = coalescer.push(&chunk.response, Instant::now()) {
+            chunk.response = text.to_string();
+            on_update(&chunk);
+        }
+    }
+    Err(anyhow!("Ollama's response ended before it was done"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_coalescer() {
+        let interval = Duration::from_millis(50);
+        let mut coalescer = StreamCoalescer::new(interval);
+        let start = Instant::now();
+
+        // Nothing to show until there's text, then it's shown right away.
+        assert_eq!(coalescer.push("", start), None);
+        assert_eq!(coalescer.push("fn", start), Some("fn"));
+
+        // Chunks within the interval are held back and shown together.
+        assert_eq!(coalescer.push(" main", start + interval / 2), None);
+        assert_eq!(coalescer.push("()", start + interval / 2), None);
+        assert_eq!(coalescer.push(" {", start + interval), Some("fn main() {"));
+
+        // A chunk that adds nothing isn't an update, even when one is due.
+        assert_eq!(coalescer.push("", start + interval * 3), None);
+        assert_eq!(
+            coalescer.push("}", start + interval * 3),
+            Some("fn main() {}")
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use editor::{
+    display_map::{
+        BlockContext, BlockId, BlockPlacement, BlockProperties, BlockStyle, CustomBlockId,
+    },
+    scroll::Autoscroll,
+    Anchor, Editor,
+};
+use gpui::{ClickEvent, ViewContext, WeakView, WindowContext};
+use std::{ops::Range, sync::Arc};
+use theme::ActiveTheme as _;
+use ui::{prelude::*, Button};
+
+/// Text the model inserted into an editor, shown highlighted like an added
+/// hunk in a diff until it's accepted or discarded.
+#[derive(Clone)]
+pub(crate) struct PendingSuggestion {
+    /// The inserted text.
+    pub inserted: Range<Anchor>,
+    /// The rows to highlight, which can leave out a final line break.
+    pub highlighted: Range<Anchor>,
+}
+
+impl PendingSuggestion {
+    pub(crate) fn highlight(&self, editor: &mut Editor, cx: &mut ViewContext<Editor>) {
+        editor.highlight_rows::<PendingSuggestion>(
+            self.highlighted.clone(),
+            cx.theme().status().created_background,
+            false,
+            cx,
+        );
+    }
+
+    /// Shows buttons above the suggestion to accept or discard it, along
+    /// with `label`.
+    pub(crate) fn offer(
+        self,
+        label: &'static str,
+        editor: &mut Editor,
+        cx: &mut ViewContext<Editor>,
+    ) {
+        let view = cx.view().downgrade();
+        editor.insert_blocks(
+            [BlockProperties {
+                placement: BlockPlacement::Above(self.inserted.start),
+                height: 1,
+                style: BlockStyle::Flex,
+                render: Arc::new(move |cx: &mut BlockContext| {
+                    let BlockId::Custom(block_id) = cx.block_id else {
+                        return div().into_any_element();
+                    };
+                    h_flex()
+                        .block_mouse_down()
+                        .pl(cx.anchor_x)
+                        .gap_2()
+                        .child(Label::new(label).size(LabelSize::Small).color(Color::Muted))
+                        .child(
+                            Button::new("accept-suggestion", "Accept")
+                                .label_size(LabelSize::Small)
+                                .on_click(self.resolve_on_click(block_id, true, view.clone())),
+                        )
+                        .child(
+                            Button::new("discard-suggestion", "Discard")
+                                .label_size(LabelSize::Small)
+                                .on_click(self.resolve_on_click(block_id, false, view.clone())),
+                        )
+                        .into_any_element()
+                }),
+                priority: 0,
+            }],
+            Some(Autoscroll::fit()),
+            cx,
+        );
+    }
+
+    fn resolve_on_click(
+        &self,
+        block_id: CustomBlockId,
+        accept: bool,
+        editor: WeakView<Editor>,
+    ) -> impl Fn(&ClickEvent, &mut WindowContext) + 'static {
+        let suggestion = self.clone();
+        move |_, cx| {
+            editor
+                .update(cx, |editor, cx| {
+                    suggestion.resolve(block_id, accept, editor, cx)
+                })
+                .ok();
+        }
+    }
+
+    fn resolve(
+        &self,
+        block_id: CustomBlockId,
+        accept: bool,
+        editor: &mut Editor,
+        cx: &mut ViewContext<Editor>,
+    ) {
+        editor.remove_blocks([block_id].into_iter().collect(), None, cx);
+        editor.remove_highlighted_rows::<PendingSuggestion>(vec![self.highlighted.clone()], cx);
+        if !accept {
+            editor.edit([(self.inserted.clone(), "")], cx);
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use crate::{http_client_for, EndpointPool, OllamaSettings};
+use anyhow::Result;
+use gpui::{AppContext, Task, ViewContext};
+use ollama::{ChatOptions, GenerateRequest, RequestPriority, RetryPolicy};
+use settings::Settings as _;
+use std::{rc::Rc, time::Duration};
+use terminal_view::{CommandSuggestionProvider, TerminalView};
+
+/// The most tokens generated for the rest of a command.
+const MAX_SUGGESTION_TOKENS: isize = 64;
+
+const SYSTEM_PROMPT: &str = "You complete shell commands. You're given a terminal's recent \
+output, followed by the line being typed, which starts with the shell's prompt. Reply with \
+only the text that should come after the cursor to finish the command, on a single line, \
+without explanations or formatting.";
+
+pub(crate) fn init(cx: &mut AppContext) {
+    cx.observe_new_views(
+        |terminal_view: &mut TerminalView, _: &mut ViewContext<TerminalView>| {
+            terminal_view.set_command_suggestion_provider(Rc::new(OllamaCommandSuggestions));
+        },
+    )
+    .detach();
+}
+
+/// Suggests how to finish commands typed in the terminal, when turned on with
+/// the `terminal_suggestions` setting. Terminal output can contain secrets,
+/// so nothing is sent to the model unless the user opted in.
+struct OllamaCommandSuggestions;
+
+impl CommandSuggestionProvider for OllamaCommandSuggestions {
+    fn suggest(
+        &self,
+        command_line: String,
+        recent_output: Vec<String>,
+        cx: &mut AppContext,
+    ) -> Task<Result<Option<String>>> {
+        let settings = OllamaSettings::get_global(cx);
+        if !settings.enabled || !settings.terminal_suggestions {
+            return Task::ready(Ok(None));
+        }
+
+        let mut request = GenerateRequest {
+            model: settings.resolve_model(&settings.model).to_string(),
+            prompt: command_prompt(&command_line, &recent_output),
+            suffix: None,
+            stream: false,
+            keep_alive: settings.keep_alive_for(&settings.model),
+            options: Some(ChatOptions {
+                num_predict: Some(MAX_SUGGESTION_TOKENS),
+                stop: Some(vec!["\n".into()]),
+                temperature: Some(0.),
+                ..settings.runtime_options(&settings.model)
+            }),
+            system: Some(SYSTEM_PROMPT.to_string()),
+            raw: None,
+            format: None,
+            context: None,
+            priority: RequestPriority::Automatic,
+        };
+        if let Some(redactor) = settings.secret_redactor() {
+            redactor.redact_request(&mut request);
+        }
+        let http_client = http_client_for(settings, &cx.http_client());
+        let endpoints = settings.endpoints();
+        let api_key = settings.effective_api_key();
+        let api_format = settings.api_format;
+        let retry = RetryPolicy::default().with_max_attempts(settings.max_request_attempts);
+        let timeout = Duration::from_secs(settings.completion_timeout_secs);
+        let pool = EndpointPool::global(cx);
+
+        cx.spawn(|_| async move {
+            let response = pool
+                .generate(
+                    http_client.as_ref(),
+                    &endpoints,
+                    api_key.as_deref(),
+                    api_format,
+                    request,
+                    retry,
+                    Some(timeout),
+                )
+                .await?;
+            Ok(command_suggestion(&response.response, &command_line))
+        })
+    }
+}
+
+fn command_prompt(command_line: &str, recent_output: &[String]) -> String {
+    let mut prompt = String::new();
+    for line in recent_output {
+        prompt.push_str(line);
+        prompt.push('\n');
+    }
+    prompt.push_str(command_line);
+    prompt
+}
+
+/// The rest of the command from the model's reply. Models often repeat what
+/// was already typed, so the longest run of whole words at the end of the
+/// command line that the reply starts with is removed.
+fn command_suggestion(response: &str, command_line: &str) -> Option<String> {
+    let line = response.lines().next()?.trim_matches('`').trim_end();
+    let typed_starts = command_line
+        .char_indices()
+        .filter(|(ix, _)| *ix == 0 || command_line[..*ix].ends_with(char::is_whitespace))
+        .map(|(ix, _)| ix);
+    let mut suggestion = line;
+    for ix in typed_starts {
+        let typed = &command_line[ix..];
+        if !typed.trim().is_empty() {
+            if let Some(rest) = line.strip_prefix(typed.trim_start()) {
+                suggestion = rest;
+                break;
+            }
+        }
+    }
+    if command_line.ends_with(char::is_whitespace) {
+        suggestion = suggestion.trim_start();
+    }
+    (!suggestion.is_empty()).then(|| suggestion.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_suggestion() {
+        assert_eq!(
+            command_suggestion("eckout main", "~/src/zed$ git ch"),
+            Some("eckout main".into())
+        );
+        assert_eq!(
+            command_suggestion("git checkout main\nSwitches branches.", "~/src/zed$ git ch"),
+            Some("eckout main".into())
+        );
+        assert_eq!(
+            command_suggestion("checkout main", "~/src/zed$ git ch"),
+            Some("eckout main".into())
+        );
+        assert_eq!(
+            command_suggestion("`cargo test --workspace`", "$ cargo "),
+            Some("test --workspace".into())
+        );
+        assert_eq!(command_suggestion("git status", "$ git status"), None);
+        assert_eq!(command_suggestion("", "$ ls"), None);
+    }
+
+    #[test]
+    fn test_command_prompt() {
+        assert_eq!(
+            command_prompt(
+                "$ git ch",
+                &["$ git status".into(), "On branch main".into()]
+            ),
+            "$ git status\nOn branch main\n$ git ch"
+        );
+    }
+}
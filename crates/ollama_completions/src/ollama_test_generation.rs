@@ -0,0 +1,323 @@
+use crate::{http_client_for, selection_or_symbol, Generations, OllamaSettings, PendingSuggestion};
+use anyhow::{Context as _, Result};
+use editor::{Editor, EditorMode};
+use futures::StreamExt as _;
+use gpui::{AppContext, Entity as _, Task, ViewContext, VisualContext as _};
+use ollama::{stream_transformation, ChatMessage, ChatRequest, RequestPriority};
+use project::ProjectPath;
+use std::path::{Path, PathBuf};
+use zed_actions::ollama::GenerateTestsForSelection;
+
+/// Statements that bring names into scope, across the bundled languages.
+const IMPORT_PREFIXES: &[&str] = &[
+    "use ",
+    "pub use ",
+    "import ",
+    "from ",
+    "export * from ",
+    "#include ",
+    "using ",
+    "require ",
+    "require_relative ",
+    "alias ",
+];
+
+pub(crate) fn init(cx: &mut AppContext) {
+    cx.observe_new_views(|editor: &mut Editor, cx: &mut ViewContext<Editor>| {
+        if editor.mode() != EditorMode::Full {
+            return;
+        }
+
+        editor
+            .register_action(cx.listener(|editor, _: &GenerateTestsForSelection, cx| {
+                let task = generate_tests(editor, cx);
+                Generations::track(Some(cx.view().entity_id()), task, cx);
+            }))
+            .detach();
+    })
+    .detach();
+}
+
+/// Asks the model for tests of the selection (or the symbol around the
+/// cursor) and streams them into the file they belong in, where they're shown
+/// as a suggestion to accept or discard. Files without a path get their tests
+/// in a new untitled buffer.
+fn generate_tests(editor: &mut Editor, cx: &mut ViewContext<Editor>) -> Task<Result<()>> {
+    let Some(workspace) = editor.workspace() else {
+        return Task::ready(Ok(()));
+    };
+    let snapshot = editor.buffer().read(cx).snapshot(cx);
+    let Some(range) = selection_or_symbol(editor, &snapshot, cx) else {
+        return Task::ready(Ok(()));
+    };
+    let Some(excerpt) = snapshot.excerpt_containing(range.clone()) else {
+        return Task::ready(Ok(()));
+    };
+    let buffer = excerpt.buffer();
+    let settings = OllamaSettings::for_file(buffer.file(), cx);
+    let server = OllamaSettings::server_for_file(buffer.file(), cx);
+    if !settings.enabled {
+        return Task::ready(Ok(()));
+    }
+
+    let code = snapshot.text_for_range(range.clone()).collect::<String>();
+    let imports = file_imports(&buffer.text());
+    let language = buffer
+        .language_at(excerpt.map_offset_to_buffer(range.start))
+        .cloned();
+    let language_name = language
+        .as_ref()
+        .map(|language| language.name().0.to_string())
+        .unwrap_or_default();
+    let path = buffer.file().map(|file| file.path().clone());
+    let test_path = buffer.file().map(|file| ProjectPath {
+        worktree_id: file.worktree_id(cx),
+        path: test_file_path(file.path()).into(),
+    });
+    let model = settings.resolve_model(&settings.model).to_string();
+    let keep_alive = settings.keep_alive_for(&settings.model);
+    let options = settings.runtime_options(&settings.model);
+    let redactor = settings.secret_redactor();
+    let http_client = http_client_for(server, &cx.http_client());
+    let api_url = server.api_url.clone();
+    let api_key = server.effective_api_key();
+
+    let test_editor = match test_path.clone() {
+        Some(test_path) => workspace.update(cx, |workspace, cx| {
+            let open = workspace.open_path(test_path, None, true, cx);
+            cx.spawn(|_, _| async move {
+                open.await?
+                    .downcast::<Editor>()
+                    .context("test file isn't open in an editor")
+            })
+        }),
+        None => {
+            let project = workspace.read(cx).project().clone();
+            let buffer = project.update(cx, |project, cx| {
+                project.create_local_buffer("", language.clone(), cx)
+            });
+            let test_editor =
+                cx.new_view(|cx| Editor::for_buffer(buffer, Some(project.clone()), cx));
+            workspace.update(cx, |workspace, cx| {
+                workspace.add_item_to_active_pane(Box::new(test_editor.clone()), None, true, cx);
+            });
+            Task::ready(Ok(test_editor))
+        }
+    };
+
+    cx.spawn(|_, mut cx| async move {
+        let test_editor = test_editor.await?;
+        let (suggestion, file_is_empty) = test_editor.update(&mut cx, |editor, cx| {
+            let snapshot = editor.buffer().read(cx).snapshot(cx);
+            let file_is_empty = snapshot.is_empty();
+            let mut end = snapshot.len();
+            // Discarding the tests removes the separator too.
+            let start = snapshot.anchor_before(end);
+            if !file_is_empty {
+                // Leave a blank line between the existing code and the tests.
+                let separator = if snapshot.reversed_chars_at(end).next() == Some('\n') {
+                    "\n"
+                } else {
+                    "\n\n"
+                };
+                editor.edit([(end..end, separator)], cx);
+                end += separator.len();
+            }
+            let snapshot = editor.buffer().read(cx).snapshot(cx);
+            let suggestion = PendingSuggestion {
+                inserted: start..snapshot.anchor_after(end),
+                highlighted: snapshot.anchor_before(end)..snapshot.anchor_after(end),
+            };
+            suggestion.highlight(editor, cx);
+            (suggestion, file_is_empty)
+        })?;
+
+        let mut request = ChatRequest {
+            model,
+            messages: vec![
+                ChatMessage::System {
+                    content: tests_instruction(
+                        path.as_deref(),
+                        test_path.as_ref().map(|test_path| test_path.path.as_ref()),
+                        file_is_empty,
+                    ),
+                },
+                ChatMessage::User {
+                    content: tests_prompt(&imports, &code, &language_name),
+                },
+            ],
+            stream: true,
+            keep_alive,
+            options: Some(options),
+            tools: Vec::new(),
+            priority: RequestPriority::UserInitiated,
+        };
+        if let Some(redactor) = &redactor {
+            redactor.redact_chat_request(&mut request);
+        }
+        let mut chunks =
+            stream_transformation(http_client.as_ref(), &api_url, api_key.as_deref(), request)
+                .await?;
+        let mut first_transaction = None;
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk?;
+            test_editor.update(&mut cx, |editor, cx| {
+                editor.buffer().update(cx, |buffer, cx| {
+                    buffer.start_transaction(cx);
+                    let end = suggestion.inserted.end;
+                    buffer.edit([(end..end, chunk)], None, cx);
+                    if let Some(transaction) = buffer.end_transaction(cx) {
+                        match first_transaction {
+                            Some(first) => buffer.group_until_transaction(first, cx),
+                            None => first_transaction = Some(transaction),
+                        }
+                    }
+                });
+            })?;
+        }
+        test_editor.update(&mut cx, |editor, cx| {
+            suggestion.offer("Generated tests", editor, cx)
+        })
+    })
+}
+
+fn tests_instruction(path: Option<&Path>, test_path: Option<&Path>, file_is_empty: bool) -> String {
+    let mut instruction = String::from(
+        "Write unit tests for the given code, following the testing conventions of its language.",
+    );
+    match (path, test_path) {
+        (Some(path), Some(test_path)) if path == test_path => {
+            instruction.push_str(&format!(
+                " The tests are added at the end of `{}`, where the code is defined.",
+                path.display()
+            ));
+        }
+        (Some(path), Some(test_path)) => {
+            instruction.push_str(&format!(
+                " The tests go in `{}`, next to `{}`.",
+                test_path.display(),
+                path.display()
+            ));
+        }
+        _ => {}
+    }
+    if file_is_empty {
+        instruction.push_str(" Respond with only the contents of the test file, including the imports the tests need, without explanations.");
+    } else {
+        instruction.push_str(" The file already has code, so respond with only the code to append to it, without explanations.");
+    }
+    instruction
+}
+
+fn tests_prompt(imports: &str, code: &str, language_name: &str) -> String {
+    if imports.is_empty() {
+        format!("```{language_name}\n{code}\n```")
+    } else {
+        format!(
+            "Imports of the file the code is in:\n```{language_name}\n{imports}\n```\n\n\
+            Code to test:\n```{language_name}\n{code}\n```"
+        )
+    }
+}
+
+/// The top-level import statements of a file, including ones that span
+/// several lines.
+fn file_imports(text: &str) -> String {
+    let mut imports = Vec::new();
+    let mut open_brackets = 0i32;
+    for line in text.lines() {
+        if open_brackets == 0
+            && !IMPORT_PREFIXES
+                .iter()
+                .any(|prefix| line.starts_with(prefix))
+        {
+            continue;
+        }
+        imports.push(line);
+        for c in line.chars() {
+            match c {
+                '(' | '{' | '[' => open_brackets += 1,
+                ')' | '}' | ']' => open_brackets -= 1,
+                _ => {}
+            }
+        }
+        open_brackets = open_brackets.max(0);
+    }
+    imports.join("\n")
+}
+
+/// Where the tests for the file at `path` go by the conventions of its
+/// language. Rust tests live in the file itself, as do tests for a file that
+/// already is a test file.
+fn test_file_path(path: &Path) -> PathBuf {
+    let (Some(stem), Some(extension)) = (
+        path.file_stem().and_then(|stem| stem.to_str()),
+        path.extension().and_then(|extension| extension.to_str()),
+    ) else {
+        return path.to_path_buf();
+    };
+    let is_test_file = stem.starts_with("test_")
+        || [".test", ".spec", "_test", "_spec"]
+            .iter()
+            .any(|suffix| stem.ends_with(suffix));
+    if is_test_file {
+        return path.to_path_buf();
+    }
+    let file_name = match extension {
+        "rs" => return path.to_path_buf(),
+        "py" => format!("test_{stem}.py"),
+        "js" | "jsx" | "mjs" | "cjs" | "ts" | "tsx" => format!("{stem}.test.{extension}"),
+        "ex" => format!("{stem}_test.exs"),
+        _ => format!("{stem}_test.{extension}"),
+    };
+    path.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_imports() {
+        assert_eq!(
+            file_imports(
+                "use std::sync::Arc;\nuse gpui::{\n    AppContext,\n    Task,\n};\n\nfn a() {\n    use std::fmt;\n}\n"
+            ),
+            "use std::sync::Arc;\nuse gpui::{\n    AppContext,\n    Task,\n};"
+        );
+        assert_eq!(
+            file_imports("import os\nfrom typing import (\n    List,\n)\n\ndef f():\n    pass\n"),
+            "import os\nfrom typing import (\n    List,\n)"
+        );
+        assert_eq!(file_imports("fn main() {}\n"), "");
+    }
+
+    #[test]
+    fn test_test_file_path() {
+        assert_eq!(
+            test_file_path(Path::new("src/lib.rs")),
+            Path::new("src/lib.rs")
+        );
+        assert_eq!(
+            test_file_path(Path::new("pkg/server.go")),
+            Path::new("pkg/server_test.go")
+        );
+        assert_eq!(
+            test_file_path(Path::new("app/models.py")),
+            Path::new("app/test_models.py")
+        );
+        assert_eq!(
+            test_file_path(Path::new("src/button.tsx")),
+            Path::new("src/button.test.tsx")
+        );
+        assert_eq!(
+            test_file_path(Path::new("src/button.test.tsx")),
+            Path::new("src/button.test.tsx")
+        );
+        assert_eq!(
+            test_file_path(Path::new("lib/parser.ex")),
+            Path::new("lib/parser_test.exs")
+        );
+        assert_eq!(test_file_path(Path::new("Makefile")), Path::new("Makefile"));
+    }
+}
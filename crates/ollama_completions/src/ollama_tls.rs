@@ -0,0 +1,159 @@
+use crate::OllamaSettings;
+use anyhow::{Context as _, Result};
+use http_client::{HttpClient, Uri};
+use ollama::{is_unix_socket_url, UnixSocketClient};
+use parking_lot::Mutex;
+use reqwest_client::{ReqwestClient, TlsOptions};
+use std::{
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+};
+
+/// Clients built for non-default TLS or proxy settings, so each combination
+/// of settings only reads its CA bundle and sets up a connection pool once.
+static CLIENTS: OnceLock<Mutex<Vec<(ClientKey, Arc<dyn HttpClient>)>>> = OnceLock::new();
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ClientKey {
+    ca_bundle_path: Option<PathBuf>,
+    accept_invalid_certs: bool,
+    proxy: Option<Uri>,
+}
+
+/// Returns the client to talk to Ollama with: `default` when the settings
+/// don't change how certificates are verified or which proxy is used,
+/// otherwise a client that trusts the configured CA bundle or skips
+/// verification, and connects through the proxy from [`proxy_for`]. When a
+/// server is a unix socket, requests to it are sent over the socket.
+pub fn http_client_for(
+    settings: &OllamaSettings,
+    default: &Arc<dyn HttpClient>,
+) -> Arc<dyn HttpClient> {
+    let client = tcp_client_for(settings, default);
+    if settings
+        .endpoints()
+        .iter()
+        .any(|url| is_unix_socket_url(url))
+    {
+        Arc::new(UnixSocketClient::new(client))
+    } else {
+        client
+    }
+}
+
+fn tcp_client_for(settings: &OllamaSettings, default: &Arc<dyn HttpClient>) -> Arc<dyn HttpClient> {
+    let key = ClientKey {
+        ca_bundle_path: settings.ca_bundle_path.clone(),
+        accept_invalid_certs: settings.accept_invalid_certs,
+        proxy: proxy_for(settings, default.proxy()),
+    };
+    if key.ca_bundle_path.is_none()
+        && !key.accept_invalid_certs
+        && key.proxy.as_ref() == default.proxy()
+    {
+        return default.clone();
+    }
+
+    let mut clients = CLIENTS.get_or_init(Default::default).lock();
+    if let Some((_, client)) = clients.iter().find(|(existing, _)| *existing == key) {
+        return client.clone();
+    }
+
+    match build_client(&key) {
+        Ok(client) => {
+            clients.push((key, client.clone()));
+            client
+        }
+        Err(error) => {
+            log::error!(
+                "Failed to configure the Ollama HTTP client, using the default one: {error:?}"
+            );
+            default.clone()
+        }
+    }
+}
+
+/// The proxy to reach the Ollama servers through: `proxy` from the Ollama
+/// settings when set, with an empty string meaning none, and otherwise the
+/// editor's proxy (from its `proxy` setting or `HTTPS_PROXY`). When every
+/// server is on this machine they're connected to directly, since a
+/// corporate proxy can't reach them.
+fn proxy_for(settings: &OllamaSettings, editor_proxy: Option<&Uri>) -> Option<Uri> {
+    if let Some(proxy) = settings.proxy.as_deref() {
+        if proxy.is_empty() {
+            return None;
+        }
+        match proxy.parse::<Uri>() {
+            Ok(proxy) => return Some(proxy),
+            Err(error) => log::error!("Invalid Ollama proxy {proxy:?}: {error}"),
+        }
+    }
+    if settings.endpoints().iter().all(|url| is_loopback(url)) {
+        return None;
+    }
+    editor_proxy.cloned()
+}
+
+fn is_loopback(url: &str) -> bool {
+    if is_unix_socket_url(url) {
+        return true;
+    }
+    let Some(host) = url
+        .parse::<Uri>()
+        .ok()
+        .and_then(|uri| uri.host().map(str::to_string))
+    else {
+        return false;
+    };
+    host == "localhost"
+        || host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .map_or(false, |ip| ip.is_loopback())
+}
+
+fn build_client(key: &ClientKey) -> Result<Arc<dyn HttpClient>> {
+    let ca_bundle_pem = key
+        .ca_bundle_path
+        .as_deref()
+        .map(read_ca_bundle)
+        .transpose()?;
+    let tls = TlsOptions {
+        ca_bundle_pem,
+        accept_invalid_certs: key.accept_invalid_certs,
+    };
+    if tls.accept_invalid_certs {
+        log::warn!("Certificate verification is disabled for Ollama servers");
+    }
+    let client = match &key.proxy {
+        Some(proxy) => ReqwestClient::with_tls(Some(proxy.clone()), "Zed", &tls)?,
+        None => ReqwestClient::direct_with_tls("Zed", &tls)?,
+    };
+    Ok(Arc::new(client))
+}
+
+fn read_ca_bundle(path: &Path) -> Result<Vec<u8>> {
+    let path = match path.strip_prefix("~") {
+        Ok(relative) => util::paths::home_dir().join(relative),
+        Err(_) => path.to_path_buf(),
+    };
+    std::fs::read(&path).with_context(|| format!("reading CA bundle {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_loopback() {
+        assert!(is_loopback("http://localhost:11434"));
+        assert!(is_loopback("http://127.0.0.1:11434"));
+        assert!(is_loopback("http://[::1]:11434"));
+        assert!(is_loopback("unix:///run/ollama.sock"));
+        assert!(!is_loopback("https://ollama.internal.example.com"));
+        assert!(!is_loopback("http://10.0.0.5:11434"));
+        assert!(!is_loopback("not a url"));
+    }
+}
@@ -0,0 +1,80 @@
+use std::sync::OnceLock;
+use tiktoken_rs::CoreBPE;
+
+/// Bytes per token assumed if the tokenizer can't be loaded.
+const FALLBACK_BYTES_PER_TOKEN: usize = 3;
+
+/// Counts the tokens in `text`. Ollama doesn't expose the model's tokenizer,
+/// so a general-purpose BPE vocabulary of similar size is used; for code it
+/// comes within a few percent of the coding models' own counts.
+pub fn count_tokens(text: &str) -> usize {
+    static TOKENIZER: OnceLock<Option<CoreBPE>> = OnceLock::new();
+    let tokenizer = TOKENIZER.get_or_init(|| {
+        tiktoken_rs::cl100k_base()
+            .map_err(|error| log::error!("Failed to load tokenizer: {error:?}"))
+            .ok()
+    });
+    match tokenizer {
+        Some(tokenizer) => tokenizer.encode_ordinary(text).len(),
+        None => text.len().div_ceil(FALLBACK_BYTES_PER_TOKEN),
+    }
+}
+
+/// Counts the tokens in `text` a line at a time, consistently with how
+/// [`tail_within`] and [`head_within`] measure it.
+pub(crate) fn count_line_tokens(text: &str) -> usize {
+    text.split_inclusive('\n').map(count_tokens).sum()
+}
+
+/// The byte length of the longest run of whole lines at the end of `text`
+/// that fits in `budget` tokens, and the tokens it takes.
+pub(crate) fn tail_within(text: &str, budget: usize) -> (usize, usize) {
+    fit_lines(text.split_inclusive('\n').rev(), budget)
+}
+
+/// The byte length of the longest run of whole lines at the start of `text`
+/// that fits in `budget` tokens, and the tokens it takes.
+pub(crate) fn head_within(text: &str, budget: usize) -> (usize, usize) {
+    fit_lines(text.split_inclusive('\n'), budget)
+}
+
+fn fit_lines<'a>(lines: impl Iterator<Item = &'a str>, budget: usize) -> (usize, usize) {
+    let mut len = 0;
+    let mut tokens = 0;
+    for line in lines {
+        let line_tokens = count_tokens(line);
+        if tokens + line_tokens > budget {
+            break;
+        }
+        len += line.len();
+        tokens += line_tokens;
+    }
+    (len, tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit_lines() {
+        assert_eq!(count_tokens(""), 0);
+
+        let line = "let x = 1;\n";
+        let line_tokens = count_tokens(line);
+        let text = line.repeat(10);
+        assert_eq!(
+            tail_within(&text, 3 * line_tokens),
+            (3 * line.len(), 3 * line_tokens)
+        );
+        assert_eq!(
+            head_within(&text, 3 * line_tokens + 1),
+            (3 * line.len(), 3 * line_tokens)
+        );
+        assert_eq!(tail_within(&text, 0), (0, 0));
+        assert_eq!(
+            head_within(&text, usize::MAX),
+            (text.len(), 10 * line_tokens)
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use crate::{http_client_for, OllamaDisabled, OllamaSettings};
+use anyhow::Result;
+use editor::{Anchor, Editor, ToOffset as _};
+use futures::StreamExt as _;
+use gpui::{Task, ViewContext};
+use ollama::{stream_transformation, transformation_request, ChatRequest};
+use std::ops::Range;
+
+/// Replaces `range` with the model's rewrite of it following `instruction`,
+/// applying the replacement as it streams in. The text is left alone until the
+/// first chunk arrives, and the whole replacement is undone at once. Dropping
+/// the returned task cancels the request, keeping what was applied so far.
+pub fn transform_range(
+    editor: &mut Editor,
+    range: Range<Anchor>,
+    instruction: &str,
+    cx: &mut ViewContext<Editor>,
+) -> Task<Result<()>> {
+    let snapshot = editor.buffer().read(cx).snapshot(cx);
+    let start = range.start.to_offset(&snapshot);
+    let settings = OllamaSettings::for_file(snapshot.file_at(start), cx);
+    if !settings.enabled {
+        return Task::ready(Err(OllamaDisabled.into()));
+    }
+    let code = snapshot.text_for_range(range.clone()).collect::<String>();
+    let language_name = snapshot
+        .language_at(start)
+        .map(|language| language.name().0.to_string())
+        .unwrap_or_default();
+    let request = transformation_request(
+        settings.resolve_model(&settings.model).to_string(),
+        instruction,
+        &code,
+        &language_name,
+        settings.runtime_options(&settings.model),
+        settings.keep_alive_for(&settings.model),
+    );
+    replace_range(editor, range, request, cx)
+}
+
+/// Replaces `range` with the code the model writes in answer to `request`,
+/// the same way as [`transform_range`].
+pub fn replace_range(
+    editor: &mut Editor,
+    range: Range<Anchor>,
+    mut request: ChatRequest,
+    cx: &mut ViewContext<Editor>,
+) -> Task<Result<()>> {
+    let snapshot = editor.buffer().read(cx).snapshot(cx);
+    let start = range.start.to_offset(&snapshot);
+    let settings = OllamaSettings::for_file(snapshot.file_at(start), cx);
+    let server = OllamaSettings::server_for_file(snapshot.file_at(start), cx);
+    if !settings.enabled {
+        return Task::ready(Err(OllamaDisabled.into()));
+    }
+    if let Some(redactor) = settings.secret_redactor() {
+        redactor.redact_chat_request(&mut request);
+    }
+    let http_client = http_client_for(server, &cx.http_client());
+    let api_url = server.api_url.clone();
+    let api_key = server.effective_api_key();
+
+    cx.spawn(|editor, mut cx| async move {
+        let mut chunks =
+            stream_transformation(http_client.as_ref(), &api_url, api_key.as_deref(), request)
+                .await?;
+        // Where the next chunk goes, biased to the right so it stays after the
+        // text inserted so far. Set once the original text is removed.
+        let mut insertion_point: Option<Anchor> = None;
+        let mut first_transaction = None;
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk?;
+            editor.update(&mut cx, |editor, cx| {
+                editor.buffer().update(cx, |buffer, cx| {
+                    buffer.start_transaction(cx);
+                    let position = *insertion_point.get_or_insert_with(|| {
+                        buffer.edit([(range.clone(), "")], None, cx);
+                        let start = range.start.to_offset(&buffer.snapshot(cx));
+                        buffer.snapshot(cx).anchor_after(start)
+                    });
+                    buffer.edit([(position..position, chunk)], None, cx);
+                    if let Some(transaction) = buffer.end_transaction(cx) {
+                        match first_transaction {
+                            Some(first) => buffer.group_until_transaction(first, cx),
+                            None => first_transaction = Some(transaction),
+                        }
+                    }
+                });
+            })?;
+        }
+        Ok(())
+    })
+}
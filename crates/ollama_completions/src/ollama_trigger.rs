@@ -0,0 +1,89 @@
+use crate::PrefixScope;
+use editor::{actions::ShowInlineCompletion, Editor};
+use gpui::AppContext;
+use language::{BufferSnapshot, CharClassifier};
+use zed_actions::ollama::RequestCompletion;
+
+/// Registers `ollama: request completion`, which asks for a suggestion at the
+/// cursor even when completions aren't requested as you type.
+pub(crate) fn init(cx: &mut AppContext) {
+    cx.observe_new_views(|editor: &mut Editor, cx| {
+        editor
+            .register_action(cx.listener(|editor, _: &RequestCompletion, cx| {
+                editor.show_inline_completion(&ShowInlineCompletion, cx)
+            }))
+            .detach();
+    })
+    .detach();
+}
+
+/// Whether the cursor at `offset` is in the middle of an identifier, with
+/// word characters on both sides of it, so a suggestion would split it.
+pub(crate) fn is_mid_identifier(snapshot: &BufferSnapshot, offset: usize) -> bool {
+    splits_word(
+        snapshot.reversed_chars_at(offset).next(),
+        snapshot.chars_at(offset).next(),
+        &snapshot.char_classifier_at(offset),
+    )
+}
+
+/// Whether there are at least `min_chars` non-whitespace characters before
+/// `offset`, counting only the cursor's line when `scope` is `Line`.
+pub(crate) fn has_min_prefix(
+    snapshot: &BufferSnapshot,
+    offset: usize,
+    min_chars: usize,
+    scope: PrefixScope,
+) -> bool {
+    counts_min_prefix(snapshot.reversed_chars_at(offset), min_chars, scope)
+}
+
+fn counts_min_prefix(
+    reversed: impl Iterator<Item = char>,
+    min_chars: usize,
+    scope: PrefixScope,
+) -> bool {
+    min_chars == 0
+        || reversed
+            .take_while(|c| scope == PrefixScope::File || *c != '\n')
+            .filter(|c| !c.is_whitespace())
+            .take(min_chars)
+            .count()
+            == min_chars
+}
+
+fn splits_word(before: Option<char>, after: Option<char>, classifier: &CharClassifier) -> bool {
+    before.zip(after).map_or(false, |(before, after)| {
+        classifier.is_word(before) && classifier.is_word(after)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_splits_word() {
+        let classifier = CharClassifier::default();
+        assert!(splits_word(Some('o'), Some('o'), &classifier));
+        assert!(splits_word(Some('_'), Some('1'), &classifier));
+        // The end of an identifier is where most completions start.
+        assert!(!splits_word(Some('o'), Some(')'), &classifier));
+        assert!(!splits_word(Some('o'), None, &classifier));
+        assert!(!splits_word(Some(' '), Some('f'), &classifier));
+        assert!(!splits_word(None, Some('f'), &classifier));
+    }
+
+    #[test]
+    fn test_counts_min_prefix() {
+        let prefix =
+            |text: &str, min_chars, scope| counts_min_prefix(text.chars().rev(), min_chars, scope);
+        assert!(prefix("", 0, PrefixScope::File));
+        assert!(!prefix("", 1, PrefixScope::File));
+        assert!(!prefix("\n  \n\t", 1, PrefixScope::File));
+        assert!(prefix("fn main() {\n    ", 3, PrefixScope::File));
+        assert!(!prefix("fn main() {\n    ", 3, PrefixScope::Line));
+        assert!(prefix("fn main() {\n    let", 3, PrefixScope::Line));
+        assert!(!prefix("fn main() {\n    l t", 3, PrefixScope::Line));
+    }
+}
@@ -0,0 +1,157 @@
+use crate::{OllamaSettings, VerbatimSuggestions};
+use std::{
+    collections::{hash_map::DefaultHasher, HashSet},
+    hash::{Hash, Hasher},
+};
+
+/// How many of a suggestion's lines the project is searched for, to find the
+/// files it may have been copied from.
+const SEARCH_LINES: usize = 3;
+/// The most files a suggestion is compared with.
+pub(crate) const MAX_VERBATIM_CANDIDATES: usize = 16;
+
+/// How suggestions are checked for text copied from elsewhere in the
+/// project, from `verbatim_check_min_chars`, `verbatim_similarity` and
+/// `verbatim_suggestions`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct VerbatimCheck {
+    pub min_chars: usize,
+    pub min_similarity: f32,
+    pub action: VerbatimSuggestions,
+}
+
+impl VerbatimCheck {
+    pub fn new(settings: &OllamaSettings) -> Self {
+        Self {
+            min_chars: settings.verbatim_check_min_chars,
+            min_similarity: settings.verbatim_similarity.clamp(0.0, 1.0),
+            action: settings.verbatim_suggestions,
+        }
+    }
+
+    /// Whether `text` is long enough to be checked.
+    pub fn applies_to(&self, text: &str) -> bool {
+        self.min_chars > 0 && text.len() >= self.min_chars
+    }
+}
+
+/// The hashes of a suggestion's lines, for telling how much of it another
+/// file already contains. Indentation and spacing are ignored, as are lines
+/// without a word in them, like closing brackets, which every file has.
+pub(crate) struct Fingerprint {
+    lines: Vec<u64>,
+    search_lines: Vec<String>,
+}
+
+impl Fingerprint {
+    /// `None` when `text` has no lines worth comparing. Its first line is
+    /// left out unless it's the only one, since it usually finishes the
+    /// cursor's line rather than being a line of its own.
+    pub fn new(text: &str) -> Option<Self> {
+        let skip = usize::from(text.contains('\n'));
+        let significant = text
+            .lines()
+            .skip(skip)
+            .map(str::trim)
+            .filter(|line| is_significant(line))
+            .collect::<Vec<_>>();
+        if significant.is_empty() {
+            return None;
+        }
+
+        let mut search_lines = significant.clone();
+        search_lines.sort_by_key(|line| std::cmp::Reverse(line.len()));
+        search_lines.dedup();
+        search_lines.truncate(SEARCH_LINES);
+        Some(Self {
+            lines: significant.iter().map(|line| line_hash(line)).collect(),
+            search_lines: search_lines.into_iter().map(ToString::to_string).collect(),
+        })
+    }
+
+    /// The suggestion's longest lines, which the project is searched for to
+    /// find the files worth comparing it with.
+    pub fn search_lines(&self) -> &[String] {
+        &self.search_lines
+    }
+
+    /// The share of the suggestion's lines that `other` contains, from 0.0
+    /// to 1.0.
+    pub fn similarity(&self, other: &str) -> f32 {
+        let other = other
+            .lines()
+            .map(str::trim)
+            .filter(|line| is_significant(line))
+            .map(line_hash)
+            .collect::<HashSet<_>>();
+        let contained = self
+            .lines
+            .iter()
+            .filter(|line| other.contains(line))
+            .count();
+        contained as f32 / self.lines.len() as f32
+    }
+}
+
+fn is_significant(line: &str) -> bool {
+    line.chars().any(char::is_alphanumeric)
+}
+
+fn line_hash(line: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for word in line.split_whitespace() {
+        word.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LICENSE: &str = "\
+// Copyright 2024 The Example Authors
+//
+// Licensed under the Apache License, Version 2.0 (the \"License\");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+";
+
+    #[test]
+    fn test_similarity() {
+        let other = format!("{LICENSE}\nuse std::fmt;\n\nfn main() {{}}\n");
+
+        // The first line finishes the cursor's line, so it isn't compared.
+        let copied = format!("ight 2024 The Example Authors\n{}", &LICENSE[38..]);
+        let fingerprint = Fingerprint::new(&copied).unwrap();
+        assert_eq!(fingerprint.similarity(&other), 1.0);
+
+        // Reindented and respaced, it's still the same text.
+        let reformatted = copied.replace("// ", "    //   ");
+        assert_eq!(
+            Fingerprint::new(&reformatted).unwrap().similarity(&other),
+            1.0
+        );
+
+        // A different year and holder only change one of the lines.
+        let relicensed = format!("\n{}", LICENSE.replace("2024 The Example", "2025 Other"));
+        let fingerprint = Fingerprint::new(&relicensed).unwrap();
+        assert_eq!(fingerprint.similarity(&other), 0.8);
+        assert_eq!(
+            fingerprint.search_lines(),
+            [
+                "// you may not use this file except in compliance with the License.",
+                "// Licensed under the Apache License, Version 2.0 (the \"License\");",
+                "//     http://www.apache.org/licenses/LICENSE-2.0",
+            ]
+        );
+
+        // Brackets and blank lines don't count towards a match.
+        let unrelated = "\n    let total = items.len();\n}\n\n}\n";
+        let fingerprint = Fingerprint::new(unrelated).unwrap();
+        assert_eq!(fingerprint.similarity(&other), 0.0);
+        assert!(Fingerprint::new("\n    }\n}\n").is_none());
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{http_client_for, ApiFormat, OllamaSettings};
+use gpui::{AppContext, Global, Task};
+use http_client::HttpClient;
+use ollama::{
+    generate, is_cloud_model, is_cloud_url, GenerateRequest, RequestPriority, RetryPolicy,
+};
+use settings::Settings as _;
+use std::{
+    collections::HashSet,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// The models each server has already been asked to load, so every editor
+/// registering a provider doesn't send its own warm-up request.
+#[derive(Default)]
+struct WarmedModels(HashSet<(String, String)>);
+
+impl Global for WarmedModels {}
+
+/// Asks every configured server to load the inline completion model ahead of
+/// the first completion, which would otherwise wait while the weights are read
+/// from disk. The request has an empty prompt, so nothing is generated, and
+/// keeps the model resident like completion requests do.
+pub fn warm_up(http_client: &Arc<dyn HttpClient>, cx: &mut AppContext) {
+    let settings = OllamaSettings::get_global(cx);
+    // OpenAI-compatible servers load their model at startup.
+    if !settings.enabled || !settings.warm_up || settings.api_format == ApiFormat::OpenAi {
+        return;
+    }
+    let http_client = http_client_for(settings, http_client);
+    let api_key = settings.effective_api_key();
+    let request = load_request(settings);
+    let model = request.model.clone();
+    let endpoints = settings.endpoints();
+
+    let warmed = cx.default_global::<WarmedModels>();
+    let endpoints = endpoints
+        .into_iter()
+        .filter(|endpoint| warmed.0.insert((endpoint.clone(), model.clone())))
+        .collect::<Vec<_>>();
+
+    for endpoint in endpoints {
+        let http_client = http_client.clone();
+        let api_key = api_key.clone();
+        let model = model.clone();
+        let request = request.clone();
+        cx.spawn(|mut cx| async move {
+            let result = generate(
+                http_client.as_ref(),
+                &endpoint,
+                api_key.as_deref(),
+                request,
+                RetryPolicy::default(),
+                None,
+            )
+            .await;
+            if let Err(error) = result {
+                log::warn!("Failed to load {model} on {endpoint}: {error:?}");
+                // Let the next provider registration try again.
+                cx.update(|cx| {
+                    cx.default_global::<WarmedModels>()
+                        .0
+                        .remove(&(endpoint, model));
+                })
+                .ok();
+            }
+        })
+        .detach();
+    }
+}
+
+/// A request with an empty prompt, which loads the completion model without
+/// generating anything.
+fn load_request(settings: &OllamaSettings) -> GenerateRequest {
+    GenerateRequest {
+        model: settings.resolve_model(&settings.model).to_string(),
+        prompt: String::new(),
+        suffix: None,
+        stream: false,
+        keep_alive: settings.keep_alive_for(&settings.model),
+        // Loading with different options would make the next completion
+        // reload it.
+        options: Some(settings.runtime_options(&settings.model)),
+        system: None,
+        raw: None,
+        format: None,
+        context: None,
+        priority: RequestPriority::Background,
+    }
+}
+
+/// The task sending heartbeats while a window has focus.
+#[derive(Default)]
+struct Heartbeat {
+    task: Option<Task<()>>,
+    last_beat: Option<Instant>,
+}
+
+impl Global for Heartbeat {}
+
+/// Starts or stops the heartbeat that keeps the completion model loaded, as
+/// a Zed window gains or loses focus. While it runs, the load request is sent
+/// to every configured server each `heartbeat_secs`, so the model doesn't
+/// unload while the user is reading. Switching between windows doesn't send
+/// extra requests, since the interval is measured from the last heartbeat.
+pub fn heartbeat(window_focused: bool, http_client: &Arc<dyn HttpClient>, cx: &mut AppContext) {
+    let heartbeat = cx.default_global::<Heartbeat>();
+    if !window_focused {
+        heartbeat.task = None;
+        return;
+    }
+    if heartbeat.task.is_some() {
+        return;
+    }
+    let mut since_last_beat = heartbeat.last_beat.map(|last_beat| last_beat.elapsed());
+    let http_client = http_client.clone();
+    let task = cx.spawn(|mut cx| async move {
+        loop {
+            let Ok(interval) =
+                cx.update(|cx| Duration::from_secs(OllamaSettings::get_global(cx).heartbeat_secs))
+            else {
+                return;
+            };
+            let delay = if interval.is_zero() {
+                // Check back later in case the setting is turned on again.
+                Duration::from_secs(60)
+            } else {
+                since_last_beat.map_or(Duration::ZERO, |since| interval.saturating_sub(since))
+            };
+            smol::Timer::after(delay).await;
+            since_last_beat = Some(Duration::ZERO);
+
+            let Ok(beat) = cx.update(|cx| {
+                let settings = OllamaSettings::get_global(cx);
+                let request = load_request(settings);
+                // Hosted models are always loaded, and each request counts
+                // towards the account's usage limit.
+                if !settings.enabled
+                    || settings.heartbeat_secs == 0
+                    || settings.api_format == ApiFormat::OpenAi
+                    || is_cloud_model(&request.model)
+                    || settings.endpoints().iter().all(|url| is_cloud_url(url))
+                {
+                    return None;
+                }
+                let beat = (
+                    http_client_for(settings, &http_client),
+                    settings.endpoints(),
+                    settings.effective_api_key(),
+                    request,
+                );
+                cx.default_global::<Heartbeat>().last_beat = Some(Instant::now());
+                Some(beat)
+            }) else {
+                return;
+            };
+            let Some((client, endpoints, api_key, request)) = beat else {
+                continue;
+            };
+            for endpoint in endpoints {
+                let result = generate(
+                    client.as_ref(),
+                    &endpoint,
+                    api_key.as_deref(),
+                    request.clone(),
+                    RetryPolicy::default().with_max_attempts(1),
+                    None,
+                )
+                .await;
+                if let Err(error) = result {
+                    log::debug!("Ollama heartbeat to {endpoint} failed: {error:?}");
+                }
+            }
+        }
+    });
+    cx.default_global::<Heartbeat>().task = Some(task);
+}
@@ -246,6 +246,25 @@ impl Output {
             })
     }
 
+    /// The output as plain text, for outputs that have a text form.
+    pub fn text(&self, cx: &WindowContext) -> Option<String> {
+        match self {
+            Output::Plain { content, .. } | Output::Stream { content } => {
+                Some(content.read(cx).full_text())
+            }
+            Output::ErrorOutput(error) => Some(format!(
+                "{}: {}\n{}",
+                error.ename,
+                error.evalue,
+                error.traceback.read(cx).full_text()
+            )),
+            Output::Message(message) => Some(message.clone()),
+            Output::Table { content, .. } => content.clipboard_content(cx)?.text(),
+            Output::Markdown { content, .. } => content.clipboard_content(cx)?.text(),
+            Output::Image { .. } | Output::ClearOutputWaitMarker => None,
+        }
+    }
+
     pub fn display_id(&self) -> Option<String> {
         match self {
             Output::Plain { display_id, .. } => display_id.clone(),
@@ -424,6 +443,15 @@ impl ExecutionView {
         }
     }
 
+    /// The text of every output that has one, in order.
+    pub fn text(&self, cx: &WindowContext) -> String {
+        self.outputs
+            .iter()
+            .filter_map(|output| output.text(cx))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
     fn apply_terminal_text(&mut self, text: &str, cx: &mut ViewContext<Self>) -> Option<Output> {
         if let Some(last_output) = self.outputs.last_mut() {
             if let Output::Stream {
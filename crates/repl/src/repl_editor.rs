@@ -4,8 +4,8 @@ use std::ops::Range;
 use std::sync::Arc;
 
 use anyhow::{Context, Result};
-use editor::Editor;
-use gpui::{prelude::*, Entity, View, WeakView, WindowContext};
+use editor::{Editor, ToPoint as _};
+use gpui::{prelude::*, Entity, View, ViewContext, WeakView, WindowContext};
 use language::{BufferSnapshot, Language, LanguageName, Point};
 use project::{Item as _, WorktreeId};
 
@@ -159,6 +159,58 @@ pub fn run(editor: WeakView<Editor>, move_down: bool, cx: &mut WindowContext) ->
     anyhow::Ok(())
 }
 
+/// A code cell in an editor: a Jupytext cell, or a code block in Markdown.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NotebookCell {
+    /// The whole cell, as it's run.
+    pub range: Range<Point>,
+    /// The cell's code, without its Jupytext marker line.
+    pub code_range: Range<Point>,
+    /// The text of what the cell output when it was last run in the editor's
+    /// REPL session.
+    pub output: Option<String>,
+}
+
+/// The code cells in the editor's buffer, in order, with their outputs.
+pub fn notebook_cells(editor: &Editor, cx: &ViewContext<Editor>) -> Vec<NotebookCell> {
+    let multibuffer = editor.buffer().read(cx);
+    let Some(buffer) = multibuffer.as_singleton() else {
+        return Vec::new();
+    };
+    let snapshot = buffer.read(cx).snapshot();
+    let multibuffer_snapshot = multibuffer.snapshot(cx);
+    let outputs = ReplStore::global(cx)
+        .read(cx)
+        .get_session(cx.view().entity_id())
+        .map(|session| session.read(cx).outputs(cx))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(range, text)| {
+            let range = range.start.to_point(&multibuffer_snapshot)
+                ..range.end.to_point(&multibuffer_snapshot);
+            (range, text)
+        })
+        .collect::<Vec<_>>();
+
+    let prefixes = jupytext_prefixes(&snapshot);
+    all_cells(&snapshot)
+        .into_iter()
+        .map(|range| {
+            let output = outputs
+                .iter()
+                .find(|(output_range, _)| {
+                    output_range.start <= range.end && range.start <= output_range.end
+                })
+                .map(|(_, text)| text.clone());
+            NotebookCell {
+                code_range: cell_code(&snapshot, &range, &prefixes),
+                range,
+                output,
+            }
+        })
+        .collect()
+}
+
 #[allow(clippy::large_enum_variant)]
 pub enum SessionSupport {
     ActiveSession(View<Session>),
@@ -338,6 +390,58 @@ fn cell_range(buffer: &BufferSnapshot, start_row: u32, end_row: u32) -> Range<Po
     Point::new(start_row, 0)..Point::new(snippet_end_row, buffer.line_len(snippet_end_row))
 }
 
+/// The markers that start a Jupytext cell in the buffer's language.
+fn jupytext_prefixes(buffer: &BufferSnapshot) -> Vec<String> {
+    let Some(language) = buffer.language() else {
+        return Vec::new();
+    };
+    language
+        .default_scope()
+        .line_comment_prefixes()
+        .iter()
+        .map(|comment_prefix| format!("{comment_prefix}%%"))
+        .collect()
+}
+
+/// Every cell in the buffer, whether or not the cursor is in it.
+fn all_cells(buffer: &BufferSnapshot) -> Vec<Range<Point>> {
+    if let Some(language) = buffer.language() {
+        if language.name() == "Markdown".into() {
+            return markdown_code_blocks(buffer, Point::zero()..buffer.max_point());
+        }
+    }
+
+    let prefixes = jupytext_prefixes(buffer);
+    let first_cell_row = (0..=buffer.max_point().row).find(|row| {
+        prefixes
+            .iter()
+            .any(|prefix| buffer.contains_str_at(Point::new(*row, 0), prefix))
+    });
+    match first_cell_row {
+        Some(row) => jupytext_cells(buffer, Point::new(row, 0)..buffer.max_point()).0,
+        None => Vec::new(),
+    }
+}
+
+/// The code in `cell`, after its Jupytext marker line if it has one. A cell
+/// with only a marker gets an empty range where its code would start.
+fn cell_code(buffer: &BufferSnapshot, cell: &Range<Point>, prefixes: &[String]) -> Range<Point> {
+    let has_marker = prefixes
+        .iter()
+        .any(|prefix| buffer.contains_str_at(cell.start, prefix));
+    if !has_marker {
+        return cell.clone();
+    }
+    if cell.end.row > cell.start.row {
+        Point::new(cell.start.row + 1, 0)..cell.end
+    } else if cell.start.row < buffer.max_point().row {
+        let start = Point::new(cell.start.row + 1, 0);
+        start..start
+    } else {
+        cell.end..cell.end
+    }
+}
+
 // Returns the ranges of the snippets in the buffer and the next point for moving the cursor to
 fn jupytext_cells(
     buffer: &BufferSnapshot,
@@ -345,21 +449,11 @@ fn jupytext_cells(
 ) -> (Vec<Range<Point>>, Option<Point>) {
     let mut current_row = range.start.row;
 
-    let Some(language) = buffer.language() else {
-        return (Vec::new(), None);
-    };
-
-    let default_scope = language.default_scope();
-    let comment_prefixes = default_scope.line_comment_prefixes();
-    if comment_prefixes.is_empty() {
+    let jupytext_prefixes = jupytext_prefixes(buffer);
+    if jupytext_prefixes.is_empty() {
         return (Vec::new(), None);
     }
 
-    let jupytext_prefixes = comment_prefixes
-        .iter()
-        .map(|comment_prefix| format!("{comment_prefix}%%"))
-        .collect::<Vec<_>>();
-
     let mut snippet_start_row = None;
     loop {
         if jupytext_prefixes
@@ -614,6 +708,65 @@ mod tests {
         );
     }
 
+    #[gpui::test]
+    fn test_all_cells(cx: &mut AppContext) {
+        let test_language = Arc::new(Language::new(
+            LanguageConfig {
+                name: "TestLang".into(),
+                line_comments: vec!["# ".into()],
+                ..Default::default()
+            },
+            None,
+        ));
+
+        let buffer = cx.new_model(|cx| {
+            Buffer::local(
+                indoc! { r#"
+                    import math
+                    # %%
+                    x = math.pi
+                    print(x)
+
+                    # %%
+
+                    # %%
+                    print(x * 2)
+                    # %%"# },
+                cx,
+            )
+            .with_language(test_language, cx)
+        });
+        let snapshot = buffer.read(cx).snapshot();
+        let prefixes = jupytext_prefixes(&snapshot);
+
+        // Text before the first marker isn't part of a cell.
+        let cells = all_cells(&snapshot);
+        assert_eq!(
+            cells,
+            vec![
+                Point::new(1, 0)..Point::new(3, 8),
+                Point::new(5, 0)..Point::new(5, 4),
+                Point::new(7, 0)..Point::new(8, 12),
+                Point::new(9, 0)..Point::new(9, 4),
+            ]
+        );
+        let code = cells
+            .iter()
+            .map(|cell| cell_code(&snapshot, cell, &prefixes))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            code,
+            vec![
+                Point::new(2, 0)..Point::new(3, 8),
+                // An empty cell's code starts on the line after its marker,
+                // or at the end of the buffer.
+                Point::new(6, 0)..Point::new(6, 0),
+                Point::new(8, 0)..Point::new(8, 12),
+                Point::new(9, 4)..Point::new(9, 4),
+            ]
+        );
+    }
+
     #[gpui::test]
     fn test_markdown_code_blocks(cx: &mut AppContext) {
         let markdown = languages::language("markdown", tree_sitter_md::LANGUAGE.into());
@@ -428,6 +428,20 @@ impl Session {
         anyhow::Ok(())
     }
 
+    /// The code ranges that were run in this session, with the text of what
+    /// they output.
+    pub fn outputs(&self, cx: &WindowContext) -> Vec<(Range<Anchor>, String)> {
+        self.blocks
+            .values()
+            .map(|block| {
+                (
+                    block.code_range.clone(),
+                    block.execution_view.read(cx).text(cx),
+                )
+            })
+            .collect()
+    }
+
     pub fn clear_outputs(&mut self, cx: &mut ViewContext<Self>) {
         let blocks_to_remove: HashSet<CustomBlockId> =
             self.blocks.values().map(|block| block.block_id).collect();
@@ -13,6 +13,16 @@ use smol::future::FutureExt;
 const DEFAULT_CAPACITY: usize = 4096;
 static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
 
+/// Trust settings beyond the platform's root certificates.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct TlsOptions {
+    /// PEM-encoded certificates to trust in addition to the system roots.
+    pub ca_bundle_pem: Option<Vec<u8>>,
+    /// Skips certificate verification entirely. Only meant for servers on a
+    /// trusted network whose certificate can't be added to `ca_bundle_pem`.
+    pub accept_invalid_certs: bool,
+}
+
 pub struct ReqwestClient {
     client: reqwest::Client,
     proxy: Option<http::Uri>,
@@ -41,11 +51,20 @@ impl ReqwestClient {
     }
 
     pub fn proxy_and_user_agent(proxy: Option<http::Uri>, agent: &str) -> anyhow::Result<Self> {
-        let mut map = HeaderMap::new();
-        map.insert(http::header::USER_AGENT, HeaderValue::from_str(agent)?);
-        let mut client = Self::builder().default_headers(map);
+        Self::with_tls(proxy, agent, &TlsOptions::default())
+    }
+
+    /// Like [`Self::proxy_and_user_agent`], but also trusts the given
+    /// certificates, for servers using a private or self-signed CA.
+    pub fn with_tls(
+        proxy: Option<http::Uri>,
+        agent: &str,
+        tls: &TlsOptions,
+    ) -> anyhow::Result<Self> {
+        let mut client = Self::tls_builder(agent, tls)?;
         if let Some(proxy) = proxy.clone().and_then(|proxy_uri| {
             reqwest::Proxy::all(proxy_uri.to_string())
+                .map(|proxy| proxy.no_proxy(reqwest::NoProxy::from_env()))
                 .inspect_err(|e| log::error!("Failed to parse proxy URI {}: {}", proxy_uri, e))
                 .ok()
         }) {
@@ -56,6 +75,27 @@ impl ReqwestClient {
         client.proxy = proxy;
         Ok(client)
     }
+
+    /// Like [`Self::with_tls`], but always connects directly, ignoring the
+    /// proxy environment variables and system settings.
+    pub fn direct_with_tls(agent: &str, tls: &TlsOptions) -> anyhow::Result<Self> {
+        Ok(Self::tls_builder(agent, tls)?.no_proxy().build()?.into())
+    }
+
+    fn tls_builder(agent: &str, tls: &TlsOptions) -> anyhow::Result<reqwest::ClientBuilder> {
+        let mut map = HeaderMap::new();
+        map.insert(http::header::USER_AGENT, HeaderValue::from_str(agent)?);
+        let mut client = Self::builder().default_headers(map);
+        if let Some(pem) = tls.ca_bundle_pem.as_deref() {
+            for certificate in reqwest::Certificate::from_pem_bundle(pem)? {
+                client = client.add_root_certificate(certificate);
+            }
+        }
+        if tls.accept_invalid_certs {
+            client = client.danger_accept_invalid_certs(true);
+        }
+        Ok(client)
+    }
 }
 
 impl From<reqwest::Client> for ReqwestClient {
@@ -1,7 +1,7 @@
-use anyhow::{Context as _, Result};
-use futures::{future::BoxFuture, AsyncReadExt as _, FutureExt};
+use anyhow::Result;
+use futures::{future::BoxFuture, FutureExt};
 use http_client::HttpClient;
-use serde::{Deserialize, Serialize};
+use ollama::{embed_batched, resolve_api_key, EmbedBatchLimits, OLLAMA_API_URL};
 use std::sync::Arc;
 
 use crate::{Embedding, EmbeddingProvider, TextToEmbed};
@@ -16,17 +16,6 @@ pub struct OllamaEmbeddingProvider {
     model: OllamaEmbeddingModel,
 }
 
-#[derive(Serialize)]
-struct OllamaEmbeddingRequest {
-    model: String,
-    prompt: String,
-}
-
-#[derive(Deserialize)]
-struct OllamaEmbeddingResponse {
-    embedding: Vec<f32>,
-}
-
 impl OllamaEmbeddingProvider {
     pub fn new(client: Arc<dyn HttpClient>, model: OllamaEmbeddingModel) -> Self {
         Self { client, model }
@@ -35,40 +24,35 @@ impl OllamaEmbeddingProvider {
 
 impl EmbeddingProvider for OllamaEmbeddingProvider {
     fn embed<'a>(&'a self, texts: &'a [TextToEmbed<'a>]) -> BoxFuture<'a, Result<Vec<Embedding>>> {
-        //
         let model = match self.model {
             OllamaEmbeddingModel::NomicEmbedText => "nomic-embed-text",
             OllamaEmbeddingModel::MxbaiEmbedLarge => "mxbai-embed-large",
         };
 
-        futures::future::try_join_all(texts.iter().map(|to_embed| {
-            let request = OllamaEmbeddingRequest {
-                model: model.to_string(),
-                prompt: to_embed.text.to_string(),
-            };
-
-            let request = serde_json::to_string(&request).unwrap();
-
-            async {
-                let response = self
-                    .client
-                    .post_json("http://localhost:11434/api/embeddings", request.into())
-                    .await?;
-
-                let mut body = String::new();
-                response.into_body().read_to_string(&mut body).await?;
-
-                let response: OllamaEmbeddingResponse =
-                    serde_json::from_str(&body).context("Unable to pull response")?;
-
-                Ok(Embedding::new(response.embedding))
-            }
-        }))
+        async move {
+            let inputs = texts
+                .iter()
+                .map(|to_embed| to_embed.text)
+                .collect::<Vec<_>>();
+            let api_key = resolve_api_key(None);
+            let embeddings = embed_batched(
+                self.client.as_ref(),
+                OLLAMA_API_URL,
+                api_key.as_deref(),
+                model,
+                &inputs,
+                EmbedBatchLimits::default(),
+            )
+            .await?;
+            Ok(embeddings.into_iter().map(Embedding::new).collect())
+        }
         .boxed()
     }
 
     fn batch_size(&self) -> usize {
-        // TODO: Figure out decent value
-        10
+        // `embed_batched` splits these into requests of its own, so this only
+        // needs to be large enough to keep a couple of them in flight.
+        let limits = EmbedBatchLimits::default();
+        limits.max_inputs * limits.max_in_flight
     }
 }
@@ -1,7 +1,9 @@
 use crate::{Supermaven, SupermavenCompletionStateId};
 use anyhow::Result;
 use client::telemetry::Telemetry;
-use editor::{CompletionProposal, Direction, InlayProposal, InlineCompletionProvider};
+use editor::{
+    CompletionMetadata, CompletionProposal, Direction, InlayProposal, InlineCompletionProvider,
+};
 use futures::StreamExt as _;
 use gpui::{AppContext, EntityId, Model, ModelContext, Task};
 use language::{language_settings::all_language_settings, Anchor, Buffer, BufferSnapshot};
@@ -104,6 +106,7 @@ fn completion_state_from_diff(
         inlays,
         text: completion_text.into(),
         delete_range: Some(delete_range),
+        metadata: Some(CompletionMetadata::new(SupermavenCompletionProvider::name())),
     }
 }
 
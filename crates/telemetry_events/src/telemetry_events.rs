@@ -128,6 +128,47 @@ pub struct InlineCompletionEvent {
     pub provider: String,
     pub suggestion_accepted: bool,
     pub file_extension: Option<String>,
+    /// Performance of the request that produced the suggestion, for providers
+    /// that report it and users who have opted in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<InlineCompletionMetrics>,
+    /// How much of an accepted suggestion was inserted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acceptance: Option<InlineCompletionAcceptance>,
+    /// Whether the user cycled to other candidates for the same position
+    #[serde(default)]
+    pub cycled: bool,
+    /// Position of the shown suggestion among the candidates, starting at 0
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub candidate_index: Option<usize>,
+    /// Number of candidates the user could cycle through
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub candidate_count: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InlineCompletionAcceptance {
+    /// The whole suggestion
+    Full,
+    /// The next word of the suggestion
+    Word,
+    /// The next line of the suggestion
+    Line,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InlineCompletionMetrics {
+    /// The model that produced the suggestion
+    pub model: String,
+    /// Time from sending the request to receiving the full response
+    pub latency_ms: u64,
+    /// Number of tokens in the prompt
+    pub prompt_tokens: Option<u64>,
+    /// Number of tokens generated
+    pub completion_tokens: Option<u64>,
+    /// Generation speed, excluding prompt processing
+    pub tokens_per_second: Option<f64>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
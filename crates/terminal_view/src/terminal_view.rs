@@ -6,10 +6,10 @@ use collections::HashSet;
 use editor::{actions::SelectAll, scroll::Autoscroll, Editor};
 use futures::{stream::FuturesUnordered, StreamExt};
 use gpui::{
-    anchored, deferred, div, impl_actions, AnyElement, AppContext, DismissEvent, EventEmitter,
-    FocusHandle, FocusableView, KeyContext, KeyDownEvent, Keystroke, Model, MouseButton,
-    MouseDownEvent, Pixels, Render, ScrollWheelEvent, Styled, Subscription, Task, View,
-    VisualContext, WeakView,
+    actions, anchored, deferred, div, impl_actions, AnyElement, AppContext, DismissEvent,
+    EventEmitter, FocusHandle, FocusableView, KeyContext, KeyDownEvent, Keystroke, Model,
+    MouseButton, MouseDownEvent, Pixels, Render, ScrollWheelEvent, Styled, Subscription, Task,
+    View, VisualContext, WeakView,
 };
 use language::Bias;
 use persistence::TERMINAL_DB;
@@ -60,6 +60,11 @@ const CURSOR_BLINK_INTERVAL: Duration = Duration::from_millis(500);
 
 const GIT_DIFF_PATH_PREFIXES: &[char] = &['a', 'b'];
 
+/// How long typing has to pause before a command suggestion is requested.
+const COMMAND_SUGGESTION_DELAY: Duration = Duration::from_millis(300);
+/// How many lines of output above the command line a suggestion is based on.
+const COMMAND_SUGGESTION_CONTEXT_LINES: usize = 20;
+
 ///Event to transmit the scroll from the element to the view
 #[derive(Clone, Debug, PartialEq)]
 pub struct ScrollTerminal(pub i32);
@@ -72,6 +77,8 @@ pub struct SendKeystroke(String);
 
 impl_actions!(terminal, [SendText, SendKeystroke]);
 
+actions!(terminal, [AcceptCommandSuggestion]);
+
 pub fn init(cx: &mut AppContext) {
     terminal_panel::init(cx);
     terminal::init(cx);
@@ -94,6 +101,18 @@ pub struct BlockContext<'a, 'b> {
     pub dimensions: TerminalSize,
 }
 
+/// Proposes the rest of the command being typed at a shell prompt.
+pub trait CommandSuggestionProvider {
+    /// Suggests the text to type after `command_line`, the line under the
+    /// cursor including the shell's prompt, given the lines of output above it.
+    fn suggest(
+        &self,
+        command_line: String,
+        recent_output: Vec<String>,
+        cx: &mut AppContext,
+    ) -> Task<anyhow::Result<Option<String>>>;
+}
+
 ///A terminal view, maintains the PTY's file handles and communicates with the terminal
 pub struct TerminalView {
     terminal: Model<Terminal>,
@@ -111,6 +130,10 @@ pub struct TerminalView {
     workspace_id: Option<WorkspaceId>,
     show_title: bool,
     block_below_cursor: Option<Rc<BlockProperties>>,
+    command_suggestion_provider: Option<Rc<dyn CommandSuggestionProvider>>,
+    /// Shown below the cursor while set.
+    command_suggestion: Option<String>,
+    pending_command_suggestion: Task<()>,
     scroll_top: Pixels,
     _subscriptions: Vec<Subscription>,
     _terminal_subscriptions: Vec<Subscription>,
@@ -191,6 +214,9 @@ impl TerminalView {
             workspace_id,
             show_title: TerminalSettings::get_global(cx).toolbar.title,
             block_below_cursor: None,
+            command_suggestion_provider: None,
+            command_suggestion: None,
+            pending_command_suggestion: Task::ready(()),
             scroll_top: Pixels::ZERO,
             _subscriptions: vec![
                 focus_in,
@@ -494,17 +520,123 @@ impl TerminalView {
     }
 
     pub fn set_block_below_cursor(&mut self, block: BlockProperties, cx: &mut ViewContext<Self>) {
+        self.command_suggestion = None;
+        self.pending_command_suggestion = Task::ready(());
         self.block_below_cursor = Some(Rc::new(block));
         self.scroll_to_bottom(&ScrollToBottom, cx);
         cx.notify();
     }
 
     pub fn clear_block_below_cursor(&mut self, cx: &mut ViewContext<Self>) {
+        self.command_suggestion = None;
         self.block_below_cursor = None;
         self.scroll_top = Pixels::ZERO;
         cx.notify();
     }
 
+    /// Suggests how to finish commands as they're typed, below the cursor.
+    pub fn set_command_suggestion_provider(&mut self, provider: Rc<dyn CommandSuggestionProvider>) {
+        self.command_suggestion_provider = Some(provider);
+    }
+
+    /// Asks for a suggestion once typing pauses, if the cursor is at the end
+    /// of a command line.
+    fn refresh_command_suggestion(&mut self, cx: &mut ViewContext<Self>) {
+        self.dismiss_command_suggestion(cx);
+        let Some(provider) = self.command_suggestion_provider.clone() else {
+            return;
+        };
+        self.pending_command_suggestion = cx.spawn(|this, mut cx| async move {
+            Timer::after(COMMAND_SUGGESTION_DELAY).await;
+            let Some((suggestion, column)) = this
+                .update(&mut cx, |this, cx| {
+                    let (command_line, recent_output, column) = this.command_line(cx)?;
+                    Some((provider.suggest(command_line, recent_output, cx), column))
+                })
+                .ok()
+                .flatten()
+            else {
+                return;
+            };
+            if let Some(Some(suggestion)) = suggestion.await.log_err() {
+                this.update(&mut cx, |this, cx| {
+                    this.show_command_suggestion(suggestion, column, cx)
+                })
+                .ok();
+            }
+        });
+    }
+
+    /// The line being typed, the output above it and the cursor's column, when
+    /// the cursor is at the end of the line's text and not in a full-screen
+    /// program like an editor or a pager.
+    fn command_line(&self, cx: &AppContext) -> Option<(String, Vec<String>, usize)> {
+        let terminal = self.terminal.read(cx);
+        let content = terminal.last_content();
+        if self.block_below_cursor.is_some()
+            || content.display_offset != 0
+            || content.mode.contains(TermMode::ALT_SCREEN)
+        {
+            return None;
+        }
+        let cursor = content.cursor.point;
+        let text_end = content
+            .cells
+            .iter()
+            .filter(|cell| cell.point.line == cursor.line && !is_blank(cell))
+            .map(|cell| cell.point.column.0 + 1)
+            .max()?;
+        if cursor.column.0 < text_end {
+            return None;
+        }
+        let mut recent_output =
+            terminal.last_n_non_empty_lines(COMMAND_SUGGESTION_CONTEXT_LINES + 1);
+        let mut command_line = recent_output.pop()?;
+        // Lines are trimmed, but spaces typed before the cursor matter.
+        command_line.extend(std::iter::repeat(' ').take(cursor.column.0 - text_end));
+        Some((command_line, recent_output, cursor.column.0))
+    }
+
+    fn show_command_suggestion(
+        &mut self,
+        suggestion: String,
+        column: usize,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let text = suggestion.clone();
+        self.set_block_below_cursor(
+            BlockProperties {
+                height: 1,
+                render: Box::new(move |cx| {
+                    h_flex()
+                        .pl(cx.dimensions.cell_width() * column as f32)
+                        .child(Label::new(text.clone()).color(Color::Muted))
+                        .into_any_element()
+                }),
+            },
+            cx,
+        );
+        self.command_suggestion = Some(suggestion);
+    }
+
+    fn dismiss_command_suggestion(&mut self, cx: &mut ViewContext<Self>) {
+        self.pending_command_suggestion = Task::ready(());
+        if self.command_suggestion.is_some() {
+            self.clear_block_below_cursor(cx);
+        }
+    }
+
+    fn accept_command_suggestion(
+        &mut self,
+        _: &AcceptCommandSuggestion,
+        cx: &mut ViewContext<Self>,
+    ) {
+        if let Some(suggestion) = self.command_suggestion.take() {
+            self.clear_block_below_cursor(cx);
+            self.terminal.update(cx, |term, _| term.input(suggestion));
+        }
+    }
+
     fn next_blink_epoch(&mut self) -> usize {
         self.blink_epoch += 1;
         self.blink_epoch
@@ -526,6 +658,7 @@ impl TerminalView {
     ///Attempt to paste the clipboard into the terminal
     fn paste(&mut self, _: &Paste, cx: &mut ViewContext<Self>) {
         if let Some(clipboard_string) = cx.read_from_clipboard().and_then(|item| item.text()) {
+            self.dismiss_command_suggestion(cx);
             self.terminal
                 .update(cx, |terminal, _cx| terminal.paste(&clipboard_string));
         }
@@ -533,6 +666,7 @@ impl TerminalView {
 
     fn send_text(&mut self, text: &SendText, cx: &mut ViewContext<Self>) {
         self.clear_bell(cx);
+        self.dismiss_command_suggestion(cx);
         self.terminal.update(cx, |term, _| {
             term.input(text.0.to_string());
         });
@@ -541,6 +675,7 @@ impl TerminalView {
     fn send_keystroke(&mut self, text: &SendKeystroke, cx: &mut ViewContext<Self>) {
         if let Some(keystroke) = Keystroke::parse(&text.0).log_err() {
             self.clear_bell(cx);
+            self.dismiss_command_suggestion(cx);
             self.terminal.update(cx, |term, cx| {
                 term.try_keystroke(&keystroke, TerminalSettings::get_global(cx).option_as_meta);
             });
@@ -597,6 +732,9 @@ impl TerminalView {
         if mode.intersects(TermMode::MOUSE_MODE) {
             dispatch_context.add("any_mouse_reporting");
         }
+        if self.command_suggestion.is_some() {
+            dispatch_context.add("command_suggestion");
+        }
         {
             let mouse_reporting = if mode.contains(TermMode::MOUSE_REPORT_CLICK) {
                 "click"
@@ -935,15 +1073,16 @@ impl TerminalView {
         self.clear_bell(cx);
         self.pause_cursor_blinking(cx);
 
-        self.terminal.update(cx, |term, cx| {
-            let handled = term.try_keystroke(
+        let handled = self.terminal.update(cx, |term, cx| {
+            term.try_keystroke(
                 &event.keystroke,
                 TerminalSettings::get_global(cx).option_as_meta,
-            );
-            if handled {
-                cx.stop_propagation();
-            }
+            )
         });
+        if handled {
+            cx.stop_propagation();
+            self.refresh_command_suggestion(cx);
+        }
     }
 
     fn focus_in(&mut self, cx: &mut ViewContext<Self>) {
@@ -991,6 +1130,7 @@ impl Render for TerminalView {
             .on_action(cx.listener(TerminalView::toggle_vi_mode))
             .on_action(cx.listener(TerminalView::show_character_palette))
             .on_action(cx.listener(TerminalView::select_all))
+            .on_action(cx.listener(TerminalView::accept_command_suggestion))
             .on_key_down(cx.listener(Self::key_down))
             .on_mouse_down(
                 MouseButton::Right,
@@ -387,6 +387,7 @@ fn main() {
             cx,
         );
         supermaven::init(app_state.client.clone(), cx);
+        ollama_completions::init(cx);
         language_model::init(
             app_state.user_store.clone(),
             app_state.client.clone(),
@@ -395,6 +396,7 @@ fn main() {
         );
         snippet_provider::init(cx);
         inline_completion_registry::init(app_state.client.telemetry().clone(), cx);
+        inline_completion_button::init(cx);
         let prompt_builder = assistant::init(
             app_state.fs.clone(),
             app_state.client.clone(),
@@ -414,6 +416,7 @@ fn main() {
             SnippetRegistry::global(cx),
             app_state.languages.clone(),
             ContextServerFactoryRegistry::global(cx),
+            ollama_completions::CompletionContextProviders::global(cx),
             cx,
         );
         extension_host::init(
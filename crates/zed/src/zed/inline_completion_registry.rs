@@ -4,10 +4,12 @@ use client::telemetry::Telemetry;
 use collections::HashMap;
 use copilot::{Copilot, CopilotCompletionProvider};
 use editor::{Editor, EditorMode};
-use gpui::{AnyWindowHandle, AppContext, Context, ViewContext, WeakView};
-use language::language_settings::all_language_settings;
-use settings::SettingsStore;
+use gpui::{AnyWindowHandle, AppContext, Context, Model, ViewContext, WeakView};
+use language::language_settings::{all_language_settings, InlineCompletionProvider};
+use ollama_completions::{ChainedCompletionProvider, OllamaCompletionProvider, OllamaSettings};
+use settings::{Settings, SettingsStore};
 use supermaven::{Supermaven, SupermavenCompletionProvider};
+use workspace::Workspace;
 
 pub fn init(telemetry: Arc<Telemetry>, cx: &mut AppContext) {
     let editors: Rc<RefCell<HashMap<WeakView<Editor>, AnyWindowHandle>>> = Rc::default();
@@ -40,6 +42,7 @@ pub fn init(telemetry: Arc<Telemetry>, cx: &mut AppContext) {
     .detach();
 
     let mut provider = all_language_settings(None, cx).inline_completions.provider;
+    let mut fallback_providers = OllamaSettings::get_global(cx).fallback_providers.clone();
     for (editor, window) in editors.borrow().iter() {
         _ = window.update(cx, |_window, cx| {
             _ = editor.update(cx, |editor, cx| {
@@ -50,8 +53,12 @@ pub fn init(telemetry: Arc<Telemetry>, cx: &mut AppContext) {
 
     cx.observe_global::<SettingsStore>(move |cx| {
         let new_provider = all_language_settings(None, cx).inline_completions.provider;
-        if new_provider != provider {
+        let new_fallback_providers = &OllamaSettings::get_global(cx).fallback_providers;
+        let fallbacks_changed = provider == InlineCompletionProvider::Ollama
+            && *new_fallback_providers != fallback_providers;
+        if new_provider != provider || fallbacks_changed {
             provider = new_provider;
+            fallback_providers = new_fallback_providers.clone();
             for (editor, window) in editors.borrow().iter() {
                 _ = window.update(cx, |_window, cx| {
                     _ = editor.update(cx, |editor, cx| {
@@ -62,6 +69,17 @@ pub fn init(telemetry: Arc<Telemetry>, cx: &mut AppContext) {
         }
     })
     .detach();
+
+    // Keep the Ollama model loaded only while the user is working in Zed.
+    cx.observe_new_views(|_: &mut Workspace, cx: &mut ViewContext<Workspace>| {
+        cx.observe_window_activation(|_, cx| {
+            let provider = all_language_settings(None, cx).inline_completions.provider;
+            let focused = provider == InlineCompletionProvider::Ollama && cx.is_window_active();
+            ollama_completions::heartbeat(focused, &cx.http_client(), cx);
+        })
+        .detach();
+    })
+    .detach();
 }
 
 fn register_backward_compatible_actions(editor: &mut Editor, cx: &ViewContext<Editor>) {
@@ -102,34 +120,103 @@ fn register_backward_compatible_actions(editor: &mut Editor, cx: &ViewContext<Ed
 
 fn assign_inline_completion_provider(
     editor: &mut Editor,
-    provider: language::language_settings::InlineCompletionProvider,
+    provider: InlineCompletionProvider,
     telemetry: &Arc<Telemetry>,
     cx: &mut ViewContext<Editor>,
 ) {
     match provider {
-        language::language_settings::InlineCompletionProvider::None => {}
-        language::language_settings::InlineCompletionProvider::Copilot => {
-            if let Some(copilot) = Copilot::global(cx) {
-                if let Some(buffer) = editor.buffer().read(cx).as_singleton() {
-                    if buffer.read(cx).file().is_some() {
-                        copilot.update(cx, |copilot, cx| {
-                            copilot.register_buffer(&buffer, cx);
-                        });
-                    }
-                }
-                let provider = cx.new_model(|_| {
-                    CopilotCompletionProvider::new(copilot).with_telemetry(telemetry.clone())
-                });
+        InlineCompletionProvider::None => {}
+        InlineCompletionProvider::Copilot => {
+            if let Some(provider) = copilot_provider(editor, telemetry, cx) {
                 editor.set_inline_completion_provider(Some(provider), cx);
             }
         }
-        language::language_settings::InlineCompletionProvider::Supermaven => {
-            if let Some(supermaven) = Supermaven::global(cx) {
-                let provider = cx.new_model(|_| {
-                    SupermavenCompletionProvider::new(supermaven).with_telemetry(telemetry.clone())
-                });
+        InlineCompletionProvider::Supermaven => {
+            if let Some(provider) = supermaven_provider(telemetry, cx) {
                 editor.set_inline_completion_provider(Some(provider), cx);
             }
         }
+        InlineCompletionProvider::Ollama => {
+            ollama_completions::warm_up(&cx.http_client(), cx);
+            let settings = OllamaSettings::get_global(cx);
+            let mut provider = OllamaCompletionProvider::new(cx.http_client(), settings.clone())
+                .with_telemetry(telemetry.clone());
+            if let Some(project) = editor.project() {
+                provider = provider.with_project(project);
+            }
+            let provider = cx.new_model(|cx| {
+                cx.observe_global::<SettingsStore>(
+                    |provider: &mut OllamaCompletionProvider, cx| {
+                        let settings = OllamaSettings::get_global(cx).clone();
+                        provider.set_settings(settings, cx);
+                        ollama_completions::warm_up(&cx.http_client(), cx);
+                    },
+                )
+                .detach();
+                provider
+            });
+
+            let fallback_providers = OllamaSettings::get_global(cx).fallback_providers.clone();
+            if fallback_providers.is_empty() {
+                editor.set_inline_completion_provider(Some(provider), cx);
+                return;
+            }
+            let copilot = fallback_providers
+                .contains(&InlineCompletionProvider::Copilot)
+                .then(|| copilot_provider(editor, telemetry, cx))
+                .flatten();
+            let supermaven = fallback_providers
+                .contains(&InlineCompletionProvider::Supermaven)
+                .then(|| supermaven_provider(telemetry, cx))
+                .flatten();
+            let chain = cx.new_model(|cx| {
+                let mut chain = ChainedCompletionProvider::new(provider, cx);
+                for fallback in fallback_providers {
+                    match fallback {
+                        InlineCompletionProvider::Copilot => {
+                            if let Some(copilot) = copilot.clone() {
+                                chain.add_fallback(copilot, cx);
+                            }
+                        }
+                        InlineCompletionProvider::Supermaven => {
+                            if let Some(supermaven) = supermaven.clone() {
+                                chain.add_fallback(supermaven, cx);
+                            }
+                        }
+                        InlineCompletionProvider::None | InlineCompletionProvider::Ollama => {}
+                    }
+                }
+                chain
+            });
+            editor.set_inline_completion_provider(Some(chain), cx);
+        }
     }
 }
+
+fn copilot_provider(
+    editor: &Editor,
+    telemetry: &Arc<Telemetry>,
+    cx: &mut ViewContext<Editor>,
+) -> Option<Model<CopilotCompletionProvider>> {
+    let copilot = Copilot::global(cx)?;
+    if let Some(buffer) = editor.buffer().read(cx).as_singleton() {
+        if buffer.read(cx).file().is_some() {
+            copilot.update(cx, |copilot, cx| {
+                copilot.register_buffer(&buffer, cx);
+            });
+        }
+    }
+    Some(
+        cx.new_model(|_| CopilotCompletionProvider::new(copilot).with_telemetry(telemetry.clone())),
+    )
+}
+
+fn supermaven_provider(
+    telemetry: &Arc<Telemetry>,
+    cx: &mut ViewContext<Editor>,
+) -> Option<Model<SupermavenCompletionProvider>> {
+    let supermaven = Supermaven::global(cx)?;
+    Some(cx.new_model(|_| {
+        SupermavenCompletionProvider::new(supermaven).with_telemetry(telemetry.clone())
+    }))
+}
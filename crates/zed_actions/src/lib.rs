@@ -49,3 +49,38 @@ pub struct InlineAssist {
 }
 
 impl_actions!(assistant, [InlineAssist]);
+
+pub mod ollama {
+    use gpui::actions;
+
+    actions!(
+        ollama,
+        [
+            ExplainSelection,
+            RewriteSelection,
+            GenerateTestsForSelection,
+            DocumentSymbol,
+            SelectModel,
+            GenerateCommitMessage,
+            ShowCompletionStats,
+            GenerateDocstring,
+            BenchmarkModels,
+            NewModelVariant,
+            CreateModel,
+            StopGenerating,
+            OpenRequestLog,
+            RequestCompletion,
+            SendChatMessage,
+            OpenChatHistory,
+            ExportChat,
+            ImportChat,
+            GenerateCell,
+            FixCell,
+            SuggestNames,
+            PreviewSuggestion,
+            ClearCompletionCache,
+            ShowPerformanceReport,
+            ToggleBufferCompletions
+        ]
+    );
+}